@@ -0,0 +1,56 @@
+//! Mede o custo de transformar os resultados de uma busca grande em contas,
+//! como na listagem do SCIM, que consome as entradas em vez de copiá-las.
+//!
+//! Roda com `cargo bench --bench busca`.
+
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::scim::buscar_usuarios;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+
+/// Um diretório com `n` contas de alunos.
+async fn diretorio(n: usize) -> DiretorioMemoria {
+    let mut d = DiretorioMemoria::default();
+    for i in 0..n {
+        let uid = format!("aluno{i}");
+        let dre = format!("{:09}", 100_000_000 + i);
+        let mail = format!("{uid}@ic.ufrj.br");
+        d.adicionar(
+            &format!("uid={uid},ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid.as_str()].into()),
+                ("dccDRE", [dre.as_str()].into()),
+                ("cn", ["Aluno"].into()),
+                ("sn", ["da Silva"].into()),
+                ("gecos", ["Aluno da Silva"].into()),
+                ("mail", [mail.as_str()].into()),
+                ("estadoConta", ["ativa"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    d
+}
+
+fn busca_grande(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut grupo = c.benchmark_group("buscar_usuarios");
+
+    for n in [1_000, 10_000] {
+        let d = Mutex::new(rt.block_on(diretorio(n)));
+        grupo.bench_with_input(BenchmarkId::from_parameter(n), &d, |b, d| {
+            b.to_async(&rt).iter(|| async {
+                buscar_usuarios(&mut *d.lock().await).await.unwrap()
+            });
+        });
+    }
+
+    grupo.finish();
+}
+
+criterion_group!(benches, busca_grande);
+criterion_main!(benches);
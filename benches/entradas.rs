@@ -0,0 +1,65 @@
+//! Mede a validação das entradas da API e a geração dos usernames, com nomes
+//! de vários tamanhos.
+//!
+//! Roda com `cargo bench --bench entradas`.
+
+use alumnic::utils::nome::Nome;
+use alumnic::utils::validacao_entradas::*;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const CHAVE_SSH: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIDgJE5tJHqoMuq5JLqULfbJIvBzZ51WPDlDDxodR6vun joao@exemplo";
+
+/// Nomes com dois a seis sobrenomes, identificados pelo número de partes.
+const NOMES: [&str; 5] = [
+    "Ana Silva",
+    "João Pedro Silva",
+    "Cláudio de Lima Cavalcante",
+    "Maria Eduarda dos Santos Oliveira",
+    "Ana Bia Cal Di Eva Flo",
+];
+
+type Processador = fn(&str) -> Option<String>;
+
+fn processar(c: &mut Criterion) {
+    let mut grupo = c.benchmark_group("processar");
+
+    let entradas: [(&str, Processador, &str); 7] = [
+        ("dre", processar_dre, "123456789"),
+        ("data", processar_data, "01/03/2025"),
+        ("hora", processar_hora, "10:00"),
+        (
+            "codigo",
+            processar_codigo,
+            "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF",
+        ),
+        ("email", processar_email, "claudio@exemplo.com"),
+        ("telefone", processar_telefone, "(21) 98765-4321"),
+        ("chave_ssh", processar_chave_ssh, CHAVE_SSH),
+    ];
+    for (nome, processar, entrada) in entradas {
+        grupo
+            .bench_function(nome, |b| b.iter(|| processar(black_box(entrada))));
+    }
+
+    grupo.finish();
+}
+
+fn usernames(c: &mut Criterion) {
+    let mut grupo = c.benchmark_group("usernames");
+
+    for nome in NOMES {
+        let partes = nome.split_whitespace().count();
+        let nome: Nome = nome.parse().unwrap();
+        grupo.bench_with_input(
+            BenchmarkId::from_parameter(partes),
+            &nome,
+            |b, nome| b.iter(|| black_box(nome).usernames().count()),
+        );
+    }
+
+    grupo.finish();
+}
+
+criterion_group!(benches, processar, usernames);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Mede os hashes das senhas gravados no cadastro.
+//!
+//! Roda com `cargo bench --bench hashes`.
+
+use alumnic::utils::hashes::{hash_nt, hash_ssha};
+use criterion::{Criterion, criterion_group, criterion_main};
+use secrecy::SecretString;
+use std::hint::black_box;
+
+fn hashes(c: &mut Criterion) {
+    let senha = SecretString::from("Senha1234 com acentuação");
+
+    c.bench_function("hash_nt", |b| b.iter(|| hash_nt(black_box(&senha))));
+    c.bench_function("hash_ssha", |b| b.iter(|| hash_ssha(black_box(&senha))));
+}
+
+criterion_group!(benches, hashes);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+#![no_main]
+
+use alumnic::utils::validacao_entradas::processar_codigo;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|entrada: &str| {
+    // Além de não entrar em pânico, a saída precisa ser um ponto fixo
+    if let Some(saida) = processar_codigo(entrada) {
+        assert_eq!(processar_codigo(&saida).as_ref(), Some(&saida));
+    }
+});
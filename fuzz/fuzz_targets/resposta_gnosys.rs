@@ -0,0 +1,14 @@
+#![no_main]
+
+use alumnic::portal_ufrj::{
+    LeituraResposta, extrair_view_state, interpretar_resposta, ler_resposta,
+};
+use libfuzzer_sys::fuzz_target;
+
+// O HTML vem de um serviço de terceiros, então qualquer resposta precisa virar
+// um resultado ou um erro, nunca um pânico
+fuzz_target!(|html: &str| {
+    let _ = extrair_view_state(html);
+    let _ = interpretar_resposta(html);
+    let _ = ler_resposta(html, LeituraResposta::Tolerante);
+});
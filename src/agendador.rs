@@ -0,0 +1,77 @@
+//! Tarefas periódicas rodadas junto com a API pelo `alumnic serve`.
+use crate::configuracao::Configuracao;
+use crate::ldap::conexao::FonteLdap;
+use crate::manutencao::Manutencao;
+use crate::prazos::aplicar_prazos_em;
+use crate::projeto::expirar_projetos;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Intervalo entre duas execuções das tarefas.
+const INTERVALO: Duration = Duration::from_secs(60 * 60);
+
+/// Roda as tarefas periódicas até o `cancelamento`. A primeira execução
+/// acontece imediatamente. As execuções durante a [manutenção](Manutencao)
+/// são puladas.
+pub async fn rodar<F: FonteLdap>(
+    ldap: F,
+    cfg: Arc<Configuracao>,
+    manutencao: Arc<Manutencao>,
+    cancelamento: CancellationToken,
+) {
+    let mut intervalo = tokio::time::interval(INTERVALO);
+
+    loop {
+        // Os prazos em andamento são aplicados até o fim
+        tokio::select! {
+            _ = intervalo.tick() => {},
+            _ = cancelamento.cancelled() => return,
+        }
+
+        if manutencao.somente_leitura().is_some() {
+            println!("Modo somente leitura: os prazos não foram aplicados");
+            continue;
+        }
+
+        match aplicar_prazos_em(&ldap, &cfg, Utc::now()).await {
+            Ok(t) => {
+                for conta in t.em_carencia {
+                    println!("A conta {:?} entrou em carência", conta.uid);
+                }
+                for conta in t.suspensas {
+                    println!("A conta {:?} foi suspensa", conta.uid);
+                }
+                for conta in t.removidas {
+                    println!("A conta {:?} foi removida", conta.uid);
+                }
+                for (conta, e) in t.falhas {
+                    eprintln!("Erro no hook da conta {:?}: {e}", conta.uid);
+                }
+            },
+            Err(e) => eprintln!("Erro ao aplicar os prazos: {e}"),
+        }
+
+        match expirar_projetos(&ldap, &cfg, Utc::now()).await {
+            Ok(t) => {
+                for conta in t.bloqueadas {
+                    println!("A conta de projeto {:?} expirou", conta.uid);
+                }
+                for conta in t.avisadas {
+                    println!(
+                        "{:?} foi avisado da expiração da conta {:?}",
+                        conta.responsavel, conta.uid,
+                    );
+                }
+                for (conta, e) in t.falhas {
+                    eprintln!(
+                        "Erro no aviso da expiração da conta {:?}: {e}",
+                        conta.uid,
+                    );
+                }
+            },
+            Err(e) => eprintln!("Erro ao expirar as contas de projeto: {e}"),
+        }
+    }
+}
@@ -1,56 +1,608 @@
-use crate::cadastro_aluno::DadosParaCadastro;
-use crate::configuracao::Configuracao;
+use crate::agendador;
+use crate::autorizacao::{Acao, autenticar_supervisor, papel_do_supervisor};
+use crate::cadastro_aluno::{
+    CadastroRealizado, DadosParaCadastro, DadosParaValidacao, ErroDeCadastro,
+};
+use crate::cadastros_por_ip::CadastrosPorIp;
+use crate::cancelamento::cancelar_no_termino;
+use crate::configuracao::{Configuracao, ConfiguracaoSocketAdmin};
+use crate::contingencia::{self, aviso_enfileirado};
+use crate::docente::{self, DadosDoDocente};
+use crate::em_andamento::{EmAndamento, Vez, esperar};
+use crate::estatisticas::{Estatisticas, Falha};
+use crate::hooks::{Evento, TipoEvento, disparar_evento};
+use crate::ldap::ErroLdap;
+use crate::ldap::LdapPool;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::consulta::{UidsEmCache, achar_nomes_livres_ldap};
+use crate::ldap::conta::{Conta, buscar_conta_por_dre, listar_contas};
+use crate::ldap::diretorio::{Ordenacao, PedidoPagina};
+use crate::ldap::reservas::limpar_reservas;
+use crate::lotes;
+use crate::manutencao::{Manutencao, ModoInterrupcao, ModoManutencao};
+use crate::metricas::{
+    metricas as metricas_atuais, registrar_cadastro, registrar_expirados,
+    registrar_falha_cadastro,
+};
+use crate::notificacao;
+use crate::origens;
+use crate::painel::{self, Sessoes};
+use crate::proximos_passos::ProximosPassos;
+use crate::redefinicao_senha::redefinir_senha;
+use crate::remocao::remover_agora;
+use crate::renovacao::{DadosParaRenovacao, dia_para_data};
+use crate::scim::{
+    FiltroScim, SCHEMA_ERRO, UsuarioScim, buscar_usuarios, paginar,
+};
+use crate::totp::{ConfiguracaoTotp, ErroTotp, VerificadorTotp};
+use crate::utils::hashes::iguais_tempo_constante;
+use crate::utils::triagem_nome::TriagemNome;
+use crate::utils::validacao_entradas::escapar_para_log;
 use axum::Router;
-use axum::extract::{Json, State, rejection::JsonRejection};
-use axum::http::StatusCode;
-use axum::routing::post;
-use serde::Serialize;
-use std::sync::Arc;
+use axum::extract::Request;
+use axum::extract::{
+    ConnectInfo, Extension, Json, Path, Query, State, rejection::JsonRejection,
+};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post, put};
+use base64::prelude::*;
+use chrono::{Local, NaiveDate, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::fs::{DirBuilder, Permissions};
+use std::net::SocketAddr;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UnixListener;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 
-#[derive(Serialize)]
-struct ResponseBody {
-    message: String,
-    sabar_mais: Option<String>,
+/// A resposta ao cadastro com o `cadastro_novo` desligado.
+pub(crate) const CADASTRO_DESLIGADO: &str =
+    "O cadastro de contas novas está desativado no momento.";
+
+/// A resposta à renovação com a `renovacao` desligada.
+pub(crate) const RENOVACAO_DESLIGADA: &str =
+    "A renovação de contas está desativada no momento.";
+
+/// Estado compartilhado entre as rotas da API e as do
+/// [painel](crate::painel).
+pub(crate) struct EstadoApi<F> {
+    pub(crate) cfg: Arc<Configuracao>,
+    pub(crate) ldap: F,
+    pub(crate) estatisticas: Mutex<Estatisticas>,
+    limite_helpdesk: Mutex<LimiteDeTaxa>,
+    /// Limita os cadastros e renovações em andamento; os demais esperam.
+    vagas: Semaphore,
+    pub(crate) manutencao: Arc<Manutencao>,
+    pub(crate) sessoes: Sessoes,
+    pub(crate) limite_login: Mutex<LimiteDeTaxa>,
+    cadastros_por_ip: Mutex<CadastrosPorIp>,
+    /// As respostas dos cadastros em andamento, pelo DRE.
+    cadastros_em_andamento: EmAndamento<(StatusCode, ResponseBody)>,
+    /// Os códigos TOTP já usados nas operações destrutivas.
+    totp: VerificadorTotp,
+    /// Os uids comparados com os usernames
+    /// [confundíveis](crate::utils::confundiveis) nas consultas públicas.
+    pub(crate) uids: UidsEmCache,
+    /// Cancelado no [término](crate::cancelamento) do serviço.
+    pub(crate) cancelamento: CancellationToken,
+    /// As tarefas que o serviço espera terminar antes de sair, como os lotes
+    /// do gRPC e os hooks dos cadastros e renovações.
+    pub(crate) tarefas: TaskTracker,
+}
+
+impl<F> EstadoApi<F> {
+    pub(crate) fn novo(
+        cfg: Arc<Configuracao>,
+        ldap: F,
+        manutencao: Arc<Manutencao>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            vagas: Semaphore::new(cfg.concorrencia.api.max(1)),
+            uids: UidsEmCache::new(Duration::from_secs(
+                cfg.usuario_novo
+                    .usernames_confundiveis
+                    .as_ref()
+                    .map_or(0, |c| c.validade_cache_segundos),
+            )),
+            manutencao,
+            cfg,
+            ldap,
+            estatisticas: Mutex::new(Estatisticas::default()),
+            limite_helpdesk: Mutex::new(LimiteDeTaxa::novo()),
+            sessoes: Sessoes::default(),
+            limite_login: Mutex::new(LimiteDeTaxa::novo()),
+            cadastros_por_ip: Mutex::new(CadastrosPorIp::default()),
+            cadastros_em_andamento: EmAndamento::default(),
+            totp: VerificadorTotp::default(),
+            cancelamento: CancellationToken::new(),
+            tarefas: TaskTracker::new(),
+        })
+    }
+
+    /// A resposta `503` das operações de escrita, se o modo somente leitura
+    /// estiver ativo.
+    fn recusar_escrita(&self) -> Option<(StatusCode, Json<ResponseBody>)> {
+        self.manutencao.somente_leitura().map(|mensagem| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ResponseBody {
+                    message: mensagem,
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+        })
+    }
+
+    /// A resposta `503` de um fluxo desligado nas
+    /// [features](crate::configuracao::Funcionalidades).
+    fn recusar_desligada(
+        &self,
+        ligada: bool,
+        mensagem: &str,
+    ) -> Option<(StatusCode, Json<ResponseBody>)> {
+        (!ligada).then(|| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ResponseBody {
+                    message: mensagem.to_string(),
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+        })
+    }
+
+    /// A resposta `503` da API pública, se ela estiver interrompida. Com a
+    /// previsão de retorno, o `Retry-After` diz quantos segundos faltam.
+    fn recusar_publica(&self) -> Option<Response> {
+        let modo = self.manutencao.interrompida()?;
+        let corpo = Json(ResponseBody {
+            message: modo.resposta(),
+            sabar_mais: None,
+            proximos_passos: None,
+            triagem_nome: None,
+        });
+
+        let faltam = modo
+            .retorno
+            .map(|retorno| (retorno.to_utc() - Utc::now()).num_seconds());
+        Some(match faltam {
+            Some(segundos) if segundos > 0 => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, segundos.to_string())],
+                corpo,
+            )
+                .into_response(),
+            _ => (StatusCode::SERVICE_UNAVAILABLE, corpo).into_response(),
+        })
+    }
+
+    /// Guarda a falha da `operacao` para o painel.
+    pub(crate) fn registrar_falha(
+        &self,
+        operacao: &str,
+        conta: &str,
+        erro: &impl std::fmt::Display,
+    ) {
+        self.estatisticas.lock().unwrap().registrar_falha(Falha {
+            quando: Local::now(),
+            operacao: operacao.to_string(),
+            conta: Some(conta.to_string()),
+            mensagem: erro.to_string(),
+            etapa: None,
+        });
+    }
+
+    /// Conta o erro de um cadastro nas estatísticas e nas métricas, pelo tipo
+    /// e pela etapa em que o cadastro parou.
+    pub(crate) fn contar_erro_de_cadastro(&self, erro: &ErroDeCadastro) {
+        let etapa = erro.etapa().nome();
+        self.estatisticas
+            .lock()
+            .unwrap()
+            .registrar_erro(erro.tipo(), etapa);
+        registrar_falha_cadastro(etapa);
+    }
+
+    /// Guarda a falha do cadastro do `dre` para o painel, com a etapa em que
+    /// ele parou, e a mostra no log.
+    pub(crate) fn registrar_falha_de_cadastro(
+        &self,
+        dre: &str,
+        erro: &ErroDeCadastro,
+    ) {
+        let etapa = erro.etapa();
+        eprintln!("O cadastro do DRE {dre} falhou na {etapa}: {erro}");
+        self.estatisticas.lock().unwrap().registrar_falha(Falha {
+            quando: Local::now(),
+            operacao: "cadastro".to_string(),
+            conta: Some(dre.to_string()),
+            mensagem: erro.to_string(),
+            etapa: Some(etapa.nome().to_string()),
+        });
+    }
+
+    /// Avisa o aluno do `dre`, no `email` externo, de que o cadastro foi
+    /// para a [fila de contingência](contingencia). O aviso sai depois da
+    /// resposta, como o dos cadastros por IP.
+    pub(crate) fn avisar_enfileirado(self: &Arc<Self>, dre: &str, email: &str)
+    where
+        F: Send + Sync + 'static,
+    {
+        let mensagem = aviso_enfileirado(email);
+        let estado = Arc::clone(self);
+        let dre = dre.to_string();
+        tokio::spawn(async move {
+            if let Err(e) =
+                notificacao::enviar(&estado.cfg.notificacao, &mensagem).await
+            {
+                eprintln!("Erro no aviso do cadastro de {dre:?}: {e}");
+            }
+        });
+    }
+
+    /// Conta o cadastro do `dre` enviado de quem fez a requisição e avisa a
+    /// supervisão se o endereço passar do limite.
+    fn contar_cadastro(
+        self: &Arc<Self>,
+        dre: &str,
+        headers: &HeaderMap,
+        conexao: Option<SocketAddr>,
+    ) where
+        F: Send + Sync + 'static,
+    {
+        let Some(cfg) = &self.cfg.cadastros_por_ip else {
+            return;
+        };
+        let Some(ip) = cfg.endereco(headers, conexao.map(|c| c.ip())) else {
+            return;
+        };
+        let Some(dres) = self.cadastros_por_ip.lock().unwrap().registrar(
+            cfg,
+            ip,
+            dre,
+            Instant::now(),
+        ) else {
+            return;
+        };
+
+        eprintln!(
+            "O endereço {ip} enviou cadastros de {} DREs diferentes",
+            dres.len(),
+        );
+        let mensagem = cfg.aviso(ip, &dres);
+        let estado = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) =
+                notificacao::enviar(&estado.cfg.notificacao, &mensagem).await
+            {
+                eprintln!("Erro no aviso dos cadastros vindos de {ip}: {e}");
+            }
+        });
+    }
+
+    /// Conta a `origem` de um cadastro aceito nas estatísticas e nas do
+    /// período, que são gravadas depois da resposta.
+    fn contar_origem(self: &Arc<Self>, origem: Option<&str>)
+    where
+        F: Send + Sync + 'static,
+    {
+        let Some(origem) = origem else {
+            return;
+        };
+        self.estatisticas.lock().unwrap().registrar_origem(origem);
+
+        let estado = Arc::clone(self);
+        let origem = origem.to_string();
+        tokio::spawn(async move {
+            let hoje = Local::now().date_naive();
+            if let Err(e) =
+                origens::contar(&estado.cfg.dados, &origem, hoje).await
+            {
+                eprintln!("Erro ao contar a origem de um cadastro: {e}");
+            }
+        });
+    }
+
+    /// Registra o `cadastro` feito nas estatísticas e dispara os hooks dele.
+    /// Os hooks rodam depois da resposta, para o aluno não esperar as
+    /// tentativas, e o serviço espera eles terminarem antes de sair.
+    pub(crate) fn depois_do_cadastro(
+        self: &Arc<Self>,
+        cadastro: &CadastroRealizado,
+    ) where
+        F: Send + Sync + 'static,
+    {
+        self.estatisticas
+            .lock()
+            .unwrap()
+            .registrar_cadastro(Local::now().date_naive(), cadastro.ou);
+        registrar_cadastro(&cadastro.dre);
+
+        let estado = Arc::clone(self);
+        let conta = cadastro.clone();
+        self.tarefas.spawn(async move {
+            let cfg = &estado.cfg;
+            let evento = Evento {
+                evento: TipoEvento::Cadastro,
+                uid: &conta.username,
+                dre: &conta.dre,
+                home: &conta.home,
+                nome: &conta.nome,
+                email: &conta.email,
+            };
+            if let Err(e) =
+                disparar_evento(&cfg.hooks, &cfg.auditoria, &evento).await
+            {
+                eprintln!(
+                    "Erro no hook de cadastro da conta {:?}: {e}",
+                    conta.username,
+                );
+                estado.registrar_falha("hook de cadastro", &conta.username, &e);
+            }
+        });
+    }
+
+    /// Dispara os hooks da renovação da `conta`, como no cadastro, depois da
+    /// resposta.
+    pub(crate) fn depois_da_renovacao(self: &Arc<Self>, conta: Conta)
+    where
+        F: Send + Sync + 'static,
+    {
+        let estado = Arc::clone(self);
+        self.tarefas.spawn(async move {
+            let cfg = &estado.cfg;
+            let evento = Evento::da_conta(TipoEvento::Renovacao, &conta);
+            if let Err(e) =
+                disparar_evento(&cfg.hooks, &cfg.auditoria, &evento).await
+            {
+                eprintln!(
+                    "Erro no hook de renovação da conta {:?}: {e}",
+                    conta.uid,
+                );
+                estado.registrar_falha("hook de renovação", &conta.uid, &e);
+            }
+        });
+    }
+
+    /// Espera uma vaga para um cadastro ou renovação.
+    pub(crate) async fn vaga(&self) -> SemaphorePermit<'_> {
+        self.vagas
+            .acquire()
+            .await
+            .expect("o semáforo das vagas nunca é fechado")
+    }
 }
 
-async fn cadastrar(
-    State(cfg): State<Arc<Configuracao>>,
+/// Conta as requisições de uma rota em janelas fixas de um minuto.
+pub(crate) struct LimiteDeTaxa {
+    inicio: Instant,
+    contagem: u32,
+}
+
+impl LimiteDeTaxa {
+    const JANELA: Duration = Duration::from_secs(60);
+
+    pub(crate) fn novo() -> Self {
+        Self {
+            inicio: Instant::now(),
+            contagem: 0,
+        }
+    }
+
+    /// Conta uma requisição feita `agora`. Se o `limite` da janela já foi
+    /// atingido, retorna quanto tempo falta para a próxima.
+    pub(crate) fn contar(
+        &mut self,
+        limite: u32,
+        agora: Instant,
+    ) -> Result<(), Duration> {
+        let passado = agora.duration_since(self.inicio);
+        if passado >= Self::JANELA {
+            self.inicio = agora;
+            self.contagem = 0;
+        } else if self.contagem >= limite {
+            return Err(Self::JANELA - passado);
+        }
+
+        self.contagem += 1;
+        Ok(())
+    }
+}
+
+/// A resposta do cadastro, da renovação e dos erros da API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseBody {
+    pub message: String,
+    pub sabar_mais: Option<String>,
+    /// Os próximos passos do aluno, só no cadastro feito.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proximos_passos: Option<Box<ProximosPassos>>,
+    /// As diferenças do nome informado para o do SIGA, com o nome sugerido,
+    /// só no erro de nomes diferentes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub triagem_nome: Option<Box<TriagemNome>>,
+}
+
+/// Responde `503` nas rotas da API pública enquanto ela estiver
+/// interrompida.
+async fn interromper<F>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match estado.recusar_publica() {
+        Some(resposta) => resposta,
+        None => next.run(req).await,
+    }
+}
+
+/// Responde `404` nas rotas administrativas com a `api_admin` desligada.
+async fn esconder_admin<F>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if !estado.cfg.features.api_admin {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    next.run(req).await
+}
+
+/// A resposta do healthcheck.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Saude {
+    pub status: String,
+    pub somente_leitura: bool,
+    pub interrompida: bool,
+}
+
+/// O healthcheck, que continua respondendo `200` em qualquer modo de
+/// manutenção.
+async fn saude<F>(State(estado): State<Arc<EstadoApi<F>>>) -> Json<Saude> {
+    Json(Saude {
+        status: "ok".to_string(),
+        somente_leitura: estado.manutencao.somente_leitura().is_some(),
+        interrompida: estado.manutencao.interrompida().is_some(),
+    })
+}
+
+async fn cadastrar<F: FonteLdap + 'static>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    conexao: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
     dados: Result<Json<DadosParaCadastro>, JsonRejection>,
 ) -> (StatusCode, Json<ResponseBody>) {
-    println!("Recebido {dados:#?}");
+    // Um campo enorme é recusado antes de chegar no log
+    match &dados {
+        Ok(Json(dados)) => {
+            if let Err(e) = dados.limitar_tamanhos() {
+                let err = ErroDeCadastro::from(e);
+                estado.contar_erro_de_cadastro(&err);
+                return (
+                    err.status(),
+                    Json(ResponseBody {
+                        message: format!("Erro: {err}"),
+                        sabar_mais: None,
+                        proximos_passos: None,
+                        triagem_nome: None,
+                    }),
+                );
+            }
+            println!("Recebido {dados:#?}");
+        },
+        Err(rej) => {
+            println!("Recebido {}", escapar_para_log(&rej.body_text()));
+        },
+    }
     println!();
     println!();
 
+    let cfg = &estado.cfg;
+    if let Some(resposta) = estado
+        .recusar_desligada(cfg.features.cadastro_novo, CADASTRO_DESLIGADO)
+        .or_else(|| estado.recusar_escrita())
+    {
+        return resposta;
+    }
+
     // Código muito ruim
     match dados {
         Ok(Json(dados)) => {
-            match dados.cadastrar(
+            let dre = dados.dre.clone();
+            let conexao = conexao.map(|Extension(ConnectInfo(c))| c);
+            estado.contar_cadastro(&dre, &headers, conexao);
+            // Só a origem fica, o endereço é descartado
+            let origem = cfg
+                .origens
+                .as_ref()
+                .and_then(|o| o.origem(&headers, conexao.map(|c| c.ip())));
+
+            // O mesmo cadastro enviado de novo, como num duplo clique, espera
+            // o em andamento e responde como ele
+            let chave = dados.chave_em_andamento();
+            let conclusao = match estado.cadastros_em_andamento.entrar(&chave) {
+                Vez::Primeira(conclusao) => Some(conclusao),
+                Vez::Repetida(receptor) => match esperar(receptor).await {
+                    Some((status, corpo)) => return (status, Json(corpo)),
+                    None => None,
+                },
+            };
+            let _vaga = estado.vaga().await;
+
+            let email = dados.email.clone();
+            let resposta = match dados.cadastrar(
                 &cfg.usuario_novo,
-                &cfg.ldap_url,
-                &cfg.ldap_bind_dn,
-                &cfg.ldap_bind_pw,
+                &cfg.renovacao,
+                &cfg.gnosys_url,
+                &estado.ldap,
+                Some(&estado.uids),
+                &estado.cancelamento,
             ).await {
-                Ok(username) => {
+                Ok(cadastro) => {
+                    estado.depois_do_cadastro(&cadastro);
+                    estado.contar_origem(origem);
+
+                    let passos = &cfg.proximos_passos;
                     (
                         StatusCode::CREATED,
                         Json(ResponseBody {
-                            message: format!(
-                                "Cadastrado como {username:?} com sucesso. Sua conta de e-mail deve funcionar em até 24 horas. Seu login é {username}@ic.ufrj.br (ou @profcomp.ic.ufrj.br caso seja aluno do ProfComp) e a senha é o seu DRE. A senha digitada nesse formulário é usada somente no login dos laboratórios.",
-                            ),
+                            message: passos.mensagem(&cadastro),
                             sabar_mais: None,
+                            proximos_passos: Some(Box::new(
+                                passos.proximos_passos(
+                                    &cadastro,
+                                    Local::now().date_naive(),
+                                ),
+                            )),
+                            triagem_nome: None,
+                        }),
+                    )
+                },
+                Err(err @ ErroDeCadastro::Enfileirado) => {
+                    estado.avisar_enfileirado(&dre, &email);
+                    estado.contar_origem(origem);
+                    (
+                        err.status(),
+                        Json(ResponseBody {
+                            message: err.to_string(),
+                            sabar_mais: None,
+                            proximos_passos: None,
+                            triagem_nome: None,
                         }),
                     )
                 },
                 Err(err) => {
+                    estado.contar_erro_de_cadastro(&err);
+                    estado.registrar_falha_de_cadastro(&dre, &err);
+
                     (
                         err.status(),
                         Json(ResponseBody {
                             message: format!("Erro: {}", err),
                             sabar_mais: None,
+                            proximos_passos: None,
+                            triagem_nome: err.triagem_nome().map(Box::new),
                         }),
                     )
                 }
+            };
+
+            if let Some(conclusao) = conclusao {
+                conclusao.concluir((resposta.0, resposta.1.0.clone()));
             }
+            resposta
         }
         Err(rej) => {
             (
@@ -58,17 +610,1481 @@ async fn cadastrar(
                 Json(ResponseBody {
                     message: "Houve um erro interno, por favor tentar novamente mais tarde.".to_string(),
                     sabar_mais: Some(rej.body_text()),
+                    proximos_passos: None,
+                    triagem_nome: None,
                 }),
             )
         }
     }
 }
 
-pub async fn main(address: String, cfg: Arc<Configuracao>) {
-    let app = Router::new()
-        .route("/api/cadastrar", post(cadastrar))
-        .with_state(cfg);
+/// A pré-validação do cadastro, para o frontend dar retorno antes de o aluno
+/// definir a senha. Só consulta o Gnosys; o LDAP não é tocado.
+async fn validar<F>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    dados: Result<Json<DadosParaValidacao>, JsonRejection>,
+) -> Response {
+    let dados = match dados {
+        Ok(Json(dados)) => dados,
+        Err(rej) => {
+            return (
+                rej.status(),
+                Json(ResponseBody {
+                    message: "Houve um erro interno, por favor tentar \
+                              novamente mais tarde."
+                        .to_string(),
+                    sabar_mais: Some(rej.body_text()),
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+                .into_response();
+        },
+    };
+
+    let cfg = &estado.cfg;
+    if let Some(resposta) =
+        estado.recusar_desligada(cfg.features.cadastro_novo, CADASTRO_DESLIGADO)
+    {
+        return resposta.into_response();
+    }
+    match dados.validar(&cfg.gnosys_url, USERNAMES_OFERECIDOS).await {
+        Ok(validacao) => Json(validacao).into_response(),
+        Err(err) => (
+            err.status(),
+            Json(ResponseBody {
+                message: format!("Erro: {err}"),
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: err.triagem_nome().map(Box::new),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// Lê os dados do documento do PDF enviado no corpo, para o frontend
+/// preencher o cadastro sem o aluno digitar a data, a hora e o código.
+#[cfg(feature = "pdf")]
+async fn ler_documento<F>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    pdf: axum::body::Bytes,
+) -> Response {
+    use crate::documento_pdf::{DadosDoDocumento, ErroDoPdf};
+
+    let cfg = &estado.cfg;
+    if let Some(resposta) =
+        estado.recusar_desligada(cfg.features.cadastro_novo, CADASTRO_DESLIGADO)
+    {
+        return resposta.into_response();
+    }
+
+    let _vaga = estado.vaga().await;
+    // Um PDF malformado pode fazer a leitura entrar em pânico
+    let leitura =
+        tokio::task::spawn_blocking(move || DadosDoDocumento::do_pdf(&pdf))
+            .await
+            .unwrap_or_else(|_| {
+                Err(ErroDoPdf::Ilegivel("o arquivo está corrompido".into()))
+            });
+    match leitura {
+        Ok(dados) => Json(dados).into_response(),
+        Err(err) => (
+            err.status(),
+            Json(ResponseBody {
+                message: format!("Erro: {err}"),
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: None,
+            }),
+        )
+            .into_response(),
+    }
+}
+
+async fn renovar<F: FonteLdap + 'static>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    dados: Result<Json<DadosParaRenovacao>, JsonRejection>,
+) -> (StatusCode, Json<ResponseBody>) {
+    let dados = match dados {
+        Ok(Json(dados)) => dados,
+        Err(rej) => {
+            return (
+                rej.status(),
+                Json(ResponseBody {
+                    message: "Houve um erro interno, por favor tentar \
+                              novamente mais tarde."
+                        .to_string(),
+                    sabar_mais: Some(rej.body_text()),
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            );
+        },
+    };
+
+    let cfg = &estado.cfg;
+    if let Some(resposta) = estado
+        .recusar_desligada(cfg.features.renovacao, RENOVACAO_DESLIGADA)
+        .or_else(|| estado.recusar_escrita())
+    {
+        return resposta;
+    }
+    let _vaga = estado.vaga().await;
+    let dre = dados.dre.clone();
+    match dados
+        .renovar(&cfg.renovacao, &cfg.gnosys_url, &estado.ldap, Utc::now())
+        .await
+    {
+        Ok(renovacao) => {
+            estado.depois_da_renovacao(renovacao.conta.clone());
+
+            (
+                StatusCode::OK,
+                Json(ResponseBody {
+                    message: format!(
+                        "A conta {:?} foi renovada até {}.",
+                        renovacao.username,
+                        renovacao.proxima_renovacao.format("%d/%m/%Y"),
+                    ),
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+        },
+        Err(err) => {
+            estado.registrar_falha("renovação", &dre, &err);
+            (
+                err.status(),
+                Json(ResponseBody {
+                    message: format!("Erro: {err}"),
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+        },
+    }
+}
+
+/// Quantos usernames livres a API oferece para o aluno escolher.
+pub(crate) const USERNAMES_OFERECIDOS: usize = 8;
+
+#[derive(Deserialize)]
+struct ParametrosUsernames {
+    nome: String,
+}
+
+/// Os usernames livres oferecidos ao aluno.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsernamesLivres {
+    pub usernames: Vec<String>,
+}
+
+/// Os usernames livres para o `nome`, entre os quais o aluno escolhe o
+/// `username` do cadastro.
+async fn usernames<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    Query(params): Query<ParametrosUsernames>,
+) -> Response {
+    match achar_nomes_livres_ldap(
+        &params.nome,
+        USERNAMES_OFERECIDOS,
+        estado.cfg.usuario_novo.usernames_confundiveis.as_ref(),
+        Some(&estado.uids),
+        &estado.ldap,
+    )
+    .await
+    {
+        Ok(usernames) => Json(UsernamesLivres { usernames }).into_response(),
+        Err(err) => {
+            let status = match err {
+                ErroLdap::ErroDeNome(..) => StatusCode::UNPROCESSABLE_ENTITY,
+                ErroLdap::Timeout => StatusCode::GATEWAY_TIMEOUT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (
+                status,
+                Json(ResponseBody {
+                    message: format!("Erro: {err}"),
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+                .into_response()
+        },
+    }
+}
+
+/// Marca as requisições que chegaram pelo
+/// [socket administrativo](abrir_socket_admin), em que quem pode abrir o
+/// socket já está autorizado.
+#[derive(Clone, Copy)]
+struct SocketLocal;
+
+/// Verifica se a requisição pode fazer a `acao` administrativa: se veio pelo
+/// socket administrativo, se tem o token administrativo ou se tem, em
+/// `Authorization: Basic`, a senha de um supervisor cujo
+/// [papel](crate::autorizacao::Papel) permita a ação. Retorna o uid do
+/// supervisor, se for o caso, ou o status de erro caso não possa.
+async fn autorizar_admin<F: FonteLdap>(
+    estado: &EstadoApi<F>,
+    headers: &HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    acao: Acao,
+) -> Result<Option<String>, StatusCode> {
+    if local.is_some() {
+        return Ok(None);
+    }
+    let cfg = &estado.cfg;
+    if cfg.socket_admin.as_ref().is_some_and(|s| s.somente_socket) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let (Some(painel), Some((uid, senha))) =
+        (&cfg.painel, credenciais_basic(headers))
+    else {
+        // O token vale como admin
+        return match autenticar(cfg.api_token.as_ref(), headers) {
+            Ok(()) => Ok(None),
+            Err(StatusCode::NOT_FOUND) if cfg.painel.is_some() => {
+                Err(StatusCode::UNAUTHORIZED)
+            },
+            Err(status) => Err(status),
+        };
+    };
+
+    let contagem = estado
+        .limite_login
+        .lock()
+        .unwrap()
+        .contar(painel.tentativas_por_minuto, Instant::now());
+    if contagem.is_err() {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    match autenticar_supervisor(&uid, &senha, cfg, &estado.ldap).await {
+        Ok(Some(papel)) if papel.permite(acao) => Ok(Some(uid)),
+        Ok(Some(_)) => Err(StatusCode::FORBIDDEN),
+        Ok(None) => Err(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            eprintln!("Erro ao autenticar o supervisor {uid:?}: {e}");
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        },
+    }
+}
+
+/// O uid e a senha de `Authorization: Basic`, se houver.
+fn credenciais_basic(headers: &HeaderMap) -> Option<(String, SecretString)> {
+    let codificado = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decodificado =
+        String::from_utf8(BASE64_STANDARD.decode(codificado).ok()?).ok()?;
+    let (uid, senha) = decodificado.split_once(':')?;
+    Some((uid.to_string(), senha.into()))
+}
+
+/// Verifica se a requisição tem o `token`. Se a rota não tiver token
+/// configurado, ela não existe.
+pub(crate) fn autenticar(
+    token: Option<&SecretString>,
+    headers: &HeaderMap,
+) -> Result<(), StatusCode> {
+    let Some(token) = token else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let recebido = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    match recebido {
+        Some(r) if iguais_tempo_constante(r, token.expose_secret()) => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+#[derive(Deserialize)]
+struct ParametrosEstatisticas {
+    formato: Option<String>,
+}
+
+async fn estatisticas<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Query(params): Query<ParametrosEstatisticas>,
+) -> Response {
+    if let Err(status) =
+        autorizar_admin(&estado, &headers, local, Acao::VerEstatisticas).await
+    {
+        return status.into_response();
+    }
+
+    let estatisticas = estado.estatisticas.lock().unwrap().clone();
+
+    match params.formato.as_deref() {
+        Some("html") => Html(estatisticas.html()).into_response(),
+        _ => Json(estatisticas).into_response(),
+    }
+}
+
+async fn somente_leitura<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    modo: Option<Json<ModoManutencao>>,
+) -> Response {
+    let acao = match modo {
+        Some(_) => Acao::MudarManutencao,
+        None => Acao::VerManutencao,
+    };
+    if let Err(status) = autorizar_admin(&estado, &headers, local, acao).await {
+        return status.into_response();
+    }
+
+    match modo {
+        Some(Json(modo)) => {
+            let modo = estado.manutencao.mudar(modo);
+            println!(
+                "Modo somente leitura {}",
+                if modo.somente_leitura {
+                    "ativado"
+                } else {
+                    "desativado"
+                },
+            );
+            Json(modo).into_response()
+        },
+        None => Json(estado.manutencao.modo()).into_response(),
+    }
+}
+
+async fn interrupcao<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    modo: Option<Json<ModoInterrupcao>>,
+) -> Response {
+    let acao = match modo {
+        Some(_) => Acao::MudarManutencao,
+        None => Acao::VerManutencao,
+    };
+    if let Err(status) = autorizar_admin(&estado, &headers, local, acao).await {
+        return status.into_response();
+    }
+
+    match modo {
+        Some(Json(modo)) => {
+            let modo = estado.manutencao.mudar_interrupcao(modo);
+            println!(
+                "API pública {}",
+                if modo.interrompida {
+                    "interrompida"
+                } else {
+                    "de volta"
+                },
+            );
+            Json(modo).into_response()
+        },
+        None => Json(estado.manutencao.interrupcao()).into_response(),
+    }
+}
+
+async fn metricas<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+) -> Response {
+    if let Err(status) =
+        autorizar_admin(&estado, &headers, local, Acao::VerEstatisticas).await
+    {
+        return status.into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metricas_atuais().prometheus(),
+    )
+        .into_response()
+}
 
+/// O pedido de pausa de um lote em `PUT /api/admin/lotes/{id}`.
+#[derive(Deserialize)]
+struct PausaDoLote {
+    pausado: bool,
+}
+
+async fn lotes<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+) -> Response {
+    if let Err(status) =
+        autorizar_admin(&estado, &headers, local, Acao::VerLotes).await
+    {
+        return status.into_response();
+    }
+
+    match lotes::lotes(&estado.cfg.dados) {
+        Ok(lotes) => Json(lotes).into_response(),
+        Err(e) => {
+            eprintln!("Erro ao ler os lotes em andamento: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        },
+    }
+}
+
+async fn pausar_lote<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Path(id): Path<String>,
+    Json(pausa): Json<PausaDoLote>,
+) -> Response {
+    let supervisor =
+        match autorizar_admin(&estado, &headers, local, Acao::PausarLote).await
+        {
+            Ok(supervisor) => supervisor.unwrap_or_default(),
+            Err(status) => return status.into_response(),
+        };
+
+    match lotes::pausar(&estado.cfg.dados, &id, pausa.pausado) {
+        Ok(Some(lote)) => {
+            println!(
+                "O lote {id} foi {} por {supervisor}",
+                if lote.pausado { "pausado" } else { "retomado" },
+            );
+            Json(lote).into_response()
+        },
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Erro ao pausar o lote {id:?}: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        },
+    }
+}
+
+/// O pedido de conta de docente em `POST /api/admin/docentes`.
+#[derive(Deserialize)]
+struct PedidoDeConta {
+    nome: String,
+    email: String,
+    ou: String,
+    senha: SecretString,
+}
+
+/// A resposta com a `message` e o `status`, nas rotas dos docentes.
+fn resposta_docente(status: StatusCode, message: String) -> Response {
+    (
+        status,
+        Json(ResponseBody {
+            message,
+            sabar_mais: None,
+            proximos_passos: None,
+            triagem_nome: None,
+        }),
+    )
+        .into_response()
+}
+
+/// O supervisor que pede, aprova ou recusa uma conta de docente pela API.
+/// Como a aprovação precisa ser de outra pessoa, o token e o socket
+/// administrativo, que não dizem quem é, não valem.
+async fn supervisor_dos_docentes<F: FonteLdap>(
+    estado: &EstadoApi<F>,
+    headers: &HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    acao: Acao,
+) -> Result<String, Response> {
+    if estado.cfg.docentes.is_none() {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    }
+    match autorizar_admin(estado, headers, local, acao).await {
+        Ok(Some(supervisor)) => Ok(supervisor),
+        Ok(None) => Err(resposta_docente(
+            StatusCode::FORBIDDEN,
+            "As contas de docentes só são pedidas e aprovadas por um \
+             supervisor, com a própria conta"
+                .to_string(),
+        )),
+        Err(status) => Err(status.into_response()),
+    }
+}
+
+async fn pedidos_docentes<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+) -> Response {
+    if estado.cfg.docentes.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if let Err(status) =
+        autorizar_admin(&estado, &headers, local, Acao::VerPedidosDocentes)
+            .await
+    {
+        return status.into_response();
+    }
+
+    match docente::pedidos(&estado.cfg.dados) {
+        Ok(pedidos) => Json(pedidos).into_response(),
+        Err(e) => {
+            eprintln!("Erro ao ler os pedidos de docentes: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        },
+    }
+}
+
+async fn pedir_docente<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Json(pedido): Json<PedidoDeConta>,
+) -> Response {
+    let supervisor = match supervisor_dos_docentes(
+        &estado,
+        &headers,
+        local,
+        Acao::PedirDocente,
+    )
+    .await
+    {
+        Ok(supervisor) => supervisor,
+        Err(resposta) => return resposta,
+    };
+
+    let dados = DadosDoDocente {
+        nome: pedido.nome,
+        email: pedido.email,
+        ou: pedido.ou,
+        senha: pedido.senha,
+    };
+    match dados.pedir(&supervisor, &estado.cfg, Utc::now()).await {
+        Ok(pedido) => {
+            println!(
+                "A conta de docente {} foi pedida por {supervisor}, no \
+                 pedido {}",
+                pedido.nome, pedido.id,
+            );
+            (StatusCode::ACCEPTED, Json(pedido)).into_response()
+        },
+        Err(e) => resposta_docente(e.status(), format!("Erro: {e}")),
+    }
+}
+
+async fn aprovar_docente<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Path(id): Path<String>,
+) -> Response {
+    let supervisor = match supervisor_dos_docentes(
+        &estado,
+        &headers,
+        local,
+        Acao::AprovarDocente,
+    )
+    .await
+    {
+        Ok(supervisor) => supervisor,
+        Err(resposta) => return resposta,
+    };
+    if let Some(resposta) = estado.recusar_escrita() {
+        return resposta.into_response();
+    }
+
+    match docente::aprovar(
+        &id,
+        &supervisor,
+        &estado.cfg,
+        &estado.ldap,
+        Utc::now(),
+    )
+    .await
+    {
+        Ok((username, dn)) => {
+            println!(
+                "A conta de docente {username} foi criada em {dn}, aprovada \
+                 por {supervisor}"
+            );
+            resposta_docente(
+                StatusCode::CREATED,
+                format!("A conta {username} foi criada."),
+            )
+        },
+        Err(e) => resposta_docente(e.status(), format!("Erro: {e}")),
+    }
+}
+
+async fn recusar_docente<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Path(id): Path<String>,
+) -> Response {
+    let supervisor = match supervisor_dos_docentes(
+        &estado,
+        &headers,
+        local,
+        Acao::AprovarDocente,
+    )
+    .await
+    {
+        Ok(supervisor) => supervisor,
+        Err(resposta) => return resposta,
+    };
+
+    match docente::recusar(&id, &supervisor, &estado.cfg, Utc::now()).await {
+        Ok(pedido) => {
+            println!("O pedido {id} foi recusado por {supervisor}");
+            resposta_docente(
+                StatusCode::OK,
+                format!("O pedido da conta de {} foi recusado.", pedido.nome),
+            )
+        },
+        Err(e) => resposta_docente(e.status(), format!("Erro: {e}")),
+    }
+}
+
+/// O header com o código TOTP do supervisor nas operações destrutivas.
+pub const HEADER_TOTP: &str = "x-alumnic-totp";
+
+/// O header com o uid do supervisor que faz uma operação destrutiva pelo
+/// socket administrativo, onde não há senha.
+pub const HEADER_OPERADOR: &str = "x-alumnic-operador";
+
+/// Autoriza uma operação destrutiva como o [autorizar_admin]. Pelo socket
+/// administrativo, o supervisor é o do [`HEADER_OPERADOR`], se o papel dele
+/// permitir a `acao`; ele ainda precisa passar o próprio código TOTP.
+async fn autorizar_destrutiva<F: FonteLdap>(
+    estado: &EstadoApi<F>,
+    headers: &HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    acao: Acao,
+) -> Result<Option<String>, StatusCode> {
+    let pelo_socket = local.is_some();
+    let supervisor = autorizar_admin(estado, headers, local, acao).await?;
+    if supervisor.is_some() || !pelo_socket {
+        return Ok(supervisor);
+    }
+    let Some(uid) = headers
+        .get(HEADER_OPERADOR)
+        .and_then(|h| h.to_str().ok())
+        .map(str::trim)
+        .filter(|uid| !uid.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    match papel_do_supervisor(uid, &estado.cfg, &estado.ldap).await {
+        Ok(Some(papel)) if papel.permite(acao) => Ok(Some(uid.to_string())),
+        Ok(_) => Err(StatusCode::FORBIDDEN),
+        Err(e) => {
+            eprintln!("Erro ao buscar o supervisor {uid:?}: {e}");
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        },
+    }
+}
+
+/// Verifica o segundo fator das operações destrutivas: elas só são feitas
+/// por um `supervisor` com o código TOTP dele no [`HEADER_TOTP`], e nunca só
+/// com o token ou só pelo socket administrativo.
+fn segundo_fator<F>(
+    estado: &EstadoApi<F>,
+    totp: &ConfiguracaoTotp,
+    supervisor: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, Json<ResponseBody>)> {
+    let recusa = |status, message: String| {
+        (
+            status,
+            Json(ResponseBody {
+                message,
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: None,
+            }),
+        )
+    };
+    let Some(supervisor) = supervisor else {
+        return Err(recusa(
+            StatusCode::FORBIDDEN,
+            "Essa operação só pode ser feita por um supervisor, com o código \
+             TOTP (pelo socket, com o uid no X-Alumnic-Operador)"
+                .to_string(),
+        ));
+    };
+    let codigo = headers
+        .get(HEADER_TOTP)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+
+    estado
+        .totp
+        .verificar(totp, supervisor, codigo.trim(), Utc::now())
+        .map_err(|e| {
+            eprintln!("Segundo fator recusado para {supervisor:?}: {e}");
+            let status = match e {
+                ErroTotp::Bloqueado => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            recusa(status, format!("Erro: {e}"))
+        })
+}
+
+/// O tamanho das páginas de contas quando o `tamanho` não é informado.
+const TAMANHO_PAGINA_PADRAO: usize = 50;
+/// O maior tamanho de página de contas aceito.
+const TAMANHO_PAGINA_MAXIMO: usize = 500;
+/// Os atributos pelos quais as contas podem ser ordenadas.
+const ORDENACOES_DE_CONTAS: [&str; 4] =
+    ["uid", "dccDRE", "dataCriacao", "dataRenovacao"];
+
+#[derive(Deserialize)]
+struct ParametrosContas {
+    pagina: Option<usize>,
+    tamanho: Option<usize>,
+    /// O atributo da ordenação, com um `-` antes para a ordem decrescente.
+    ordenar: Option<String>,
+}
+
+impl ParametrosContas {
+    fn pedido(self) -> Result<PedidoPagina, String> {
+        let pagina = self.pagina.unwrap_or(1);
+        if pagina == 0 {
+            return Err("A página começa em 1.".to_string());
+        }
+        let tamanho = self.tamanho.unwrap_or(TAMANHO_PAGINA_PADRAO);
+        if !(1..=TAMANHO_PAGINA_MAXIMO).contains(&tamanho) {
+            return Err(format!(
+                "O tamanho da página deve ser de 1 a {TAMANHO_PAGINA_MAXIMO}."
+            ));
+        }
+        let ordenar = match self.ordenar.as_deref() {
+            None => None,
+            Some(ordenar) => {
+                let (decrescente, atributo) = match ordenar.strip_prefix('-') {
+                    Some(atributo) => (true, atributo),
+                    None => (false, ordenar),
+                };
+                let Some(atributo) = ORDENACOES_DE_CONTAS
+                    .iter()
+                    .find(|a| a.eq_ignore_ascii_case(atributo))
+                else {
+                    return Err(format!(
+                        "As contas só podem ser ordenadas por {}.",
+                        ORDENACOES_DE_CONTAS.join(", ")
+                    ));
+                };
+                Some(Ordenacao {
+                    atributo: atributo.to_string(),
+                    decrescente,
+                })
+            },
+        };
+        Ok(PedidoPagina {
+            pagina,
+            tamanho,
+            ordenar,
+        })
+    }
+}
+
+async fn contas<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Query(params): Query<ParametrosContas>,
+) -> Response {
+    if let Err(status) =
+        autorizar_admin(&estado, &headers, local, Acao::ListarContas).await
+    {
+        return status.into_response();
+    }
+    let pedido = match params.pedido() {
+        Ok(pedido) => pedido,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ResponseBody {
+                    message,
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+                .into_response();
+        },
+    };
+
+    let pagina: Result<_, ErroLdap> = async {
+        let mut conexao = estado.ldap.abrir().await?;
+        let r = listar_contas(&pedido, &mut conexao).await;
+        estado.ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+
+    match pagina {
+        Ok(pagina) => Json(serde_json::json!({
+            "pagina": pedido.pagina,
+            "tamanho": pedido.tamanho,
+            "total": pagina.total,
+            "ultima": pagina.ultima,
+            "contas": pagina.contas,
+        }))
+        .into_response(),
+        Err(e) => {
+            eprintln!("Erro ao listar as contas: {e}");
+            (
+                if e.indisponivel() {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                },
+                Json(ResponseBody {
+                    message: "Erro ao consultar o LDAP.".to_string(),
+                    sabar_mais: None,
+                    proximos_passos: None,
+                    triagem_nome: None,
+                }),
+            )
+                .into_response()
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct PedidoDeRemocao {
+    motivo: String,
+}
+
+#[derive(Deserialize)]
+struct PedidoDeSenha {
+    senha: SecretString,
+    motivo: String,
+}
+
+/// O `motivo` com o supervisor que fez a operação, para a auditoria.
+fn motivo_com_supervisor(motivo: &str, supervisor: &str) -> String {
+    match motivo.trim() {
+        "" => String::new(),
+        motivo => format!("{motivo} (pela API, por {supervisor})"),
+    }
+}
+
+async fn remover_conta<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Path(uid): Path<String>,
+    Json(pedido): Json<PedidoDeRemocao>,
+) -> Response {
+    let Some(totp) = &estado.cfg.totp else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let supervisor = match autorizar_destrutiva(
+        &estado,
+        &headers,
+        local,
+        Acao::RemoverConta,
+    )
+    .await
+    {
+        Ok(supervisor) => supervisor,
+        Err(status) => return status.into_response(),
+    };
+    // Sem gastar o código TOTP numa escrita que seria recusada
+    if let Some(resposta) = estado.recusar_escrita() {
+        return resposta.into_response();
+    }
+    if let Err(recusa) =
+        segundo_fator(&estado, totp, supervisor.as_deref(), &headers)
+    {
+        return recusa.into_response();
+    }
+
+    let supervisor = supervisor.unwrap_or_default();
+    let motivo = motivo_com_supervisor(&pedido.motivo, &supervisor);
+    match remover_agora(&uid, &motivo, &estado.cfg, &estado.ldap, Utc::now())
+        .await
+    {
+        Ok(conta) => {
+            println!("A conta {} foi removida por {supervisor}", conta.uid);
+            Json(ResponseBody {
+                message: format!("A conta {} foi removida.", conta.uid),
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: None,
+            })
+            .into_response()
+        },
+        Err(e) => (
+            e.status(),
+            Json(ResponseBody {
+                message: format!("Erro: {e}"),
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: None,
+            }),
+        )
+            .into_response(),
+    }
+}
+
+async fn redefinir_senha_da_conta<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    local: Option<Extension<SocketLocal>>,
+    Path(uid): Path<String>,
+    Json(pedido): Json<PedidoDeSenha>,
+) -> Response {
+    let Some(totp) = &estado.cfg.totp else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !estado.cfg.features.reset_senha {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let supervisor = match autorizar_destrutiva(
+        &estado,
+        &headers,
+        local,
+        Acao::RedefinirSenha,
+    )
+    .await
+    {
+        Ok(supervisor) => supervisor,
+        Err(status) => return status.into_response(),
+    };
+    if let Some(resposta) = estado.recusar_escrita() {
+        return resposta.into_response();
+    }
+    if let Err(recusa) =
+        segundo_fator(&estado, totp, supervisor.as_deref(), &headers)
+    {
+        return recusa.into_response();
+    }
+
+    let supervisor = supervisor.unwrap_or_default();
+    let motivo = motivo_com_supervisor(&pedido.motivo, &supervisor);
+    match redefinir_senha(
+        &uid,
+        &pedido.senha,
+        &motivo,
+        &estado.cfg,
+        &estado.ldap,
+        Utc::now(),
+    )
+    .await
+    {
+        Ok(()) => {
+            println!("A senha de {uid} foi redefinida por {supervisor}");
+            Json(ResponseBody {
+                message: format!("A senha da conta {uid} foi redefinida."),
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: None,
+            })
+            .into_response()
+        },
+        Err(e) => (
+            e.status(),
+            Json(ResponseBody {
+                message: format!("Erro: {e}"),
+                sabar_mais: None,
+                proximos_passos: None,
+                triagem_nome: None,
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// A situação de uma conta, como o helpdesk a vê.
+#[derive(Serialize)]
+struct ContaHelpdesk {
+    uid: String,
+    estado: &'static str,
+    /// Até quando a conta vale sem ser renovada.
+    valida_ate: Option<NaiveDate>,
+    /// Até quando a conta continua funcionando sem a renovação.
+    carencia_ate: Option<NaiveDate>,
+    /// Quando a conta suspensa será removida.
+    remocao_em: Option<NaiveDate>,
+}
+
+async fn helpdesk<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    Path(dre): Path<String>,
+) -> Response {
+    let cfg = &estado.cfg;
+    let Some(helpdesk) = &cfg.helpdesk else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(status) = autenticar(Some(&helpdesk.token), &headers) {
+        return status.into_response();
+    }
+
+    let contagem = estado
+        .limite_helpdesk
+        .lock()
+        .unwrap()
+        .contar(helpdesk.limite_por_minuto, Instant::now());
+    if let Err(espera) = contagem {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, (espera.as_secs() + 1).to_string())],
+        )
+            .into_response();
+    }
+
+    let conta: Result<_, ErroLdap> = async {
+        let mut conexao = estado.ldap.abrir().await?;
+        let r = buscar_conta_por_dre(&dre, &mut conexao).await;
+        estado.ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+
+    match conta {
+        Ok(Some(conta)) => Json(ContaHelpdesk {
+            uid: conta.uid,
+            estado: conta.estado.valor(),
+            valida_ate: conta.data_renovacao.map(dia_para_data),
+            carencia_ate: conta
+                .data_renovacao
+                .map(|d| dia_para_data(d + cfg.renovacao.carencia_dias)),
+            remocao_em: conta.data_remocao.map(dia_para_data),
+        })
+        .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            eprintln!("Erro na consulta do helpdesk: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        },
+    }
+}
+
+/// Uma resposta no formato do SCIM, com o `Content-Type` dele.
+fn resposta_scim(status: StatusCode, corpo: impl Serialize) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/scim+json")],
+        Json(corpo),
+    )
+        .into_response()
+}
+
+/// Um erro no formato do SCIM (RFC 7644, seção 3.12).
+fn erro_scim(status: StatusCode, detalhe: &str) -> Response {
+    resposta_scim(
+        status,
+        serde_json::json!({
+            "schemas": [SCHEMA_ERRO],
+            "status": status.as_u16().to_string(),
+            "detail": detalhe,
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ParametrosScim {
+    filter: Option<String>,
+    start_index: Option<usize>,
+    count: Option<usize>,
+}
+
+/// Busca os usuários SCIM, respondendo com o erro se não for possível.
+async fn usuarios_scim<F: FonteLdap>(
+    estado: &EstadoApi<F>,
+    headers: &HeaderMap,
+) -> Result<Vec<UsuarioScim>, Response> {
+    let Some(scim) = &estado.cfg.scim else {
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+    if let Err(status) = autenticar(Some(&scim.token), headers) {
+        return Err(erro_scim(status, "token inválido"));
+    }
+
+    let usuarios: Result<_, ErroLdap> = async {
+        let mut conexao = estado.ldap.abrir().await?;
+        let r = buscar_usuarios(&mut conexao).await;
+        estado.ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+
+    usuarios.map_err(|e| {
+        eprintln!("Erro na consulta SCIM: {e}");
+        erro_scim(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "erro ao consultar o LDAP",
+        )
+    })
+}
+
+async fn scim_listar<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    Query(params): Query<ParametrosScim>,
+) -> Response {
+    let filtro = match params.filter.as_deref().map(FiltroScim::interpretar) {
+        Some(Err(e)) => {
+            return erro_scim(StatusCode::BAD_REQUEST, &e.to_string());
+        },
+        Some(Ok(filtro)) => Some(filtro),
+        None => None,
+    };
+
+    let mut usuarios = match usuarios_scim(&estado, &headers).await {
+        Ok(usuarios) => usuarios,
+        Err(resposta) => return resposta,
+    };
+    if let Some(filtro) = filtro {
+        usuarios.retain(|u| filtro.aceita(u));
+    }
+
+    let maximo = estado.cfg.scim.as_ref().map_or(0, |s| s.maximo_por_pagina);
+    resposta_scim(
+        StatusCode::OK,
+        paginar(
+            usuarios,
+            params.start_index.unwrap_or(1),
+            params.count.unwrap_or(maximo).min(maximo),
+        ),
+    )
+}
+
+async fn scim_usuario<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let usuarios = match usuarios_scim(&estado, &headers).await {
+        Ok(usuarios) => usuarios,
+        Err(resposta) => return resposta,
+    };
+
+    match usuarios.into_iter().find(|u| u.id == id) {
+        Some(usuario) => resposta_scim(StatusCode::OK, usuario),
+        None => erro_scim(StatusCode::NOT_FOUND, "usuário não encontrado"),
+    }
+}
+
+/// Monta as rotas da API, que usam o diretório fornecido por `ldap`.
+pub fn router<F: FonteLdap + 'static>(
+    cfg: Arc<Configuracao>,
+    ldap: F,
+) -> Router {
+    let manutencao = Arc::new(Manutencao::nova(&cfg.manutencao));
+    router_com(cfg, ldap, manutencao)
+}
+
+/// Como o [router], mas com o modo de `manutencao` compartilhado com as
+/// tarefas periódicas.
+pub fn router_com<F: FonteLdap + 'static>(
+    cfg: Arc<Configuracao>,
+    ldap: F,
+    manutencao: Arc<Manutencao>,
+) -> Router {
+    rotas(EstadoApi::novo(cfg, ldap, manutencao))
+}
+
+/// As rotas da API servidas com o `estado`.
+fn rotas<F: FonteLdap + 'static>(estado: Arc<EstadoApi<F>>) -> Router {
+    // As rotas dos alunos, que saem do ar com a interrupção
+    let publica = Router::new()
+        .route("/api/cadastrar", post(cadastrar::<F>))
+        .route("/api/validar", post(validar::<F>))
+        .route("/api/renovar", post(renovar::<F>))
+        .route("/api/usernames", get(usernames::<F>));
+    #[cfg(feature = "pdf")]
+    let publica = publica.route("/api/documento", post(ler_documento::<F>));
+    let publica = publica.route_layer(middleware::from_fn_with_state(
+        estado.clone(),
+        interromper::<F>,
+    ));
+
+    // As rotas administrativas, que somem com a `api_admin` desligada
+    let admin = Router::new()
+        .route("/api/admin/estatisticas", get(estatisticas::<F>))
+        .route("/api/admin/metricas", get(metricas::<F>))
+        .route(
+            "/api/admin/somente-leitura",
+            get(somente_leitura::<F>).put(somente_leitura::<F>),
+        )
+        .route(
+            "/api/admin/manutencao",
+            get(interrupcao::<F>).put(interrupcao::<F>),
+        )
+        .route("/api/admin/contas", get(contas::<F>))
+        .route("/api/admin/lotes", get(lotes::<F>))
+        .route("/api/admin/lotes/{id}", put(pausar_lote::<F>))
+        .route(
+            "/api/admin/docentes",
+            get(pedidos_docentes::<F>).post(pedir_docente::<F>),
+        )
+        .route(
+            "/api/admin/docentes/{id}/aprovar",
+            post(aprovar_docente::<F>),
+        )
+        .route(
+            "/api/admin/docentes/{id}/recusar",
+            post(recusar_docente::<F>),
+        )
+        .route("/api/admin/contas/{uid}/remover", post(remover_conta::<F>))
+        .route(
+            "/api/admin/contas/{uid}/senha",
+            post(redefinir_senha_da_conta::<F>),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            estado.clone(),
+            esconder_admin::<F>,
+        ));
+
+    Router::new()
+        .merge(publica)
+        .merge(admin)
+        .route("/api/saude", get(saude::<F>))
+        .route("/api/helpdesk/contas/{dre}", get(helpdesk::<F>))
+        .route("/scim/v2/Users", get(scim_listar::<F>))
+        .route("/scim/v2/Users/{id}", get(scim_usuario::<F>))
+        .merge(painel::rotas::<F>())
+        .with_state(estado)
+}
+
+pub async fn main<F: FonteLdap + Clone + 'static>(
+    address: String,
+    cfg: Arc<Configuracao>,
+    ldap: F,
+) {
+    let manutencao = Arc::new(Manutencao::nova(&cfg.manutencao));
+    tokio::spawn(alternar_pelo_sinal(manutencao.clone()));
+
+    // As requisições e as tarefas periódicas reusam as conexões umas das
+    // outras, em vez de abrir uma com o TLS e o bind a cada vez
+    let ldap = LdapPool::new(ldap, &cfg.ldap_pool);
     let listener = tokio::net::TcpListener::bind(address).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let socket_admin = cfg.socket_admin.clone();
+    let estado = EstadoApi::novo(cfg.clone(), ldap.clone(), manutencao.clone());
+    let cancelamento = estado.cancelamento.clone();
+    let tarefas = estado.tarefas.clone();
+    tokio::spawn(cancelar_no_termino(cancelamento.clone()));
+
+    tarefas.spawn(agendador::rodar(
+        ldap,
+        cfg,
+        manutencao,
+        cancelamento.clone(),
+    ));
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc) = &estado.cfg.grpc {
+        tarefas.spawn(crate::grpc::servir(grpc.endereco, estado.clone()));
+    }
+
+    if let Some(fila) = &estado.cfg.usuario_novo.contingencia {
+        tarefas.spawn(processar_contingencia(estado.clone(), fila.intervalo()));
+    }
+
+    let limpeza = estado.cfg.limpeza.intervalo();
+    tarefas.spawn(limpar_periodicamente(estado.clone(), limpeza));
+
+    let app = rotas(estado);
+
+    if let Some(socket) = socket_admin {
+        let local = abrir_socket_admin(&socket).unwrap_or_else(|e| {
+            panic!("Não foi possível abrir o socket {:?}: {e}", socket.caminho)
+        });
+        let app = com_socket_local(app.clone());
+        let termino = cancelamento.clone().cancelled_owned();
+        tarefas.spawn(async move {
+            if let Err(e) = axum::serve(local, app)
+                .with_graceful_shutdown(termino)
+                .await
+            {
+                eprintln!("Erro no socket administrativo: {e}");
+            }
+        });
+    }
+
+    // O endereço de quem se conecta é usado na contagem dos cadastros por IP.
+    // No término, as requisições em andamento são respondidas antes de sair
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(cancelamento.cancelled_owned())
+    .await
+    .unwrap();
+
+    tarefas.close();
+    tarefas.wait().await;
+    println!("Serviço encerrado");
+}
+
+/// Tenta a [fila de contingência](contingencia) a cada `intervalo`, até o
+/// cancelamento, registrando os cadastros feitos nas estatísticas e nos hooks
+/// como os da API. As tentativas durante a [manutenção](Manutencao) são
+/// puladas.
+async fn processar_contingencia<F: FonteLdap + 'static>(
+    estado: Arc<EstadoApi<F>>,
+    intervalo: Duration,
+) {
+    let mut intervalo = tokio::time::interval(intervalo);
+
+    loop {
+        tokio::select! {
+            _ = intervalo.tick() => {},
+            _ = estado.cancelamento.cancelled() => return,
+        }
+        if estado.manutencao.somente_leitura().is_some() {
+            continue;
+        }
+
+        let processamento = contingencia::processar_fila(
+            &estado.cfg,
+            &estado.ldap,
+            &estado.cancelamento,
+        )
+        .await;
+        match processamento {
+            Ok(p) => {
+                for cadastro in &p.realizados {
+                    println!(
+                        "O cadastro de {:?} na fila de contingência foi feito \
+                         como {:?}",
+                        cadastro.dre, cadastro.username,
+                    );
+                    estado.depois_do_cadastro(cadastro);
+                }
+                for (dre, e) in &p.falhas {
+                    estado.registrar_falha_de_cadastro(dre, e);
+                }
+            },
+            Err(e) => eprintln!("Erro ao ler a fila de contingência: {e}"),
+        }
+    }
+}
+
+/// Apaga de tempos em tempos o estado auxiliar expirado: as reservas de
+/// username vencidas no LDAP e, em memória, as sessões do painel e as janelas
+/// dos cadastros por IP. As reservas ficam para depois no modo somente
+/// leitura.
+async fn limpar_periodicamente<F: FonteLdap + 'static>(
+    estado: Arc<EstadoApi<F>>,
+    intervalo: Duration,
+) {
+    let mut intervalo = tokio::time::interval(intervalo);
+
+    loop {
+        tokio::select! {
+            _ = intervalo.tick() => {},
+            _ = estado.cancelamento.cancelled() => return,
+        }
+
+        let agora = Instant::now();
+        registrar_expirados("sessao_painel", estado.sessoes.limpar(agora));
+        if let Some(cfg) = &estado.cfg.cadastros_por_ip {
+            let janelas =
+                estado.cadastros_por_ip.lock().unwrap().limpar(cfg, agora);
+            registrar_expirados("cadastros_por_ip", janelas);
+        }
+
+        if estado.manutencao.somente_leitura().is_some() {
+            continue;
+        }
+        let limpeza: Result<_, ErroLdap> = async {
+            let mut conexao = estado.ldap.abrir().await?;
+            let r = limpar_reservas(Utc::now(), &mut conexao).await;
+            estado.ldap.fechar(conexao).await?;
+            r
+        }
+        .await;
+        match limpeza {
+            Ok(0) => {},
+            Ok(n) => {
+                println!("{n} reservas de username vencidas foram apagadas");
+                registrar_expirados("reserva_username", n);
+            },
+            Err(e) => {
+                eprintln!("Erro ao apagar as reservas de username: {e}")
+            },
+        }
+    }
+}
+
+/// Cria o socket Unix da `cfg` já com as permissões configuradas. O socket
+/// é criado dentro de um diretório temporário que só o alumnic acessa e só
+/// depois de receber as permissões é movido para o caminho final, para que
+/// ninguém consiga se conectar nele antes disso, qualquer que seja a umask.
+pub fn abrir_socket_admin(
+    cfg: &ConfiguracaoSocketAdmin,
+) -> std::io::Result<UnixListener> {
+    // Um socket que sobrou é trocado, mas outro tipo de arquivo não
+    if let Ok(existente) = std::fs::symlink_metadata(&cfg.caminho)
+        && !existente.file_type().is_socket()
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "o caminho já existe e não é um socket",
+        ));
+    }
+
+    let mut privado = cfg.caminho.clone().into_os_string();
+    privado.push(".novo");
+    let privado = PathBuf::from(privado);
+    // O que sobrou de uma execução interrompida
+    let _ = std::fs::remove_file(&privado);
+    let _ = std::fs::remove_dir_all(&privado);
+    // O `create` falha se o diretório voltar a existir nesse meio tempo
+    DirBuilder::new().mode(0o700).create(&privado)?;
+
+    let r = (|| {
+        std::fs::set_permissions(&privado, Permissions::from_mode(0o700))?;
+        let novo = privado.join("admin.sock");
+        let listener = UnixListener::bind(&novo)?;
+        std::fs::set_permissions(&novo, Permissions::from_mode(cfg.modo))?;
+        std::fs::rename(&novo, &cfg.caminho)?;
+        Ok(listener)
+    })();
+    let _ = std::fs::remove_dir_all(&privado);
+    r
+}
+
+/// O `app` servido pelo [socket administrativo](abrir_socket_admin), com as
+/// rotas administrativas liberadas sem o token.
+pub fn com_socket_local(app: Router) -> Router {
+    app.layer(Extension(SocketLocal))
+}
+
+/// Liga e desliga a interrupção da API pública a cada `SIGUSR1`.
+async fn alternar_pelo_sinal(manutencao: Arc<Manutencao>) {
+    let mut sinais = match signal(SignalKind::user_defined1()) {
+        Ok(sinais) => sinais,
+        Err(e) => {
+            eprintln!("Não foi possível receber o SIGUSR1: {e}");
+            return;
+        },
+    };
+    while sinais.recv().await.is_some() {
+        let interrompida = manutencao.alternar_interrupcao();
+        println!(
+            "API pública {} pelo SIGUSR1",
+            if interrompida {
+                "interrompida"
+            } else {
+                "de volta"
+            },
+        );
+    }
 }
@@ -0,0 +1,240 @@
+//! Registro das operações feitas pela supervisão nas contas. Cada operação é
+//! uma linha JSON acrescentada ao arquivo de auditoria.
+//!
+//! Como a auditoria é usada para investigar incidentes, as linhas são
+//! encadeadas: cada uma guarda em `anterior` o `hash` da linha anterior, e no
+//! `hash` o SHA-256 do seu próprio texto, sem o `hash`. Uma linha alterada
+//! deixa de bater com o próprio hash, e uma linha apagada, inserida ou trocada
+//! de lugar quebra o encadeamento da seguinte, o que o [verificar] encontra.
+//! Só o fim da trilha pode sumir sem deixar rastro, e por isso o último hash
+//! deve ser guardado fora da máquina, para a verificação com o `ultimo`.
+use chrono::{DateTime, Utc};
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+
+/// Configuração da auditoria.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ConfiguracaoAuditoria {
+    /// O arquivo onde as operações são registradas. Se não for definido,
+    /// nada é registrado.
+    pub arquivo: Option<PathBuf>,
+}
+
+/// Uma operação feita em uma conta.
+#[derive(Debug, Serialize)]
+pub struct Registro<'a> {
+    pub quando: DateTime<Utc>,
+    /// O nome da operação, como `reativar`.
+    pub operacao: &'a str,
+    pub uid: &'a str,
+    /// A justificativa dada por quem fez a operação.
+    pub motivo: &'a str,
+}
+
+/// O que separa o `hash` do resto da linha, que termina com ele.
+const CAMPO_HASH: &str = ",\"hash\":\"";
+
+/// Acrescenta o `registro` ao arquivo de auditoria, encadeado com a última
+/// linha. O arquivo fica travado enquanto a última linha é lida e a nova é
+/// escrita, para que dois processos não encadeiem no mesmo registro.
+pub async fn registrar(
+    cfg: &ConfiguracaoAuditoria,
+    registro: &Registro<'_>,
+) -> std::io::Result<()> {
+    let Some(arquivo) = &cfg.arquivo else {
+        return Ok(());
+    };
+
+    let registro = serde_json::to_string(registro)?;
+    let arquivo = arquivo.clone();
+    tokio::task::spawn_blocking(move || {
+        acrescentar_encadeado(&arquivo, &registro)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// O SHA-256 do `texto`, em hexadecimal.
+fn hash(texto: &str) -> String {
+    hex::encode(digest(&SHA256, texto.as_bytes()))
+}
+
+/// Separa a `linha` no texto sem o `hash` e no `hash`, ou `None` se ela não
+/// estiver encadeada, como as gravadas antes do encadeamento.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::auditoria::separar_hash;
+/// assert_eq!(
+///     separar_hash(r#"{"uid":"a","anterior":"","hash":"abc"}"#),
+///     Some((r#"{"uid":"a","anterior":""}"#.to_string(), "abc")),
+/// );
+/// assert_eq!(separar_hash(r#"{"uid":"a"}"#), None);
+/// ```
+pub fn separar_hash(linha: &str) -> Option<(String, &str)> {
+    let (texto, hash) = linha.strip_suffix("\"}")?.rsplit_once(CAMPO_HASH)?;
+    Some((format!("{texto}}}"), hash))
+}
+
+/// A última linha do arquivo `f`, lida de trás para frente.
+fn ultima_linha(f: &mut File) -> io::Result<String> {
+    let tamanho = f.metadata()?.len();
+    let mut janela = 4096;
+    loop {
+        let inicio = tamanho.saturating_sub(janela);
+        let mut fim = vec![];
+        f.seek(SeekFrom::Start(inicio))?;
+        f.read_to_end(&mut fim)?;
+        let fim = String::from_utf8_lossy(&fim);
+        let fim = fim.trim_end_matches('\n');
+        match fim.rsplit_once('\n') {
+            Some((_, linha)) => return Ok(linha.to_string()),
+            None if inicio == 0 => return Ok(fim.to_string()),
+            None => janela *= 2,
+        }
+    }
+}
+
+/// Acrescenta a linha do `registro`, já em JSON, ao `arquivo`, com o
+/// `anterior` e o `hash`.
+fn acrescentar_encadeado(arquivo: &Path, registro: &str) -> io::Result<()> {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(arquivo)?;
+    // A trava é desfeita quando o arquivo é fechado
+    f.lock()?;
+
+    let ultima = ultima_linha(&mut f)?;
+    let anterior = separar_hash(&ultima).map_or("", |(_, hash)| hash);
+    let sem_hash = format!(
+        "{},\"anterior\":\"{anterior}\"}}",
+        registro.strip_suffix('}').unwrap_or(registro),
+    );
+    let linha = format!(
+        "{}{CAMPO_HASH}{}\"}}\n",
+        &sem_hash[..sem_hash.len() - 1],
+        hash(&sem_hash),
+    );
+    f.write_all(linha.as_bytes())?;
+    f.sync_data()
+}
+
+/// Um problema encontrado na [verificação](verificar) da trilha, com o
+/// número da linha, a partir de 1.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Adulteracao {
+    #[error("a linha {0} foi alterada: o conteúdo não bate com o hash")]
+    Alterada(usize),
+    #[error(
+        "o encadeamento quebra na linha {0}: a linha anterior foi apagada, \
+         inserida ou trocada de lugar"
+    )]
+    EncadeamentoQuebrado(usize),
+    #[error("a linha {0} não está encadeada, mas as anteriores estão")]
+    SemEncadeamento(usize),
+    #[error("a linha {0} foi encadeada, mas o hash dela foi apagado")]
+    HashApagado(usize),
+    #[error(
+        "o último hash guardado não está na trilha: ela foi truncada ou reescrita"
+    )]
+    UltimoAusente,
+}
+
+/// O resultado da [verificação](verificar) da trilha.
+#[derive(Debug, Default)]
+pub struct Verificacao {
+    /// Quantas linhas estão encadeadas.
+    pub encadeadas: usize,
+    /// Quantas linhas do começo foram gravadas antes do encadeamento.
+    pub antigas: usize,
+    /// O hash da última linha, que deve ser guardado para a próxima
+    /// verificação.
+    pub ultimo_hash: Option<String>,
+    /// Quantas linhas vieram depois da com o `ultimo` hash dado.
+    pub depois_do_ultimo: Option<usize>,
+    pub problemas: Vec<Adulteracao>,
+}
+
+/// Verifica o encadeamento da trilha de `texto`, lida do arquivo de
+/// auditoria. Com o `ultimo` hash guardado numa verificação anterior, também
+/// confere que a linha dele continua na trilha, o que revela o truncamento.
+///
+/// As linhas do começo sem encadeamento, gravadas antes dele existir, são
+/// contadas à parte; depois da primeira linha encadeada, todas precisam ser.
+/// Uma linha com o `anterior` mas sem o `hash` não é antiga: o hash dela foi
+/// apagado. Sem o `ultimo`, uma trilha em que todas as linhas foram
+/// reescritas como antigas não tem como ser distinguida de uma antiga de
+/// verdade.
+pub fn verificar(texto: &str, ultimo: Option<&str>) -> Verificacao {
+    let mut v = Verificacao::default();
+    let mut anterior: Option<String> = None;
+
+    for (i, linha) in texto.lines().enumerate() {
+        let numero = i + 1;
+        let Some((sem_hash, hash_gravado)) = separar_hash(linha) else {
+            let encadeada = serde_json::from_str::<serde_json::Value>(linha)
+                .is_ok_and(|valor| valor.get("anterior").is_some());
+            if encadeada {
+                v.problemas.push(Adulteracao::HashApagado(numero));
+            } else if anterior.is_some() {
+                v.problemas.push(Adulteracao::SemEncadeamento(numero));
+            } else {
+                v.antigas += 1;
+            }
+            continue;
+        };
+        v.encadeadas += 1;
+
+        if hash(&sem_hash) != hash_gravado {
+            v.problemas.push(Adulteracao::Alterada(numero));
+        }
+        let encadeado_em = serde_json::from_str::<serde_json::Value>(&sem_hash)
+            .ok()
+            .and_then(|valor| {
+                Some(valor.get("anterior")?.as_str()?.to_string())
+            });
+        if encadeado_em.as_deref() != Some(anterior.as_deref().unwrap_or("")) {
+            v.problemas.push(Adulteracao::EncadeamentoQuebrado(numero));
+        }
+
+        if let Some(depois) = &mut v.depois_do_ultimo {
+            *depois += 1;
+        } else if ultimo == Some(hash_gravado) {
+            v.depois_do_ultimo = Some(0);
+        }
+        anterior = Some(hash_gravado.to_string());
+    }
+
+    if ultimo.is_some() && v.depois_do_ultimo.is_none() {
+        v.problemas.push(Adulteracao::UltimoAusente);
+    }
+    v.ultimo_hash = anterior;
+    v
+}
+
+/// Acrescenta o `valor` ao `arquivo` como uma linha JSON.
+pub(crate) async fn acrescentar_linha(
+    arquivo: &Path,
+    valor: &impl Serialize,
+) -> std::io::Result<()> {
+    let mut linha = serde_json::to_string(valor)?;
+    linha.push('\n');
+
+    let mut f = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(arquivo)
+        .await?;
+    f.write_all(linha.as_bytes()).await?;
+    // O tokio só termina a escrita no arquivo no flush
+    f.flush().await
+}
@@ -0,0 +1,252 @@
+//! Os papéis dos supervisores e o que cada um pode fazer. O supervisor entra
+//! com a própria conta do LDAP, no [painel](crate::painel) ou nas rotas
+//! administrativas da API, e recebe o papel configurado para ele ou para um
+//! dos grupos dele. Toda operação administrativa passa pelo
+//! [`Papel::permite`], que concentra a decisão.
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Scope, SearchEntry};
+use secrecy::SecretString;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// O que um supervisor pode fazer, do menor para o maior papel. Cada papel
+/// pode tudo o que os anteriores podem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Papel {
+    /// Só consulta: o painel, a busca, as estatísticas e as métricas.
+    Leitura,
+    /// Também faz as operações do dia a dia sobre as contas.
+    Operador,
+    /// Também muda os modos de manutenção da API e remove contas.
+    Admin,
+}
+
+/// Uma operação administrativa, seja pelo painel ou pela API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Acao {
+    /// Ver o painel e buscar os alunos.
+    VerPainel,
+    /// Ver as estatísticas e as métricas da API.
+    VerEstatisticas,
+    /// Ver o modo somente leitura e a interrupção da API pública.
+    VerManutencao,
+    /// Listar as contas cadastradas.
+    ListarContas,
+    /// Ver o progresso dos [lotes](crate::lotes) em andamento.
+    VerLotes,
+    /// Ver os pedidos de contas de [docentes](crate::docente).
+    VerPedidosDocentes,
+    /// Reativar uma conta suspensa.
+    Reativar,
+    /// Aplicar os prazos das contas fora do horário agendado.
+    AplicarPrazos,
+    /// Criar as caixas de email pendentes.
+    CriarCaixas,
+    /// Pausar ou retomar um lote em andamento.
+    PausarLote,
+    /// Pedir uma conta de docente ou técnico.
+    PedirDocente,
+    /// Aprovar ou recusar o pedido de conta de docente de outro operador.
+    AprovarDocente,
+    /// Ligar ou desligar o modo somente leitura ou a interrupção.
+    MudarManutencao,
+    /// Redefinir a senha de uma conta. Exige também o
+    /// [segundo fator](crate::totp).
+    RedefinirSenha,
+    /// Remover uma conta na hora. Exige também o [segundo fator](crate::totp).
+    RemoverConta,
+}
+
+impl Acao {
+    /// O menor papel que pode fazer a ação.
+    pub fn papel_minimo(self) -> Papel {
+        match self {
+            Acao::VerPainel
+            | Acao::VerEstatisticas
+            | Acao::VerManutencao
+            | Acao::ListarContas
+            | Acao::VerLotes
+            | Acao::VerPedidosDocentes => Papel::Leitura,
+            Acao::Reativar
+            | Acao::AplicarPrazos
+            | Acao::CriarCaixas
+            | Acao::PausarLote
+            | Acao::PedirDocente
+            | Acao::AprovarDocente
+            | Acao::RedefinirSenha => Papel::Operador,
+            Acao::MudarManutencao | Acao::RemoverConta => Papel::Admin,
+        }
+    }
+}
+
+impl Papel {
+    /// Se o papel pode fazer a `acao`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::autorizacao::{Acao, Papel};
+    /// assert!(Papel::Leitura.permite(Acao::VerPainel));
+    /// assert!(!Papel::Leitura.permite(Acao::Reativar));
+    /// assert!(Papel::Operador.permite(Acao::Reativar));
+    /// assert!(!Papel::Operador.permite(Acao::MudarManutencao));
+    /// assert!(Papel::Admin.permite(Acao::MudarManutencao));
+    /// ```
+    pub fn permite(self, acao: Acao) -> bool {
+        self >= acao.papel_minimo()
+    }
+}
+
+/// Os papéis dos supervisores, configurados como
+///
+/// ```yaml
+/// autorizacao:
+///   padrao: leitura
+///   usuarios:
+///     fulano: admin
+///   grupos:
+///     "cn=lci,ou=grupos,dc=dcc,dc=ufrj,dc=br": operador
+/// ```
+///
+/// Quem pode entrar continua sendo decidido pelo grupo do
+/// [painel](crate::painel::ConfiguracaoPainel). Sem essa configuração, todos
+/// os supervisores são `admin`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoAutorizacao {
+    /// O papel de quem não está em `usuarios` nem em nenhum dos `grupos`.
+    #[serde(default = "padrao_padrao")]
+    pub padrao: Papel,
+    /// O papel de cada uid.
+    #[serde(default)]
+    pub usuarios: BTreeMap<String, Papel>,
+    /// O papel dos membros, no `memberUid`, de cada grupo (um
+    /// `posixGroup`).
+    #[serde(default)]
+    pub grupos: BTreeMap<String, Papel>,
+}
+
+fn padrao_padrao() -> Papel {
+    Papel::Leitura
+}
+
+/// O que o LDAP diz de um supervisor.
+struct Supervisor {
+    /// As contas com o uid dele, que deve ser uma só.
+    contas: Vec<SearchEntry>,
+    /// Se ele está no grupo do painel.
+    membro: bool,
+    /// Os papéis dos grupos dele.
+    papeis: Vec<Papel>,
+}
+
+/// Busca o supervisor `uid`, ou `None` se o painel não estiver configurado.
+async fn buscar_supervisor<F: FonteLdap>(
+    uid: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+) -> Result<Option<Supervisor>, ErroLdap> {
+    let Some(painel) = &cfg.painel else {
+        return Ok(None);
+    };
+    let filtro = filtros::igual("memberUid", uid);
+    let grupos = cfg
+        .autorizacao
+        .as_ref()
+        .map(|a| a.grupos.iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        let contas = conexao
+            .buscar(
+                BASE_CONTAS,
+                Scope::Subtree,
+                &filtros::igual("uid", uid),
+                vec!["uid"],
+            )
+            .await?;
+        let membro = !conexao
+            .buscar(&painel.grupo, Scope::Base, &filtro, vec!["cn"])
+            .await?
+            .is_empty();
+
+        let mut papeis = vec![];
+        for (grupo, papel) in grupos {
+            if !conexao
+                .buscar(grupo, Scope::Base, &filtro, vec!["cn"])
+                .await?
+                .is_empty()
+            {
+                papeis.push(*papel);
+            }
+        }
+        Ok::<_, ErroLdap>(Supervisor {
+            contas,
+            membro,
+            papeis,
+        })
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    r.map(Some)
+}
+
+/// O maior papel entre o do `uid` e os `papeis` dos grupos dele.
+fn maior_papel(uid: &str, cfg: &Configuracao, papeis: Vec<Papel>) -> Papel {
+    let Some(autorizacao) = &cfg.autorizacao else {
+        return Papel::Admin;
+    };
+    papeis
+        .into_iter()
+        .chain(autorizacao.usuarios.get(uid).copied())
+        .max()
+        .unwrap_or(autorizacao.padrao)
+}
+
+/// Autentica o supervisor `uid` com a `senha` e retorna o papel dele, ou
+/// `None` se a senha estiver errada, se ele não estiver no grupo do painel
+/// ou se o painel não estiver configurado. Vale o maior papel entre o do
+/// uid e o dos grupos dele.
+pub async fn autenticar_supervisor<F: FonteLdap>(
+    uid: &str,
+    senha: &SecretString,
+    cfg: &Configuracao,
+    ldap: &F,
+) -> Result<Option<Papel>, ErroLdap> {
+    let Some(supervisor) = buscar_supervisor(uid, cfg, ldap).await? else {
+        return Ok(None);
+    };
+
+    // A senha é verificada mesmo de quem não é supervisor, para que a
+    // resposta não revele quem é
+    let [conta] = &supervisor.contas[..] else {
+        return Ok(None);
+    };
+    if !ldap.verificar_senha(&conta.dn, senha).await? || !supervisor.membro {
+        return Ok(None);
+    }
+
+    Ok(Some(maior_papel(uid, cfg, supervisor.papeis)))
+}
+
+/// O papel do supervisor `uid`, sem a senha, para quem já foi autorizado de
+/// outra forma, como pelo socket administrativo. `None` se ele não estiver
+/// no grupo do painel ou se o painel não estiver configurado.
+pub async fn papel_do_supervisor<F: FonteLdap>(
+    uid: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+) -> Result<Option<Papel>, ErroLdap> {
+    match buscar_supervisor(uid, cfg, ldap).await? {
+        Some(s) if s.membro && s.contas.len() == 1 => {
+            Ok(Some(maior_papel(uid, cfg, s.papeis)))
+        },
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,169 @@
+//! Backups das contas em LDIF, assinados com ed25519. Os dumps têm os dados
+//! pessoais e os hashes das senhas dos alunos, então cada arquivo sai com uma
+//! assinatura ao lado, em `<arquivo>.sig`, e a restauração recusa o arquivo
+//! cuja assinatura não confere, seja ele adulterado ou truncado.
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::ldif::escrever_ldif;
+use base64::prelude::*;
+use ldap3::Scope;
+use ring::rand::SystemRandom;
+use ring::signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey};
+use serde::Deserialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// As chaves dos backups, configuradas como
+///
+/// ```yaml
+/// backup:
+///   chave_privada: "/etc/alumnic/backup.pk8"
+///   chave_publica: "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo="
+/// ```
+///
+/// A chave privada, em PKCS#8, só é necessária onde os backups são feitos, e
+/// é gerada com `alumnic gerar-chave-backup`. Sem essa configuração, os
+/// backups não são feitos e a restauração aceita arquivos sem assinatura.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoBackup {
+    #[serde(default)]
+    pub chave_privada: Option<PathBuf>,
+    /// A chave pública, em base64, com que as assinaturas são verificadas.
+    pub chave_publica: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeBackup {
+    #[error("O backup não está configurado com uma chave privada")]
+    SemChavePrivada,
+    #[error("A chave privada do backup é inválida: {0}")]
+    ChavePrivadaInvalida(String),
+    #[error("A chave pública do backup não é uma chave ed25519 em base64")]
+    ChavePublicaInvalida,
+    #[error("O backup não tem a assinatura em {0}")]
+    SemAssinatura(PathBuf),
+    #[error("A assinatura não confere; o backup foi adulterado ou truncado")]
+    AssinaturaInvalida,
+    #[error("Houve um problema ao ler as contas no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("Houve um problema ao ler ou gravar o backup: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Gera um par de chaves para os backups, retornando a chave privada em
+/// PKCS#8 e a pública em base64, como vai na configuração.
+pub fn gerar_chave() -> Result<(Vec<u8>, String), ErroDeBackup> {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|e| ErroDeBackup::ChavePrivadaInvalida(e.to_string()))?;
+    let par = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|e| ErroDeBackup::ChavePrivadaInvalida(e.to_string()))?;
+    let publica = BASE64_STANDARD.encode(par.public_key());
+    Ok((pkcs8.as_ref().to_vec(), publica))
+}
+
+/// O arquivo com a assinatura do backup em `arquivo`.
+pub fn arquivo_da_assinatura(arquivo: &Path) -> PathBuf {
+    let mut assinatura = arquivo.as_os_str().to_owned();
+    assinatura.push(".sig");
+    assinatura.into()
+}
+
+/// Assina os `dados` com a chave privada `pkcs8`, retornando a assinatura
+/// em base64.
+pub fn assinar(pkcs8: &[u8], dados: &[u8]) -> Result<String, ErroDeBackup> {
+    let par = Ed25519KeyPair::from_pkcs8(pkcs8)
+        .map_err(|e| ErroDeBackup::ChavePrivadaInvalida(e.to_string()))?;
+    Ok(BASE64_STANDARD.encode(par.sign(dados)))
+}
+
+/// Verifica a `assinatura`, em base64, dos `dados` com a `chave_publica`.
+pub fn verificar(
+    chave_publica: &str,
+    dados: &[u8],
+    assinatura: &str,
+) -> Result<(), ErroDeBackup> {
+    let chave = BASE64_STANDARD
+        .decode(chave_publica.trim())
+        .map_err(|_| ErroDeBackup::ChavePublicaInvalida)?;
+    if chave.len() != 32 {
+        return Err(ErroDeBackup::ChavePublicaInvalida);
+    }
+    let assinatura = BASE64_STANDARD
+        .decode(assinatura.trim())
+        .map_err(|_| ErroDeBackup::AssinaturaInvalida)?;
+
+    UnparsedPublicKey::new(&ED25519, chave)
+        .verify(dados, &assinatura)
+        .map_err(|_| ErroDeBackup::AssinaturaInvalida)
+}
+
+/// Grava em `destino` o backup de todas as contas em LDIF, com a assinatura
+/// ao lado. Retorna quantas contas foram gravadas. O LDIF, com os hashes das
+/// senhas, é criado só para o dono, e um backup que já exista em `destino`
+/// não é sobrescrito.
+pub async fn fazer_backup<F: FonteLdap>(
+    destino: &Path,
+    cfg: &Configuracao,
+    ldap: &F,
+) -> Result<usize, ErroDeBackup> {
+    let chave = cfg
+        .backup
+        .as_ref()
+        .and_then(|b| b.chave_privada.as_ref())
+        .ok_or(ErroDeBackup::SemChavePrivada)?;
+    let pkcs8 = std::fs::read(chave)?;
+
+    let mut conexao = ldap.abrir().await?;
+    let contas = conexao
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            "(objectClass=posixAccount)",
+            vec!["*"],
+        )
+        .await;
+    ldap.fechar(conexao).await?;
+    let mut contas = contas?;
+    contas.sort_by(|a, b| a.dn.cmp(&b.dn));
+
+    let ldif = escrever_ldif(&contas);
+    let assinatura = assinar(&pkcs8, ldif.as_bytes())?;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(destino)?
+        .write_all(ldif.as_bytes())?;
+    std::fs::write(arquivo_da_assinatura(destino), assinatura + "\n")?;
+
+    Ok(contas.len())
+}
+
+/// Lê o backup em `arquivo`, verificando a assinatura dele se os backups
+/// estiverem configurados.
+pub fn ler_backup(
+    arquivo: &Path,
+    cfg: &Configuracao,
+) -> Result<String, ErroDeBackup> {
+    let dados = std::fs::read(arquivo)?;
+
+    if let Some(backup) = &cfg.backup {
+        let caminho = arquivo_da_assinatura(arquivo);
+        let assinatura = match std::fs::read_to_string(&caminho) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(ErroDeBackup::SemAssinatura(caminho));
+            },
+            r => r?,
+        };
+        verificar(&backup.chave_publica, &dados, &assinatura)?;
+    }
+
+    String::from_utf8(dados)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+}
@@ -1,17 +1,43 @@
 //! Módulo com os tipos e funções necessárias para o cadastro de um aluno novo.
-use crate::configuracao::ConfiguracaoUsuario;
+use crate::caixa_email::provisionar;
+use crate::configuracao::{ConfiguracaoRenovacao, ConfiguracaoUsuario};
+use crate::contingencia::CadastroPendente;
+use crate::duplicidade::verificar as verificar_duplicidade;
+use crate::foto::{ErroFoto, Foto};
+use crate::impressao::UsuarioImpressao;
+#[cfg(feature = "kerberos")]
+use crate::kerberos::{ErroKerberos, criar_principal_ou_desfazer};
 use crate::ldap::ErroLdap;
-use crate::ldap::cadastrar::cadastrar_usuario;
+use crate::ldap::cadastrar::{
+    cadastrar_usuario, cadastrar_usuario_em, home_directory, normalizar_gecos,
+};
+use crate::ldap::caixa_email::{CaixaPendente, EstadoCaixa};
+use crate::ldap::conexao::FonteLdap;
 use crate::ldap::consulta::{
-    Consulta as ConsultaLdap, consultar_cadastro_ldap,
+    Consulta as ConsultaLdap, UidsEmCache, consultar_escolha, e_candidato,
 };
-use crate::portal_ufrj::{Consulta, ConsultaErro, consulta};
+use crate::ldap::reservas::{liberar_username_ldap, reservar_username};
+use crate::metricas::registrar_etapa_cadastro;
+use crate::moodle::UsuarioMoodle;
+use crate::portal_ufrj::{Consulta, ConsultaErro, consulta_em};
 use crate::utils::nome::Nome;
+use crate::utils::triagem_nome::{TriagemNome, triagem};
 use crate::utils::validacao_entradas::*;
 use axum::http::StatusCode;
-use secrecy::SecretString;
-use serde::Deserialize;
+use chrono::Utc;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize, Serializer};
+use sha1::{Digest, Sha1};
+use std::time::Instant;
 use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Serializa as partes dos cadastros que não podem rodar ao mesmo tempo neste
+/// processo: a verificação do DRE, a escolha do username e a alocação dos IDs
+/// do Samba, até a entrada ser criada. O resto do cadastro, como a consulta
+/// ao SIGA e a caixa de email, roda em paralelo.
+pub(crate) static SECAO_CRITICA: Mutex<()> = Mutex::const_new(());
 
 /// Struct contendo os dados para cadastrar um novo usuário. Esses dados são
 /// recebidos pela aplicação e são o suficiente para cadastrar a maior parte
@@ -19,7 +45,7 @@ use thiserror::Error;
 /// `data_emissao`, `hora_emissao` e `codigo` são dados contidos no documento
 /// "Regularmente Matriculado" disponível no SIGA, que é autenticado pelo
 /// programa. O `nome` deve ser o mesmo do SIGA.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DadosParaCadastro {
     /// O DRE, somente números, 9 dígitos.
     pub dre: String,
@@ -44,7 +70,86 @@ pub struct DadosParaCadastro {
     pub telefone: String,
     /// A senha. Precisa ter entre 8 e 25 caracteres, ao menos uma letra
     /// minúscula, maiúscula e um dígito.
+    #[serde(serialize_with = "serializar_senha")]
     pub senha: SecretString,
+    /// Uma chave SSH pública, opcional, no formato do `authorized_keys`.
+    #[serde(default)]
+    pub chave_ssh: Option<String>,
+    /// O username escolhido pelo aluno entre os candidatos livres listados
+    /// pela API, opcional. Sem ele, o cadastro usa o primeiro livre.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Uma foto opcional, em JPEG codificado em base64, gravada no
+    /// `jpegPhoto`.
+    #[serde(default)]
+    pub foto: Option<Foto>,
+}
+
+/// A senha vai em claro no JSON, que é como o cliente da API (o
+/// `alumnic::client`) envia o cadastro; no `Debug`, ela continua escondida.
+fn serializar_senha<S: Serializer>(
+    senha: &SecretString,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.serialize_str(senha.expose_secret())
+}
+
+/// A etapa do cadastro em que um [`ErroDeCadastro`] aconteceu, mostrada no
+/// log, no painel e nas métricas, já que o mesmo erro do LDAP pode vir da
+/// consulta ou da criação da conta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EtapaCadastro {
+    /// A validação dos dados enviados, antes de qualquer consulta.
+    Validacao,
+    /// A autenticação do documento no Gnosys e a conferência do curso e do
+    /// nome.
+    ConsultaGnosys,
+    /// A consulta do DRE e dos usernames livres no LDAP.
+    ConsultaLdap,
+    /// A criação da entrada da conta no LDAP.
+    CriacaoLdap,
+    /// A criação do que acompanha a conta, como o principal Kerberos.
+    Servicos,
+}
+
+impl EtapaCadastro {
+    /// O nome da etapa, usado como rótulo nas estatísticas e nas métricas.
+    pub fn nome(self) -> &'static str {
+        match self {
+            EtapaCadastro::Validacao => "validacao",
+            EtapaCadastro::ConsultaGnosys => "consulta_gnosys",
+            EtapaCadastro::ConsultaLdap => "consulta_ldap",
+            EtapaCadastro::CriacaoLdap => "criacao_ldap",
+            EtapaCadastro::Servicos => "servicos",
+        }
+    }
+
+    /// Espera o `futuro` desta etapa, registrando nas
+    /// [métricas](crate::metricas) quanto ele levou.
+    async fn medir<T>(self, futuro: impl Future<Output = T>) -> T {
+        let inicio = Instant::now();
+        let r = futuro.await;
+        registrar_etapa_cadastro(self.nome(), inicio.elapsed());
+        r
+    }
+
+    /// Converte um erro do LDAP nesta etapa em um [`ErroDeCadastro`].
+    fn erro_ldap(self) -> impl FnOnce(ErroLdap) -> ErroDeCadastro {
+        move |e| ErroDeCadastro::ErroNoCadastro(self, e)
+    }
+}
+
+impl std::fmt::Display for EtapaCadastro {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EtapaCadastro::Validacao => "validação dos dados",
+            EtapaCadastro::ConsultaGnosys => "consulta ao Gnosys",
+            EtapaCadastro::ConsultaLdap => "consulta ao LDAP",
+            EtapaCadastro::CriacaoLdap => "criação da conta no LDAP",
+            EtapaCadastro::Servicos => "criação dos serviços da conta",
+        })
+    }
 }
 
 #[derive(Debug, Error)]
@@ -66,6 +171,16 @@ pub enum ErroDeCadastro {
     // TODO: mudar verificacao da senha
     #[error("A senha precisa ter entre 8 e 25 caracteres, uma letra minúscula, uma maiúscula e um dígito")]
     SenhaInvalida,
+    #[error("A chave SSH não é uma chave pública válida")]
+    ChaveSshInvalida,
+    #[error("O nome de usuário {0:?} não é um dos gerados para o seu nome")]
+    UsernameInvalido(String),
+    #[error(transparent)]
+    CampoGrande(#[from] CampoGrande),
+    #[error(transparent)]
+    FotoInvalida(#[from] ErroFoto),
+    #[error("O campo {0:?} é obrigatório")]
+    CampoAusente(&'static str),
 
     #[error("Não foi possível obter informações do SIGA: {0}")]
     ErroNaConsulta(#[from] ConsultaErro),
@@ -74,10 +189,25 @@ pub enum ErroDeCadastro {
     #[error("Seu documento de matrícula é inválido")]
     DocumentoInvalido,
 
-    #[error("Houve um problema ao verificar o estado do cadastro no LDAP: {0}")]
-    ErroNoCadastro(#[from] ErroLdap),
+    #[error("Houve um problema no LDAP durante a {0}: {1}")]
+    ErroNoCadastro(EtapaCadastro, ErroLdap),
     #[error("O cadastro já existe, com o nome de usuário {0:?}")]
     CadastroRedundante(String),
+    #[cfg(feature = "kerberos")]
+    #[error("Não foi possível criar o principal Kerberos: {0}")]
+    ErroKerberos(#[from] ErroKerberos),
+
+    #[error(
+        "O serviço está sendo reiniciado e o cadastro não foi feito; tente de \
+         novo em alguns instantes"
+    )]
+    Cancelado,
+    #[error(
+        "O LDAP está fora do ar, mas o seu cadastro foi recebido: a conta será \
+         criada assim que ele voltar, e você receberá um email quando ela for \
+         criada"
+    )]
+    Enfileirado,
 
     #[error("O nome informado {informado:?} não é o mesmo do SIGA {siga:?}")]
     // TODO: trocar informado para Nome
@@ -95,122 +225,1049 @@ impl ErroDeCadastro {
             | ErroDeCadastro::EmailInvalido(..)
             | ErroDeCadastro::TelefoneInvalido(..)
             | ErroDeCadastro::SenhaInvalida
+            | ErroDeCadastro::ChaveSshInvalida
+            | ErroDeCadastro::UsernameInvalido(..)
+            | ErroDeCadastro::CampoGrande(..)
+            | ErroDeCadastro::FotoInvalida(..)
+            | ErroDeCadastro::CampoAusente(..)
             | ErroDeCadastro::NomesDiferentes { .. } => {
                 StatusCode::UNPROCESSABLE_ENTITY
             },
             ErroDeCadastro::AlunoOutroCurso(..) => StatusCode::FORBIDDEN,
             ErroDeCadastro::DocumentoInvalido => StatusCode::UNAUTHORIZED,
+            // Sem saber se a conta foi criada, o aluno tenta de novo mais
+            // tarde, quando o DRE já aparece como cadastrado se ela foi
+            ErroDeCadastro::ErroNoCadastro(EtapaCadastro::CriacaoLdap, e)
+                if e.indisponivel() =>
+            {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            ErroDeCadastro::ErroNoCadastro(_, ErroLdap::Timeout) => {
+                StatusCode::GATEWAY_TIMEOUT
+            },
+            ErroDeCadastro::ErroNoCadastro(_, ErroLdap::SomenteLeitura) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            ErroDeCadastro::ErroNoCadastro(
+                _,
+                ErroLdap::UsernameOcupado(..)
+                | ErroLdap::UsernameConfundivel { .. },
+            ) => StatusCode::CONFLICT,
+            ErroDeCadastro::ErroNaConsulta(ConsultaErro::EsperaEsgotada(
+                ..,
+            )) => StatusCode::SERVICE_UNAVAILABLE,
             ErroDeCadastro::ErroNaConsulta(..)
             | ErroDeCadastro::ErroNoCadastro(..) => {
                 StatusCode::INTERNAL_SERVER_ERROR
             },
+            #[cfg(feature = "kerberos")]
+            ErroDeCadastro::ErroKerberos(..) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            },
             ErroDeCadastro::CadastroRedundante(..) => StatusCode::CONFLICT,
+            ErroDeCadastro::Cancelado => StatusCode::SERVICE_UNAVAILABLE,
+            ErroDeCadastro::Enfileirado => StatusCode::ACCEPTED,
+        }
+    }
+
+    /// O nome da variante do erro, usado para agrupar erros nas estatísticas.
+    pub fn tipo(&self) -> &'static str {
+        match self {
+            ErroDeCadastro::DREInvalido(..) => "DREInvalido",
+            ErroDeCadastro::DataInvalida(..) => "DataInvalida",
+            ErroDeCadastro::HoraInvalida(..) => "HoraInvalida",
+            ErroDeCadastro::CodigoInvalido(..) => "CodigoInvalido",
+            ErroDeCadastro::NomeInvalido(..) => "NomeInvalido",
+            ErroDeCadastro::EmailInvalido(..) => "EmailInvalido",
+            ErroDeCadastro::TelefoneInvalido(..) => "TelefoneInvalido",
+            ErroDeCadastro::SenhaInvalida => "SenhaInvalida",
+            ErroDeCadastro::ChaveSshInvalida => "ChaveSshInvalida",
+            ErroDeCadastro::UsernameInvalido(..) => "UsernameInvalido",
+            ErroDeCadastro::CampoGrande(..) => "CampoGrande",
+            ErroDeCadastro::FotoInvalida(..) => "FotoInvalida",
+            ErroDeCadastro::CampoAusente(..) => "CampoAusente",
+            ErroDeCadastro::ErroNaConsulta(..) => "ErroNaConsulta",
+            ErroDeCadastro::AlunoOutroCurso(..) => "AlunoOutroCurso",
+            ErroDeCadastro::DocumentoInvalido => "DocumentoInvalido",
+            ErroDeCadastro::ErroNoCadastro(..) => "ErroNoCadastro",
+            ErroDeCadastro::CadastroRedundante(..) => "CadastroRedundante",
+            ErroDeCadastro::Cancelado => "Cancelado",
+            ErroDeCadastro::Enfileirado => "Enfileirado",
+            #[cfg(feature = "kerberos")]
+            ErroDeCadastro::ErroKerberos(..) => "ErroKerberos",
+            ErroDeCadastro::NomesDiferentes { .. } => "NomesDiferentes",
+        }
+    }
+
+    /// A etapa do cadastro em que o erro aconteceu.
+    pub fn etapa(&self) -> EtapaCadastro {
+        match self {
+            ErroDeCadastro::DREInvalido(..)
+            | ErroDeCadastro::DataInvalida(..)
+            | ErroDeCadastro::HoraInvalida(..)
+            | ErroDeCadastro::CodigoInvalido(..)
+            | ErroDeCadastro::NomeInvalido(..)
+            | ErroDeCadastro::EmailInvalido(..)
+            | ErroDeCadastro::TelefoneInvalido(..)
+            | ErroDeCadastro::SenhaInvalida
+            | ErroDeCadastro::ChaveSshInvalida
+            | ErroDeCadastro::UsernameInvalido(..)
+            | ErroDeCadastro::CampoGrande(..)
+            | ErroDeCadastro::FotoInvalida(..)
+            | ErroDeCadastro::CampoAusente(..) => EtapaCadastro::Validacao,
+            ErroDeCadastro::ErroNaConsulta(..)
+            | ErroDeCadastro::AlunoOutroCurso(..)
+            | ErroDeCadastro::DocumentoInvalido
+            | ErroDeCadastro::NomesDiferentes { .. } => {
+                EtapaCadastro::ConsultaGnosys
+            },
+            ErroDeCadastro::CadastroRedundante(..) => {
+                EtapaCadastro::ConsultaLdap
+            },
+            ErroDeCadastro::ErroNoCadastro(etapa, _) => *etapa,
+            ErroDeCadastro::Cancelado | ErroDeCadastro::Enfileirado => {
+                EtapaCadastro::CriacaoLdap
+            },
+            #[cfg(feature = "kerberos")]
+            ErroDeCadastro::ErroKerberos(..) => EtapaCadastro::Servicos,
+        }
+    }
+
+    /// As diferenças entre o nome informado e o do SIGA, com o nome
+    /// sugerido, quando o erro é de [nomes
+    /// diferentes](ErroDeCadastro::NomesDiferentes).
+    pub fn triagem_nome(&self) -> Option<TriagemNome> {
+        match self {
+            ErroDeCadastro::NomesDiferentes { informado, siga } => {
+                Some(triagem(&informado.parse().ok()?, siga))
+            },
+            _ => None,
         }
     }
+
+    /// Se o cadastro falhou com o LDAP fora do ar antes de a conta ser
+    /// criada, e pode ir para a [fila de contingência](crate::contingencia).
+    /// Só as falhas da consulta contam: uma falha na criação pode chegar
+    /// depois de a conta ou os contadores do Samba já terem sido gravados.
+    pub fn ldap_indisponivel(&self) -> bool {
+        matches!(
+            self,
+            ErroDeCadastro::ErroNoCadastro(EtapaCadastro::ConsultaLdap, e)
+                if e.indisponivel()
+        )
+    }
 }
 
-impl DadosParaCadastro {
-    pub async fn cadastrar_sem_verificar_documento(
+/// Os dados pessoais de um cadastro, já validados e normalizados. Só são
+/// construídos pela [validação](DadosParaCadastro::validar), então o
+/// [cadastro no LDAP](crate::ldap::cadastrar::cadastrar_usuario) nunca recebe
+/// os dados como chegaram na requisição.
+#[derive(Debug)]
+pub struct DadosValidados {
+    dre: String,
+    nome: String,
+    email: String,
+    telefone: String,
+    senha: SecretString,
+    chave_ssh: Option<String>,
+    username: Option<String>,
+    foto: Option<Foto>,
+    responsavel: Option<String>,
+}
+
+/// Resultado de um cadastro bem-sucedido.
+#[derive(Debug, Clone)]
+pub struct CadastroRealizado {
+    /// O username/uid criado para o aluno.
+    pub username: String,
+    /// A OU em que o aluno foi cadastrado, que depende do curso.
+    pub ou: &'static str,
+    pub dre: String,
+    pub home: String,
+    /// O nome completo, sem acentos, como no `gecos`.
+    pub nome: String,
+    /// O email institucional.
+    pub email: String,
+}
+
+/// Os dados do cadastro conferidos antes de o aluno definir a senha, pela
+/// [pré-validação](DadosParaValidacao::validar), com o mesmo significado
+/// dos campos de [`DadosParaCadastro`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DadosParaValidacao {
+    pub dre: String,
+    pub data: String,
+    pub hora: String,
+    pub codigo: String,
+    pub nome: String,
+    pub email: String,
+    pub telefone: String,
+}
+
+/// Resultado de uma pré-validação bem-sucedida: os dados normalizados, como
+/// serão cadastrados, e os usernames que podem ser gerados para o nome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidacaoRealizada {
+    pub dre: String,
+    pub data: String,
+    pub hora: String,
+    pub codigo: String,
+    pub nome: String,
+    pub email: String,
+    pub telefone: String,
+    /// A OU em que o aluno será cadastrado, que depende do curso.
+    pub ou: String,
+    /// Os primeiros usernames gerados para o nome, sem consultar o LDAP; os
+    /// livres são listados por `/api/usernames`.
+    pub usernames: Vec<String>,
+}
+
+/// Processa o nome, que vai para o log e para o LDAP e por isso não pode ter
+/// quebras de linha nem caracteres invisíveis.
+fn processar_nome(nome: String) -> Result<String, ErroDeCadastro> {
+    processar_texto(&nome)
+        .filter(|nome| nome.parse::<Nome>().is_ok())
+        .ok_or(ErroDeCadastro::NomeInvalido(nome))
+}
+
+/// Confere a resposta do SIGA para o aluno que informou o `nome`, retornando
+/// a OU do curso dele.
+fn conferir_siga(
+    consulta: Result<Consulta, ConsultaErro>,
+    nome: &str,
+) -> Result<&'static str, ErroDeCadastro> {
+    let (nome_siga, ou) = match consulta? {
+        Consulta::AlunoBCC { nome } => (nome, "alunos"),
+        Consulta::AlunoProfComp { nome } => (nome, "profcomp"),
+        Consulta::AlunoOutroCurso { curso, .. } => {
+            Err(ErroDeCadastro::AlunoOutroCurso(curso))?
+        },
+        Consulta::Desconhecido => Err(ErroDeCadastro::DocumentoInvalido)?,
+    };
+
+    // Verifica se o nome é o mesmo do SIGA
+
+    // TODO: remover essa porção ruim de código fazendo o self.nome já ser
+    // do tipo nome ao criar a estrutura
+    if Ok(nome_siga.clone()) != nome.parse() {
+        Err(ErroDeCadastro::NomesDiferentes {
+            informado: nome.to_string(),
+            siga: nome_siga,
+        })?
+    }
+    Ok(ou)
+}
+
+impl DadosParaValidacao {
+    /// Valida e normaliza os dados e autentica o documento no Gnosys
+    /// hospedado em `gnosys_url`, sem consultar nem alterar o LDAP. Os
+    /// erros são os mesmos do [cadastro](DadosParaCadastro::cadastrar).
+    pub async fn validar(
         mut self,
-        uid: String,
-        config: &ConfiguracaoUsuario,
-        ou: &str,
-        ldap_url: &str,
-        ldap_bind_dn: &str,
-        ldap_bind_pw: &str,
-    ) -> Result<(), ErroDeCadastro> {
+        gnosys_url: &str,
+        usernames: usize,
+    ) -> Result<ValidacaoRealizada, ErroDeCadastro> {
+        limitar("dre", &self.dre, MAXIMO_CAMPO)?;
+        limitar("data", &self.data, MAXIMO_CAMPO)?;
+        limitar("hora", &self.hora, MAXIMO_CAMPO)?;
+        limitar("codigo", &self.codigo, MAXIMO_CAMPO)?;
+        limitar("nome", &self.nome, MAXIMO_NOME)?;
+        limitar("email", &self.email, MAXIMO_EMAIL)?;
+        limitar("telefone", &self.telefone, MAXIMO_CAMPO)?;
+
         self.dre = processar_dre(&self.dre)
             .ok_or_else(move || ErroDeCadastro::DREInvalido(self.dre))?;
-        //self.nome = processar_nome(&self.nome)
-        //    .ok_or_else(move || ErroDeCadastro::NomeInvalido(self.nome))?;
+        self.nome = processar_nome(self.nome)?;
         self.email = processar_email(&self.email)
             .ok_or_else(move || ErroDeCadastro::EmailInvalido(self.email))?;
         self.telefone =
             processar_telefone(&self.telefone).ok_or_else(move || {
                 ErroDeCadastro::TelefoneInvalido(self.telefone)
             })?;
+        self.data = processar_data(&self.data)
+            .ok_or_else(move || ErroDeCadastro::DataInvalida(self.data))?;
+        self.hora = processar_hora(&self.hora)
+            .ok_or_else(move || ErroDeCadastro::HoraInvalida(self.hora))?;
+        self.codigo = processar_codigo(&self.codigo)
+            .ok_or_else(move || ErroDeCadastro::CodigoInvalido(self.codigo))?;
+
+        let consulta = consulta_em(
+            gnosys_url,
+            &self.dre,
+            &self.data,
+            &self.hora,
+            &self.codigo,
+        )
+        .await;
+        let ou = conferir_siga(consulta, &self.nome)?;
+
+        let usernames = self
+            .nome
+            .parse::<Nome>()
+            .map(|nome| nome.usernames().take(usernames).collect())
+            .unwrap_or_default();
+        Ok(ValidacaoRealizada {
+            dre: self.dre,
+            data: self.data,
+            hora: self.hora,
+            codigo: self.codigo,
+            nome: self.nome,
+            email: self.email,
+            telefone: self.telefone,
+            ou: ou.to_string(),
+            usernames,
+        })
+    }
+}
+
+impl DadosParaCadastro {
+    /// Verifica o tamanho de cada campo. Roda antes de qualquer log ou
+    /// consulta, para que um campo enorme não chegue a eles.
+    pub fn limitar_tamanhos(&self) -> Result<(), CampoGrande> {
+        limitar("dre", &self.dre, MAXIMO_CAMPO)?;
+        limitar("data", &self.data, MAXIMO_CAMPO)?;
+        limitar("hora", &self.hora, MAXIMO_CAMPO)?;
+        limitar("codigo", &self.codigo, MAXIMO_CAMPO)?;
+        limitar("nome", &self.nome, MAXIMO_NOME)?;
+        limitar("email", &self.email, MAXIMO_EMAIL)?;
+        limitar("telefone", &self.telefone, MAXIMO_CAMPO)?;
+        limitar("senha", self.senha.expose_secret(), MAXIMO_CAMPO)?;
+        if let Some(chave) = &self.chave_ssh {
+            limitar("chave_ssh", chave, MAXIMO_CHAVE_SSH)?;
+        }
+        if let Some(username) = &self.username {
+            limitar("username", username, MAXIMO_CAMPO)?;
+        }
+        if let Some(foto) = &self.foto {
+            limitar("foto", foto.base64(), MAXIMO_FOTO)?;
+        }
+        Ok(())
+    }
+
+    /// A chave do cadastro em [andamento](crate::em_andamento), que só junta
+    /// os envios idênticos, como num duplo clique: o DRE normalizado e um
+    /// hash de todos os outros campos. Outro documento, senha ou email com o
+    /// mesmo DRE fazem um cadastro separado, em vez de receber a resposta
+    /// do primeiro.
+    pub fn chave_em_andamento(&self) -> String {
+        let dre = processar_dre(&self.dre).unwrap_or_else(|| self.dre.clone());
+        let campos = [
+            Some(self.data.as_str()),
+            Some(&self.hora),
+            Some(&self.codigo),
+            Some(&self.nome),
+            Some(&self.email),
+            Some(&self.telefone),
+            Some(self.senha.expose_secret()),
+            self.chave_ssh.as_deref(),
+            self.username.as_deref(),
+            self.foto.as_ref().map(Foto::base64),
+        ];
+
+        let mut hasher = Sha1::new();
+        for campo in campos {
+            // O tamanho antes de cada campo separa `Some("")` de `None` e
+            // impede que um campo invada o próximo
+            match campo {
+                Some(valor) => {
+                    hasher.update((valor.len() as u64 + 1).to_le_bytes());
+                    hasher.update(valor.as_bytes());
+                },
+                None => hasher.update(0u64.to_le_bytes()),
+            }
+        }
+        format!("{dre}:{}", hex::encode(hasher.finalize()))
+    }
+
+    /// Valida e normaliza os dados pessoais do aluno, deixando de fora os
+    /// dados do documento. A foto é recodificada, se a `config` aceitar
+    /// fotos; sem a configuração, a foto enviada é descartada.
+    pub fn validar(
+        self,
+        config: &ConfiguracaoUsuario,
+    ) -> Result<DadosValidados, ErroDeCadastro> {
+        self.limitar_tamanhos()?;
+        let dre = processar_dre(&self.dre)
+            .ok_or_else(move || ErroDeCadastro::DREInvalido(self.dre))?;
+        let nome = processar_nome(self.nome)?;
+        let email = processar_email(&self.email)
+            .ok_or_else(move || ErroDeCadastro::EmailInvalido(self.email))?;
+        let telefone =
+            processar_telefone(&self.telefone).ok_or_else(move || {
+                ErroDeCadastro::TelefoneInvalido(self.telefone)
+            })?;
         validar_senha(&self.senha)
             .then_some(())
             .ok_or(ErroDeCadastro::SenhaInvalida)?;
+        let chave_ssh = match self.chave_ssh {
+            Some(chave) => Some(
+                processar_chave_ssh(&chave)
+                    .ok_or(ErroDeCadastro::ChaveSshInvalida)?,
+            ),
+            None => None,
+        };
+        // O username escolhido precisa ser um dos gerados pelo nome, o mesmo
+        // do SIGA
+        let username = match self.username {
+            Some(username) => {
+                conferir_username(&nome, &username)?;
+                Some(username.to_lowercase())
+            },
+            None => None,
+        };
+        let foto = match (self.foto, &config.foto) {
+            (Some(foto), Some(cfg)) => Some(foto.processar(cfg)?),
+            _ => None,
+        };
 
-        cadastrar_usuario(
-            uid,
-            &self,
-            config,
-            ou,
-            ldap_url,
-            ldap_bind_dn,
-            ldap_bind_pw,
-        )
-        .await?;
+        Ok(DadosValidados {
+            dre,
+            nome,
+            email,
+            telefone,
+            senha: self.senha,
+            chave_ssh,
+            username,
+            foto,
+            responsavel: None,
+        })
+    }
 
-        Ok(())
+    /// Cadastra o aluno com o `uid` sem autenticar o documento, como nas
+    /// contas criadas pela supervisão. O `responsavel` pela conta, como o
+    /// operador e o bind usado, é gravado no
+    /// [`atributo_responsavel`](ConfiguracaoUsuario::atributo_responsavel).
+    pub async fn cadastrar_sem_verificar_documento<F: FonteLdap>(
+        self,
+        uid: String,
+        responsavel: &str,
+        config: &ConfiguracaoUsuario,
+        prazos: &ConfiguracaoRenovacao,
+        ou: &str,
+        ldap: &F,
+    ) -> Result<(), ErroDeCadastro> {
+        let mut dados = self.validar(config)?;
+        // Vai junto com a entrada, então a conta nunca fica sem ele
+        dados.responsavel = Some(responsavel.to_string());
+
+        let dn = {
+            let _secao = SECAO_CRITICA.lock().await;
+            cadastrar_usuario(uid.clone(), &dados, config, prazos, ou, ldap)
+                .await
+                .map_err(EtapaCadastro::CriacaoLdap.erro_ldap())?
+        };
+
+        dados.provisionar_servicos(uid, dn, config, ou, ldap).await
     }
 
-    pub async fn cadastrar(
+    /// Valida os dados pessoais e os do documento, retornando a data, a hora
+    /// e o código do documento normalizados.
+    fn validar_com_documento(
         mut self,
         config: &ConfiguracaoUsuario,
-        ldap_url: &str,
-        ldap_bind_dn: &str,
-        ldap_bind_pw: &str,
-    ) -> Result<String, ErroDeCadastro> {
-        self.data = processar_data(&self.data)
-            .ok_or_else(move || ErroDeCadastro::DataInvalida(self.data))?;
-        self.hora = processar_hora(&self.hora)
-            .ok_or_else(move || ErroDeCadastro::HoraInvalida(self.hora))?;
-        self.codigo = processar_codigo(&self.codigo)
-            .ok_or_else(move || ErroDeCadastro::CodigoInvalido(self.codigo))?;
+    ) -> Result<(DadosValidados, String, String, String), ErroDeCadastro> {
+        self.limitar_tamanhos()?;
+        let data = std::mem::take(&mut self.data);
+        let hora = std::mem::take(&mut self.hora);
+        let codigo = std::mem::take(&mut self.codigo);
+        let dados = self.validar(config)?;
+        let data = processar_data(&data)
+            .ok_or_else(move || ErroDeCadastro::DataInvalida(data))?;
+        let hora = processar_hora(&hora)
+            .ok_or_else(move || ErroDeCadastro::HoraInvalida(hora))?;
+        let codigo = processar_codigo(&codigo)
+            .ok_or_else(move || ErroDeCadastro::CodigoInvalido(codigo))?;
+        Ok((dados, data, hora, codigo))
+    }
+
+    /// Valida os dados, autentica o documento no Gnosys hospedado em
+    /// `gnosys_url` e cadastra o aluno no LDAP fornecido por `ldap`, com os
+    /// `prazos` da conta e da senha. Com o `cancelamento` cancelado, o
+    /// cadastro para antes de entrar na seção crítica, com
+    /// [`ErroDeCadastro::Cancelado`]; depois dela, a conta é criada até o fim.
+    ///
+    /// Com a [fila de contingência](crate::contingencia) configurada, o
+    /// cadastro com o documento autenticado que encontra o LDAP fora do ar
+    /// vai para a fila, com [`ErroDeCadastro::Enfileirado`]. Os `uids` em
+    /// cache só são usados na consulta feita junto com a do SIGA; a da seção
+    /// crítica carrega os uids do LDAP.
+    pub async fn cadastrar<F: FonteLdap>(
+        self,
+        config: &ConfiguracaoUsuario,
+        prazos: &ConfiguracaoRenovacao,
+        gnosys_url: &str,
+        ldap: &F,
+        uids: Option<&UidsEmCache>,
+        cancelamento: &CancellationToken,
+    ) -> Result<CadastroRealizado, ErroDeCadastro> {
+        // Valida tudo antes de consultar o SIGA e o LDAP, para que uma
+        // entrada inválida nunca chegue neles
+        let inicio = Instant::now();
+        let validacao = self.validar_com_documento(config);
+        registrar_etapa_cadastro(
+            EtapaCadastro::Validacao.nome(),
+            inicio.elapsed(),
+        );
+        let (dados, data, hora, codigo) = validacao?;
 
         // Faz a consulta no SIGA e no LDAP ao mesmo tempo
         let (consulta_siga, consulta_ldap) = tokio::join!(
-            consulta(&self.dre, &self.data, &self.hora, &self.codigo),
-            consultar_cadastro_ldap(
-                &self.dre,
-                &self.nome,
-                ldap_url,
-                ldap_bind_dn,
-                ldap_bind_pw
-            ),
+            EtapaCadastro::ConsultaGnosys.medir(consulta_em(
+                gnosys_url, &dados.dre, &data, &hora, &codigo
+            )),
+            EtapaCadastro::ConsultaLdap
+                .medir(dados.consultar_e_reservar(config, uids, ldap)),
         );
 
-        let uid_ldap = match consulta_ldap? {
-            ConsultaLdap::CadastroDisponivel(uid) => uid,
-            ConsultaLdap::CadastroRedundante(uid) => {
+        let documento = (data, hora, codigo);
+        let consulta_ldap =
+            consulta_ldap.map_err(EtapaCadastro::ConsultaLdap.erro_ldap());
+        let reservado = match consulta_ldap {
+            Ok(ConsultaLdap::CadastroDisponivel(uid)) => uid,
+            Ok(ConsultaLdap::CadastroRedundante(uid)) => {
                 Err(ErroDeCadastro::CadastroRedundante(uid))?
             },
+            // Com o LDAP fora do ar, só o documento autenticado vai para a
+            // fila
+            Err(e)
+                if e.ldap_indisponivel() && config.contingencia.is_some() =>
+            {
+                let ou = conferir_siga(consulta_siga, &dados.nome)?;
+                return Err(dados.enfileirar(e, &documento, ou, config));
+            },
+            Err(e) => Err(e)?,
+        };
+
+        let r = match conferir_siga(consulta_siga, &dados.nome) {
+            Ok(ou) => dados
+                .concluir(ou, config, prazos, ldap, cancelamento)
+                .await
+                .map_err(|e| dados.enfileirar(e, &documento, ou, config)),
+            Err(e) => Err(e),
+        };
+
+        // A reserva não é mais necessária, com a conta criada ou não
+        if config.reserva_username.is_some()
+            && let Err(e) =
+                liberar_username_ldap(&reservado, &dados.dre, ldap).await
+        {
+            eprintln!(
+                "Não foi possível desfazer a reserva do username \
+                 {reservado:?}: {e}"
+            );
+        }
+        r
+    }
+
+    /// Cadastra o aluno que estava na [fila de
+    /// contingência](crate::contingencia), na `ou` que o SIGA deu quando o
+    /// cadastro foi recebido. Os dados são validados de novo, mas o
+    /// documento, já autenticado, não volta ao Gnosys.
+    pub async fn cadastrar_pendente<F: FonteLdap>(
+        self,
+        ou: &str,
+        config: &ConfiguracaoUsuario,
+        prazos: &ConfiguracaoRenovacao,
+        ldap: &F,
+        cancelamento: &CancellationToken,
+    ) -> Result<CadastroRealizado, ErroDeCadastro> {
+        // As OUs dadas pela conferência do SIGA
+        let ou = match ou {
+            "alunos" => "alunos",
+            "profcomp" => "profcomp",
+            _ => return Err(ErroDeCadastro::DocumentoInvalido),
         };
+        let dados = self.validar(config)?;
+        dados.concluir(ou, config, prazos, ldap, cancelamento).await
+    }
+}
+
+/// Monta um [`DadosParaCadastro`] campo a campo, validando cada um assim que
+/// é informado, para quem cadastra alunos por código em vez de pelo JSON da
+/// API. Cada método retorna o erro específico do campo, o mesmo que o
+/// [cadastro](DadosParaCadastro::cadastrar) retornaria, e guarda o valor já
+/// normalizado.
+///
+/// ```
+/// # use alumnic::cadastro_aluno::{CadastroBuilder, ErroDeCadastro};
+/// # fn main() -> Result<(), ErroDeCadastro> {
+/// let dados = CadastroBuilder::novo()
+///     .dre("123456789")?
+///     .nome("Cláudio de Lima Cavalcante")?
+///     .documento(
+///         "1/2/2025",
+///         "10:30",
+///         "0A1B.2C3D.4E5F.6A7B.8C9D.0E1F.2A3B.4C5D",
+///     )?
+///     .email("claudio@exemplo.com")?
+///     .telefone("(21) 99999-0000")?
+///     .senha("Senha123".into())?
+///     .construir()?;
+/// assert_eq!(dados.data, "01/02/2025");
+///
+/// let erro = CadastroBuilder::novo().dre("12345").unwrap_err();
+/// assert!(matches!(erro, ErroDeCadastro::DREInvalido(_)));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct CadastroBuilder {
+    dre: Option<String>,
+    documento: Option<(String, String, String)>,
+    nome: Option<String>,
+    email: Option<String>,
+    telefone: Option<String>,
+    senha: Option<SecretString>,
+    chave_ssh: Option<String>,
+    username: Option<String>,
+    foto: Option<Foto>,
+}
+
+impl CadastroBuilder {
+    pub fn novo() -> Self {
+        Self::default()
+    }
+
+    pub fn dre(mut self, dre: &str) -> Result<Self, ErroDeCadastro> {
+        limitar("dre", dre, MAXIMO_CAMPO)?;
+        self.dre = Some(
+            processar_dre(dre)
+                .ok_or_else(|| ErroDeCadastro::DREInvalido(dre.to_string()))?,
+        );
+        Ok(self)
+    }
+
+    /// Os dados do documento "Regularmente Matriculado": a `data` e a `hora`
+    /// de emissão e o `codigo` de autenticação.
+    pub fn documento(
+        mut self,
+        data: &str,
+        hora: &str,
+        codigo: &str,
+    ) -> Result<Self, ErroDeCadastro> {
+        limitar("data", data, MAXIMO_CAMPO)?;
+        limitar("hora", hora, MAXIMO_CAMPO)?;
+        limitar("codigo", codigo, MAXIMO_CAMPO)?;
+        let data = processar_data(data)
+            .ok_or_else(|| ErroDeCadastro::DataInvalida(data.to_string()))?;
+        let hora = processar_hora(hora)
+            .ok_or_else(|| ErroDeCadastro::HoraInvalida(hora.to_string()))?;
+        let codigo = processar_codigo(codigo).ok_or_else(|| {
+            ErroDeCadastro::CodigoInvalido(codigo.to_string())
+        })?;
+        self.documento = Some((data, hora, codigo));
+        Ok(self)
+    }
+
+    /// O nome completo. Se o username já foi escolhido, ele precisa ser um
+    /// dos gerados para esse nome.
+    pub fn nome(mut self, nome: &str) -> Result<Self, ErroDeCadastro> {
+        limitar("nome", nome, MAXIMO_NOME)?;
+        let nome = processar_nome(nome.to_string())?;
+        if let Some(username) = &self.username {
+            conferir_username(&nome, username)?;
+        }
+        self.nome = Some(nome);
+        Ok(self)
+    }
+
+    pub fn email(mut self, email: &str) -> Result<Self, ErroDeCadastro> {
+        limitar("email", email, MAXIMO_EMAIL)?;
+        self.email =
+            Some(processar_email(email).ok_or_else(|| {
+                ErroDeCadastro::EmailInvalido(email.to_string())
+            })?);
+        Ok(self)
+    }
+
+    pub fn telefone(mut self, telefone: &str) -> Result<Self, ErroDeCadastro> {
+        limitar("telefone", telefone, MAXIMO_CAMPO)?;
+        self.telefone =
+            Some(processar_telefone(telefone).ok_or_else(|| {
+                ErroDeCadastro::TelefoneInvalido(telefone.to_string())
+            })?);
+        Ok(self)
+    }
+
+    pub fn senha(
+        mut self,
+        senha: SecretString,
+    ) -> Result<Self, ErroDeCadastro> {
+        limitar("senha", senha.expose_secret(), MAXIMO_CAMPO)?;
+        if !validar_senha(&senha) {
+            return Err(ErroDeCadastro::SenhaInvalida);
+        }
+        self.senha = Some(senha);
+        Ok(self)
+    }
+
+    pub fn chave_ssh(mut self, chave: &str) -> Result<Self, ErroDeCadastro> {
+        limitar("chave_ssh", chave, MAXIMO_CHAVE_SSH)?;
+        self.chave_ssh = Some(
+            processar_chave_ssh(chave)
+                .ok_or(ErroDeCadastro::ChaveSshInvalida)?,
+        );
+        Ok(self)
+    }
+
+    /// O username escolhido, conferido com o nome se ele já foi informado.
+    pub fn username(mut self, username: &str) -> Result<Self, ErroDeCadastro> {
+        limitar("username", username, MAXIMO_CAMPO)?;
+        if let Some(nome) = &self.nome {
+            conferir_username(nome, username)?;
+        }
+        self.username = Some(username.to_lowercase());
+        Ok(self)
+    }
+
+    /// A foto só é validada no cadastro, que conhece as dimensões aceitas
+    /// pela configuração; aqui, só o tamanho é verificado.
+    pub fn foto(mut self, foto: Foto) -> Result<Self, ErroDeCadastro> {
+        limitar("foto", foto.base64(), MAXIMO_FOTO)?;
+        self.foto = Some(foto);
+        Ok(self)
+    }
 
-        let (nome_siga, ou) = match consulta_siga? {
-            Consulta::AlunoBCC { nome } => (nome, "alunos"),
-            Consulta::AlunoProfComp { nome } => (nome, "profcomp"),
-            Consulta::AlunoOutroCurso { curso, .. } => {
-                Err(ErroDeCadastro::AlunoOutroCurso(curso))?
+    /// Retorna os dados montados, ou [`ErroDeCadastro::CampoAusente`] com o
+    /// primeiro campo obrigatório que não foi informado.
+    pub fn construir(self) -> Result<DadosParaCadastro, ErroDeCadastro> {
+        let ausente = ErroDeCadastro::CampoAusente;
+        let (data, hora, codigo) =
+            self.documento.ok_or(ausente("documento"))?;
+        Ok(DadosParaCadastro {
+            dre: self.dre.ok_or(ausente("dre"))?,
+            data,
+            hora,
+            codigo,
+            nome: self.nome.ok_or(ausente("nome"))?,
+            email: self.email.ok_or(ausente("email"))?,
+            telefone: self.telefone.ok_or(ausente("telefone"))?,
+            senha: self.senha.ok_or(ausente("senha"))?,
+            chave_ssh: self.chave_ssh,
+            username: self.username,
+            foto: self.foto,
+        })
+    }
+}
+
+/// Confere que o `username` é um dos gerados para o `nome`, para que o aluno
+/// não escolha um qualquer.
+fn conferir_username(nome: &str, username: &str) -> Result<(), ErroDeCadastro> {
+    match nome.parse::<Nome>() {
+        Ok(n) if e_candidato(&n, username) => Ok(()),
+        _ => Err(ErroDeCadastro::UsernameInvalido(username.to_string())),
+    }
+}
+
+impl DadosValidados {
+    pub fn dre(&self) -> &str {
+        &self.dre
+    }
+
+    /// O nome completo, com os acentos.
+    pub fn nome(&self) -> &str {
+        &self.nome
+    }
+
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    pub fn telefone(&self) -> &str {
+        &self.telefone
+    }
+
+    pub fn senha(&self) -> &SecretString {
+        &self.senha
+    }
+
+    pub fn chave_ssh(&self) -> Option<&str> {
+        self.chave_ssh.as_deref()
+    }
+
+    /// A foto já recodificada.
+    pub fn foto(&self) -> Option<&Foto> {
+        self.foto.as_ref()
+    }
+
+    /// Quem criou a conta, nas criadas pela supervisão, gravado no
+    /// [`atributo_responsavel`](ConfiguracaoUsuario::atributo_responsavel).
+    pub fn responsavel(&self) -> Option<&str> {
+        self.responsavel.as_deref()
+    }
+
+    /// Cria o que acompanha a entrada `dn` recém-criada no LDAP: o principal
+    /// Kerberos, a caixa de email, o registro na impressão e a inscrição no
+    /// Moodle.
+    async fn provisionar_servicos<F: FonteLdap>(
+        &self,
+        uid: String,
+        dn: String,
+        config: &ConfiguracaoUsuario,
+        ou: &str,
+        ldap: &F,
+    ) -> Result<(), ErroDeCadastro> {
+        // Com a feature kerberos, a conta só existe junto com o principal
+        #[cfg(feature = "kerberos")]
+        if let Some(kerberos) = &config.kerberos {
+            criar_principal_ou_desfazer(kerberos, &uid, &self.senha, &dn, ldap)
+                .await?;
+        }
+
+        // Uma falha na caixa de email não desfaz a conta: ela fica marcada
+        // no LDAP e é criada depois com `alumnic caixas`
+        let email = config.dominios_email.email(&uid, ou);
+        if let Some(caixa) = &config.caixa_email {
+            let pendente = CaixaPendente {
+                endereco: email.clone(),
+                dn,
+                uid: uid.clone(),
+                nome: self.nome.clone(),
+                estado: EstadoCaixa::Pendente,
+            };
+            if let Err(e) = provisionar(caixa, &pendente, ldap).await {
+                eprintln!(
+                    "Não foi possível criar a caixa de email de {:?}: {e}",
+                    pendente.uid,
+                );
+            }
+        }
+
+        // Assim como a caixa, o registro na impressão pode ser refeito à mão
+        if let Some(impressao) = &config.impressao {
+            let usuario = UsuarioImpressao {
+                uid: &uid,
+                nome: &self.nome,
+                email: &email,
+                perfil: ou,
+            };
+            if let Err(e) = impressao.registrar(&usuario).await {
+                eprintln!(
+                    "Não foi possível registrar {uid:?} na impressão: {e}"
+                );
+            }
+        }
+
+        // E a inscrição no Moodle também
+        if let Some(moodle) = &config.moodle {
+            let usuario = UsuarioMoodle {
+                uid: &uid,
+                nome: &self.nome,
+                email: &email,
+                ou,
+                dre: &self.dre,
+            };
+            if let Err(e) = moodle.inscrever(&usuario).await {
+                eprintln!("Não foi possível inscrever {uid:?} no Moodle: {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Consulta o cadastro no LDAP e, com a reserva configurada, reserva o
+    /// username livre para o aluno enquanto o SIGA responde e o cadastro
+    /// espera a vez na seção crítica.
+    async fn consultar_e_reservar<F: FonteLdap>(
+        &self,
+        config: &ConfiguracaoUsuario,
+        uids: Option<&UidsEmCache>,
+        ldap: &F,
+    ) -> Result<ConsultaLdap, ErroLdap> {
+        let mut conexao = ldap.abrir().await?;
+        let r = async {
+            let consulta = consultar_escolha(
+                &self.dre,
+                &self.nome,
+                self.username.as_deref(),
+                config.usernames_confundiveis.as_ref(),
+                uids,
+                &mut conexao,
+            )
+            .await?;
+
+            // Sem a reserva, o cadastro continua: o username é conferido de
+            // novo na seção crítica
+            if let (ConsultaLdap::CadastroDisponivel(uid), Some(reserva)) =
+                (&consulta, &config.reserva_username)
+                && let Err(e) = reservar_username(
+                    uid,
+                    &self.dre,
+                    reserva,
+                    Utc::now(),
+                    &mut conexao,
+                )
+                .await
+            {
+                eprintln!("Não foi possível reservar o username {uid:?}: {e}");
+            }
+            Ok(consulta)
+        }
+        .await;
+        ldap.fechar(conexao).await?;
+        r
+    }
+
+    /// Põe o cadastro na [fila de contingência](crate::contingencia) se o
+    /// `erro` for do LDAP fora do ar, retornando
+    /// [`ErroDeCadastro::Enfileirado`]. Sem a fila, ou se ela não puder ser
+    /// gravada, o `erro` volta como veio.
+    fn enfileirar(
+        &self,
+        erro: ErroDeCadastro,
+        (data, hora, codigo): &(String, String, String),
+        ou: &str,
+        config: &ConfiguracaoUsuario,
+    ) -> ErroDeCadastro {
+        let Some(fila) = &config.contingencia else {
+            return erro;
+        };
+        if !erro.ldap_indisponivel() {
+            return erro;
+        }
+
+        let pendente = CadastroPendente {
+            dados: DadosParaCadastro {
+                dre: self.dre.clone(),
+                data: data.clone(),
+                hora: hora.clone(),
+                codigo: codigo.clone(),
+                nome: self.nome.clone(),
+                email: self.email.clone(),
+                telefone: self.telefone.clone(),
+                senha: self.senha.expose_secret().into(),
+                chave_ssh: self.chave_ssh.clone(),
+                username: self.username.clone(),
+                foto: self.foto.clone(),
             },
-            Consulta::Desconhecido => Err(ErroDeCadastro::DocumentoInvalido)?,
+            ou: ou.to_string(),
+            recebido: Utc::now(),
         };
+        match fila.enfileirar(&pendente) {
+            Ok(()) => {
+                eprintln!(
+                    "O cadastro de {:?} foi para a fila de contingência: {erro}",
+                    self.dre,
+                );
+                ErroDeCadastro::Enfileirado
+            },
+            Err(e) => {
+                eprintln!(
+                    "Não foi possível pôr o cadastro de {:?} na fila de \
+                     contingência: {e}",
+                    self.dre,
+                );
+                erro
+            },
+        }
+    }
 
+    /// O resto do [cadastro](Self::cadastrar), depois das consultas e da
+    /// conferência do SIGA: cria a conta na `ou` do curso.
+    async fn concluir<F: FonteLdap>(
+        &self,
+        ou: &'static str,
+        config: &ConfiguracaoUsuario,
+        prazos: &ConfiguracaoRenovacao,
+        ldap: &F,
+        cancelamento: &CancellationToken,
+    ) -> Result<CadastroRealizado, ErroDeCadastro> {
+        // A consulta ao LDAP é refeita dentro da seção crítica, já que outro
+        // cadastro pode ter usado o DRE ou o username enquanto o SIGA
+        // respondia
+        let secao = SECAO_CRITICA.lock().await;
+        // O último ponto em que o cadastro pode parar: daqui em diante, os IDs
+        // são alocados e a conta é criada sem interrupção
+        if cancelamento.is_cancelled() {
+            return Err(ErroDeCadastro::Cancelado);
+        }
+        // A conexão é aberta na consulta e reaproveitada na criação: depois
+        // que a criação começa, uma falha não diz se a conta foi criada, e o
+        // cadastro não pode ir para a fila de contingência
+        let mut conexao = EtapaCadastro::ConsultaLdap
+            .medir(ldap.abrir())
+            .await
+            .map_err(EtapaCadastro::ConsultaLdap.erro_ldap())?;
+        let r = async {
+            let consulta = consultar_escolha(
+                &self.dre,
+                &self.nome,
+                self.username.as_deref(),
+                config.usernames_confundiveis.as_ref(),
+                None,
+                &mut conexao,
+            );
+            let consulta = EtapaCadastro::ConsultaLdap
+                .medir(consulta)
+                .await
+                .map_err(EtapaCadastro::ConsultaLdap.erro_ldap())?;
+            let uid_ldap = match consulta {
+                ConsultaLdap::CadastroDisponivel(uid) => uid,
+                ConsultaLdap::CadastroRedundante(uid) => {
+                    Err(ErroDeCadastro::CadastroRedundante(uid))?
+                },
+            };
+            let cadastro = cadastrar_usuario_em(
+                uid_ldap.clone(),
+                self,
+                config,
+                prazos,
+                ou,
+                &mut conexao,
+            );
+            let inicio = Instant::now();
+            let dn = EtapaCadastro::CriacaoLdap
+                .medir(cadastro)
+                .await
+                .map_err(EtapaCadastro::CriacaoLdap.erro_ldap())?;
+            Ok::<_, ErroDeCadastro>((uid_ldap, dn, inicio.elapsed()))
+        }
+        .await;
+        if let Err(e) = ldap.fechar(conexao).await {
+            eprintln!(
+                "Erro ao fechar a conexão do cadastro de {:?}: {e}",
+                self.dre,
+            );
+        }
+        let (uid_ldap, dn, latencia_criacao) = r?;
+        drop(secao);
 
-        // Verifica se o nome é o mesmo do SIGA
-        
-        // TODO: remover essa porção ruim de código fazendo o self.nome já ser
-        // do tipo nome ao criar a estrutura
-        if Ok(nome_siga.clone()) != self.nome.parse() {
-            Err(ErroDeCadastro::NomesDiferentes {
-                informado: self.nome.clone(),
-                siga: nome_siga,
-            })?
+        // Outro processo pode ter criado uma conta para o mesmo DRE enquanto
+        // esta era criada; com a correção, fica só a mais antiga
+        if let Some(duplicidade) = &config.duplicidade {
+            let verificacao = verificar_duplicidade(
+                duplicidade,
+                &self.dre,
+                (&uid_ldap, &dn),
+                latencia_criacao,
+                ldap,
+            );
+            match verificacao.await {
+                Ok(Some(incidente)) if incidente.corrigido => {
+                    let original = incidente.original().unwrap_or_default();
+                    Err(ErroDeCadastro::CadastroRedundante(original.into()))?
+                },
+                Ok(_) => {},
+                Err(e) => eprintln!(
+                    "Não foi possível verificar a duplicidade do DRE {:?}: {e}",
+                    self.dre,
+                ),
+            }
         }
 
-        self.cadastrar_sem_verificar_documento(
-            uid_ldap.clone(),
-            config,
-            ou,
-            ldap_url,
-            ldap_bind_dn,
-            ldap_bind_pw,
-        )
-        .await?;
+        let dre = self.dre.clone();
+        let nome = normalizar_gecos(&self.nome);
+        let servicos =
+            self.provisionar_servicos(uid_ldap.clone(), dn, config, ou, ldap);
+        EtapaCadastro::Servicos.medir(servicos).await?;
 
-        Ok(uid_ldap)
+        Ok(CadastroRealizado {
+            home: home_directory(&uid_ldap),
+            email: config.dominios_email.email(&uid_ldap, ou),
+            username: uid_ldap,
+            ou,
+            dre,
+            nome,
+        })
     }
 }
@@ -0,0 +1,219 @@
+//! Contagem dos cadastros enviados por cada endereço IP. Quando um mesmo
+//! endereço envia cadastros de muitos DREs diferentes dentro da janela, a
+//! supervisão recebe um aviso; pode ser abuso do formulário, ou só o
+//! laboratório onde os calouros estão se cadastrando juntos. Nenhum cadastro
+//! é recusado pela contagem.
+use crate::notificacao::Mensagem;
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// A contagem dos cadastros por IP, configurada como
+///
+/// ```yaml
+/// cadastros_por_ip:
+///   janela_minutos: 60
+///   limite: 10
+///   destinatario: "supervisao@ic.ufrj.br"
+///   proxy: true
+/// ```
+///
+/// O aviso sai pelo comando da [notificação](crate::notificacao).
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoCadastrosPorIp {
+    /// O tamanho da janela da contagem, em minutos.
+    #[serde(default = "janela_minutos_padrao")]
+    pub janela_minutos: u64,
+    /// Quantos DREs diferentes vindos do mesmo endereço na janela disparam o
+    /// aviso.
+    #[serde(default = "limite_padrao")]
+    pub limite: usize,
+    /// O email da supervisão, que recebe os avisos.
+    pub destinatario: String,
+    /// Se a API fica atrás de um proxy reverso, que passa o endereço do
+    /// aluno no último valor do `X-Forwarded-For`. Sem o proxy, o endereço é
+    /// o da própria conexão.
+    #[serde(default)]
+    pub proxy: bool,
+}
+
+fn janela_minutos_padrao() -> u64 {
+    60
+}
+
+fn limite_padrao() -> usize {
+    10
+}
+
+impl ConfiguracaoCadastrosPorIp {
+    fn janela(&self) -> Duration {
+        Duration::from_secs(self.janela_minutos * 60)
+    }
+
+    /// O endereço de quem enviou a requisição: o último do `X-Forwarded-For`
+    /// se houver [proxy](Self::proxy), ou o da `conexao`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::cadastros_por_ip::ConfiguracaoCadastrosPorIp;
+    /// # use axum::http::HeaderMap;
+    /// let mut cfg = ConfiguracaoCadastrosPorIp {
+    ///     janela_minutos: 60,
+    ///     limite: 10,
+    ///     destinatario: "supervisao@ic.ufrj.br".to_string(),
+    ///     proxy: true,
+    /// };
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert("x-forwarded-for", "10.0.0.1, 146.164.2.7".parse().unwrap());
+    /// let conexao = Some("127.0.0.1".parse().unwrap());
+    ///
+    /// assert_eq!(
+    ///     cfg.endereco(&headers, conexao),
+    ///     Some("146.164.2.7".parse().unwrap()),
+    /// );
+    /// cfg.proxy = false;
+    /// assert_eq!(cfg.endereco(&headers, conexao), conexao);
+    /// ```
+    pub fn endereco(
+        &self,
+        headers: &HeaderMap,
+        conexao: Option<IpAddr>,
+    ) -> Option<IpAddr> {
+        endereco_da_requisicao(self.proxy, headers, conexao)
+    }
+
+    /// O aviso à supervisão de que o endereço `ip` enviou os cadastros dos
+    /// `dres`.
+    pub fn aviso(&self, ip: IpAddr, dres: &[String]) -> Mensagem {
+        Mensagem {
+            para: self.destinatario.clone(),
+            assunto: format!("Muitos cadastros vindos de {ip}"),
+            corpo: format!(
+                "O endereço {ip} enviou cadastros de {} DREs diferentes nos \
+                 últimos {} minutos:\n\n{}",
+                dres.len(),
+                self.janela_minutos,
+                dres.join("\n"),
+            ),
+        }
+    }
+}
+
+/// O endereço de quem enviou a requisição: o último do `X-Forwarded-For`
+/// atrás de um `proxy`, ou o da `conexao`.
+pub(crate) fn endereco_da_requisicao(
+    proxy: bool,
+    headers: &HeaderMap,
+    conexao: Option<IpAddr>,
+) -> Option<IpAddr> {
+    if !proxy {
+        return conexao;
+    }
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .next_back()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Os DREs enviados por um endereço na janela atual.
+struct Janela {
+    inicio: Instant,
+    dres: BTreeSet<String>,
+    avisado: bool,
+}
+
+/// Os cadastros de cada endereço, em janelas fixas que começam no primeiro
+/// cadastro do endereço, como no limite de taxa do helpdesk.
+#[derive(Default)]
+pub struct CadastrosPorIp {
+    janelas: HashMap<IpAddr, Janela>,
+}
+
+impl CadastrosPorIp {
+    /// Conta o cadastro do `dre` enviado pelo `ip` em `agora`. Retorna os
+    /// DREs da janela quando eles chegam ao limite, o que acontece no máximo
+    /// uma vez por janela.
+    pub fn registrar(
+        &mut self,
+        cfg: &ConfiguracaoCadastrosPorIp,
+        ip: IpAddr,
+        dre: &str,
+        agora: Instant,
+    ) -> Option<Vec<String>> {
+        // As janelas vencidas saem, para o mapa não crescer sem parar
+        self.limpar(cfg, agora);
+
+        let j = self.janelas.entry(ip).or_insert_with(|| Janela {
+            inicio: agora,
+            dres: BTreeSet::new(),
+            avisado: false,
+        });
+        j.dres.insert(dre.to_string());
+
+        if j.avisado || j.dres.len() < cfg.limite {
+            return None;
+        }
+        j.avisado = true;
+        Some(j.dres.iter().cloned().collect())
+    }
+
+    /// Esquece as janelas vencidas em `agora`, retornando quantas eram.
+    pub fn limpar(
+        &mut self,
+        cfg: &ConfiguracaoCadastrosPorIp,
+        agora: Instant,
+    ) -> usize {
+        let janela = cfg.janela();
+        let antes = self.janelas.len();
+        self.janelas
+            .retain(|_, j| agora.duration_since(j.inicio) < janela);
+        antes - self.janelas.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn avisa_uma_vez_por_janela() {
+        let cfg = ConfiguracaoCadastrosPorIp {
+            janela_minutos: 60,
+            limite: 3,
+            destinatario: "supervisao@ic.ufrj.br".to_string(),
+            proxy: false,
+        };
+        let ip: IpAddr = "146.164.2.7".parse().unwrap();
+        let outro: IpAddr = "146.164.2.8".parse().unwrap();
+        let inicio = Instant::now();
+        let mut contagem = CadastrosPorIp::default();
+
+        // O mesmo DRE repetido conta uma vez só
+        for dre in ["1", "2", "2"] {
+            assert_eq!(contagem.registrar(&cfg, ip, dre, inicio), None);
+        }
+        assert_eq!(contagem.registrar(&cfg, outro, "3", inicio), None);
+
+        let minuto = Duration::from_secs(60);
+        assert_eq!(
+            contagem.registrar(&cfg, ip, "3", inicio + minuto),
+            Some(vec!["1".into(), "2".into(), "3".into()]),
+        );
+        assert_eq!(contagem.registrar(&cfg, ip, "4", inicio + minuto), None);
+
+        // Na janela seguinte, a contagem recomeça
+        let depois = inicio + 60 * minuto;
+        for dre in ["5", "6"] {
+            assert_eq!(contagem.registrar(&cfg, ip, dre, depois), None);
+        }
+        assert!(contagem.registrar(&cfg, ip, "7", depois).is_some());
+    }
+}
@@ -0,0 +1,138 @@
+//! Criação da caixa de email institucional das contas novas, pela API do
+//! servidor de email ou por um comando. O estado de cada caixa fica no LDAP,
+//! para que as que falharem possam ser criadas depois com `alumnic caixas`.
+use crate::ldap::ErroLdap;
+use crate::ldap::caixa_email::{
+    CaixaPendente, EstadoCaixa, buscar_caixas_pendentes, marcar_caixa,
+};
+use crate::ldap::conexao::FonteLdap;
+use reqwest::header::CONTENT_TYPE;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::process::ExitStatus;
+use thiserror::Error;
+use tokio::process::Command;
+
+/// Como as caixas são criadas, configurado como
+///
+/// ```yaml
+/// tipo: api
+/// url: "https://email.ic.ufrj.br/api/caixas"
+/// token: "..."
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum ConfiguracaoCaixa {
+    /// Envia o `endereco`, o `uid` e o `nome` em JSON por um `POST` para a
+    /// `url`, com o `token` no cabeçalho `Authorization: Bearer`.
+    Api {
+        url: String,
+        #[serde(default)]
+        token: Option<SecretString>,
+    },
+    /// Roda o comando com os dados da caixa nas variáveis de ambiente
+    /// `ALUMNIC_EMAIL`, `ALUMNIC_UID` e `ALUMNIC_NOME`.
+    Comando { comando: Vec<String> },
+}
+
+#[derive(Debug, Error)]
+pub enum ErroCaixa {
+    #[error("o comando da caixa de email não foi configurado")]
+    SemComando,
+    #[error("não foi possível rodar o comando: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("o comando falhou com {0}")]
+    Falha(ExitStatus),
+    #[error("a chamada ao servidor de email falhou: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("não foi possível guardar o estado da caixa: {0}")]
+    ErroLdap(#[from] ErroLdap),
+}
+
+impl ConfiguracaoCaixa {
+    /// Cria a caixa `endereco` do usuário `uid`.
+    pub async fn criar(
+        &self,
+        endereco: &str,
+        uid: &str,
+        nome: &str,
+    ) -> Result<(), ErroCaixa> {
+        match self {
+            ConfiguracaoCaixa::Api { url, token } => {
+                let corpo = serde_json::json!({
+                    "endereco": endereco,
+                    "uid": uid,
+                    "nome": nome,
+                });
+
+                let mut requisicao = reqwest::Client::new()
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(corpo.to_string());
+                if let Some(token) = token {
+                    requisicao = requisicao.bearer_auth(token.expose_secret());
+                }
+                requisicao.send().await?.error_for_status()?;
+            },
+            ConfiguracaoCaixa::Comando { comando } => {
+                let (programa, argumentos) =
+                    comando.split_first().ok_or(ErroCaixa::SemComando)?;
+
+                let status = Command::new(programa)
+                    .args(argumentos)
+                    .env("ALUMNIC_EMAIL", endereco)
+                    .env("ALUMNIC_UID", uid)
+                    .env("ALUMNIC_NOME", nome)
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(ErroCaixa::Falha(status));
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Cria a caixa da conta `pendente` e grava no LDAP se ela foi criada ou se
+/// falhou.
+pub async fn provisionar<F: FonteLdap>(
+    cfg: &ConfiguracaoCaixa,
+    pendente: &CaixaPendente,
+    ldap: &F,
+) -> Result<(), ErroCaixa> {
+    let r = cfg
+        .criar(&pendente.endereco, &pendente.uid, &pendente.nome)
+        .await;
+    let estado = match r {
+        Ok(()) => EstadoCaixa::Criada,
+        Err(_) => EstadoCaixa::Falhou,
+    };
+
+    let mut conexao = ldap.abrir().await?;
+    let marcada = marcar_caixa(&pendente.dn, estado, &mut conexao).await;
+    ldap.fechar(conexao).await?;
+    marcada?;
+
+    r
+}
+
+/// Tenta criar de novo as caixas pendentes ou que falharam, retornando o
+/// resultado de cada conta.
+pub async fn provisionar_pendentes<F: FonteLdap>(
+    cfg: &ConfiguracaoCaixa,
+    ldap: &F,
+) -> Result<Vec<(CaixaPendente, Result<(), ErroCaixa>)>, ErroLdap> {
+    let mut conexao = ldap.abrir().await?;
+    let pendentes = buscar_caixas_pendentes(&mut conexao).await;
+    ldap.fechar(conexao).await?;
+
+    let mut resultados = vec![];
+    for pendente in pendentes? {
+        let r = provisionar(cfg, &pendente, ldap).await;
+        resultados.push((pendente, r));
+    }
+
+    Ok(resultados)
+}
@@ -0,0 +1,39 @@
+//! Cancelamento cooperativo das operações em andamento quando o processo
+//! recebe SIGTERM ou SIGINT. O sinal cancela um [`CancellationToken`], que é
+//! passado aos cadastros e aos lotes; eles só param nos pontos seguros, antes
+//! de começar uma linha do lote ou de o cadastro entrar na seção crítica, e
+//! nunca entre a alocação dos IDs e o add da conta. As linhas que não
+//! chegaram a começar voltam com um erro de cancelamento.
+use tokio::signal::unix::{SignalKind, signal};
+use tokio_util::sync::CancellationToken;
+
+/// Cancela o `token` no primeiro SIGTERM ou SIGINT. Um segundo sinal encerra
+/// o processo na hora, para quem não quer esperar as operações em andamento.
+pub async fn cancelar_no_termino(token: CancellationToken) {
+    let (mut termino, mut interrupcao) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(termino), Ok(interrupcao)) => (termino, interrupcao),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Não foi possível receber o SIGTERM e o SIGINT: {e}");
+            return;
+        },
+    };
+
+    tokio::select! {
+        _ = termino.recv() => {},
+        _ = interrupcao.recv() => {},
+    }
+    eprintln!(
+        "Encerrando depois das operações em andamento; um segundo sinal \
+         encerra na hora"
+    );
+    token.cancel();
+
+    tokio::select! {
+        _ = termino.recv() => {},
+        _ = interrupcao.recv() => {},
+    }
+    std::process::exit(130);
+}
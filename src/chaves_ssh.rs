@@ -0,0 +1,146 @@
+//! Módulo com a gerência das chaves SSH públicas das contas depois do
+//! cadastro. Cada chave adicionada ou removida é registrada na auditoria.
+use crate::auditoria::{ConfiguracaoAuditoria, Registro, registrar};
+use crate::ldap::ErroLdap;
+use crate::ldap::chaves_ssh::{self, ChavesDaConta, buscar_chaves};
+use crate::ldap::conexao::FonteLdap;
+use crate::utils::validacao_entradas::processar_chave_ssh;
+use chrono::{DateTime, Utc};
+use ssh_key::{HashAlg, PublicKey};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroChaveSsh {
+    #[error("A chave SSH não é uma chave pública válida")]
+    ChaveInvalida,
+    #[error("Não existe conta com o uid {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta já tem a chave {0}")]
+    ChaveRepetida(String),
+    #[error("A conta não tem a chave {0:?}")]
+    ChaveInexistente(String),
+    #[error("Houve um problema ao alterar as chaves no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("As chaves foram alteradas, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+/// A impressão digital SHA256 da `chave`, como a mostrada pelo
+/// `ssh-keygen -l`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::chaves_ssh::impressao_digital;
+/// assert_eq!(
+///     impressao_digital("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIDgJE5tJHqoMuq5JLqULfbJIvBzZ51WPDlDDxodR6vun"),
+///     Some("SHA256:jGfrNhkq7KoGXSqttQMF265Tbv2ltp64A6do8OzrP8Q".to_string()),
+/// );
+/// ```
+pub fn impressao_digital(chave: &str) -> Option<String> {
+    let chave = PublicKey::from_openssh(chave.trim()).ok()?;
+    Some(chave.fingerprint(HashAlg::Sha256).to_string())
+}
+
+/// Busca as chaves da conta com o `uid`.
+async fn chaves_da_conta<F: FonteLdap>(
+    uid: &str,
+    ldap: &F,
+) -> Result<ChavesDaConta, ErroChaveSsh> {
+    let mut conexao = ldap.abrir().await?;
+    let r = buscar_chaves(uid, &mut conexao).await;
+    ldap.fechar(conexao).await?;
+    r?.ok_or_else(|| ErroChaveSsh::ContaInexistente(uid.into()))
+}
+
+/// Lista as chaves SSH da conta com o `uid`.
+pub async fn listar_chaves<F: FonteLdap>(
+    uid: &str,
+    ldap: &F,
+) -> Result<Vec<String>, ErroChaveSsh> {
+    Ok(chaves_da_conta(uid, ldap).await?.chaves)
+}
+
+/// Adiciona a `chave` à conta com o `uid`, retornando a sua impressão
+/// digital.
+pub async fn adicionar_chave<F: FonteLdap>(
+    uid: &str,
+    chave: &str,
+    auditoria: &ConfiguracaoAuditoria,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<String, ErroChaveSsh> {
+    let chave =
+        processar_chave_ssh(chave).ok_or(ErroChaveSsh::ChaveInvalida)?;
+    let impressao =
+        impressao_digital(&chave).ok_or(ErroChaveSsh::ChaveInvalida)?;
+
+    let conta = chaves_da_conta(uid, ldap).await?;
+    if conta
+        .chaves
+        .iter()
+        .any(|c| impressao_digital(c).as_ref() == Some(&impressao))
+    {
+        return Err(ErroChaveSsh::ChaveRepetida(impressao));
+    }
+
+    let mut conexao = ldap.abrir().await?;
+    let r = chaves_ssh::adicionar_chave(&conta, &chave, &mut conexao).await;
+    ldap.fechar(conexao).await?;
+    r?;
+
+    registrar(
+        auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "adicionar_chave_ssh",
+            uid,
+            motivo: &impressao,
+        },
+    )
+    .await?;
+
+    Ok(impressao)
+}
+
+/// Remove da conta com o `uid` a chave informada pela própria chave ou pela
+/// impressão digital, retornando a chave removida.
+pub async fn remover_chave<F: FonteLdap>(
+    uid: &str,
+    chave_ou_impressao: &str,
+    auditoria: &ConfiguracaoAuditoria,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<String, ErroChaveSsh> {
+    let procurada = chave_ou_impressao.trim();
+    let impressao =
+        impressao_digital(procurada).unwrap_or_else(|| procurada.to_string());
+
+    let conta = chaves_da_conta(uid, ldap).await?;
+    let Some(chave) = conta
+        .chaves
+        .iter()
+        .find(|c| impressao_digital(c).as_ref() == Some(&impressao))
+        .cloned()
+    else {
+        return Err(ErroChaveSsh::ChaveInexistente(procurada.into()));
+    };
+
+    let mut conexao = ldap.abrir().await?;
+    let r = chaves_ssh::remover_chave(&conta, &chave, &mut conexao).await;
+    ldap.fechar(conexao).await?;
+    r?;
+
+    registrar(
+        auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "remover_chave_ssh",
+            uid,
+            motivo: &impressao,
+        },
+    )
+    .await?;
+
+    Ok(chave)
+}
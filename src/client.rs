@@ -0,0 +1,269 @@
+//! Cliente tipado da API, para o CLI remoto e os outros serviços do IC. As
+//! requisições e as respostas usam os mesmos tipos da própria API, como os
+//! [`DadosParaCadastro`] e as [`Estatisticas`]. Só existe com a feature
+//! `client`.
+//!
+//! ```no_run
+//! # async fn exemplo() -> Result<(), alumnic::client::ErroCliente> {
+//! use alumnic::client::ClienteApi;
+//!
+//! let api = ClienteApi::novo("https://cadastro.ic.ufrj.br")
+//!     .com_token("TOKEN".into());
+//! if api.saude().await?.somente_leitura {
+//!     println!("A API está em modo somente leitura");
+//! }
+//! println!("{:?}", api.estatisticas().await?.cadastros_por_curso);
+//! # Ok(())
+//! # }
+//! ```
+use crate::api::{HEADER_TOTP, ResponseBody, Saude, UsernamesLivres};
+use crate::cadastro_aluno::{
+    DadosParaCadastro, DadosParaValidacao, ValidacaoRealizada,
+};
+use crate::estatisticas::Estatisticas;
+use crate::manutencao::{ModoInterrupcao, ModoManutencao};
+use crate::renovacao::DadosParaRenovacao;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, RequestBuilder};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroCliente {
+    #[error("Não foi possível falar com a API: {0}")]
+    Http(#[from] reqwest::Error),
+    /// A API respondeu com um erro; a `mensagem` é a do corpo da resposta.
+    #[error("A API respondeu {status}: {mensagem}")]
+    Api { status: u16, mensagem: String },
+    #[error("A resposta da API não está no formato esperado: {0}")]
+    Resposta(#[from] serde_json::Error),
+}
+
+/// Um cliente da API no endereço `url`, com as credenciais das rotas
+/// administrativas, se houver.
+#[derive(Clone)]
+pub struct ClienteApi {
+    url: String,
+    credenciais: Option<Credenciais>,
+    http: reqwest::Client,
+}
+
+/// Como o cliente se autentica nas rotas administrativas.
+#[derive(Clone)]
+enum Credenciais {
+    Token(SecretString),
+    Supervisor { uid: String, senha: SecretString },
+}
+
+impl ClienteApi {
+    /// Um cliente da API em `url`, como `https://cadastro.ic.ufrj.br`.
+    pub fn novo(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            credenciais: None,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Usa o `api_token` nas rotas administrativas.
+    pub fn com_token(mut self, token: SecretString) -> Self {
+        self.credenciais = Some(Credenciais::Token(token));
+        self
+    }
+
+    /// Entra nas rotas administrativas como o supervisor `uid`, com o
+    /// [papel](crate::autorizacao::Papel) dele.
+    pub fn com_supervisor(
+        mut self,
+        uid: impl Into<String>,
+        senha: SecretString,
+    ) -> Self {
+        self.credenciais = Some(Credenciais::Supervisor {
+            uid: uid.into(),
+            senha,
+        });
+        self
+    }
+
+    fn requisicao(&self, metodo: Method, caminho: &str) -> RequestBuilder {
+        let req = self.http.request(metodo, format!("{}{caminho}", self.url));
+        match &self.credenciais {
+            Some(Credenciais::Token(token)) => {
+                req.bearer_auth(token.expose_secret())
+            },
+            Some(Credenciais::Supervisor { uid, senha }) => {
+                req.basic_auth(uid, Some(senha.expose_secret()))
+            },
+            None => req,
+        }
+    }
+
+    /// Envia a `req`, com o `corpo` em JSON se houver, e retorna o texto da
+    /// resposta, ou o erro com a mensagem dela.
+    async fn enviar(
+        &self,
+        req: RequestBuilder,
+        corpo: Option<&impl Serialize>,
+    ) -> Result<String, ErroCliente> {
+        let req = match corpo {
+            Some(corpo) => req
+                .header(CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(corpo)?),
+            None => req,
+        };
+        let res = req.send().await?;
+        let status = res.status();
+        let texto = res.text().await?;
+        if status.is_success() {
+            return Ok(texto);
+        }
+
+        // Os erros da API vêm com a mensagem no JSON; os das rotas
+        // administrativas, só com o status
+        let mensagem = serde_json::from_str::<ResponseBody>(&texto)
+            .map(|r| r.message)
+            .unwrap_or(texto);
+        Err(ErroCliente::Api {
+            status: status.as_u16(),
+            mensagem,
+        })
+    }
+
+    async fn json<R: DeserializeOwned>(
+        &self,
+        metodo: Method,
+        caminho: &str,
+        corpo: Option<&impl Serialize>,
+    ) -> Result<R, ErroCliente> {
+        let texto =
+            self.enviar(self.requisicao(metodo, caminho), corpo).await?;
+        Ok(serde_json::from_str(&texto)?)
+    }
+
+    /// Cadastra o aluno, retornando a mensagem de sucesso com o username.
+    pub async fn cadastrar(
+        &self,
+        dados: &DadosParaCadastro,
+    ) -> Result<ResponseBody, ErroCliente> {
+        self.json(Method::POST, "/api/cadastrar", Some(dados)).await
+    }
+
+    /// Confere os dados do cadastro antes da senha, retornando os dados
+    /// normalizados e os usernames candidatos.
+    pub async fn validar(
+        &self,
+        dados: &DadosParaValidacao,
+    ) -> Result<ValidacaoRealizada, ErroCliente> {
+        self.json(Method::POST, "/api/validar", Some(dados)).await
+    }
+
+    /// Renova a conta do documento, retornando a mensagem com a nova
+    /// validade.
+    pub async fn renovar(
+        &self,
+        dados: &DadosParaRenovacao,
+    ) -> Result<ResponseBody, ErroCliente> {
+        self.json(Method::POST, "/api/renovar", Some(dados)).await
+    }
+
+    /// Os usernames livres para o `nome`.
+    pub async fn usernames(
+        &self,
+        nome: &str,
+    ) -> Result<Vec<String>, ErroCliente> {
+        let req = self
+            .requisicao(Method::GET, "/api/usernames")
+            .query(&[("nome", nome)]);
+        let texto = self.enviar(req, None::<&()>).await?;
+        Ok(serde_json::from_str::<UsernamesLivres>(&texto)?.usernames)
+    }
+
+    /// O healthcheck, com os modos de manutenção ativos.
+    pub async fn saude(&self) -> Result<Saude, ErroCliente> {
+        self.json(Method::GET, "/api/saude", None::<&()>).await
+    }
+
+    /// As estatísticas acumuladas desde o início do serviço.
+    pub async fn estatisticas(&self) -> Result<Estatisticas, ErroCliente> {
+        self.json(Method::GET, "/api/admin/estatisticas", None::<&()>)
+            .await
+    }
+
+    /// As métricas no formato do Prometheus.
+    pub async fn metricas(&self) -> Result<String, ErroCliente> {
+        self.enviar(
+            self.requisicao(Method::GET, "/api/admin/metricas"),
+            None::<&()>,
+        )
+        .await
+    }
+
+    /// O modo somente leitura atual.
+    pub async fn somente_leitura(&self) -> Result<ModoManutencao, ErroCliente> {
+        self.json(Method::GET, "/api/admin/somente-leitura", None::<&()>)
+            .await
+    }
+
+    /// Muda o modo somente leitura, retornando o novo.
+    pub async fn mudar_somente_leitura(
+        &self,
+        modo: &ModoManutencao,
+    ) -> Result<ModoManutencao, ErroCliente> {
+        self.json(Method::PUT, "/api/admin/somente-leitura", Some(modo))
+            .await
+    }
+
+    /// A interrupção atual da API pública.
+    pub async fn interrupcao(&self) -> Result<ModoInterrupcao, ErroCliente> {
+        self.json(Method::GET, "/api/admin/manutencao", None::<&()>)
+            .await
+    }
+
+    /// Muda a interrupção da API pública, retornando a nova.
+    pub async fn mudar_interrupcao(
+        &self,
+        modo: &ModoInterrupcao,
+    ) -> Result<ModoInterrupcao, ErroCliente> {
+        self.json(Method::PUT, "/api/admin/manutencao", Some(modo))
+            .await
+    }
+
+    /// Remove a conta com o `uid` na hora. Só um supervisor pode fazê-lo,
+    /// com o `codigo` TOTP dele.
+    pub async fn remover_conta(
+        &self,
+        uid: &str,
+        motivo: &str,
+        codigo: &str,
+    ) -> Result<ResponseBody, ErroCliente> {
+        let req = self
+            .requisicao(
+                Method::POST,
+                &format!("/api/admin/contas/{uid}/remover"),
+            )
+            .header(HEADER_TOTP, codigo);
+        let texto =
+            self.enviar(req, Some(&json!({ "motivo": motivo }))).await?;
+        Ok(serde_json::from_str(&texto)?)
+    }
+
+    /// Redefine a senha da conta com o `uid`. Só um supervisor pode fazê-lo,
+    /// com o `codigo` TOTP dele.
+    pub async fn redefinir_senha(
+        &self,
+        uid: &str,
+        senha: &SecretString,
+        motivo: &str,
+        codigo: &str,
+    ) -> Result<ResponseBody, ErroCliente> {
+        let req = self
+            .requisicao(Method::POST, &format!("/api/admin/contas/{uid}/senha"))
+            .header(HEADER_TOTP, codigo);
+        let corpo = json!({ "senha": senha.expose_secret(), "motivo": motivo });
+        let texto = self.enviar(req, Some(&corpo)).await?;
+        Ok(serde_json::from_str(&texto)?)
+    }
+}
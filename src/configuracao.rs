@@ -1,15 +1,231 @@
+use crate::auditoria::ConfiguracaoAuditoria;
+use crate::autorizacao::ConfiguracaoAutorizacao;
+use crate::backup::ConfiguracaoBackup;
+use crate::cadastros_por_ip::ConfiguracaoCadastrosPorIp;
+use crate::caixa_email::ConfiguracaoCaixa;
+use crate::contingencia::ConfiguracaoContingencia;
+use crate::cotas::ConfiguracaoCotas;
+use crate::docente::ConfiguracaoDocentes;
+use crate::duplicidade::ConfiguracaoDuplicidade;
+use crate::espelho_ad::ConfiguracaoAd;
+use crate::foto::ConfiguracaoFoto;
+#[cfg(feature = "grpc")]
+use crate::grpc::ConfiguracaoGrpc;
+use crate::hooks::ConfiguracaoHooks;
+use crate::impressao::ConfiguracaoImpressao;
+#[cfg(feature = "kerberos")]
+use crate::kerberos::ConfiguracaoKerberos;
+use crate::ldap::ConfiguracaoPool;
+use crate::ldap::alias_email::ConfiguracaoAliasEmail;
+use crate::ldap::conexao::{CertificadoCliente, ConfiguracaoTls};
+use crate::ldap::dominios_email::ConfiguracaoDominiosEmail;
+use crate::manutencao::ConfiguracaoManutencao;
+use crate::moodle::ConfiguracaoMoodle;
+use crate::notificacao::ConfiguracaoNotificacao;
+use crate::origens::ConfiguracaoOrigens;
+use crate::painel::ConfiguracaoPainel;
+use crate::portal_ufrj::{GNOSYS_URL, LeituraResposta};
+use crate::proximos_passos::ConfiguracaoProximosPassos;
+use crate::scim::ConfiguracaoScim;
+use crate::totp::ConfiguracaoTotp;
+use crate::turmas::ConfiguracaoTurmas;
+use crate::utils::confundiveis::ConfiguracaoConfundiveis;
+use crate::utils::validacao_entradas::telefone_nacional;
 use config::{Config, ConfigError, File};
 use directories::ProjectDirs;
+use secrecy::SecretString;
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Configuracao {
     pub ldap_url: String,
+    /// O DN e a senha do bind simples. Não são usados quando o
+    /// `ldap_certificado` é configurado.
+    #[serde(default)]
     pub ldap_bind_dn: String,
+    #[serde(default)]
     pub ldap_bind_pw: String,
+    /// O certificado de cliente TLS com que o alumnic se autentica no LDAP
+    /// por SASL EXTERNAL, no lugar da senha de bind.
+    #[serde(default)]
+    pub ldap_certificado: Option<CertificadoCliente>,
+    /// O [TLS](crate::ldap::conexao::ConfiguracaoTls) da conexão: a CA do
+    /// servidor e o StartTLS.
+    #[serde(default)]
+    pub ldap_tls: ConfiguracaoTls,
+    /// O tempo máximo, em segundos, da conexão e de cada operação no LDAP.
+    /// Uma operação que passa dele falha com
+    /// [`ErroLdap::Timeout`](crate::ldap::ErroLdap::Timeout).
+    #[serde(default = "ldap_timeout_padrao")]
+    pub ldap_timeout_segundos: u64,
+    /// As [réplicas](crate::ldap::conexao::Replicas) só de leitura, usadas
+    /// nas consultas quando o `ldap_url` está fora do ar.
+    #[serde(default)]
+    pub ldap_replicas: Vec<String>,
+    /// O [pool](crate::ldap::LdapPool) das conexões da API.
+    #[serde(default)]
+    pub ldap_pool: ConfiguracaoPool,
 
     pub usuario_novo: ConfiguracaoUsuario,
+
+    /// Token exigido pelas rotas administrativas da API, no cabeçalho
+    /// `Authorization: Bearer <token>`. Se não for definido, as rotas
+    /// administrativas ficam desativadas.
+    #[serde(default)]
+    pub api_token: Option<SecretString>,
+
+    /// Um socket Unix onde a API também escuta. Quem consegue abrir o socket
+    /// usa as rotas administrativas sem o `api_token`.
+    #[serde(default)]
+    pub socket_admin: Option<ConfiguracaoSocketAdmin>,
+
+    /// O serviço gRPC para as integrações internas. Se não for definido, só
+    /// a API REST é servida.
+    #[cfg(feature = "grpc")]
+    #[serde(default)]
+    pub grpc: Option<ConfiguracaoGrpc>,
+
+    /// Endereço do Gnosys usado para autenticar os documentos. Só precisa ser
+    /// mudado para testes.
+    #[serde(default = "gnosys_url_padrao")]
+    pub gnosys_url: String,
+
+    /// Como as respostas do Gnosys são lidas: `estrita`, só na ordem de
+    /// sempre, ou `tolerante`, pelos rótulos dos campos.
+    #[serde(default)]
+    pub leitura_gnosys: LeituraResposta,
+
+    #[serde(default)]
+    pub renovacao: ConfiguracaoRenovacao,
+
+    #[serde(default)]
+    pub desligamento: ConfiguracaoDesligamento,
+
+    #[serde(default)]
+    pub notificacao: ConfiguracaoNotificacao,
+
+    #[serde(default)]
+    pub auditoria: ConfiguracaoAuditoria,
+
+    #[serde(default)]
+    pub hooks: ConfiguracaoHooks,
+
+    /// Dados da pós-graduação para onde os alunos da graduação podem migrar.
+    /// Se não for definida, a migração fica desativada.
+    #[serde(default)]
+    pub pos: Option<ConfiguracaoPos>,
+
+    #[serde(default)]
+    pub projetos: ConfiguracaoProjetos,
+
+    /// As contas de docentes e técnicos criadas pela supervisão. Se não for
+    /// definida, a criação fica desativada.
+    #[serde(default)]
+    pub docentes: Option<ConfiguracaoDocentes>,
+
+    /// O Active Directory dos laboratórios Windows, que espelha as contas.
+    /// Se não for definido, o espelhamento fica desativado.
+    #[serde(default)]
+    pub ad: Option<ConfiguracaoAd>,
+
+    /// A consulta das contas pelo helpdesk (o GLPI). Se não for definida, a
+    /// rota de consulta fica desativada.
+    #[serde(default)]
+    pub helpdesk: Option<ConfiguracaoHelpdesk>,
+
+    /// As rotas SCIM, só de leitura. Se não forem configuradas, ficam
+    /// desativadas.
+    #[serde(default)]
+    pub scim: Option<ConfiguracaoScim>,
+
+    /// O painel web administrativo em `/painel`. Se não for configurado, o
+    /// painel fica desativado.
+    #[serde(default)]
+    pub painel: Option<ConfiguracaoPainel>,
+    /// Os papéis dos supervisores, no painel e nas rotas administrativas.
+    /// Se não for definida, todos os supervisores podem tudo.
+    #[serde(default)]
+    pub autorizacao: Option<ConfiguracaoAutorizacao>,
+    /// O segundo fator exigido na remoção de contas e na redefinição de
+    /// senhas pela API. Sem ele, essas rotas não existem.
+    #[serde(default)]
+    pub totp: Option<ConfiguracaoTotp>,
+
+    /// As chaves com que os backups em LDIF são assinados e verificados. Se
+    /// não for configurado, a restauração aceita backups sem assinatura.
+    #[serde(default)]
+    pub backup: Option<ConfiguracaoBackup>,
+
+    /// De onde vem o uso das cotas no servidor de arquivos, para o relatório
+    /// de uso. Se não for configurado, o relatório fica desativado.
+    #[serde(default)]
+    pub cotas: Option<ConfiguracaoCotas>,
+
+    /// O aviso à supervisão quando um mesmo IP envia muitos cadastros. Se
+    /// não for configurado, os cadastros não são contados.
+    #[serde(default)]
+    pub cadastros_por_ip: Option<ConfiguracaoCadastrosPorIp>,
+
+    /// As redes pelas quais os cadastros são contados nas
+    /// [estatísticas de origem](crate::origens). Se não for configurado, a
+    /// origem não é contada.
+    #[serde(default)]
+    pub origens: Option<ConfiguracaoOrigens>,
+
+    #[serde(default)]
+    pub turmas: ConfiguracaoTurmas,
+
+    /// A limpeza periódica do estado auxiliar do cadastro, como as reservas
+    /// de username vencidas.
+    #[serde(default)]
+    pub limpeza: ConfiguracaoLimpeza,
+
+    /// Os modelos dos próximos passos mandados na resposta do cadastro.
+    #[serde(default)]
+    pub proximos_passos: ConfiguracaoProximosPassos,
+
+    #[serde(default)]
+    pub concorrencia: ConfiguracaoConcorrencia,
+
+    #[serde(default)]
+    pub manutencao: ConfiguracaoManutencao,
+
+    #[serde(default)]
+    pub features: Funcionalidades,
+
+    /// O diretório dos dados gravados pelo alumnic. Os caminhos relativos da
+    /// `contingencia` e da `duplicidade` são relativos a ele.
+    #[serde(default = "dados_padrao")]
+    pub dados: PathBuf,
+    /// O diretório dos registros gravados pelo alumnic. Os caminhos relativos
+    /// da `auditoria` e do registro dos `hooks` são relativos a ele.
+    #[serde(default = "logs_padrao")]
+    pub logs: PathBuf,
+}
+
+/// O arquivo de configuração quando o usuário não tem um
+/// `~/.config/alumnic/config.yaml`, como o usuário dedicado de um serviço do
+/// systemd.
+pub const CONFIG_PADRAO: &str = "/etc/alumnic/config.yaml";
+
+fn dados_padrao() -> PathBuf {
+    PathBuf::from("/var/lib/alumnic")
+}
+
+fn logs_padrao() -> PathBuf {
+    PathBuf::from("/var/log/alumnic")
+}
+
+fn gnosys_url_padrao() -> String {
+    GNOSYS_URL.to_string()
+}
+
+pub(crate) fn ldap_timeout_padrao() -> u64 {
+    30
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -21,27 +237,551 @@ pub struct ConfiguracaoUsuario {
     pub samba_password_history: String,
     pub samba_primary_group_sid: String,
     pub cota: String,
+    /// A criação da caixa de email institucional de cada conta nova. Se não
+    /// for definida, as caixas não são criadas pelo alumnic.
+    #[serde(default)]
+    pub caixa_email: Option<ConfiguracaoCaixa>,
+    /// O registro de cada conta nova no sistema de impressão, com a cota
+    /// inicial do perfil. Se não for definido, as contas não são
+    /// registradas pelo alumnic.
+    #[serde(default)]
+    pub impressao: Option<ConfiguracaoImpressao>,
+    /// A inscrição de cada conta nova no Moodle. Se não for definida, as
+    /// contas não são inscritas pelo alumnic.
+    #[serde(default)]
+    pub moodle: Option<ConfiguracaoMoodle>,
+    /// O principal Kerberos criado junto com cada conta nova. Se não for
+    /// definido, nenhum principal é criado.
+    #[cfg(feature = "kerberos")]
+    #[serde(default)]
+    pub kerberos: Option<ConfiguracaoKerberos>,
+    /// O curso e o período de ingresso gravados em cada conta nova. Se não
+    /// for definido, a entrada fica sem esses atributos.
+    #[serde(default)]
+    pub campos_academicos: Option<ConfiguracaoCamposAcademicos>,
+    /// A reutilização dos uidNumbers das contas removidas. Se não for
+    /// definida, os uidNumbers só saem do contador do domínio do Samba.
+    #[serde(default)]
+    pub reutilizacao_uids: Option<ConfiguracaoReutilizacaoUids>,
+    /// A reserva do username durante o cadastro. Se não for definida, o
+    /// username só é garantido dentro da seção crítica.
+    #[serde(default)]
+    pub reserva_username: Option<ConfiguracaoReservaUsername>,
+    /// A rejeição dos usernames novos confundíveis com os que já existem. Se
+    /// não for definida, só os usernames iguais são rejeitados.
+    #[serde(default)]
+    pub usernames_confundiveis: Option<ConfiguracaoConfundiveis>,
+    /// A foto opcional enviada no cadastro. Se não for definida, a foto
+    /// enviada é ignorada.
+    #[serde(default)]
+    pub foto: Option<ConfiguracaoFoto>,
+    /// A fila dos cadastros recebidos com o LDAP fora do ar. Se não for
+    /// definida, esses cadastros falham e o aluno precisa tentar de novo.
+    #[serde(default)]
+    pub contingencia: Option<ConfiguracaoContingencia>,
+    /// O alias `nome.sobrenome` criado junto com o email institucional. Se
+    /// não for definido, a conta só tem o `mail` com o username.
+    #[serde(default)]
+    pub alias_email: Option<ConfiguracaoAliasEmail>,
+    /// A verificação de que o DRE não ficou com duas contas depois do
+    /// cadastro. Se não for definida, a verificação não é feita.
+    #[serde(default)]
+    pub duplicidade: Option<ConfiguracaoDuplicidade>,
+    #[serde(default)]
+    pub telefone: ConfiguracaoTelefone,
+    /// Os domínios do `mail` de cada OU.
+    #[serde(default)]
+    pub dominios_email: ConfiguracaoDominiosEmail,
+    /// O atributo em que as contas criadas pela supervisão, fora do fluxo do
+    /// aluno, guardam quem as criou, como `description`. Se não for
+    /// definido, quem criou a conta só fica na auditoria.
+    #[serde(default)]
+    pub atributo_responsavel: Option<String>,
+}
+
+/// Como o telefone, validado e normalizado na entrada, é gravado na conta
+/// nova.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoTelefone {
+    pub formato: FormatoTelefone,
+    /// O atributo do formato nacional quando o `formato` é `ambos`.
+    pub atributo_nacional: String,
+}
+
+impl Default for ConfiguracaoTelefone {
+    fn default() -> Self {
+        Self {
+            formato: FormatoTelefone::default(),
+            atributo_nacional: "mobile".to_string(),
+        }
+    }
+}
+
+/// O formato do telefone gravado no `telephoneNumber`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FormatoTelefone {
+    /// Como `+5521987654321`.
+    #[default]
+    E164,
+    /// Como `(21) 98765-4321`, esperado por alguns sistemas.
+    Nacional,
+    /// O E.164 no `telephoneNumber` e o nacional no `atributo_nacional`.
+    Ambos,
+}
+
+impl ConfiguracaoTelefone {
+    /// O valor do `telephoneNumber` para o `telefone`, que está no formato
+    /// E.164.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::configuracao::{ConfiguracaoTelefone, FormatoTelefone};
+    /// let mut cfg = ConfiguracaoTelefone::default();
+    /// assert_eq!(cfg.telephone_number("+5521987654321"), "+5521987654321");
+    /// assert_eq!(cfg.nacional_a_parte("+5521987654321"), None);
+    ///
+    /// cfg.formato = FormatoTelefone::Nacional;
+    /// assert_eq!(cfg.telephone_number("+5521987654321"), "(21) 98765-4321");
+    ///
+    /// cfg.formato = FormatoTelefone::Ambos;
+    /// assert_eq!(cfg.telephone_number("+5521987654321"), "+5521987654321");
+    /// assert_eq!(
+    ///     cfg.nacional_a_parte("+5521987654321"),
+    ///     Some(("mobile", "(21) 98765-4321".to_string())),
+    /// );
+    /// ```
+    pub fn telephone_number(&self, telefone: &str) -> String {
+        match self.formato {
+            FormatoTelefone::Nacional => telefone_nacional(telefone),
+            FormatoTelefone::E164 | FormatoTelefone::Ambos => {
+                telefone.to_string()
+            },
+        }
+    }
+
+    /// O atributo com o formato nacional do `telefone`, se ele for gravado
+    /// além do `telephoneNumber`.
+    pub fn nacional_a_parte(&self, telefone: &str) -> Option<(&str, String)> {
+        (self.formato == FormatoTelefone::Ambos).then(|| {
+            (self.atributo_nacional.as_str(), telefone_nacional(telefone))
+        })
+    }
+}
+
+/// A [reserva](crate::ldap::reservas) do username escolhido para um aluno,
+/// do começo do cadastro até a criação da conta.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoReservaUsername {
+    /// Por quantos minutos a reserva vale.
+    pub minutos: i64,
+}
+
+impl Default for ConfiguracaoReservaUsername {
+    fn default() -> Self {
+        Self { minutos: 30 }
+    }
+}
+
+/// A limpeza do estado auxiliar que cresce durante o período de matrícula:
+/// as [reservas de username](crate::ldap::reservas) vencidas, as sessões
+/// expiradas do [painel](crate::painel) e as janelas vencidas dos
+/// [cadastros por IP](crate::cadastros_por_ip).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoLimpeza {
+    /// De quantos em quantos minutos a limpeza roda no `alumnic serve`.
+    pub intervalo_minutos: u64,
+}
+
+impl Default for ConfiguracaoLimpeza {
+    fn default() -> Self {
+        Self {
+            intervalo_minutos: 15,
+        }
+    }
+}
+
+impl ConfiguracaoLimpeza {
+    pub fn intervalo(&self) -> Duration {
+        Duration::from_secs(self.intervalo_minutos.max(1) * 60)
+    }
+}
+
+/// A reutilização dos uidNumbers liberados pelas contas removidas, para que o
+/// contador não estoure as faixas reservadas.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoReutilizacaoUids {
+    /// Por quantos dias um uidNumber liberado fica sem uso antes de poder ser
+    /// dado a outra conta, para que os arquivos que sobraram do dono antigo
+    /// não passem para o novo.
+    pub quarentena_dias: i64,
+}
+
+impl Default for ConfiguracaoReutilizacaoUids {
+    fn default() -> Self {
+        Self {
+            quarentena_dias: 365,
+        }
+    }
+}
+
+/// Os atributos com o curso e o período de ingresso do aluno, para que os
+/// relatórios e as limpezas não precisem consultar o SIGA de novo.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoCamposAcademicos {
+    /// O atributo com o nome do curso.
+    pub atributo_curso: String,
+    /// O atributo com o período de ingresso, como `2025.1`, tirado do DRE.
+    pub atributo_ingresso: String,
+    /// O nome do curso dos alunos de cada OU. As contas de OUs fora da lista
+    /// ficam sem o curso.
+    pub cursos: BTreeMap<String, String>,
+}
+
+impl Default for ConfiguracaoCamposAcademicos {
+    fn default() -> Self {
+        Self {
+            atributo_curso: "dccCurso".to_string(),
+            atributo_ingresso: "dccIngresso".to_string(),
+            cursos: [
+                ("alunos", "Ciência da Computação"),
+                ("profcomp", "Ensino de Computação"),
+            ]
+            .map(|(ou, curso)| (ou.to_string(), curso.to_string()))
+            .into(),
+        }
+    }
+}
+
+/// A pós-graduação (o PPGI) para onde os alunos da graduação migram mantendo
+/// o uid.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoPos {
+    /// O nome do curso no SIGA, como aparece no documento.
+    pub curso: String,
+    /// A OU dos alunos da pós, ao lado da `ou=alunos`.
+    pub ou: String,
+    pub gid_number: String,
+    pub cota: String,
+}
+
+/// As contas temporárias de projetos de extensão e de visitantes, que não são
+/// de alunos e ficam sob a responsabilidade de um docente.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoProjetos {
+    /// A OU das contas, em `ou=usuarios`.
+    pub ou: String,
+    /// Os objectClasses das entradas.
+    pub classes: Vec<String>,
+    /// Onde ficam as contas dos docentes que podem ser responsáveis.
+    pub base_docentes: String,
+    /// Por quantos dias, no máximo, uma conta de projeto pode valer.
+    pub validade_maxima: i64,
+    /// Com quantos dias de antecedência o docente responsável é avisado de
+    /// que a conta vai expirar.
+    pub aviso_dias: i64,
+    /// O gid das contas. Se não for definido, é o mesmo dos alunos.
+    pub gid_number: Option<String>,
+    /// A cota das contas. Se não for definida, é a mesma dos alunos.
+    pub cota: Option<String>,
+}
+
+impl Default for ConfiguracaoProjetos {
+    fn default() -> Self {
+        Self {
+            ou: "projetos".to_string(),
+            classes: [
+                "dcc",
+                "dccProjeto",
+                "shadowAccount",
+                "posixAccount",
+                "inetOrgPerson",
+            ]
+            .map(String::from)
+            .into(),
+            base_docentes: "ou=professores,ou=usuarios,dc=dcc,dc=ufrj,dc=br"
+                .to_string(),
+            validade_maxima: 365,
+            aviso_dias: 15,
+            gid_number: None,
+            cota: None,
+        }
+    }
+}
+
+/// O socket Unix da API, para as operações feitas na própria máquina.
+/// Configurado como
+///
+/// ```yaml
+/// socket_admin:
+///   caminho: "/run/alumnic/admin.sock"
+///   modo: 0o600
+///   somente_socket: true
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoSocketAdmin {
+    /// Onde o socket é criado. Um socket que tenha sobrado de uma execução
+    /// anterior é removido.
+    pub caminho: PathBuf,
+    /// As permissões do arquivo do socket, que decidem quem pode usar as
+    /// rotas administrativas por ele.
+    #[serde(default = "modo_socket_padrao")]
+    pub modo: u32,
+    /// Se as rotas administrativas saem do TCP e só respondem pelo socket,
+    /// mesmo com o `api_token` definido.
+    #[serde(default)]
+    pub somente_socket: bool,
+}
+
+fn modo_socket_padrao() -> u32 {
+    0o600
+}
+
+/// A rota de consulta usada pelo helpdesk, que só lê as contas.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoHelpdesk {
+    /// Token exigido pela rota, separado do `api_token` para que o helpdesk
+    /// não tenha acesso às rotas administrativas.
+    pub token: SecretString,
+    /// Quantas consultas podem ser feitas por minuto.
+    #[serde(default = "limite_por_minuto_padrao")]
+    pub limite_por_minuto: u32,
+}
+
+fn limite_por_minuto_padrao() -> u32 {
+    60
+}
+
+/// Prazos das contas, em dias. Os mesmos prazos são gravados no cadastro e
+/// em cada renovação anual do vínculo.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoRenovacao {
+    /// Por quanto tempo a conta vale, a partir do cadastro ou da última
+    /// renovação.
+    #[serde(alias = "validade_dias")]
+    pub validade_conta: i64,
+    /// Por quanto tempo uma conta não renovada continua funcionando.
+    pub carencia_dias: i64,
+    /// Idade máxima do documento do SIGA usado para renovar.
+    pub idade_documento_dias: i64,
+    /// Por quanto tempo uma senha vale até precisar ser trocada
+    /// (`shadowMax` e `sambaPwdMustChange`).
+    pub validade_senha: i64,
+    /// Quanto tempo antes da senha vencer o usuário é avisado
+    /// (`shadowWarning`).
+    pub aviso_expiracao: i64,
+}
+
+impl Default for ConfiguracaoRenovacao {
+    fn default() -> Self {
+        Self {
+            validade_conta: 365,
+            carencia_dias: 60,
+            idade_documento_dias: 30,
+            validade_senha: 3600,
+            aviso_expiracao: 14,
+        }
+    }
+}
+
+/// Prazos do desligamento dos alunos formados ou jubilados, em dias.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoDesligamento {
+    /// Quanto tempo uma conta fica suspensa antes de ser removida, tanto no
+    /// desligamento quanto ao fim da carência.
+    pub dias_ate_remocao: i64,
+    /// A OU para onde as entradas dos ex-alunos são movidas.
+    pub ou_egressos: String,
+}
+
+impl Default for ConfiguracaoDesligamento {
+    fn default() -> Self {
+        Self {
+            dias_ate_remocao: 90,
+            ou_egressos: "ou=egressos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"
+                .to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum ConfiguracaoErro {
-    #[error("Não foi possível encontrar o diretório de configuração")]
-    ProjectDirs,
     #[error(transparent)]
     ErroNaConfig(#[from] ConfigError),
 }
 
+/// Os caminhos dados pelas flags ou pelas variáveis de ambiente, que valem
+/// mais que os da configuração. Os que faltam vêm da configuração ou dos
+/// padrões.
+#[derive(Debug, Clone, Default)]
+pub struct Caminhos {
+    /// O arquivo de configuração. Sem ele, é usado o
+    /// `~/.config/alumnic/config.yaml`, se existir, ou o [CONFIG_PADRAO].
+    pub config: Option<PathBuf>,
+    /// O diretório dos dados, no lugar do [`Configuracao::dados`].
+    pub dados: Option<PathBuf>,
+    /// O diretório dos registros, no lugar do [`Configuracao::logs`].
+    pub logs: Option<PathBuf>,
+}
+
+impl Caminhos {
+    /// O arquivo de configuração que será lido.
+    pub fn arquivo_de_config(&self) -> PathBuf {
+        if let Some(config) = &self.config {
+            return config.clone();
+        }
+        ProjectDirs::from("br", "ufrj.ic", "alumnic")
+            .map(|d| d.config_dir().join("config.yaml"))
+            .filter(|arquivo| arquivo.exists())
+            .unwrap_or_else(|| PathBuf::from(CONFIG_PADRAO))
+    }
+}
+
 impl Configuracao {
+    /// Lê a configuração do lugar padrão, como o [`importar_de`] sem
+    /// nenhum caminho dado.
+    ///
+    /// [`importar_de`]: Configuracao::importar_de
     pub fn importar() -> Result<Self, ConfiguracaoErro> {
-        let arquivo_de_config = ProjectDirs::from("br", "ufrj.ic", "alumnic")
-            .ok_or(ConfiguracaoErro::ProjectDirs)?
-            .config_dir()
-            .to_path_buf()
-            .join("config.yaml");
-
-        Ok(Config::builder()
-            .add_source(File::from(arquivo_de_config))
+        Self::importar_de(&Caminhos::default())
+    }
+
+    /// Lê a configuração com os `caminhos` dados, resolvendo os caminhos
+    /// relativos dela. As chaves, como a do backup e o certificado do LDAP,
+    /// ficam relativas ao diretório do arquivo de configuração.
+    pub fn importar_de(caminhos: &Caminhos) -> Result<Self, ConfiguracaoErro> {
+        let arquivo = caminhos.arquivo_de_config();
+        let mut cfg: Configuracao = Config::builder()
+            .add_source(File::from(arquivo.as_path()))
             .build()?
-            .try_deserialize()?)
+            .try_deserialize()?;
+
+        if let Some(dados) = &caminhos.dados {
+            cfg.dados = dados.clone();
+        }
+        if let Some(logs) = &caminhos.logs {
+            cfg.logs = logs.clone();
+        }
+        cfg.resolver_caminhos(arquivo.parent().unwrap_or(Path::new("")));
+        Ok(cfg)
+    }
+
+    /// Troca os caminhos relativos da configuração pelos caminhos dentro do
+    /// diretório de cada um: o dos dados, o dos registros ou o `config`, o
+    /// do arquivo de configuração. Os absolutos continuam como estão.
+    pub fn resolver_caminhos(&mut self, config: &Path) {
+        let dados = &self.dados;
+        let logs = &self.logs;
+        let usuario = &mut self.usuario_novo;
+        if let Some(c) = &mut usuario.contingencia {
+            c.diretorio = dados.join(&c.diretorio);
+        }
+        if let Some(d) = &mut usuario.duplicidade {
+            d.arquivo = dados.join(&d.arquivo);
+        }
+        if let Some(arquivo) = &mut self.auditoria.arquivo {
+            *arquivo = logs.join(&*arquivo);
+        }
+        if let Some(registro) = &mut self.hooks.registro {
+            *registro = logs.join(&*registro);
+        }
+        if let Some(certificado) = &mut self.ldap_certificado {
+            certificado.certificado = config.join(&certificado.certificado);
+            certificado.chave = config.join(&certificado.chave);
+            if let Some(ca) = &mut certificado.ca {
+                *ca = config.join(&*ca);
+            }
+        }
+        if let Some(ca) = &mut self.ldap_tls.ca {
+            *ca = config.join(&*ca);
+        }
+        if let Some(chave) =
+            self.backup.as_mut().and_then(|b| b.chave_privada.as_mut())
+        {
+            *chave = config.join(&*chave);
+        }
+    }
+}
+
+/// Quantos cadastros e renovações rodam ao mesmo tempo. A verificação do DRE
+/// e a alocação dos IDs de cada cadastro continuam sendo feitas um de cada
+/// vez.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoConcorrencia {
+    /// O limite da API; as requisições além dele esperam na fila.
+    pub api: usize,
+    /// O limite dos subcomandos em lote.
+    pub lote: usize,
+    /// O limite das consultas ao Gnosys, somando a API e os subcomandos; as
+    /// consultas além dele esperam na fila, na ordem de chegada.
+    pub gnosys: usize,
+    /// Por quantos segundos uma consulta espera uma vaga antes de desistir.
+    pub espera_gnosys: u64,
+}
+
+impl Default for ConfiguracaoConcorrencia {
+    fn default() -> Self {
+        Self {
+            api: 8,
+            lote: 4,
+            gnosys: 4,
+            espera_gnosys: 30,
+        }
+    }
+}
+
+impl ConfiguracaoConcorrencia {
+    /// Aplica o limite das consultas ao Gnosys a todo o processo.
+    pub fn limitar_gnosys(&self) {
+        crate::portal_ufrj::limitar_consultas(
+            self.gnosys,
+            Duration::from_secs(self.espera_gnosys),
+        );
+    }
+}
+
+/// Os fluxos do serviço que podem ser desligados em produção, como o
+/// cadastro fora do período de matrícula, configurados como
+///
+/// ```yaml
+/// features:
+///   cadastro_novo: false
+///   renovacao: true
+///   reset_senha: true
+///   api_admin: true
+/// ```
+///
+/// Todos ficam ligados por padrão.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Funcionalidades {
+    /// O cadastro de contas novas, pela API e pelo gRPC.
+    pub cadastro_novo: bool,
+    /// A renovação das contas, pela API e pelo gRPC.
+    pub renovacao: bool,
+    /// A redefinição de senhas pela rota administrativa.
+    pub reset_senha: bool,
+    /// As rotas `/api/admin`, inclusive pelo socket administrativo.
+    pub api_admin: bool,
+}
+
+impl Default for Funcionalidades {
+    fn default() -> Self {
+        Self {
+            cadastro_novo: true,
+            renovacao: true,
+            reset_senha: true,
+            api_admin: true,
+        }
     }
 }
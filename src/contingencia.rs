@@ -0,0 +1,272 @@
+//! Fila de contingência dos cadastros recebidos com o LDAP fora do ar, como
+//! numa queda no meio do dia de matrícula. O cadastro com o documento já
+//! autenticado no Gnosys é gravado em disco, o aluno é avisado de que a conta
+//! será criada em breve, e o `alumnic serve` tenta a fila de novo a cada
+//! intervalo, até o LDAP voltar.
+//!
+//! Cada cadastro fica num arquivo com o DRE, então um aluno que envia o
+//! formulário de novo durante a queda só troca o cadastro dele na fila. Os
+//! arquivos têm a senha em claro, que o principal Kerberos precisa, e por
+//! isso são gravados só para o dono, num diretório também só dele.
+use crate::cadastro_aluno::{
+    CadastroRealizado, DadosParaCadastro, ErroDeCadastro,
+};
+use crate::configuracao::Configuracao;
+use crate::ldap::conexao::FonteLdap;
+use crate::lotes::Lote;
+use crate::notificacao::{Mensagem, enviar};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// A fila de contingência, configurada em `usuario_novo` como
+///
+/// ```yaml
+/// contingencia:
+///   diretorio: "/var/lib/alumnic/contingencia"
+///   intervalo_segundos: 60
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoContingencia {
+    /// O diretório com os cadastros na fila, criado se não existir.
+    pub diretorio: PathBuf,
+    /// De quanto em quanto tempo a fila é tentada de novo.
+    #[serde(default = "intervalo_segundos_padrao")]
+    pub intervalo_segundos: u64,
+}
+
+fn intervalo_segundos_padrao() -> u64 {
+    60
+}
+
+/// Um cadastro na fila, esperando o LDAP voltar.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CadastroPendente {
+    /// Os dados do cadastro, já validados e normalizados.
+    pub dados: DadosParaCadastro,
+    /// A OU do curso, que o SIGA deu quando o documento foi autenticado.
+    pub ou: String,
+    pub recebido: DateTime<Utc>,
+}
+
+impl ConfiguracaoContingencia {
+    pub fn intervalo(&self) -> Duration {
+        Duration::from_secs(self.intervalo_segundos)
+    }
+
+    fn arquivo(&self, dre: &str) -> PathBuf {
+        self.diretorio.join(format!("{dre}.json"))
+    }
+
+    /// Grava o `pendente` na fila, no lugar do cadastro do mesmo DRE que
+    /// já estiver nela. O arquivo é gravado com outro nome e só depois
+    /// renomeado, para a fila nunca ter um cadastro pela metade.
+    pub fn enfileirar(&self, pendente: &CadastroPendente) -> io::Result<()> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.diretorio)?;
+
+        let arquivo = self.arquivo(&pendente.dados.dre);
+        let mut novo = arquivo.clone().into_os_string();
+        novo.push(".novo");
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&novo)?;
+        f.write_all(&serde_json::to_vec(pendente)?)?;
+        f.sync_all()?;
+        std::fs::rename(&novo, &arquivo)
+    }
+
+    /// Os cadastros na fila, do mais antigo para o mais novo. Um arquivo
+    /// que não pode ser lido fica de fora, com o erro no log, para não
+    /// travar os outros.
+    pub fn pendentes(&self) -> io::Result<Vec<CadastroPendente>> {
+        let entradas = match std::fs::read_dir(&self.diretorio) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new());
+            },
+            r => r?,
+        };
+
+        let mut pendentes = Vec::new();
+        for entrada in entradas {
+            let caminho = entrada?.path();
+            if caminho.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            match ler(&caminho) {
+                Ok(pendente) => pendentes.push(pendente),
+                Err(e) => eprintln!(
+                    "Não foi possível ler o cadastro {} da fila de \
+                     contingência: {e}",
+                    caminho.display(),
+                ),
+            }
+        }
+        pendentes.sort_by_key(|p| p.recebido);
+        Ok(pendentes)
+    }
+
+    /// Tira da fila o cadastro do `dre` que foi `recebido` naquele momento.
+    /// Um cadastro do mesmo DRE enviado de novo durante a tentativa fica na
+    /// fila para a próxima passada. Diz se o arquivo foi removido.
+    pub fn remover(
+        &self,
+        dre: &str,
+        recebido: DateTime<Utc>,
+    ) -> io::Result<bool> {
+        let arquivo = self.arquivo(dre);
+        match ler(&arquivo) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Ok(atual) if atual.recebido != recebido => {
+                return Ok(false);
+            },
+            _ => {},
+        }
+        std::fs::remove_file(arquivo)?;
+        Ok(true)
+    }
+}
+
+fn ler(caminho: &Path) -> io::Result<CadastroPendente> {
+    Ok(serde_json::from_slice(&std::fs::read(caminho)?)?)
+}
+
+/// O aviso ao aluno, no email `para`, de que o cadastro foi para a fila.
+pub fn aviso_enfileirado(para: &str) -> Mensagem {
+    Mensagem {
+        para: para.to_string(),
+        assunto: "Seu cadastro no IC foi recebido".to_string(),
+        corpo: "O seu cadastro foi recebido, mas a sua conta não pôde ser \
+                criada agora porque o sistema de contas do IC está fora do \
+                ar. Ela será criada automaticamente assim que ele voltar, e \
+                você receberá outro email com o seu login. Não é preciso \
+                enviar o formulário de novo."
+            .to_string(),
+    }
+}
+
+fn aviso_realizado(para: &str, cadastro: &CadastroRealizado) -> Mensagem {
+    Mensagem {
+        para: para.to_string(),
+        assunto: "Sua conta do IC foi criada".to_string(),
+        corpo: format!(
+            "A sua conta foi criada com o nome de usuário {:?}. Seu login é \
+             {}, e a senha dos laboratórios é a que você digitou no \
+             formulário.",
+            cadastro.username, cadastro.email,
+        ),
+    }
+}
+
+fn aviso_falha(para: &str, erro: &ErroDeCadastro) -> Mensagem {
+    Mensagem {
+        para: para.to_string(),
+        assunto: "Não foi possível criar sua conta do IC".to_string(),
+        corpo: format!(
+            "O seu cadastro, que esperava o sistema de contas do IC voltar, \
+             não pôde ser concluído: {erro}. Procure a supervisão."
+        ),
+    }
+}
+
+/// O resultado de uma passada pela fila.
+#[derive(Debug, Default)]
+pub struct Processamento {
+    pub realizados: Vec<CadastroRealizado>,
+    /// Os cadastros que falharam por outro motivo e saíram da fila, pelo
+    /// DRE.
+    pub falhas: Vec<(String, ErroDeCadastro)>,
+    /// Quantos cadastros continuam na fila, porque o LDAP continua fora do
+    /// ar ou o serviço está sendo encerrado.
+    pub pendentes: usize,
+}
+
+/// Tenta os cadastros da fila, do mais antigo para o mais novo, avisando
+/// cada aluno do resultado. Na primeira falha com o LDAP ainda fora do ar, a
+/// passada para e o resto espera a próxima. A passada é um
+/// [lote acompanhado](crate::lotes), que pode ser pausado.
+pub async fn processar_fila<F: FonteLdap>(
+    cfg: &Configuracao,
+    ldap: &F,
+    cancelamento: &CancellationToken,
+) -> io::Result<Processamento> {
+    let mut processamento = Processamento::default();
+    let Some(fila) = &cfg.usuario_novo.contingencia else {
+        return Ok(processamento);
+    };
+
+    let pendentes = fila.pendentes()?;
+    let total = pendentes.len();
+    if total == 0 {
+        return Ok(processamento);
+    }
+    let lote = Lote::acompanhar(
+        &cfg.dados,
+        "contingencia",
+        total,
+        cancelamento.clone(),
+    );
+    for (i, pendente) in pendentes.into_iter().enumerate() {
+        if !lote.comecar_item().await {
+            processamento.pendentes = total - i;
+            break;
+        }
+        let dre = pendente.dados.dre.clone();
+        let email = pendente.dados.email.clone();
+        let recebido = pendente.recebido;
+        let r = pendente
+            .dados
+            .cadastrar_pendente(
+                &pendente.ou,
+                &cfg.usuario_novo,
+                &cfg.renovacao,
+                ldap,
+                cancelamento,
+            )
+            .await;
+
+        let mensagem = match r {
+            Err(e)
+                if e.ldap_indisponivel()
+                    || matches!(e, ErroDeCadastro::Cancelado) =>
+            {
+                processamento.pendentes = total - i;
+                break;
+            },
+            Ok(cadastro) => {
+                lote.registrar(true);
+                let mensagem = aviso_realizado(&email, &cadastro);
+                processamento.realizados.push(cadastro);
+                mensagem
+            },
+            Err(e) => {
+                lote.registrar(false);
+                let mensagem = aviso_falha(&email, &e);
+                processamento.falhas.push((dre.clone(), e));
+                mensagem
+            },
+        };
+
+        if let Err(e) = fila.remover(&dre, recebido) {
+            eprintln!(
+                "Não foi possível tirar o cadastro de {dre:?} da fila de \
+                 contingência: {e}"
+            );
+        }
+        if let Err(e) = enviar(&cfg.notificacao, &mensagem).await {
+            eprintln!("Erro no aviso do cadastro de {dre:?}: {e}");
+        }
+    }
+
+    Ok(processamento)
+}
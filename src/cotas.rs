@@ -0,0 +1,294 @@
+//! Relatório do uso das cotas e ajuste das cotas em lote. A cota de cada
+//! conta fica no atributo `cota`, e o espaço usado vem do servidor de
+//! arquivos, por um comando ou por uma API, na mesma unidade da cota.
+use crate::auditoria::{ConfiguracaoAuditoria, Registro, registrar};
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::ou;
+use crate::ldap::cotas::{
+    CotaDaConta, buscar_cotas, membros_do_grupo, mudar_cota,
+};
+use chrono::{DateTime, Utc};
+use reqwest::header::AUTHORIZATION;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::process::ExitStatus;
+use thiserror::Error;
+use tokio::process::Command;
+
+/// De onde vem o espaço usado por cada conta, configurado como
+///
+/// ```yaml
+/// cotas:
+///   tipo: comando
+///   comando: ["ssh", "arquivos.ic.ufrj.br", "/usr/local/bin/uso-homes"]
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum ConfiguracaoCotas {
+    /// Roda o comando, que deve imprimir uma linha `uid uso` por conta,
+    /// separados por espaços ou por vírgula. As linhas vazias ou começadas
+    /// por `#` são ignoradas.
+    Comando { comando: Vec<String> },
+    /// Faz um GET na `url`, que deve responder um objeto JSON com o uso de
+    /// cada uid, como `{"joaops": 512}`. O `token`, se houver, vai no
+    /// cabeçalho `Authorization: Bearer`.
+    Api {
+        url: String,
+        #[serde(default)]
+        token: Option<SecretString>,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeCotas {
+    #[error("A consulta do uso das cotas não foi configurada")]
+    SemConfiguracao,
+    #[error("O comando do servidor de arquivos não foi configurado")]
+    SemComando,
+    #[error("Não foi possível rodar o comando: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("O comando falhou com {0}")]
+    Falha(ExitStatus),
+    #[error("A chamada ao servidor de arquivos falhou: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("A resposta do servidor de arquivos não é válida: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("A linha {0:?} do uso das cotas não é válida")]
+    LinhaInvalida(String),
+    #[error("Não existe o grupo {0:?}")]
+    GrupoInexistente(String),
+    #[error("Houve um problema ao consultar as cotas no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+}
+
+/// Lê a saída do comando do uso das cotas, com uma linha `uid uso` por
+/// conta.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::cotas::ler_uso;
+/// let uso = ler_uso("# uid uso\njoaops 512\nmariaas,1024\n\n").unwrap();
+/// assert_eq!(uso["joaops"], 512);
+/// assert_eq!(uso["mariaas"], 1024);
+///
+/// assert!(ler_uso("joaops muito").is_err());
+/// ```
+pub fn ler_uso(saida: &str) -> Result<BTreeMap<String, u64>, ErroDeCotas> {
+    saida
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|linha| {
+            let mut campos = linha
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|c| !c.is_empty());
+            match (campos.next(), campos.next().map(str::parse), campos.next())
+            {
+                (Some(uid), Some(Ok(uso)), None) => Ok((uid.to_string(), uso)),
+                _ => Err(ErroDeCotas::LinhaInvalida(linha.to_string())),
+            }
+        })
+        .collect()
+}
+
+impl ConfiguracaoCotas {
+    /// O espaço usado por cada uid no servidor de arquivos.
+    pub async fn consultar_uso(
+        &self,
+    ) -> Result<BTreeMap<String, u64>, ErroDeCotas> {
+        match self {
+            ConfiguracaoCotas::Comando { comando } => {
+                let (programa, argumentos) =
+                    comando.split_first().ok_or(ErroDeCotas::SemComando)?;
+
+                let saida =
+                    Command::new(programa).args(argumentos).output().await?;
+                if !saida.status.success() {
+                    return Err(ErroDeCotas::Falha(saida.status));
+                }
+                ler_uso(&String::from_utf8_lossy(&saida.stdout))
+            },
+            ConfiguracaoCotas::Api { url, token } => {
+                let mut req = reqwest::Client::new().get(url);
+                if let Some(token) = token {
+                    req = req.header(
+                        AUTHORIZATION,
+                        format!("Bearer {}", token.expose_secret()),
+                    );
+                }
+                let corpo =
+                    req.send().await?.error_for_status()?.text().await?;
+                Ok(serde_json::from_str(&corpo)?)
+            },
+        }
+    }
+}
+
+/// O uso da cota de uma conta.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UsoDaCota {
+    pub uid: String,
+    pub cota: u64,
+    pub uso: u64,
+}
+
+impl UsoDaCota {
+    /// O uso em porcentagem da cota. Uma cota zerada está sempre cheia.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::cotas::UsoDaCota;
+    /// let uso = UsoDaCota { uid: "joaops".into(), cota: 1000, uso: 1250 };
+    /// assert_eq!(uso.percentual(), 125);
+    /// ```
+    pub fn percentual(&self) -> u64 {
+        (self.uso * 100).checked_div(self.cota).unwrap_or(u64::MAX)
+    }
+}
+
+/// As contas que usam pelo menos `limiar` por cento da cota, da mais cheia
+/// para a menos cheia. As contas sem cota ou sem uso informado ficam de
+/// fora.
+pub async fn relatorio<F: FonteLdap>(
+    cfg: &ConfiguracaoCotas,
+    limiar: u64,
+    ldap: &F,
+) -> Result<Vec<UsoDaCota>, ErroDeCotas> {
+    let uso = cfg.consultar_uso().await?;
+
+    let mut conexao = ldap.abrir().await?;
+    let contas = buscar_cotas(&mut conexao).await;
+    ldap.fechar(conexao).await?;
+
+    let mut relatorio: Vec<_> = contas?
+        .into_iter()
+        .filter_map(|conta| {
+            Some(UsoDaCota {
+                cota: conta.cota?,
+                uso: *uso.get(&conta.uid)?,
+                uid: conta.uid,
+            })
+        })
+        .filter(|u| u.percentual() >= limiar)
+        .collect();
+
+    relatorio.sort_by(|a, b| {
+        b.percentual().cmp(&a.percentual()).then(a.uid.cmp(&b.uid))
+    });
+    Ok(relatorio)
+}
+
+/// O `relatorio` em CSV.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::cotas::{UsoDaCota, csv};
+/// let relatorio = [UsoDaCota { uid: "joaops".into(), cota: 1000, uso: 1250 }];
+/// assert_eq!(csv(&relatorio), "uid,cota,uso,percentual\njoaops,1000,1250,125\n");
+/// ```
+pub fn csv(relatorio: &[UsoDaCota]) -> String {
+    let mut csv = "uid,cota,uso,percentual\n".to_string();
+    for u in relatorio {
+        writeln!(csv, "{},{},{},{}", u.uid, u.cota, u.uso, u.percentual())
+            .unwrap();
+    }
+    csv
+}
+
+/// Quais contas têm a cota ajustada.
+#[derive(Debug, Clone)]
+pub enum Criterio {
+    /// Os membros do grupo com o `cn`, como `monitores`.
+    Grupo(String),
+    /// As contas da OU, como `profcomp`.
+    Ou(String),
+    /// As contas com os uids.
+    Uids(Vec<String>),
+}
+
+/// Busca as contas que satisfazem o `criterio`.
+async fn contas_do_criterio<F: FonteLdap>(
+    criterio: &Criterio,
+    ldap: &F,
+) -> Result<Vec<CotaDaConta>, ErroDeCotas> {
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        let mut contas = buscar_cotas(&mut conexao).await?;
+        match criterio {
+            Criterio::Grupo(cn) => {
+                let membros: BTreeSet<_> = membros_do_grupo(cn, &mut conexao)
+                    .await?
+                    .ok_or_else(|| ErroDeCotas::GrupoInexistente(cn.clone()))?
+                    .into_iter()
+                    .collect();
+                contas.retain(|c| membros.contains(&c.uid));
+            },
+            Criterio::Ou(o) => contas.retain(|c| ou(&c.dn) == *o),
+            Criterio::Uids(uids) => contas.retain(|c| uids.contains(&c.uid)),
+        }
+        Ok::<_, ErroDeCotas>(contas)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    r
+}
+
+/// Troca por `cota` a cota das contas que satisfazem o `criterio`,
+/// retornando as contas alteradas e o resultado de cada uma. As que já têm a
+/// cota ficam de fora. Com `simular`, só retorna as contas, sem alterá-las.
+///
+/// Cada alteração é registrada na auditoria com o `motivo`.
+pub async fn ajustar_em_lote<F: FonteLdap>(
+    criterio: &Criterio,
+    cota: u64,
+    motivo: &str,
+    simular: bool,
+    auditoria: &ConfiguracaoAuditoria,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<Vec<(CotaDaConta, Result<(), ErroDeCotas>)>, ErroDeCotas> {
+    let contas: Vec<_> = contas_do_criterio(criterio, ldap)
+        .await?
+        .into_iter()
+        .filter(|c| c.cota != Some(cota))
+        .collect();
+    if simular {
+        return Ok(contas.into_iter().map(|c| (c, Ok(()))).collect());
+    }
+
+    let mut resultados = vec![];
+    let mut conexao = ldap.abrir().await?;
+    for conta in contas {
+        let r = mudar_cota(&conta.dn, cota, &mut conexao).await;
+        resultados.push((conta, r.map_err(ErroDeCotas::from)));
+    }
+    ldap.fechar(conexao).await?;
+
+    for (conta, r) in &mut resultados {
+        if r.is_err() {
+            continue;
+        }
+        let anterior =
+            conta.cota.map_or("nenhuma".to_string(), |c| c.to_string());
+        let motivo = format!("{motivo} (cota de {anterior} para {cota})");
+        *r = registrar(
+            auditoria,
+            &Registro {
+                quando: agora,
+                operacao: "ajustar-cota",
+                uid: &conta.uid,
+                motivo: &motivo,
+            },
+        )
+        .await
+        .map_err(ErroDeCotas::from);
+    }
+
+    Ok(resultados)
+}
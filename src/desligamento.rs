@@ -0,0 +1,147 @@
+//! Módulo com o desligamento em massa dos alunos que deixaram o instituto,
+//! a partir das listas de formados e jubilados. As contas são bloqueadas e
+//! ficam suspensas até a data de remoção, e os alunos são avisados por email.
+use crate::configuracao::Configuracao;
+use crate::hooks::{ErroHook, Evento, TipoEvento, disparar_evento};
+use crate::ldap::ErroLdap;
+use crate::ldap::bloqueio::bloquear_conta;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{Conta, buscar_conta_por_dre};
+use crate::notificacao::{ErroNotificacao, Mensagem, enviar};
+use crate::renovacao::{dia, dia_para_data};
+use crate::utils::validacao_entradas::processar_dre;
+use chrono::{DateTime, NaiveDate, Utc};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Error)]
+pub enum ErroDeDesligamento {
+    #[error("O DRE {0:?} não é válido")]
+    DREInvalido(String),
+    #[error("Houve um problema ao bloquear a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("O lote foi interrompido antes deste DRE")]
+    Cancelado,
+}
+
+/// O que aconteceu com um DRE da lista.
+#[derive(Debug)]
+pub enum ResultadoDesligamento {
+    /// A conta foi bloqueada e será removida em `data_remocao`. O aviso ao
+    /// aluno e os hooks podem ter falhado sem que o bloqueio seja desfeito.
+    Desligado {
+        uid: String,
+        data_remocao: NaiveDate,
+        aviso: Result<(), ErroNotificacao>,
+        hooks: Result<(), ErroHook>,
+    },
+    /// A conta já estava bloqueada, e nada foi alterado.
+    JaSuspensa { uid: String },
+    /// Não há conta com o DRE no LDAP.
+    NaoEncontrado,
+}
+
+/// O aviso enviado ao aluno cuja `conta` foi bloqueada.
+fn aviso(conta: &Conta, email: &str, remocao: NaiveDate) -> Mensagem {
+    Mensagem {
+        para: email.to_string(),
+        assunto: "Sua conta do IC foi bloqueada".to_string(),
+        corpo: format!(
+            "Olá, {}.\n\n\
+             Como o seu vínculo com o Instituto de Computação terminou, a \
+             sua conta {} foi bloqueada e será removida em {}.\n\n\
+             Se você precisa de algum arquivo, ou se o bloqueio foi um \
+             engano, procure a supervisão do LCI antes dessa data.",
+            conta.nome,
+            conta.uid,
+            remocao.format("%d/%m/%Y"),
+        ),
+    }
+}
+
+/// Os valores da primeira coluna de cada linha da `lista`, um CSV. Linhas
+/// vazias, começadas por `#` e o `cabecalho` são ignorados.
+pub(crate) fn primeira_coluna<'a>(
+    lista: &'a str,
+    cabecalho: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    lista
+        .lines()
+        .map(str::trim)
+        .filter(|linha| !linha.is_empty() && !linha.starts_with('#'))
+        .map(|linha| linha.split(',').next().unwrap_or_default().trim())
+        .filter(move |valor| !valor.eq_ignore_ascii_case(cabecalho))
+}
+
+/// Desliga os alunos cujos DREs estão em `lista`, um por linha na primeira
+/// coluna de um CSV. Linhas vazias, começadas por `#` e o cabeçalho `dre`
+/// são ignorados. Depois de o `cancelamento` ser cancelado, os DREs que
+/// faltam voltam com [`ErroDeDesligamento::Cancelado`]. Retorna o resultado
+/// de cada DRE, na ordem da lista.
+pub async fn desligar_em_lote<F: FonteLdap>(
+    lista: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+    cancelamento: &CancellationToken,
+) -> Result<
+    Vec<(String, Result<ResultadoDesligamento, ErroDeDesligamento>)>,
+    ErroLdap,
+> {
+    let hoje = dia(agora);
+    let remocao = hoje + cfg.desligamento.dias_ate_remocao;
+
+    let mut conexao = ldap.abrir().await?;
+    let mut resultados = vec![];
+
+    for dre in primeira_coluna(lista, "dre") {
+        let r = async {
+            if cancelamento.is_cancelled() {
+                return Err(ErroDeDesligamento::Cancelado);
+            }
+            let dre = processar_dre(dre)
+                .ok_or_else(|| ErroDeDesligamento::DREInvalido(dre.into()))?;
+
+            let Some(conta) = buscar_conta_por_dre(&dre, &mut conexao).await?
+            else {
+                return Ok(ResultadoDesligamento::NaoEncontrado);
+            };
+            if conta.estado.bloqueada() {
+                return Ok(ResultadoDesligamento::JaSuspensa {
+                    uid: conta.uid,
+                });
+            }
+
+            bloquear_conta(&conta, hoje, remocao, &mut conexao).await?;
+
+            let data_remocao = dia_para_data(remocao);
+            let aviso = match &conta.email {
+                Some(email) => {
+                    let mensagem = aviso(&conta, email, data_remocao);
+                    enviar(&cfg.notificacao, &mensagem).await
+                },
+                None => Err(ErroNotificacao::SemEndereco),
+            };
+            let hooks = disparar_evento(
+                &cfg.hooks,
+                &cfg.auditoria,
+                &Evento::da_conta(TipoEvento::Suspensao, &conta),
+            )
+            .await;
+
+            Ok(ResultadoDesligamento::Desligado {
+                uid: conta.uid,
+                data_remocao,
+                aviso,
+                hooks,
+            })
+        }
+        .await;
+
+        resultados.push((dre.to_string(), r));
+    }
+
+    ldap.fechar(conexao).await?;
+
+    Ok(resultados)
+}
@@ -0,0 +1,418 @@
+//! Módulo com a criação das contas de docentes e técnicos pela supervisão.
+//! Não há documento do SIGA: um operador pede a conta, com o perfil que dá a
+//! OU, o gid e a validade, e ela só é criada quando outro operador aprova o
+//! pedido.
+//!
+//! Os pedidos ficam em `DADOS/docentes/ID.json` até a aprovação ou a recusa,
+//! com a hash SSHA da senha, e não a senha em claro. Os arquivos são gravados
+//! só para o dono, como os da [fila de contingência](crate::contingencia).
+use crate::auditoria::{Registro, registrar};
+use crate::cadastro_aluno::SECAO_CRITICA;
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::consulta::UidsExistentes;
+use crate::ldap::docente::cadastrar_docente_em;
+use crate::utils::hashes::hash_ssha;
+use crate::utils::nome::Nome;
+use crate::utils::validacao_entradas::{processar_email, validar_senha};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// As contas de docentes e técnicos, configuradas como
+///
+/// ```yaml
+/// docentes:
+///   perfis:
+///     professores:
+///       gid_number: "4000"
+///       cota: "10000"
+///     tecnicos:
+///       gid_number: "4100"
+///       validade_dias: 730
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoDocentes {
+    /// Os objectClasses das entradas.
+    #[serde(default = "classes_padrao")]
+    pub classes: Vec<String>,
+    /// Os perfis das contas, pela OU, em `ou=usuarios`, onde elas ficam.
+    pub perfis: BTreeMap<String, PerfilDocente>,
+}
+
+fn classes_padrao() -> Vec<String> {
+    ["dcc", "shadowAccount", "posixAccount", "inetOrgPerson"]
+        .map(String::from)
+        .into()
+}
+
+/// O gid e a validade das contas de uma OU de docentes ou técnicos.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PerfilDocente {
+    pub gid_number: String,
+    /// Por quantos dias a conta vale depois de criada. Se não for definida,
+    /// a conta não vence.
+    #[serde(default)]
+    pub validade_dias: Option<u64>,
+    /// A cota das contas. Se não for definida, é a mesma dos alunos.
+    #[serde(default)]
+    pub cota: Option<String>,
+}
+
+/// Os dados de uma conta de docente ou técnico.
+#[derive(Debug)]
+pub struct DadosDoDocente {
+    /// O nome completo, de onde sai o username.
+    pub nome: String,
+    /// O email de contato. Precisa ser um email válido.
+    pub email: String,
+    /// A OU da conta, um dos perfis configurados.
+    pub ou: String,
+    /// A senha, com as mesmas regras das contas de alunos.
+    pub senha: SecretString,
+}
+
+/// Um pedido de conta esperando a aprovação.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PedidoDeDocente {
+    pub id: String,
+    pub nome: String,
+    pub email: String,
+    pub ou: String,
+    /// Quem pediu a conta, que não pode aprová-la.
+    pub solicitante: String,
+    pub pedido_em: DateTime<Utc>,
+}
+
+/// O pedido como gravado no arquivo, com a senha.
+#[derive(Serialize, Deserialize)]
+struct PedidoGravado {
+    #[serde(flatten)]
+    pedido: PedidoDeDocente,
+    /// A hash SSHA da senha, pronta para o `userPassword`.
+    hash_senha: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeDocente {
+    #[error("A criação de contas de docentes não está configurada")]
+    Desativada,
+    #[error("O nome {0:?} não é válido")]
+    NomeInvalido(String),
+    #[error("O email {0:?} não é válido")]
+    EmailInvalido(String),
+    #[error("A OU {0:?} não é de nenhum perfil de docentes")]
+    OuDesconhecida(String),
+    #[error(
+        "A senha precisa ter entre 8 e 25 caracteres, uma letra minúscula, uma maiúscula e um dígito"
+    )]
+    SenhaInvalida,
+    #[error("Não existe o pedido {0:?}")]
+    PedidoInexistente(String),
+    #[error("O pedido {0:?} precisa ser aprovado por outro operador")]
+    MesmoOperador(String),
+    #[error("Houve um problema ao cadastrar a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("Houve um problema ao gravar o pedido ou a auditoria: {0}")]
+    ErroDeArquivo(#[from] io::Error),
+}
+
+impl ErroDeDocente {
+    /// O status HTTP com que o erro é retornado pela API.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErroDeDocente::Desativada
+            | ErroDeDocente::PedidoInexistente(..) => StatusCode::NOT_FOUND,
+            ErroDeDocente::NomeInvalido(..)
+            | ErroDeDocente::EmailInvalido(..)
+            | ErroDeDocente::OuDesconhecida(..)
+            | ErroDeDocente::SenhaInvalida => StatusCode::UNPROCESSABLE_ENTITY,
+            ErroDeDocente::MesmoOperador(..) => StatusCode::FORBIDDEN,
+            ErroDeDocente::ErroLdap(..) | ErroDeDocente::ErroDeArquivo(..) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            },
+        }
+    }
+}
+
+/// O diretório dos pedidos dentro do diretório dos `dados`.
+fn diretorio(dados: &Path) -> PathBuf {
+    dados.join("docentes")
+}
+
+fn arquivo(dados: &Path, id: &str) -> PathBuf {
+    diretorio(dados).join(format!("{id}.json"))
+}
+
+/// O arquivo do pedido `id` enquanto ele é aprovado, fora da listagem.
+fn arquivo_em_aprovacao(dados: &Path, id: &str) -> PathBuf {
+    diretorio(dados).join(format!("{id}.aprovando"))
+}
+
+/// Se o `id` pode ser o de um pedido, para que ele não saia do diretório
+/// dos pedidos.
+fn id_valido(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn ler(caminho: &Path) -> io::Result<PedidoGravado> {
+    Ok(serde_json::from_slice(&std::fs::read(caminho)?)?)
+}
+
+/// Grava o `gravado` com outro nome e só depois renomeia, para nunca haver
+/// um pedido pela metade.
+fn gravar(dados: &Path, gravado: &PedidoGravado) -> io::Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(diretorio(dados))?;
+
+    let arquivo = arquivo(dados, &gravado.pedido.id);
+    let mut novo = arquivo.clone().into_os_string();
+    novo.push(".novo");
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&novo)?;
+    f.write_all(&serde_json::to_vec(gravado)?)?;
+    f.sync_all()?;
+    std::fs::rename(&novo, &arquivo)
+}
+
+impl DadosDoDocente {
+    /// Valida os dados e grava o pedido da conta feito pelo `solicitante`,
+    /// que fica esperando a [aprovação](aprovar) de outro operador. O pedido
+    /// é guardado na auditoria.
+    pub async fn pedir(
+        mut self,
+        solicitante: &str,
+        cfg: &Configuracao,
+        agora: DateTime<Utc>,
+    ) -> Result<PedidoDeDocente, ErroDeDocente> {
+        let docentes =
+            cfg.docentes.as_ref().ok_or(ErroDeDocente::Desativada)?;
+
+        if self.nome.parse::<Nome>().is_err() {
+            return Err(ErroDeDocente::NomeInvalido(self.nome));
+        }
+        self.email = processar_email(&self.email)
+            .ok_or_else(move || ErroDeDocente::EmailInvalido(self.email))?;
+        self.ou = self.ou.trim().to_string();
+        if !docentes.perfis.contains_key(&self.ou) {
+            return Err(ErroDeDocente::OuDesconhecida(self.ou));
+        }
+        validar_senha(&self.senha)
+            .then_some(())
+            .ok_or(ErroDeDocente::SenhaInvalida)?;
+
+        let pedido = PedidoDeDocente {
+            id: format!(
+                "{}-{:04x}",
+                agora.timestamp_millis(),
+                rand::rng().random::<u16>(),
+            ),
+            nome: self.nome.trim().to_string(),
+            email: self.email,
+            ou: self.ou,
+            solicitante: solicitante.to_string(),
+            pedido_em: agora,
+        };
+        gravar(
+            &cfg.dados,
+            &PedidoGravado {
+                pedido: pedido.clone(),
+                hash_senha: hash_ssha(&self.senha).expose_secret().to_string(),
+            },
+        )?;
+
+        registrar(
+            &cfg.auditoria,
+            &Registro {
+                quando: agora,
+                operacao: "pedido_docente",
+                uid: "",
+                motivo: &format!(
+                    "pedido {} de {} em {}, por {solicitante}",
+                    pedido.id, pedido.nome, pedido.ou,
+                ),
+            },
+        )
+        .await?;
+
+        Ok(pedido)
+    }
+}
+
+/// Os pedidos esperando aprovação no diretório dos `dados`, do mais antigo
+/// para o mais novo. Um arquivo que não pode ser lido fica de fora, com o
+/// erro no log.
+pub fn pedidos(dados: &Path) -> io::Result<Vec<PedidoDeDocente>> {
+    let entradas = match std::fs::read_dir(diretorio(dados)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        r => r?,
+    };
+
+    let mut pedidos = vec![];
+    for entrada in entradas {
+        let caminho = entrada?.path();
+        if caminho.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        match ler(&caminho) {
+            Ok(gravado) => pedidos.push(gravado.pedido),
+            Err(e) => eprintln!(
+                "Não foi possível ler o pedido de docente {}: {e}",
+                caminho.display(),
+            ),
+        }
+    }
+    pedidos.sort_by_key(|p| p.pedido_em);
+    Ok(pedidos)
+}
+
+/// Aprova o pedido `id` e cria a conta, retornando o username e o DN. O
+/// `aprovador` precisa ser outro que não o solicitante. O pedido sai da
+/// lista antes da criação, para que duas aprovações ao mesmo tempo não criem
+/// duas contas, e volta para ela se a criação falhar.
+pub async fn aprovar<F: FonteLdap>(
+    id: &str,
+    aprovador: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<(String, String), ErroDeDocente> {
+    let docentes = cfg.docentes.as_ref().ok_or(ErroDeDocente::Desativada)?;
+    if !id_valido(id) {
+        return Err(ErroDeDocente::PedidoInexistente(id.to_string()));
+    }
+    let pendente = arquivo(&cfg.dados, id);
+    let gravado = match ler(&pendente) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ErroDeDocente::PedidoInexistente(id.to_string()));
+        },
+        r => r?,
+    };
+    let pedido = &gravado.pedido;
+    if pedido.solicitante == aprovador {
+        return Err(ErroDeDocente::MesmoOperador(id.to_string()));
+    }
+    let perfil = docentes
+        .perfis
+        .get(&pedido.ou)
+        .ok_or_else(|| ErroDeDocente::OuDesconhecida(pedido.ou.clone()))?;
+
+    let em_aprovacao = arquivo_em_aprovacao(&cfg.dados, id);
+    match std::fs::rename(&pendente, &em_aprovacao) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ErroDeDocente::PedidoInexistente(id.to_string()));
+        },
+        r => r?,
+    }
+
+    let r = async {
+        let mut conexao = ldap.abrir().await?;
+        let r = async {
+            let confundiveis = cfg.usuario_novo.usernames_confundiveis.as_ref();
+            let _secao = SECAO_CRITICA.lock().await;
+            let mut uids = match confundiveis {
+                Some(_) => UidsExistentes::carregar(&mut conexao).await?,
+                None => UidsExistentes::default(),
+            };
+            let username = uids
+                .achar_nome_livre(&pedido.nome, confundiveis, &mut conexao)
+                .await?;
+            let dn = cadastrar_docente_em(
+                &username,
+                pedido,
+                &gravado.hash_senha,
+                (&cfg.usuario_novo, docentes, perfil, &cfg.renovacao),
+                agora,
+                &mut conexao,
+            )
+            .await?;
+            Ok::<_, ErroLdap>((username, dn))
+        }
+        .await;
+        ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+    let (username, dn) = match r {
+        Ok(criada) => criada,
+        Err(e) => {
+            std::fs::rename(&em_aprovacao, &pendente)?;
+            return Err(e.into());
+        },
+    };
+    std::fs::remove_file(&em_aprovacao)?;
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "cadastro_docente",
+            uid: &username,
+            motivo: &format!(
+                "pedido {id} em {}, por {}, aprovado por {aprovador}",
+                pedido.ou, pedido.solicitante,
+            ),
+        },
+    )
+    .await?;
+
+    Ok((username, dn))
+}
+
+/// Recusa o pedido `id`, que sai da lista sem criar a conta, retornando-o.
+/// A recusa é guardada na auditoria, com o `operador` que recusou.
+pub async fn recusar(
+    id: &str,
+    operador: &str,
+    cfg: &Configuracao,
+    agora: DateTime<Utc>,
+) -> Result<PedidoDeDocente, ErroDeDocente> {
+    cfg.docentes.as_ref().ok_or(ErroDeDocente::Desativada)?;
+    if !id_valido(id) {
+        return Err(ErroDeDocente::PedidoInexistente(id.to_string()));
+    }
+    let pendente = arquivo(&cfg.dados, id);
+    let pedido = match ler(&pendente) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ErroDeDocente::PedidoInexistente(id.to_string()));
+        },
+        r => r?.pedido,
+    };
+    match std::fs::remove_file(&pendente) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(ErroDeDocente::PedidoInexistente(id.to_string()));
+        },
+        r => r?,
+    }
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "recusa_docente",
+            uid: "",
+            motivo: &format!(
+                "pedido {id} de {}, por {}, recusado por {operador}",
+                pedido.nome, pedido.solicitante,
+            ),
+        },
+    )
+    .await?;
+
+    Ok(pedido)
+}
@@ -0,0 +1,110 @@
+//! Leitura dos dados do documento "Regularmente Matriculado" a partir do PDF
+//! emitido pelo SIGA, para o aluno não precisar digitar a data, a hora e o
+//! código, que são os campos que mais falham na validação. Só existe com a
+//! feature `pdf`.
+use crate::cadastro_aluno::DadosParaCadastro;
+use crate::utils::validacao_entradas::{
+    processar_codigo, processar_data, processar_hora,
+};
+use axum::http::StatusCode;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDoPdf {
+    #[error("Não foi possível ler o PDF: {0}")]
+    Ilegivel(String),
+    #[error("O {0} não foi encontrado no documento")]
+    SemCampo(&'static str),
+}
+
+impl ErroDoPdf {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+}
+
+/// Os dados do documento usados no cadastro, já nos formatos do
+/// [DadosParaCadastro].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DadosDoDocumento {
+    pub dre: String,
+    pub data: String,
+    pub hora: String,
+    pub codigo: String,
+}
+
+impl DadosDoDocumento {
+    /// Lê os dados do PDF do documento, como enviado pelo aluno.
+    pub fn do_pdf(pdf: &[u8]) -> Result<Self, ErroDoPdf> {
+        let texto = pdf_extract::extract_text_from_mem(pdf)
+            .map_err(|e| ErroDoPdf::Ilegivel(e.to_string()))?;
+        Self::do_texto(&texto)
+    }
+
+    /// Procura os dados no texto do documento. O DRE é o que vem depois de
+    /// "DRE", ou o primeiro número de 9 dígitos; a data e a hora são as da
+    /// emissão, a primeira data seguida de uma hora, já que o documento
+    /// também pode ter outras datas, como a de nascimento.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::documento_pdf::DadosDoDocumento;
+    /// let texto = "Declaramos que CLÁUDIO DE LIMA CAVALCANTE, nascido em \
+    ///     02/05/2005, DRE: 123456789, está regularmente matriculado.\n\
+    ///     Documento emitido em 1/3/2025 às 9h05.\n\
+    ///     Código de autenticação: A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF";
+    /// let dados = DadosDoDocumento::do_texto(texto).unwrap();
+    ///
+    /// assert_eq!(dados.dre, "123456789");
+    /// assert_eq!(dados.data, "01/03/2025");
+    /// assert_eq!(dados.hora, "09:05");
+    /// assert_eq!(dados.codigo, "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF");
+    ///
+    /// assert!(DadosDoDocumento::do_texto("DRE: 123456789").is_err());
+    /// ```
+    pub fn do_texto(texto: &str) -> Result<Self, ErroDoPdf> {
+        let dre_marcado = Regex::new(r"(?i)\bDRE\D{0,5}(\d{9})\b").unwrap();
+        let dre_solto = Regex::new(r"\b(\d{9})\b").unwrap();
+        let emissao = Regex::new(
+            r"\b(\d{1,2}/\d{1,2}/\d{4})\D{1,12}?\b(\d{1,2})\s*[:hH]\s*(\d{2})\b",
+        )
+        .unwrap();
+        let codigo =
+            Regex::new(r"\b[0-9A-F]{4}(?:\s*\.\s*[0-9A-F]{4}){7}\b").unwrap();
+
+        let dre = dre_marcado
+            .captures(texto)
+            .or_else(|| dre_solto.captures(texto))
+            .map(|caps| caps[1].to_string())
+            .ok_or(ErroDoPdf::SemCampo("DRE"))?;
+        let emissao = emissao
+            .captures(texto)
+            .ok_or(ErroDoPdf::SemCampo("horário da emissão"))?;
+        let data = processar_data(&emissao[1])
+            .ok_or(ErroDoPdf::SemCampo("dia da emissão"))?;
+        let hora = processar_hora(&format!("{}:{}", &emissao[2], &emissao[3]))
+            .ok_or(ErroDoPdf::SemCampo("horário da emissão"))?;
+        let codigo = codigo
+            .find(texto)
+            .and_then(|m| processar_codigo(m.as_str()))
+            .ok_or(ErroDoPdf::SemCampo("código de autenticação"))?;
+
+        Ok(DadosDoDocumento {
+            dre,
+            data,
+            hora,
+            codigo,
+        })
+    }
+
+    /// Preenche os campos do documento nos `dados` do cadastro.
+    pub fn preencher(self, dados: &mut DadosParaCadastro) {
+        dados.dre = self.dre;
+        dados.data = self.data;
+        dados.hora = self.hora;
+        dados.codigo = self.codigo;
+    }
+}
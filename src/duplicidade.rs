@@ -0,0 +1,369 @@
+//! Verificação, logo depois de cada cadastro, de que o DRE não ficou com duas
+//! contas, como aconteceu no período 2025.2. A seção crítica só serializa os
+//! cadastros de um mesmo processo, então dois processos (ou uma conta criada
+//! à mão ao mesmo tempo) ainda podem repetir o DRE. Cada duplicidade achada
+//! vira um incidente, com as contas envolvidas e a latência do LDAP naquele
+//! momento, numa linha JSON do arquivo de incidentes; o `alumnic
+//! duplicidades` agrega o arquivo num relatório.
+use crate::auditoria::acrescentar_linha;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::metricas::registrar_duplicidade;
+use crate::utils::ingresso::periodo_do_dre;
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
+use ldap3::Scope;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A verificação de duplicidade, configurada em `usuario_novo` como
+///
+/// ```yaml
+/// duplicidade:
+///   arquivo: "/var/lib/alumnic/duplicidades.jsonl"
+///   corrigir: true
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoDuplicidade {
+    /// O arquivo onde os incidentes são acrescentados.
+    pub arquivo: PathBuf,
+    /// Se a conta recém-criada é apagada quando o DRE já tinha outra, com o
+    /// cadastro respondendo que ele já existe.
+    #[serde(default)]
+    pub corrigir: bool,
+}
+
+/// Uma conta com o DRE repetido.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContaDuplicada {
+    pub dn: String,
+    pub uid: String,
+    /// O dia da criação, em dias desde 1970, se a conta tiver a
+    /// `dataCriacao`.
+    pub data_criacao: Option<i64>,
+}
+
+/// Um DRE achado com mais de uma conta logo depois de um cadastro.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Incidente {
+    pub detectado: DateTime<Utc>,
+    pub dre: String,
+    /// O uid da conta criada pelo cadastro que achou a duplicidade.
+    pub uid: String,
+    /// Todas as contas com o DRE, inclusive a criada.
+    pub contas: Vec<ContaDuplicada>,
+    /// Quanto a criação da conta levou no LDAP, em milissegundos.
+    pub latencia_criacao_ms: u64,
+    /// Quanto a busca da verificação levou, em milissegundos.
+    pub latencia_verificacao_ms: u64,
+    /// Se a conta criada foi apagada pela correção.
+    pub corrigido: bool,
+}
+
+impl Incidente {
+    /// O uid da conta que fica com o DRE depois da correção: a mais antiga
+    /// entre as outras contas.
+    pub fn original(&self) -> Option<&str> {
+        self.contas
+            .iter()
+            .filter(|c| c.uid != self.uid)
+            .min_by_key(|c| c.data_criacao.unwrap_or(i64::MIN))
+            .map(|c| c.uid.as_str())
+    }
+}
+
+/// As contas com o `dre`.
+async fn contas_do_dre<D: DiretorioLdap>(
+    dre: &str,
+    ldap: &mut D,
+) -> Result<Vec<ContaDuplicada>, ErroLdap> {
+    let filtro = filtros::igual("dccDRE", dre);
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtro,
+            vec!["uid", "dataCriacao"],
+        )
+        .await?;
+
+    Ok(entradas
+        .into_iter()
+        .map(|e| {
+            let primeiro =
+                |atributo: &str| e.attrs.get(atributo).and_then(|v| v.first());
+            ContaDuplicada {
+                uid: primeiro("uid").cloned().unwrap_or_default(),
+                data_criacao: primeiro("dataCriacao")
+                    .and_then(|d| d.parse().ok()),
+                dn: e.dn,
+            }
+        })
+        .collect())
+}
+
+/// Confere se o `dre` da conta `uid`, recém-criada em `dn`, tem outras
+/// contas. Com a duplicidade, o incidente é registrado no arquivo e, com a
+/// correção configurada, a conta criada é apagada.
+pub async fn verificar<F: FonteLdap>(
+    cfg: &ConfiguracaoDuplicidade,
+    dre: &str,
+    (uid, dn): (&str, &str),
+    latencia_criacao: Duration,
+    fonte: &F,
+) -> Result<Option<Incidente>, ErroLdap> {
+    let mut ldap = fonte.abrir().await?;
+    let r = async {
+        let inicio = Instant::now();
+        let contas = contas_do_dre(dre, &mut ldap).await?;
+        let latencia_verificacao = inicio.elapsed();
+        if contas.len() < 2 {
+            return Ok(None);
+        }
+
+        let mut incidente = Incidente {
+            detectado: Utc::now(),
+            dre: dre.to_string(),
+            uid: uid.to_string(),
+            contas,
+            latencia_criacao_ms: latencia_criacao.as_millis() as u64,
+            latencia_verificacao_ms: latencia_verificacao.as_millis() as u64,
+            corrigido: false,
+        };
+        if cfg.corrigir && incidente.original().is_some() {
+            match ldap.remover(dn).await {
+                Ok(()) => incidente.corrigido = true,
+                Err(e) => eprintln!(
+                    "Não foi possível apagar a conta duplicada {uid:?}: {e}"
+                ),
+            }
+        }
+        Ok(Some(incidente))
+    }
+    .await;
+    fonte.fechar(ldap).await?;
+
+    if let Ok(Some(incidente)) = &r {
+        registrar_duplicidade(incidente.corrigido);
+        eprintln!(
+            "O DRE {dre:?} ficou com {} contas depois do cadastro de {uid:?}",
+            incidente.contas.len(),
+        );
+        if let Err(e) = acrescentar_linha(&cfg.arquivo, incidente).await {
+            eprintln!(
+                "Não foi possível registrar a duplicidade de {dre:?}: {e}"
+            );
+        }
+    }
+    r
+}
+
+/// Os incidentes do `arquivo`, na ordem em que foram registrados. Um arquivo
+/// que não existe não tem incidentes.
+pub fn ler(arquivo: &Path) -> io::Result<Vec<Incidente>> {
+    let conteudo = match std::fs::read_to_string(arquivo) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        },
+        r => r?,
+    };
+    conteudo
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).map_err(io::Error::from))
+        .collect()
+}
+
+/// O relatório dos incidentes, para procurar o padrão das duplicidades.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Relatorio {
+    pub total: usize,
+    pub corrigidos: usize,
+    /// Os incidentes de cada dia.
+    pub por_dia: BTreeMap<NaiveDate, usize>,
+    /// Os incidentes de cada hora do dia, de 0 a 23, em UTC.
+    pub por_hora: BTreeMap<u32, usize>,
+    /// Os incidentes de cada período de ingresso, pelo DRE, ou
+    /// `desconhecido`.
+    pub por_ingresso: BTreeMap<String, usize>,
+    /// Os DREs com mais de um incidente.
+    pub dres_repetidos: BTreeMap<String, usize>,
+    /// A latência média e a máxima da criação, em milissegundos.
+    pub latencia_criacao_media_ms: u64,
+    pub latencia_criacao_maxima_ms: u64,
+}
+
+impl Relatorio {
+    /// Agrega os `incidentes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::duplicidade::{Incidente, Relatorio};
+    /// let incidente = |dre: &str, latencia| Incidente {
+    ///     detectado: "2025-08-04T13:02:00Z".parse().unwrap(),
+    ///     dre: dre.to_string(),
+    ///     uid: "claudiolc".to_string(),
+    ///     contas: vec![],
+    ///     latencia_criacao_ms: latencia,
+    ///     latencia_verificacao_ms: 5,
+    ///     corrigido: false,
+    /// };
+    /// let relatorio = Relatorio::de(&[
+    ///     incidente("122134567", 100),
+    ///     incidente("122134567", 300),
+    ///     incidente("123456789", 800),
+    /// ]);
+    ///
+    /// assert_eq!(relatorio.total, 3);
+    /// assert_eq!(relatorio.por_hora[&13], 3);
+    /// assert_eq!(relatorio.por_ingresso["2022.1"], 2);
+    /// assert_eq!(relatorio.por_ingresso["desconhecido"], 1);
+    /// assert_eq!(relatorio.dres_repetidos["122134567"], 2);
+    /// assert!(!relatorio.dres_repetidos.contains_key("123456789"));
+    /// assert_eq!(relatorio.latencia_criacao_media_ms, 400);
+    /// assert_eq!(relatorio.latencia_criacao_maxima_ms, 800);
+    /// ```
+    pub fn de(incidentes: &[Incidente]) -> Self {
+        let mut relatorio = Relatorio {
+            total: incidentes.len(),
+            ..Default::default()
+        };
+        let mut soma_latencias = 0;
+        for i in incidentes {
+            relatorio.corrigidos += usize::from(i.corrigido);
+            *relatorio
+                .por_dia
+                .entry(i.detectado.date_naive())
+                .or_default() += 1;
+            *relatorio.por_hora.entry(i.detectado.hour()).or_default() += 1;
+            let ingresso = periodo_do_dre(&i.dre)
+                .map_or_else(|| "desconhecido".to_string(), |p| p.to_string());
+            *relatorio.por_ingresso.entry(ingresso).or_default() += 1;
+            *relatorio.dres_repetidos.entry(i.dre.clone()).or_default() += 1;
+            soma_latencias += i.latencia_criacao_ms;
+            relatorio.latencia_criacao_maxima_ms = relatorio
+                .latencia_criacao_maxima_ms
+                .max(i.latencia_criacao_ms);
+        }
+        relatorio.dres_repetidos.retain(|_, n| *n > 1);
+        if !incidentes.is_empty() {
+            relatorio.latencia_criacao_media_ms =
+                soma_latencias / incidentes.len() as u64;
+        }
+        relatorio
+    }
+}
+
+/// Os `incidentes` em CSV, com uma linha por incidente e os uids separados
+/// por espaços.
+pub fn csv(incidentes: &[Incidente]) -> String {
+    let mut csv = "detectado,dre,uid,uids,latencia_criacao_ms,\
+                   latencia_verificacao_ms,corrigido\n"
+        .to_string();
+    for i in incidentes {
+        let uids: Vec<&str> = i.contas.iter().map(|c| c.uid.as_str()).collect();
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{}",
+            i.detectado.to_rfc3339(),
+            i.dre,
+            i.uid,
+            uids.join(" "),
+            i.latencia_criacao_ms,
+            i.latencia_verificacao_ms,
+            i.corrigido,
+        )
+        .unwrap();
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    const BASE: &str =
+        "ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+    async fn diretorio() -> Arc<Mutex<DiretorioMemoria>> {
+        let mut ldap = DiretorioMemoria::default();
+        for (uid, data) in [("claudioc", "20300"), ("claudiolc", "20310")] {
+            ldap.adicionar(
+                &format!("uid={uid},{BASE}"),
+                vec![
+                    ("uid", [uid].into()),
+                    ("dccDRE", ["122134567"].into()),
+                    ("dataCriacao", [data].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+        Arc::new(Mutex::new(ldap))
+    }
+
+    #[tokio::test]
+    async fn registra_e_corrige_a_duplicidade() {
+        let ldap = diretorio().await;
+        let arquivo = std::env::temp_dir().join(format!(
+            "alumnic-duplicidades-{}.jsonl",
+            rand::random::<u32>()
+        ));
+        let cfg = ConfiguracaoDuplicidade {
+            arquivo: arquivo.clone(),
+            corrigir: true,
+        };
+
+        let dn = format!("uid=claudiolc,{BASE}");
+        let incidente = verificar(
+            &cfg,
+            "122134567",
+            ("claudiolc", &dn),
+            Duration::from_millis(250),
+            &ldap,
+        )
+        .await
+        .unwrap()
+        .expect("a duplicidade não foi achada");
+
+        assert!(incidente.corrigido);
+        assert_eq!(incidente.contas.len(), 2);
+        assert_eq!(incidente.original(), Some("claudioc"));
+        assert_eq!(incidente.latencia_criacao_ms, 250);
+        assert!(ldap.lock().await.entrada(&dn).is_none());
+        assert_eq!(ler(&arquivo).unwrap(), [incidente]);
+        std::fs::remove_file(arquivo).unwrap();
+    }
+
+    #[tokio::test]
+    async fn dre_com_uma_conta_nao_e_incidente() {
+        let ldap = diretorio().await;
+        let cfg = ConfiguracaoDuplicidade {
+            arquivo: PathBuf::from("/nao/existe"),
+            corrigir: true,
+        };
+
+        let r = verificar(
+            &cfg,
+            "123456789",
+            ("joaops", "uid=joaops"),
+            Duration::ZERO,
+            &ldap,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(r, None);
+        assert_eq!(ldap.lock().await.entradas().count(), 2);
+    }
+}
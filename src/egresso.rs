@@ -0,0 +1,70 @@
+//! Módulo com a transição de aluno para ex-aluno. Em vez de apagar a entrada
+//! e o histórico do aluno, ela é movida para a OU de egressos sem o acesso aos
+//! laboratórios.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{EstadoConta, buscar_conta_por_uid};
+use crate::ldap::egresso::tornar_egresso;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDeEgresso {
+    #[error("É preciso informar o motivo da mudança")]
+    SemMotivo,
+    #[error("Não existe conta com o uid {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta {0} já é de um egresso")]
+    JaEgresso(String),
+    #[error("Houve um problema ao mover a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A conta foi movida, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+/// Transforma a conta com o `uid` em uma conta de ex-aluno, retornando o seu
+/// novo DN. O `motivo` é guardado na auditoria.
+pub async fn tornar_egresso_em<F: FonteLdap>(
+    uid: &str,
+    motivo: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<String, ErroDeEgresso> {
+    if motivo.trim().is_empty() {
+        Err(ErroDeEgresso::SemMotivo)?
+    }
+
+    let mut conexao = ldap.abrir().await?;
+    let r: Result<_, ErroDeEgresso> = async {
+        let conta = buscar_conta_por_uid(uid, &mut conexao)
+            .await?
+            .ok_or_else(|| ErroDeEgresso::ContaInexistente(uid.into()))?;
+        if conta.estado == EstadoConta::Egresso {
+            return Err(ErroDeEgresso::JaEgresso(conta.uid));
+        }
+
+        let dn =
+            tornar_egresso(&conta, &cfg.desligamento.ou_egressos, &mut conexao)
+                .await?;
+        Ok(dn)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    let dn = r?;
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "egresso",
+            uid,
+            motivo: motivo.trim(),
+        },
+    )
+    .await?;
+
+    Ok(dn)
+}
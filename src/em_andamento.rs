@@ -0,0 +1,109 @@
+//! Coalescimento das requisições repetidas enquanto a primeira ainda está em
+//! andamento, como o mesmo cadastro enviado duas vezes por um duplo clique no
+//! formulário. A repetida espera o resultado da primeira e responde com ele,
+//! em vez de consultar o Gnosys e o LDAP de novo.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// As operações em andamento, pela chave que identifica as repetidas, como o
+/// DRE de um cadastro.
+pub struct EmAndamento<T> {
+    operacoes: Mutex<HashMap<String, watch::Receiver<Option<T>>>>,
+}
+
+impl<T> Default for EmAndamento<T> {
+    fn default() -> Self {
+        Self {
+            operacoes: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// A vez de uma requisição em [`EmAndamento::entrar`].
+pub enum Vez<'a, T> {
+    /// Nenhuma outra está em andamento: esta faz a operação e entrega o
+    /// resultado pela [`Conclusao`].
+    Primeira(Conclusao<'a, T>),
+    /// Outra já está em andamento, e o resultado dela chega por aqui.
+    Repetida(watch::Receiver<Option<T>>),
+}
+
+/// Entrega o resultado da operação às repetidas. A operação sai do
+/// andamento quando a conclusão é destruída, com resultado ou não.
+pub struct Conclusao<'a, T> {
+    em_andamento: &'a EmAndamento<T>,
+    chave: String,
+    resultado: watch::Sender<Option<T>>,
+}
+
+impl<T> EmAndamento<T> {
+    /// Entra na operação com a `chave`, que é a primeira se nenhuma outra
+    /// com a mesma chave estiver em andamento.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use alumnic::em_andamento::{EmAndamento, Vez, esperar};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let em_andamento = EmAndamento::default();
+    ///
+    /// let Vez::Primeira(conclusao) = em_andamento.entrar("123456789") else {
+    ///     unreachable!()
+    /// };
+    /// let Vez::Repetida(repetida) = em_andamento.entrar("123456789") else {
+    ///     unreachable!()
+    /// };
+    /// conclusao.concluir("cadastrado");
+    ///
+    /// assert_eq!(esperar(repetida).await, Some("cadastrado"));
+    /// assert!(matches!(em_andamento.entrar("123456789"), Vez::Primeira(_)));
+    /// # }
+    /// ```
+    pub fn entrar(&self, chave: &str) -> Vez<'_, T> {
+        let mut operacoes = self.operacoes.lock().unwrap();
+        if let Some(resultado) = operacoes.get(chave) {
+            return Vez::Repetida(resultado.clone());
+        }
+
+        let (resultado, receptor) = watch::channel(None);
+        operacoes.insert(chave.to_string(), receptor);
+        Vez::Primeira(Conclusao {
+            em_andamento: self,
+            chave: chave.to_string(),
+            resultado,
+        })
+    }
+}
+
+impl<T> Conclusao<'_, T> {
+    /// Entrega o `resultado` às requisições repetidas.
+    pub fn concluir(self, resultado: T) {
+        self.resultado.send_replace(Some(resultado));
+    }
+}
+
+impl<T> Drop for Conclusao<'_, T> {
+    fn drop(&mut self) {
+        self.em_andamento
+            .operacoes
+            .lock()
+            .unwrap()
+            .remove(&self.chave);
+    }
+}
+
+/// Espera o resultado da requisição [primeira](Vez::Primeira). Retorna
+/// `None` se ela terminou sem resultado, como quando a conexão dela caiu no
+/// meio.
+pub async fn esperar<T: Clone>(
+    mut receptor: watch::Receiver<Option<T>>,
+) -> Option<T> {
+    receptor
+        .wait_for(Option::is_some)
+        .await
+        .ok()
+        .and_then(|resultado| resultado.clone())
+}
@@ -0,0 +1,140 @@
+//! Espelhamento das contas no Active Directory dos laboratórios Windows. A
+//! sincronização é só do LDAP para o AD: as contas criadas, bloqueadas e
+//! removidas aqui são criadas, desabilitadas e apagadas lá, e o AD nunca é
+//! lido como fonte de nada além das divergências.
+use crate::configuracao::ldap_timeout_padrao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::{
+    ConfiguracaoTls, FonteLdap, Replicas, ServidorLdap,
+};
+use crate::ldap::espelho::{Divergencia, comparar, corrigir};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use thiserror::Error;
+
+/// O AD espelhado, configurado como
+///
+/// ```yaml
+/// url: "ldaps://ad.lab.ic.ufrj.br"
+/// bind_dn: "CN=alumnic,OU=Servicos,DC=lab,DC=ic,DC=ufrj,DC=br"
+/// bind_pw: "..."
+/// base: "OU=Alunos,DC=lab,DC=ic,DC=ufrj,DC=br"
+/// atributos:
+///   gecos: displayName
+///   mail: mail
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoAd {
+    /// O endereço do AD, que precisa ser `ldaps://`.
+    pub url: String,
+    pub bind_dn: String,
+    pub bind_pw: String,
+    /// A OU onde ficam as contas espelhadas. Toda conta de usuário abaixo
+    /// dela que não existir no LDAP é apagada, então ela deve ser usada só
+    /// pelo alumnic.
+    pub base: String,
+    /// Os atributos copiados, do nome no LDAP para o nome no AD. O uid é
+    /// sempre copiado para o `cn` e o `sAMAccountName`.
+    #[serde(default = "atributos_padrao")]
+    pub atributos: BTreeMap<String, String>,
+    /// O tempo máximo, em segundos, da conexão e de cada operação no AD.
+    #[serde(default = "ldap_timeout_padrao")]
+    pub timeout_segundos: u64,
+}
+
+fn atributos_padrao() -> BTreeMap<String, String> {
+    [
+        ("cn", "givenName"),
+        ("sn", "sn"),
+        ("gecos", "displayName"),
+        ("mail", "mail"),
+    ]
+    .into_iter()
+    .map(|(ldap, ad)| (ldap.to_string(), ad.to_string()))
+    .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum ErroEspelho {
+    #[error("o AD precisa ser acessado por ldaps://, não por {0}")]
+    SemTls(String),
+    #[error(transparent)]
+    ErroLdap(#[from] ErroLdap),
+}
+
+impl ConfiguracaoAd {
+    /// O servidor do AD, recusando as conexões sem TLS.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::espelho_ad::ConfiguracaoAd;
+    /// let mut cfg = ConfiguracaoAd {
+    ///     url: "ldap://ad.invalido".to_string(),
+    ///     bind_dn: String::new(),
+    ///     bind_pw: String::new(),
+    ///     base: "OU=Alunos,DC=lab".to_string(),
+    ///     atributos: Default::default(),
+    ///     timeout_segundos: 30,
+    /// };
+    /// assert!(cfg.servidor().is_err());
+    ///
+    /// cfg.url = "LDAPS://ad.invalido".to_string();
+    /// assert!(cfg.servidor().is_ok());
+    /// ```
+    pub fn servidor(&self) -> Result<ServidorLdap, ErroEspelho> {
+        if !self.url.to_lowercase().starts_with("ldaps://") {
+            return Err(ErroEspelho::SemTls(self.url.clone()));
+        }
+
+        Ok(ServidorLdap {
+            url: self.url.clone(),
+            bind_dn: self.bind_dn.clone(),
+            bind_pw: self.bind_pw.clone(),
+            timeout: Duration::from_secs(self.timeout_segundos),
+            certificado: None,
+            tls: ConfiguracaoTls::default(),
+            replicas: Replicas::default(),
+        })
+    }
+}
+
+/// Compara as contas do `ldap` com as do `ad`, retornando as divergências.
+pub async fn divergencias<F: FonteLdap, G: FonteLdap>(
+    cfg: &ConfiguracaoAd,
+    ldap: &F,
+    ad: &G,
+) -> Result<Vec<Divergencia>, ErroLdap> {
+    let mut conexao = ldap.abrir().await?;
+    let mut conexao_ad = match ad.abrir().await {
+        Ok(c) => c,
+        Err(e) => {
+            ldap.fechar(conexao).await?;
+            return Err(e);
+        },
+    };
+
+    let r = comparar(cfg, &mut conexao, &mut conexao_ad).await;
+    ad.fechar(conexao_ad).await?;
+    ldap.fechar(conexao).await?;
+    r
+}
+
+/// Corrige as `divergencias` no `ad`, retornando o resultado de cada uma.
+/// Uma correção que falha não impede as outras.
+pub async fn espelhar<G: FonteLdap>(
+    cfg: &ConfiguracaoAd,
+    divergencias: &[Divergencia],
+    ad: &G,
+) -> Result<Vec<Result<(), ErroLdap>>, ErroLdap> {
+    let mut conexao = ad.abrir().await?;
+
+    let mut resultados = vec![];
+    for divergencia in divergencias {
+        resultados.push(corrigir(cfg, divergencia, &mut conexao).await);
+    }
+
+    ad.fechar(conexao).await?;
+    Ok(resultados)
+}
@@ -0,0 +1,173 @@
+//! Estatísticas do período de cadastro, usadas no relatório da supervisão.
+//! Os números ficam somente em memória e são zerados quando o serviço é
+//! reiniciado.
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+/// Quantas falhas recentes são guardadas.
+const MAXIMO_FALHAS: usize = 50;
+
+/// Uma operação da API que falhou, mostrada no
+/// [painel administrativo](crate::painel).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Falha {
+    pub quando: DateTime<Local>,
+    /// A operação, como `cadastro` ou `hook de renovação`.
+    pub operacao: String,
+    /// A conta ou o DRE envolvido, se houver.
+    pub conta: Option<String>,
+    pub mensagem: String,
+    /// A [etapa](crate::cadastro_aluno::EtapaCadastro) em que o cadastro
+    /// parou, nas falhas de cadastro.
+    #[serde(default)]
+    pub etapa: Option<String>,
+}
+
+/// Contadores acumulados pela API desde que o serviço foi iniciado.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Estatisticas {
+    /// Quantidade de cadastros bem-sucedidos em cada dia.
+    pub cadastros_por_dia: BTreeMap<NaiveDate, u64>,
+    /// Quantidade de cadastros bem-sucedidos por curso (OU do LDAP).
+    pub cadastros_por_curso: BTreeMap<String, u64>,
+    /// Quantidade de cadastros por [origem](crate::origens), como a rede
+    /// dos laboratórios ou o eduroam.
+    #[serde(default)]
+    pub cadastros_por_origem: BTreeMap<String, u64>,
+    /// Quantidade de erros por tipo, ou seja, pela variante do
+    /// [`ErroDeCadastro`](crate::cadastro_aluno::ErroDeCadastro).
+    pub erros_por_tipo: BTreeMap<String, u64>,
+    /// Quantidade de erros por
+    /// [etapa do cadastro](crate::cadastro_aluno::EtapaCadastro).
+    #[serde(default)]
+    pub erros_por_etapa: BTreeMap<String, u64>,
+    /// As últimas falhas, da mais recente para a mais antiga.
+    pub ultimas_falhas: VecDeque<Falha>,
+}
+
+impl Estatisticas {
+    /// Registra um cadastro bem-sucedido.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::estatisticas::Estatisticas;
+    /// # use chrono::NaiveDate;
+    /// let mut e = Estatisticas::default();
+    /// let dia = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+    ///
+    /// e.registrar_cadastro(dia, "alunos");
+    /// e.registrar_cadastro(dia, "profcomp");
+    ///
+    /// assert_eq!(e.cadastros_por_dia[&dia], 2);
+    /// assert_eq!(e.cadastros_por_curso["alunos"], 1);
+    /// ```
+    pub fn registrar_cadastro(&mut self, dia: NaiveDate, ou: &str) {
+        *self.cadastros_por_dia.entry(dia).or_default() += 1;
+        *self.cadastros_por_curso.entry(ou.to_string()).or_default() += 1;
+    }
+
+    /// Registra a [origem](crate::origens) de um cadastro bem-sucedido.
+    pub fn registrar_origem(&mut self, origem: &str) {
+        *self
+            .cadastros_por_origem
+            .entry(origem.to_string())
+            .or_default() += 1;
+    }
+
+    /// Registra um erro do tipo `tipo`, que aconteceu na `etapa`.
+    pub fn registrar_erro(&mut self, tipo: &str, etapa: &str) {
+        *self.erros_por_tipo.entry(tipo.to_string()).or_default() += 1;
+        *self.erros_por_etapa.entry(etapa.to_string()).or_default() += 1;
+    }
+
+    /// Guarda a `falha` entre as últimas, esquecendo as mais antigas.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::estatisticas::{Estatisticas, Falha};
+    /// # use chrono::Local;
+    /// let mut e = Estatisticas::default();
+    /// for i in 0..60 {
+    ///     e.registrar_falha(Falha {
+    ///         quando: Local::now(),
+    ///         operacao: "cadastro".to_string(),
+    ///         conta: None,
+    ///         mensagem: format!("falha {i}"),
+    ///         etapa: None,
+    ///     });
+    /// }
+    ///
+    /// assert_eq!(e.ultimas_falhas.len(), 50);
+    /// assert_eq!(e.ultimas_falhas[0].mensagem, "falha 59");
+    /// ```
+    pub fn registrar_falha(&mut self, falha: Falha) {
+        self.ultimas_falhas.push_front(falha);
+        self.ultimas_falhas.truncate(MAXIMO_FALHAS);
+    }
+
+    /// Gera uma página HTML simples com as tabelas de estatísticas.
+    pub fn html(&self) -> String {
+        fn tabela<K: ToString>(
+            html: &mut String,
+            titulo: &str,
+            linhas: impl Iterator<Item = (K, u64)>,
+        ) {
+            let _ = write!(html, "<h2>{titulo}</h2><table>");
+            for (chave, valor) in linhas {
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td>{valor}</td></tr>",
+                    escapar_html(&chave.to_string()),
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        let mut html = String::from(concat!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">",
+            "<title>alumnic - estatísticas</title></head><body>",
+            "<h1>Estatísticas do período</h1>",
+        ));
+
+        tabela(
+            &mut html,
+            "Cadastros por dia",
+            self.cadastros_por_dia.iter().map(|(k, v)| (k, *v)),
+        );
+        tabela(
+            &mut html,
+            "Cadastros por curso",
+            self.cadastros_por_curso.iter().map(|(k, v)| (k, *v)),
+        );
+        tabela(
+            &mut html,
+            "Cadastros por origem",
+            self.cadastros_por_origem.iter().map(|(k, v)| (k, *v)),
+        );
+        tabela(
+            &mut html,
+            "Erros por tipo",
+            self.erros_por_tipo.iter().map(|(k, v)| (k, *v)),
+        );
+        tabela(
+            &mut html,
+            "Erros por etapa",
+            self.erros_por_etapa.iter().map(|(k, v)| (k, *v)),
+        );
+        html.push_str("</body></html>");
+
+        html
+    }
+}
+
+pub(crate) fn escapar_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
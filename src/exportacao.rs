@@ -0,0 +1,115 @@
+//! Exportação dos alunos novos para a secretaria acadêmica, que pede no
+//! início de cada período a lista das contas criadas desde uma data.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::renovacao::dia_para_data;
+use chrono::{DateTime, NaiveDate};
+use ldap3::{Scope, SearchEntry};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Atributos lidos de cada conta.
+const ATRIBUTOS: [&str; 5] = ["uid", "gecos", "dccDRE", "mail", "dataCriacao"];
+
+/// Uma conta criada desde a data pedida.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AlunoNovo {
+    pub uid: String,
+    /// O nome completo, sem acentos.
+    pub nome: String,
+    pub dre: String,
+    /// O email institucional, do atributo `mail`.
+    pub email: String,
+    pub criacao: NaiveDate,
+}
+
+impl AlunoNovo {
+    fn da_entrada(mut e: SearchEntry) -> Option<Self> {
+        let mut primeiro = |atributo: &str| {
+            e.attrs.remove(atributo).and_then(|v| v.into_iter().next())
+        };
+
+        Some(AlunoNovo {
+            uid: primeiro("uid")?,
+            nome: primeiro("gecos").unwrap_or_default(),
+            dre: primeiro("dccDRE").unwrap_or_default(),
+            email: primeiro("mail").unwrap_or_default(),
+            criacao: dia_para_data(primeiro("dataCriacao")?.parse().ok()?),
+        })
+    }
+}
+
+/// Busca as contas de alunos criadas a partir de `desde`, inclusive, em
+/// ordem de criação. As contas antigas, sem a `dataCriacao`, nunca entram.
+pub async fn buscar_novos<D: DiretorioLdap>(
+    desde: NaiveDate,
+    ldap: &mut D,
+) -> Result<Vec<AlunoNovo>, ErroLdap> {
+    let dia = (desde - DateTime::UNIX_EPOCH.date_naive()).num_days();
+
+    let mut novos: Vec<_> = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtros::e([
+                filtros::igual("objectClass", "dccAluno"),
+                filtros::maior_ou_igual("dataCriacao", &dia.to_string()),
+            ]),
+            ATRIBUTOS.to_vec(),
+        )
+        .await?
+        .into_iter()
+        .filter_map(AlunoNovo::da_entrada)
+        .collect();
+
+    novos.sort_by(|a, b| (a.criacao, &a.uid).cmp(&(b.criacao, &b.uid)));
+    Ok(novos)
+}
+
+/// Um campo do CSV, entre aspas se tiver vírgula, aspas ou quebra de linha.
+fn campo_csv(campo: &str) -> String {
+    if campo.contains([',', '"', '\n']) {
+        format!("\"{}\"", campo.replace('"', "\"\""))
+    } else {
+        campo.to_string()
+    }
+}
+
+/// Os `novos` alunos em CSV, no layout pedido pela secretaria.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::exportacao::{AlunoNovo, csv};
+/// # use chrono::NaiveDate;
+/// let novos = [AlunoNovo {
+///     uid: "joaops".to_string(),
+///     nome: "Joao Pereira, Jr.".to_string(),
+///     dre: "123456789".to_string(),
+///     email: "joaops@ic.ufrj.br".to_string(),
+///     criacao: NaiveDate::from_ymd_opt(2025, 7, 3).unwrap(),
+/// }];
+/// assert_eq!(
+///     csv(&novos),
+///     "uid,nome,dre,email,data_criacao\n\
+///      joaops,\"Joao Pereira, Jr.\",123456789,joaops@ic.ufrj.br,03/07/2025\n",
+/// );
+/// ```
+pub fn csv(novos: &[AlunoNovo]) -> String {
+    let mut csv = "uid,nome,dre,email,data_criacao\n".to_string();
+    for aluno in novos {
+        writeln!(
+            csv,
+            "{},{},{},{},{}",
+            campo_csv(&aluno.uid),
+            campo_csv(&aluno.nome),
+            campo_csv(&aluno.dre),
+            campo_csv(&aluno.email),
+            aluno.criacao.format("%d/%m/%Y"),
+        )
+        .unwrap();
+    }
+    csv
+}
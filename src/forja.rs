@@ -0,0 +1,93 @@
+//! Criação dos usuários no GitLab ou no Gitea do IC pela API REST deles,
+//! usada pelos hooks [`Hook::Gitlab`](crate::hooks::Hook::Gitlab) e
+//! [`Hook::Gitea`](crate::hooks::Hook::Gitea). O usuário tem o mesmo username
+//! e o email institucional da conta, e o id retornado é guardado na auditoria
+//! para que ele possa ser removido junto com a conta.
+use crate::hooks::{ErroHook, Evento};
+use rand::distr::{Alphanumeric, SampleString};
+use reqwest::RequestBuilder;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::{Value, json};
+use zeroize::Zeroizing;
+
+/// Tamanho da senha aleatória dos usuários do Gitea sem fonte de
+/// autenticação externa.
+const TAMANHO_SENHA: usize = 32;
+
+/// Envia o `corpo` e retorna o `id` do usuário criado.
+async fn enviar(
+    requisicao: RequestBuilder,
+    corpo: &str,
+) -> Result<u64, ErroHook> {
+    let resposta = requisicao
+        .header(CONTENT_TYPE, "application/json")
+        .body(corpo.to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    serde_json::from_str::<Value>(&resposta)
+        .ok()
+        .and_then(|v| v["id"].as_u64())
+        .ok_or(ErroHook::RespostaInvalida(resposta))
+}
+
+/// Cria o usuário do `evento` no GitLab em `url`, com uma senha aleatória que
+/// ele nunca usa, já que o login é pelo LDAP.
+pub async fn criar_usuario_gitlab(
+    url: &str,
+    token: &SecretString,
+    evento: &Evento<'_>,
+) -> Result<u64, ErroHook> {
+    let corpo = json!({
+        "username": evento.uid,
+        "email": evento.email,
+        "name": evento.nome,
+        "force_random_password": true,
+        "skip_confirmation": true,
+    });
+
+    let requisicao = reqwest::Client::new()
+        .post(format!("{}/api/v4/users", url.trim_end_matches('/')))
+        .header("PRIVATE-TOKEN", token.expose_secret());
+    enviar(requisicao, &corpo.to_string()).await
+}
+
+/// Cria o usuário do `evento` no Gitea em `url`. Com a `fonte` de
+/// autenticação (o LDAP configurado no Gitea), o login é feito por ela; sem,
+/// o usuário recebe uma senha aleatória e precisa trocá-la.
+pub async fn criar_usuario_gitea(
+    url: &str,
+    token: &SecretString,
+    fonte: Option<u64>,
+    evento: &Evento<'_>,
+) -> Result<u64, ErroHook> {
+    let mut corpo = json!({
+        "username": evento.uid,
+        "email": evento.email,
+        "full_name": evento.nome,
+        "send_notify": false,
+    });
+    match fonte {
+        Some(fonte) => {
+            corpo["source_id"] = fonte.into();
+            corpo["login_name"] = evento.uid.into();
+            corpo["must_change_password"] = false.into();
+        },
+        None => {
+            let senha =
+                Alphanumeric.sample_string(&mut rand::rng(), TAMANHO_SENHA);
+            corpo["password"] = senha.into();
+            corpo["must_change_password"] = true.into();
+        },
+    }
+    let corpo = Zeroizing::new(corpo.to_string());
+
+    let requisicao = reqwest::Client::new()
+        .post(format!("{}/api/v1/admin/users", url.trim_end_matches('/')))
+        .header(AUTHORIZATION, format!("token {}", token.expose_secret()));
+    enviar(requisicao, &corpo).await
+}
@@ -0,0 +1,200 @@
+//! Foto opcional do aluno, enviada no cadastro e gravada no `jpegPhoto`, que
+//! é usado pelos sistemas de identificação dos laboratórios. A foto só é
+//! aceita em JPEG e é recodificada antes de ir para o LDAP, o que descarta os
+//! metadados, como o EXIF com a localização de onde ela foi tirada.
+use base64::prelude::*;
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, ImageReader, Limits};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use thiserror::Error;
+
+/// O atributo onde a foto é gravada.
+pub const ATRIBUTO_FOTO: &str = "jpegPhoto";
+
+/// O envio da foto no cadastro, configurado como
+///
+/// ```yaml
+/// usuario_novo:
+///   foto:
+///     largura_maxima: 1024
+///     altura_maxima: 1024
+///     tamanho_maximo_kb: 512
+///     qualidade: 85
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoFoto {
+    /// A largura máxima, em pixels.
+    pub largura_maxima: u32,
+    /// A altura máxima, em pixels.
+    pub altura_maxima: u32,
+    /// O tamanho máximo do arquivo enviado, em KiB.
+    pub tamanho_maximo_kb: usize,
+    /// A qualidade da recodificação, de 1 a 100.
+    pub qualidade: u8,
+}
+
+impl Default for ConfiguracaoFoto {
+    fn default() -> Self {
+        Self {
+            largura_maxima: 1024,
+            altura_maxima: 1024,
+            tamanho_maximo_kb: 512,
+            qualidade: 85,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ErroFoto {
+    #[error("A foto não está em base64")]
+    Base64,
+    #[error("A foto passa do limite de {0} KiB")]
+    Grande(usize),
+    #[error("A foto precisa estar em JPEG")]
+    NaoEJpeg,
+    #[error("A foto tem {largura}x{altura} pixels, mais que o permitido")]
+    Dimensoes { largura: u32, altura: u32 },
+    #[error("Não foi possível ler a foto: {0}")]
+    Imagem(#[from] image::ImageError),
+}
+
+/// Uma foto em JPEG, codificada em base64 como no JSON do cadastro.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Foto(String);
+
+/// A foto fica fora dos logs, que só mostram o tamanho dela.
+impl std::fmt::Debug for Foto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Foto({} caracteres)", self.0.len())
+    }
+}
+
+impl Foto {
+    /// A foto como enviada, em base64.
+    pub fn base64(&self) -> &str {
+        &self.0
+    }
+
+    /// O JPEG da foto, se o base64 for válido.
+    pub fn jpeg(&self) -> Option<Vec<u8>> {
+        BASE64_STANDARD.decode(self.0.trim()).ok()
+    }
+
+    /// Valida a foto pela configuração e recodifica ela, retornando a foto
+    /// que vai para o LDAP, sem os metadados da enviada.
+    pub fn processar(&self, cfg: &ConfiguracaoFoto) -> Result<Foto, ErroFoto> {
+        let jpeg = self.jpeg().ok_or(ErroFoto::Base64)?;
+        if jpeg.len() > cfg.tamanho_maximo_kb * 1024 {
+            return Err(ErroFoto::Grande(cfg.tamanho_maximo_kb));
+        }
+        if image::guess_format(&jpeg).ok() != Some(ImageFormat::Jpeg) {
+            return Err(ErroFoto::NaoEJpeg);
+        }
+
+        // As dimensões vêm do cabeçalho, antes de a imagem ser decodificada
+        let leitor =
+            || ImageReader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg);
+        let (largura, altura) = leitor().into_dimensions()?;
+        if largura > cfg.largura_maxima || altura > cfg.altura_maxima {
+            return Err(ErroFoto::Dimensoes { largura, altura });
+        }
+
+        let mut limites = Limits::default();
+        limites.max_image_width = Some(cfg.largura_maxima);
+        limites.max_image_height = Some(cfg.altura_maxima);
+        let mut leitor = leitor();
+        leitor.limits(limites);
+        let imagem = leitor.decode()?.to_rgb8();
+
+        let mut recodificada = vec![];
+        JpegEncoder::new_with_quality(&mut recodificada, cfg.qualidade)
+            .encode_image(&imagem)?;
+        Ok(Foto(BASE64_STANDARD.encode(recodificada)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    /// Uma foto lisa de `largura`x`altura`, com um comentário no lugar dos
+    /// metadados.
+    fn foto(largura: u32, altura: u32) -> Foto {
+        let mut jpeg = vec![];
+        JpegEncoder::new(&mut jpeg)
+            .encode_image(&RgbImage::new(largura, altura))
+            .unwrap();
+
+        // Um segmento COM logo depois do SOI
+        let comentario = b"GPS 22.86S 43.23W";
+        let tamanho = (comentario.len() + 2) as u16;
+        let mut segmento = vec![0xFF, 0xFE];
+        segmento.extend(tamanho.to_be_bytes());
+        segmento.extend(comentario);
+        jpeg.splice(2..2, segmento);
+
+        Foto(BASE64_STANDARD.encode(jpeg))
+    }
+
+    #[test]
+    fn recodifica_sem_os_metadados() {
+        let enviada = foto(64, 48);
+        assert!(enviada.jpeg().unwrap().windows(3).any(|w| w == b"GPS"));
+
+        let gravada = enviada.processar(&ConfiguracaoFoto::default()).unwrap();
+        let jpeg = gravada.jpeg().unwrap();
+        assert!(!jpeg.windows(3).any(|w| w == b"GPS"));
+        let (largura, altura) =
+            ImageReader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg)
+                .into_dimensions()
+                .unwrap();
+        assert_eq!((largura, altura), (64, 48));
+    }
+
+    #[test]
+    fn recusa_fotos_fora_dos_limites() {
+        let cfg = ConfiguracaoFoto {
+            largura_maxima: 100,
+            altura_maxima: 100,
+            tamanho_maximo_kb: 1,
+            qualidade: 85,
+        };
+
+        assert!(matches!(
+            foto(101, 10).processar(&cfg),
+            Err(ErroFoto::Dimensoes {
+                largura: 101,
+                altura: 10
+            }),
+        ));
+        let no_limite = ConfiguracaoFoto {
+            largura_maxima: 100,
+            altura_maxima: 100,
+            ..Default::default()
+        };
+        assert!(foto(100, 100).processar(&no_limite).is_ok());
+        assert!(matches!(
+            Foto(BASE64_STANDARD.encode(vec![0xFF; 2048])).processar(&cfg),
+            Err(ErroFoto::Grande(1)),
+        ));
+        assert!(matches!(
+            Foto(BASE64_STANDARD.encode(b"\x89PNG\r\n\x1a\n")).processar(&cfg),
+            Err(ErroFoto::NaoEJpeg),
+        ));
+        assert!(matches!(
+            Foto("não é base64".to_string()).processar(&cfg),
+            Err(ErroFoto::Base64),
+        ));
+        // Nem um JPEG truncado
+        let jpeg = foto(50, 50).jpeg().unwrap();
+        let truncada = &jpeg[..jpeg.len() / 2];
+        assert!(matches!(
+            Foto(BASE64_STANDARD.encode(truncada)).processar(&cfg),
+            Err(ErroFoto::Imagem(_)),
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+//! O serviço gRPC da API, para os serviços internos do LCI que preferem
+//! contratos tipados: as consultas, o cadastro e a renovação em lote, que
+//! envia o progresso de cada linha. Ele divide o estado com a API REST, então
+//! o modo somente leitura, as vagas e as estatísticas valem para os dois. Só
+//! existe com a feature `grpc`.
+//!
+//! O contrato está em `proto/alumnic.proto`. As [mensagens] e o roteamento
+//! dos métodos são escritos à mão a partir dele, para o build não depender do
+//! `protoc`; uma mudança no contrato precisa ser feita nos dois.
+use crate::api::{
+    CADASTRO_DESLIGADO, EstadoApi, RENOVACAO_DESLIGADA, USERNAMES_OFERECIDOS,
+    autenticar,
+};
+use crate::cadastro_aluno::{DadosParaCadastro, ErroDeCadastro};
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::consulta::achar_nomes_livres_ldap;
+use crate::ldap::conta::buscar_conta_por_dre;
+use crate::lotes::Lote;
+use crate::manutencao::Manutencao;
+use crate::renovacao::{dia_para_data, linhas_do_lote, renovacoes_em_lote};
+use axum::http::StatusCode;
+use chrono::Utc;
+use futures::StreamExt;
+use mensagens::progresso_lote::Resultado;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::{Body, BoxFuture, Service, StdError, http};
+use tonic::server::{Grpc, NamedService};
+use tonic::{Code, Request, Response, Status};
+use tonic_prost::ProstCodec;
+use tower::service_fn;
+
+/// O serviço gRPC, configurado como
+///
+/// ```yaml
+/// grpc:
+///   endereco: "127.0.0.1:50051"
+/// ```
+///
+/// As chamadas levam o `api_token` no metadado `authorization`, como nas
+/// rotas administrativas; sem o token configurado, o serviço não responde.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoGrpc {
+    /// Onde o serviço escuta, separado da API REST para que só a rede
+    /// interna chegue nele.
+    pub endereco: SocketAddr,
+}
+
+/// As mensagens do `proto/alumnic.proto`.
+pub mod mensagens {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ConsultaConta {
+        #[prost(string, tag = "1")]
+        pub dre: String,
+    }
+
+    /// As datas estão no formato `AAAA-MM-DD`, e ficam vazias quando não
+    /// existem.
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SituacaoConta {
+        #[prost(string, tag = "1")]
+        pub uid: String,
+        #[prost(string, tag = "2")]
+        pub estado: String,
+        #[prost(string, tag = "3")]
+        pub valida_ate: String,
+        #[prost(string, tag = "4")]
+        pub carencia_ate: String,
+        #[prost(string, tag = "5")]
+        pub remocao_em: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ConsultaUsernames {
+        #[prost(string, tag = "1")]
+        pub nome: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Usernames {
+        #[prost(string, repeated, tag = "1")]
+        pub usernames: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Cadastro {
+        #[prost(string, tag = "1")]
+        pub dre: String,
+        #[prost(string, tag = "2")]
+        pub data: String,
+        #[prost(string, tag = "3")]
+        pub hora: String,
+        #[prost(string, tag = "4")]
+        pub codigo: String,
+        #[prost(string, tag = "5")]
+        pub nome: String,
+        #[prost(string, tag = "6")]
+        pub email: String,
+        #[prost(string, tag = "7")]
+        pub telefone: String,
+        #[prost(string, tag = "8")]
+        pub senha: String,
+        #[prost(string, optional, tag = "9")]
+        pub chave_ssh: Option<String>,
+        #[prost(string, optional, tag = "10")]
+        pub username: Option<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CadastroRealizado {
+        #[prost(string, tag = "1")]
+        pub username: String,
+        #[prost(string, tag = "2")]
+        pub ou: String,
+        #[prost(string, tag = "3")]
+        pub dre: String,
+        #[prost(string, tag = "4")]
+        pub home: String,
+        #[prost(string, tag = "5")]
+        pub nome: String,
+        #[prost(string, tag = "6")]
+        pub email: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Lote {
+        #[prost(string, repeated, tag = "1")]
+        pub linhas: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ProgressoLote {
+        #[prost(string, tag = "1")]
+        pub linha: String,
+        #[prost(uint32, tag = "2")]
+        pub feitas: u32,
+        #[prost(uint32, tag = "3")]
+        pub total: u32,
+        #[prost(oneof = "progresso_lote::Resultado", tags = "4, 5")]
+        pub resultado: Option<progresso_lote::Resultado>,
+    }
+
+    pub mod progresso_lote {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Resultado {
+            #[prost(message, tag = "4")]
+            Renovada(super::Renovada),
+            #[prost(string, tag = "5")]
+            Erro(String),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Renovada {
+        #[prost(string, tag = "1")]
+        pub username: String,
+        #[prost(string, tag = "2")]
+        pub proxima_renovacao: String,
+    }
+}
+
+/// O nome do serviço no contrato, que prefixa o caminho dos métodos.
+pub const SERVICO: &str = "alumnic.Alumnic";
+
+/// O status gRPC equivalente ao `status` HTTP de um erro da API.
+fn status_grpc(status: StatusCode, mensagem: String) -> Status {
+    let codigo = match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+            Code::InvalidArgument
+        },
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::AlreadyExists,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => {
+            Code::Unavailable
+        },
+        StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ => Code::Internal,
+    };
+    Status::new(codigo, mensagem)
+}
+
+fn status_ldap(erro: ErroLdap) -> Status {
+    let status = match erro {
+        ErroLdap::ErroDeNome(..) => StatusCode::UNPROCESSABLE_ENTITY,
+        ErroLdap::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        ErroLdap::SomenteLeitura => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    status_grpc(status, format!("Erro: {erro}"))
+}
+
+/// O serviço gRPC, que atende os métodos do [SERVICO].
+pub struct ServicoGrpc<F> {
+    estado: Arc<EstadoApi<F>>,
+}
+
+impl<F> Clone for ServicoGrpc<F> {
+    fn clone(&self) -> Self {
+        Self {
+            estado: Arc::clone(&self.estado),
+        }
+    }
+}
+
+impl<F: FonteLdap + 'static> ServicoGrpc<F> {
+    /// O serviço com um estado próprio, como o [router](crate::api::router).
+    pub fn novo(cfg: Arc<Configuracao>, ldap: F) -> Self {
+        let manutencao = Arc::new(Manutencao::nova(&cfg.manutencao));
+        Self::com_estado(EstadoApi::novo(cfg, ldap, manutencao))
+    }
+
+    /// O serviço com o `estado` da API REST.
+    pub(crate) fn com_estado(estado: Arc<EstadoApi<F>>) -> Self {
+        Self { estado }
+    }
+}
+
+impl<F> NamedService for ServicoGrpc<F> {
+    const NAME: &'static str = SERVICO;
+}
+
+impl<F, B> Service<http::Request<B>> for ServicoGrpc<F>
+where
+    F: FonteLdap + 'static,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let estado = Arc::clone(&self.estado);
+        Box::pin(async move {
+            match autenticar(estado.cfg.api_token.as_ref(), req.headers()) {
+                Ok(()) => {},
+                Err(StatusCode::NOT_FOUND) => {
+                    return Ok(Status::unimplemented(
+                        "O gRPC precisa do api_token configurado",
+                    )
+                    .into_http());
+                },
+                Err(_) => {
+                    return Ok(
+                        Status::unauthenticated("Token inválido").into_http()
+                    );
+                },
+            }
+
+            let metodo = req
+                .uri()
+                .path()
+                .strip_prefix('/')
+                .and_then(|m| m.strip_prefix(SERVICO))
+                .and_then(|m| m.strip_prefix('/'))
+                .unwrap_or_default()
+                .to_string();
+            let resposta = match metodo.as_str() {
+                "ConsultarConta" => {
+                    let metodo =
+                        service_fn(move |r| consultar_conta(estado.clone(), r));
+                    Grpc::new(ProstCodec::default()).unary(metodo, req).await
+                },
+                "UsernamesLivres" => {
+                    let metodo = service_fn(move |r| {
+                        usernames_livres(estado.clone(), r)
+                    });
+                    Grpc::new(ProstCodec::default()).unary(metodo, req).await
+                },
+                "Cadastrar" => {
+                    let metodo =
+                        service_fn(move |r| cadastrar(estado.clone(), r));
+                    Grpc::new(ProstCodec::default()).unary(metodo, req).await
+                },
+                "RenovarLote" => {
+                    let metodo =
+                        service_fn(move |r| renovar_lote(estado.clone(), r));
+                    Grpc::new(ProstCodec::default())
+                        .server_streaming(metodo, req)
+                        .await
+                },
+                _ => Status::unimplemented(format!(
+                    "O método {metodo:?} não existe",
+                ))
+                .into_http(),
+            };
+            Ok(resposta)
+        })
+    }
+}
+
+/// Serve o gRPC no `endereco`, com o `estado` da API REST.
+pub(crate) async fn servir<F: FonteLdap + 'static>(
+    endereco: SocketAddr,
+    estado: Arc<EstadoApi<F>>,
+) {
+    let termino = estado.cancelamento.clone().cancelled_owned();
+    let servico = ServicoGrpc::com_estado(estado);
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(servico)
+        .serve_with_shutdown(endereco, termino)
+        .await
+    {
+        eprintln!("Erro no serviço gRPC em {endereco}: {e}");
+    }
+}
+
+async fn consultar_conta<F: FonteLdap>(
+    estado: Arc<EstadoApi<F>>,
+    req: Request<mensagens::ConsultaConta>,
+) -> Result<Response<mensagens::SituacaoConta>, Status> {
+    let dre = req.into_inner().dre;
+    let conta: Result<_, ErroLdap> = async {
+        let mut conexao = estado.ldap.abrir().await?;
+        let r = buscar_conta_por_dre(&dre, &mut conexao).await;
+        estado.ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+
+    let Some(conta) = conta.map_err(status_ldap)? else {
+        return Err(Status::not_found(format!(
+            "Não existe conta com o DRE {dre:?}"
+        )));
+    };
+    let data = |dia: Option<i64>| {
+        dia.map(|d| dia_para_data(d).to_string())
+            .unwrap_or_default()
+    };
+    let carencia = estado.cfg.renovacao.carencia_dias;
+    Ok(Response::new(mensagens::SituacaoConta {
+        uid: conta.uid,
+        estado: conta.estado.valor().to_string(),
+        valida_ate: data(conta.data_renovacao),
+        carencia_ate: data(conta.data_renovacao.map(|d| d + carencia)),
+        remocao_em: data(conta.data_remocao),
+    }))
+}
+
+async fn usernames_livres<F: FonteLdap>(
+    estado: Arc<EstadoApi<F>>,
+    req: Request<mensagens::ConsultaUsernames>,
+) -> Result<Response<mensagens::Usernames>, Status> {
+    let usernames = achar_nomes_livres_ldap(
+        &req.into_inner().nome,
+        USERNAMES_OFERECIDOS,
+        estado.cfg.usuario_novo.usernames_confundiveis.as_ref(),
+        Some(&estado.uids),
+        &estado.ldap,
+    )
+    .await
+    .map_err(status_ldap)?;
+    Ok(Response::new(mensagens::Usernames { usernames }))
+}
+
+async fn cadastrar<F: FonteLdap + 'static>(
+    estado: Arc<EstadoApi<F>>,
+    req: Request<mensagens::Cadastro>,
+) -> Result<Response<mensagens::CadastroRealizado>, Status> {
+    let c = req.into_inner();
+    let dados = DadosParaCadastro {
+        dre: c.dre,
+        data: c.data,
+        hora: c.hora,
+        codigo: c.codigo,
+        nome: c.nome,
+        email: c.email,
+        telefone: c.telefone,
+        senha: c.senha.into(),
+        chave_ssh: c.chave_ssh,
+        username: c.username,
+        foto: None,
+    };
+    let recusar = |err: ErroDeCadastro| {
+        estado.contar_erro_de_cadastro(&err);
+        status_grpc(err.status(), format!("Erro: {err}"))
+    };
+    dados
+        .limitar_tamanhos()
+        .map_err(|e| recusar(ErroDeCadastro::from(e)))?;
+
+    if !estado.cfg.features.cadastro_novo {
+        return Err(Status::unavailable(CADASTRO_DESLIGADO));
+    }
+    if let Some(mensagem) = estado.manutencao.somente_leitura() {
+        return Err(Status::unavailable(mensagem));
+    }
+    let _vaga = estado.vaga().await;
+
+    let cfg = &estado.cfg;
+    let dre = dados.dre.clone();
+    let email = dados.email.clone();
+    match dados
+        .cadastrar(
+            &cfg.usuario_novo,
+            &cfg.renovacao,
+            &cfg.gnosys_url,
+            &estado.ldap,
+            Some(&estado.uids),
+            &estado.cancelamento,
+        )
+        .await
+    {
+        Ok(cadastro) => {
+            estado.depois_do_cadastro(&cadastro);
+            Ok(Response::new(mensagens::CadastroRealizado {
+                username: cadastro.username,
+                ou: cadastro.ou.to_string(),
+                dre: cadastro.dre,
+                home: cadastro.home,
+                nome: cadastro.nome,
+                email: cadastro.email,
+            }))
+        },
+        // O cadastro não tem username ainda; quem chamou fica sabendo que
+        // ele será feito quando o LDAP voltar
+        Err(err @ ErroDeCadastro::Enfileirado) => {
+            estado.avisar_enfileirado(&dre, &email);
+            Err(Status::unavailable(err.to_string()))
+        },
+        Err(err) => {
+            estado.registrar_falha_de_cadastro(&dre, &err);
+            Err(recusar(err))
+        },
+    }
+}
+
+async fn renovar_lote<F: FonteLdap + 'static>(
+    estado: Arc<EstadoApi<F>>,
+    req: Request<mensagens::Lote>,
+) -> Result<
+    Response<ReceiverStream<Result<mensagens::ProgressoLote, Status>>>,
+    Status,
+> {
+    if !estado.cfg.features.renovacao {
+        return Err(Status::unavailable(RENOVACAO_DESLIGADA));
+    }
+    if let Some(mensagem) = estado.manutencao.somente_leitura() {
+        return Err(Status::unavailable(mensagem));
+    }
+
+    let lista = req.into_inner().linhas.join("\n");
+    let total = linhas_do_lote(&lista).count() as u32;
+    let (tx, rx) = mpsc::channel(16);
+    // O serviço espera o lote terminar antes de sair
+    estado.tarefas.clone().spawn(async move {
+        let cfg = &estado.cfg;
+        let lote = Lote::acompanhar(
+            &cfg.dados,
+            "renovacao",
+            total as usize,
+            estado.cancelamento.clone(),
+        );
+        let mut renovacoes = pin!(renovacoes_em_lote(
+            &lista,
+            &cfg.renovacao,
+            &cfg.gnosys_url,
+            &estado.ldap,
+            Utc::now(),
+            cfg.concorrencia.lote,
+            &lote,
+        ));
+
+        let mut feitas = 0;
+        while let Some((linha, r)) = renovacoes.next().await {
+            feitas += 1;
+            let resultado = match r {
+                Ok(r) => {
+                    estado.depois_da_renovacao(r.conta);
+                    Resultado::Renovada(mensagens::Renovada {
+                        username: r.username,
+                        proxima_renovacao: r.proxima_renovacao.to_string(),
+                    })
+                },
+                Err(e) => {
+                    estado.registrar_falha("renovação", &linha, &e);
+                    Resultado::Erro(e.to_string())
+                },
+            };
+            let progresso = mensagens::ProgressoLote {
+                linha,
+                feitas,
+                total,
+                resultado: Some(resultado),
+            };
+            // Quem chamou desistiu do lote, e as linhas que faltam ficam
+            // sem renovar
+            if tx.send(Ok(progresso)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(Response::new(ReceiverStream::new(rx)))
+}
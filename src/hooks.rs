@@ -0,0 +1,555 @@
+//! Hooks configuráveis, disparados quando uma conta é criada ou muda de
+//! estado, para que outros sistemas (como o servidor de arquivos) acompanhem o
+//! LDAP. Um hook é um comando local, um comando por SSH, uma chamada HTTP ou a
+//! criação do usuário no GitLab ou no Gitea do IC.
+//!
+//! Os comandos e as URLs recebem o mesmo JSON, o [`Evento`] com a versão do
+//! formato e o momento do disparo, para que integrações novas não dependam
+//! do tipo de hook.
+use crate::auditoria::{
+    ConfiguracaoAuditoria, Registro, acrescentar_linha, registrar,
+};
+use crate::forja::{criar_usuario_gitea, criar_usuario_gitlab};
+use crate::ldap::conta::Conta;
+use chrono::{DateTime, Utc};
+use reqwest::header::CONTENT_TYPE;
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// A versão do JSON enviado aos hooks. Ela só muda se algum campo mudar de
+/// significado ou deixar de existir; campos novos não mudam a versão.
+pub const VERSAO_PAYLOAD: u32 = 1;
+
+/// Os hooks de cada evento, e como eles são tentados.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoHooks {
+    /// Rodados depois que uma conta é criada, como a criação do home no
+    /// servidor de arquivos.
+    pub ao_cadastrar: Vec<Hook>,
+    /// Rodados depois que o vínculo de uma conta é renovado.
+    pub ao_renovar: Vec<Hook>,
+    /// Rodados depois que uma conta é suspensa.
+    pub ao_suspender: Vec<Hook>,
+    /// Rodados antes de uma conta ser marcada como removida. Se algum
+    /// falhar, a conta continua suspensa e a remoção é tentada de novo na
+    /// próxima execução dos prazos.
+    pub ao_remover: Vec<Hook>,
+    /// Quantas vezes cada hook é tentado antes de ser dado como falho.
+    pub tentativas: u32,
+    /// Quanto tempo esperar entre as tentativas, em segundos.
+    pub intervalo_segundos: u64,
+    /// Quanto tempo cada tentativa pode levar, em segundos. Um comando que
+    /// passa do tempo é morto.
+    pub timeout_segundos: u64,
+    /// O arquivo onde o resultado dos hooks de cada conta é registrado, uma
+    /// linha JSON por hook. Se não for definido, nada é registrado.
+    pub registro: Option<PathBuf>,
+}
+
+impl Default for ConfiguracaoHooks {
+    fn default() -> Self {
+        Self {
+            ao_cadastrar: vec![],
+            ao_renovar: vec![],
+            ao_suspender: vec![],
+            ao_remover: vec![],
+            tentativas: 3,
+            intervalo_segundos: 30,
+            timeout_segundos: 60,
+            registro: None,
+        }
+    }
+}
+
+impl ConfiguracaoHooks {
+    /// Os hooks disparados pelo `evento`.
+    pub fn do_evento(&self, evento: TipoEvento) -> &[Hook] {
+        match evento {
+            TipoEvento::Cadastro => &self.ao_cadastrar,
+            TipoEvento::Renovacao => &self.ao_renovar,
+            TipoEvento::Suspensao => &self.ao_suspender,
+            TipoEvento::Remocao => &self.ao_remover,
+        }
+    }
+}
+
+/// Um hook, configurado como
+///
+/// ```yaml
+/// - tipo: comando
+///   comando: ["/usr/local/bin/arquivar-home"]
+/// - tipo: ssh
+///   destino: "alumnic@arquivos.ic.ufrj.br"
+///   comando: ["/usr/local/bin/criar-home"]
+/// - tipo: http
+///   url: "https://arquivos.ic.ufrj.br/arquivar"
+/// - tipo: gitlab
+///   url: "https://git.ic.ufrj.br"
+///   token: "..."
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum Hook {
+    /// Roda o comando com os dados do [`Evento`] nas variáveis de ambiente
+    /// `ALUMNIC_EVENTO`, `ALUMNIC_UID`, `ALUMNIC_DRE`, `ALUMNIC_HOME`,
+    /// `ALUMNIC_NOME` e `ALUMNIC_EMAIL`, e em JSON na entrada padrão.
+    Comando { comando: Vec<String> },
+    /// Roda o comando no `destino` pelo `ssh`, com as mesmas variáveis de
+    /// ambiente e a mesma entrada do [`Hook::Comando`]. O `ssh` não pode pedir
+    /// senha.
+    Ssh {
+        destino: String,
+        comando: Vec<String>,
+    },
+    /// Envia os dados do [`Evento`] em JSON por um `POST` para a `url`.
+    Http { url: String },
+    /// Cria o usuário no GitLab em `url`, com um token de administrador.
+    Gitlab { url: String, token: SecretString },
+    /// Cria o usuário no Gitea em `url`, com um token de administrador. Com
+    /// a `fonte_de_autenticacao`, o id da fonte LDAP do Gitea, o login é
+    /// feito pelo LDAP.
+    Gitea {
+        url: String,
+        token: SecretString,
+        #[serde(default)]
+        fonte_de_autenticacao: Option<u64>,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum ErroHook {
+    #[error("o hook não tem comando")]
+    SemComando,
+    #[error("não foi possível rodar o hook: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("o hook falhou com {0}")]
+    Falha(ExitStatus),
+    #[error("a chamada do hook falhou: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("a resposta não tem o id do usuário criado: {0:?}")]
+    RespostaInvalida(String),
+    #[error("o hook não terminou em {0} segundos")]
+    TempoEsgotado(u64),
+}
+
+/// O tipo do evento que disparou o hook.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TipoEvento {
+    Cadastro,
+    Renovacao,
+    Suspensao,
+    Remocao,
+}
+
+impl TipoEvento {
+    fn valor(&self) -> &'static str {
+        match self {
+            TipoEvento::Cadastro => "cadastro",
+            TipoEvento::Renovacao => "renovacao",
+            TipoEvento::Suspensao => "suspensao",
+            TipoEvento::Remocao => "remocao",
+        }
+    }
+}
+
+/// Os dados passados a um hook.
+#[derive(Debug, Serialize)]
+pub struct Evento<'a> {
+    pub evento: TipoEvento,
+    pub uid: &'a str,
+    pub dre: &'a str,
+    pub home: &'a str,
+    /// O nome completo, sem acentos.
+    pub nome: &'a str,
+    /// O email institucional.
+    pub email: &'a str,
+}
+
+impl<'a> Evento<'a> {
+    pub fn da_conta(evento: TipoEvento, conta: &'a Conta) -> Self {
+        Self {
+            evento,
+            uid: &conta.uid,
+            dre: &conta.dre,
+            home: conta.home.as_deref().unwrap_or_default(),
+            nome: &conta.nome,
+            email: conta.email_institucional.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+/// O JSON enviado aos hooks: o [`Evento`] com a versão do formato e o momento
+/// do disparo.
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    versao: u32,
+    quando: DateTime<Utc>,
+    #[serde(flatten)]
+    evento: &'a Evento<'a>,
+}
+
+impl Evento<'_> {
+    /// O JSON enviado aos hooks.
+    pub fn payload(&self) -> String {
+        let payload = Payload {
+            versao: VERSAO_PAYLOAD,
+            quando: Utc::now(),
+            evento: self,
+        };
+        serde_json::to_string(&payload)
+            .expect("o evento sempre pode ser serializado")
+    }
+
+    /// As variáveis de ambiente passadas aos comandos.
+    fn variaveis(&self) -> [(&'static str, &str); 6] {
+        [
+            ("ALUMNIC_EVENTO", self.evento.valor()),
+            ("ALUMNIC_UID", self.uid),
+            ("ALUMNIC_DRE", self.dre),
+            ("ALUMNIC_HOME", self.home),
+            ("ALUMNIC_NOME", self.nome),
+            ("ALUMNIC_EMAIL", self.email),
+        ]
+    }
+}
+
+/// Coloca `s` entre aspas simples, para ser interpretado pelo shell remoto
+/// como um argumento só.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::hooks::citar;
+/// assert_eq!(citar("/usuarios/alunos/joaops"), "'/usuarios/alunos/joaops'");
+/// assert_eq!(citar("d'avila"), r"'d'\''avila'");
+/// ```
+pub fn citar(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// A linha de comando rodada no destino de um [`Hook::Ssh`], com as variáveis
+/// do `evento` passadas pelo `env`.
+fn comando_remoto(comando: &[String], evento: &Evento<'_>) -> String {
+    let variaveis = evento
+        .variaveis()
+        .map(|(nome, valor)| format!("{nome}={}", citar(valor)));
+
+    std::iter::once("env".to_string())
+        .chain(variaveis)
+        .chain(comando.iter().map(|c| citar(c)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Roda o `comando` com o `payload` na entrada padrão, esperando ele
+/// terminar. Se o futuro for cancelado, o comando é morto.
+async fn rodar(mut comando: Command, payload: &str) -> Result<(), ErroHook> {
+    let mut filho = comando.stdin(Stdio::piped()).kill_on_drop(true).spawn()?;
+
+    if let Some(mut entrada) = filho.stdin.take()
+        && let Err(e) = entrada.write_all(payload.as_bytes()).await
+        // O comando pode terminar sem ler a entrada
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        return Err(e.into());
+    }
+
+    let status = filho.wait().await?;
+    if !status.success() {
+        return Err(ErroHook::Falha(status));
+    }
+    Ok(())
+}
+
+impl Hook {
+    /// Uma descrição curta do hook, para os registros.
+    pub fn descricao(&self) -> String {
+        match self {
+            Hook::Comando { comando } => comando.join(" "),
+            Hook::Ssh { destino, comando } => {
+                format!("ssh {destino} {}", comando.join(" "))
+            },
+            Hook::Http { url } => url.clone(),
+            Hook::Gitlab { url, .. } => format!("gitlab {url}"),
+            Hook::Gitea { url, .. } => format!("gitea {url}"),
+        }
+    }
+
+    /// Dispara o hook com os dados do `evento`. Os hooks que criam o usuário
+    /// em outro sistema retornam o id dele.
+    pub async fn disparar(
+        &self,
+        evento: &Evento<'_>,
+    ) -> Result<Option<u64>, ErroHook> {
+        match self {
+            Hook::Comando { comando } => {
+                let (programa, argumentos) =
+                    comando.split_first().ok_or(ErroHook::SemComando)?;
+
+                let mut processo = Command::new(programa);
+                processo.args(argumentos).envs(evento.variaveis());
+                rodar(processo, &evento.payload()).await?;
+            },
+            Hook::Ssh { destino, comando } => {
+                if comando.is_empty() {
+                    return Err(ErroHook::SemComando);
+                }
+
+                let mut processo = Command::new("ssh");
+                processo
+                    .args(["-o", "BatchMode=yes", destino])
+                    .arg(comando_remoto(comando, evento));
+                rodar(processo, &evento.payload()).await?;
+            },
+            Hook::Http { url } => {
+                reqwest::Client::new()
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(evento.payload())
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            },
+            Hook::Gitlab { url, token } => {
+                return criar_usuario_gitlab(url, token, evento)
+                    .await
+                    .map(Some);
+            },
+            Hook::Gitea {
+                url,
+                token,
+                fonte_de_autenticacao,
+            } => {
+                return criar_usuario_gitea(
+                    url,
+                    token,
+                    *fonte_de_autenticacao,
+                    evento,
+                )
+                .await
+                .map(Some);
+            },
+        }
+
+        Ok(None)
+    }
+}
+
+/// O resultado de um hook, guardado no registro dos hooks.
+#[derive(Debug, Serialize)]
+struct RegistroHook<'a> {
+    quando: DateTime<Utc>,
+    evento: TipoEvento,
+    uid: &'a str,
+    hook: String,
+    tentativas: u32,
+    sucesso: bool,
+    erro: Option<String>,
+}
+
+/// Dispara o `hook` com o limite de tempo da `cfg`.
+async fn disparar_com_timeout(
+    cfg: &ConfiguracaoHooks,
+    hook: &Hook,
+    evento: &Evento<'_>,
+) -> Result<Option<u64>, ErroHook> {
+    let limite = Duration::from_secs(cfg.timeout_segundos);
+    tokio::time::timeout(limite, hook.disparar(evento))
+        .await
+        .unwrap_or(Err(ErroHook::TempoEsgotado(cfg.timeout_segundos)))
+}
+
+/// Dispara em ordem os hooks do tipo do `evento`, parando no primeiro que
+/// falhar. Cada hook é tentado até `cfg.tentativas` vezes, cada tentativa
+/// limitada a `cfg.timeout_segundos`, e o resultado é guardado no registro.
+///
+/// O id dos usuários criados em outros sistemas é guardado na `auditoria`,
+/// para que eles possam ser removidos junto com a conta.
+pub async fn disparar_evento(
+    cfg: &ConfiguracaoHooks,
+    auditoria: &ConfiguracaoAuditoria,
+    evento: &Evento<'_>,
+) -> Result<(), ErroHook> {
+    for hook in cfg.do_evento(evento.evento) {
+        let mut tentativas = 0;
+        let r = loop {
+            tentativas += 1;
+            match disparar_com_timeout(cfg, hook, evento).await {
+                Err(_) if tentativas < cfg.tentativas => {
+                    tokio::time::sleep(Duration::from_secs(
+                        cfg.intervalo_segundos,
+                    ))
+                    .await;
+                },
+                r => break r,
+            }
+        };
+
+        if let Some(arquivo) = &cfg.registro {
+            let registro = RegistroHook {
+                quando: Utc::now(),
+                evento: evento.evento,
+                uid: evento.uid,
+                hook: hook.descricao(),
+                tentativas,
+                sucesso: r.is_ok(),
+                erro: r.as_ref().err().map(ToString::to_string),
+            };
+            // Uma falha no registro não desfaz o que o hook já fez
+            if let Err(e) = acrescentar_linha(arquivo, &registro).await {
+                eprintln!("Não foi possível registrar o hook: {e}");
+            }
+        }
+
+        if let Some(id) = r? {
+            let registro = Registro {
+                quando: Utc::now(),
+                operacao: "criar_usuario_externo",
+                uid: evento.uid,
+                motivo: &format!("{} id {id}", hook.descricao()),
+            };
+            if let Err(e) = registrar(auditoria, &registro).await {
+                eprintln!("Não foi possível registrar o usuário externo: {e}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evento() -> Evento<'static> {
+        Evento {
+            evento: TipoEvento::Remocao,
+            uid: "joaops",
+            dre: "123456789",
+            home: "/usuarios/alunos/joaops",
+            nome: "Joao Pedro Silva",
+            email: "joaops@ic.ufrj.br",
+        }
+    }
+
+    fn sh(script: &str) -> Hook {
+        Hook::Comando {
+            comando: vec!["sh".into(), "-c".into(), script.into()],
+        }
+    }
+
+    #[tokio::test]
+    async fn comando_recebe_o_evento() {
+        let hook = sh(concat!(
+            r#"test "$ALUMNIC_EVENTO" = remocao && "#,
+            r#"test "$ALUMNIC_UID" = joaops && "#,
+            r#"test "$ALUMNIC_HOME" = /usuarios/alunos/joaops"#,
+        ));
+        hook.disparar(&evento()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn para_no_primeiro_que_falha() {
+        let marcador = std::env::temp_dir()
+            .join(format!("alumnic-hook-{}", std::process::id()));
+        let cfg = ConfiguracaoHooks {
+            ao_remover: vec![
+                sh("exit 3"),
+                sh(&format!("touch {}", marcador.display())),
+            ],
+            tentativas: 1,
+            ..Default::default()
+        };
+
+        let r =
+            disparar_evento(&cfg, &ConfiguracaoAuditoria::default(), &evento())
+                .await;
+        assert!(matches!(r, Err(ErroHook::Falha(s)) if s.code() == Some(3)));
+        assert!(!marcador.exists());
+    }
+
+    #[tokio::test]
+    async fn comando_recebe_o_payload_na_entrada() {
+        let hook = sh(concat!(
+            r#"json=$(cat) && "#,
+            r#"echo "$json" | grep -q '"versao":1' && "#,
+            r#"echo "$json" | grep -q '"evento":"remocao"' && "#,
+            r#"echo "$json" | grep -q '"uid":"joaops"'"#,
+        ));
+        hook.disparar(&evento()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn comando_demorado_e_interrompido() {
+        let cfg = ConfiguracaoHooks {
+            ao_remover: vec![sh("sleep 5")],
+            tentativas: 1,
+            timeout_segundos: 0,
+            ..Default::default()
+        };
+
+        let r =
+            disparar_evento(&cfg, &ConfiguracaoAuditoria::default(), &evento())
+                .await;
+        assert!(matches!(r, Err(ErroHook::TempoEsgotado(0))));
+    }
+
+    #[test]
+    fn comando_remoto_cita_os_argumentos() {
+        let comando = ["/usr/local/bin/criar-home".into(), "a b".into()];
+        assert_eq!(
+            comando_remoto(&comando, &evento()),
+            "env ALUMNIC_EVENTO='remocao' ALUMNIC_UID='joaops' \
+             ALUMNIC_DRE='123456789' ALUMNIC_HOME='/usuarios/alunos/joaops' \
+             ALUMNIC_NOME='Joao Pedro Silva' ALUMNIC_EMAIL='joaops@ic.ufrj.br' \
+             '/usr/local/bin/criar-home' 'a b'",
+        );
+    }
+
+    #[tokio::test]
+    async fn tenta_de_novo_e_registra() {
+        let base = std::env::temp_dir()
+            .join(format!("alumnic-cadastro-{}", std::process::id()));
+        let marcador = base.with_extension("marcador");
+        let registro = base.with_extension("jsonl");
+
+        // Falha na primeira vez e funciona na segunda
+        let instavel = sh(&format!(
+            "test -e {0} || {{ touch {0}; exit 1; }}",
+            marcador.display(),
+        ));
+        let cfg = ConfiguracaoHooks {
+            ao_remover: vec![instavel, sh("exit 2")],
+            intervalo_segundos: 0,
+            registro: Some(registro.clone()),
+            ..Default::default()
+        };
+
+        let r =
+            disparar_evento(&cfg, &ConfiguracaoAuditoria::default(), &evento())
+                .await;
+        assert!(matches!(r, Err(ErroHook::Falha(s)) if s.code() == Some(2)));
+
+        let linhas = std::fs::read_to_string(&registro).unwrap();
+        std::fs::remove_file(&registro).unwrap();
+        std::fs::remove_file(&marcador).unwrap();
+        let linhas: Vec<serde_json::Value> = linhas
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(linhas.len(), 2);
+        assert_eq!(linhas[0]["uid"], "joaops");
+        assert_eq!(linhas[0]["tentativas"], 2);
+        assert_eq!(linhas[0]["sucesso"], true);
+        assert_eq!(linhas[1]["tentativas"], 3);
+        assert_eq!(linhas[1]["sucesso"], false);
+    }
+}
@@ -0,0 +1,218 @@
+//! Registro das contas novas no sistema de impressão dos laboratórios, com a
+//! cota inicial do perfil do aluno. O PaperCut é usado pela API XML-RPC dele;
+//! outros sistemas, como o PyKota, por um comando.
+use reqwest::header::CONTENT_TYPE;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::process::ExitStatus;
+use thiserror::Error;
+use tokio::process::Command;
+
+/// O sistema de impressão e as cotas iniciais, configurados como
+///
+/// ```yaml
+/// tipo: papercut
+/// url: "https://impressao.ic.ufrj.br:9192"
+/// token: "..."
+/// cota_padrao: 50
+/// cotas:
+///   profcomp: 100
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoImpressao {
+    #[serde(flatten)]
+    pub sistema: SistemaImpressao,
+    /// A cota inicial de quem não tem um perfil em `cotas`.
+    #[serde(default)]
+    pub cota_padrao: f64,
+    /// A cota inicial de cada perfil, a OU do aluno (como `alunos` ou
+    /// `profcomp`).
+    #[serde(default)]
+    pub cotas: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum SistemaImpressao {
+    /// Cria o usuário pela API XML-RPC do PaperCut em `url`, autenticada pelo
+    /// `token` (o `auth.webservices.auth-token` do servidor).
+    Papercut { url: String, token: SecretString },
+    /// Roda o comando com os dados do usuário nas variáveis de ambiente
+    /// `ALUMNIC_UID`, `ALUMNIC_NOME`, `ALUMNIC_EMAIL`, `ALUMNIC_PERFIL` e
+    /// `ALUMNIC_COTA`, como um `pkusers --add --limitby balance`.
+    Comando { comando: Vec<String> },
+}
+
+#[derive(Debug, Error)]
+pub enum ErroImpressao {
+    #[error("o comando do sistema de impressão não foi configurado")]
+    SemComando,
+    #[error("não foi possível rodar o comando: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("o comando falhou com {0}")]
+    Falha(ExitStatus),
+    #[error("a chamada ao PaperCut falhou: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("o PaperCut recusou a chamada {metodo}: {resposta}")]
+    Recusada {
+        metodo: &'static str,
+        resposta: String,
+    },
+}
+
+/// Um usuário registrado no sistema de impressão.
+#[derive(Debug, Clone, Copy)]
+pub struct UsuarioImpressao<'a> {
+    pub uid: &'a str,
+    pub nome: &'a str,
+    pub email: &'a str,
+    /// O perfil, a OU do aluno.
+    pub perfil: &'a str,
+}
+
+/// Um parâmetro de uma chamada XML-RPC.
+#[derive(Clone, Copy)]
+enum Parametro<'a> {
+    Texto(&'a str),
+    Numero(f64),
+}
+
+/// Escapa os caracteres especiais do XML em `s`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::impressao::escapar_xml;
+/// assert_eq!(escapar_xml("D'Ávila & <Filhos>"), "D'Ávila &amp; &lt;Filhos&gt;");
+/// ```
+pub fn escapar_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// O corpo da chamada XML-RPC do `metodo` com os `parametros`.
+fn corpo_xmlrpc(metodo: &str, parametros: &[Parametro<'_>]) -> String {
+    let parametros: String = parametros
+        .iter()
+        .map(|p| match p {
+            Parametro::Texto(t) => format!(
+                "<param><value><string>{}</string></value></param>",
+                escapar_xml(t),
+            ),
+            Parametro::Numero(n) => {
+                format!("<param><value><double>{n}</double></value></param>")
+            },
+        })
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\"?><methodCall><methodName>{metodo}\
+         </methodName><params>{parametros}</params></methodCall>",
+    )
+}
+
+impl ConfiguracaoImpressao {
+    /// A cota inicial do `perfil`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::impressao::{ConfiguracaoImpressao, SistemaImpressao};
+    /// let cfg = ConfiguracaoImpressao {
+    ///     sistema: SistemaImpressao::Comando { comando: vec![] },
+    ///     cota_padrao: 50.0,
+    ///     cotas: [("profcomp".to_string(), 100.0)].into(),
+    /// };
+    /// assert_eq!(cfg.cota("profcomp"), 100.0);
+    /// assert_eq!(cfg.cota("alunos"), 50.0);
+    /// ```
+    pub fn cota(&self, perfil: &str) -> f64 {
+        self.cotas.get(perfil).copied().unwrap_or(self.cota_padrao)
+    }
+
+    /// Registra o `usuario` no sistema de impressão com a cota do perfil
+    /// dele.
+    pub async fn registrar(
+        &self,
+        usuario: &UsuarioImpressao<'_>,
+    ) -> Result<(), ErroImpressao> {
+        let cota = self.cota(usuario.perfil);
+
+        match &self.sistema {
+            SistemaImpressao::Papercut { url, token } => {
+                let token = Parametro::Texto(token.expose_secret());
+                let uid = Parametro::Texto(usuario.uid);
+                let propriedade = |nome, valor| {
+                    vec![
+                        token,
+                        uid,
+                        Parametro::Texto(nome),
+                        Parametro::Texto(valor),
+                    ]
+                };
+                let chamadas = [
+                    ("api.addNewUser", vec![token, uid]),
+                    (
+                        "api.setUserProperty",
+                        propriedade("full-name", usuario.nome),
+                    ),
+                    (
+                        "api.setUserProperty",
+                        propriedade("email", usuario.email),
+                    ),
+                    (
+                        "api.setUserAccountBalance",
+                        vec![
+                            token,
+                            uid,
+                            Parametro::Numero(cota),
+                            Parametro::Texto("Cota inicial do alumnic"),
+                        ],
+                    ),
+                ];
+
+                let cliente = reqwest::Client::new();
+                let endereco =
+                    format!("{}/rpc/api/xmlrpc", url.trim_end_matches('/'));
+                for (metodo, parametros) in chamadas {
+                    let resposta = cliente
+                        .post(&endereco)
+                        .header(CONTENT_TYPE, "text/xml")
+                        .body(corpo_xmlrpc(metodo, &parametros))
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .text()
+                        .await?;
+                    if resposta.contains("<fault>") {
+                        return Err(ErroImpressao::Recusada {
+                            metodo,
+                            resposta,
+                        });
+                    }
+                }
+            },
+            SistemaImpressao::Comando { comando } => {
+                let (programa, argumentos) =
+                    comando.split_first().ok_or(ErroImpressao::SemComando)?;
+
+                let status = Command::new(programa)
+                    .args(argumentos)
+                    .env("ALUMNIC_UID", usuario.uid)
+                    .env("ALUMNIC_NOME", usuario.nome)
+                    .env("ALUMNIC_EMAIL", usuario.email)
+                    .env("ALUMNIC_PERFIL", usuario.perfil)
+                    .env("ALUMNIC_COTA", cota.to_string())
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(ErroImpressao::Falha(status));
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
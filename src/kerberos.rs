@@ -0,0 +1,239 @@
+//! Criação do principal Kerberos de cada conta nova, com a mesma senha do
+//! LDAP, para a autenticação dos laboratórios. Só existe com a feature
+//! `kerberos`.
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use reqwest::header::CONTENT_TYPE;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::process::{ExitStatus, Stdio};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use zeroize::Zeroizing;
+
+/// O KDC onde os principais são criados, configurado como
+///
+/// ```yaml
+/// realm: "IC.UFRJ.BR"
+/// kdc:
+///   tipo: kadmin
+///   comando: ["kadmin", "-p", "alumnic/admin", "-k", "-t", "/etc/alumnic.keytab"]
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoKerberos {
+    /// O realm dos principais, como `IC.UFRJ.BR`.
+    pub realm: String,
+    pub kdc: Kdc,
+}
+
+/// Como o principal é criado no KDC.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum Kdc {
+    /// Roda o `comando` com `-q "addprinc <principal>"` no final, passando a
+    /// senha duas vezes pela entrada padrão, como o `addprinc` pede.
+    Kadmin { comando: Vec<String> },
+    /// Envia o `principal` e a `senha` em JSON por um `POST` para a `url`.
+    Http { url: String },
+}
+
+#[derive(Debug, Error)]
+pub enum ErroKerberos {
+    #[error("o kadmin não tem comando")]
+    SemComando,
+    #[error("não foi possível rodar o kadmin: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("o kadmin falhou com {0}")]
+    Falha(ExitStatus),
+    #[error("a chamada ao KDC falhou: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error(
+        "o principal não foi criado ({erro}) e a conta não pôde ser apagada \
+         do LDAP: {ldap}"
+    )]
+    ContaNaoDesfeita {
+        erro: Box<ErroKerberos>,
+        ldap: ErroLdap,
+    },
+}
+
+impl ConfiguracaoKerberos {
+    /// O principal do usuário `uid`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::kerberos::{ConfiguracaoKerberos, Kdc};
+    /// let cfg = ConfiguracaoKerberos {
+    ///     realm: "IC.UFRJ.BR".to_string(),
+    ///     kdc: Kdc::Http { url: "https://kdc.invalido".to_string() },
+    /// };
+    /// assert_eq!(cfg.principal("joaops"), "joaops@IC.UFRJ.BR");
+    /// ```
+    pub fn principal(&self, uid: &str) -> String {
+        format!("{uid}@{}", self.realm)
+    }
+
+    /// Cria o principal do usuário `uid` com a `senha`.
+    pub async fn criar_principal(
+        &self,
+        uid: &str,
+        senha: &SecretString,
+    ) -> Result<(), ErroKerberos> {
+        let principal = self.principal(uid);
+
+        match &self.kdc {
+            Kdc::Kadmin { comando } => {
+                let (programa, argumentos) =
+                    comando.split_first().ok_or(ErroKerberos::SemComando)?;
+
+                let mut processo = Command::new(programa)
+                    .args(argumentos)
+                    .arg("-q")
+                    .arg(format!("addprinc {principal}"))
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .spawn()?;
+
+                let senhas = Zeroizing::new(format!(
+                    "{0}\n{0}\n",
+                    senha.expose_secret()
+                ));
+                let mut entrada =
+                    processo.stdin.take().expect("stdin foi configurado");
+                // O kadmin pode sair sem ler a senha, e aí o status é que diz
+                // o que aconteceu
+                if let Err(e) = entrada.write_all(senhas.as_bytes()).await
+                    && e.kind() != ErrorKind::BrokenPipe
+                {
+                    return Err(e.into());
+                }
+                drop(entrada);
+
+                let status = processo.wait().await?;
+                if !status.success() {
+                    return Err(ErroKerberos::Falha(status));
+                }
+            },
+            Kdc::Http { url } => {
+                let corpo = Zeroizing::new(
+                    serde_json::json!({
+                        "principal": principal,
+                        "senha": senha.expose_secret(),
+                    })
+                    .to_string(),
+                );
+
+                reqwest::Client::new()
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(corpo.as_bytes().to_vec())
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Cria o principal do usuário `uid`, cuja entrada `dn` acabou de ser criada
+/// no LDAP. Se o KDC falhar, a entrada é apagada, para que a conta não fique
+/// pela metade e o aluno possa se cadastrar de novo.
+pub async fn criar_principal_ou_desfazer<F: FonteLdap>(
+    cfg: &ConfiguracaoKerberos,
+    uid: &str,
+    senha: &SecretString,
+    dn: &str,
+    ldap: &F,
+) -> Result<(), ErroKerberos> {
+    let Err(erro) = cfg.criar_principal(uid, senha).await else {
+        return Ok(());
+    };
+
+    let desfazer: Result<(), ErroLdap> = async {
+        let mut conexao = ldap.abrir().await?;
+        let r = conexao.remover(dn).await;
+        ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+
+    match desfazer {
+        Ok(()) => Err(erro),
+        Err(ldap) => Err(ErroKerberos::ContaNaoDesfeita {
+            erro: Box::new(erro),
+            ldap,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    const DN: &str = "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br";
+
+    /// Um kadmin falso que roda o `script`, com o `-q` e a consulta em `$0` e
+    /// `$1`.
+    fn kadmin(script: &str) -> ConfiguracaoKerberos {
+        ConfiguracaoKerberos {
+            realm: "IC.UFRJ.BR".to_string(),
+            kdc: Kdc::Kadmin {
+                comando: vec!["sh".into(), "-c".into(), script.into()],
+            },
+        }
+    }
+
+    async fn ldap() -> Arc<Mutex<DiretorioMemoria>> {
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(DN, vec![("uid", ["joaops"].into())])
+            .await
+            .unwrap();
+        Arc::new(Mutex::new(d))
+    }
+
+    #[tokio::test]
+    async fn kadmin_recebe_o_principal_e_a_senha() {
+        let cfg = kadmin(concat!(
+            r#"test "$1" = "addprinc joaops@IC.UFRJ.BR" && "#,
+            r#"read a && read b && test "$a" = Senha1234 && test "$a" = "$b""#,
+        ));
+        let ldap = ldap().await;
+
+        criar_principal_ou_desfazer(
+            &cfg,
+            "joaops",
+            &"Senha1234".to_string().into(),
+            DN,
+            &ldap,
+        )
+        .await
+        .unwrap();
+        assert!(ldap.lock().await.entrada(DN).is_some());
+    }
+
+    #[tokio::test]
+    async fn falha_no_kdc_desfaz_a_conta() {
+        let cfg = kadmin("exit 1");
+        let ldap = ldap().await;
+
+        let r = criar_principal_ou_desfazer(
+            &cfg,
+            "joaops",
+            &"Senha1234".to_string().into(),
+            DN,
+            &ldap,
+        )
+        .await;
+        assert!(matches!(r, Err(ErroKerberos::Falha(..))));
+        assert!(ldap.lock().await.entrada(DN).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! O alias de email institucional no formato `nome.sobrenome`, criado junto
+//! com a conta além do `mail` com o username. O alias é gravado num atributo
+//! à parte, por padrão o `mailAlternateAddress`, e só é dado se nenhuma outra
+//! conta já usa o endereço, como `mail` ou como alias; quando o
+//! `nome.sobrenome` está ocupado, as regras de desambiguação configuradas
+//! geram as alternativas.
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::EntradaUsuario;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::utils::nome::Nome;
+use ldap3::Scope;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// O alias de email das contas novas, configurado como
+///
+/// ```yaml
+/// usuario_novo:
+///   alias_email:
+///     atributo: "mailAlternateAddress"
+///     classe: "inetLocalMailRecipient"
+///     desambiguacao: [inicial_do_meio, numero]
+/// ```
+///
+/// Sem a `classe`, o atributo precisa ser aceito pelas classes que a conta já
+/// tem.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoAliasEmail {
+    /// O atributo com o alias.
+    pub atributo: String,
+    /// A classe acrescentada à conta para que ela aceite o atributo.
+    pub classe: Option<String>,
+    /// As regras tentadas, em ordem, quando o `nome.sobrenome` já está
+    /// ocupado. Sem nenhuma, a conta fica sem alias.
+    pub desambiguacao: Vec<Desambiguacao>,
+}
+
+impl Default for ConfiguracaoAliasEmail {
+    fn default() -> Self {
+        Self {
+            atributo: "mailAlternateAddress".to_string(),
+            classe: Some("inetLocalMailRecipient".to_string()),
+            desambiguacao: vec![
+                Desambiguacao::InicialDoMeio,
+                Desambiguacao::Numero,
+            ],
+        }
+    }
+}
+
+/// Uma regra de desambiguação do alias.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Desambiguacao {
+    /// A inicial de um dos nomes do meio, como `claudio.l.cavalcante`.
+    InicialDoMeio,
+    /// Um dos nomes do meio inteiro, como `claudio.lima.cavalcante`.
+    SobrenomeDoMeio,
+    /// Um número de 2 a 9 no fim, como `claudio.cavalcante2`.
+    Numero,
+}
+
+/// Os aliases possíveis para o `nome`, sem o domínio, na ordem em que são
+/// tentados. Um nome com uma parte só não tem alias.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::alias_email::{Desambiguacao, candidatos};
+/// let nome = "Cláudio de Lima Cavalcante".parse().unwrap();
+/// let regras = [Desambiguacao::InicialDoMeio, Desambiguacao::Numero];
+/// let aliases = candidatos(&nome, &regras);
+///
+/// assert_eq!(aliases[..3], [
+///     "claudio.cavalcante",
+///     "claudio.l.cavalcante",
+///     "claudio.cavalcante2",
+/// ]);
+/// assert_eq!(aliases.last().unwrap(), "claudio.cavalcante9");
+/// assert!(candidatos(&"Cláudio".parse().unwrap(), &regras).is_empty());
+/// ```
+pub fn candidatos(nome: &Nome, desambiguacao: &[Desambiguacao]) -> Vec<String> {
+    let partes = nome.partes();
+    let [primeiro, meio @ .., ultimo] = &partes[..] else {
+        return vec![];
+    };
+
+    let mut candidatos = vec![format!("{primeiro}.{ultimo}")];
+    for regra in desambiguacao {
+        match regra {
+            Desambiguacao::InicialDoMeio => {
+                candidatos.extend(
+                    meio.iter()
+                        .map(|m| format!("{primeiro}.{}.{ultimo}", &m[..1])),
+                );
+            },
+            Desambiguacao::SobrenomeDoMeio => {
+                candidatos.extend(
+                    meio.iter().map(|m| format!("{primeiro}.{m}.{ultimo}")),
+                );
+            },
+            Desambiguacao::Numero => {
+                candidatos
+                    .extend((2..=9).map(|n| format!("{primeiro}.{ultimo}{n}")));
+            },
+        }
+    }
+    // Duas regras podem gerar o mesmo alias, como as duas para um nome do
+    // meio de uma letra
+    let mut vistos = HashSet::new();
+    candidatos.retain(|c| vistos.insert(c.clone()));
+    candidatos
+}
+
+/// Se o `endereco` não é usado por nenhuma conta, nem como `mail` nem como
+/// alias.
+async fn livre<D: DiretorioLdap>(
+    endereco: &str,
+    cfg: &ConfiguracaoAliasEmail,
+    ldap: &mut D,
+) -> Result<bool, ErroLdap> {
+    let filtro = filtros::ou([
+        filtros::igual("mail", endereco),
+        filtros::igual(&cfg.atributo, endereco),
+    ]);
+    let usos = ldap
+        .buscar(BASE_CONTAS, Scope::Subtree, &filtro, vec!["uid"])
+        .await?;
+    Ok(usos.is_empty())
+}
+
+/// O primeiro alias livre para o `nome` no `dominio`, ou `None` se
+/// todos os candidatos estão ocupados. Roda na seção crítica do cadastro,
+/// para que dois cadastros não recebam o mesmo alias.
+pub async fn escolher_alias<D: DiretorioLdap>(
+    nome: &str,
+    dominio: &str,
+    cfg: &ConfiguracaoAliasEmail,
+    ldap: &mut D,
+) -> Result<Option<String>, ErroLdap> {
+    let Ok(nome) = nome.parse::<Nome>() else {
+        return Ok(None);
+    };
+    for candidato in candidatos(&nome, &cfg.desambiguacao) {
+        let endereco = format!("{candidato}@{dominio}");
+        if livre(&endereco, cfg, ldap).await? {
+            return Ok(Some(endereco));
+        }
+    }
+    Ok(None)
+}
+
+impl ConfiguracaoAliasEmail {
+    /// Acrescenta o `alias` à `entrada`, com a classe que aceita o atributo.
+    pub fn aplicar(&self, entrada: &mut EntradaUsuario, alias: String) {
+        if let Some(classe) = &self.classe
+            && let Some((_, classes)) = entrada
+                .atributos
+                .iter_mut()
+                .find(|(a, _)| a == "objectClass")
+            && !classes.contains(classe)
+        {
+            classes.push(classe.clone());
+        }
+        entrada.atributos.push((self.atributo.clone(), vec![alias]));
+    }
+}
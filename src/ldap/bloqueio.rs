@@ -0,0 +1,340 @@
+//! Módulo com as alterações feitas no LDAP para bloquear a conta de um aluno
+//! que deixou de ter vínculo com o instituto.
+use crate::configuracao::ConfiguracaoRenovacao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::{Conta, EstadoConta, buscar_contas};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::ldap::renovar::valores_da_renovacao;
+use ldap3::{Mod, Scope};
+
+const SEGUNDOS_POR_DIA: i64 = 24 * 60 * 60;
+
+/// Prefixo colocado no `userPassword` de uma conta bloqueada. Com ele, o hash
+/// deixa de ser reconhecido e nenhuma senha é aceita, mas o hash original
+/// continua guardado caso a conta seja reativada.
+pub const PREFIXO_BLOQUEIO: &str = "!";
+
+/// Adiciona a `flag` às flags do Samba, no formato `[UX]`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::bloqueio::com_flag;
+/// assert_eq!(com_flag("[UX]", 'D'), "[DUX]");
+/// assert_eq!(com_flag("[DUX]", 'D'), "[DUX]");
+/// assert_eq!(com_flag("[U          ]", 'D'), "[DU]");
+/// assert_eq!(com_flag("", 'D'), "[D]");
+/// ```
+pub fn com_flag(flags: &str, flag: char) -> String {
+    let mut flags: Vec<char> =
+        flags.chars().filter(|c| c.is_ascii_alphabetic()).collect();
+    if !flags.contains(&flag) {
+        flags.push(flag);
+    }
+    flags.sort_unstable();
+
+    format!("[{}]", flags.into_iter().collect::<String>())
+}
+
+/// Remove a `flag` das flags do Samba, no formato `[UX]`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::bloqueio::sem_flag;
+/// assert_eq!(sem_flag("[DUX]", 'D'), "[UX]");
+/// assert_eq!(sem_flag("[UX]", 'D'), "[UX]");
+/// ```
+pub fn sem_flag(flags: &str, flag: char) -> String {
+    let flags: String = flags
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic() && *c != flag)
+        .collect();
+
+    format!("[{flags}]")
+}
+
+/// Lê a senha e as flags do Samba atuais da conta com o `dn`.
+async fn senha_e_flags<D: DiretorioLdap>(
+    dn: &str,
+    ldap: &mut D,
+) -> Result<(String, String), ErroLdap> {
+    let entrada = ldap
+        .buscar(
+            dn,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["userPassword", "sambaAcctFlags"],
+        )
+        .await?
+        .into_iter()
+        .next();
+    // Se a entrada sumiu, a modificação feita depois falha com o erro do LDAP
+    let primeiro = |atributo: &str| {
+        entrada
+            .as_ref()
+            .and_then(|e| e.attrs.get(atributo))
+            .and_then(|v| v.first())
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    Ok((primeiro("userPassword"), primeiro("sambaAcctFlags")))
+}
+
+/// Bloqueia a `conta` no dia `hoje` (contado desde 01/01/1970) e marca a sua
+/// remoção para o dia `remocao`.
+///
+/// A senha é invalidada com o [`PREFIXO_BLOQUEIO`], a conta é desativada no
+/// Samba (flag `D`), o login nos laboratórios expira hoje e a conta fica
+/// suspensa. Todas as alterações são feitas em uma única operação.
+pub async fn bloquear_conta<D: DiretorioLdap>(
+    conta: &Conta,
+    hoje: i64,
+    remocao: i64,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let (senha, flags) = senha_e_flags(&conta.dn, ldap).await?;
+    let senha = if senha.starts_with(PREFIXO_BLOQUEIO) {
+        senha
+    } else {
+        format!("{PREFIXO_BLOQUEIO}{senha}")
+    };
+    let flags = com_flag(&flags, 'D');
+    let (hoje_s, kickoff_s, remocao_s) = (
+        hoje.to_string(),
+        (hoje * SEGUNDOS_POR_DIA).to_string(),
+        remocao.to_string(),
+    );
+
+    ldap.modificar(
+        &conta.dn,
+        vec![
+            Mod::Replace("userPassword", [senha.as_str()].into()),
+            Mod::Replace("sambaAcctFlags", [flags.as_str()].into()),
+            Mod::Replace("shadowExpire", [hoje_s.as_str()].into()),
+            Mod::Replace("sambaKickoffTime", [kickoff_s.as_str()].into()),
+            Mod::Replace("estadoConta", [EstadoConta::Suspensa.valor()].into()),
+            Mod::Replace("dataRemocao", [remocao_s.as_str()].into()),
+        ],
+    )
+    .await
+}
+
+/// Desfaz o [bloqueio](bloquear_conta) da `conta` e a renova a partir do dia
+/// `hoje`, retornando o novo dia limite para a próxima renovação, como em
+/// [`renovar_conta`](crate::ldap::renovar::renovar_conta). A senha volta a
+/// ser a que o aluno usava antes do bloqueio.
+pub async fn desbloquear_conta<D: DiretorioLdap>(
+    conta: &Conta,
+    prazos: &ConfiguracaoRenovacao,
+    hoje: i64,
+    ldap: &mut D,
+) -> Result<i64, ErroLdap> {
+    let (senha, flags) = senha_e_flags(&conta.dn, ldap).await?;
+    let senha = senha.strip_prefix(PREFIXO_BLOQUEIO).unwrap_or(&senha);
+    let flags = sem_flag(&flags, 'D');
+    let (renovacao, valores) = valores_da_renovacao(prazos, hoje);
+
+    let mut mods: Vec<_> = valores
+        .iter()
+        .map(|(atributo, v)| Mod::Replace(*atributo, [v.as_str()].into()))
+        .collect();
+    mods.extend([
+        Mod::Replace("userPassword", [senha].into()),
+        Mod::Replace("sambaAcctFlags", [flags.as_str()].into()),
+        Mod::Replace("dataRemocao", [].into()),
+    ]);
+    ldap.modificar(&conta.dn, mods).await?;
+
+    Ok(renovacao)
+}
+
+/// Suspende as contas em carência cuja carência acabou antes de `hoje`,
+/// marcando a remoção para o dia `remocao`, e retorna as contas alteradas.
+pub async fn suspender_carencias_vencidas<D: DiretorioLdap>(
+    hoje: i64,
+    carencia_dias: i64,
+    remocao: i64,
+    ldap: &mut D,
+) -> Result<Vec<Conta>, ErroLdap> {
+    let filtro = filtros::e([
+        filtros::igual("estadoConta", EstadoConta::Carencia.valor()),
+        filtros::nao(&filtros::maior_ou_igual(
+            "dataRenovacao",
+            &(hoje - carencia_dias).to_string(),
+        )),
+    ]);
+
+    let mut alteradas = vec![];
+    for mut conta in buscar_contas(&filtro, ldap).await? {
+        bloquear_conta(&conta, hoje, remocao, ldap).await?;
+
+        conta.estado = EstadoConta::Suspensa;
+        conta.data_remocao = Some(remocao);
+        alteradas.push(conta);
+    }
+
+    Ok(alteradas)
+}
+
+/// Busca as contas suspensas cuja `dataRemocao` já chegou em `hoje`.
+pub async fn contas_a_remover<D: DiretorioLdap>(
+    hoje: i64,
+    ldap: &mut D,
+) -> Result<Vec<Conta>, ErroLdap> {
+    let filtro = filtros::e([
+        filtros::igual("estadoConta", EstadoConta::Suspensa.valor()),
+        filtros::menor_ou_igual("dataRemocao", &hoje.to_string()),
+    ]);
+
+    buscar_contas(&filtro, ldap).await
+}
+
+/// Marca a `conta` como removida. A entrada continua no LDAP, bloqueada, para
+/// que o DRE e o uid não sejam reaproveitados.
+pub async fn marcar_removida<D: DiretorioLdap>(
+    conta: &mut Conta,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    ldap.modificar(
+        &conta.dn,
+        vec![Mod::Replace(
+            "estadoConta",
+            [EstadoConta::Removida.valor()].into(),
+        )],
+    )
+    .await?;
+
+    conta.estado = EstadoConta::Removida;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::conta::buscar_conta_por_dre;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    #[tokio::test]
+    async fn bloqueio_invalida_a_senha_e_marca_a_remocao() {
+        let dn = "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br";
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(
+            dn,
+            vec![
+                ("objectClass", ["dcc", "dccAluno"].into()),
+                ("uid", ["joaops"].into()),
+                ("dccDRE", ["123456789"].into()),
+                ("userPassword", ["{SSHA}abc"].into()),
+                ("sambaAcctFlags", ["[UX]"].into()),
+                ("shadowExpire", ["-1"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let conta = buscar_conta_por_dre("123456789", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        bloquear_conta(&conta, 20000, 20090, &mut d).await.unwrap();
+
+        let e = d.entrada(dn).unwrap();
+        assert_eq!(e.attrs["userPassword"], vec!["!{SSHA}abc"]);
+        assert_eq!(e.attrs["sambaAcctFlags"], vec!["[DUX]"]);
+        assert_eq!(e.attrs["shadowExpire"], vec!["20000"]);
+        assert_eq!(e.attrs["sambaKickoffTime"], vec!["1728000000"]);
+        assert_eq!(e.attrs["estadoConta"], vec!["suspensa"]);
+        assert_eq!(e.attrs["dataRemocao"], vec!["20090"]);
+
+        // Bloquear de novo não acumula prefixos
+        bloquear_conta(&conta, 20000, 20090, &mut d).await.unwrap();
+        let e = d.entrada(dn).unwrap();
+        assert_eq!(e.attrs["userPassword"], vec!["!{SSHA}abc"]);
+    }
+
+    #[tokio::test]
+    async fn desbloqueio_restaura_a_conta() {
+        let dn = "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br";
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(
+            dn,
+            vec![
+                ("objectClass", ["dcc", "dccAluno"].into()),
+                ("uid", ["joaops"].into()),
+                ("dccDRE", ["123456789"].into()),
+                ("userPassword", ["{SSHA}abc"].into()),
+                ("sambaAcctFlags", ["[UX]"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        let conta = buscar_conta_por_dre("123456789", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        bloquear_conta(&conta, 20000, 20090, &mut d).await.unwrap();
+
+        let prazos = ConfiguracaoRenovacao::default();
+        let renovacao = desbloquear_conta(&conta, &prazos, 20010, &mut d)
+            .await
+            .unwrap();
+        assert_eq!(renovacao, 20375);
+
+        let e = d.entrada(dn).unwrap();
+        assert_eq!(e.attrs["userPassword"], vec!["{SSHA}abc"]);
+        assert_eq!(e.attrs["sambaAcctFlags"], vec!["[UX]"]);
+        assert_eq!(e.attrs["shadowExpire"], vec!["20435"]);
+        assert_eq!(e.attrs["estadoConta"], vec!["ativa"]);
+        assert!(!e.attrs.contains_key("dataRemocao"));
+    }
+
+    #[tokio::test]
+    async fn carencia_vencida_suspende_e_depois_remove() {
+        let mut d = DiretorioMemoria::default();
+        for (uid, dre, data_renovacao) in [
+            ("vencida", "111111111", "19900"),
+            ("no_prazo", "222222222", "19950"),
+        ] {
+            d.adicionar(
+                &format!("uid={uid},ou=alunos,dc=dcc,dc=ufrj,dc=br"),
+                vec![
+                    ("objectClass", ["dcc", "dccAluno"].into()),
+                    ("uid", [uid].into()),
+                    ("dccDRE", [dre].into()),
+                    ("userPassword", ["{SSHA}abc"].into()),
+                    ("estadoConta", ["carencia"].into()),
+                    ("dataRenovacao", [data_renovacao].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+
+        let suspensas = suspender_carencias_vencidas(20000, 60, 20090, &mut d)
+            .await
+            .unwrap();
+        assert_eq!(
+            suspensas.iter().map(|c| c.uid.as_str()).collect::<Vec<_>>(),
+            vec!["vencida"],
+        );
+
+        // Antes da data de remoção, nada é removido
+        assert!(contas_a_remover(20089, &mut d).await.unwrap().is_empty());
+
+        let mut removidas = contas_a_remover(20090, &mut d).await.unwrap();
+        assert_eq!(removidas.len(), 1);
+        marcar_removida(&mut removidas[0], &mut d).await.unwrap();
+        let conta = buscar_conta_por_dre("111111111", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conta.estado, EstadoConta::Removida);
+        assert_eq!(
+            d.entrada(&conta.dn).unwrap().attrs["userPassword"],
+            vec!["!{SSHA}abc"],
+        );
+    }
+}
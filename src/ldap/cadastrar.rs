@@ -1,179 +1,493 @@
 //! Módulo com funções relacionadas ao cadastro de um aluno no sistema já tendo
 //! o username.
-use crate::cadastro_aluno::DadosParaCadastro;
-use crate::configuracao::ConfiguracaoUsuario;
+use crate::cadastro_aluno::DadosValidados;
+use crate::configuracao::{
+    ConfiguracaoCamposAcademicos, ConfiguracaoRenovacao, ConfiguracaoUsuario,
+};
+use crate::foto::{ATRIBUTO_FOTO, Foto};
 use crate::ldap::ErroLdap;
-use crate::ldap::utils::rodar_ldap;
-use crate::utils::hashes::{hash_nt, hash_ssha};
-use chrono::Utc;
+use crate::ldap::alias_email::escolher_alias;
+use crate::ldap::caixa_email::EstadoCaixa;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::contadores::ler_contadores;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::ldap::renovar::{SEGUNDOS_POR_DIA, valores_da_renovacao};
+use crate::ldap::uids_liberados::{
+    UidLiberado, devolver_liberado, donos_do_uid_number, reservar_liberado,
+};
+use crate::utils::hashes::{hash_nt, hash_ssha_with_salt};
+use crate::utils::ingresso::periodo_do_dre;
+use chrono::{DateTime, Utc};
 use deunicode::deunicode;
-use ldap3::{Ldap, Mod, Scope, SearchEntry, dn_escape};
+use ldap3::{Mod, Scope};
+use rand::Rng;
 use secrecy::ExposeSecret;
+use zeroize::Zeroize;
 
 /// Cadastra um usuário com os dados fornecidos, a partir da configuração base
-/// fornecida.
+/// fornecida, retornando o DN da entrada criada.
 ///
 /// # Errors
 ///
 /// - conflito de username, ou seja, já existir um aluno com o mesmo username;
-/// - erro de conexão do LDAP; ou
-/// - [DadosParaCadastro] não sanitizados.
-pub async fn cadastrar_usuario(
+/// - erro de conexão do LDAP.
+pub async fn cadastrar_usuario<F: FonteLdap>(
     username: String,
-    dados: &DadosParaCadastro,
+    dados: &DadosValidados,
     cfg: &ConfiguracaoUsuario,
+    prazos: &ConfiguracaoRenovacao,
     ou: &str,
-    ldap_url: &str,
-    bind_dn: &str,
-    bind_pw: &str,
+    fonte: &F,
+) -> Result<String, ErroLdap> {
+    let mut ldap = fonte.abrir().await?;
+    let r =
+        cadastrar_usuario_em(username, dados, cfg, prazos, ou, &mut ldap).await;
+    fonte.fechar(ldap).await?;
+    r
+}
+
+/// Faz o mesmo que [cadastrar_usuario], mas em um [DiretorioLdap] já
+/// conectado.
+pub async fn cadastrar_usuario_em<D: DiretorioLdap>(
+    username: String,
+    dados: &DadosValidados,
+    cfg: &ConfiguracaoUsuario,
+    prazos: &ConfiguracaoRenovacao,
+    ou: &str,
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    let agora = Utc::now();
+    let hoje = agora.timestamp().div_euclid(SEGUNDOS_POR_DIA);
+    let ids = alocar_ids(cfg, hoje, ldap).await?;
+
+    let mut salt = [0u8; 4];
+    rand::rng().fill(&mut salt);
+
+    let mut entrada = montar_entrada(
+        &username,
+        dados,
+        (cfg, prazos),
+        ou,
+        (&ids.uid_number, &ids.rid),
+        agora,
+        &salt,
+    );
+
+    salt.zeroize();
+
+    let criacao = async {
+        if let Some(alias) = &cfg.alias_email
+            && let Some(endereco) = escolher_alias(
+                dados.nome(),
+                cfg.dominios_email.dominio(ou),
+                alias,
+                ldap,
+            )
+            .await?
+        {
+            alias.aplicar(&mut entrada, endereco);
+        }
+        adicionar_entrada(&entrada, ldap).await
+    }
+    .await;
+    ids.devolver_se_falhou(criacao, ldap).await?;
+
+    // A foto é binária e vai depois da entrada criada; sem ela, a conta
+    // continua valendo
+    if let Some(jpeg) = dados.foto().and_then(Foto::jpeg)
+        && let Err(e) =
+            ldap.gravar_binario(&entrada.dn, ATRIBUTO_FOTO, &jpeg).await
+    {
+        eprintln!("Não foi possível gravar a foto de {username:?}: {e}");
+    }
+    Ok(entrada.dn)
+}
+
+/// Adiciona a `entrada` montada ao diretório, conferindo logo antes que o
+/// uidNumber dela ainda não é de nenhuma conta, o que acontece quando o
+/// contador do `sambaDomain` está dessincronizado.
+pub(crate) async fn adicionar_entrada<D: DiretorioLdap>(
+    entrada: &EntradaUsuario,
+    ldap: &mut D,
 ) -> Result<(), ErroLdap> {
-    async fn cadastrar(
-        username: String,
-        dados: &DadosParaCadastro,
-        cfg: &ConfiguracaoUsuario,
-        ou: &str,
-        ldap: &mut Ldap,
-    ) -> Result<(), ErroLdap> {
-        let (samba_uid, samba_rid) = samba_ids(ldap).await?;
-
-        let dn = format!(
-            "uid={},ou={},ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
-            dn_escape(&username), ou,
-        );
+    let uid_number = entrada
+        .atributos
+        .iter()
+        .find(|(atributo, _)| atributo == "uidNumber")
+        .and_then(|(_, valores)| valores.first());
+    if let Some(uid_number) = uid_number
+        && let Some(dono) = donos_do_uid_number(uid_number, ldap)
+            .await?
+            .into_iter()
+            .next()
+    {
+        return Err(ErroLdap::UidNumberEmUso {
+            uid_number: uid_number.clone(),
+            dono,
+        });
+    }
 
-        let hash_nt = hash_nt(&dados.senha);
-        let hash_ssha = hash_ssha(&dados.senha);
-
-        // Hoje no tempo UNIX
-        let samba_today = Utc::now().timestamp();
-        // + 10 anos
-        let samba_kickoff = samba_today + (3600 * 24 * 60 * 60);
-        // De segundos para dias
-        let shadow_today = samba_today / (24 * 60 * 60);
-        // + 10 anos
-        let shadow_renovacao = shadow_today + 3600;
-        // Converte tudo para String
-        let (samba_today, samba_kickoff, shadow_today, shadow_renovacao) = (
-            samba_today.to_string(),
-            samba_kickoff.to_string(),
-            shadow_today.to_string(),
-            shadow_renovacao.to_string(),
-        );
+    ldap.adicionar(
+        &entrada.dn,
+        entrada
+            .atributos
+            .iter()
+            .map(|(atributo, valores)| {
+                (
+                    atributo.as_str(),
+                    valores.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect(),
+    )
+    .await
+}
 
-        ldap.add(
-            &dn,
+/// Uma entrada LDAP de um usuário novo, antes de ser adicionada ao diretório.
+#[derive(Debug, Clone)]
+pub struct EntradaUsuario {
+    pub dn: String,
+    /// Os atributos, na ordem em que são enviados ao LDAP.
+    pub atributos: Vec<(String, Vec<String>)>,
+}
+
+/// Os atributos com o curso e o período de ingresso de uma conta do `curso`
+/// com o `dre`, com os nomes configurados em `cfg`. O período fica de fora se
+/// o DRE não seguir o padrão da UFRJ.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::configuracao::ConfiguracaoCamposAcademicos;
+/// # use alumnic::ldap::cadastrar::campos_academicos;
+/// let cfg = ConfiguracaoCamposAcademicos::default();
+/// assert_eq!(
+///     campos_academicos(&cfg, Some("Ciência da Computação"), "122134567"),
+///     [
+///         ("dccCurso", "Ciência da Computação".to_string()),
+///         ("dccIngresso", "2022.1".to_string()),
+///     ],
+/// );
+/// assert_eq!(campos_academicos(&cfg, None, "123456789"), []);
+/// ```
+pub fn campos_academicos<'a>(
+    cfg: &'a ConfiguracaoCamposAcademicos,
+    curso: Option<&str>,
+    dre: &str,
+) -> Vec<(&'a str, String)> {
+    let mut campos = vec![];
+    if let Some(curso) = curso {
+        campos.push((cfg.atributo_curso.as_str(), curso.to_string()));
+    }
+    if let Some(periodo) = periodo_do_dre(dre) {
+        campos.push((cfg.atributo_ingresso.as_str(), periodo.to_string()));
+    }
+    campos
+}
+
+/// Base das OUs dos alunos, como a `ou=alunos` e a `ou=profcomp`.
+pub const BASE_ACADEMICOS: &str =
+    "ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// Tamanho máximo do `gecos`, em caracteres.
+pub const MAXIMO_GECOS: usize = 128;
+
+/// O `gecos` do nome completo `nome`: sem acentos, pelo [deunicode], e sem as
+/// vírgulas, que separam os campos do GECOS, os dois-pontos, que separam os
+/// campos do `/etc/passwd`, e os caracteres de controle, trocados por
+/// espaços. O resultado tem no máximo [MAXIMO_GECOS] caracteres.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::cadastrar::{MAXIMO_GECOS, normalizar_gecos};
+/// assert_eq!(
+///     normalizar_gecos("Cláudio de Lima Cavalcante"),
+///     "Claudio de Lima Cavalcante",
+/// );
+/// assert_eq!(normalizar_gecos("João Pedro,Sala 1,,"), "Joao Pedro Sala 1");
+/// assert_eq!(normalizar_gecos("Ana:0:0:root\n"), "Ana 0 0 root");
+/// assert_eq!(normalizar_gecos(&"Ana ".repeat(100)).len(), MAXIMO_GECOS - 1);
+/// ```
+pub fn normalizar_gecos(nome: &str) -> String {
+    let limpo: String = deunicode(nome)
+        .chars()
+        .map(|c| match c {
+            ',' | ':' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let mut gecos = limpo.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    // Depois do deunicode, cada caractere é um byte
+    gecos.truncate(MAXIMO_GECOS);
+    gecos.truncate(gecos.trim_end().len());
+    gecos
+}
+
+/// O home directory do usuário `username` no servidor de arquivos.
+pub fn home_directory(username: &str) -> String {
+    format!("/usuarios/alunos/{username}")
+}
+
+/// Monta a entrada LDAP do usuário `username` na OU `ou`, com o uidNumber e o
+/// RID do Samba `ids_samba` já reservados, a partir da configuração base
+/// `cfg` e dos `prazos` da conta e da senha. O horário `agora` e o `salt` da
+/// hash SSHA são recebidos em vez de gerados aqui para que a entrada seja
+/// determinística nos testes.
+pub fn montar_entrada(
+    username: &str,
+    dados: &DadosValidados,
+    (cfg, prazos): (&ConfiguracaoUsuario, &ConfiguracaoRenovacao),
+    ou: &str,
+    (samba_uid, samba_rid): (&str, &str),
+    agora: DateTime<Utc>,
+    salt: &[u8; 4],
+) -> EntradaUsuario {
+    let dn =
+        filtros::dn("uid", username, &filtros::dn("ou", ou, BASE_ACADEMICOS));
+
+    let hash_nt = hash_nt(dados.senha());
+    let hash_ssha = hash_ssha_with_salt(dados.senha(), salt);
+
+    // Hoje no tempo UNIX
+    let samba_today = agora.timestamp();
+    // De segundos para dias
+    let shadow_today = samba_today.div_euclid(SEGUNDOS_POR_DIA);
+    // A senha precisa ser trocada depois da validade_senha
+    let samba_pwd_must_change =
+        samba_today + prazos.validade_senha * SEGUNDOS_POR_DIA;
+    // A conta nova vence como se tivesse sido renovada hoje: a
+    // dataRenovacao, o shadowExpire e o sambaKickoffTime
+    let (_, renovacao) = valores_da_renovacao(prazos, shadow_today);
+
+    let um = |valor: &str| vec![valor.to_string()];
+
+    let mut classes: Vec<_> = [
+        "dcc",
+        "dccAluno",
+        "sambaSamAccount",
+        "shadowAccount",
+        "posixAccount",
+        "inetOrgPerson",
+    ]
+    .map(String::from)
+    .into();
+    if dados.chave_ssh().is_some() {
+        classes.push("ldapPublicKey".to_string());
+    }
+
+    let mut atributos = vec![
+        ("objectClass", classes),
+        ("dccDRE", um(dados.dre())),
+        ("gidNumber", um(&cfg.gid_number)),
+        ("homeDirectory", vec![home_directory(username)]),
+        (
+            "sambaSID",
+            vec![format!("{}{samba_rid}", cfg.samba_sid_prefix)],
+        ),
+        ("uid", um(username)),
+        ("mail", vec![cfg.dominios_email.email(username, ou)]),
+        ("uidNumber", um(samba_uid)),
+        ("gecos", vec![normalizar_gecos(dados.nome())]),
+        ("cn", um(dados.nome().split_whitespace().next().unwrap())),
+        (
+            "sn",
             vec![
-                (
-                    "objectClass",
-                    [
-                        "dcc",
-                        "dccAluno",
-                        "sambaSamAccount",
-                        "shadowAccount",
-                        "posixAccount",
-                        "inetOrgPerson",
-                    ]
-                    .into(),
-                ),
-                ("dccDRE", [dados.dre.as_str()].into()),
-                ("gidNumber", [cfg.gid_number.as_str()].into()),
-                (
-                    "homeDirectory",
-                    [format!("/usuarios/alunos/{username}").as_str()].into(),
-                ),
-                (
-                    "sambaSID",
-                    [format!("{}{samba_rid}", cfg.samba_sid_prefix).as_str()]
-                        .into(),
-                ),
-                ("uid", [username.as_str()].into()),
-                ("mail", [format!(
-                    "{}@{}.ufrj.br", username, if ou == "profcomp" { "profcomp.ic" } else { "ic" }
-                ).as_str()].into()),
-                ("uidNumber", [samba_uid.as_str()].into()),
-                ("gecos", [deunicode(&dados.nome).as_str()].into()),
-                ("cn", [dados.nome.split_whitespace().next().unwrap()].into()),
-                (
-                    "sn",
-                    [dados
-                        .nome
-                        .split_whitespace()
-                        .skip(1)
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                        .as_str()]
-                    .into(),
-                ),
-                ("loginShell", ["/bin/bash"].into()),
-                ("emailExterno", [dados.email.as_str()].into()),
-                /* SAMBA - relacionado ao samba, desativado no momento */
-                ("sambaAcctFlags", [cfg.samba_acct_flags.as_str()].into()),
-                ("sambaKickoffTime", [samba_kickoff.as_str()].into()),
-                ("sambaLMPassword", [cfg.samba_lm_password.as_str()].into()),
-                ("sambaNTPassword", [hash_nt.expose_secret()].into()),
-                (
-                    "sambaPasswordHistory",
-                    [cfg.samba_password_history.as_str()].into(),
-                ),
-                (
-                    "sambaPrimaryGroupSID",
-                    [cfg.samba_primary_group_sid.as_str()].into(),
-                ),
-                ("sambaPwdLastSet", [samba_today.as_str()].into()),
-                ("sambaPwdMustChange", [samba_kickoff.as_str()].into()),
-                /* SHADOW - relacionado ao login nos laboratórios */
-                // O acesso aos laboratórios não expira
-                ("shadowExpire", ["-1"].into()),
-                // Parece ser sempre -1
-                ("shadowFlag", ["-1"].into()),
-                // Desabilita bloqueio da conta após a senha expirar
-                ("shadowInactive", ["-1"].into()),
-                // Data da última troca de senha
-                ("shadowLastChange", [shadow_today.as_str()].into()),
-                // Vencimento das senhas após 10 anos
-                ("shadowMax", ["3600"].into()),
-                // A senha pode ser trocada a qualquer momento.
-                ("shadowMin", ["0"].into()),
-                // Quanto tempo antes da expiração da senha alertar o usuário
-                ("shadowWarning", ["14"].into()),
-                ("telephoneNumber", [dados.telefone.as_str()].into()),
-                ("userPassword", [hash_ssha.expose_secret()].into()),
-                ("cota", [cfg.cota.as_str()].into()),
-                ("monitor", ["0"].into()),
-                ("dataCriacao", [shadow_today.as_str()].into()),
-                ("dataRenovacao", [shadow_renovacao.as_str()].into()),
+                dados
+                    .nome()
+                    .split_whitespace()
+                    .skip(1)
+                    .collect::<Vec<_>>()
+                    .join(" "),
             ],
-        )
-        .await?
-        .success()?;
+        ),
+        ("loginShell", um("/bin/bash")),
+        ("emailExterno", um(dados.email())),
+        /* SAMBA - relacionado ao samba, desativado no momento */
+        ("sambaAcctFlags", um(&cfg.samba_acct_flags)),
+        ("sambaLMPassword", um(&cfg.samba_lm_password)),
+        ("sambaNTPassword", um(hash_nt.expose_secret())),
+        ("sambaPasswordHistory", um(&cfg.samba_password_history)),
+        ("sambaPrimaryGroupSID", um(&cfg.samba_primary_group_sid)),
+        ("sambaPwdLastSet", vec![samba_today.to_string()]),
+        (
+            "sambaPwdMustChange",
+            vec![samba_pwd_must_change.to_string()],
+        ),
+        /* SHADOW - relacionado ao login nos laboratórios */
+        // Parece ser sempre -1
+        ("shadowFlag", um("-1")),
+        // Desabilita bloqueio da conta após a senha expirar
+        ("shadowInactive", um("-1")),
+        // Data da última troca de senha
+        ("shadowLastChange", vec![shadow_today.to_string()]),
+        // Vencimento das senhas
+        ("shadowMax", vec![prazos.validade_senha.to_string()]),
+        // A senha pode ser trocada a qualquer momento.
+        ("shadowMin", um("0")),
+        // Quanto tempo antes da expiração da senha alertar o usuário
+        ("shadowWarning", vec![prazos.aviso_expiracao.to_string()]),
+        (
+            "telephoneNumber",
+            vec![cfg.telefone.telephone_number(dados.telefone())],
+        ),
+        ("userPassword", um(hash_ssha.expose_secret())),
+        ("cota", um(&cfg.cota)),
+        ("monitor", um("0")),
+        ("dataCriacao", vec![shadow_today.to_string()]),
+    ];
+    if let Some((atributo, telefone)) =
+        cfg.telefone.nacional_a_parte(dados.telefone())
+    {
+        atributos.push((atributo, vec![telefone]));
+    }
+    if let Some(chave) = dados.chave_ssh() {
+        atributos.push(("sshPublicKey", vec![chave.to_string()]));
+    }
+    // A caixa de email é criada logo depois da entrada
+    if cfg.caixa_email.is_some() {
+        atributos.push(("estadoCaixa", um(EstadoCaixa::Pendente.valor())));
+    }
+    atributos
+        .extend(renovacao.map(|(atributo, valor)| (atributo, vec![valor])));
+
+    let mut atributos: Vec<_> = atributos
+        .into_iter()
+        .map(|(atributo, valores)| (atributo.to_string(), valores))
+        .collect();
+    if let Some(campos) = &cfg.campos_academicos {
+        let curso = campos.cursos.get(ou).map(String::as_str);
+        atributos.extend(
+            campos_academicos(campos, curso, dados.dre())
+                .into_iter()
+                .map(|(atributo, valor)| (atributo.to_string(), vec![valor])),
+        );
+    }
+    if let (Some(atributo), Some(responsavel)) =
+        (&cfg.atributo_responsavel, dados.responsavel())
+    {
+        atributos.push((atributo.clone(), vec![responsavel.to_string()]));
+    }
+
+    EntradaUsuario { dn, atributos }
+}
+
+/// O uidNumber e o RID do Samba reservados para uma conta nova por
+/// [alocar_ids].
+pub(crate) struct IdsAlocados {
+    pub(crate) uid_number: String,
+    pub(crate) rid: String,
+    /// O registro dos liberados de onde o uidNumber saiu, se ele foi
+    /// reutilizado.
+    liberado: Option<UidLiberado>,
+}
+
+impl IdsAlocados {
+    /// Retorna o resultado da `criacao` da conta com estes IDs. Se ela
+    /// falhou, o uidNumber reutilizado volta para os liberados, em vez de se
+    /// perder; os do contador não voltam, como sempre foi.
+    pub(crate) async fn devolver_se_falhou<D: DiretorioLdap, T>(
+        &self,
+        criacao: Result<T, ErroLdap>,
+        ldap: &mut D,
+    ) -> Result<T, ErroLdap> {
+        devolver_se_falhou(self.liberado, criacao, ldap).await
+    }
+}
 
-        Ok(())
+async fn devolver_se_falhou<D: DiretorioLdap, T>(
+    liberado: Option<UidLiberado>,
+    r: Result<T, ErroLdap>,
+    ldap: &mut D,
+) -> Result<T, ErroLdap> {
+    if r.is_err()
+        && let Some(liberado) = liberado
+        && let Err(e) = devolver_liberado(liberado, ldap).await
+    {
+        eprintln!(
+            "Não foi possível devolver o uidNumber {} aos liberados: {e}",
+            liberado.uid_number,
+        );
     }
+    r
+}
 
-    rodar_ldap(ldap_url, bind_dn, bind_pw, |mut ldap| async move {
-        (cadastrar(username, dados, &cfg, ou, &mut ldap).await, ldap)
+/// Reserva o uidNumber e o RID do Samba de uma conta nova. Com a
+/// `reutilizacao_uids` configurada, o uidNumber vem dos liberados pelas contas
+/// removidas, se algum já tiver passado da quarentena em `hoje`, e só o RID
+/// sai do contador.
+pub(crate) async fn alocar_ids<D: DiretorioLdap>(
+    cfg: &ConfiguracaoUsuario,
+    hoje: i64,
+    ldap: &mut D,
+) -> Result<IdsAlocados, ErroLdap> {
+    if let Some(reutilizacao) = &cfg.reutilizacao_uids
+        && let Some(liberado) =
+            reservar_liberado(hoje, reutilizacao.quarentena_dias, ldap).await?
+    {
+        let rid = proximo_rid(ldap).await;
+        return Ok(IdsAlocados {
+            uid_number: liberado.uid_number.to_string(),
+            rid: devolver_se_falhou(Some(liberado), rid, ldap).await?,
+            liberado: Some(liberado),
+        });
+    }
+    let (uid_number, rid) = samba_ids(ldap).await?;
+    Ok(IdsAlocados {
+        uid_number,
+        rid,
+        liberado: None,
     })
-    .await
 }
 
-async fn samba_ids(ldap: &mut Ldap) -> Result<(String, String), ErroLdap> {
-    let (ids_s, _) = ldap
-        .search(
+/// Reserva o próximo RID do Samba no objeto `sambaDomain`, sem mexer no
+/// contador de uidNumbers.
+async fn proximo_rid<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    for _ in 1..=5 {
+        let rid = ler_contadores(ldap).await?;
+        let (atual, proximo) =
+            (rid.next_rid.to_string(), (rid.next_rid + 1).to_string());
+
+        let modificacao = ldap
+            .modificar(
+                &rid.dn,
+                vec![
+                    Mod::Delete("sambaNextRid", [atual.as_str()].into()),
+                    Mod::Add("sambaNextRid", [proximo.as_str()].into()),
+                ],
+            )
+            .await;
+        if modificacao.is_ok() {
+            return Ok(proximo);
+        }
+    }
+    Err(ErroLdap::ErroSamba)
+}
+
+/// Reserva o próximo uidNumber e o próximo RID do Samba no objeto
+/// `sambaDomain`, tentando de novo se outro cadastro mudar os contadores ao
+/// mesmo tempo.
+pub(crate) async fn samba_ids<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<(String, String), ErroLdap> {
+    let ids_s = ldap
+        .buscar(
             "dc=dcc,dc=ufrj,dc=br",
             Scope::OneLevel,
             "(objectClass=sambaDomain)",
             vec!["uidNumber", "sambaNextRid"],
         )
-        .await?
-        .success()?;
+        .await?;
 
-    let Some(ids_s) = ids_s.first() else {
+    let Some(ids_s) = ids_s.into_iter().next() else {
         return Err(ErroLdap::ErroSamba);
     };
 
-    // TODO: precisa desse clone?
-    let ids_s = SearchEntry::construct(ids_s.clone());
-
     let samba_uid = ids_s
         .attrs
         .get("uidNumber")
@@ -195,7 +509,7 @@ async fn samba_ids(ldap: &mut Ldap) -> Result<(String, String), ErroLdap> {
 
     for _ in 1..=5 {
         let modificacao = ldap
-            .modify(
+            .modificar(
                 &ids_s.dn,
                 vec![
                     Mod::Delete("uidNumber", [samba_uid.as_str()].into()),
@@ -204,8 +518,7 @@ async fn samba_ids(ldap: &mut Ldap) -> Result<(String, String), ErroLdap> {
                     Mod::Add("sambaNextRid", [prox_samba_rid.as_str()].into()),
                 ],
             )
-            .await
-            .and_then(|x| x.success());
+            .await;
 
         if modificacao.is_ok() {
             return Ok((prox_samba_uid, prox_samba_rid));
@@ -213,3 +526,440 @@ async fn samba_ids(ldap: &mut Ldap) -> Result<(String, String), ErroLdap> {
     }
     Err(ErroLdap::ErroSamba)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cadastro_aluno::DadosParaCadastro;
+    use crate::configuracao::FormatoTelefone;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    const DN_DOMINIO: &str = "sambaDomainName=DCC,dc=dcc,dc=ufrj,dc=br";
+
+    fn dados() -> DadosValidados {
+        dados_com_dre("123456789")
+    }
+
+    fn dados_com_dre(dre: &str) -> DadosValidados {
+        DadosParaCadastro {
+            dre: dre.to_string(),
+            data: "01/03/2025".to_string(),
+            hora: "10:00".to_string(),
+            codigo: "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF".to_string(),
+            nome: "Cláudio de Lima Cavalcante".to_string(),
+            email: "claudio@exemplo.com".to_string(),
+            telefone: "+5521987654321".to_string(),
+            senha: "Senha1234".to_string().into(),
+            chave_ssh: None,
+            username: None,
+            foto: None,
+        }
+        .validar(&cfg())
+        .unwrap()
+    }
+
+    fn cfg() -> ConfiguracaoUsuario {
+        ConfiguracaoUsuario {
+            gid_number: "1000".to_string(),
+            samba_sid_prefix: "S-1-5-21-1-2-3-".to_string(),
+            samba_acct_flags: "[UX]".to_string(),
+            samba_lm_password: "XXX".to_string(),
+            samba_password_history: "000".to_string(),
+            samba_primary_group_sid: "S-1-5-21-1-2-3-513".to_string(),
+            cota: "1000".to_string(),
+            caixa_email: None,
+            impressao: None,
+            moodle: None,
+            #[cfg(feature = "kerberos")]
+            kerberos: None,
+            campos_academicos: None,
+            reutilizacao_uids: None,
+            reserva_username: None,
+            usernames_confundiveis: None,
+            foto: None,
+            contingencia: None,
+            alias_email: None,
+            duplicidade: None,
+            telefone: Default::default(),
+            dominios_email: Default::default(),
+            atributo_responsavel: None,
+        }
+    }
+
+    async fn diretorio() -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(
+            DN_DOMINIO,
+            vec![
+                ("objectClass", ["sambaDomain"].into()),
+                ("uidNumber", ["5000"].into()),
+                ("sambaNextRid", ["9000"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        d
+    }
+
+    #[tokio::test]
+    async fn monta_a_entrada_e_incrementa_os_ids() {
+        let mut d = diretorio().await;
+
+        cadastrar_usuario_em(
+            "claudiolc".to_string(),
+            &dados(),
+            &cfg(),
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &mut d,
+        )
+        .await
+        .unwrap();
+
+        let dominio = d.entrada(DN_DOMINIO).unwrap();
+        assert_eq!(dominio.attrs["uidNumber"], vec!["5001"]);
+        assert_eq!(dominio.attrs["sambaNextRid"], vec!["9001"]);
+
+        let e = d
+            .entrada(
+                "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,\
+                 dc=dcc,dc=ufrj,dc=br",
+            )
+            .unwrap();
+        assert_eq!(e.attrs["uidNumber"], vec!["5001"]);
+        assert_eq!(e.attrs["sambaSID"], vec!["S-1-5-21-1-2-3-9001"]);
+        assert_eq!(e.attrs["mail"], vec!["claudiolc@ic.ufrj.br"]);
+        assert_eq!(e.attrs["gecos"], vec!["Claudio de Lima Cavalcante"]);
+        assert_eq!(e.attrs["cn"], vec!["Cláudio"]);
+        assert_eq!(e.attrs["sn"], vec!["de Lima Cavalcante"]);
+        assert_eq!(e.attrs["dccDRE"], vec!["123456789"]);
+    }
+
+    /// Monta a entrada com um horário e um salt fixos e formata ela como um
+    /// LDIF, para ser comparada com o snapshot.
+    fn entrada_fixa(ou: &str) -> String {
+        let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+        let entrada = montar_entrada(
+            "claudiolc",
+            &dados(),
+            (&cfg(), &ConfiguracaoRenovacao::default()),
+            ou,
+            ("5001", "9001"),
+            agora,
+            &[1, 2, 3, 4],
+        );
+
+        let mut ldif = format!("dn: {}\n", entrada.dn);
+        for (atributo, valores) in &entrada.atributos {
+            for valor in valores {
+                ldif += &format!("{atributo}: {valor}\n");
+            }
+        }
+        ldif
+    }
+
+    #[test]
+    fn entrada_de_aluno() {
+        insta::assert_snapshot!(entrada_fixa("alunos"));
+    }
+
+    #[test]
+    fn entrada_de_aluno_do_profcomp() {
+        insta::assert_snapshot!(entrada_fixa("profcomp"));
+    }
+
+    #[test]
+    fn gecos_sem_separadores_nem_sobras() {
+        for (nome, gecos) in [
+            ("Maria, da Silva", "Maria da Silva"),
+            // A vírgula de largura cheia vira uma vírgula no deunicode
+            ("Maria，Silva", "Maria Silva"),
+            ("Maria\tda\r\nSilva:", "Maria da Silva"),
+            (",:,", ""),
+            ("", ""),
+        ] {
+            assert_eq!(normalizar_gecos(nome), gecos, "{nome:?}");
+        }
+
+        // O corte no limite não deixa um espaço no fim
+        let nome = format!("{} Silva", "a".repeat(MAXIMO_GECOS - 1));
+        let gecos = normalizar_gecos(&nome);
+        assert_eq!(gecos, "a".repeat(MAXIMO_GECOS - 1));
+        assert!(normalizar_gecos(&"Ação ".repeat(100)).len() <= MAXIMO_GECOS);
+    }
+
+    #[test]
+    fn prazos_da_configuracao_vao_para_a_entrada() {
+        let prazos = ConfiguracaoRenovacao {
+            validade_conta: 100,
+            carencia_dias: 10,
+            validade_senha: 180,
+            aviso_expiracao: 7,
+            ..Default::default()
+        };
+        let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+        let entrada = montar_entrada(
+            "claudiolc",
+            &dados(),
+            (&cfg(), &prazos),
+            "alunos",
+            ("5001", "9001"),
+            agora,
+            &[1, 2, 3, 4],
+        );
+        let valor = |atributo: &str| {
+            let (_, valores) = entrada
+                .atributos
+                .iter()
+                .find(|(a, _)| *a == atributo)
+                .unwrap();
+            valores[0].clone()
+        };
+
+        // 01/03/2025 é o dia 20148
+        assert_eq!(valor("dataRenovacao"), "20248");
+        assert_eq!(valor("shadowExpire"), "20258");
+        assert_eq!(valor("sambaKickoffTime"), (20258 * 86400).to_string());
+        assert_eq!(valor("shadowMax"), "180");
+        assert_eq!(valor("shadowWarning"), "7");
+        assert_eq!(
+            valor("sambaPwdMustChange"),
+            (1740830400 + 180 * 86400).to_string(),
+        );
+
+        // Os mesmos valores de uma renovação feita no dia do cadastro
+        let (_, renovacao) = valores_da_renovacao(&prazos, 20148);
+        for (atributo, esperado) in renovacao {
+            assert_eq!(valor(atributo), esperado, "{atributo}");
+        }
+    }
+
+    #[test]
+    fn campos_academicos_vao_para_a_entrada() {
+        let mut cfg = cfg();
+        cfg.campos_academicos = Some(ConfiguracaoCamposAcademicos {
+            atributo_curso: "curso".to_string(),
+            ..Default::default()
+        });
+        let dados = dados_com_dre("125112345");
+        let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+
+        let atributos = |ou: &str| {
+            montar_entrada(
+                "claudiolc",
+                &dados,
+                (&cfg, &ConfiguracaoRenovacao::default()),
+                ou,
+                ("5001", "9001"),
+                agora,
+                &[1, 2, 3, 4],
+            )
+            .atributos
+        };
+
+        let entrada = atributos("profcomp");
+        assert!(entrada.contains(&(
+            "curso".to_string(),
+            vec!["Ensino de Computação".to_string()]
+        )));
+        assert!(entrada.contains(&(
+            "dccIngresso".to_string(),
+            vec!["2025.1".to_string()]
+        )));
+
+        // Uma OU sem curso configurado fica só com o ingresso
+        let entrada = atributos("visitantes");
+        assert!(!entrada.iter().any(|(a, _)| a == "curso"));
+        assert!(entrada.iter().any(|(a, _)| a == "dccIngresso"));
+    }
+
+    #[test]
+    fn telefone_no_formato_configurado() {
+        let mut cfg = cfg();
+        let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+        let atributos = |cfg: &ConfiguracaoUsuario| {
+            montar_entrada(
+                "claudiolc",
+                &dados(),
+                (cfg, &ConfiguracaoRenovacao::default()),
+                "alunos",
+                ("5001", "9001"),
+                agora,
+                &[1, 2, 3, 4],
+            )
+            .atributos
+        };
+        let telefone = |valor: &str| vec![valor.to_string()];
+
+        cfg.telefone.formato = FormatoTelefone::Nacional;
+        let entrada = atributos(&cfg);
+        assert!(entrada.contains(&(
+            "telephoneNumber".to_string(),
+            telefone("(21) 98765-4321")
+        )));
+        assert!(!entrada.iter().any(|(a, _)| a == "mobile"));
+
+        cfg.telefone.formato = FormatoTelefone::Ambos;
+        let entrada = atributos(&cfg);
+        assert!(entrada.contains(&(
+            "telephoneNumber".to_string(),
+            telefone("+5521987654321")
+        )));
+        assert!(
+            entrada
+                .contains(&("mobile".to_string(), telefone("(21) 98765-4321")))
+        );
+    }
+
+    #[tokio::test]
+    async fn reutiliza_o_uid_number_liberado() {
+        let mut d = diretorio().await;
+        d.modificar(
+            DN_DOMINIO,
+            vec![Mod::Add("dccUidLiberado", ["4321:0"].into())],
+        )
+        .await
+        .unwrap();
+        let mut cfg = cfg();
+        cfg.reutilizacao_uids = Some(Default::default());
+
+        cadastrar_usuario_em(
+            "claudiolc".to_string(),
+            &dados(),
+            &cfg,
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &mut d,
+        )
+        .await
+        .unwrap();
+
+        // Só o contador de RIDs anda
+        let dominio = d.entrada(DN_DOMINIO).unwrap();
+        assert_eq!(dominio.attrs["uidNumber"], vec!["5000"]);
+        assert_eq!(dominio.attrs["sambaNextRid"], vec!["9001"]);
+        assert!(!dominio.attrs.contains_key("dccUidLiberado"));
+
+        let e = d
+            .entrada(
+                "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,\
+                 dc=dcc,dc=ufrj,dc=br",
+            )
+            .unwrap();
+        assert_eq!(e.attrs["uidNumber"], vec!["4321"]);
+        assert_eq!(e.attrs["sambaSID"], vec!["S-1-5-21-1-2-3-9001"]);
+    }
+
+    #[tokio::test]
+    async fn uid_number_liberado_volta_se_a_conta_nao_e_criada() {
+        let mut d = diretorio().await;
+        d.modificar(
+            DN_DOMINIO,
+            vec![Mod::Add("dccUidLiberado", ["4321:0"].into())],
+        )
+        .await
+        .unwrap();
+        // Uma conta com o mesmo DN faz a criação falhar depois da reserva
+        d.adicionar(
+            "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+            vec![
+                ("objectClass", ["posixAccount"].into()),
+                ("uid", ["claudiolc"].into()),
+                ("uidNumber", ["4000"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        let mut cfg = cfg();
+        cfg.reutilizacao_uids = Some(Default::default());
+
+        let r = cadastrar_usuario_em(
+            "claudiolc".to_string(),
+            &dados(),
+            &cfg,
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &mut d,
+        )
+        .await;
+
+        assert!(r.is_err());
+        let dominio = d.entrada(DN_DOMINIO).unwrap();
+        assert_eq!(dominio.attrs["dccUidLiberado"], vec!["4321:0"]);
+        assert_eq!(dominio.attrs["uidNumber"], vec!["5000"]);
+    }
+
+    #[tokio::test]
+    async fn uid_number_em_uso_nao_cria_a_conta() {
+        let mut d = diretorio().await;
+        // Uma conta criada à mão com o próximo uidNumber do contador
+        d.adicionar(
+            "uid=manual,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+            vec![
+                ("objectClass", ["posixAccount"].into()),
+                ("uid", ["manual"].into()),
+                ("uidNumber", ["5001"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let r = cadastrar_usuario_em(
+            "claudiolc".to_string(),
+            &dados(),
+            &cfg(),
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &mut d,
+        )
+        .await;
+
+        assert!(matches!(
+            r,
+            Err(ErroLdap::UidNumberEmUso { uid_number, dono })
+                if uid_number == "5001" && dono == "manual"
+        ));
+        assert!(
+            d.entrada(
+                "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,\
+                 dc=dcc,dc=ufrj,dc=br",
+            )
+            .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn falha_sem_dominio_samba() {
+        let mut d = DiretorioMemoria::default();
+
+        let r = cadastrar_usuario_em(
+            "claudiolc".to_string(),
+            &dados(),
+            &cfg(),
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &mut d,
+        )
+        .await;
+
+        assert!(matches!(r, Err(ErroLdap::ErroSamba)));
+    }
+
+    #[tokio::test]
+    async fn username_repetido_e_um_erro() {
+        let mut d = diretorio().await;
+
+        for esperado_ok in [true, false] {
+            let r = cadastrar_usuario_em(
+                "claudiolc".to_string(),
+                &dados(),
+                &cfg(),
+                &ConfiguracaoRenovacao::default(),
+                "alunos",
+                &mut d,
+            )
+            .await;
+            assert_eq!(r.is_ok(), esperado_ok);
+        }
+    }
+}
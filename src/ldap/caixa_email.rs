@@ -0,0 +1,110 @@
+//! Estado da caixa de email institucional de cada conta, guardado no atributo
+//! `estadoCaixa`.
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::BASE_ACADEMICOS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope};
+
+/// O estado da caixa de email de uma conta. As contas sem o atributo, criadas
+/// antes do alumnic criar as caixas, não são acompanhadas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstadoCaixa {
+    /// A conta foi criada, mas a caixa ainda não.
+    Pendente,
+    Criada,
+    /// A criação falhou e precisa ser tentada de novo.
+    Falhou,
+}
+
+impl EstadoCaixa {
+    /// O valor gravado no atributo `estadoCaixa`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::caixa_email::EstadoCaixa;
+    /// assert_eq!(EstadoCaixa::Falhou.valor(), "falhou");
+    /// assert_eq!(EstadoCaixa::do_valor("CRIADA"), Some(EstadoCaixa::Criada));
+    /// assert_eq!(EstadoCaixa::do_valor("outro"), None);
+    /// ```
+    pub fn valor(&self) -> &'static str {
+        match self {
+            EstadoCaixa::Pendente => "pendente",
+            EstadoCaixa::Criada => "criada",
+            EstadoCaixa::Falhou => "falhou",
+        }
+    }
+
+    /// O estado representado pelo valor do atributo `estadoCaixa`.
+    pub fn do_valor(valor: &str) -> Option<Self> {
+        match valor.to_lowercase().as_str() {
+            "pendente" => Some(EstadoCaixa::Pendente),
+            "criada" => Some(EstadoCaixa::Criada),
+            "falhou" => Some(EstadoCaixa::Falhou),
+            _ => None,
+        }
+    }
+}
+
+/// Uma conta cuja caixa de email ainda precisa ser criada.
+#[derive(Debug, Clone)]
+pub struct CaixaPendente {
+    pub dn: String,
+    pub uid: String,
+    /// O endereço da caixa, do atributo `mail`.
+    pub endereco: String,
+    /// O nome completo.
+    pub nome: String,
+    pub estado: EstadoCaixa,
+}
+
+/// Busca as contas com a caixa de email pendente ou que falhou.
+pub async fn buscar_caixas_pendentes<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<Vec<CaixaPendente>, ErroLdap> {
+    let filtro = filtros::ou([
+        filtros::igual("estadoCaixa", EstadoCaixa::Pendente.valor()),
+        filtros::igual("estadoCaixa", EstadoCaixa::Falhou.valor()),
+    ]);
+    let entradas = ldap
+        .buscar(
+            BASE_ACADEMICOS,
+            Scope::Subtree,
+            &filtro,
+            vec!["uid", "mail", "gecos", "estadoCaixa"],
+        )
+        .await?;
+
+    let mut pendentes: Vec<_> = entradas
+        .into_iter()
+        .filter_map(|mut e| {
+            let mut primeiro = |atributo: &str| {
+                e.attrs.remove(atributo).and_then(|v| v.into_iter().next())
+            };
+            Some(CaixaPendente {
+                uid: primeiro("uid")?,
+                endereco: primeiro("mail")?,
+                nome: primeiro("gecos").unwrap_or_default(),
+                estado: EstadoCaixa::do_valor(&primeiro("estadoCaixa")?)?,
+                dn: e.dn,
+            })
+        })
+        .collect();
+    pendentes.sort_by(|a, b| a.uid.cmp(&b.uid));
+
+    Ok(pendentes)
+}
+
+/// Grava o `estado` da caixa de email da conta `dn`.
+pub async fn marcar_caixa<D: DiretorioLdap>(
+    dn: &str,
+    estado: EstadoCaixa,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    ldap.modificar(
+        dn,
+        vec![Mod::Replace("estadoCaixa", [estado.valor()].into())],
+    )
+    .await
+}
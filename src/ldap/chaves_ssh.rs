@@ -0,0 +1,81 @@
+//! Leitura e alteração das chaves SSH públicas de uma conta, guardadas no
+//! atributo `sshPublicKey` do objectClass `ldapPublicKey`.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope};
+
+/// O objectClass que permite o atributo `sshPublicKey`.
+const CLASSE: &str = "ldapPublicKey";
+
+/// As chaves SSH de uma conta.
+#[derive(Debug, Clone)]
+pub struct ChavesDaConta {
+    pub dn: String,
+    /// Se a entrada já tem o objectClass `ldapPublicKey`.
+    pub tem_classe: bool,
+    pub chaves: Vec<String>,
+}
+
+/// Busca as chaves SSH da conta do aluno com o `uid`.
+pub async fn buscar_chaves<D: DiretorioLdap>(
+    uid: &str,
+    ldap: &mut D,
+) -> Result<Option<ChavesDaConta>, ErroLdap> {
+    let filtro = filtros::e([
+        filtros::igual("objectClass", "dccAluno"),
+        filtros::igual("uid", uid),
+    ]);
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtro,
+            vec!["objectClass", "sshPublicKey"],
+        )
+        .await?;
+
+    Ok(entradas.into_iter().next().map(|mut e| {
+        let tem_classe = e
+            .attrs
+            .get("objectClass")
+            .is_some_and(|c| c.iter().any(|c| c.eq_ignore_ascii_case(CLASSE)));
+        ChavesDaConta {
+            dn: e.dn,
+            tem_classe,
+            chaves: e.attrs.remove("sshPublicKey").unwrap_or_default(),
+        }
+    }))
+}
+
+/// Acrescenta a `chave` à `conta`, colocando o objectClass se ela ainda não
+/// tiver nenhuma chave.
+pub async fn adicionar_chave<D: DiretorioLdap>(
+    conta: &ChavesDaConta,
+    chave: &str,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let mut mods = vec![];
+    if !conta.tem_classe {
+        mods.push(Mod::Add("objectClass", [CLASSE].into()));
+    }
+    mods.push(Mod::Add("sshPublicKey", [chave].into()));
+
+    ldap.modificar(&conta.dn, mods).await
+}
+
+/// Remove a `chave` da `conta`. Sem a última chave, o objectClass também é
+/// removido, já que ele exige o `sshPublicKey`.
+pub async fn remover_chave<D: DiretorioLdap>(
+    conta: &ChavesDaConta,
+    chave: &str,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let mut mods = vec![Mod::Delete("sshPublicKey", [chave].into())];
+    if conta.tem_classe && conta.chaves.iter().all(|c| c == chave) {
+        mods.push(Mod::Delete("objectClass", [CLASSE].into()));
+    }
+
+    ldap.modificar(&conta.dn, mods).await
+}
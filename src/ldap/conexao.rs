@@ -0,0 +1,496 @@
+//! Fontes de conexões com o diretório LDAP. As funções que precisam abrir uma
+//! conexão recebem uma [`FonteLdap`] em vez do endereço e das credenciais do
+//! servidor, o que permite trocar o servidor de verdade por um
+//! [`DiretorioMemoria`](crate::ldap::memoria::DiretorioMemoria) compartilhado
+//! nos testes da API.
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::utils::medir;
+use crate::utils::hashes::{compare_ssha, ssha_valido};
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope};
+use native_tls::{Certificate, Identity, TlsConnector, TlsConnectorBuilder};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::task::JoinHandle;
+
+/// Algo que fornece conexões com um diretório LDAP.
+pub trait FonteLdap: Send + Sync {
+    /// O tipo da conexão aberta.
+    type Conexao: DiretorioLdap;
+
+    /// Abre uma conexão, já autenticada, com o diretório.
+    fn abrir(
+        &self,
+    ) -> impl Future<Output = Result<Self::Conexao, ErroLdap>> + Send;
+
+    /// Fecha uma conexão aberta com [`abrir`](FonteLdap::abrir).
+    fn fechar(
+        &self,
+        conexao: Self::Conexao,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send;
+
+    /// Autentica de novo a `conexao`, com o mesmo bind do
+    /// [`abrir`](FonteLdap::abrir), como quando o servidor esquece o bind de
+    /// uma conexão guardada num [pool](crate::ldap::LdapPool). Por padrão,
+    /// não faz nada, como num diretório sem bind.
+    fn autenticar(
+        &self,
+        conexao: &mut Self::Conexao,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        let _ = conexao;
+        async { Ok(()) }
+    }
+
+    /// Verifica se a `senha` é a da entrada `dn`, sem mudar a identidade das
+    /// conexões abertas com [`abrir`](FonteLdap::abrir). Uma senha vazia
+    /// nunca é aceita.
+    fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> impl Future<Output = Result<bool, ErroLdap>> + Send;
+}
+
+/// Um servidor LDAP de verdade. Cada conexão é aberta e autenticada com um
+/// bind simples, ou com SASL EXTERNAL se houver um certificado de cliente, e
+/// fechada com um unbind.
+#[derive(Debug, Clone)]
+pub struct ServidorLdap {
+    pub url: String,
+    pub bind_dn: String,
+    pub bind_pw: String,
+    /// O tempo máximo da conexão e de cada operação, depois do qual ela falha
+    /// com [`ErroLdap::Timeout`].
+    pub timeout: Duration,
+    pub certificado: Option<CertificadoCliente>,
+    pub tls: ConfiguracaoTls,
+    /// As réplicas usadas só para leitura quando o servidor está fora do ar.
+    pub replicas: Replicas,
+}
+
+/// Por quanto tempo, depois de o servidor principal cair, as conexões vão
+/// direto para as réplicas, sem esperar o principal falhar de novo.
+pub const ESPERA_PRINCIPAL: Duration = Duration::from_secs(30);
+
+/// As réplicas só de leitura de um [ServidorLdap], com as mesmas credenciais
+/// dele, configuradas como
+///
+/// ```yaml
+/// ldap_url: "ldaps://ldap.ic.ufrj.br"
+/// ldap_replicas:
+///   - "ldaps://ldap2.ic.ufrj.br"
+/// ```
+///
+/// Quando o principal não responde, a conexão é aberta com a primeira réplica
+/// que responder, e as escritas nela falham com
+/// [`ErroLdap::SomenteLeitura`]. As cópias de um [ServidorLdap] compartilham a
+/// lembrança da última queda do principal.
+#[derive(Debug, Clone, Default)]
+pub struct Replicas {
+    pub urls: Vec<String>,
+    queda: Arc<std::sync::Mutex<Option<Instant>>>,
+}
+
+impl Replicas {
+    pub fn new(urls: Vec<String>) -> Self {
+        Self {
+            urls,
+            ..Default::default()
+        }
+    }
+
+    /// Se o principal caiu há menos de [ESPERA_PRINCIPAL].
+    fn principal_em_espera(&self) -> bool {
+        self.queda
+            .lock()
+            .unwrap()
+            .is_some_and(|q| q.elapsed() < ESPERA_PRINCIPAL)
+    }
+
+    fn registrar_queda(&self) {
+        *self.queda.lock().unwrap() = Some(Instant::now());
+    }
+}
+
+/// O TLS das conexões com um [ServidorLdap], para que a senha do bind e os
+/// hashes das senhas não passem em texto claro pela rede. Uma URL `ldaps://`
+/// já usa TLS, com o certificado do servidor validado pelas CAs do sistema;
+/// com `starttls`, uma URL `ldap://` também, e com a `ca`, o certificado pode
+/// ser de uma CA própria:
+///
+/// ```yaml
+/// ldap_url: "ldap://ldap.ic.ufrj.br"
+/// ldap_tls:
+///   starttls: true
+///   ca: /etc/alumnic/ca.pem
+/// ```
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ConfiguracaoTls {
+    /// A CA do servidor, em PEM, se ela não estiver entre as do sistema.
+    pub ca: Option<PathBuf>,
+    /// Usa o StartTLS numa URL `ldap://`. Uma URL `ldaps://` não precisa.
+    pub starttls: bool,
+}
+
+/// Lê o arquivo em `caminho`, para montar o conector TLS.
+fn ler_arquivo(caminho: &Path) -> Result<Vec<u8>, ErroLdap> {
+    std::fs::read(caminho).map_err(|e| {
+        ErroLdap::Certificado(format!("{}: {e}", caminho.display()))
+    })
+}
+
+fn certificado_invalido(e: native_tls::Error) -> ErroLdap {
+    ErroLdap::Certificado(e.to_string())
+}
+
+/// Acrescenta a CA em `caminho` às do sistema no `conector`.
+fn confiar_na_ca(
+    conector: &mut TlsConnectorBuilder,
+    caminho: &Path,
+) -> Result<(), ErroLdap> {
+    let ca = Certificate::from_pem(&ler_arquivo(caminho)?)
+        .map_err(certificado_invalido)?;
+    conector.add_root_certificate(ca);
+    Ok(())
+}
+
+/// Um certificado de cliente TLS para o bind por SASL EXTERNAL, em que o
+/// servidor identifica o alumnic pelo certificado (com o `authz-regexp` do
+/// OpenLDAP, por exemplo) em vez de uma senha. Configurado como
+///
+/// ```yaml
+/// ldap_url: "ldaps://ldap.ic.ufrj.br"
+/// ldap_certificado:
+///   certificado: /etc/alumnic/cliente.pem
+///   chave: /etc/alumnic/cliente.key
+///   ca: /etc/alumnic/ca.pem
+/// ```
+///
+/// Os arquivos são lidos a cada conexão, então um certificado renovado passa
+/// a valer sem reiniciar o alumnic.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CertificadoCliente {
+    /// O certificado, em PEM.
+    pub certificado: PathBuf,
+    /// A chave privada do certificado, em PEM no formato PKCS#8.
+    pub chave: PathBuf,
+    /// A CA do servidor, em PEM, se ela não estiver entre as do sistema.
+    #[serde(default)]
+    pub ca: Option<PathBuf>,
+    /// Usa o StartTLS numa URL `ldap://` em vez de exigir `ldaps://`.
+    #[serde(default)]
+    pub starttls: bool,
+}
+
+impl CertificadoCliente {
+    /// Monta o conector TLS que apresenta o certificado ao servidor.
+    pub fn conector(&self) -> Result<TlsConnector, ErroLdap> {
+        let mut conector = TlsConnector::builder();
+        self.configurar(&mut conector)?;
+        conector.build().map_err(certificado_invalido)
+    }
+
+    /// Configura o `conector` para apresentar o certificado e confiar na
+    /// `ca`.
+    fn configurar(
+        &self,
+        conector: &mut TlsConnectorBuilder,
+    ) -> Result<(), ErroLdap> {
+        let identidade = Identity::from_pkcs8(
+            &ler_arquivo(&self.certificado)?,
+            &ler_arquivo(&self.chave)?,
+        )
+        .map_err(certificado_invalido)?;
+        conector.identity(identidade);
+        if let Some(ca) = &self.ca {
+            confiar_na_ca(conector, ca)?;
+        }
+        Ok(())
+    }
+}
+
+/// Uma conexão aberta com um [ServidorLdap], que aplica o timeout dele em
+/// todas as operações.
+///
+/// A task que conduz a conexão é abortada quando a conexão é destruída, o que
+/// fecha o socket mesmo quando ela não é fechada com
+/// [`fechar`](FonteLdap::fechar): depois de um erro, de um pânico ou do
+/// cancelamento da requisição. Sem isso, a task ficaria órfã e a conexão
+/// pendurada no servidor.
+pub struct ConexaoLdap {
+    pub(crate) ldap: Ldap,
+    pub(crate) timeout: Duration,
+    tarefa: JoinHandle<()>,
+    somente_leitura: bool,
+}
+
+impl Drop for ConexaoLdap {
+    fn drop(&mut self) {
+        self.tarefa.abort();
+    }
+}
+
+impl ConexaoLdap {
+    /// A conexão, com o timeout já aplicado na próxima operação.
+    pub(crate) fn com_timeout(&mut self) -> &mut Ldap {
+        self.ldap.with_timeout(self.timeout)
+    }
+
+    /// Se a conexão é com uma [réplica](Replicas), que só aceita consultas.
+    pub fn somente_leitura(&self) -> bool {
+        self.somente_leitura
+    }
+
+    /// Se a task que conduz a conexão ainda roda, ou seja, se o socket não
+    /// foi fechado pelo servidor nem por um erro.
+    pub(crate) fn aberta(&self) -> bool {
+        !self.tarefa.is_finished()
+    }
+
+    /// Falha com [`ErroLdap::SomenteLeitura`] numa conexão com uma réplica,
+    /// antes de a escrita chegar a ela.
+    pub(crate) fn permitir_escrita(&self) -> Result<(), ErroLdap> {
+        if self.somente_leitura {
+            return Err(ErroLdap::SomenteLeitura);
+        }
+        Ok(())
+    }
+}
+
+impl ServidorLdap {
+    /// O servidor LDAP descrito na [Configuracao].
+    pub fn da_configuracao(cfg: &Configuracao) -> Self {
+        Self {
+            url: cfg.ldap_url.clone(),
+            bind_dn: cfg.ldap_bind_dn.clone(),
+            bind_pw: cfg.ldap_bind_pw.clone(),
+            timeout: Duration::from_secs(cfg.ldap_timeout_segundos),
+            certificado: cfg.ldap_certificado.clone(),
+            tls: cfg.ldap_tls.clone(),
+            replicas: Replicas::new(cfg.ldap_replicas.clone()),
+        }
+    }
+}
+
+impl ServidorLdap {
+    /// Se as conexões com uma URL `ldap://` usam o StartTLS, pelo `tls` ou
+    /// pelo [certificado de cliente](CertificadoCliente::starttls).
+    pub fn starttls(&self) -> bool {
+        self.tls.starttls
+            || self.certificado.as_ref().is_some_and(|c| c.starttls)
+    }
+
+    /// Se as conexões com o servidor principal passam sem TLS pela rede,
+    /// com uma URL `ldap://` sem o StartTLS. Um socket `ldapi://` não passa.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::conexao::{ConfiguracaoTls, Replicas, ServidorLdap};
+    /// # use std::time::Duration;
+    /// let mut servidor = ServidorLdap {
+    ///     url: "LDAP://ldap.ic.ufrj.br".to_string(),
+    ///     bind_dn: "cn=admin,dc=dcc,dc=ufrj,dc=br".to_string(),
+    ///     bind_pw: "admin".to_string(),
+    ///     timeout: Duration::from_secs(30),
+    ///     certificado: None,
+    ///     tls: ConfiguracaoTls::default(),
+    ///     replicas: Replicas::default(),
+    /// };
+    /// assert!(servidor.em_texto_claro());
+    ///
+    /// servidor.tls.starttls = true;
+    /// assert!(!servidor.em_texto_claro());
+    ///
+    /// servidor.tls.starttls = false;
+    /// servidor.url = "ldaps://ldap.ic.ufrj.br".to_string();
+    /// assert!(!servidor.em_texto_claro());
+    /// ```
+    pub fn em_texto_claro(&self) -> bool {
+        self.url.to_lowercase().starts_with("ldap://") && !self.starttls()
+    }
+
+    /// O conector TLS com o certificado de cliente e as CAs configuradas, ou
+    /// nenhum, para usar o padrão, que confia só nas CAs do sistema.
+    pub fn conector(&self) -> Result<Option<TlsConnector>, ErroLdap> {
+        if self.certificado.is_none() && self.tls.ca.is_none() {
+            return Ok(None);
+        }
+
+        let mut conector = TlsConnector::builder();
+        if let Some(certificado) = &self.certificado {
+            certificado.configurar(&mut conector)?;
+        }
+        if let Some(ca) = &self.tls.ca {
+            confiar_na_ca(&mut conector, ca)?;
+        }
+        conector.build().map(Some).map_err(certificado_invalido)
+    }
+
+    /// Abre uma conexão, ainda sem o bind, com o servidor ou, se ele estiver
+    /// fora do ar, com a primeira réplica que responder.
+    async fn conectar(&self) -> Result<ConexaoLdap, ErroLdap> {
+        if !self.replicas.principal_em_espera() {
+            match self.conectar_em(&self.url).await {
+                Err(e)
+                    if e.indisponivel() && !self.replicas.urls.is_empty() =>
+                {
+                    eprintln!(
+                        "O LDAP {} está fora do ar ({e}); as consultas vão \
+                         para as réplicas",
+                        self.url,
+                    );
+                    self.replicas.registrar_queda();
+                },
+                r => return r,
+            }
+        }
+
+        let mut erro = ErroLdap::Timeout;
+        for url in &self.replicas.urls {
+            match self.conectar_em(url).await {
+                Ok(mut conexao) => {
+                    conexao.somente_leitura = true;
+                    return Ok(conexao);
+                },
+                Err(e) => erro = e,
+            }
+        }
+        Err(erro)
+    }
+
+    /// Abre uma conexão com o servidor em `url`, ainda sem o bind.
+    async fn conectar_em(&self, url: &str) -> Result<ConexaoLdap, ErroLdap> {
+        let mut configuracoes = LdapConnSettings::new()
+            .set_conn_timeout(self.timeout)
+            .set_starttls(self.starttls());
+        if let Some(conector) = self.conector()? {
+            configuracoes = configuracoes.set_connector(conector);
+        }
+        let (conn, ldap) =
+            LdapConnAsync::with_settings(configuracoes, url).await?;
+        let tarefa = tokio::spawn(async move {
+            if let Err(e) = conn.drive().await {
+                eprintln!("Erro na conexão com o LDAP: {e}");
+            }
+        });
+
+        Ok(ConexaoLdap {
+            ldap,
+            timeout: self.timeout,
+            tarefa,
+            somente_leitura: false,
+        })
+    }
+}
+
+impl FonteLdap for ServidorLdap {
+    type Conexao = ConexaoLdap;
+
+    /// A duração da conexão e do bind é registrada nas
+    /// [métricas](crate::metricas) como a operação `bind`.
+    async fn abrir(&self) -> Result<ConexaoLdap, ErroLdap> {
+        medir("bind", async {
+            // Se o bind falhar, a conexão é destruída e a task, abortada
+            let mut conexao = self.conectar().await?;
+            self.autenticar(&mut conexao).await?;
+            Ok(conexao)
+        })
+        .await
+    }
+
+    /// Faz o bind simples ou, com o certificado de cliente, o SASL
+    /// EXTERNAL.
+    async fn autenticar(
+        &self,
+        conexao: &mut ConexaoLdap,
+    ) -> Result<(), ErroLdap> {
+        let ldap = conexao.com_timeout();
+        match &self.certificado {
+            Some(_) => ldap.sasl_external_bind().await?,
+            None => ldap.simple_bind(&self.bind_dn, &self.bind_pw).await?,
+        }
+        .success()?;
+        Ok(())
+    }
+
+    async fn fechar(&self, mut conexao: ConexaoLdap) -> Result<(), ErroLdap> {
+        let r = conexao.ldap.unbind().await;
+        // Depois do unbind, a task envia o pedido e fecha o socket sozinha
+        let _ =
+            tokio::time::timeout(conexao.timeout, &mut conexao.tarefa).await;
+        Ok(r?)
+    }
+
+    /// A senha é verificada com um bind simples numa conexão separada, que
+    /// é fechada logo depois.
+    async fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> Result<bool, ErroLdap> {
+        if senha.expose_secret().is_empty() {
+            return Ok(false);
+        }
+
+        let mut conexao = self.conectar().await?;
+        let resultado = conexao
+            .com_timeout()
+            .simple_bind(dn, senha.expose_secret())
+            .await?;
+        let _ = self.fechar(conexao).await;
+
+        match resultado.rc {
+            0 => Ok(true),
+            // invalidCredentials
+            49 => Ok(false),
+            _ => Err(resultado.success().unwrap_err().into()),
+        }
+    }
+}
+
+/// Um diretório compartilhado entre várias tarefas. Cada conexão trava o
+/// diretório até ser fechada, o que é o suficiente para os testes.
+impl<D: DiretorioLdap + 'static> FonteLdap for Arc<Mutex<D>> {
+    type Conexao = OwnedMutexGuard<D>;
+
+    async fn abrir(&self) -> Result<OwnedMutexGuard<D>, ErroLdap> {
+        Ok(self.clone().lock_owned().await)
+    }
+
+    async fn fechar(&self, _: OwnedMutexGuard<D>) -> Result<(), ErroLdap> {
+        Ok(())
+    }
+
+    /// Como não há bind, a senha é comparada com o hash SSHA do
+    /// `userPassword` da entrada.
+    async fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> Result<bool, ErroLdap> {
+        if senha.expose_secret().is_empty() {
+            return Ok(false);
+        }
+
+        let entradas = self
+            .lock()
+            .await
+            .buscar(dn, Scope::Base, "(objectClass=*)", vec!["userPassword"])
+            .await?;
+        let hash = entradas
+            .first()
+            .and_then(|e| e.attrs.get("userPassword"))
+            .and_then(|v| v.first());
+
+        Ok(hash.is_some_and(|h| {
+            ssha_valido(h) && compare_ssha(senha, &h.clone().into())
+        }))
+    }
+}
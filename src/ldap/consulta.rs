@@ -1,9 +1,21 @@
 //! Módulo para consulta de um username disponível para um usuário novo no LDAP,
 //! além de verificar se um usuário com a DRE já existe.
 use crate::ldap::ErroLdap;
-use crate::ldap::utils::rodar_ldap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::ldap::reservas::{ATRIBUTO_DONO, ATRIBUTO_VALIDADE, ocupa};
+use crate::utils::confundiveis::ConfiguracaoConfundiveis;
 use crate::utils::nome::Nome;
-use ldap3::{Ldap, Scope, SearchEntry, ldap_escape};
+use chrono::Utc;
+use ldap3::Scope;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Quantos usernames candidatos são consultados em cada busca.
+const TAMANHO_LOTE: usize = 32;
 
 /// Representa as informações sobre o cadastro de um usuário no LDAP
 #[derive(Debug)]
@@ -27,85 +39,712 @@ pub enum Consulta {
 /// válido ou caso o nome do usuário não seja válido.
 ///
 /// Mais informações em [ErroLdap]
-pub async fn consultar_cadastro_ldap(
+pub async fn consultar_cadastro_ldap<F: FonteLdap>(
+    dre: &str,
+    nome: &str,
+    fonte: &F,
+) -> Result<Consulta, ErroLdap> {
+    consultar_escolha_ldap(dre, nome, None, None, None, fonte).await
+}
+
+/// Faz o mesmo que [consultar_cadastro_ldap], mas com o username `escolhido`
+/// pelo aluno entre os [candidatos], se houver, em vez do primeiro livre.
+///
+/// # Errors
+///
+/// Além dos erros de [consultar_cadastro_ldap], retorna
+/// [ErroLdap::UsernameOcupado] se o username escolhido já existir, e
+/// [ErroLdap::UsernameConfundivel] se, com os `confundiveis` configurados, ele
+/// se confundir com um que existe. Cabe a quem chama verificar que ele é um
+/// dos [candidatos] do `nome`, com [e_candidato]. Com os `uids` em cache, a
+/// comparação dos confundíveis usa os uids guardados neles em vez de
+/// carregar todos do LDAP.
+pub async fn consultar_escolha_ldap<F: FonteLdap>(
+    dre: &str,
+    nome: &str,
+    escolhido: Option<&str>,
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    uids: Option<&UidsEmCache>,
+    fonte: &F,
+) -> Result<Consulta, ErroLdap> {
+    let mut ldap = fonte.abrir().await?;
+    let r =
+        consultar_escolha(dre, nome, escolhido, confundiveis, uids, &mut ldap)
+            .await;
+    fonte.fechar(ldap).await?;
+    r
+}
+
+/// Faz o mesmo que [consultar_cadastro_ldap], mas em um [DiretorioLdap] já
+/// conectado.
+pub async fn consultar_cadastro<D: DiretorioLdap>(
+    dre: &str,
+    nome: &str,
+    ldap: &mut D,
+) -> Result<Consulta, ErroLdap> {
+    consultar_escolha(dre, nome, None, None, None, ldap).await
+}
+
+/// Faz o mesmo que [consultar_escolha_ldap], mas em um [DiretorioLdap] já
+/// conectado.
+pub async fn consultar_escolha<D: DiretorioLdap>(
     dre: &str,
     nome: &str,
-    ldap_url: &str,
-    bind_dn: &str,
-    bind_pw: &str,
+    escolhido: Option<&str>,
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    uids: Option<&UidsEmCache>,
+    ldap: &mut D,
 ) -> Result<Consulta, ErroLdap> {
-    rodar_ldap(ldap_url, bind_dn, bind_pw, |mut ldap| async move {
-        match consulta_dre(dre, &mut ldap).await {
-            Err(err) => (Err(err), ldap),
-            Ok(Some(uid)) => (Ok(Consulta::CadastroRedundante(uid)), ldap),
-            Ok(None) => match achar_nome_livre(nome, &mut ldap).await {
-                Err(err) => (Err(err), ldap),
-                Ok(uid) => (Ok(Consulta::CadastroDisponivel(uid)), ldap),
-            },
+    if let Some(uid) = consulta_dre(dre, ldap).await? {
+        return Ok(Consulta::CadastroRedundante(uid));
+    }
+
+    let Some(escolhido) = escolhido else {
+        let mut livres =
+            livres(nome, 1, Some(dre), confundiveis, uids, ldap).await?;
+        return livres
+            .pop()
+            .map(Consulta::CadastroDisponivel)
+            .ok_or(ErroLdap::UsuarioDificil);
+    };
+    let escolhido = escolhido.to_lowercase();
+    if !usuarios_existentes(std::slice::from_ref(&escolhido), Some(dre), ldap)
+        .await?
+        .is_empty()
+    {
+        return Err(ErroLdap::UsernameOcupado(escolhido));
+    }
+    if let Some(confundiveis) = confundiveis {
+        let existentes = existentes(Some(confundiveis), uids, ldap).await?;
+        if let Some(parecido) = existentes.parecido(confundiveis, &escolhido) {
+            return Err(ErroLdap::UsernameConfundivel {
+                existente: parecido.to_string(),
+                username: escolhido,
+            });
         }
-    })
-    .await
+    }
+    Ok(Consulta::CadastroDisponivel(escolhido))
 }
 
-async fn consulta_dre(
+async fn consulta_dre<D: DiretorioLdap>(
     dre: &str,
-    ldap: &mut Ldap,
+    ldap: &mut D,
 ) -> Result<Option<String>, ErroLdap> {
-    let search_dre = format!("(dre={})", ldap_escape(dre));
+    let search_dre = filtros::igual("dre", dre);
 
-    let (dre_s, _) = ldap
-        .search(
+    let dre_s = ldap
+        .buscar(
             "dc=dcc,dc=ufrj,dc=br",
             Scope::Subtree,
             &search_dre,
             vec!["uid"],
         )
-        .await?
-        .success()?;
+        .await?;
 
-    let Some(dre_s) = dre_s.first() else {
+    let Some(mut dre_s) = dre_s.into_iter().next() else {
         return Ok(None);
     };
 
-    // TODO: precisa desse clone?
-    let dre_s = SearchEntry::construct(dre_s.clone());
     let uid = dre_s
         .attrs
-        .get("uid")
-        .ok_or(ErroLdap::FalhaUid)?
-        .first()
+        .remove("uid")
+        .and_then(|v| v.into_iter().next())
         .ok_or(ErroLdap::FalhaUid)?;
 
-    Ok(Some(uid.to_string()))
+    Ok(Some(uid))
+}
+
+/// Os usernames candidatos do `nome`, na ordem de [`Nome::usernames`].
+pub fn candidatos(nome: &Nome) -> impl Iterator<Item = String> {
+    nome.usernames()
 }
 
-async fn achar_nome_livre(
+/// Se o `username` é um dos [candidatos] do `nome`, sem diferenciar
+/// maiúsculas de minúsculas.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::consulta::e_candidato;
+/// # use alumnic::utils::nome::Nome;
+/// let nome: Nome = "Ana Braga".parse().unwrap();
+/// assert!(e_candidato(&nome, "anabraga"));
+/// assert!(e_candidato(&nome, "AnaB"));
+/// assert!(!e_candidato(&nome, "anab2"));
+/// ```
+pub fn e_candidato(nome: &Nome, username: &str) -> bool {
+    let username = username.to_lowercase();
+    candidatos(nome).any(|u| u == username)
+}
+
+/// Acha até `quantidade` usernames livres para o `nome`, na ordem dos
+/// [candidatos], para o aluno escolher um deles. Os candidatos são
+/// consultados em lotes de [`TAMANHO_LOTE`], com uma busca por lote. Com os
+/// `confundiveis` configurados, os que se confundem com um uid existente
+/// também ficam de fora, comparados com os `uids` em cache, se houver.
+///
+/// # Errors
+///
+/// Retorna [ErroLdap::UsuarioDificil] se nenhum candidato estiver livre.
+pub async fn achar_nomes_livres<D: DiretorioLdap>(
     nome: &str,
-    ldap: &mut Ldap,
-) -> Result<String, ErroLdap> {
-    for username in nome.parse::<Nome>()?.usernames() {
-        if !consulta_usuario_existe(&username, ldap).await? {
-            return Ok(username);
+    quantidade: usize,
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    uids: Option<&UidsEmCache>,
+    ldap: &mut D,
+) -> Result<Vec<String>, ErroLdap> {
+    livres(nome, quantidade, None, confundiveis, uids, ldap).await
+}
+
+/// Faz o mesmo que [achar_nomes_livres], com as
+/// [reservas](crate::ldap::reservas) do aluno com o DRE `dono` contando como
+/// livres.
+async fn livres<D: DiretorioLdap>(
+    nome: &str,
+    quantidade: usize,
+    dono: Option<&str>,
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    uids: Option<&UidsEmCache>,
+    ldap: &mut D,
+) -> Result<Vec<String>, ErroLdap> {
+    let nome = nome.parse::<Nome>()?;
+    let existentes = existentes(confundiveis, uids, ldap).await?;
+    let mut candidatos = candidatos(&nome);
+    let mut livres = vec![];
+
+    'busca: while livres.len() < quantidade {
+        let lote: Vec<_> = candidatos.by_ref().take(TAMANHO_LOTE).collect();
+        if lote.is_empty() {
+            break;
         }
+
+        let ocupados = usuarios_existentes(&lote, dono, ldap).await?;
+        for username in lote {
+            if livres.len() == quantidade {
+                break 'busca;
+            }
+            let confundivel = confundiveis
+                .and_then(|cfg| existentes.parecido(cfg, &username))
+                .is_some();
+            if !ocupados.contains(&username.to_lowercase()) && !confundivel {
+                livres.push(username);
+            }
+        }
+    }
+
+    if livres.is_empty() {
+        return Err(ErroLdap::UsuarioDificil);
+    }
+    Ok(livres)
+}
+
+/// Faz o mesmo que [achar_nomes_livres], abrindo uma conexão com a `fonte`.
+pub async fn achar_nomes_livres_ldap<F: FonteLdap>(
+    nome: &str,
+    quantidade: usize,
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    uids: Option<&UidsEmCache>,
+    fonte: &F,
+) -> Result<Vec<String>, ErroLdap> {
+    let mut ldap = fonte.abrir().await?;
+    let r = achar_nomes_livres(nome, quantidade, confundiveis, uids, &mut ldap)
+        .await;
+    fonte.fechar(ldap).await?;
+    r
+}
+
+/// Os uids que já existem no LDAP, carregados de uma vez para as operações em
+/// lote. Os usernames são escolhidos em memória, e só o escolhido é
+/// confirmado no LDAP.
+#[derive(Debug, Clone, Default)]
+pub struct UidsExistentes(HashSet<String>);
+
+impl UidsExistentes {
+    /// Carrega todos os uids do LDAP com uma busca só, contando os
+    /// [reservados](crate::ldap::reservas) que ainda valem.
+    pub async fn carregar<D: DiretorioLdap>(
+        ldap: &mut D,
+    ) -> Result<Self, ErroLdap> {
+        let entradas = ldap
+            .buscar(
+                "dc=dcc,dc=ufrj,dc=br",
+                Scope::Subtree,
+                "(uid=*)",
+                vec!["uid", ATRIBUTO_DONO, ATRIBUTO_VALIDADE],
+            )
+            .await?;
+
+        Ok(UidsExistentes(uids_ocupados(entradas, None)))
+    }
+
+    /// Se o `username` está entre os uids carregados ou reservados.
+    pub fn contem(&self, username: &str) -> bool {
+        self.0.contains(&username.to_lowercase())
+    }
+
+    /// Marca o `username` como ocupado, como depois de criar a conta, para
+    /// que ele não seja escolhido de novo no mesmo lote.
+    pub fn reservar(&mut self, username: &str) {
+        self.0.insert(username.to_lowercase());
+    }
+
+    /// O primeiro dos uids carregados ou reservados que se confunde com o
+    /// `username`, pelos critérios de `cfg`.
+    pub fn parecido(
+        &self,
+        cfg: &ConfiguracaoConfundiveis,
+        username: &str,
+    ) -> Option<&str> {
+        cfg.parecido(username, self.0.iter().map(String::as_str))
+    }
+
+    /// Acha o primeiro username livre para o `nome`, escolhendo em memória e
+    /// confirmando no LDAP. Um candidato que já existe no LDAP, criado depois
+    /// da carga, passa a constar como ocupado. Com os `confundiveis`
+    /// configurados, os que se confundem com um uid carregado ficam de fora.
+    /// O username retornado fica reservado.
+    pub async fn achar_nome_livre<D: DiretorioLdap>(
+        &mut self,
+        nome: &str,
+        confundiveis: Option<&ConfiguracaoConfundiveis>,
+        ldap: &mut D,
+    ) -> Result<String, ErroLdap> {
+        let nome = nome.parse::<Nome>()?;
+
+        for username in candidatos(&nome) {
+            if self.contem(&username)
+                || confundiveis
+                    .and_then(|cfg| self.parecido(cfg, &username))
+                    .is_some()
+            {
+                continue;
+            }
+
+            self.reservar(&username);
+            if usuarios_existentes(std::slice::from_ref(&username), None, ldap)
+                .await?
+                .is_empty()
+            {
+                return Ok(username);
+            }
+        }
+
+        Err(ErroLdap::UsuarioDificil)
+    }
+}
+
+/// Os [UidsExistentes] guardados por um tempo curto, para que as consultas
+/// públicas, como a dos usernames livres, não busquem todos os uids do LDAP
+/// a cada requisição ao comparar os confundíveis. Um uid criado nesse meio
+/// tempo só entra nessa comparação na carga seguinte; por isso a consulta
+/// que antecede a criação da conta não usa o cache.
+#[derive(Debug)]
+pub struct UidsEmCache {
+    validade: Duration,
+    carga: Mutex<Option<(Instant, Arc<UidsExistentes>)>>,
+}
+
+impl UidsEmCache {
+    /// Um cache vazio, cujas cargas valem por `validade`.
+    pub fn new(validade: Duration) -> Self {
+        Self {
+            validade,
+            carga: Mutex::new(None),
+        }
+    }
+
+    /// Os uids carregados há menos da validade do cache ou, se não houver,
+    /// carregados agora do `ldap`. As requisições que chegam durante a carga
+    /// esperam por ela em vez de buscar os uids de novo.
+    pub async fn carregar<D: DiretorioLdap>(
+        &self,
+        ldap: &mut D,
+    ) -> Result<Arc<UidsExistentes>, ErroLdap> {
+        let mut carga = self.carga.lock().await;
+        if let Some((quando, uids)) = &*carga
+            && quando.elapsed() < self.validade
+        {
+            return Ok(uids.clone());
+        }
+
+        let uids = Arc::new(UidsExistentes::carregar(ldap).await?);
+        *carga = Some((Instant::now(), uids.clone()));
+        Ok(uids)
+    }
+}
+
+/// Os uids com que os candidatos são comparados: nenhum sem os
+/// `confundiveis`, os dos `uids` em cache ou, sem eles, todos os do LDAP.
+async fn existentes<D: DiretorioLdap>(
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    uids: Option<&UidsEmCache>,
+    ldap: &mut D,
+) -> Result<Arc<UidsExistentes>, ErroLdap> {
+    match (confundiveis, uids) {
+        (None, _) => Ok(Arc::default()),
+        (Some(_), Some(uids)) => uids.carregar(ldap).await,
+        (Some(_), None) => Ok(Arc::new(UidsExistentes::carregar(ldap).await?)),
     }
-    Err(ErroLdap::UsuarioDificil)
 }
 
-async fn consulta_usuario_existe(
-    username: &str,
-    ldap: &mut Ldap,
-) -> Result<bool, ErroLdap> {
-    let search_username = format!("(uid={})", ldap_escape(username));
+/// Faz o mesmo que [consultar_cadastro], mas escolhendo o username com os
+/// `uids` já carregados, para os cadastros em lote.
+pub async fn consultar_cadastro_com_uids<D: DiretorioLdap>(
+    dre: &str,
+    nome: &str,
+    uids: &mut UidsExistentes,
+    confundiveis: Option<&ConfiguracaoConfundiveis>,
+    ldap: &mut D,
+) -> Result<Consulta, ErroLdap> {
+    match consulta_dre(dre, ldap).await? {
+        Some(uid) => Ok(Consulta::CadastroRedundante(uid)),
+        None => Ok(Consulta::CadastroDisponivel(
+            uids.achar_nome_livre(nome, confundiveis, ldap).await?,
+        )),
+    }
+}
 
-    let (username_s, _) = ldap
-        .search(
+/// Quais dos `usernames` já existem no LDAP, em minúsculas, com uma busca só.
+/// As reservas do aluno com o DRE `dono` ficam de fora.
+async fn usuarios_existentes<D: DiretorioLdap>(
+    usernames: &[String],
+    dono: Option<&str>,
+    ldap: &mut D,
+) -> Result<HashSet<String>, ErroLdap> {
+    let filtro =
+        filtros::ou(usernames.iter().map(|u| filtros::igual("uid", u)));
+
+    let entradas = ldap
+        .buscar(
             "dc=dcc,dc=ufrj,dc=br",
             Scope::Subtree,
-            &search_username,
-            Vec::<&str>::new(),
+            &filtro,
+            vec!["uid", ATRIBUTO_DONO, ATRIBUTO_VALIDADE],
         )
-        .await?
-        .success()?;
+        .await?;
+
+    Ok(uids_ocupados(entradas, dono))
+}
+
+/// Os uids, em minúsculas, das `entradas` que os
+/// [ocupam](crate::ldap::reservas) para o `dono`.
+fn uids_ocupados(
+    entradas: Vec<ldap3::SearchEntry>,
+    dono: Option<&str>,
+) -> HashSet<String> {
+    let agora = Utc::now().timestamp();
+    entradas
+        .into_iter()
+        .filter(|e| ocupa(e, dono, agora))
+        .filter_map(|mut e| e.attrs.remove("uid"))
+        .flatten()
+        .map(|u| u.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    async fn diretorio_com(uids: &[(&str, &str)]) -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        for (uid, dre) in uids {
+            d.adicionar(
+                &format!("uid={uid},ou=alunos,dc=dcc,dc=ufrj,dc=br"),
+                vec![("uid", [*uid].into()), ("dccDRE", [*dre].into())],
+            )
+            .await
+            .unwrap();
+        }
+        d
+    }
+
+    #[tokio::test]
+    async fn escolhe_o_primeiro_username_livre() {
+        let mut d =
+            diretorio_com(&[("valterls", "1"), ("valterlsilva", "2")]).await;
+
+        let r = consultar_cadastro("3", "Valter Luiz da Silva", &mut d)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            r,
+            Consulta::CadastroDisponivel(uid) if uid == "valterluizs"
+        ));
+    }
+
+    #[tokio::test]
+    async fn sem_combinacao_livre_e_usuario_dificil() {
+        let mut d = diretorio_com(&[("anab", "1"), ("anabraga", "2")]).await;
+
+        let r = consultar_cadastro("3", "Ana Braga", &mut d).await;
+
+        assert!(matches!(r, Err(ErroLdap::UsuarioDificil)));
+    }
+
+    /// Um diretório que conta as buscas feitas.
+    struct Contador(DiretorioMemoria, usize);
+
+    impl DiretorioLdap for Contador {
+        async fn buscar(
+            &mut self,
+            base: &str,
+            escopo: Scope,
+            filtro: &str,
+            atributos: Vec<&str>,
+        ) -> Result<Vec<ldap3::SearchEntry>, ErroLdap> {
+            self.1 += 1;
+            self.0.buscar(base, escopo, filtro, atributos).await
+        }
+
+        async fn adicionar(
+            &mut self,
+            dn: &str,
+            atributos: Vec<(&str, HashSet<&str>)>,
+        ) -> Result<(), ErroLdap> {
+            self.0.adicionar(dn, atributos).await
+        }
+
+        async fn modificar(
+            &mut self,
+            dn: &str,
+            mods: Vec<ldap3::Mod<&str>>,
+        ) -> Result<(), ErroLdap> {
+            self.0.modificar(dn, mods).await
+        }
+
+        async fn gravar_binario(
+            &mut self,
+            dn: &str,
+            atributo: &str,
+            valor: &[u8],
+        ) -> Result<(), ErroLdap> {
+            self.0.gravar_binario(dn, atributo, valor).await
+        }
+
+        async fn mover(
+            &mut self,
+            dn: &str,
+            nova_base: &str,
+        ) -> Result<(), ErroLdap> {
+            self.0.mover(dn, nova_base).await
+        }
+
+        async fn remover(&mut self, dn: &str) -> Result<(), ErroLdap> {
+            self.0.remover(dn).await
+        }
+
+        async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+            self.0.quem_sou().await
+        }
+    }
 
-    Ok(!username_s.is_empty())
+    #[tokio::test]
+    async fn consulta_os_candidatos_em_lotes() {
+        // As combinações do nome não cabem num lote só, e as do primeiro estão
+        // ocupadas
+        let nome = "Ana Bia Cal Di Eva Flo Gil";
+        let usernames: Vec<_> =
+            nome.parse::<Nome>().unwrap().usernames().collect();
+        assert!(usernames.len() > TAMANHO_LOTE);
+        let uids: Vec<_> = usernames[..TAMANHO_LOTE]
+            .iter()
+            .enumerate()
+            .map(|(i, u)| (u.to_uppercase(), i.to_string()))
+            .collect();
+        let uids: Vec<_> =
+            uids.iter().map(|(u, d)| (u.as_str(), d.as_str())).collect();
+        let mut d = Contador(diretorio_com(&uids).await, 0);
+
+        let r = consultar_cadastro("999", nome, &mut d).await.unwrap();
+
+        assert!(matches!(
+            r,
+            Consulta::CadastroDisponivel(uid) if uid == usernames[TAMANHO_LOTE]
+        ));
+        // Uma busca pelo DRE e uma por lote
+        assert_eq!(d.1, 3);
+    }
+
+    #[tokio::test]
+    async fn escolhe_os_usernames_com_os_uids_carregados() {
+        let mut d = Contador(
+            diretorio_com(&[("anabcd", "1"), ("anabcdi", "2")]).await,
+            0,
+        );
+        let mut uids = UidsExistentes::carregar(&mut d).await.unwrap();
+        assert!(uids.contem("ANABCD"));
+
+        // Uma conta criada depois da carga é descoberta na confirmação
+        d.0.adicionar(
+            "uid=anabcald,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+            vec![("uid", ["anabcald"].into()), ("dccDRE", ["3"].into())],
+        )
+        .await
+        .unwrap();
+
+        let mut escolhidos = vec![];
+        for dre in ["4", "5"] {
+            match consultar_cadastro_com_uids(
+                dre,
+                "Ana Bia Cal Di",
+                &mut uids,
+                None,
+                &mut d,
+            )
+            .await
+            .unwrap()
+            {
+                Consulta::CadastroDisponivel(uid) => escolhidos.push(uid),
+                r => panic!("{r:?}"),
+            }
+        }
+
+        // O mesmo nome no lote recebe outro username
+        assert_eq!(escolhidos, ["anabcaldi", "anabiacd"]);
+        // A carga, e por aluno o DRE e a confirmação (duas na primeira)
+        assert_eq!(d.1, 1 + 3 + 2);
+    }
+
+    #[tokio::test]
+    async fn lista_os_usernames_livres() {
+        let mut d =
+            diretorio_com(&[("valterls", "1"), ("valterluizs", "2")]).await;
+
+        let livres =
+            achar_nomes_livres("Valter Luiz da Silva", 3, None, None, &mut d)
+                .await
+                .unwrap();
+        assert_eq!(livres, ["valterlsilva", "valterluizsilva"]);
+
+        let mut d = diretorio_com(&[("anab", "1"), ("anabraga", "2")]).await;
+        let r = achar_nomes_livres("Ana Braga", 3, None, None, &mut d).await;
+        assert!(matches!(r, Err(ErroLdap::UsuarioDificil)));
+    }
+
+    #[tokio::test]
+    async fn aceita_o_username_escolhido_se_estiver_livre() {
+        let mut d = diretorio_com(&[("anab", "1")]).await;
+
+        let r = consultar_escolha(
+            "2",
+            "Ana Braga",
+            Some("AnaBraga"),
+            None,
+            None,
+            &mut d,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            r,
+            Consulta::CadastroDisponivel(uid) if uid == "anabraga"
+        ));
+
+        let r = consultar_escolha(
+            "2",
+            "Ana Braga",
+            Some("anab"),
+            None,
+            None,
+            &mut d,
+        )
+        .await;
+        assert!(
+            matches!(r, Err(ErroLdap::UsernameOcupado(uid)) if uid == "anab")
+        );
+    }
+
+    #[tokio::test]
+    async fn pula_os_usernames_confundiveis() {
+        let mut d = diretorio_com(&[("anba", "1")]).await;
+        let cfg = ConfiguracaoConfundiveis::default();
+
+        let livres =
+            achar_nomes_livres("Ana Braga", 3, Some(&cfg), None, &mut d)
+                .await
+                .unwrap();
+        assert_eq!(livres, ["anabraga"]);
+
+        let r = consultar_escolha(
+            "3",
+            "Ana Braga",
+            Some("anab"),
+            Some(&cfg),
+            None,
+            &mut d,
+        )
+        .await;
+        assert!(matches!(
+            r,
+            Err(ErroLdap::UsernameConfundivel { existente, .. })
+                if existente == "anba"
+        ));
+
+        // Sem a configuração, só os iguais são rejeitados
+        let r = consultar_cadastro("3", "Ana Braga", &mut d).await.unwrap();
+        assert!(matches!(
+            r,
+            Consulta::CadastroDisponivel(uid) if uid == "anab"
+        ));
+    }
+
+    #[tokio::test]
+    async fn compara_os_confundiveis_com_os_uids_em_cache() {
+        let mut d = Contador(diretorio_com(&[("anba", "1")]).await, 0);
+        let cfg = ConfiguracaoConfundiveis::default();
+        let cache = UidsEmCache::new(Duration::from_secs(60));
+
+        let livres = achar_nomes_livres(
+            "Ana Braga",
+            1,
+            Some(&cfg),
+            Some(&cache),
+            &mut d,
+        )
+        .await
+        .unwrap();
+        assert_eq!(livres, ["anabraga"]);
+        let buscas = d.1;
+
+        // A segunda consulta só confere os candidatos no LDAP
+        d.0.adicionar(
+            "uid=anabrga,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+            vec![("uid", ["anabrga"].into()), ("dccDRE", ["2"].into())],
+        )
+        .await
+        .unwrap();
+        let livres = achar_nomes_livres(
+            "Ana Braga",
+            1,
+            Some(&cfg),
+            Some(&cache),
+            &mut d,
+        )
+        .await
+        .unwrap();
+        assert_eq!(livres, ["anabraga"]);
+        assert_eq!(d.1 - buscas, buscas - 1);
+
+        // Sem o cache, o uid novo é carregado e o candidato fica de fora
+        let r =
+            achar_nomes_livres("Ana Braga", 1, Some(&cfg), None, &mut d).await;
+        assert!(matches!(r, Err(ErroLdap::UsuarioDificil)));
+    }
+
+    #[tokio::test]
+    async fn detecta_dre_ja_cadastrado() {
+        let mut d = diretorio_com(&[("anab", "123456789")]).await;
+
+        let r = consultar_cadastro("123456789", "Ana Braga Souza", &mut d)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            r,
+            Consulta::CadastroRedundante(uid) if uid == "anab"
+        ));
+    }
 }
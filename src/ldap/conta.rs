@@ -0,0 +1,235 @@
+//! Busca das contas de alunos já cadastradas e do estado em que elas estão.
+use crate::ldap::ErroLdap;
+use crate::ldap::diretorio::{DiretorioLdap, PedidoPagina};
+use crate::ldap::filtros;
+use ldap3::{Scope, SearchEntry};
+use serde::Serialize;
+
+/// Base das buscas por contas de alunos.
+pub const BASE_CONTAS: &str = "dc=dcc,dc=ufrj,dc=br";
+
+/// Atributos lidos de uma conta.
+const ATRIBUTOS: [&str; 10] = [
+    "uid",
+    "dccDRE",
+    "gecos",
+    "mail",
+    "homeDirectory",
+    "emailExterno",
+    "estadoConta",
+    "dataRenovacao",
+    "dataRemocao",
+    "dataCriacao",
+];
+
+/// O estado de uma conta, guardado no atributo `estadoConta`. As contas sem o
+/// atributo, criadas antes dele existir, são consideradas ativas.
+///
+/// Uma conta passa de ativa para carência, suspensa e removida conforme os
+/// prazos vencem, o que é feito pelo [`prazos`](crate::prazos).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EstadoConta {
+    /// A conta funciona normalmente.
+    Ativa,
+    /// O vínculo não foi renovado até a data limite. A conta ainda funciona,
+    /// mas deixa de funcionar se o aluno não renovar até o fim da carência.
+    Carencia,
+    /// A conta está bloqueada e será removida na `dataRemocao`.
+    Suspensa,
+    /// A conta foi removida. A entrada continua no LDAP, bloqueada, para
+    /// guardar o histórico do aluno.
+    Removida,
+    /// O aluno se formou. A entrada foi movida para a OU de egressos e só
+    /// guarda a identidade e o email de contato.
+    Egresso,
+}
+
+impl EstadoConta {
+    /// O valor gravado no atributo `estadoConta`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::conta::EstadoConta;
+    /// assert_eq!(EstadoConta::Carencia.valor(), "carencia");
+    /// assert_eq!(EstadoConta::do_valor("ativa"), Some(EstadoConta::Ativa));
+    /// assert_eq!(EstadoConta::do_valor("CARENCIA"), Some(EstadoConta::Carencia));
+    /// assert_eq!(EstadoConta::do_valor("outro"), None);
+    /// ```
+    pub fn valor(&self) -> &'static str {
+        match self {
+            EstadoConta::Ativa => "ativa",
+            EstadoConta::Carencia => "carencia",
+            EstadoConta::Suspensa => "suspensa",
+            EstadoConta::Removida => "removida",
+            EstadoConta::Egresso => "egresso",
+        }
+    }
+
+    /// Se a conta está bloqueada, sem acesso aos laboratórios.
+    pub fn bloqueada(&self) -> bool {
+        matches!(
+            self,
+            EstadoConta::Suspensa
+                | EstadoConta::Removida
+                | EstadoConta::Egresso
+        )
+    }
+
+    /// O estado representado pelo valor do atributo `estadoConta`.
+    pub fn do_valor(valor: &str) -> Option<Self> {
+        match valor.to_lowercase().as_str() {
+            "ativa" => Some(EstadoConta::Ativa),
+            "carencia" => Some(EstadoConta::Carencia),
+            "suspensa" => Some(EstadoConta::Suspensa),
+            "removida" => Some(EstadoConta::Removida),
+            "egresso" => Some(EstadoConta::Egresso),
+            _ => None,
+        }
+    }
+}
+
+/// A OU da conta, como `alunos` ou `profcomp`, tirada do DN.
+pub(crate) fn ou(dn: &str) -> String {
+    filtros::rdns(dn)
+        .find_map(|rdn| {
+            let (atributo, valor) = rdn.split_once('=')?;
+            atributo
+                .trim()
+                .eq_ignore_ascii_case("ou")
+                .then(|| valor.trim())
+        })
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Uma conta de aluno cadastrada no LDAP.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conta {
+    pub dn: String,
+    pub uid: String,
+    pub dre: String,
+    /// O nome completo, sem acentos.
+    pub nome: String,
+    /// O email externo, usado para avisar o aluno.
+    pub email: Option<String>,
+    /// O email institucional, do atributo `mail`.
+    pub email_institucional: Option<String>,
+    /// O home directory, no servidor de arquivos.
+    pub home: Option<String>,
+    pub estado: EstadoConta,
+    /// O dia (contado desde 01/01/1970) em que o vínculo precisa ser renovado.
+    pub data_renovacao: Option<i64>,
+    /// O dia (contado desde 01/01/1970) em que uma conta suspensa será
+    /// removida.
+    pub data_remocao: Option<i64>,
+    /// O dia (contado desde 01/01/1970) em que a conta foi criada.
+    pub data_criacao: Option<i64>,
+}
+
+impl Conta {
+    fn da_entrada(e: SearchEntry) -> Result<Self, ErroLdap> {
+        let primeiro = |atributo: &str| {
+            e.attrs.get(atributo).and_then(|v| v.first()).cloned()
+        };
+
+        Ok(Conta {
+            uid: primeiro("uid").ok_or(ErroLdap::FalhaUid)?,
+            dre: primeiro("dccDRE").unwrap_or_default(),
+            nome: primeiro("gecos").unwrap_or_default(),
+            email: primeiro("emailExterno"),
+            email_institucional: primeiro("mail"),
+            home: primeiro("homeDirectory"),
+            estado: match primeiro("estadoConta") {
+                Some(v) => EstadoConta::do_valor(&v)
+                    .ok_or(ErroLdap::EstadoInvalido(v))?,
+                None => EstadoConta::Ativa,
+            },
+            data_renovacao: primeiro("dataRenovacao")
+                .and_then(|d| d.parse().ok()),
+            data_remocao: primeiro("dataRemocao").and_then(|d| d.parse().ok()),
+            data_criacao: primeiro("dataCriacao").and_then(|d| d.parse().ok()),
+            dn: e.dn,
+        })
+    }
+}
+
+/// Busca a conta do aluno com o `dre`.
+pub async fn buscar_conta_por_dre<D: DiretorioLdap>(
+    dre: &str,
+    ldap: &mut D,
+) -> Result<Option<Conta>, ErroLdap> {
+    let filtro = filtros::igual("dre", dre);
+
+    buscar_contas(&filtro, ldap)
+        .await
+        .map(|contas| contas.into_iter().next())
+}
+
+/// Busca a conta do aluno com o `uid`.
+pub async fn buscar_conta_por_uid<D: DiretorioLdap>(
+    uid: &str,
+    ldap: &mut D,
+) -> Result<Option<Conta>, ErroLdap> {
+    let filtro = filtros::igual("uid", uid);
+
+    buscar_contas(&filtro, ldap)
+        .await
+        .map(|contas| contas.into_iter().next())
+}
+
+/// Busca as contas que satisfazem o `filtro`, que é combinado com a exigência
+/// de ser uma conta de aluno.
+pub async fn buscar_contas<D: DiretorioLdap>(
+    filtro: &str,
+    ldap: &mut D,
+) -> Result<Vec<Conta>, ErroLdap> {
+    ldap.buscar(
+        BASE_CONTAS,
+        Scope::Subtree,
+        &filtros::e([&filtros::igual("objectClass", "dccAluno"), filtro]),
+        ATRIBUTOS.to_vec(),
+    )
+    .await?
+    .into_iter()
+    .map(Conta::da_entrada)
+    .collect()
+}
+
+/// Uma página das contas de alunos, como pedida por
+/// [`listar_contas`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PaginaDeContas {
+    pub contas: Vec<Conta>,
+    /// Quantas contas existem ao todo, se o servidor informar.
+    pub total: Option<usize>,
+    /// Se não há páginas depois desta.
+    pub ultima: bool,
+}
+
+/// Lista as contas de alunos na página do `pedido`, que é buscada e ordenada
+/// pelo próprio servidor, sem carregar as outras.
+pub async fn listar_contas<D: DiretorioLdap>(
+    pedido: &PedidoPagina,
+    ldap: &mut D,
+) -> Result<PaginaDeContas, ErroLdap> {
+    let pagina = ldap
+        .buscar_pagina(
+            BASE_CONTAS,
+            Scope::Subtree,
+            "(objectClass=dccAluno)",
+            ATRIBUTOS.to_vec(),
+            pedido,
+        )
+        .await?;
+
+    Ok(PaginaDeContas {
+        contas: pagina
+            .entradas
+            .into_iter()
+            .map(Conta::da_entrada)
+            .collect::<Result<_, _>>()?,
+        total: pagina.total,
+        ultima: pagina.ultima,
+    })
+}
@@ -0,0 +1,259 @@
+//! Verificação dos contadores do objeto `sambaDomain`, o `uidNumber` e o
+//! `sambaNextRid`, que guardam os últimos IDs dados a uma conta. Um contador
+//! atrasado, como depois de uma conta criada à mão, faz o próximo cadastro
+//! repetir um ID.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use ldap3::{Mod, Scope};
+use std::collections::BTreeMap;
+
+/// Os contadores do domínio do Samba.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contadores {
+    pub dn: String,
+    /// O último uidNumber dado.
+    pub uid_number: i64,
+    /// O último RID do Samba dado.
+    pub next_rid: i64,
+}
+
+/// O resultado da verificação dos contadores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificacaoContadores {
+    pub contadores: Contadores,
+    /// O maior uidNumber usado por uma conta.
+    pub maior_uid_number: Option<i64>,
+    /// O maior RID usado em um `sambaSID` do domínio.
+    pub maior_rid: Option<i64>,
+    /// Os uidNumbers usados por mais de uma conta, com os uids delas. As
+    /// contas removidas ficam de fora, já que o uidNumber delas pode ter sido
+    /// [reutilizado](crate::ldap::uids_liberados).
+    pub duplicados: BTreeMap<i64, Vec<String>>,
+}
+
+impl VerificacaoContadores {
+    /// Os contadores corrigidos, que passam a ser os maiores IDs usados, ou
+    /// [None] se nenhum estiver atrasado. Um contador adiantado não é
+    /// mudado, já que os IDs pulados só ficam sem uso.
+    pub fn correcao(&self) -> Option<Contadores> {
+        let uid_number = self
+            .maior_uid_number
+            .map_or(self.contadores.uid_number, |maior| {
+                maior.max(self.contadores.uid_number)
+            });
+        let next_rid =
+            self.maior_rid.map_or(self.contadores.next_rid, |maior| {
+                maior.max(self.contadores.next_rid)
+            });
+
+        let corrigidos = Contadores {
+            dn: self.contadores.dn.clone(),
+            uid_number,
+            next_rid,
+        };
+        (corrigidos != self.contadores).then_some(corrigidos)
+    }
+}
+
+/// O RID de um `sambaSID` com o `prefixo` do domínio, como o `9001` de
+/// `S-1-5-21-1-2-3-9001`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::contadores::rid;
+/// assert_eq!(rid("S-1-5-21-1-2-3-9001", "S-1-5-21-1-2-3-"), Some(9001));
+/// assert_eq!(rid("S-1-5-21-9-9-9-9001", "S-1-5-21-1-2-3-"), None);
+/// ```
+pub fn rid(sid: &str, prefixo: &str) -> Option<i64> {
+    sid.strip_prefix(prefixo)?.parse().ok()
+}
+
+/// Lê os contadores do objeto `sambaDomain`.
+pub async fn ler_contadores<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<Contadores, ErroLdap> {
+    let dominio = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::OneLevel,
+            "(objectClass=sambaDomain)",
+            vec!["uidNumber", "sambaNextRid"],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ErroLdap::ErroSamba)?;
+
+    let numero = |atributo: &str| {
+        dominio
+            .attrs
+            .get(atributo)
+            .and_then(|v| v.first())
+            .and_then(|v| v.parse().ok())
+            .ok_or(ErroLdap::ErroSamba)
+    };
+    Ok(Contadores {
+        uid_number: numero("uidNumber")?,
+        next_rid: numero("sambaNextRid")?,
+        dn: dominio.dn,
+    })
+}
+
+/// Compara os contadores com os IDs usados pelas entradas, com os SIDs do
+/// domínio começando com o `prefixo_sid`.
+pub async fn verificar_contadores<D: DiretorioLdap>(
+    prefixo_sid: &str,
+    ldap: &mut D,
+) -> Result<VerificacaoContadores, ErroLdap> {
+    let contadores = ler_contadores(ldap).await?;
+
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            "(|(objectClass=posixAccount)(sambaSID=*))",
+            vec!["uid", "uidNumber", "sambaSID", "estadoConta"],
+        )
+        .await?;
+
+    let mut maior_rid = None;
+    let mut maior_uid_number = None;
+    let mut usos: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for mut e in entradas {
+        let mut primeiro = |atributo: &str| {
+            e.attrs.remove(atributo).and_then(|v| v.into_iter().next())
+        };
+        let uid = primeiro("uid");
+        let uid_number = primeiro("uidNumber").and_then(|n| n.parse().ok());
+        let rid = primeiro("sambaSID").and_then(|s| rid(&s, prefixo_sid));
+        let removida = primeiro("estadoConta").as_deref() == Some("removida");
+
+        maior_rid = maior_rid.max(rid);
+        if let (Some(uid), Some(uid_number)) = (uid, uid_number) {
+            maior_uid_number = maior_uid_number.max(Some(uid_number));
+            if !removida {
+                usos.entry(uid_number).or_default().push(uid);
+            }
+        }
+    }
+
+    Ok(VerificacaoContadores {
+        contadores,
+        maior_uid_number,
+        maior_rid,
+        duplicados: usos
+            .into_iter()
+            .filter(|(_, uids)| uids.len() > 1)
+            .collect(),
+    })
+}
+
+/// Troca os contadores `atuais` pelos `corrigidos`. Como na alocação dos IDs,
+/// os valores antigos são apagados junto, então a correção falha se outro
+/// cadastro mudar os contadores no meio.
+pub async fn corrigir_contadores<D: DiretorioLdap>(
+    atuais: &Contadores,
+    corrigidos: &Contadores,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let (uid_antigo, uid_novo) = (
+        atuais.uid_number.to_string(),
+        corrigidos.uid_number.to_string(),
+    );
+    let (rid_antigo, rid_novo) =
+        (atuais.next_rid.to_string(), corrigidos.next_rid.to_string());
+
+    ldap.modificar(
+        &atuais.dn,
+        vec![
+            Mod::Delete("uidNumber", [uid_antigo.as_str()].into()),
+            Mod::Add("uidNumber", [uid_novo.as_str()].into()),
+            Mod::Delete("sambaNextRid", [rid_antigo.as_str()].into()),
+            Mod::Add("sambaNextRid", [rid_novo.as_str()].into()),
+        ],
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    const DN_DOMINIO: &str = "sambaDomainName=DCC,dc=dcc,dc=ufrj,dc=br";
+    const PREFIXO: &str = "S-1-5-21-1-2-3-";
+
+    async fn diretorio(contas: &[(&str, &str, &str)]) -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(
+            DN_DOMINIO,
+            vec![
+                ("objectClass", ["sambaDomain"].into()),
+                ("uidNumber", ["5001"].into()),
+                ("sambaNextRid", ["9001"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        for (uid, uid_number, rid) in contas {
+            let sid = format!("{PREFIXO}{rid}");
+            d.adicionar(
+                &format!("uid={uid},ou=alunos,dc=dcc,dc=ufrj,dc=br"),
+                vec![
+                    ("objectClass", ["posixAccount"].into()),
+                    ("uid", [*uid].into()),
+                    ("uidNumber", [*uid_number].into()),
+                    ("sambaSID", [sid.as_str()].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+        d
+    }
+
+    #[tokio::test]
+    async fn contadores_em_dia_nao_mudam() {
+        let mut d =
+            diretorio(&[("joaops", "5000", "9000"), ("anacs", "5001", "9001")])
+                .await;
+
+        let v = verificar_contadores(PREFIXO, &mut d).await.unwrap();
+        assert_eq!(v.maior_uid_number, Some(5001));
+        assert_eq!(v.maior_rid, Some(9001));
+        assert!(v.duplicados.is_empty());
+        assert_eq!(v.correcao(), None);
+    }
+
+    #[tokio::test]
+    async fn detecta_e_corrige_contadores_atrasados_e_duplicatas() {
+        let mut d = diretorio(&[
+            ("joaops", "5001", "9000"),
+            ("anacs", "5001", "9001"),
+            ("manual", "5010", "9004"),
+        ])
+        .await;
+
+        let v = verificar_contadores(PREFIXO, &mut d).await.unwrap();
+        assert_eq!(
+            v.duplicados,
+            BTreeMap::from([(5001, vec!["anacs".into(), "joaops".into()])]),
+        );
+
+        let corrigidos = v.correcao().unwrap();
+        assert_eq!((corrigidos.uid_number, corrigidos.next_rid), (5010, 9004));
+        corrigir_contadores(&v.contadores, &corrigidos, &mut d)
+            .await
+            .unwrap();
+        assert_eq!(ler_contadores(&mut d).await.unwrap(), corrigidos);
+
+        // Com os contadores já mudados, a mesma correção falha
+        assert!(
+            corrigir_contadores(&v.contadores, &corrigidos, &mut d)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Leitura e ajuste do atributo `cota` das contas, o limite de espaço do
+//! home no servidor de arquivos.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope};
+
+/// A cota de uma conta.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CotaDaConta {
+    pub dn: String,
+    pub uid: String,
+    /// A cota atual. As contas sem o atributo, ou com um valor que não é um
+    /// número, ficam sem.
+    pub cota: Option<u64>,
+}
+
+/// Busca as cotas das contas de alunos, deixando de fora as removidas e as de
+/// egressos, que não têm mais home.
+pub async fn buscar_cotas<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<Vec<CotaDaConta>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            "(&(objectClass=dccAluno)(!(estadoConta=removida))\
+             (!(estadoConta=egresso)))",
+            vec!["uid", "cota"],
+        )
+        .await?;
+
+    Ok(entradas
+        .into_iter()
+        .filter_map(|mut e| {
+            let mut primeiro = |atributo: &str| {
+                e.attrs.remove(atributo).and_then(|v| v.into_iter().next())
+            };
+            Some(CotaDaConta {
+                uid: primeiro("uid")?,
+                cota: primeiro("cota").and_then(|c| c.trim().parse().ok()),
+                dn: e.dn,
+            })
+        })
+        .collect())
+}
+
+/// Os uids dos membros (`memberUid`) do grupo com o `cn`, ou [None] se o
+/// grupo não existir.
+pub async fn membros_do_grupo<D: DiretorioLdap>(
+    cn: &str,
+    ldap: &mut D,
+) -> Result<Option<Vec<String>>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtros::e([
+                filtros::igual("objectClass", "posixGroup"),
+                filtros::igual("cn", cn),
+            ]),
+            vec!["memberUid"],
+        )
+        .await?;
+
+    Ok(entradas
+        .into_iter()
+        .next()
+        .map(|mut e| e.attrs.remove("memberUid").unwrap_or_default()))
+}
+
+/// Troca a cota da conta `dn` por `cota`.
+pub async fn mudar_cota<D: DiretorioLdap>(
+    dn: &str,
+    cota: u64,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let cota = cota.to_string();
+    ldap.modificar(dn, vec![Mod::Replace("cota", [cota.as_str()].into())])
+        .await
+}
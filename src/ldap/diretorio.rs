@@ -0,0 +1,599 @@
+//! Abstração das operações feitas no diretório LDAP. O resto do módulo usa o
+//! [`DiretorioLdap`] em vez de usar o [`Ldap`](ldap3::Ldap) diretamente, o que permite
+//! testar a lógica de cadastro com o [`DiretorioMemoria`] sem precisar de um
+//! servidor de verdade.
+//!
+//! [`DiretorioMemoria`]: crate::ldap::memoria::DiretorioMemoria
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::ConexaoLdap;
+use crate::ldap::filtros::{separar_rdn, validar_dn};
+use crate::ldap::utils::medir;
+use ldap3::controls::{ControlType, PagedResults, RawControl};
+use ldap3::exop::{WhoAmI, WhoAmIResp};
+use ldap3::{Mod, Scope, SearchEntry};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use tokio::sync::OwnedMutexGuard;
+
+/// A ordem pedida numa [busca paginada](DiretorioLdap::buscar_pagina).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ordenacao {
+    pub atributo: String,
+    pub decrescente: bool,
+}
+
+impl Ordenacao {
+    /// Compara as entradas `a` e `b` pelo primeiro valor do atributo, como
+    /// números se os dois forem números. As entradas sem o atributo vão para
+    /// o fim, como na ordenação do servidor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::diretorio::Ordenacao;
+    /// # use ldap3::SearchEntry;
+    /// let entrada = |dia: Option<&str>| SearchEntry {
+    ///     dn: String::new(),
+    ///     attrs: dia
+    ///         .map(|d| ("dataCriacao".to_string(), vec![d.to_string()]))
+    ///         .into_iter()
+    ///         .collect(),
+    ///     bin_attrs: Default::default(),
+    /// };
+    /// let mut entradas = vec![entrada(None), entrada(Some("900")), entrada(Some("20300"))];
+    /// let ordenacao = Ordenacao {
+    ///     atributo: "dataCriacao".to_string(),
+    ///     decrescente: true,
+    /// };
+    /// entradas.sort_by(|a, b| ordenacao.comparar(a, b));
+    ///
+    /// let dias: Vec<_> = entradas.iter().map(|e| e.attrs.get("dataCriacao")).collect();
+    /// assert_eq!(dias, [Some(&vec!["20300".to_string()]), Some(&vec!["900".to_string()]), None]);
+    /// ```
+    pub fn comparar(&self, a: &SearchEntry, b: &SearchEntry) -> Ordering {
+        let valor = |e: &SearchEntry| {
+            e.attrs
+                .iter()
+                .find(|(atributo, _)| {
+                    atributo.eq_ignore_ascii_case(&self.atributo)
+                })
+                .and_then(|(_, v)| v.first().cloned())
+        };
+        match (valor(a), valor(b)) {
+            (Some(a), Some(b)) => {
+                let ordem = match (a.parse::<i64>(), b.parse::<i64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.cmp(&b),
+                };
+                if self.decrescente {
+                    ordem.reverse()
+                } else {
+                    ordem
+                }
+            },
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    /// O controle de ordenação no servidor (RFC 2891), com uma chave só. O
+    /// controle não é crítico: um servidor sem suporte a ele devolve as
+    /// entradas na ordem dele.
+    fn controle(&self) -> RawControl {
+        // SortKeyList ::= SEQUENCE OF SEQUENCE {
+        //     attributeType   AttributeDescription,
+        //     reverseOrder    [1] BOOLEAN DEFAULT FALSE }
+        let mut chave = ber(0x04, self.atributo.as_bytes());
+        if self.decrescente {
+            chave.extend(ber(0x81, &[0xFF]));
+        }
+        RawControl {
+            ctype: "1.2.840.113556.1.4.473".to_string(),
+            crit: false,
+            val: Some(ber(0x30, &ber(0x30, &chave))),
+        }
+    }
+}
+
+/// O elemento BER com a `tag` e o `conteudo`.
+fn ber(tag: u8, conteudo: &[u8]) -> Vec<u8> {
+    let mut elemento = vec![tag];
+    if conteudo.len() < 0x80 {
+        elemento.push(conteudo.len() as u8);
+    } else {
+        let tamanho = conteudo.len().to_be_bytes();
+        let inicio = tamanho.iter().take_while(|b| **b == 0).count();
+        elemento.push(0x80 | (tamanho.len() - inicio) as u8);
+        elemento.extend(&tamanho[inicio..]);
+    }
+    elemento.extend(conteudo);
+    elemento
+}
+
+/// Qual página uma [busca paginada](DiretorioLdap::buscar_pagina) retorna.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PedidoPagina {
+    /// O número da página, começando em 1.
+    pub pagina: usize,
+    /// Quantas entradas cada página tem.
+    pub tamanho: usize,
+    pub ordenar: Option<Ordenacao>,
+}
+
+/// Uma página de uma [busca paginada](DiretorioLdap::buscar_pagina).
+#[derive(Debug, Clone)]
+pub struct Pagina {
+    pub entradas: Vec<SearchEntry>,
+    /// Quantas entradas a busca tem ao todo, se o servidor informar.
+    pub total: Option<usize>,
+    /// Se não há páginas depois desta.
+    pub ultima: bool,
+}
+
+/// As operações de diretório usadas pelo alumnic.
+pub trait DiretorioLdap: Send {
+    /// Busca as entradas abaixo de `base` que satisfazem o `filtro`,
+    /// retornando somente os `atributos` pedidos (todos, se a lista for
+    /// vazia).
+    fn buscar(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+    ) -> impl Future<Output = Result<Vec<SearchEntry>, ErroLdap>> + Send;
+
+    /// Adiciona uma entrada nova com o `dn` e os `atributos` fornecidos.
+    fn adicionar(
+        &mut self,
+        dn: &str,
+        atributos: Vec<(&str, HashSet<&str>)>,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send;
+
+    /// Aplica as modificações `mods` na entrada `dn`. As modificações são
+    /// atômicas: se uma falhar, nenhuma é aplicada.
+    fn modificar(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<&str>>,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send;
+
+    /// Troca os valores do `atributo` da entrada `dn` por um só valor
+    /// binário, como uma foto no `jpegPhoto`.
+    fn gravar_binario(
+        &mut self,
+        dn: &str,
+        atributo: &str,
+        valor: &[u8],
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send;
+
+    /// Move a entrada `dn` para baixo de `nova_base`, mantendo o RDN. Só
+    /// entradas sem filhos podem ser movidas.
+    fn mover(
+        &mut self,
+        dn: &str,
+        nova_base: &str,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send;
+
+    /// Apaga a entrada `dn`. Só entradas sem filhos podem ser apagadas.
+    fn remover(
+        &mut self,
+        dn: &str,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send;
+
+    /// Faz o mesmo que [`buscar`](DiretorioLdap::buscar), mas retorna só a
+    /// página do `pedido`, na ordem dele. Por padrão, todas as entradas são
+    /// buscadas e a página é separada na memória; a conexão com o servidor
+    /// pede a página a ele, sem trazer as outras.
+    fn buscar_pagina(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+        pedido: &PedidoPagina,
+    ) -> impl Future<Output = Result<Pagina, ErroLdap>> + Send {
+        async move {
+            let mut entradas =
+                self.buscar(base, escopo, filtro, atributos).await?;
+            if let Some(ordenacao) = &pedido.ordenar {
+                entradas.sort_by(|a, b| ordenacao.comparar(a, b));
+            }
+            let total = entradas.len();
+            let inicio = pedido.pagina.saturating_sub(1) * pedido.tamanho;
+            let entradas: Vec<_> = entradas
+                .into_iter()
+                .skip(inicio)
+                .take(pedido.tamanho)
+                .collect();
+            Ok(Pagina {
+                ultima: inicio + entradas.len() >= total,
+                total: Some(total),
+                entradas,
+            })
+        }
+    }
+
+    /// Faz o mesmo que [`buscar`](DiretorioLdap::buscar), mas pede as
+    /// entradas em páginas de `tamanho`, para não passar do limite de
+    /// entradas por busca do servidor, como o `MaxPageSize` do Active
+    /// Directory. Por padrão, as páginas são pedidas uma a uma pela
+    /// [`buscar_pagina`](DiretorioLdap::buscar_pagina); a conexão com o
+    /// servidor segue o cookie da paginação, sem repetir as anteriores.
+    fn buscar_todas(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+        tamanho: usize,
+    ) -> impl Future<Output = Result<Vec<SearchEntry>, ErroLdap>> + Send {
+        async move {
+            let mut todas = vec![];
+            for pagina in 1.. {
+                let pedido = PedidoPagina {
+                    pagina,
+                    tamanho,
+                    ordenar: None,
+                };
+                let pagina = self
+                    .buscar_pagina(
+                        base,
+                        escopo,
+                        filtro,
+                        atributos.clone(),
+                        &pedido,
+                    )
+                    .await?;
+                todas.extend(pagina.entradas);
+                if pagina.ultima {
+                    break;
+                }
+            }
+            Ok(todas)
+        }
+    }
+
+    /// A identidade com que o servidor autorizou a conexão, como
+    /// `dn:cn=admin,dc=dcc,dc=ufrj,dc=br`, pelo "Who am I?" (RFC 4532). Uma
+    /// identidade vazia é a de uma conexão anônima.
+    fn quem_sou(
+        &mut self,
+    ) -> impl Future<Output = Result<String, ErroLdap>> + Send;
+
+    /// Se a conexão pode ser guardada num [pool](crate::ldap::LdapPool)
+    /// e usada de novo depois de fechada. Por padrão, pode.
+    fn reutilizavel(&self) -> bool {
+        true
+    }
+}
+
+impl ConexaoLdap {
+    /// Avisa o servidor que as páginas seguintes da busca não serão pedidas,
+    /// com uma página de tamanho zero (RFC 2696), para ele liberar o que
+    /// guardou dela.
+    async fn encerrar_paginacao(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        cookie: Vec<u8>,
+    ) {
+        let encerramento = PagedResults { size: 0, cookie };
+        let _ = self
+            .com_timeout()
+            .with_controls(encerramento)
+            .search(base, escopo, filtro, vec!["1.1"])
+            .await;
+    }
+}
+
+impl DiretorioLdap for ConexaoLdap {
+    async fn buscar(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+    ) -> Result<Vec<SearchEntry>, ErroLdap> {
+        let (entradas, _) = medir("busca", async {
+            Ok(self
+                .com_timeout()
+                .search(base, escopo, filtro, atributos)
+                .await?
+                .success()?)
+        })
+        .await?;
+
+        Ok(entradas.into_iter().map(SearchEntry::construct).collect())
+    }
+
+    /// As páginas são pedidas com o controle de paginação (RFC 2696) e
+    /// ordenadas pelo servidor (RFC 2891). Para chegar na página pedida, as
+    /// anteriores são buscadas e descartadas uma a uma, e a busca é encerrada
+    /// no servidor logo depois dela.
+    async fn buscar_pagina(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+        pedido: &PedidoPagina,
+    ) -> Result<Pagina, ErroLdap> {
+        let ordenacao = pedido.ordenar.as_ref().map(Ordenacao::controle);
+        let tamanho = i32::try_from(pedido.tamanho).unwrap_or(i32::MAX);
+        let mut cookie = vec![];
+        let mut numero = 1;
+        loop {
+            let mut controles: Vec<RawControl> = vec![
+                PagedResults {
+                    size: tamanho,
+                    cookie: std::mem::take(&mut cookie),
+                }
+                .into(),
+            ];
+            controles.extend(ordenacao.clone());
+            let (entradas, resultado) = medir("busca", async {
+                Ok(self
+                    .com_timeout()
+                    .with_controls(controles)
+                    .search(base, escopo, filtro, atributos.clone())
+                    .await?
+                    .success()?)
+            })
+            .await?;
+
+            let mut total = None;
+            if let Some(paginacao) = resultado
+                .ctrls
+                .iter()
+                .find(|c| matches!(c.0, Some(ControlType::PagedResults)))
+            {
+                let paginacao = paginacao.1.parse::<PagedResults>();
+                cookie = paginacao.cookie;
+                total = usize::try_from(paginacao.size).ok().filter(|t| *t > 0);
+            }
+
+            if numero >= pedido.pagina || cookie.is_empty() {
+                let ultima = cookie.is_empty();
+                if !ultima {
+                    self.encerrar_paginacao(base, escopo, filtro, cookie).await;
+                }
+                return Ok(Pagina {
+                    // Uma página além da última fica vazia
+                    entradas: match numero >= pedido.pagina {
+                        true => entradas
+                            .into_iter()
+                            .map(SearchEntry::construct)
+                            .collect(),
+                        false => vec![],
+                    },
+                    total,
+                    ultima,
+                });
+            }
+            numero += 1;
+        }
+    }
+
+    async fn buscar_todas(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+        tamanho: usize,
+    ) -> Result<Vec<SearchEntry>, ErroLdap> {
+        let tamanho = i32::try_from(tamanho).unwrap_or(i32::MAX);
+        let mut todas = vec![];
+        let mut cookie = vec![];
+        loop {
+            let controles: Vec<RawControl> = vec![
+                PagedResults {
+                    size: tamanho,
+                    cookie: std::mem::take(&mut cookie),
+                }
+                .into(),
+            ];
+            let (entradas, resultado) = medir("busca", async {
+                Ok(self
+                    .com_timeout()
+                    .with_controls(controles)
+                    .search(base, escopo, filtro, atributos.clone())
+                    .await?
+                    .success()?)
+            })
+            .await?;
+            todas.extend(entradas.into_iter().map(SearchEntry::construct));
+
+            if let Some(paginacao) = resultado
+                .ctrls
+                .iter()
+                .find(|c| matches!(c.0, Some(ControlType::PagedResults)))
+            {
+                cookie = paginacao.1.parse::<PagedResults>().cookie;
+            }
+            if cookie.is_empty() {
+                return Ok(todas);
+            }
+        }
+    }
+
+    async fn adicionar(
+        &mut self,
+        dn: &str,
+        atributos: Vec<(&str, HashSet<&str>)>,
+    ) -> Result<(), ErroLdap> {
+        self.permitir_escrita()?;
+        validar_dn(dn)?;
+        medir("add", async {
+            self.com_timeout().add(dn, atributos).await?.success()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn modificar(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<&str>>,
+    ) -> Result<(), ErroLdap> {
+        self.permitir_escrita()?;
+        validar_dn(dn)?;
+        medir("modify", async {
+            self.com_timeout().modify(dn, mods).await?.success()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn gravar_binario(
+        &mut self,
+        dn: &str,
+        atributo: &str,
+        valor: &[u8],
+    ) -> Result<(), ErroLdap> {
+        let mods = vec![Mod::Replace(atributo.as_bytes(), [valor].into())];
+        self.permitir_escrita()?;
+        validar_dn(dn)?;
+        medir("modify", async {
+            self.com_timeout().modify(dn, mods).await?.success()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn mover(
+        &mut self,
+        dn: &str,
+        nova_base: &str,
+    ) -> Result<(), ErroLdap> {
+        let (rdn, _) = separar_rdn(dn);
+
+        self.permitir_escrita()?;
+        validar_dn(dn)?;
+        validar_dn(nova_base)?;
+        medir("modrdn", async {
+            self.com_timeout()
+                .modifydn(dn, rdn, true, Some(nova_base))
+                .await?
+                .success()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remover(&mut self, dn: &str) -> Result<(), ErroLdap> {
+        self.permitir_escrita()?;
+        validar_dn(dn)?;
+        medir("delete", async {
+            self.com_timeout().delete(dn).await?.success()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+        medir("whoami", async {
+            let (resposta, _) =
+                self.com_timeout().extended(WhoAmI).await?.success()?;
+            Ok(resposta.parse::<WhoAmIResp>().authzid)
+        })
+        .await
+    }
+
+    /// Uma conexão com uma réplica não é reusada, para que as próximas
+    /// voltem ao principal quando ele voltar, nem uma cujo socket já fechou.
+    fn reutilizavel(&self) -> bool {
+        !self.somente_leitura() && self.aberta()
+    }
+}
+
+/// Uma conexão aberta com uma [FonteLdap] compartilhada.
+///
+/// [FonteLdap]: crate::ldap::conexao::FonteLdap
+impl<D: DiretorioLdap> DiretorioLdap for OwnedMutexGuard<D> {
+    fn buscar(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+    ) -> impl Future<Output = Result<Vec<SearchEntry>, ErroLdap>> + Send {
+        D::buscar(self, base, escopo, filtro, atributos)
+    }
+
+    fn adicionar(
+        &mut self,
+        dn: &str,
+        atributos: Vec<(&str, HashSet<&str>)>,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        D::adicionar(self, dn, atributos)
+    }
+
+    fn modificar(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<&str>>,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        D::modificar(self, dn, mods)
+    }
+
+    fn gravar_binario(
+        &mut self,
+        dn: &str,
+        atributo: &str,
+        valor: &[u8],
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        D::gravar_binario(self, dn, atributo, valor)
+    }
+
+    fn mover(
+        &mut self,
+        dn: &str,
+        nova_base: &str,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        D::mover(self, dn, nova_base)
+    }
+
+    fn remover(
+        &mut self,
+        dn: &str,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        D::remover(self, dn)
+    }
+
+    fn buscar_pagina(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+        pedido: &PedidoPagina,
+    ) -> impl Future<Output = Result<Pagina, ErroLdap>> + Send {
+        D::buscar_pagina(self, base, escopo, filtro, atributos, pedido)
+    }
+
+    fn buscar_todas(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+        tamanho: usize,
+    ) -> impl Future<Output = Result<Vec<SearchEntry>, ErroLdap>> + Send {
+        D::buscar_todas(self, base, escopo, filtro, atributos, tamanho)
+    }
+
+    fn quem_sou(
+        &mut self,
+    ) -> impl Future<Output = Result<String, ErroLdap>> + Send {
+        D::quem_sou(self)
+    }
+
+    /// Guardar a conexão num pool deixaria o diretório travado.
+    fn reutilizavel(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,120 @@
+//! Módulo com a criação das contas de docentes e técnicos, que não são de
+//! alunos: ficam na OU do perfil em `ou=usuarios`, sem DRE nem Samba, com o
+//! gid e a validade do perfil.
+use crate::configuracao::{ConfiguracaoRenovacao, ConfiguracaoUsuario};
+use crate::docente::{ConfiguracaoDocentes, PedidoDeDocente, PerfilDocente};
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::{
+    EntradaUsuario, adicionar_entrada, alocar_ids, normalizar_gecos,
+};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::ldap::projeto::BASE_USUARIOS;
+use crate::ldap::renovar::SEGUNDOS_POR_DIA;
+use chrono::{DateTime, Utc};
+
+/// O home directory do docente `username` na `ou` dele.
+pub fn home_directory_docente(username: &str, ou: &str) -> String {
+    format!("/usuarios/{ou}/{username}")
+}
+
+/// Monta a entrada LDAP da conta do `pedido`, com o `username` e o
+/// `uid_number` já reservados. A senha vai com a hash gravada no pedido, e
+/// a conta vence depois da validade do `perfil`, pelo `shadowExpire`, se ele
+/// tiver uma.
+pub fn montar_entrada_docente(
+    username: &str,
+    pedido: &PedidoDeDocente,
+    hash_senha: &str,
+    (cfg, docentes, perfil, prazos): (
+        &ConfiguracaoUsuario,
+        &ConfiguracaoDocentes,
+        &PerfilDocente,
+        &ConfiguracaoRenovacao,
+    ),
+    uid_number: &str,
+    agora: DateTime<Utc>,
+) -> EntradaUsuario {
+    let dn = filtros::dn(
+        "uid",
+        username,
+        &filtros::dn("ou", &pedido.ou, BASE_USUARIOS),
+    );
+
+    let hoje = agora.timestamp().div_euclid(SEGUNDOS_POR_DIA);
+
+    let um = |valor: &str| vec![valor.to_string()];
+    let mut nomes = pedido.nome.split_whitespace();
+    let cn = nomes.next().unwrap_or_default();
+    let sn = nomes.collect::<Vec<_>>().join(" ");
+
+    let mut atributos = vec![
+        ("objectClass", docentes.classes.clone()),
+        ("uid", um(username)),
+        ("uidNumber", um(uid_number)),
+        ("gidNumber", um(&perfil.gid_number)),
+        (
+            "homeDirectory",
+            vec![home_directory_docente(username, &pedido.ou)],
+        ),
+        ("gecos", vec![normalizar_gecos(&pedido.nome)]),
+        ("cn", um(cn)),
+        // O inetOrgPerson exige um sobrenome
+        ("sn", um(if sn.is_empty() { cn } else { &sn })),
+        ("loginShell", um("/bin/bash")),
+        ("emailExterno", um(&pedido.email)),
+        ("userPassword", um(hash_senha)),
+        ("shadowFlag", um("-1")),
+        ("shadowInactive", um("-1")),
+        ("shadowLastChange", vec![hoje.to_string()]),
+        ("shadowMax", vec![prazos.validade_senha.to_string()]),
+        ("shadowMin", um("0")),
+        ("shadowWarning", vec![prazos.aviso_expiracao.to_string()]),
+        ("cota", um(perfil.cota.as_ref().unwrap_or(&cfg.cota))),
+        ("dataCriacao", vec![hoje.to_string()]),
+    ];
+    if let Some(validade) = perfil.validade_dias {
+        atributos
+            .push(("shadowExpire", vec![(hoje + validade as i64).to_string()]));
+    }
+
+    EntradaUsuario {
+        dn,
+        atributos: atributos
+            .into_iter()
+            .map(|(atributo, valores)| (atributo.to_string(), valores))
+            .collect(),
+    }
+}
+
+/// Cria a conta do `pedido` com o `username` em um [DiretorioLdap] já
+/// conectado, retornando o DN da entrada criada. O uidNumber sai do mesmo
+/// contador das contas de alunos.
+pub async fn cadastrar_docente_em<D: DiretorioLdap>(
+    username: &str,
+    pedido: &PedidoDeDocente,
+    hash_senha: &str,
+    cfgs: (
+        &ConfiguracaoUsuario,
+        &ConfiguracaoDocentes,
+        &PerfilDocente,
+        &ConfiguracaoRenovacao,
+    ),
+    agora: DateTime<Utc>,
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    let hoje = agora.timestamp().div_euclid(SEGUNDOS_POR_DIA);
+    let ids = alocar_ids(cfgs.0, hoje, ldap).await?;
+
+    let entrada = montar_entrada_docente(
+        username,
+        pedido,
+        hash_senha,
+        cfgs,
+        &ids.uid_number,
+        agora,
+    );
+    let criacao = adicionar_entrada(&entrada, ldap).await;
+    ids.devolver_se_falhou(criacao, ldap).await?;
+    Ok(entrada.dn)
+}
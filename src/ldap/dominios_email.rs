@@ -0,0 +1,265 @@
+//! Os domínios do email institucional de cada perfil de usuário e a migração
+//! das contas de um domínio para outro.
+//!
+//! A migração é feita em duas etapas, para que os dois endereços funcionem
+//! durante a transição: na primeira, o endereço no domínio novo é
+//! acrescentado como alias; na segunda, ele passa a ser o `mail` e o antigo
+//! fica como alias.
+use crate::ldap::ErroLdap;
+use crate::ldap::alias_email::ConfiguracaoAliasEmail;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Os domínios do `mail` das contas novas, configurados como
+///
+/// ```yaml
+/// usuario_novo:
+///   dominios_email:
+///     padrao: "ic.ufrj.br"
+///     por_ou:
+///       profcomp: "profcomp.ic.ufrj.br"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoDominiosEmail {
+    /// O domínio das OUs sem um domínio próprio.
+    pub padrao: String,
+    /// O domínio de cada OU, como `profcomp`.
+    pub por_ou: BTreeMap<String, String>,
+}
+
+impl Default for ConfiguracaoDominiosEmail {
+    fn default() -> Self {
+        Self {
+            padrao: "ic.ufrj.br".to_string(),
+            por_ou: [(
+                "profcomp".to_string(),
+                "profcomp.ic.ufrj.br".to_string(),
+            )]
+            .into(),
+        }
+    }
+}
+
+impl ConfiguracaoDominiosEmail {
+    /// O domínio do email das contas da OU `ou`.
+    pub fn dominio(&self, ou: &str) -> &str {
+        self.por_ou.get(ou).unwrap_or(&self.padrao)
+    }
+
+    /// O email institucional do usuário `username` da OU `ou`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::dominios_email::ConfiguracaoDominiosEmail;
+    /// let dominios = ConfiguracaoDominiosEmail::default();
+    /// assert_eq!(dominios.email("joaops", "alunos"), "joaops@ic.ufrj.br");
+    /// assert_eq!(
+    ///     dominios.email("joaops", "profcomp"),
+    ///     "joaops@profcomp.ic.ufrj.br",
+    /// );
+    /// ```
+    pub fn email(&self, username: &str, ou: &str) -> String {
+        format!("{username}@{}", self.dominio(ou))
+    }
+}
+
+/// A etapa da migração de domínio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EtapaMigracao {
+    /// Acrescenta o endereço no domínio novo como alias.
+    Adicionar,
+    /// Troca o `mail` pelo endereço no domínio novo, deixando o antigo como
+    /// alias.
+    Trocar,
+}
+
+/// A migração do `mail` das contas do domínio `de` para o domínio `para`.
+#[derive(Debug, Clone)]
+pub struct MigracaoDominio {
+    pub de: String,
+    pub para: String,
+    /// Só as contas da OU, como `profcomp`. Sem ela, todas as contas.
+    pub ou: Option<String>,
+    pub etapa: EtapaMigracao,
+}
+
+/// A migração do email de uma conta.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAMigrar {
+    pub dn: String,
+    pub uid: String,
+    /// O `mail` atual, no domínio antigo.
+    pub antigo: String,
+    /// O endereço no domínio novo.
+    pub novo: String,
+    /// A conta que já usa o endereço novo, quando é outra. A conta com
+    /// conflito não é migrada.
+    pub conflito: Option<String>,
+    etapa: EtapaMigracao,
+    /// O atributo do alias.
+    atributo: String,
+    /// A classe que precisa ser acrescentada para a conta aceitar o alias.
+    classe: Option<String>,
+    novo_e_alias: bool,
+    antigo_e_alias: bool,
+}
+
+/// A OU de uma conta, o segundo RDN do `dn`, como em `uid=x,ou=alunos,...`.
+fn ou(dn: &str) -> &str {
+    filtros::rdns(dn)
+        .nth(1)
+        .and_then(|rdn| rdn.split_once('='))
+        .map_or("", |(_, ou)| ou)
+}
+
+/// As contas com o `mail` no domínio antigo da `migracao` que ainda têm
+/// algo a mudar na etapa dela, ordenadas pelo uid. O alias é gravado no
+/// atributo do `alias`.
+pub async fn planejar_migracao<D: DiretorioLdap>(
+    migracao: &MigracaoDominio,
+    alias: &ConfiguracaoAliasEmail,
+    ldap: &mut D,
+) -> Result<Vec<EmailAMigrar>, ErroLdap> {
+    let atributo = alias.atributo.as_str();
+    let contas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtros::termina_com("mail", &format!("@{}", migracao.de)),
+            vec!["uid", "mail", "objectClass", atributo],
+        )
+        .await?;
+
+    let dominio_novo = format!("@{}", migracao.para);
+    // Quem já usa cada endereço do domínio novo, como `mail` ou como alias
+    let donos: HashMap<String, String> = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtros::ou([
+                filtros::termina_com("mail", &dominio_novo),
+                filtros::termina_com(atributo, &dominio_novo),
+            ]),
+            vec!["uid", "mail", atributo],
+        )
+        .await?
+        .into_iter()
+        .flat_map(|e| {
+            let uid = e
+                .attrs
+                .get("uid")
+                .and_then(|v| v.first())
+                .cloned()
+                .unwrap_or_else(|| e.dn.clone());
+            e.attrs
+                .into_iter()
+                .filter(|(a, _)| a != "uid")
+                .flat_map(|(_, v)| v)
+                .map(move |v| (v.to_lowercase(), uid.clone()))
+        })
+        .collect();
+
+    let mut planos = vec![];
+    for e in contas {
+        if migracao
+            .ou
+            .as_ref()
+            .is_some_and(|o| ou(&e.dn) != o.as_str())
+        {
+            continue;
+        }
+        let valores = |a: &str| {
+            e.attrs
+                .iter()
+                .find(|(nome, _)| nome.eq_ignore_ascii_case(a))
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        let uid = valores("uid").into_iter().next().unwrap_or_default();
+        let Some(antigo) = valores("mail").into_iter().next() else {
+            continue;
+        };
+        let local = antigo.rsplit_once('@').map_or("", |(local, _)| local);
+        let novo = format!("{local}@{}", migracao.para);
+        let aliases: HashSet<_> =
+            valores(atributo).iter().map(|a| a.to_lowercase()).collect();
+        let novo_e_alias = aliases.contains(&novo.to_lowercase());
+        let antigo_e_alias = aliases.contains(&antigo.to_lowercase());
+        let sem_classe = alias.classe.as_ref().filter(|c| {
+            !valores("objectClass")
+                .iter()
+                .any(|o| o.eq_ignore_ascii_case(c))
+        });
+
+        if migracao.etapa == EtapaMigracao::Adicionar && novo_e_alias {
+            continue;
+        }
+
+        let conflito = donos
+            .get(&novo.to_lowercase())
+            .filter(|dono| **dono != uid)
+            .cloned();
+        planos.push(EmailAMigrar {
+            dn: e.dn,
+            uid,
+            antigo,
+            novo,
+            conflito,
+            etapa: migracao.etapa,
+            atributo: atributo.to_string(),
+            classe: sem_classe.cloned(),
+            novo_e_alias,
+            antigo_e_alias,
+        });
+    }
+    planos.sort_by(|a, b| a.uid.cmp(&b.uid));
+    Ok(planos)
+}
+
+/// Aplica a migração de email `plano` na conta dele.
+///
+/// # Errors
+///
+/// - [ErroLdap::EmailEmUso], se outra conta já usa o endereço novo;
+/// - erro de conexão do LDAP.
+pub async fn migrar_email<D: DiretorioLdap>(
+    plano: &EmailAMigrar,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    if let Some(dono) = &plano.conflito {
+        return Err(ErroLdap::EmailEmUso {
+            email: plano.novo.clone(),
+            dono: dono.clone(),
+        });
+    }
+    let atributo = plano.atributo.as_str();
+    let mut modificacoes = vec![];
+    let mut acrescentar_alias = |endereco| {
+        if let Some(classe) = &plano.classe {
+            modificacoes
+                .push(Mod::Add("objectClass", [classe.as_str()].into()));
+        }
+        modificacoes.push(Mod::Add(atributo, [endereco].into()));
+    };
+    match plano.etapa {
+        EtapaMigracao::Adicionar => acrescentar_alias(plano.novo.as_str()),
+        EtapaMigracao::Trocar => {
+            if !plano.antigo_e_alias {
+                acrescentar_alias(plano.antigo.as_str());
+            }
+            if plano.novo_e_alias {
+                modificacoes
+                    .push(Mod::Delete(atributo, [plano.novo.as_str()].into()));
+            }
+            modificacoes
+                .push(Mod::Replace("mail", [plano.novo.as_str()].into()));
+        },
+    }
+    ldap.modificar(&plano.dn, modificacoes).await
+}
@@ -0,0 +1,157 @@
+//! Módulo com as alterações feitas no LDAP quando um aluno vira ex-aluno.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::{Conta, EstadoConta};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope};
+use std::collections::HashSet;
+
+/// As objectClasses que dão acesso aos laboratórios.
+const CLASSES_DE_ACESSO: [&str; 3] =
+    ["posixAccount", "shadowAccount", "sambaSamAccount"];
+
+/// Os atributos que só existem por causa das [`CLASSES_DE_ACESSO`], além da
+/// senha e da data de remoção. Os atributos de identidade (`uid`, `cn`, `sn`,
+/// `dccDRE`) e o email de contato (`emailExterno`) são mantidos.
+const ATRIBUTOS_DE_ACESSO: [&str; 23] = [
+    "userPassword",
+    "uidNumber",
+    "gidNumber",
+    "homeDirectory",
+    "loginShell",
+    "gecos",
+    "shadowLastChange",
+    "shadowMin",
+    "shadowMax",
+    "shadowWarning",
+    "shadowInactive",
+    "shadowExpire",
+    "shadowFlag",
+    "sambaSID",
+    "sambaAcctFlags",
+    "sambaKickoffTime",
+    "sambaLMPassword",
+    "sambaNTPassword",
+    "sambaPasswordHistory",
+    "sambaPrimaryGroupSID",
+    "sambaPwdLastSet",
+    "sambaPwdMustChange",
+    "dataRemocao",
+];
+
+/// Transforma a `conta` em uma conta de ex-aluno, retornando o seu novo DN.
+///
+/// Primeiro, as objectClasses e os atributos de acesso aos laboratórios são
+/// removidos, em uma única operação. Depois, a entrada é movida para baixo
+/// de `ou_egressos`.
+pub async fn tornar_egresso<D: DiretorioLdap>(
+    conta: &Conta,
+    ou_egressos: &str,
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    let classes: Vec<String> = ldap
+        .buscar(
+            &conta.dn,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["objectClass"],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|mut e| e.attrs.remove("objectClass"))
+        .unwrap_or_default();
+    let restantes: HashSet<&str> = classes
+        .iter()
+        .map(String::as_str)
+        .filter(|c| {
+            !CLASSES_DE_ACESSO.iter().any(|a| a.eq_ignore_ascii_case(c))
+        })
+        .collect();
+
+    let mut mods = vec![
+        Mod::Replace("objectClass", restantes),
+        Mod::Replace("estadoConta", [EstadoConta::Egresso.valor()].into()),
+    ];
+    // Substituir por nada apaga o atributo, sem erro se ele não existir
+    mods.extend(
+        ATRIBUTOS_DE_ACESSO
+            .iter()
+            .map(|a| Mod::Replace(*a, HashSet::new())),
+    );
+    ldap.modificar(&conta.dn, mods).await?;
+
+    ldap.mover(&conta.dn, ou_egressos).await?;
+
+    let (rdn, _) = filtros::separar_rdn(&conta.dn);
+    Ok(format!("{rdn},{ou_egressos}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::conta::{buscar_conta_por_dre, buscar_conta_por_uid};
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    #[tokio::test]
+    async fn egresso_perde_o_acesso_e_muda_de_ou() {
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(
+            "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+            vec![
+                (
+                    "objectClass",
+                    [
+                        "dcc",
+                        "dccAluno",
+                        "inetOrgPerson",
+                        "posixAccount",
+                        "shadowAccount",
+                        "sambaSamAccount",
+                    ]
+                    .into(),
+                ),
+                ("uid", ["joaops"].into()),
+                ("cn", ["João"].into()),
+                ("sn", ["Pedro Silva"].into()),
+                ("dccDRE", ["123456789"].into()),
+                ("emailExterno", ["joao@exemplo.com"].into()),
+                ("uidNumber", ["5001"].into()),
+                ("userPassword", ["!{SSHA}abc"].into()),
+                ("sambaNTPassword", ["ABC"].into()),
+                ("shadowExpire", ["20000"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let conta = buscar_conta_por_uid("joaops", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        let dn =
+            tornar_egresso(&conta, "ou=egressos,dc=dcc,dc=ufrj,dc=br", &mut d)
+                .await
+                .unwrap();
+        assert_eq!(dn, "uid=joaops,ou=egressos,dc=dcc,dc=ufrj,dc=br");
+
+        let e = d.entrada(&dn).unwrap();
+        let mut classes = e.attrs["objectClass"].clone();
+        classes.sort();
+        assert_eq!(classes, vec!["dcc", "dccAluno", "inetOrgPerson"]);
+        assert_eq!(e.attrs["cn"], vec!["João"]);
+        assert_eq!(e.attrs["emailExterno"], vec!["joao@exemplo.com"]);
+        assert_eq!(e.attrs["estadoConta"], vec!["egresso"]);
+        for atributo in ["uidNumber", "userPassword", "sambaNTPassword"] {
+            assert!(!e.attrs.contains_key(atributo), "{atributo}");
+        }
+
+        // A conta continua sendo encontrada pelo DRE
+        let conta = buscar_conta_por_dre("123456789", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conta.estado, EstadoConta::Egresso);
+        assert_eq!(conta.dre, "123456789");
+    }
+}
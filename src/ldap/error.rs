@@ -1,4 +1,5 @@
 //! Tipos de erro do módulo de contato com o LDAP.
+use crate::ldap::filtros::DnInvalido;
 use crate::utils::nome::NomeErro;
 use ldap3::LdapError;
 use thiserror::Error;
@@ -12,7 +13,31 @@ pub enum ErroLdap {
     /// um problema com as operações feitas no LDAP. Para saber, acesse a
     /// estrutura [`LdapError`].
     #[error("Houve um problema com o ldap3")]
-    ErroLdap(#[from] LdapError),
+    ErroLdap(LdapError),
+
+    /// O servidor LDAP não respondeu dentro do timeout configurado. Uma
+    /// conexão ou operação travada vira esse erro em vez de segurar a
+    /// requisição indefinidamente.
+    #[error("O LDAP não respondeu a tempo")]
+    Timeout,
+
+    /// O servidor principal está fora do ar, e a conexão foi aberta com uma
+    /// [réplica](crate::ldap::conexao::Replicas), que só aceita consultas.
+    #[error(
+        "O LDAP principal está fora do ar; as consultas funcionam, mas \
+         nenhuma alteração pode ser feita agora"
+    )]
+    SomenteLeitura,
+
+    /// O certificado de cliente ou a chave dele não puderam ser lidos.
+    #[error("Não foi possível carregar o certificado de cliente do LDAP: {0}")]
+    Certificado(String),
+
+    /// Uma alteração foi pedida num DN mal formado, o que indica um valor
+    /// posto nele sem o [escape](crate::ldap::filtros::dn). Ela não chega ao
+    /// servidor.
+    #[error(transparent)]
+    DnInvalido(#[from] DnInvalido),
 
     /// Houve um erro ao tentar achar o uid de um usuário cujo DRE já está
     /// registrado. Se esse erro foi retornado, significa que o usuário está
@@ -29,6 +54,18 @@ pub enum ErroLdap {
     #[error("Não foi possível encontrar um nome de usuário válido")]
     UsuarioDificil,
 
+    /// O username escolhido pelo aluno entre os candidatos já foi usado,
+    /// provavelmente por outro cadastro feito depois que a lista foi
+    /// mostrada.
+    #[error("O nome de usuário {0:?} já está em uso")]
+    UsernameOcupado(String),
+
+    /// O username escolhido se confunde com um que já existe, como
+    /// `joaosilva` e `joa0silva`, pelos critérios de
+    /// [`ConfiguracaoConfundiveis`](crate::utils::confundiveis::ConfiguracaoConfundiveis).
+    #[error("O nome de usuário {username:?} se confunde com {existente:?}")]
+    UsernameConfundivel { username: String, existente: String },
+
     /// Houve um problema ao processar o nome retornado pelo Gnosys/SIGA. Isso
     /// significa que, provavelmente, a nossa forma de acessar dados do SIGA
     /// quebrou. Também pode ocorrer caso o usuário tenha um nome "diferente",
@@ -39,6 +76,64 @@ pub enum ErroLdap {
 
     #[error("Houve um erro ao tentar criar os IDs do Samba")]
     ErroSamba,
+
+    /// O uidNumber dado pelo contador do `sambaDomain` já é de outra conta,
+    /// o que indica que o contador ficou para trás, como depois de uma conta
+    /// criada à mão. A conta nova não é criada, para que duas contas POSIX
+    /// não fiquem com o mesmo id; o contador precisa ser corrigido com
+    /// `alumnic contadores`.
+    #[error("O uidNumber {uid_number} já é da conta {dono:?}")]
+    UidNumberEmUso { uid_number: String, dono: String },
+
+    /// O endereço para o qual o email da conta seria migrado já é usado por
+    /// outra conta, como `mail` ou como alias.
+    #[error("O email {email} já é da conta {dono:?}")]
+    EmailEmUso { email: String, dono: String },
+
+    /// O atributo `estadoConta` de uma conta tem um valor desconhecido, o que
+    /// indica que ele foi alterado manualmente ou por uma versão mais nova.
+    #[error("A conta tem um estado desconhecido: {0:?}")]
+    EstadoInvalido(String),
+}
+
+impl ErroLdap {
+    /// Se o erro é do LDAP fora do ar, e não de uma operação recusada por
+    /// ele: a conexão não abriu, caiu no meio ou não respondeu a tempo. Uma
+    /// escrita recusada por [`SomenteLeitura`](ErroLdap::SomenteLeitura) não
+    /// conta: o cadastro recebe o `503` em vez de ir para a
+    /// [fila de contingência](crate::contingencia), com a senha no disco.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::ErroLdap;
+    /// assert!(ErroLdap::Timeout.indisponivel());
+    /// assert!(!ErroLdap::SomenteLeitura.indisponivel());
+    /// ```
+    pub fn indisponivel(&self) -> bool {
+        matches!(
+            self,
+            ErroLdap::Timeout
+                | ErroLdap::ErroLdap(
+                    LdapError::Io { .. }
+                        | LdapError::EndOfStream
+                        | LdapError::OpSend { .. }
+                        | LdapError::ResultRecv { .. }
+                        | LdapError::IdScrubSend { .. }
+                        | LdapError::MiscSend { .. }
+                )
+        )
+    }
+}
+
+impl From<LdapError> for ErroLdap {
+    /// Separa os timeouts das outras falhas do ldap3.
+    fn from(e: LdapError) -> Self {
+        match e {
+            LdapError::Timeout { .. } => ErroLdap::Timeout,
+            e => ErroLdap::ErroLdap(e),
+        }
+    }
 }
 
 /// Variação do [std::result::Result] para o [ErroLdap].
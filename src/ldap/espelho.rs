@@ -0,0 +1,641 @@
+//! Comparação das contas dos alunos com as contas espelhadas no Active
+//! Directory e correção das diferenças no AD.
+use crate::espelho_ad::ConfiguracaoAd;
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::BASE_ACADEMICOS;
+use crate::ldap::conta::EstadoConta;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope, SearchEntry};
+use rand::distr::{Alphanumeric, SampleString};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// O `userAccountControl` de uma conta normal do AD.
+const CONTA_NORMAL: u32 = 0x200;
+/// O bit do `userAccountControl` que desabilita a conta.
+const CONTA_DESABILITADA: u32 = 0x2;
+
+/// Tamanho da senha aleatória das contas criadas no AD.
+const TAMANHO_SENHA: usize = 32;
+
+/// Quantas contas cada página da busca no AD traz, abaixo do `MaxPageSize`
+/// padrão dele, que é 1000.
+const PAGINA_AD: usize = 500;
+
+/// Os `objectClass` das contas criadas no AD.
+const CLASSES_AD: [&str; 4] = ["top", "person", "organizationalPerson", "user"];
+
+/// Um atributo copiado com valores diferentes no LDAP e no AD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiferencaAtributo {
+    /// O nome do atributo no AD.
+    pub atributo: String,
+    pub ad: Option<String>,
+    pub ldap: Option<String>,
+}
+
+/// Uma diferença entre uma conta do LDAP e a sua cópia no AD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergencia {
+    /// A conta existe no LDAP e não no AD.
+    Ausente {
+        uid: String,
+        bloqueada: bool,
+        /// Os atributos copiados, já com os nomes do AD.
+        atributos: Vec<(String, String)>,
+    },
+    /// A conta está no AD, mas foi removida ou não existe no LDAP.
+    Sobrando { uid: String, dn: String },
+    /// A conta está bloqueada em um dos diretórios e no outro não.
+    Bloqueio {
+        uid: String,
+        dn: String,
+        bloqueada: bool,
+        /// O novo `userAccountControl` da conta no AD.
+        controle: u32,
+    },
+    /// Atributos copiados com valores diferentes.
+    Atributos {
+        uid: String,
+        dn: String,
+        diferencas: Vec<DiferencaAtributo>,
+    },
+}
+
+impl Divergencia {
+    pub fn uid(&self) -> &str {
+        match self {
+            Divergencia::Ausente { uid, .. }
+            | Divergencia::Sobrando { uid, .. }
+            | Divergencia::Bloqueio { uid, .. }
+            | Divergencia::Atributos { uid, .. } => uid,
+        }
+    }
+}
+
+impl fmt::Display for Divergencia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergencia::Ausente { uid, .. } => {
+                write!(f, "{uid}: não existe no AD")
+            },
+            Divergencia::Sobrando { uid, .. } => {
+                write!(f, "{uid}: não existe mais no LDAP")
+            },
+            Divergencia::Bloqueio { uid, bloqueada, .. } => {
+                if *bloqueada {
+                    write!(f, "{uid}: bloqueada no LDAP e habilitada no AD")
+                } else {
+                    write!(f, "{uid}: ativa no LDAP e desabilitada no AD")
+                }
+            },
+            Divergencia::Atributos {
+                uid, diferencas, ..
+            } => {
+                write!(f, "{uid}:")?;
+                for d in diferencas {
+                    write!(
+                        f,
+                        " {} {:?} -> {:?};",
+                        d.atributo,
+                        d.ad.as_deref().unwrap_or_default(),
+                        d.ldap.as_deref().unwrap_or_default(),
+                    )?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// O primeiro valor do `atributo`, sem diferenciar maiúsculas de minúsculas
+/// no nome, como o servidor faria.
+fn primeiro<'a>(e: &'a SearchEntry, atributo: &str) -> Option<&'a str> {
+    e.attrs
+        .iter()
+        .find(|(nome, _)| nome.eq_ignore_ascii_case(atributo))
+        .and_then(|(_, v)| v.first())
+        .map(String::as_str)
+}
+
+/// O DN da conta `uid` no AD.
+fn dn_ad(cfg: &ConfiguracaoAd, uid: &str) -> String {
+    filtros::dn("CN", uid, &cfg.base)
+}
+
+/// Compara as contas dos alunos no `ldap` com as contas abaixo da base do
+/// `ad`, usando o mapeamento de atributos da `cfg`. As contas removidas e as
+/// que não estão mais entre os alunos devem sair do AD; as suspensas, ficar
+/// desabilitadas.
+pub async fn comparar<D: DiretorioLdap, A: DiretorioLdap>(
+    cfg: &ConfiguracaoAd,
+    ldap: &mut D,
+    ad: &mut A,
+) -> Result<Vec<Divergencia>, ErroLdap> {
+    let mut atributos_ldap = vec!["uid", "estadoConta"];
+    atributos_ldap.extend(cfg.atributos.keys().map(String::as_str));
+    let contas = ldap
+        .buscar(
+            BASE_ACADEMICOS,
+            Scope::Subtree,
+            "(objectClass=dccAluno)",
+            atributos_ldap,
+        )
+        .await?;
+
+    let mut atributos_ad = vec!["sAMAccountName", "userAccountControl"];
+    atributos_ad.extend(cfg.atributos.values().map(String::as_str));
+    let mut espelhadas: BTreeMap<String, SearchEntry> = ad
+        .buscar_todas(
+            &cfg.base,
+            Scope::Subtree,
+            // O `computer` é uma subclasse do `user`, e as máquinas dos
+            // laboratórios não podem ser tomadas por contas sobrando
+            "(&(objectCategory=person)(objectClass=user))",
+            atributos_ad,
+            PAGINA_AD,
+        )
+        .await?
+        .into_iter()
+        .filter_map(|e| {
+            let nome = primeiro(&e, "sAMAccountName")?.to_lowercase();
+            // As contas de máquina terminam em `$`
+            (!nome.ends_with('$')).then_some((nome, e))
+        })
+        .collect();
+
+    let mut divergencias = vec![];
+    for conta in &contas {
+        let Some(uid) = primeiro(conta, "uid") else {
+            continue;
+        };
+        let estado = match primeiro(conta, "estadoConta") {
+            Some(v) => EstadoConta::do_valor(v)
+                .ok_or_else(|| ErroLdap::EstadoInvalido(v.to_string()))?,
+            None => EstadoConta::Ativa,
+        };
+        if estado == EstadoConta::Removida {
+            // Continua em `espelhadas` e é apagada do AD
+            continue;
+        }
+        let bloqueada = estado.bloqueada();
+        let copiados: Vec<_> = cfg
+            .atributos
+            .iter()
+            .filter_map(|(no_ldap, no_ad)| {
+                Some((no_ad.clone(), primeiro(conta, no_ldap)?.to_string()))
+            })
+            .collect();
+
+        let Some(espelhada) = espelhadas.remove(&uid.to_lowercase()) else {
+            divergencias.push(Divergencia::Ausente {
+                uid: uid.to_string(),
+                bloqueada,
+                atributos: copiados,
+            });
+            continue;
+        };
+
+        let controle: u32 = primeiro(&espelhada, "userAccountControl")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(CONTA_NORMAL);
+        if (controle & CONTA_DESABILITADA != 0) != bloqueada {
+            divergencias.push(Divergencia::Bloqueio {
+                uid: uid.to_string(),
+                dn: espelhada.dn.clone(),
+                bloqueada,
+                controle: controle ^ CONTA_DESABILITADA,
+            });
+        }
+
+        let diferencas: Vec<_> = cfg
+            .atributos
+            .values()
+            .filter_map(|no_ad| {
+                let no_ldap = copiados
+                    .iter()
+                    .find(|(a, _)| a == no_ad)
+                    .map(|(_, v)| v.clone());
+                let atual = primeiro(&espelhada, no_ad).map(str::to_string);
+                (atual != no_ldap).then(|| DiferencaAtributo {
+                    atributo: no_ad.clone(),
+                    ad: atual,
+                    ldap: no_ldap,
+                })
+            })
+            .collect();
+        if !diferencas.is_empty() {
+            divergencias.push(Divergencia::Atributos {
+                uid: uid.to_string(),
+                dn: espelhada.dn,
+                diferencas,
+            });
+        }
+    }
+
+    // As que sobraram não são mais de nenhum aluno
+    divergencias.extend(espelhadas.into_values().map(|e| {
+        Divergencia::Sobrando {
+            uid: primeiro(&e, "sAMAccountName")
+                .unwrap_or_default()
+                .to_string(),
+            dn: e.dn,
+        }
+    }));
+    divergencias.sort_by(|a, b| a.uid().cmp(b.uid()));
+
+    Ok(divergencias)
+}
+
+/// O valor do `unicodePwd` do AD com a `senha`: ela entre aspas, em UTF-16LE.
+fn unicode_pwd(senha: &str) -> Zeroizing<Vec<u8>> {
+    Zeroizing::new(
+        format!("\"{senha}\"")
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect(),
+    )
+}
+
+/// Corrige a `divergencia` no `ad`, criando, desabilitando, alterando ou
+/// apagando a conta. A senha não é copiada, já que o LDAP só guarda o hash:
+/// as contas criadas nascem desabilitadas, recebem uma senha aleatória e só
+/// então são habilitadas, se não estiverem bloqueadas.
+pub async fn corrigir<A: DiretorioLdap>(
+    cfg: &ConfiguracaoAd,
+    divergencia: &Divergencia,
+    ad: &mut A,
+) -> Result<(), ErroLdap> {
+    match divergencia {
+        Divergencia::Ausente {
+            uid,
+            bloqueada,
+            atributos,
+        } => {
+            // Pela política de senhas do AD, uma conta habilitada precisa de
+            // senha
+            let desabilitada = (CONTA_NORMAL | CONTA_DESABILITADA).to_string();
+            let mut entrada: Vec<(&str, HashSet<&str>)> = vec![
+                ("objectClass", CLASSES_AD.into()),
+                ("cn", [uid.as_str()].into()),
+                ("sAMAccountName", [uid.as_str()].into()),
+                ("userAccountControl", [desabilitada.as_str()].into()),
+            ];
+            entrada.extend(
+                atributos
+                    .iter()
+                    .map(|(a, v)| (a.as_str(), [v.as_str()].into())),
+            );
+            let dn = dn_ad(cfg, uid);
+            ad.adicionar(&dn, entrada).await?;
+
+            let senha = Zeroizing::new(
+                Alphanumeric.sample_string(&mut rand::rng(), TAMANHO_SENHA),
+            );
+            if let Err(e) = ad
+                .gravar_binario(&dn, "unicodePwd", &unicode_pwd(&senha))
+                .await
+            {
+                // Sem a senha, a conta é apagada para ser criada de novo na
+                // próxima vez
+                if let Err(e) = ad.remover(&dn).await {
+                    eprintln!("Não foi possível apagar {dn:?} do AD: {e}");
+                }
+                return Err(e);
+            }
+
+            if *bloqueada {
+                return Ok(());
+            }
+            let controle = CONTA_NORMAL.to_string();
+            ad.modificar(
+                &dn,
+                vec![Mod::Replace(
+                    "userAccountControl",
+                    [controle.as_str()].into(),
+                )],
+            )
+            .await
+        },
+        Divergencia::Sobrando { dn, .. } => ad.remover(dn).await,
+        Divergencia::Bloqueio { dn, controle, .. } => {
+            let controle = controle.to_string();
+            ad.modificar(
+                dn,
+                vec![Mod::Replace(
+                    "userAccountControl",
+                    [controle.as_str()].into(),
+                )],
+            )
+            .await
+        },
+        Divergencia::Atributos { dn, diferencas, .. } => {
+            let mods = diferencas
+                .iter()
+                .map(|d| {
+                    Mod::Replace(
+                        d.atributo.as_str(),
+                        d.ldap.iter().map(String::as_str).collect(),
+                    )
+                })
+                .collect();
+            ad.modificar(dn, mods).await
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::diretorio::{Pagina, PedidoPagina};
+    use crate::ldap::memoria::DiretorioMemoria;
+    use ldap3::{LdapError, LdapResult};
+
+    const BASE_AD: &str = "OU=Alunos,DC=lab,DC=ic,DC=ufrj,DC=br";
+
+    fn cfg() -> ConfiguracaoAd {
+        ConfiguracaoAd {
+            url: "ldaps://ad.invalido".to_string(),
+            bind_dn: String::new(),
+            bind_pw: String::new(),
+            base: BASE_AD.to_string(),
+            atributos: [("gecos".to_string(), "displayName".to_string())]
+                .into(),
+            timeout_segundos: 30,
+        }
+    }
+
+    async fn aluno(d: &mut DiretorioMemoria, uid: &str, estado: &str) {
+        let dn = format!("uid={uid},ou=alunos,{BASE_ACADEMICOS}");
+        d.adicionar(
+            &dn,
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("gecos", ["Joao Pedro Silva"].into()),
+                ("estadoConta", [estado].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn sincronizar(
+        ldap: &mut DiretorioMemoria,
+        ad: &mut DiretorioMemoria,
+    ) {
+        let cfg = cfg();
+        for d in comparar(&cfg, ldap, ad).await.unwrap() {
+            corrigir(&cfg, &d, ad).await.unwrap();
+            // O AD preenche o `objectCategory` das contas criadas
+            if let Divergencia::Ausente { uid, .. } = &d {
+                ad.modificar(
+                    &dn_ad(&cfg, uid),
+                    vec![Mod::Add("objectCategory", ["person"].into())],
+                )
+                .await
+                .unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn replica_criacao_bloqueio_e_remocao() {
+        let mut ldap = DiretorioMemoria::default();
+        let mut ad = DiretorioMemoria::default();
+        aluno(&mut ldap, "joaops", "ativa").await;
+        aluno(&mut ldap, "anams", "suspensa").await;
+        aluno(&mut ldap, "pedrors", "removida").await;
+
+        let divergencias = comparar(&cfg(), &mut ldap, &mut ad).await.unwrap();
+        let uids: Vec<_> = divergencias.iter().map(Divergencia::uid).collect();
+        assert_eq!(uids, ["anams", "joaops"]);
+
+        sincronizar(&mut ldap, &mut ad).await;
+        let joao = ad.entrada(&format!("CN=joaops,{BASE_AD}")).unwrap();
+        assert_eq!(joao.attrs["userAccountControl"], vec!["512"]);
+        assert_eq!(joao.attrs["displayName"], vec!["Joao Pedro Silva"]);
+        let ana = ad.entrada(&format!("CN=anams,{BASE_AD}")).unwrap();
+        assert_eq!(ana.attrs["userAccountControl"], vec!["514"]);
+        // As duas recebem uma senha aleatória, entre aspas e em UTF-16LE
+        for conta in [joao, ana] {
+            let senha = &conta.bin_attrs["unicodePwd"][0];
+            assert_eq!(senha.len(), (TAMANHO_SENHA + 2) * 2);
+            assert_eq!(senha[..2], [b'"', 0]);
+        }
+        assert_ne!(joao.bin_attrs["unicodePwd"], ana.bin_attrs["unicodePwd"]);
+        assert!(
+            comparar(&cfg(), &mut ldap, &mut ad)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // Bloqueio, mudança de nome e remoção no LDAP
+        let dn = format!("uid=joaops,ou=alunos,{BASE_ACADEMICOS}");
+        ldap.modificar(
+            &dn,
+            vec![
+                Mod::Replace("estadoConta", ["suspensa"].into()),
+                Mod::Replace("gecos", ["Joao Pedro"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        let dn = format!("uid=anams,ou=alunos,{BASE_ACADEMICOS}");
+        ldap.modificar(
+            &dn,
+            vec![Mod::Replace("estadoConta", ["removida"].into())],
+        )
+        .await
+        .unwrap();
+
+        let divergencias = comparar(&cfg(), &mut ldap, &mut ad).await.unwrap();
+        assert!(matches!(divergencias[0], Divergencia::Sobrando { .. }));
+        assert!(matches!(
+            divergencias[1],
+            Divergencia::Bloqueio {
+                bloqueada: true,
+                controle: 514,
+                ..
+            }
+        ));
+        assert!(matches!(divergencias[2], Divergencia::Atributos { .. }));
+
+        sincronizar(&mut ldap, &mut ad).await;
+        assert!(ad.entrada(&format!("CN=anams,{BASE_AD}")).is_none());
+        let joao = ad.entrada(&format!("CN=joaops,{BASE_AD}")).unwrap();
+        assert_eq!(joao.attrs["userAccountControl"], vec!["514"]);
+        assert_eq!(joao.attrs["displayName"], vec!["Joao Pedro"]);
+    }
+
+    /// Um AD que, como o de verdade, recusa as buscas que trazem mais que
+    /// [`LIMITE`](AdComLimite::LIMITE) entradas de uma vez.
+    struct AdComLimite(DiretorioMemoria);
+
+    impl AdComLimite {
+        const LIMITE: usize = 1000;
+    }
+
+    impl DiretorioLdap for AdComLimite {
+        async fn buscar(
+            &mut self,
+            base: &str,
+            escopo: Scope,
+            filtro: &str,
+            atributos: Vec<&str>,
+        ) -> Result<Vec<SearchEntry>, ErroLdap> {
+            let entradas =
+                self.0.buscar(base, escopo, filtro, atributos).await?;
+            if entradas.len() > Self::LIMITE {
+                // sizeLimitExceeded
+                return Err(ErroLdap::ErroLdap(LdapError::LdapResult {
+                    result: LdapResult {
+                        rc: 4,
+                        matched: String::new(),
+                        text: String::new(),
+                        refs: vec![],
+                        ctrls: vec![],
+                    },
+                }));
+            }
+            Ok(entradas)
+        }
+
+        async fn buscar_pagina(
+            &mut self,
+            base: &str,
+            escopo: Scope,
+            filtro: &str,
+            atributos: Vec<&str>,
+            pedido: &PedidoPagina,
+        ) -> Result<Pagina, ErroLdap> {
+            assert!(pedido.tamanho <= Self::LIMITE);
+            self.0
+                .buscar_pagina(base, escopo, filtro, atributos, pedido)
+                .await
+        }
+
+        async fn adicionar(
+            &mut self,
+            dn: &str,
+            atributos: Vec<(&str, HashSet<&str>)>,
+        ) -> Result<(), ErroLdap> {
+            self.0.adicionar(dn, atributos).await
+        }
+
+        async fn modificar(
+            &mut self,
+            dn: &str,
+            mods: Vec<Mod<&str>>,
+        ) -> Result<(), ErroLdap> {
+            self.0.modificar(dn, mods).await
+        }
+
+        async fn gravar_binario(
+            &mut self,
+            dn: &str,
+            atributo: &str,
+            valor: &[u8],
+        ) -> Result<(), ErroLdap> {
+            self.0.gravar_binario(dn, atributo, valor).await
+        }
+
+        async fn mover(
+            &mut self,
+            dn: &str,
+            nova_base: &str,
+        ) -> Result<(), ErroLdap> {
+            self.0.mover(dn, nova_base).await
+        }
+
+        async fn remover(&mut self, dn: &str) -> Result<(), ErroLdap> {
+            self.0.remover(dn).await
+        }
+
+        async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+            self.0.quem_sou().await
+        }
+    }
+
+    #[tokio::test]
+    async fn le_o_ad_em_paginas() {
+        let mut ldap = DiretorioMemoria::default();
+        let mut ad = AdComLimite(DiretorioMemoria::default());
+        let total = AdComLimite::LIMITE + 1;
+        for i in 0..total {
+            let uid = format!("aluno{i:04}");
+            ad.adicionar(
+                &dn_ad(&cfg(), &uid),
+                vec![
+                    ("objectClass", ["user"].into()),
+                    ("objectCategory", ["person"].into()),
+                    ("sAMAccountName", [uid.as_str()].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+
+        let divergencias = comparar(&cfg(), &mut ldap, &mut ad).await.unwrap();
+        assert_eq!(divergencias.len(), total);
+        assert!(
+            divergencias
+                .iter()
+                .all(|d| matches!(d, Divergencia::Sobrando { .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn contas_de_maquina_nao_sao_tocadas() {
+        let mut ldap = DiretorioMemoria::default();
+        let mut ad = DiretorioMemoria::default();
+        for (nome, classes, categoria) in [
+            ("PC01$", vec!["user", "computer"], "computer"),
+            ("PC02$", vec!["user"], "person"),
+        ] {
+            ad.adicionar(
+                &dn_ad(&cfg(), nome),
+                vec![
+                    ("objectClass", classes.into_iter().collect()),
+                    ("objectCategory", [categoria].into()),
+                    ("sAMAccountName", [nome].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+
+        assert!(
+            comparar(&cfg(), &mut ldap, &mut ad)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn contas_fora_da_base_nao_sao_tocadas() {
+        let mut ldap = DiretorioMemoria::default();
+        let mut ad = DiretorioMemoria::default();
+        ad.adicionar(
+            "CN=admin,OU=Servicos,DC=lab,DC=ic,DC=ufrj,DC=br",
+            vec![
+                ("objectClass", ["user"].into()),
+                ("objectCategory", ["person"].into()),
+                ("sAMAccountName", ["admin"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert!(
+            comparar(&cfg(), &mut ldap, &mut ad)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
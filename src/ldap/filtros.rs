@@ -0,0 +1,364 @@
+//! A construção dos filtros de busca e dos DNs do LDAP. Todo valor que vem de
+//! fora, como um uid, um DRE, um nome ou um valor da configuração, entra nos
+//! filtros pelo [ldap_escape] e nos DNs pelo [dn_escape], para que um `*`,
+//! um `)` ou uma `,` nele não mude o que é buscado ou a entrada alterada. Os
+//! filtros e DNs devem ser montados só com estas funções, em vez de um
+//! `format!`.
+use ldap3::{dn_escape, ldap_escape};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("DN inválido: {0:?}")]
+pub struct DnInvalido(pub String);
+
+/// O filtro das entradas com o `atributo` igual ao `valor`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::igual;
+/// assert_eq!(igual("uid", "joaops"), "(uid=joaops)");
+/// assert_eq!(igual("uid", "*)(uid=*"), r"(uid=\2a\29\28uid=\2a)");
+/// ```
+pub fn igual(atributo: &str, valor: &str) -> String {
+    format!("({atributo}={})", ldap_escape(valor))
+}
+
+/// O filtro das entradas que têm o `atributo`.
+pub fn presente(atributo: &str) -> String {
+    format!("({atributo}=*)")
+}
+
+/// O filtro das entradas com o `atributo` maior ou igual ao `valor`.
+pub fn maior_ou_igual(atributo: &str, valor: &str) -> String {
+    format!("({atributo}>={})", ldap_escape(valor))
+}
+
+/// O filtro das entradas com o `atributo` menor ou igual ao `valor`.
+pub fn menor_ou_igual(atributo: &str, valor: &str) -> String {
+    format!("({atributo}<={})", ldap_escape(valor))
+}
+
+/// O filtro das entradas com o `valor` em qualquer parte do `atributo`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::contem;
+/// assert_eq!(contem("gecos", "Silva"), "(gecos=*Silva*)");
+/// assert_eq!(contem("gecos", "*"), r"(gecos=*\2a*)");
+/// ```
+pub fn contem(atributo: &str, valor: &str) -> String {
+    format!("({atributo}=*{}*)", ldap_escape(valor))
+}
+
+/// O filtro das entradas com o `atributo` começando pelo `valor`.
+pub fn comeca_com(atributo: &str, valor: &str) -> String {
+    format!("({atributo}={}*)", ldap_escape(valor))
+}
+
+/// O filtro das entradas com o `atributo` terminando no `valor`.
+pub fn termina_com(atributo: &str, valor: &str) -> String {
+    format!("({atributo}=*{})", ldap_escape(valor))
+}
+
+/// O filtro das entradas aceitas por todos os `filtros`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::{e, igual, nao, ou};
+/// assert_eq!(
+///     e([igual("objectClass", "dccAluno"), igual("uid", "joaops")]),
+///     "(&(objectClass=dccAluno)(uid=joaops))",
+/// );
+/// assert_eq!(
+///     ou([igual("uid", "a"), nao(&igual("uid", "b"))]),
+///     "(|(uid=a)(!(uid=b)))",
+/// );
+/// ```
+pub fn e<S: AsRef<str>>(filtros: impl IntoIterator<Item = S>) -> String {
+    juntar('&', filtros)
+}
+
+/// O filtro das entradas aceitas por algum dos `filtros`.
+pub fn ou<S: AsRef<str>>(filtros: impl IntoIterator<Item = S>) -> String {
+    juntar('|', filtros)
+}
+
+/// O filtro das entradas recusadas pelo `filtro`.
+pub fn nao(filtro: &str) -> String {
+    format!("(!{filtro})")
+}
+
+fn juntar<S: AsRef<str>>(
+    operador: char,
+    filtros: impl IntoIterator<Item = S>,
+) -> String {
+    let mut s = format!("({operador}");
+    for filtro in filtros {
+        s.push_str(filtro.as_ref());
+    }
+    s.push(')');
+    s
+}
+
+/// O DN da entrada com o `atributo` igual ao `valor` abaixo da `base`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::dn;
+/// let base = "ou=alunos,dc=dcc,dc=ufrj,dc=br";
+/// assert_eq!(dn("uid", "joaops", base), "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br");
+/// assert_eq!(
+///     dn("uid", "x,ou=admins", base),
+///     r"uid=x\2cou\3dadmins,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+/// );
+/// ```
+pub fn dn(atributo: &str, valor: &str, base: &str) -> String {
+    if base.is_empty() {
+        format!("{atributo}={}", dn_escape(valor))
+    } else {
+        format!("{atributo}={},{base}", dn_escape(valor))
+    }
+}
+
+/// Separa o primeiro RDN do `dn` do resto dele, a base, sem cortar uma `,`
+/// escapada.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::separar_rdn;
+/// assert_eq!(
+///     separar_rdn(r"cn=Silva\, Ana,ou=alunos"),
+///     (r"cn=Silva\, Ana", "ou=alunos"),
+/// );
+/// assert_eq!(separar_rdn("dc=br"), ("dc=br", ""));
+/// ```
+pub fn separar_rdn(dn: &str) -> (&str, &str) {
+    let mut escapado = false;
+    for (i, c) in dn.char_indices() {
+        match c {
+            _ if escapado => escapado = false,
+            '\\' => escapado = true,
+            ',' => return (&dn[..i], &dn[i + 1..]),
+            _ => {},
+        }
+    }
+    (dn, "")
+}
+
+/// Os RDNs do `dn`, do primeiro à raiz.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::rdns;
+/// let dn = r"uid=a\,b,ou=alunos,dc=br";
+/// assert_eq!(rdns(dn).collect::<Vec<_>>(), [r"uid=a\,b", "ou=alunos", "dc=br"]);
+/// ```
+pub fn rdns(dn: &str) -> impl Iterator<Item = &str> {
+    let mut resto = Some(dn).filter(|dn| !dn.is_empty());
+    std::iter::from_fn(move || {
+        let (rdn, base) = separar_rdn(resto?);
+        resto = Some(base).filter(|base| !base.is_empty());
+        Some(rdn)
+    })
+}
+
+/// Confere se o `dn` é bem formado: RDNs `atributo=valor` separados por
+/// vírgulas, com os caracteres especiais dos valores escapados. Um DN assim
+/// não esconde outro RDN num valor que veio de fora.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::filtros::validar_dn;
+/// assert!(validar_dn("uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br").is_ok());
+/// assert!(validar_dn(r"cn=Silva\2c Ana+sn=Silva,dc=br").is_ok());
+/// assert!(validar_dn("").is_ok());
+///
+/// assert!(validar_dn("uid=joaops,,dc=br").is_err());
+/// assert!(validar_dn("uid=a;b,dc=br").is_err());
+/// assert!(validar_dn("joaops,dc=br").is_err());
+/// assert!(validar_dn(r"uid=joaops\,dc=br\").is_err());
+/// ```
+pub fn validar_dn(dn: &str) -> Result<(), DnInvalido> {
+    let invalido = || DnInvalido(dn.to_string());
+    let mut resto = dn;
+    while !resto.is_empty() {
+        let (rdn, base) = separar_rdn(resto);
+        if base.is_empty() && resto.ends_with(',') {
+            return Err(invalido());
+        }
+        // Um RDN pode ter vários atributos, juntados por `+`
+        for par in dividir(rdn, '+') {
+            let (atributo, valor) = par.split_once('=').ok_or_else(invalido)?;
+            if !atributo_valido(atributo.trim()) || !valor_valido(valor) {
+                return Err(invalido());
+            }
+        }
+        resto = base;
+    }
+    Ok(())
+}
+
+/// Divide o `rdn` nos `separador`es não escapados.
+fn dividir(rdn: &str, separador: char) -> Vec<&str> {
+    let mut partes = vec![];
+    let mut inicio = 0;
+    let mut escapado = false;
+    for (i, c) in rdn.char_indices() {
+        match c {
+            _ if escapado => escapado = false,
+            '\\' => escapado = true,
+            c if c == separador => {
+                partes.push(&rdn[inicio..i]);
+                inicio = i + 1;
+            },
+            _ => {},
+        }
+    }
+    partes.push(&rdn[inicio..]);
+    partes
+}
+
+/// Um nome de atributo ou um OID.
+fn atributo_valido(atributo: &str) -> bool {
+    let mut caracteres = atributo.chars();
+    caracteres.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && caracteres.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+/// Um valor não vazio em que os caracteres especiais estão escapados, com
+/// `\` antes deles ou em hexadecimal, como `\2c`.
+fn valor_valido(valor: &str) -> bool {
+    let mut caracteres = valor.chars().peekable();
+    if caracteres.peek().is_none() {
+        return false;
+    }
+    while let Some(c) = caracteres.next() {
+        match c {
+            '\\' => match caracteres.next() {
+                Some(
+                    ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=' | ' ' | '#',
+                ) => {},
+                Some(a) if a.is_ascii_hexdigit() => {
+                    if !caracteres.next().is_some_and(|b| b.is_ascii_hexdigit())
+                    {
+                        return false;
+                    }
+                },
+                _ => return false,
+            },
+            ',' | '+' | '"' | '<' | '>' | ';' | '\0' => return false,
+            _ => {},
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::diretorio::DiretorioLdap;
+    use crate::ldap::memoria::DiretorioMemoria;
+    use ldap3::Scope;
+
+    const BASE: &str = "ou=alunos,dc=dcc,dc=ufrj,dc=br";
+
+    async fn diretorio() -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        for (uid, dre, nome) in [
+            ("joaops", "123456789", "Joao Pedro Silva"),
+            ("anams", "987654321", "Ana Maria Souza"),
+        ] {
+            let atributos = vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("dre", [dre].into()),
+                ("gecos", [nome].into()),
+            ];
+            d.adicionar(&dn("uid", uid, BASE), atributos).await.unwrap();
+        }
+        d
+    }
+
+    async fn uids(d: &mut DiretorioMemoria, filtro: &str) -> Vec<String> {
+        let mut uids: Vec<_> = d
+            .buscar(BASE, Scope::OneLevel, filtro, vec!["uid"])
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.attrs["uid"][0].clone())
+            .collect();
+        uids.sort();
+        uids
+    }
+
+    #[tokio::test]
+    async fn dres_e_nomes_maliciosos_nao_ampliam_a_busca() {
+        let mut d = diretorio().await;
+        for valor in [
+            "*",
+            "123456789)(uid=*",
+            "*)(|(uid=*",
+            "joaops)(|(objectClass=*)",
+            "\\2a",
+            "joaops\0",
+            "Silva*)(gecos=*",
+        ] {
+            let filtro =
+                e([igual("objectClass", "dccAluno"), igual("dre", valor)]);
+            assert!(uids(&mut d, &filtro).await.is_empty(), "{valor:?}");
+            assert!(uids(&mut d, &contem("gecos", valor)).await.is_empty());
+            assert!(uids(&mut d, &igual("uid", valor)).await.is_empty());
+        }
+
+        assert_eq!(uids(&mut d, &igual("dre", "123456789")).await, ["joaops"]);
+        assert_eq!(uids(&mut d, &contem("gecos", "maria")).await, ["anams"]);
+        assert_eq!(
+            uids(&mut d, &ou([igual("uid", "anams"), igual("uid", "joaops")]))
+                .await,
+            ["anams", "joaops"],
+        );
+    }
+
+    #[test]
+    fn valores_maliciosos_nao_criam_rdns() {
+        for valor in [
+            "joaops,ou=admins",
+            "joaops+uid=root",
+            "=joaops",
+            " joaops ",
+            "#joaops",
+            "jo\\ops",
+            "jo\"ops;<>",
+        ] {
+            let dn = dn("uid", valor, BASE);
+            assert_eq!(validar_dn(&dn), Ok(()), "{dn}");
+            let (rdn, base) = separar_rdn(&dn);
+            assert_eq!(base, BASE, "{dn}");
+            assert_eq!(dividir(rdn, '+').len(), 1, "{dn}");
+        }
+    }
+
+    #[test]
+    fn dns_sem_escape_sao_invalidos() {
+        for dn in [
+            "uid=joaops,ou=alunos,",
+            ",ou=alunos",
+            "uid=,ou=alunos",
+            "=joaops,ou=alunos",
+            "uid=jo,ops=,ou=alunos",
+            "uid=jo\"ops,ou=alunos",
+            "uid=jo\\zops,ou=alunos",
+            "uid=jo\\2",
+            "u id=joaops",
+        ] {
+            assert!(validar_dn(dn).is_err(), "{dn}");
+        }
+    }
+}
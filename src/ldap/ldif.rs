@@ -0,0 +1,303 @@
+//! Leitura de arquivos LDIF (RFC 2849), como os gerados pelo `slapcat` e pelo
+//! `ldapsearch -L`: as entradas separadas por linhas em branco, as linhas
+//! longas dobradas com um espaço no começo da seguinte, os comentários e os
+//! valores em base64. Os registros de mudança (`changetype`) não são aceitos.
+//! A escrita gera o mesmo formato, para os backups.
+use base64::prelude::*;
+use ldap3::SearchEntry;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ErroLdif {
+    #[error("Linha {0}: {1:?} não é um atributo")]
+    LinhaInvalida(usize, String),
+    #[error("Linha {0}: o valor em base64 é inválido")]
+    Base64Invalido(usize),
+    #[error("Linha {0}: a entrada não começa com o dn")]
+    SemDn(usize),
+    #[error("Linha {0}: registros de mudança não são aceitos")]
+    Mudanca(usize),
+    #[error("Linha {0}: valores lidos de URLs não são aceitos")]
+    Url(usize),
+}
+
+/// Lê as entradas do `ldif`. Os valores que não são UTF-8, como as fotos no
+/// `jpegPhoto`, ficam nos `bin_attrs`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::ldif::ler_ldif;
+/// let ldif = "\
+/// version: 1
+///
+/// ## Uma conta
+/// dn: uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br
+/// uid: joaops
+/// gecos: Jo
+///  ao
+/// cn:: Sm/Do28=
+/// ";
+/// let entradas = ler_ldif(ldif).unwrap();
+/// assert_eq!(entradas[0].dn, "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br");
+/// assert_eq!(entradas[0].attrs["gecos"], vec!["Joao"]);
+/// assert_eq!(entradas[0].attrs["cn"], vec!["João"]);
+/// ```
+pub fn ler_ldif(ldif: &str) -> Result<Vec<SearchEntry>, ErroLdif> {
+    let mut entradas = vec![];
+    let mut atual: Option<SearchEntry> = None;
+
+    for (numero, linha) in desdobrar(ldif) {
+        if linha.is_empty() {
+            entradas.extend(atual.take());
+            continue;
+        }
+
+        let (atributo, valor) = interpretar(numero, &linha)?;
+        let Some(entrada) = &mut atual else {
+            match (atributo.to_lowercase().as_str(), valor) {
+                ("dn", Valor::Texto(dn)) => {
+                    atual = Some(SearchEntry {
+                        dn,
+                        attrs: HashMap::new(),
+                        bin_attrs: HashMap::new(),
+                    });
+                },
+                // Só o começo do arquivo pode ter a versão
+                ("version", _) if entradas.is_empty() => {},
+                _ => return Err(ErroLdif::SemDn(numero)),
+            }
+            continue;
+        };
+
+        if atributo.eq_ignore_ascii_case("changetype") {
+            return Err(ErroLdif::Mudanca(numero));
+        }
+        match valor {
+            Valor::Texto(v) => {
+                entrada.attrs.entry(atributo).or_default().push(v)
+            },
+            Valor::Binario(v) => {
+                entrada.bin_attrs.entry(atributo).or_default().push(v)
+            },
+        }
+    }
+    entradas.extend(atual);
+
+    Ok(entradas)
+}
+
+/// Escreve as `entradas` em LDIF, com os atributos em ordem alfabética para
+/// que o mesmo diretório gere sempre o mesmo arquivo. Os valores que não
+/// podem ir como texto ficam em base64, e as linhas longas são dobradas.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::ldif::{escrever_ldif, ler_ldif};
+/// # use ldap3::SearchEntry;
+/// let entrada = SearchEntry {
+///     dn: "uid=joaops,dc=dcc,dc=ufrj,dc=br".to_string(),
+///     attrs: [("cn".to_string(), vec!["João".to_string()])].into(),
+///     bin_attrs: Default::default(),
+/// };
+/// let ldif = escrever_ldif(&[entrada]);
+/// assert_eq!(ldif, "version: 1\n\ndn: uid=joaops,dc=dcc,dc=ufrj,dc=br\ncn:: Sm/Do28=\n");
+/// assert_eq!(ler_ldif(&ldif).unwrap()[0].attrs["cn"], vec!["João"]);
+/// ```
+pub fn escrever_ldif(entradas: &[SearchEntry]) -> String {
+    let mut ldif = String::from("version: 1\n");
+
+    for entrada in entradas {
+        ldif.push('\n');
+        escrever_linha(&mut ldif, "dn", entrada.dn.as_bytes());
+
+        let mut texto: Vec<_> = entrada.attrs.iter().collect();
+        texto.sort();
+        for (atributo, valores) in texto {
+            for valor in valores {
+                escrever_linha(&mut ldif, atributo, valor.as_bytes());
+            }
+        }
+        let mut binarios: Vec<_> = entrada.bin_attrs.iter().collect();
+        binarios.sort();
+        for (atributo, valores) in binarios {
+            for valor in valores {
+                escrever_linha(&mut ldif, atributo, valor);
+            }
+        }
+    }
+    ldif
+}
+
+/// O tamanho máximo das linhas escritas, como recomenda a RFC 2849.
+const LARGURA: usize = 76;
+
+/// Escreve a linha `atributo: valor`, dobrada em [`LARGURA`] colunas.
+fn escrever_linha(ldif: &mut String, atributo: &str, valor: &[u8]) {
+    let linha = match std::str::from_utf8(valor) {
+        Ok(texto) if seguro(texto) => format!("{atributo}: {texto}"),
+        _ => format!("{atributo}:: {}", BASE64_STANDARD.encode(valor)),
+    };
+
+    // Dobra sem separar os bytes de um mesmo caractere
+    let mut largura = LARGURA;
+    let mut resto = linha.as_str();
+    while resto.len() > largura {
+        let mut corte = largura;
+        while !resto.is_char_boundary(corte) {
+            corte -= 1;
+        }
+        let (inicio, fim) = resto.split_at(corte);
+        ldif.push_str(inicio);
+        ldif.push_str("\n ");
+        resto = fim;
+        largura = LARGURA - 1;
+    }
+    ldif.push_str(resto);
+    ldif.push('\n');
+}
+
+/// Se o `valor` pode ir como texto no LDIF: só ASCII visível, sem começar
+/// com espaço, `:` ou `<`, e sem terminar com espaço.
+fn seguro(valor: &str) -> bool {
+    !valor.starts_with([' ', ':', '<'])
+        && !valor.ends_with(' ')
+        && valor.bytes().all(|b| (b' '..=b'~').contains(&b))
+}
+
+enum Valor {
+    Texto(String),
+    Binario(Vec<u8>),
+}
+
+/// Junta as linhas dobradas e tira os comentários, retornando cada linha
+/// com o número da primeira linha dela no arquivo.
+fn desdobrar(ldif: &str) -> Vec<(usize, String)> {
+    let mut linhas: Vec<(usize, String)> = vec![];
+    let mut comentario = false;
+
+    for (i, linha) in ldif.lines().enumerate() {
+        if let Some(continuacao) = linha.strip_prefix(' ') {
+            // A continuação de um comentário também é comentário
+            if !comentario && let Some((_, anterior)) = linhas.last_mut() {
+                anterior.push_str(continuacao);
+            }
+            continue;
+        }
+
+        comentario = linha.starts_with('#');
+        if !comentario {
+            linhas.push((i + 1, linha.to_string()));
+        }
+    }
+    linhas
+}
+
+/// Separa o atributo do valor em uma linha `atributo: valor`, com o valor
+/// em base64 se o separador for `::`.
+fn interpretar(
+    numero: usize,
+    linha: &str,
+) -> Result<(String, Valor), ErroLdif> {
+    let Some((atributo, resto)) = linha.split_once(':') else {
+        return Err(ErroLdif::LinhaInvalida(numero, linha.to_string()));
+    };
+    if atributo.is_empty() || atributo.contains(' ') {
+        return Err(ErroLdif::LinhaInvalida(numero, linha.to_string()));
+    }
+
+    let valor = if let Some(base64) = resto.strip_prefix(':') {
+        let bytes = BASE64_STANDARD
+            .decode(base64.trim())
+            .map_err(|_| ErroLdif::Base64Invalido(numero))?;
+        match String::from_utf8(bytes) {
+            Ok(texto) => Valor::Texto(texto),
+            Err(e) => Valor::Binario(e.into_bytes()),
+        }
+    } else if resto.starts_with('<') {
+        return Err(ErroLdif::Url(numero));
+    } else {
+        Valor::Texto(resto.trim_start_matches(' ').to_string())
+    };
+
+    Ok((atributo.to_string(), valor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn le_varias_entradas_com_valores_binarios() {
+        let ldif = "dn: uid=a,dc=x\r\n\
+                    objectClass: top\r\n\
+                    objectClass: posixAccount\r\n\
+                    jpegPhoto:: /9j/4A==\r\n\
+                    # um comentário\r\n\
+                    \x20dobrado\r\n\
+                    \r\n\
+                    \r\n\
+                    dn:: dWlkPWIsZGM9eA==\r\n\
+                    description:\r\n";
+
+        let entradas = ler_ldif(ldif).unwrap();
+        assert_eq!(entradas.len(), 2);
+        assert_eq!(
+            entradas[0].attrs["objectClass"],
+            vec!["top", "posixAccount"],
+        );
+        assert_eq!(
+            entradas[0].bin_attrs["jpegPhoto"],
+            vec![vec![0xff, 0xd8, 0xff, 0xe0]],
+        );
+        assert_eq!(entradas[1].dn, "uid=b,dc=x");
+        assert_eq!(entradas[1].attrs["description"], vec![""]);
+    }
+
+    #[test]
+    fn le_o_que_escreve() {
+        let foto = (0..=255).collect::<Vec<u8>>();
+        let gecos = "Joao ".repeat(40);
+        let entrada = SearchEntry {
+            dn: "uid=a,dc=x".to_string(),
+            attrs: [
+                ("gecos".to_string(), vec![gecos.clone()]),
+                ("cn".to_string(), vec!["Ação ".repeat(30), ":x".into()]),
+            ]
+            .into(),
+            bin_attrs: [("jpegPhoto".to_string(), vec![foto.clone()])].into(),
+        };
+
+        let ldif = escrever_ldif(&[entrada.clone(), entrada]);
+        assert!(ldif.lines().all(|l| l.len() <= LARGURA), "{ldif}");
+
+        let entradas = ler_ldif(&ldif).unwrap();
+        assert_eq!(entradas.len(), 2);
+        assert_eq!(entradas[1].attrs["gecos"], vec![gecos]);
+        assert_eq!(entradas[1].attrs["cn"][1], ":x");
+        assert_eq!(entradas[1].bin_attrs["jpegPhoto"], vec![foto]);
+    }
+
+    #[test]
+    fn recusa_o_que_nao_sabe_ler() {
+        assert_eq!(ler_ldif("uid: a\n").unwrap_err(), ErroLdif::SemDn(1));
+        assert_eq!(
+            ler_ldif("dn: uid=a\nchangetype: delete\n").unwrap_err(),
+            ErroLdif::Mudanca(2),
+        );
+        assert_eq!(
+            ler_ldif("dn: uid=a\njpegPhoto:< file:///foto.jpg\n").unwrap_err(),
+            ErroLdif::Url(2),
+        );
+        assert_eq!(
+            ler_ldif("dn: uid=a\ncn:: !!!\n").unwrap_err(),
+            ErroLdif::Base64Invalido(2),
+        );
+        assert_eq!(
+            ler_ldif("dn: uid=a\nsem separador\n").unwrap_err(),
+            ErroLdif::LinhaInvalida(2, "sem separador".to_string()),
+        );
+    }
+}
@@ -0,0 +1,614 @@
+//! Implementação em memória do [`DiretorioLdap`], usada nos testes. Ela
+//! entende o subconjunto de filtros e de operações que o alumnic usa, com as
+//! mesmas regras de um servidor LDAP: nomes de atributos e valores são
+//! comparados sem diferenciar maiúsculas de minúsculas e as modificações são
+//! atômicas.
+use crate::ldap::ErroLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros::{separar_rdn, validar_dn};
+use ldap3::{LdapError, LdapResult, Mod, Scope, SearchEntry};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Nomes alternativos de atributos definidos pelo schema do DCC. As buscas
+/// usam `dre`, mas as entradas são gravadas com `dccDRE`.
+const SINONIMOS: &[(&str, &str)] = &[("dre", "dccdre")];
+
+/// Códigos de resultado do LDAP (RFC 4511) retornados pelo diretório.
+mod rc {
+    pub const NO_SUCH_ATTRIBUTE: u32 = 16;
+    pub const ATTRIBUTE_OR_VALUE_EXISTS: u32 = 20;
+    pub const NO_SUCH_OBJECT: u32 = 32;
+    pub const NOT_ALLOWED_ON_NON_LEAF: u32 = 66;
+    pub const ENTRY_ALREADY_EXISTS: u32 = 68;
+}
+
+/// Um diretório LDAP guardado em memória.
+///
+/// # Examples
+///
+/// ```
+/// use alumnic::ldap::diretorio::DiretorioLdap;
+/// use alumnic::ldap::memoria::DiretorioMemoria;
+/// use ldap3::Scope;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut d = DiretorioMemoria::default();
+/// d.adicionar(
+///     "uid=joaops,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+///     vec![("uid", ["joaops"].into()), ("dccDRE", ["123456789"].into())],
+/// )
+/// .await
+/// .unwrap();
+///
+/// let r = d
+///     .buscar("dc=dcc,dc=ufrj,dc=br", Scope::Subtree, "(dre=123456789)", vec![])
+///     .await
+///     .unwrap();
+/// assert_eq!(r[0].attrs["uid"], vec!["joaops"]);
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct DiretorioMemoria {
+    /// Entradas indexadas pelo DN normalizado.
+    entradas: BTreeMap<String, SearchEntry>,
+}
+
+impl DiretorioMemoria {
+    /// A identidade de todas as conexões com o diretório em memória.
+    pub const IDENTIDADE: &str = "dn:cn=admin,dc=dcc,dc=ufrj,dc=br";
+
+    /// Retorna a entrada com o `dn`, se ela existir.
+    pub fn entrada(&self, dn: &str) -> Option<&SearchEntry> {
+        self.entradas.get(&normalizar_dn(dn))
+    }
+
+    /// Retorna todas as entradas do diretório.
+    pub fn entradas(&self) -> impl Iterator<Item = &SearchEntry> {
+        self.entradas.values()
+    }
+}
+
+impl DiretorioLdap for DiretorioMemoria {
+    async fn buscar(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+    ) -> Result<Vec<SearchEntry>, ErroLdap> {
+        let filtro = Filtro::interpretar(filtro)?;
+        let base = normalizar_dn(base);
+
+        Ok(self
+            .entradas
+            .iter()
+            .filter(|(dn, _)| no_escopo(dn, &base, escopo))
+            .filter(|(_, e)| filtro.aceita(e))
+            .map(|(_, e)| projetar(e, &atributos))
+            .collect())
+    }
+
+    async fn adicionar(
+        &mut self,
+        dn: &str,
+        atributos: Vec<(&str, HashSet<&str>)>,
+    ) -> Result<(), ErroLdap> {
+        validar_dn(dn)?;
+        let chave = normalizar_dn(dn);
+        if self.entradas.contains_key(&chave) {
+            return Err(erro(rc::ENTRY_ALREADY_EXISTS, dn));
+        }
+
+        let attrs = atributos
+            .into_iter()
+            .map(|(nome, valores)| {
+                (
+                    nome.to_string(),
+                    valores.into_iter().map(str::to_string).collect(),
+                )
+            })
+            .collect();
+
+        self.entradas.insert(
+            chave,
+            SearchEntry {
+                dn: dn.to_string(),
+                attrs,
+                bin_attrs: HashMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn modificar(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<&str>>,
+    ) -> Result<(), ErroLdap> {
+        let chave = normalizar_dn(dn);
+        let Some(original) = self.entradas.get(&chave) else {
+            return Err(erro(rc::NO_SUCH_OBJECT, dn));
+        };
+
+        // Aplica numa cópia para não deixar a entrada pela metade se alguma
+        // modificação falhar
+        let mut entrada = original.clone();
+        for m in mods {
+            aplicar(&mut entrada, m)?;
+        }
+        self.entradas.insert(chave, entrada);
+
+        Ok(())
+    }
+
+    async fn gravar_binario(
+        &mut self,
+        dn: &str,
+        atributo: &str,
+        valor: &[u8],
+    ) -> Result<(), ErroLdap> {
+        let Some(entrada) = self.entradas.get_mut(&normalizar_dn(dn)) else {
+            return Err(erro(rc::NO_SUCH_OBJECT, dn));
+        };
+        entrada
+            .bin_attrs
+            .insert(atributo.to_string(), vec![valor.to_vec()]);
+        Ok(())
+    }
+
+    async fn mover(
+        &mut self,
+        dn: &str,
+        nova_base: &str,
+    ) -> Result<(), ErroLdap> {
+        let chave = normalizar_dn(dn);
+        if !self.entradas.contains_key(&chave) {
+            return Err(erro(rc::NO_SUCH_OBJECT, dn));
+        }
+        if self.entradas.keys().any(|outro| {
+            *outro != chave && no_escopo(outro, &chave, Scope::Subtree)
+        }) {
+            return Err(erro(rc::NOT_ALLOWED_ON_NON_LEAF, dn));
+        }
+
+        let rdn = separar_rdn(dn).0.trim();
+        let novo_dn = format!("{rdn},{nova_base}");
+        let nova_chave = normalizar_dn(&novo_dn);
+        if self.entradas.contains_key(&nova_chave) {
+            return Err(erro(rc::ENTRY_ALREADY_EXISTS, &novo_dn));
+        }
+
+        let mut entrada = self.entradas.remove(&chave).expect("já verificada");
+        entrada.dn = novo_dn;
+        self.entradas.insert(nova_chave, entrada);
+
+        Ok(())
+    }
+
+    async fn remover(&mut self, dn: &str) -> Result<(), ErroLdap> {
+        let chave = normalizar_dn(dn);
+        if !self.entradas.contains_key(&chave) {
+            return Err(erro(rc::NO_SUCH_OBJECT, dn));
+        }
+        if self.entradas.keys().any(|outro| {
+            *outro != chave && no_escopo(outro, &chave, Scope::Subtree)
+        }) {
+            return Err(erro(rc::NOT_ALLOWED_ON_NON_LEAF, dn));
+        }
+
+        self.entradas.remove(&chave);
+        Ok(())
+    }
+
+    /// Sem bind, o diretório em memória responde sempre como o
+    /// [`IDENTIDADE`](DiretorioMemoria::IDENTIDADE).
+    async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+        Ok(Self::IDENTIDADE.to_string())
+    }
+}
+
+fn erro(codigo: u32, texto: &str) -> ErroLdap {
+    ErroLdap::ErroLdap(LdapError::LdapResult {
+        result: LdapResult {
+            rc: codigo,
+            matched: String::new(),
+            text: texto.to_string(),
+            refs: vec![],
+            ctrls: vec![],
+        },
+    })
+}
+
+fn normalizar_dn(dn: &str) -> String {
+    dn.split(',')
+        .map(|rdn| rdn.trim().to_lowercase())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn no_escopo(dn: &str, base: &str, escopo: Scope) -> bool {
+    // A parte do DN que fica abaixo da base
+    let resto = if base.is_empty() || dn == base {
+        dn.strip_suffix(base).unwrap_or(dn)
+    } else if let Some(resto) = dn.strip_suffix(&format!(",{base}")) {
+        resto
+    } else {
+        return false;
+    };
+
+    match escopo {
+        Scope::Base => resto.is_empty(),
+        Scope::OneLevel => !resto.is_empty() && !resto.contains(','),
+        Scope::Subtree => true,
+    }
+}
+
+fn mesmo_atributo(a: &str, b: &str) -> bool {
+    let canonico = |x: &str| {
+        let x = x.to_lowercase();
+        SINONIMOS
+            .iter()
+            .find(|(sinonimo, _)| *sinonimo == x)
+            .map(|(_, nome)| nome.to_string())
+            .unwrap_or(x)
+    };
+    canonico(a) == canonico(b)
+}
+
+fn valores<'a>(entrada: &'a SearchEntry, atributo: &str) -> &'a [String] {
+    entrada
+        .attrs
+        .iter()
+        .find(|(nome, _)| mesmo_atributo(nome, atributo))
+        .map(|(_, v)| v.as_slice())
+        .unwrap_or_default()
+}
+
+fn projetar(entrada: &SearchEntry, atributos: &[&str]) -> SearchEntry {
+    let pedido = |nome: &String| {
+        atributos.is_empty()
+            || atributos
+                .iter()
+                .any(|a| *a == "*" || mesmo_atributo(a, nome))
+    };
+
+    SearchEntry {
+        dn: entrada.dn.clone(),
+        attrs: entrada
+            .attrs
+            .iter()
+            .filter(|(nome, _)| pedido(nome))
+            .map(|(nome, v)| (nome.clone(), v.clone()))
+            .collect(),
+        bin_attrs: entrada
+            .bin_attrs
+            .iter()
+            .filter(|(nome, _)| pedido(nome))
+            .map(|(nome, v)| (nome.clone(), v.clone()))
+            .collect(),
+    }
+}
+
+fn aplicar(entrada: &mut SearchEntry, m: Mod<&str>) -> Result<(), ErroLdap> {
+    fn chave(entrada: &SearchEntry, atributo: &str) -> String {
+        entrada
+            .attrs
+            .keys()
+            .find(|nome| mesmo_atributo(nome, atributo))
+            .cloned()
+            .unwrap_or_else(|| atributo.to_string())
+    }
+
+    match m {
+        Mod::Add(atributo, novos) => {
+            let chave = chave(entrada, atributo);
+            let atuais = entrada.attrs.entry(chave).or_default();
+            for v in novos {
+                if atuais.iter().any(|a| a.eq_ignore_ascii_case(v)) {
+                    return Err(erro(rc::ATTRIBUTE_OR_VALUE_EXISTS, atributo));
+                }
+                atuais.push(v.to_string());
+            }
+        },
+        Mod::Delete(atributo, removidos) => {
+            let chave = chave(entrada, atributo);
+            let Some(atuais) = entrada.attrs.get_mut(&chave) else {
+                return Err(erro(rc::NO_SUCH_ATTRIBUTE, atributo));
+            };
+            if removidos.is_empty() {
+                entrada.attrs.remove(&chave);
+                return Ok(());
+            }
+            for v in removidos {
+                let Some(i) =
+                    atuais.iter().position(|a| a.eq_ignore_ascii_case(v))
+                else {
+                    return Err(erro(rc::NO_SUCH_ATTRIBUTE, atributo));
+                };
+                atuais.remove(i);
+            }
+            if atuais.is_empty() {
+                entrada.attrs.remove(&chave);
+            }
+        },
+        Mod::Replace(atributo, novos) => {
+            let chave = chave(entrada, atributo);
+            if novos.is_empty() {
+                entrada.attrs.remove(&chave);
+            } else {
+                entrada.attrs.insert(
+                    chave,
+                    novos.into_iter().map(str::to_string).collect(),
+                );
+            }
+        },
+        Mod::Increment(atributo, valor) => {
+            let chave = chave(entrada, atributo);
+            let incremento: i64 = valor
+                .parse()
+                .map_err(|_| erro(rc::NO_SUCH_ATTRIBUTE, atributo))?;
+            let Some(atual) =
+                entrada.attrs.get_mut(&chave).and_then(|v| v.first_mut())
+            else {
+                return Err(erro(rc::NO_SUCH_ATTRIBUTE, atributo));
+            };
+            let n: i64 = atual
+                .parse()
+                .map_err(|_| erro(rc::NO_SUCH_ATTRIBUTE, atributo))?;
+            *atual = (n + incremento).to_string();
+        },
+    }
+
+    Ok(())
+}
+
+/// Um filtro de busca LDAP (RFC 4515) já interpretado.
+#[derive(Debug, PartialEq)]
+enum Filtro {
+    E(Vec<Filtro>),
+    Ou(Vec<Filtro>),
+    Nao(Box<Filtro>),
+    Presente(String),
+    Igual(String, String),
+    /// Valor com `*`, dividido nos pedaços entre os `*`.
+    Parcial(String, Vec<String>),
+    MaiorOuIgual(String, String),
+    MenorOuIgual(String, String),
+}
+
+impl Filtro {
+    fn interpretar(s: &str) -> Result<Filtro, ErroLdap> {
+        let (filtro, resto) = Self::interpretar_parte(s.trim())
+            .ok_or(ErroLdap::ErroLdap(LdapError::FilterParsing))?;
+        if !resto.is_empty() {
+            return Err(ErroLdap::ErroLdap(LdapError::FilterParsing));
+        }
+        Ok(filtro)
+    }
+
+    fn interpretar_parte(s: &str) -> Option<(Filtro, &str)> {
+        let s = s.strip_prefix('(')?;
+
+        if let Some(s) = s.strip_prefix('&') {
+            let (filtros, resto) = Self::interpretar_lista(s)?;
+            return Some((Filtro::E(filtros), resto));
+        }
+        if let Some(s) = s.strip_prefix('|') {
+            let (filtros, resto) = Self::interpretar_lista(s)?;
+            return Some((Filtro::Ou(filtros), resto));
+        }
+        if let Some(s) = s.strip_prefix('!') {
+            let (f, resto) = Self::interpretar_parte(s)?;
+            let resto = resto.strip_prefix(')')?;
+            return Some((Filtro::Nao(Box::new(f)), resto));
+        }
+
+        let fim = s.find(')')?;
+        let (item, resto) = (&s[..fim], &s[fim + 1..]);
+
+        let filtro = if let Some((a, v)) = item.split_once(">=") {
+            Filtro::MaiorOuIgual(a.to_string(), desescapar(v)?)
+        } else if let Some((a, v)) = item.split_once("<=") {
+            Filtro::MenorOuIgual(a.to_string(), desescapar(v)?)
+        } else {
+            let (a, v) = item.split_once('=')?;
+            if v == "*" {
+                Filtro::Presente(a.to_string())
+            } else if v.contains('*') {
+                let pedacos =
+                    v.split('*').map(desescapar).collect::<Option<_>>()?;
+                Filtro::Parcial(a.to_string(), pedacos)
+            } else {
+                Filtro::Igual(a.to_string(), desescapar(v)?)
+            }
+        };
+
+        Some((filtro, resto))
+    }
+
+    /// Interpreta os filtros de um `&` ou `|` até o `)` que fecha a lista.
+    fn interpretar_lista(mut s: &str) -> Option<(Vec<Filtro>, &str)> {
+        let mut filtros = vec![];
+        while !s.starts_with(')') {
+            let (f, resto) = Self::interpretar_parte(s)?;
+            filtros.push(f);
+            s = resto;
+        }
+        Some((filtros, &s[1..]))
+    }
+
+    fn aceita(&self, e: &SearchEntry) -> bool {
+        let comparar = |a: &str, f: &dyn Fn(&str) -> bool| {
+            valores(e, a).iter().any(|v| f(&v.to_lowercase()))
+        };
+
+        match self {
+            Filtro::E(fs) => fs.iter().all(|f| f.aceita(e)),
+            Filtro::Ou(fs) => fs.iter().any(|f| f.aceita(e)),
+            Filtro::Nao(f) => !f.aceita(e),
+            Filtro::Presente(a) => !valores(e, a).is_empty(),
+            Filtro::Igual(a, v) => comparar(a, &|x| x == v.to_lowercase()),
+            Filtro::Parcial(a, pedacos) => comparar(a, &|x| {
+                casa_parcial(
+                    x,
+                    &pedacos
+                        .iter()
+                        .map(|p| p.to_lowercase())
+                        .collect::<Vec<_>>(),
+                )
+            }),
+            Filtro::MaiorOuIgual(a, v) => {
+                comparar(a, &|x| comparar_valores(x, v).is_ge())
+            },
+            Filtro::MenorOuIgual(a, v) => {
+                comparar(a, &|x| comparar_valores(x, v).is_le())
+            },
+        }
+    }
+}
+
+/// Compara numericamente se os dois valores forem números, e
+/// lexicograficamente caso contrário.
+fn comparar_valores(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<i64>(), b.parse::<i64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(&b.to_lowercase()),
+    }
+}
+
+fn casa_parcial(valor: &str, pedacos: &[String]) -> bool {
+    let (Some(primeiro), Some(ultimo)) = (pedacos.first(), pedacos.last())
+    else {
+        return false;
+    };
+    if !valor.starts_with(primeiro.as_str()) {
+        return false;
+    }
+
+    let mut resto = &valor[primeiro.len()..];
+    for meio in &pedacos[1..pedacos.len() - 1] {
+        let Some(i) = resto.find(meio.as_str()) else {
+            return false;
+        };
+        resto = &resto[i + meio.len()..];
+    }
+    resto.ends_with(ultimo.as_str())
+}
+
+/// Desfaz o escape `\XX` dos valores de filtro.
+fn desescapar(v: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut it = v.bytes();
+    while let Some(b) = it.next() {
+        if b == b'\\' {
+            let hex = [it.next()?, it.next()?];
+            bytes.push(
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?,
+            );
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entrada(attrs: &[(&str, &str)]) -> SearchEntry {
+        SearchEntry {
+            dn: "uid=x,dc=dcc,dc=ufrj,dc=br".to_string(),
+            attrs: attrs
+                .iter()
+                .map(|(a, v)| (a.to_string(), vec![v.to_string()]))
+                .collect(),
+            bin_attrs: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn filtros_compostos() {
+        let e = entrada(&[("objectClass", "dccAluno"), ("uid", "joaops")]);
+        let aceita = |f: &str| Filtro::interpretar(f).unwrap().aceita(&e);
+
+        assert!(aceita("(&(objectClass=dccaluno)(|(uid=a)(uid=JOAOPS)))"));
+        assert!(!aceita("(!(uid=joaops))"));
+        assert!(aceita("(uid=jo*s)"));
+        assert!(!aceita("(uid=*x*)"));
+        assert!(aceita("(uid=*)"));
+        assert!(!aceita("(cota=*)"));
+        // \2a é o escape do *
+        assert!(!aceita("(uid=jo\\2a)"));
+    }
+
+    #[test]
+    fn filtros_invalidos() {
+        assert!(Filtro::interpretar("uid=a").is_err());
+        assert!(Filtro::interpretar("(uid=a").is_err());
+        assert!(Filtro::interpretar("(uid=a))").is_err());
+    }
+
+    #[test]
+    fn escopos() {
+        let base = "dc=dcc,dc=ufrj,dc=br";
+        let filho = "ou=alunos,dc=dcc,dc=ufrj,dc=br";
+        let neto = "uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br";
+
+        assert!(no_escopo(base, base, Scope::Base));
+        assert!(!no_escopo(filho, base, Scope::Base));
+        assert!(no_escopo(filho, base, Scope::OneLevel));
+        assert!(!no_escopo(neto, base, Scope::OneLevel));
+        assert!(no_escopo(neto, base, Scope::Subtree));
+        assert!(!no_escopo("dc=outro,dc=br", base, Scope::Subtree));
+    }
+
+    #[tokio::test]
+    async fn mover_entrada() {
+        let mut d = DiretorioMemoria::default();
+        for dn in [
+            "ou=alunos,dc=dcc,dc=ufrj,dc=br",
+            "uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+        ] {
+            d.adicionar(dn, vec![]).await.unwrap();
+        }
+
+        // Entradas com filhos não podem ser movidas
+        assert!(
+            d.mover("ou=alunos,dc=dcc,dc=ufrj,dc=br", "dc=ufrj,dc=br")
+                .await
+                .is_err()
+        );
+
+        d.mover("uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br", "ou=egressos,dc=br")
+            .await
+            .unwrap();
+        assert!(d.entrada("uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br").is_none());
+        let e = d.entrada("UID=a,ou=egressos,dc=br").unwrap();
+        assert_eq!(e.dn, "uid=a,ou=egressos,dc=br");
+    }
+
+    #[tokio::test]
+    async fn remover_entrada() {
+        let mut d = DiretorioMemoria::default();
+        for dn in [
+            "ou=alunos,dc=dcc,dc=ufrj,dc=br",
+            "uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br",
+        ] {
+            d.adicionar(dn, vec![]).await.unwrap();
+        }
+
+        assert!(d.remover("ou=alunos,dc=dcc,dc=ufrj,dc=br").await.is_err());
+
+        d.remover("UID=a,ou=alunos,dc=dcc,dc=ufrj,dc=br")
+            .await
+            .unwrap();
+        assert!(d.entrada("uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br").is_none());
+        assert!(
+            d.remover("uid=a,ou=alunos,dc=dcc,dc=ufrj,dc=br")
+                .await
+                .is_err()
+        );
+    }
+}
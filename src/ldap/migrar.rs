@@ -0,0 +1,47 @@
+//! Módulo com as alterações feitas no LDAP quando um aluno da graduação entra
+//! na pós-graduação.
+use crate::configuracao::{ConfiguracaoCamposAcademicos, ConfiguracaoPos};
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::BASE_ACADEMICOS;
+use crate::ldap::cadastrar::campos_academicos;
+use crate::ldap::conta::Conta;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::Mod;
+
+/// Migra a `conta` para a pós-graduação `pos` com o `novo_dre`, retornando o
+/// seu novo DN. O DRE, o gid e a cota são trocados e a entrada é movida para
+/// a OU da pós, mas o uid, o uidNumber e o homeDirectory são mantidos. Com
+/// os `campos` acadêmicos, o curso e o período de ingresso também passam a
+/// ser os da pós.
+pub async fn migrar_para_pos<D: DiretorioLdap>(
+    conta: &Conta,
+    novo_dre: &str,
+    pos: &ConfiguracaoPos,
+    campos: Option<&ConfiguracaoCamposAcademicos>,
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    let campos = campos
+        .map(|c| campos_academicos(c, Some(&pos.curso), novo_dre))
+        .unwrap_or_default();
+
+    let mut modificacoes = vec![
+        Mod::Replace("dccDRE", [novo_dre].into()),
+        Mod::Replace("gidNumber", [pos.gid_number.as_str()].into()),
+        Mod::Replace("cota", [pos.cota.as_str()].into()),
+    ];
+    modificacoes.extend(campos.iter().map(|(atributo, valor)| {
+        Mod::Replace(*atributo, [valor.as_str()].into())
+    }));
+    ldap.modificar(&conta.dn, modificacoes).await?;
+
+    // Um aluno que já está na pós, como um do mestrado indo para o
+    // doutorado, só troca de DRE
+    let nova_base = filtros::dn("ou", &pos.ou, BASE_ACADEMICOS);
+    let (rdn, base) = filtros::separar_rdn(&conta.dn);
+    if !base.eq_ignore_ascii_case(&nova_base) {
+        ldap.mover(&conta.dn, &nova_base).await?;
+    }
+
+    Ok(format!("{rdn},{nova_base}"))
+}
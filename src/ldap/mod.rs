@@ -1,9 +1,36 @@
 //! Funções relacionadas ao sistema de LDAP usado pela supervisão do LCI para
 //! cadastro dos alunos do Instituto de Computação.
 
+pub mod alias_email;
+pub mod bloqueio;
 pub mod cadastrar;
+pub mod caixa_email;
+pub mod chaves_ssh;
+pub mod conexao;
 pub mod consulta;
+pub mod conta;
+pub mod contadores;
+pub mod cotas;
+pub mod diretorio;
+pub mod docente;
+pub mod dominios_email;
+pub mod egresso;
 pub mod error;
+pub mod espelho;
+pub mod filtros;
+pub mod ldif;
+pub mod memoria;
+pub mod migrar;
+pub mod projeto;
+pub mod renovar;
+pub mod reparo;
+pub mod reservas;
+pub mod schema;
+pub mod senha;
+pub mod turmas;
+pub mod uids_liberados;
 mod utils;
+pub mod verificacao;
 
 pub use error::{ErroLdap, Result};
+pub use utils::{ConfiguracaoPool, LdapPool};
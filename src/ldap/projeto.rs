@@ -0,0 +1,272 @@
+//! Módulo com o cadastro e a expiração das contas de projetos de extensão e
+//! de visitantes, que não são de alunos: não têm DRE nem Samba, vencem numa
+//! data fixa e têm um docente responsável.
+use crate::configuracao::{
+    ConfiguracaoProjetos, ConfiguracaoRenovacao, ConfiguracaoUsuario,
+};
+use crate::ldap::ErroLdap;
+use crate::ldap::bloqueio::PREFIXO_BLOQUEIO;
+use crate::ldap::cadastrar::{
+    EntradaUsuario, adicionar_entrada, alocar_ids, normalizar_gecos,
+};
+use crate::ldap::conta::EstadoConta;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::ldap::renovar::SEGUNDOS_POR_DIA;
+use crate::projeto::DadosDoProjeto;
+use crate::utils::hashes::hash_ssha_with_salt;
+use chrono::{DateTime, Utc};
+use ldap3::{Mod, Scope};
+use rand::Rng;
+use secrecy::ExposeSecret;
+use zeroize::Zeroize;
+
+/// Base das OUs fora das acadêmicas, como a dos projetos.
+pub const BASE_USUARIOS: &str = "ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// O home directory da conta de projeto `username`.
+pub fn home_directory_projeto(username: &str) -> String {
+    format!("/usuarios/projetos/{username}")
+}
+
+/// Se existe uma conta com o `uid` em `base`, onde ficam os docentes.
+pub async fn docente_existe<D: DiretorioLdap>(
+    uid: &str,
+    base: &str,
+    ldap: &mut D,
+) -> Result<bool, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            base,
+            Scope::Subtree,
+            &filtros::e([
+                filtros::igual("objectClass", "posixAccount"),
+                filtros::igual("uid", uid),
+            ]),
+            vec!["uid"],
+        )
+        .await?;
+    Ok(!entradas.is_empty())
+}
+
+/// Monta a entrada LDAP da conta de projeto `username`, com o `uid_number`
+/// já reservado. A conta vence no fim do dia da `expiracao` dos `dados`, pelo
+/// `shadowExpire`, e o responsável fica em `dccResponsavel`.
+pub fn montar_entrada_projeto(
+    username: &str,
+    dados: &DadosDoProjeto,
+    (cfg, projetos, prazos): (
+        &ConfiguracaoUsuario,
+        &ConfiguracaoProjetos,
+        &ConfiguracaoRenovacao,
+    ),
+    uid_number: &str,
+    agora: DateTime<Utc>,
+    salt: &[u8; 4],
+) -> EntradaUsuario {
+    let dn = filtros::dn(
+        "uid",
+        username,
+        &filtros::dn("ou", &projetos.ou, BASE_USUARIOS),
+    );
+
+    let hash_ssha = hash_ssha_with_salt(&dados.senha, salt);
+    let hoje = agora.timestamp().div_euclid(SEGUNDOS_POR_DIA);
+    let expiracao =
+        (dados.expiracao - DateTime::UNIX_EPOCH.date_naive()).num_days();
+
+    let um = |valor: &str| vec![valor.to_string()];
+    let mut nomes = dados.nome.split_whitespace();
+    let cn = nomes.next().unwrap_or_default();
+    let sn = nomes.collect::<Vec<_>>().join(" ");
+
+    let atributos = vec![
+        ("objectClass", projetos.classes.clone()),
+        ("uid", um(username)),
+        ("uidNumber", um(uid_number)),
+        (
+            "gidNumber",
+            um(projetos.gid_number.as_ref().unwrap_or(&cfg.gid_number)),
+        ),
+        ("homeDirectory", vec![home_directory_projeto(username)]),
+        ("gecos", vec![normalizar_gecos(&dados.nome)]),
+        ("cn", um(cn)),
+        // O inetOrgPerson exige um sobrenome
+        ("sn", um(if sn.is_empty() { cn } else { &sn })),
+        ("loginShell", um("/bin/bash")),
+        ("emailExterno", um(&dados.email)),
+        ("userPassword", um(hash_ssha.expose_secret())),
+        ("shadowFlag", um("-1")),
+        ("shadowInactive", um("-1")),
+        ("shadowLastChange", vec![hoje.to_string()]),
+        ("shadowMax", vec![prazos.validade_senha.to_string()]),
+        ("shadowMin", um("0")),
+        ("shadowWarning", vec![prazos.aviso_expiracao.to_string()]),
+        // O login para de funcionar depois do dia da expiração
+        ("shadowExpire", vec![(expiracao + 1).to_string()]),
+        ("cota", um(projetos.cota.as_ref().unwrap_or(&cfg.cota))),
+        ("dataCriacao", vec![hoje.to_string()]),
+        ("dccNomeProjeto", um(&dados.projeto)),
+        ("dccResponsavel", um(&dados.responsavel)),
+    ]
+    .into_iter()
+    .map(|(atributo, valores)| (atributo.to_string(), valores))
+    .collect();
+
+    EntradaUsuario { dn, atributos }
+}
+
+/// Cadastra a conta de projeto `username` em um [DiretorioLdap] já
+/// conectado, retornando o DN da entrada criada. O uidNumber sai do mesmo
+/// contador das contas de alunos.
+pub async fn cadastrar_projeto_em<D: DiretorioLdap>(
+    username: &str,
+    dados: &DadosDoProjeto,
+    cfgs: (
+        &ConfiguracaoUsuario,
+        &ConfiguracaoProjetos,
+        &ConfiguracaoRenovacao,
+    ),
+    agora: DateTime<Utc>,
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    let hoje = agora.timestamp().div_euclid(SEGUNDOS_POR_DIA);
+    let ids = alocar_ids(cfgs.0, hoje, ldap).await?;
+
+    let mut salt = [0u8; 4];
+    rand::rng().fill(&mut salt);
+    let entrada = montar_entrada_projeto(
+        username,
+        dados,
+        cfgs,
+        &ids.uid_number,
+        agora,
+        &salt,
+    );
+    salt.zeroize();
+
+    let criacao = adicionar_entrada(&entrada, ldap).await;
+    ids.devolver_se_falhou(criacao, ldap).await?;
+    Ok(entrada.dn)
+}
+
+/// Uma conta de projeto, com o que a expiração precisa dela.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContaDeProjeto {
+    pub dn: String,
+    pub uid: String,
+    /// O nome completo, sem acentos.
+    pub nome: String,
+    pub projeto: String,
+    /// O uid do docente responsável.
+    pub responsavel: String,
+    /// O dia (contado desde 01/01/1970) em que a conta para de funcionar,
+    /// do `shadowExpire`.
+    pub expiracao: i64,
+    /// Se a conta já foi bloqueada.
+    pub bloqueada: bool,
+}
+
+/// As contas de projeto da OU `ou`, com ou sem bloqueio. As entradas sem o
+/// `shadowExpire` ficam de fora.
+pub async fn contas_de_projeto<D: DiretorioLdap>(
+    ou: &str,
+    ldap: &mut D,
+) -> Result<Vec<ContaDeProjeto>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            &filtros::dn("ou", ou, BASE_USUARIOS),
+            Scope::Subtree,
+            "(&(objectClass=dccProjeto)(shadowExpire=*))",
+            vec![
+                "uid",
+                "gecos",
+                "dccNomeProjeto",
+                "dccResponsavel",
+                "shadowExpire",
+                "estadoConta",
+            ],
+        )
+        .await?;
+
+    Ok(entradas
+        .into_iter()
+        .filter_map(|e| {
+            let primeiro = |atributo: &str| {
+                e.attrs.get(atributo).and_then(|v| v.first()).cloned()
+            };
+            Some(ContaDeProjeto {
+                uid: primeiro("uid")?,
+                nome: primeiro("gecos").unwrap_or_default(),
+                projeto: primeiro("dccNomeProjeto").unwrap_or_default(),
+                responsavel: primeiro("dccResponsavel").unwrap_or_default(),
+                expiracao: primeiro("shadowExpire")?.parse().ok()?,
+                bloqueada: primeiro("estadoConta")
+                    .and_then(|v| EstadoConta::do_valor(&v))
+                    .is_some_and(|estado| estado.bloqueada()),
+                dn: e.dn,
+            })
+        })
+        .collect())
+}
+
+/// O email do docente `uid` em `base`, o institucional ou, se ele não tiver
+/// um, o externo.
+pub async fn email_do_docente<D: DiretorioLdap>(
+    uid: &str,
+    base: &str,
+    ldap: &mut D,
+) -> Result<Option<String>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            base,
+            Scope::Subtree,
+            &filtros::e([
+                filtros::igual("objectClass", "posixAccount"),
+                filtros::igual("uid", uid),
+            ]),
+            vec!["mail", "emailExterno"],
+        )
+        .await?;
+
+    Ok(entradas.into_iter().next().and_then(|e| {
+        ["mail", "emailExterno"]
+            .iter()
+            .find_map(|atributo| e.attrs.get(*atributo)?.first().cloned())
+    }))
+}
+
+/// Bloqueia a `conta` de projeto que expirou: a senha é invalidada com o
+/// [`PREFIXO_BLOQUEIO`], como nas contas de alunos, e a conta fica suspensa.
+/// Sem o Samba, não há mais nada a desativar, e o `shadowExpire` já passou.
+pub async fn bloquear_projeto<D: DiretorioLdap>(
+    conta: &ContaDeProjeto,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let senha = ldap
+        .buscar(
+            &conta.dn,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["userPassword"],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|e| e.attrs.get("userPassword")?.first().cloned())
+        .unwrap_or_default();
+    let senha = if senha.starts_with(PREFIXO_BLOQUEIO) {
+        senha
+    } else {
+        format!("{PREFIXO_BLOQUEIO}{senha}")
+    };
+
+    ldap.modificar(
+        &conta.dn,
+        vec![
+            Mod::Replace("userPassword", [senha.as_str()].into()),
+            Mod::Replace("estadoConta", [EstadoConta::Suspensa.valor()].into()),
+        ],
+    )
+    .await
+}
@@ -0,0 +1,190 @@
+//! Módulo com as alterações feitas no LDAP pela renovação anual do vínculo e
+//! pela entrada das contas não renovadas em carência.
+use crate::configuracao::ConfiguracaoRenovacao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::{Conta, EstadoConta, buscar_contas};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::Mod;
+
+pub(crate) const SEGUNDOS_POR_DIA: i64 = 24 * 60 * 60;
+
+/// Renova o vínculo da `conta` a partir do dia `hoje` (contado desde
+/// 01/01/1970), retornando o novo dia limite para a próxima renovação.
+///
+/// Além da `dataRenovacao`, a expiração do login nos laboratórios
+/// (`shadowExpire`) e no Samba (`sambaKickoffTime`) é movida para o fim da
+/// carência seguinte, e a conta volta a ficar ativa.
+pub async fn renovar_conta<D: DiretorioLdap>(
+    conta: &Conta,
+    prazos: &ConfiguracaoRenovacao,
+    hoje: i64,
+    ldap: &mut D,
+) -> Result<i64, ErroLdap> {
+    let (renovacao, valores) = valores_da_renovacao(prazos, hoje);
+
+    ldap.modificar(
+        &conta.dn,
+        valores
+            .iter()
+            .map(|(atributo, v)| Mod::Replace(*atributo, [v.as_str()].into()))
+            .collect(),
+    )
+    .await?;
+
+    Ok(renovacao)
+}
+
+/// O novo dia limite para a renovação feita no dia `hoje` e os valores dos
+/// atributos alterados por ela.
+pub(crate) fn valores_da_renovacao(
+    prazos: &ConfiguracaoRenovacao,
+    hoje: i64,
+) -> (i64, [(&'static str, String); 4]) {
+    let renovacao = hoje + prazos.validade_conta;
+    let expiracao = renovacao + prazos.carencia_dias;
+
+    (
+        renovacao,
+        [
+            ("dataRenovacao", renovacao.to_string()),
+            ("shadowExpire", expiracao.to_string()),
+            (
+                "sambaKickoffTime",
+                (expiracao * SEGUNDOS_POR_DIA).to_string(),
+            ),
+            ("estadoConta", EstadoConta::Ativa.valor().to_string()),
+        ],
+    )
+}
+
+/// Coloca em carência as contas ativas cuja data de renovação é anterior a
+/// `hoje`, retornando as contas alteradas.
+pub async fn aplicar_carencia<D: DiretorioLdap>(
+    hoje: i64,
+    ldap: &mut D,
+) -> Result<Vec<Conta>, ErroLdap> {
+    let filtro = filtros::e([
+        filtros::nao(&filtros::maior_ou_igual(
+            "dataRenovacao",
+            &hoje.to_string(),
+        )),
+        filtros::presente("dataRenovacao"),
+        filtros::nao(&filtros::igual(
+            "estadoConta",
+            EstadoConta::Carencia.valor(),
+        )),
+    ]);
+
+    let mut alteradas = vec![];
+    for mut conta in buscar_contas(&filtro, ldap).await? {
+        if conta.estado != EstadoConta::Ativa {
+            continue;
+        }
+
+        ldap.modificar(
+            &conta.dn,
+            vec![Mod::Replace(
+                "estadoConta",
+                [EstadoConta::Carencia.valor()].into(),
+            )],
+        )
+        .await?;
+
+        conta.estado = EstadoConta::Carencia;
+        alteradas.push(conta);
+    }
+
+    Ok(alteradas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::conta::buscar_conta_por_dre;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    async fn diretorio(contas: &[(&str, &str, &str)]) -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        for (uid, dre, data_renovacao) in contas {
+            d.adicionar(
+                &format!("uid={uid},ou=alunos,dc=dcc,dc=ufrj,dc=br"),
+                vec![
+                    ("objectClass", ["dcc", "dccAluno"].into()),
+                    ("uid", [*uid].into()),
+                    ("dccDRE", [*dre].into()),
+                    ("dataRenovacao", [*data_renovacao].into()),
+                    ("shadowExpire", ["-1"].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+        d
+    }
+
+    #[tokio::test]
+    async fn renovacao_estende_os_prazos() {
+        let mut d = diretorio(&[("joaops", "123456789", "20000")]).await;
+        let conta = buscar_conta_por_dre("123456789", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let prazos = ConfiguracaoRenovacao::default();
+        let renovacao =
+            renovar_conta(&conta, &prazos, 20100, &mut d).await.unwrap();
+        assert_eq!(renovacao, 20465);
+
+        let e = d.entrada(&conta.dn).unwrap();
+        assert_eq!(e.attrs["dataRenovacao"], vec!["20465"]);
+        assert_eq!(e.attrs["shadowExpire"], vec!["20525"]);
+        assert_eq!(e.attrs["sambaKickoffTime"], vec!["1773360000"]);
+        assert_eq!(e.attrs["estadoConta"], vec!["ativa"]);
+    }
+
+    #[tokio::test]
+    async fn carencia_so_para_contas_vencidas() {
+        let mut d = diretorio(&[
+            ("vencida", "111111111", "19999"),
+            ("em_dia", "222222222", "20000"),
+        ])
+        .await;
+
+        let alteradas = aplicar_carencia(20000, &mut d).await.unwrap();
+        assert_eq!(
+            alteradas.iter().map(|c| c.uid.as_str()).collect::<Vec<_>>(),
+            vec!["vencida"],
+        );
+
+        let vencida = buscar_conta_por_dre("111111111", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(vencida.estado, EstadoConta::Carencia);
+
+        // Rodar de novo não altera nada
+        assert!(aplicar_carencia(20000, &mut d).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn renovacao_tira_da_carencia() {
+        let mut d = diretorio(&[("joaops", "123456789", "19000")]).await;
+        aplicar_carencia(20000, &mut d).await.unwrap();
+
+        let conta = buscar_conta_por_dre("123456789", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conta.estado, EstadoConta::Carencia);
+
+        let prazos = ConfiguracaoRenovacao::default();
+        renovar_conta(&conta, &prazos, 20000, &mut d).await.unwrap();
+
+        let conta = buscar_conta_por_dre("123456789", &mut d)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(conta.estado, EstadoConta::Ativa);
+    }
+}
@@ -0,0 +1,245 @@
+//! Detecção e correção das contas criadas por versões antigas do alumnic, com
+//! atributos diferentes dos que o cadastro grava hoje.
+use crate::configuracao::ConfiguracaoRenovacao;
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::{BASE_ACADEMICOS, normalizar_gecos};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::dominios_email::ConfiguracaoDominiosEmail;
+use crate::ldap::filtros;
+use deunicode::deunicode;
+use ldap3::{Mod, Scope, SearchEntry};
+
+/// Atributos lidos de cada conta.
+const ATRIBUTOS: [&str; 6] = ["uid", "cn", "sn", "gecos", "mail", "shadowMax"];
+
+/// A troca do valor `atual` de um atributo pelo valor `canonico`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correcao {
+    pub atributo: &'static str,
+    pub atual: Option<String>,
+    pub canonico: String,
+}
+
+/// Uma conta com atributos fora do padrão.
+#[derive(Debug, Clone)]
+pub struct ContaAReparar {
+    pub dn: String,
+    pub uid: String,
+    pub correcoes: Vec<Correcao>,
+}
+
+/// Limpa um `gecos` antigo, que pode ter os campos extras separados por
+/// vírgula, acentos e outros caracteres, deixando só o nome.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::reparo::limpar_gecos;
+/// assert_eq!(limpar_gecos("João  Pedro,,,"), "Joao Pedro");
+/// assert_eq!(limpar_gecos("Ana (IC)"), "Ana IC");
+/// ```
+pub fn limpar_gecos(gecos: &str) -> String {
+    let nome = gecos.split(',').next().unwrap_or_default();
+    deunicode(nome)
+        .chars()
+        .map(|c| if c.is_ascii_alphabetic() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// As correções que deixam a entrada `e` com os valores canônicos, com a
+/// validade da senha dos `prazos` e o email nos `dominios` da OU.
+///
+/// O nome completo vem do `cn` e do `sn`, que guardam os acentos. Se o `sn`
+/// estiver vazio, ele é reconstruído a partir do `gecos`, sem os acentos.
+fn correcoes(
+    e: &SearchEntry,
+    prazos: &ConfiguracaoRenovacao,
+    dominios: &ConfiguracaoDominiosEmail,
+) -> Vec<Correcao> {
+    let primeiro = |atributo: &str| {
+        e.attrs
+            .get(atributo)
+            .and_then(|v| v.first())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let uid = primeiro("uid").unwrap_or_default();
+    let cn = primeiro("cn").unwrap_or_default();
+    let sn = primeiro("sn");
+    let gecos = primeiro("gecos");
+
+    let mut correcoes = vec![];
+    let mut corrigir = |atributo, atual: Option<String>, canonico: String| {
+        if atual.as_deref() != Some(canonico.as_str()) {
+            correcoes.push(Correcao {
+                atributo,
+                atual,
+                canonico,
+            });
+        }
+    };
+
+    let nome = match &sn {
+        Some(sn) => format!("{cn} {sn}"),
+        None => limpar_gecos(gecos.as_deref().unwrap_or_default()),
+    };
+    let palavras: Vec<_> = nome.split_whitespace().collect();
+    if sn.is_none() && palavras.len() > 1 {
+        corrigir("sn", None, palavras[1..].join(" "));
+    }
+    if !palavras.is_empty() {
+        corrigir("gecos", gecos, normalizar_gecos(&palavras.join(" ")));
+    }
+
+    // A OU é o segundo RDN, como em `uid=x,ou=alunos,...`
+    let ou = filtros::rdns(&e.dn)
+        .nth(1)
+        .and_then(|rdn| rdn.split_once('='))
+        .map(|(_, ou)| ou)
+        .unwrap_or_default();
+    corrigir("mail", primeiro("mail"), dominios.email(&uid, ou));
+
+    corrigir(
+        "shadowMax",
+        primeiro("shadowMax"),
+        prazos.validade_senha.to_string(),
+    );
+
+    correcoes
+}
+
+/// Busca as contas ativas dos alunos com atributos fora do padrão: `shadowMax`
+/// errado, `gecos` com lixo, `sn` vazio ou `mail` diferente do uid ou fora
+/// do domínio da OU nos `dominios`.
+pub async fn buscar_reparos<D: DiretorioLdap>(
+    prazos: &ConfiguracaoRenovacao,
+    dominios: &ConfiguracaoDominiosEmail,
+    ldap: &mut D,
+) -> Result<Vec<ContaAReparar>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            BASE_ACADEMICOS,
+            Scope::Subtree,
+            "(&(objectClass=dccAluno)(objectClass=posixAccount))",
+            ATRIBUTOS.to_vec(),
+        )
+        .await?;
+
+    let mut reparos: Vec<_> = entradas
+        .into_iter()
+        .filter_map(|e| {
+            let correcoes = correcoes(&e, prazos, dominios);
+            let uid = e.attrs.get("uid")?.first()?.clone();
+            (!correcoes.is_empty()).then_some(ContaAReparar {
+                dn: e.dn,
+                uid,
+                correcoes,
+            })
+        })
+        .collect();
+    reparos.sort_by(|a, b| a.uid.cmp(&b.uid));
+
+    Ok(reparos)
+}
+
+/// Aplica as correções da `conta`, em uma única operação.
+pub async fn reparar_conta<D: DiretorioLdap>(
+    conta: &ContaAReparar,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    ldap.modificar(
+        &conta.dn,
+        conta
+            .correcoes
+            .iter()
+            .map(|c| Mod::Replace(c.atributo, [c.canonico.as_str()].into()))
+            .collect(),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    const DN: &str =
+        "uid=joaops,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+    async fn diretorio(atributos: Vec<(&str, [&str; 1])>) -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        let mut entrada = vec![
+            ("objectClass", ["dccAluno", "posixAccount"].into()),
+            ("uid", ["joaops"].into()),
+            ("cn", ["João"].into()),
+        ];
+        entrada.extend(atributos.into_iter().map(|(a, v)| (a, v.into())));
+        d.adicionar(DN, entrada).await.unwrap();
+        d
+    }
+
+    #[tokio::test]
+    async fn conta_canonica_nao_precisa_de_reparo() {
+        let mut d = diretorio(vec![
+            ("sn", ["Pedro Silva"]),
+            ("gecos", ["Joao Pedro Silva"]),
+            ("mail", ["joaops@ic.ufrj.br"]),
+            ("shadowMax", ["3600"]),
+        ])
+        .await;
+
+        assert!(
+            buscar_reparos(
+                &ConfiguracaoRenovacao::default(),
+                &Default::default(),
+                &mut d,
+            )
+            .await
+            .unwrap()
+            .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn corrige_a_conta_antiga() {
+        let mut d = diretorio(vec![
+            ("sn", [" "]),
+            ("gecos", ["João Pedro Silva,,,"]),
+            ("mail", ["joao@ic.ufrj.br"]),
+            ("shadowMax", ["99999"]),
+        ])
+        .await;
+
+        let reparos = buscar_reparos(
+            &ConfiguracaoRenovacao::default(),
+            &Default::default(),
+            &mut d,
+        )
+        .await
+        .unwrap();
+        assert_eq!(reparos.len(), 1);
+        let atributos: Vec<_> =
+            reparos[0].correcoes.iter().map(|c| c.atributo).collect();
+        assert_eq!(atributos, ["sn", "gecos", "mail", "shadowMax"]);
+
+        reparar_conta(&reparos[0], &mut d).await.unwrap();
+        let e = d.entrada(DN).unwrap();
+        assert_eq!(e.attrs["sn"], vec!["Pedro Silva"]);
+        assert_eq!(e.attrs["gecos"], vec!["Joao Pedro Silva"]);
+        assert_eq!(e.attrs["mail"], vec!["joaops@ic.ufrj.br"]);
+        assert_eq!(e.attrs["shadowMax"], vec!["3600"]);
+        assert!(
+            buscar_reparos(
+                &ConfiguracaoRenovacao::default(),
+                &Default::default(),
+                &mut d,
+            )
+            .await
+            .unwrap()
+            .is_empty()
+        );
+    }
+}
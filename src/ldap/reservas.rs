@@ -0,0 +1,389 @@
+//! Reserva temporária dos usernames durante o cadastro. Entre a consulta de
+//! um username livre e a criação da conta pode passar tempo, como na fila da
+//! seção crítica, e outro cadastro poderia escolher o mesmo username. A
+//! reserva é uma entrada em [BASE_RESERVAS], com o `uid` reservado, o DRE de
+//! quem reservou e a validade, e a busca dos usernames livres a trata como
+//! ocupada até vencer, menos para o próprio aluno.
+use crate::configuracao::ConfiguracaoReservaUsername;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use chrono::{DateTime, TimeDelta, Utc};
+use ldap3::{LdapError, Mod, Scope, SearchEntry};
+
+/// A OU onde ficam as reservas.
+pub const BASE_RESERVAS: &str = "ou=reservas,dc=dcc,dc=ufrj,dc=br";
+
+/// O atributo com o DRE do aluno que fez a reserva.
+pub const ATRIBUTO_DONO: &str = "dccReservadoPara";
+
+/// O atributo com o fim da reserva, em segundos desde 01/01/1970.
+pub const ATRIBUTO_VALIDADE: &str = "dccReservadoAte";
+
+/// O DN da reserva do `username`.
+pub fn dn_reserva(username: &str) -> String {
+    filtros::dn("uid", username, BASE_RESERVAS)
+}
+
+fn primeiro<'a>(e: &'a SearchEntry, atributo: &str) -> Option<&'a str> {
+    e.attrs.get(atributo)?.first().map(String::as_str)
+}
+
+/// Se a entrada `e`, de uma conta ou de uma reserva, ocupa o uid dela para o
+/// aluno com o DRE `dono` no instante `agora`. Uma reserva vencida, ou feita
+/// pelo próprio `dono`, não ocupa. A entrada precisa ter sido buscada com o
+/// [ATRIBUTO_DONO] e o [ATRIBUTO_VALIDADE].
+pub(crate) fn ocupa(e: &SearchEntry, dono: Option<&str>, agora: i64) -> bool {
+    let Some(validade) = primeiro(e, ATRIBUTO_VALIDADE) else {
+        // Não é uma reserva
+        return true;
+    };
+
+    let vencida = validade.parse::<i64>().is_ok_and(|v| v <= agora);
+    let do_dono = dono.is_some() && primeiro(e, ATRIBUTO_DONO) == dono;
+    !vencida && !do_dono
+}
+
+/// A reserva do `username`, se houver.
+async fn buscar_reserva<D: DiretorioLdap>(
+    username: &str,
+    ldap: &mut D,
+) -> Result<Option<SearchEntry>, ErroLdap> {
+    Ok(ldap
+        .buscar(
+            BASE_RESERVAS,
+            Scope::OneLevel,
+            &filtros::igual("uid", username),
+            vec!["uid", ATRIBUTO_DONO, ATRIBUTO_VALIDADE],
+        )
+        .await?
+        .into_iter()
+        .next())
+}
+
+/// Reserva o `username` para o aluno com o `dre`, pelos minutos da
+/// configuração a partir de `agora`. Uma reserva vencida, ou do mesmo aluno,
+/// é renovada.
+///
+/// # Errors
+///
+/// Retorna [ErroLdap::UsernameOcupado] se outro aluno tiver uma reserva
+/// válida do `username`. Como nos contadores do Samba, a validade antiga é
+/// apagada junto, então duas renovações ao mesmo tempo não dão certo as duas.
+pub async fn reservar_username<D: DiretorioLdap>(
+    username: &str,
+    dre: &str,
+    cfg: &ConfiguracaoReservaUsername,
+    agora: DateTime<Utc>,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let ate = (agora + TimeDelta::minutes(cfg.minutos))
+        .timestamp()
+        .to_string();
+
+    let Some(reserva) = buscar_reserva(username, ldap).await? else {
+        return ldap
+            .adicionar(
+                &dn_reserva(username),
+                vec![
+                    ("objectClass", ["account", "dccReserva"].into()),
+                    ("uid", [username].into()),
+                    (ATRIBUTO_DONO, [dre].into()),
+                    (ATRIBUTO_VALIDADE, [ate.as_str()].into()),
+                ],
+            )
+            .await;
+    };
+    if ocupa(&reserva, Some(dre), agora.timestamp()) {
+        return Err(ErroLdap::UsernameOcupado(username.to_string()));
+    }
+
+    let antiga = primeiro(&reserva, ATRIBUTO_VALIDADE).unwrap_or_default();
+    ldap.modificar(
+        &reserva.dn,
+        vec![
+            Mod::Delete(ATRIBUTO_VALIDADE, [antiga].into()),
+            Mod::Add(ATRIBUTO_VALIDADE, [ate.as_str()].into()),
+            Mod::Replace(ATRIBUTO_DONO, [dre].into()),
+        ],
+    )
+    .await
+}
+
+/// Desfaz a reserva do `username` feita pelo aluno com o `dre`, como depois
+/// do cadastro. A reserva de outro aluno não é mexida.
+pub async fn liberar_username<D: DiretorioLdap>(
+    username: &str,
+    dre: &str,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    match buscar_reserva(username, ldap).await? {
+        Some(reserva) if primeiro(&reserva, ATRIBUTO_DONO) == Some(dre) => {
+            ldap.remover(&reserva.dn).await
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Faz o mesmo que [liberar_username], abrindo uma conexão com a `fonte`.
+pub async fn liberar_username_ldap<F: FonteLdap>(
+    username: &str,
+    dre: &str,
+    fonte: &F,
+) -> Result<(), ErroLdap> {
+    let mut ldap = fonte.abrir().await?;
+    let r = liberar_username(username, dre, &mut ldap).await;
+    fonte.fechar(ldap).await?;
+    r
+}
+
+/// Se o `erro` é de uma entrada ou de um valor que já não existe, como numa
+/// reserva renovada ou apagada por outro depois da busca.
+fn mudou_antes(erro: &ErroLdap) -> bool {
+    matches!(
+        erro,
+        ErroLdap::ErroLdap(LdapError::LdapResult { result })
+            // noSuchAttribute, noSuchObject
+            if matches!(result.rc, 16 | 32)
+    )
+}
+
+/// Apaga as reservas vencidas em `agora`, retornando quantas foram
+/// apagadas. Uma reserva vencida já não ocupa o username, mas as dos
+/// cadastros que falharam ou foram abandonados ficariam na OU para sempre.
+///
+/// Como em [reservar_username], a validade lida na busca é trocada por
+/// `agora` antes da remoção, e a reserva renovada nesse meio tempo, que já
+/// não tem essa validade, fica. Uma reserva que sumiu antes de ser apagada é
+/// pulada.
+pub async fn limpar_reservas<D: DiretorioLdap>(
+    agora: DateTime<Utc>,
+    ldap: &mut D,
+) -> Result<usize, ErroLdap> {
+    let reservas = ldap
+        .buscar(
+            BASE_RESERVAS,
+            Scope::OneLevel,
+            &filtros::presente(ATRIBUTO_VALIDADE),
+            vec!["uid", ATRIBUTO_DONO, ATRIBUTO_VALIDADE],
+        )
+        .await?;
+
+    let vencida = agora.timestamp().to_string();
+    let mut apagadas = 0;
+    for reserva in reservas {
+        if ocupa(&reserva, None, agora.timestamp()) {
+            continue;
+        }
+        let antiga = primeiro(&reserva, ATRIBUTO_VALIDADE).unwrap_or_default();
+        let mods = vec![
+            Mod::Delete(ATRIBUTO_VALIDADE, [antiga].into()),
+            Mod::Add(ATRIBUTO_VALIDADE, [vencida.as_str()].into()),
+        ];
+        let r = match ldap.modificar(&reserva.dn, mods).await {
+            Ok(()) => ldap.remover(&reserva.dn).await,
+            Err(e) => Err(e),
+        };
+        match r {
+            Ok(()) => apagadas += 1,
+            Err(e) if mudou_antes(&e) => {},
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(apagadas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::consulta::{Consulta, consultar_cadastro};
+    use crate::ldap::memoria::DiretorioMemoria;
+    use std::collections::HashSet;
+
+    fn agora() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_750_000_000, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reserva_vale_so_para_o_dono_ate_vencer() {
+        let mut d = DiretorioMemoria::default();
+        let cfg = ConfiguracaoReservaUsername { minutos: 30 };
+
+        reservar_username("anab", "1", &cfg, agora(), &mut d)
+            .await
+            .unwrap();
+        assert!(matches!(
+            reservar_username("anab", "2", &cfg, agora(), &mut d).await,
+            Err(ErroLdap::UsernameOcupado(_)),
+        ));
+        // O próprio aluno renova a reserva
+        reservar_username("anab", "1", &cfg, agora(), &mut d)
+            .await
+            .unwrap();
+
+        // Depois de vencida, a reserva passa para outro aluno
+        let depois = agora() + TimeDelta::minutes(31);
+        reservar_username("anab", "2", &cfg, depois, &mut d)
+            .await
+            .unwrap();
+        let reserva = d.entrada(&dn_reserva("anab")).unwrap();
+        assert_eq!(reserva.attrs[ATRIBUTO_DONO], vec!["2"]);
+
+        // Só o dono desfaz a reserva
+        liberar_username("anab", "1", &mut d).await.unwrap();
+        assert!(d.entrada(&dn_reserva("anab")).is_some());
+        liberar_username("anab", "2", &mut d).await.unwrap();
+        assert!(d.entrada(&dn_reserva("anab")).is_none());
+    }
+
+    #[tokio::test]
+    async fn limpeza_apaga_so_as_reservas_vencidas() {
+        let mut d = DiretorioMemoria::default();
+        let cfg = ConfiguracaoReservaUsername { minutos: 30 };
+        let depois = agora() + TimeDelta::minutes(20);
+        reservar_username("anab", "1", &cfg, agora(), &mut d)
+            .await
+            .unwrap();
+        reservar_username("brunoc", "2", &cfg, depois, &mut d)
+            .await
+            .unwrap();
+
+        let limpeza = agora() + TimeDelta::minutes(40);
+        assert_eq!(limpar_reservas(limpeza, &mut d).await.unwrap(), 1);
+        assert!(d.entrada(&dn_reserva("anab")).is_none());
+        assert!(d.entrada(&dn_reserva("brunoc")).is_some());
+    }
+
+    /// Um diretório em que o dono da reserva do `anab` mexe nela logo depois
+    /// da busca da limpeza, renovando ou desfazendo a reserva.
+    struct MexeNaReserva {
+        d: DiretorioMemoria,
+        renovacao: Option<DateTime<Utc>>,
+    }
+
+    impl DiretorioLdap for MexeNaReserva {
+        async fn buscar(
+            &mut self,
+            base: &str,
+            escopo: Scope,
+            filtro: &str,
+            atributos: Vec<&str>,
+        ) -> Result<Vec<SearchEntry>, ErroLdap> {
+            let r = self.d.buscar(base, escopo, filtro, atributos).await?;
+            let cfg = ConfiguracaoReservaUsername { minutos: 30 };
+            match self.renovacao {
+                Some(agora) => {
+                    reservar_username("anab", "1", &cfg, agora, &mut self.d)
+                        .await?
+                },
+                None => liberar_username("anab", "1", &mut self.d).await?,
+            }
+            Ok(r)
+        }
+
+        async fn adicionar(
+            &mut self,
+            dn: &str,
+            atributos: Vec<(&str, HashSet<&str>)>,
+        ) -> Result<(), ErroLdap> {
+            self.d.adicionar(dn, atributos).await
+        }
+
+        async fn modificar(
+            &mut self,
+            dn: &str,
+            mods: Vec<Mod<&str>>,
+        ) -> Result<(), ErroLdap> {
+            self.d.modificar(dn, mods).await
+        }
+
+        async fn gravar_binario(
+            &mut self,
+            dn: &str,
+            atributo: &str,
+            valor: &[u8],
+        ) -> Result<(), ErroLdap> {
+            self.d.gravar_binario(dn, atributo, valor).await
+        }
+
+        async fn mover(
+            &mut self,
+            dn: &str,
+            nova_base: &str,
+        ) -> Result<(), ErroLdap> {
+            self.d.mover(dn, nova_base).await
+        }
+
+        async fn remover(&mut self, dn: &str) -> Result<(), ErroLdap> {
+            self.d.remover(dn).await
+        }
+
+        async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+            self.d.quem_sou().await
+        }
+    }
+
+    /// As reservas do `anab`, do aluno 1, e do `brunoc`, do aluno 2, ambas
+    /// vencidas em `agora() + 40min`.
+    async fn reservas_vencidas() -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        let cfg = ConfiguracaoReservaUsername { minutos: 30 };
+        for (username, dre) in [("anab", "1"), ("brunoc", "2")] {
+            reservar_username(username, dre, &cfg, agora(), &mut d)
+                .await
+                .unwrap();
+        }
+        d
+    }
+
+    #[tokio::test]
+    async fn limpeza_nao_apaga_a_reserva_renovada_depois_da_busca() {
+        let limpeza = agora() + TimeDelta::minutes(40);
+        let mut d = MexeNaReserva {
+            d: reservas_vencidas().await,
+            renovacao: Some(limpeza),
+        };
+
+        assert_eq!(limpar_reservas(limpeza, &mut d).await.unwrap(), 1);
+        let reserva = d.d.entrada(&dn_reserva("anab")).unwrap();
+        let ate = (limpeza + TimeDelta::minutes(30)).timestamp().to_string();
+        assert_eq!(reserva.attrs[ATRIBUTO_VALIDADE], vec![ate]);
+        assert!(d.d.entrada(&dn_reserva("brunoc")).is_none());
+    }
+
+    #[tokio::test]
+    async fn limpeza_pula_a_reserva_desfeita_depois_da_busca() {
+        let mut d = MexeNaReserva {
+            d: reservas_vencidas().await,
+            renovacao: None,
+        };
+
+        let limpeza = agora() + TimeDelta::minutes(40);
+        assert_eq!(limpar_reservas(limpeza, &mut d).await.unwrap(), 1);
+        assert!(d.d.entrada(&dn_reserva("anab")).is_none());
+        assert!(d.d.entrada(&dn_reserva("brunoc")).is_none());
+    }
+
+    #[tokio::test]
+    async fn consulta_pula_o_username_reservado_por_outro() {
+        let mut d = DiretorioMemoria::default();
+        let cfg = ConfiguracaoReservaUsername { minutos: 30 };
+        reservar_username("anab", "1", &cfg, Utc::now(), &mut d)
+            .await
+            .unwrap();
+
+        let r = consultar_cadastro("2", "Ana Braga", &mut d).await.unwrap();
+        assert!(matches!(
+            r,
+            Consulta::CadastroDisponivel(uid) if uid == "anabraga"
+        ));
+
+        // Quem reservou continua recebendo o username
+        let r = consultar_cadastro("1", "Ana Braga", &mut d).await.unwrap();
+        assert!(matches!(
+            r,
+            Consulta::CadastroDisponivel(uid) if uid == "anab"
+        ));
+    }
+}
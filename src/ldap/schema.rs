@@ -0,0 +1,255 @@
+//! Validação do schema do LDAP, feita ao subir a API e pelo
+//! `alumnic verificar-config`. O alumnic lê o subschema do servidor e confere
+//! que ele define todas as classes e atributos que o cadastro e as outras
+//! operações gravam, para que um LDAP sem o schema do IC seja apontado logo,
+//! e não como um `objectClassViolation` no primeiro cadastro.
+use crate::configuracao::{ConfiguracaoUsuario, FormatoTelefone};
+use crate::foto::ATRIBUTO_FOTO;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use ldap3::Scope;
+use std::collections::{BTreeSet, HashMap};
+use thiserror::Error;
+
+/// As classes gravadas pelo alumnic em qualquer configuração.
+const CLASSES: &[&str] = &[
+    "dcc",
+    "dccAluno",
+    "dccProjeto",
+    "dccReserva",
+    "inetOrgPerson",
+    "ldapPublicKey",
+    "posixAccount",
+    "sambaSamAccount",
+    "shadowAccount",
+];
+
+/// Os atributos gravados pelo alumnic em qualquer configuração.
+const ATRIBUTOS: &[&str] = &[
+    // Do schema do IC
+    "cota",
+    "dataCriacao",
+    "dataRemocao",
+    "dataRenovacao",
+    "dccDRE",
+    "dccNomeProjeto",
+    "dccReservadoAte",
+    "dccReservadoPara",
+    "dccResponsavel",
+    "dccUidLiberado",
+    "emailExterno",
+    "estadoCaixa",
+    "estadoConta",
+    "monitor",
+    // Dos schemas padrão
+    "cn",
+    "gecos",
+    "gidNumber",
+    "homeDirectory",
+    ATRIBUTO_FOTO,
+    "loginShell",
+    "mail",
+    "sambaAcctFlags",
+    "sambaKickoffTime",
+    "sambaLMPassword",
+    "sambaNextRid",
+    "sambaNTPassword",
+    "sambaPasswordHistory",
+    "sambaPrimaryGroupSID",
+    "sambaPwdLastSet",
+    "sambaPwdMustChange",
+    "sambaSID",
+    "shadowExpire",
+    "shadowFlag",
+    "shadowInactive",
+    "shadowLastChange",
+    "shadowMax",
+    "shadowMin",
+    "shadowWarning",
+    "sn",
+    "sshPublicKey",
+    "telephoneNumber",
+    "uid",
+    "uidNumber",
+    "userPassword",
+];
+
+/// O DN do subschema quando o servidor não informa o `subschemaSubentry`.
+const SUBSCHEMA_PADRAO: &str = "cn=Subschema";
+
+#[derive(Debug, Error)]
+pub enum ErroDeSchema {
+    #[error("Não foi possível ler o schema do LDAP: {0}")]
+    Leitura(#[from] ErroLdap),
+    #[error("O LDAP não tem o subschema {0}")]
+    SemSubschema(String),
+    #[error(
+        "O LDAP não tem o schema do IC: faltam as classes {classes:?} e os \
+         atributos {atributos:?}"
+    )]
+    Incompleto {
+        classes: Vec<String>,
+        atributos: Vec<String>,
+    },
+}
+
+/// Os nomes das classes e dos atributos definidos por um servidor.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    /// Os nomes das classes, em minúsculas.
+    classes: BTreeSet<String>,
+    /// Os nomes dos atributos, com os sinônimos, em minúsculas.
+    atributos: BTreeSet<String>,
+}
+
+impl Schema {
+    pub fn tem_classe(&self, classe: &str) -> bool {
+        self.classes.contains(&classe.to_lowercase())
+    }
+
+    pub fn tem_atributo(&self, atributo: &str) -> bool {
+        self.atributos.contains(&atributo.to_lowercase())
+    }
+}
+
+/// Os nomes de uma definição de classe ou de atributo do subschema
+/// (RFC 4512), em minúsculas.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::ldap::schema::nomes;
+/// assert_eq!(
+///     nomes("( 1.3.6.1.4.1.4203.666.100.1.1 NAME ( 'dccDRE' 'dre' ) SINGLE-VALUE )"),
+///     ["dccdre", "dre"],
+/// );
+/// assert_eq!(nomes("( 2.5.4.3 NAME 'cn' SUP name )"), ["cn"]);
+/// assert!(nomes("( 2.5.4.3 SUP name )").is_empty());
+/// ```
+pub fn nomes(definicao: &str) -> Vec<String> {
+    let Some((_, resto)) = definicao.split_once(" NAME ") else {
+        return vec![];
+    };
+    let resto = resto.trim_start();
+    let lista = match resto.strip_prefix('(') {
+        Some(lista) => lista.split(')').next().unwrap_or_default(),
+        None => resto.split_whitespace().next().unwrap_or_default(),
+    };
+    lista
+        .split_whitespace()
+        .map(|nome| nome.trim_matches('\'').to_lowercase())
+        .filter(|nome| !nome.is_empty())
+        .collect()
+}
+
+/// Lê as classes e os atributos do subschema do `ldap`.
+pub async fn ler_schema<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<Schema, ErroDeSchema> {
+    // Alguns servidores não mostram o root DSE ao bind; nesse caso o
+    // subschema fica no DN padrão
+    let subschema = ldap
+        .buscar(
+            "",
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["subschemaSubentry"],
+        )
+        .await
+        .ok()
+        .and_then(|r| r.into_iter().next())
+        .and_then(|e| valores(&e.attrs, "subschemaSubentry").first().cloned())
+        .unwrap_or_else(|| SUBSCHEMA_PADRAO.to_string());
+
+    let entrada = ldap
+        .buscar(
+            &subschema,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["objectClasses", "attributeTypes"],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ErroDeSchema::SemSubschema(subschema.clone()))?;
+
+    let classes = valores(&entrada.attrs, "objectClasses");
+    let atributos = valores(&entrada.attrs, "attributeTypes");
+    Ok(Schema {
+        classes: classes.iter().flat_map(|d| nomes(d)).collect(),
+        atributos: atributos.iter().flat_map(|d| nomes(d)).collect(),
+    })
+}
+
+/// Os valores do `atributo`, sem diferenciar maiúsculas de minúsculas no
+/// nome dele.
+fn valores<'a>(
+    attrs: &'a HashMap<String, Vec<String>>,
+    atributo: &str,
+) -> &'a [String] {
+    attrs
+        .iter()
+        .find(|(nome, _)| nome.eq_ignore_ascii_case(atributo))
+        .map_or(&[], |(_, v)| v)
+}
+
+/// As classes e os atributos gravados pelo alumnic com a configuração `cfg`
+/// das contas novas.
+pub fn exigidos(cfg: &ConfiguracaoUsuario) -> (Vec<String>, Vec<String>) {
+    let mut classes: BTreeSet<_> =
+        CLASSES.iter().map(|c| c.to_string()).collect();
+    let mut atributos: BTreeSet<_> =
+        ATRIBUTOS.iter().map(|a| a.to_string()).collect();
+
+    if let Some(alias) = &cfg.alias_email {
+        atributos.insert(alias.atributo.clone());
+        classes.extend(alias.classe.clone());
+    }
+    if cfg.telefone.formato == FormatoTelefone::Ambos {
+        atributos.insert(cfg.telefone.atributo_nacional.clone());
+    }
+    atributos.extend(cfg.atributo_responsavel.clone());
+    if let Some(campos) = &cfg.campos_academicos {
+        atributos.insert(campos.atributo_curso.clone());
+        atributos.insert(campos.atributo_ingresso.clone());
+    }
+
+    (
+        classes.into_iter().collect(),
+        atributos.into_iter().collect(),
+    )
+}
+
+/// Confere que o schema do `ldap` tem todas as classes e atributos gravados
+/// com a configuração `cfg` das contas novas.
+///
+/// # Errors
+///
+/// - [ErroDeSchema::Incompleto], com tudo o que falta, se o schema não tem
+///   alguma classe ou atributo;
+/// - erro de conexão do LDAP ou subschema ilegível.
+pub async fn verificar_schema<F: FonteLdap>(
+    cfg: &ConfiguracaoUsuario,
+    ldap: &F,
+) -> Result<(), ErroDeSchema> {
+    let mut conexao = ldap.abrir().await?;
+    let schema = ler_schema(&mut conexao).await;
+    ldap.fechar(conexao).await?;
+    let schema = schema?;
+
+    let (classes, atributos) = exigidos(cfg);
+    let classes: Vec<_> = classes
+        .into_iter()
+        .filter(|c| !schema.tem_classe(c))
+        .collect();
+    let atributos: Vec<_> = atributos
+        .into_iter()
+        .filter(|a| !schema.tem_atributo(a))
+        .collect();
+    if classes.is_empty() && atributos.is_empty() {
+        Ok(())
+    } else {
+        Err(ErroDeSchema::Incompleto { classes, atributos })
+    }
+}
@@ -0,0 +1,42 @@
+//! Módulo com a troca da senha de uma conta pela supervisão.
+use crate::configuracao::ConfiguracaoRenovacao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::Conta;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::renovar::SEGUNDOS_POR_DIA;
+use crate::utils::hashes::{hash_nt, hash_ssha};
+use chrono::{DateTime, Utc};
+use ldap3::Mod;
+use secrecy::{ExposeSecret, SecretString};
+
+/// Troca a senha da `conta` pela `senha` em `agora`, tanto no
+/// `userPassword` quanto no Samba, e recomeça a contagem da validade da
+/// senha como no cadastro.
+pub async fn trocar_senha<D: DiretorioLdap>(
+    conta: &Conta,
+    senha: &SecretString,
+    prazos: &ConfiguracaoRenovacao,
+    agora: DateTime<Utc>,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let hash_ssha = hash_ssha(senha);
+    let hash_nt = hash_nt(senha);
+    let samba_hoje = agora.timestamp();
+    let (samba_hoje, muda_em, shadow_hoje) = (
+        samba_hoje.to_string(),
+        (samba_hoje + prazos.validade_senha * SEGUNDOS_POR_DIA).to_string(),
+        samba_hoje.div_euclid(SEGUNDOS_POR_DIA).to_string(),
+    );
+
+    ldap.modificar(
+        &conta.dn,
+        vec![
+            Mod::Replace("userPassword", [hash_ssha.expose_secret()].into()),
+            Mod::Replace("sambaNTPassword", [hash_nt.expose_secret()].into()),
+            Mod::Replace("sambaPwdLastSet", [samba_hoje.as_str()].into()),
+            Mod::Replace("sambaPwdMustChange", [muda_em.as_str()].into()),
+            Mod::Replace("shadowLastChange", [shadow_hoje.as_str()].into()),
+        ],
+    )
+    .await
+}
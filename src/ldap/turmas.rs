@@ -0,0 +1,385 @@
+//! Grupos LDAP das turmas do SIGA, um `posixGroup` por disciplina e período
+//! com os uids dos inscritos no `memberUid`.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+pub use crate::utils::periodo::Periodo;
+use ldap3::{Mod, Scope, SearchEntry};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// O prefixo do cn dos grupos das turmas.
+const PREFIXO: &str = "turma-";
+
+/// Uma turma: uma disciplina em um período.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Turma {
+    /// O código da disciplina, como o `MAB123`.
+    pub codigo: String,
+    pub periodo: Periodo,
+}
+
+impl Turma {
+    /// O cn do grupo da turma.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::turmas::{Periodo, Turma};
+    /// let turma = Turma {
+    ///     codigo: "MAB123".to_string(),
+    ///     periodo: Periodo { ano: 2024, semestre: 1 },
+    /// };
+    /// assert_eq!(turma.grupo(), "turma-mab123-2024.1");
+    /// assert_eq!(Turma::do_grupo("turma-mab123-2024.1"), Some(turma));
+    /// assert_eq!(Turma::do_grupo("professores"), None);
+    /// ```
+    pub fn grupo(&self) -> String {
+        format!("{PREFIXO}{}-{}", self.codigo.to_lowercase(), self.periodo)
+    }
+
+    /// A turma do grupo com o `cn`, se ele for o grupo de uma turma. O código
+    /// volta em maiúsculas, como no SIGA.
+    pub fn do_grupo(cn: &str) -> Option<Self> {
+        let (codigo, periodo) = cn
+            .get(..PREFIXO.len())
+            .filter(|p| p.eq_ignore_ascii_case(PREFIXO))
+            .and_then(|_| cn[PREFIXO.len()..].rsplit_once('-'))?;
+        if codigo.is_empty() {
+            return None;
+        }
+
+        Some(Turma {
+            codigo: codigo.to_uppercase(),
+            periodo: Periodo::interpretar(periodo)?,
+        })
+    }
+}
+
+/// O grupo de uma turma, como está no LDAP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrupoTurma {
+    pub dn: String,
+    pub turma: Turma,
+    pub membros: BTreeSet<String>,
+}
+
+/// Uma alteração nos grupos das turmas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MudancaTurma {
+    /// A turma ainda não tem grupo.
+    Criar {
+        turma: Turma,
+        membros: BTreeSet<String>,
+    },
+    /// Os inscritos mudaram desde a última sincronização.
+    Atualizar {
+        dn: String,
+        turma: Turma,
+        entrar: Vec<String>,
+        sair: Vec<String>,
+    },
+    /// O período da turma já terminou.
+    Remover { dn: String, turma: Turma },
+}
+
+impl MudancaTurma {
+    pub fn turma(&self) -> &Turma {
+        match self {
+            MudancaTurma::Criar { turma, .. }
+            | MudancaTurma::Atualizar { turma, .. }
+            | MudancaTurma::Remover { turma, .. } => turma,
+        }
+    }
+}
+
+impl fmt::Display for MudancaTurma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let grupo = self.turma().grupo();
+        match self {
+            MudancaTurma::Criar { membros, .. } => {
+                write!(f, "{grupo}: criado com {} membros", membros.len())
+            },
+            MudancaTurma::Atualizar { entrar, sair, .. } => {
+                write!(
+                    f,
+                    "{grupo}: {} entram, {} saem",
+                    entrar.len(),
+                    sair.len()
+                )
+            },
+            MudancaTurma::Remover { .. } => {
+                write!(f, "{grupo}: removido, o período terminou")
+            },
+        }
+    }
+}
+
+/// O primeiro valor do `atributo`.
+fn primeiro<'a>(e: &'a SearchEntry, atributo: &str) -> Option<&'a str> {
+    e.attrs
+        .get(atributo)
+        .and_then(|v| v.first())
+        .map(String::as_str)
+}
+
+/// Busca os uids das contas dos alunos, indexados pelo DRE.
+pub async fn uids_por_dre<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<BTreeMap<String, String>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            "(objectClass=dccAluno)",
+            vec!["uid", "dccDRE"],
+        )
+        .await?;
+
+    Ok(entradas
+        .iter()
+        .filter_map(|e| {
+            Some((primeiro(e, "dccDRE")?.into(), primeiro(e, "uid")?.into()))
+        })
+        .collect())
+}
+
+/// Busca os grupos das turmas que estão na `ou`. Os outros grupos da OU são
+/// ignorados.
+pub async fn buscar_grupos<D: DiretorioLdap>(
+    ou: &str,
+    ldap: &mut D,
+) -> Result<Vec<GrupoTurma>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            ou,
+            Scope::OneLevel,
+            &filtros::e([
+                filtros::igual("objectClass", "posixGroup"),
+                filtros::comeca_com("cn", PREFIXO),
+            ]),
+            vec!["cn", "memberUid"],
+        )
+        .await?;
+
+    Ok(entradas
+        .into_iter()
+        .filter_map(|mut e| {
+            let turma = Turma::do_grupo(primeiro(&e, "cn")?)?;
+            Some(GrupoTurma {
+                turma,
+                membros: e
+                    .attrs
+                    .remove("memberUid")
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+                dn: e.dn,
+            })
+        })
+        .collect())
+}
+
+/// Compara os `inscritos` de cada turma, já como uids, com os `grupos`
+/// existentes. As turmas sem grupo ganham um, e os grupos com membros
+/// diferentes são atualizados. Os grupos de períodos anteriores ao mais
+/// antigo dos `inscritos` são removidos; os dos outros períodos que não estão
+/// na listagem ficam como estão.
+pub fn comparar(
+    inscritos: &BTreeMap<Turma, BTreeSet<String>>,
+    grupos: &[GrupoTurma],
+) -> Vec<MudancaTurma> {
+    let existentes: BTreeMap<_, _> =
+        grupos.iter().map(|g| (&g.turma, g)).collect();
+    let mut mudancas = vec![];
+
+    for (turma, membros) in inscritos {
+        match existentes.get(turma) {
+            None => mudancas.push(MudancaTurma::Criar {
+                turma: turma.clone(),
+                membros: membros.clone(),
+            }),
+            Some(grupo) if grupo.membros != *membros => {
+                mudancas.push(MudancaTurma::Atualizar {
+                    dn: grupo.dn.clone(),
+                    turma: turma.clone(),
+                    entrar: membros
+                        .difference(&grupo.membros)
+                        .cloned()
+                        .collect(),
+                    sair: grupo.membros.difference(membros).cloned().collect(),
+                })
+            },
+            Some(_) => {},
+        }
+    }
+
+    if let Some(atual) = inscritos.keys().map(|t| t.periodo).min() {
+        mudancas.extend(
+            existentes
+                .values()
+                .filter(|g| g.turma.periodo < atual)
+                .map(|g| MudancaTurma::Remover {
+                    dn: g.dn.clone(),
+                    turma: g.turma.clone(),
+                }),
+        );
+    }
+
+    mudancas
+}
+
+/// Reserva o próximo gidNumber do `sambaUnixIdPool` do domínio.
+async fn proximo_gid<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<String, ErroLdap> {
+    let dominio = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::OneLevel,
+            "(objectClass=sambaDomain)",
+            vec!["gidNumber"],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ErroLdap::ErroSamba)?;
+    let gid = primeiro(&dominio, "gidNumber")
+        .ok_or(ErroLdap::ErroSamba)?
+        .to_string();
+    let prox_gid =
+        (gid.parse::<i64>().map_err(|_| ErroLdap::ErroSamba)? + 1).to_string();
+
+    ldap.modificar(
+        &dominio.dn,
+        vec![
+            Mod::Delete("gidNumber", [gid.as_str()].into()),
+            Mod::Add("gidNumber", [prox_gid.as_str()].into()),
+        ],
+    )
+    .await
+    .map_err(|_| ErroLdap::ErroSamba)?;
+
+    Ok(prox_gid)
+}
+
+/// Aplica a `mudanca` nos grupos da `ou`.
+pub async fn aplicar<D: DiretorioLdap>(
+    mudanca: &MudancaTurma,
+    ou: &str,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    match mudanca {
+        MudancaTurma::Criar { turma, membros } => {
+            let gid = proximo_gid(ldap).await?;
+            let cn = turma.grupo();
+            let descricao =
+                format!("Turma de {} em {}", turma.codigo, turma.periodo);
+
+            let mut atributos = vec![
+                ("objectClass", ["top", "posixGroup"].into()),
+                ("cn", [cn.as_str()].into()),
+                ("gidNumber", [gid.as_str()].into()),
+                ("description", [descricao.as_str()].into()),
+            ];
+            if !membros.is_empty() {
+                atributos.push((
+                    "memberUid",
+                    membros.iter().map(String::as_str).collect(),
+                ));
+            }
+
+            ldap.adicionar(&filtros::dn("cn", &cn, ou), atributos).await
+        },
+        MudancaTurma::Atualizar {
+            dn, entrar, sair, ..
+        } => {
+            let mut mods = vec![];
+            if !entrar.is_empty() {
+                mods.push(Mod::Add(
+                    "memberUid",
+                    entrar.iter().map(String::as_str).collect(),
+                ));
+            }
+            if !sair.is_empty() {
+                mods.push(Mod::Delete(
+                    "memberUid",
+                    sair.iter().map(String::as_str).collect(),
+                ));
+            }
+
+            ldap.modificar(dn, mods).await
+        },
+        MudancaTurma::Remover { dn, .. } => ldap.remover(dn).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turma(codigo: &str, ano: u16, semestre: u8) -> Turma {
+        Turma {
+            codigo: codigo.to_string(),
+            periodo: Periodo { ano, semestre },
+        }
+    }
+
+    fn uids(uids: &[&str]) -> BTreeSet<String> {
+        uids.iter().map(|u| u.to_string()).collect()
+    }
+
+    #[test]
+    fn compara_inscritos_com_os_grupos() {
+        let grupo = |t: Turma, membros: &[&str]| GrupoTurma {
+            dn: format!("cn={},ou=turmas", t.grupo()),
+            turma: t,
+            membros: uids(membros),
+        };
+        let grupos = [
+            grupo(turma("MAB123", 2024, 1), &["ana", "joaops"]),
+            grupo(turma("MAB456", 2024, 1), &["ana"]),
+            grupo(turma("MAB123", 2023, 2), &["ana"]),
+            grupo(turma("MAB789", 2024, 1), &["joaops"]),
+        ];
+        let inscritos = [
+            (turma("MAB123", 2024, 1), uids(&["joaops", "maria"])),
+            (turma("MAB456", 2024, 1), uids(&["ana"])),
+            (turma("MAB999", 2024, 1), uids(&["maria"])),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            comparar(&inscritos, &grupos),
+            vec![
+                MudancaTurma::Atualizar {
+                    dn: "cn=turma-mab123-2024.1,ou=turmas".to_string(),
+                    turma: turma("MAB123", 2024, 1),
+                    entrar: vec!["maria".to_string()],
+                    sair: vec!["ana".to_string()],
+                },
+                MudancaTurma::Criar {
+                    turma: turma("MAB999", 2024, 1),
+                    membros: uids(&["maria"]),
+                },
+                MudancaTurma::Remover {
+                    dn: "cn=turma-mab123-2023.2,ou=turmas".to_string(),
+                    turma: turma("MAB123", 2023, 2),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn listagem_vazia_nao_remove_nada() {
+        let grupos = [GrupoTurma {
+            dn: "cn=turma-mab123-2023.2,ou=turmas".to_string(),
+            turma: turma("MAB123", 2023, 2),
+            membros: uids(&["ana"]),
+        }];
+
+        assert!(comparar(&BTreeMap::new(), &grupos).is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+//! O registro dos uidNumbers liberados pelas contas removidas, guardado no
+//! objeto `sambaDomain` ao lado dos contadores. Cada valor do atributo
+//! [ATRIBUTO] é um uidNumber e o dia em que ele foi liberado, como
+//! `5003:20100`, e só pode ser dado a uma conta nova depois da quarentena.
+//!
+//! A entrada da conta removida continua com o uidNumber, como todo o resto
+//! do histórico; só as contas que não foram removidas contam como donas dele.
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::BASE_CONTAS;
+use crate::ldap::contadores::ler_contadores;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::{Mod, Scope};
+
+/// O atributo do `sambaDomain` com os uidNumbers liberados.
+pub const ATRIBUTO: &str = "dccUidLiberado";
+
+/// Um uidNumber liberado no `dia` (contado desde 01/01/1970).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UidLiberado {
+    pub dia: i64,
+    pub uid_number: i64,
+}
+
+impl UidLiberado {
+    /// Interpreta um valor do [ATRIBUTO].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::ldap::uids_liberados::UidLiberado;
+    /// let liberado = UidLiberado { dia: 20100, uid_number: 5003 };
+    /// assert_eq!(UidLiberado::interpretar("5003:20100"), Some(liberado));
+    /// assert_eq!(liberado.valor(), "5003:20100");
+    /// assert_eq!(UidLiberado::interpretar("5003"), None);
+    /// ```
+    pub fn interpretar(valor: &str) -> Option<Self> {
+        let (uid_number, dia) = valor.trim().split_once(':')?;
+        Some(UidLiberado {
+            dia: dia.parse().ok()?,
+            uid_number: uid_number.parse().ok()?,
+        })
+    }
+
+    /// O valor guardado no [ATRIBUTO].
+    pub fn valor(&self) -> String {
+        format!("{}:{}", self.uid_number, self.dia)
+    }
+}
+
+/// Os uidNumbers liberados, do mais antigo para o mais novo, e o DN do
+/// `sambaDomain` onde eles ficam.
+pub async fn listar_liberados<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<(String, Vec<UidLiberado>), ErroLdap> {
+    let dominio = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::OneLevel,
+            "(objectClass=sambaDomain)",
+            vec![ATRIBUTO],
+        )
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(ErroLdap::ErroSamba)?;
+
+    let mut liberados: Vec<_> = dominio
+        .attrs
+        .get(ATRIBUTO)
+        .into_iter()
+        .flatten()
+        .filter_map(|v| UidLiberado::interpretar(v))
+        .collect();
+    liberados.sort();
+    Ok((dominio.dn, liberados))
+}
+
+/// Acrescenta o uidNumber da conta `dn`, removida em `hoje`, aos liberados,
+/// retornando ele. Uma entrada sem uidNumber não libera nada.
+pub async fn liberar_uid<D: DiretorioLdap>(
+    dn: &str,
+    hoje: i64,
+    ldap: &mut D,
+) -> Result<Option<i64>, ErroLdap> {
+    let uid_number = ldap
+        .buscar(dn, Scope::Base, "(objectClass=*)", vec!["uidNumber"])
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|mut e| e.attrs.remove("uidNumber"))
+        .and_then(|v| v.into_iter().next())
+        .and_then(|n| n.parse().ok());
+    let Some(uid_number) = uid_number else {
+        return Ok(None);
+    };
+
+    let dominio = ler_contadores(ldap).await?;
+    let valor = UidLiberado {
+        dia: hoje,
+        uid_number,
+    }
+    .valor();
+    ldap.modificar(
+        &dominio.dn,
+        vec![Mod::Add(ATRIBUTO, [valor.as_str()].into())],
+    )
+    .await?;
+    Ok(Some(uid_number))
+}
+
+/// Tira dos liberados o uidNumber mais antigo que já cumpriu a `quarentena`
+/// em `hoje` e retorna ele, ou [None] se não houver. Como na alocação pelo
+/// contador, o valor é apagado do registro antes de ser usado, então dois
+/// cadastros ao mesmo tempo nunca recebem o mesmo; se a conta acabar não
+/// sendo criada, ele volta com [devolver_liberado]. Um uidNumber que voltou
+/// a ser usado por uma conta que não foi removida é descartado.
+pub async fn reservar_liberado<D: DiretorioLdap>(
+    hoje: i64,
+    quarentena: i64,
+    ldap: &mut D,
+) -> Result<Option<UidLiberado>, ErroLdap> {
+    let (dn, liberados) = listar_liberados(ldap).await?;
+
+    for liberado in liberados {
+        if liberado.dia + quarentena > hoje {
+            break;
+        }
+
+        let valor = liberado.valor();
+        let reservado = ldap
+            .modificar(
+                &dn,
+                vec![Mod::Delete(ATRIBUTO, [valor.as_str()].into())],
+            )
+            .await;
+        if reservado.is_err() {
+            // Outro cadastro reservou antes
+            continue;
+        }
+
+        let uid_number = liberado.uid_number.to_string();
+        if donos_do_uid_number(&uid_number, ldap).await?.is_empty() {
+            return Ok(Some(liberado));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Devolve aos liberados o `liberado` tirado por [reservar_liberado] para
+/// uma conta que não foi criada, com o mesmo dia, então ele continua na
+/// mesma posição da fila.
+pub async fn devolver_liberado<D: DiretorioLdap>(
+    liberado: UidLiberado,
+    ldap: &mut D,
+) -> Result<(), ErroLdap> {
+    let dominio = ler_contadores(ldap).await?;
+    let valor = liberado.valor();
+    ldap.modificar(
+        &dominio.dn,
+        vec![Mod::Add(ATRIBUTO, [valor.as_str()].into())],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Os uids das contas que não foram removidas com o `uid_number`. O contador
+/// do `sambaDomain`, que também é um `uidNumber`, não conta.
+pub async fn donos_do_uid_number<D: DiretorioLdap>(
+    uid_number: &str,
+    ldap: &mut D,
+) -> Result<Vec<String>, ErroLdap> {
+    Ok(ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            &filtros::e([
+                filtros::igual("uidNumber", uid_number),
+                filtros::nao(&filtros::igual("objectClass", "sambaDomain")),
+                filtros::nao(&filtros::igual("estadoConta", "removida")),
+            ]),
+            vec!["uid"],
+        )
+        .await?
+        .into_iter()
+        .map(|e| {
+            e.attrs
+                .get("uid")
+                .and_then(|v| v.first().cloned())
+                .unwrap_or(e.dn)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+
+    const DN_DOMINIO: &str = "sambaDomainName=DCC,dc=dcc,dc=ufrj,dc=br";
+
+    async fn diretorio() -> DiretorioMemoria {
+        let mut d = DiretorioMemoria::default();
+        d.adicionar(
+            DN_DOMINIO,
+            vec![
+                ("objectClass", ["sambaDomain"].into()),
+                ("uidNumber", ["5010"].into()),
+                ("sambaNextRid", ["9010"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        for (uid, uid_number, estado) in [
+            ("antigo", "5003", "removida"),
+            ("velho", "5001", "removida"),
+            ("reusado", "5002", "removida"),
+            ("novo", "5002", "ativa"),
+        ] {
+            d.adicionar(
+                &format!("uid={uid},ou=alunos,dc=dcc,dc=ufrj,dc=br"),
+                vec![
+                    ("objectClass", ["posixAccount"].into()),
+                    ("uid", [uid].into()),
+                    ("uidNumber", [uid_number].into()),
+                    ("estadoConta", [estado].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+        d
+    }
+
+    #[tokio::test]
+    async fn reserva_o_mais_antigo_depois_da_quarentena() {
+        let mut d = diretorio().await;
+        for (uid, dia) in [("antigo", 20100), ("velho", 20200)] {
+            let dn = format!("uid={uid},ou=alunos,dc=dcc,dc=ufrj,dc=br");
+            liberar_uid(&dn, dia, &mut d).await.unwrap();
+        }
+
+        assert_eq!(reservar_liberado(20199, 100, &mut d).await.unwrap(), None);
+        let liberado = reservar_liberado(20200, 100, &mut d).await.unwrap();
+        assert_eq!(liberado.map(|l| l.uid_number), Some(5003));
+        assert_eq!(reservar_liberado(20200, 100, &mut d).await.unwrap(), None);
+        let liberado = reservar_liberado(20300, 100, &mut d).await.unwrap();
+        assert_eq!(liberado.map(|l| l.uid_number), Some(5001));
+        assert!(listar_liberados(&mut d).await.unwrap().1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn descarta_o_uid_number_em_uso() {
+        let mut d = diretorio().await;
+        let dn = "uid=reusado,ou=alunos,dc=dcc,dc=ufrj,dc=br";
+        liberar_uid(dn, 20100, &mut d).await.unwrap();
+
+        assert_eq!(reservar_liberado(20500, 100, &mut d).await.unwrap(), None);
+        assert!(listar_liberados(&mut d).await.unwrap().1.is_empty());
+    }
+}
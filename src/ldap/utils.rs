@@ -1,25 +1,332 @@
 use crate::ldap::ErroLdap;
-use ldap3::{Ldap, LdapConnAsync};
-
-/// Inicia uma conexão com o servidor de LDAP, executa a função `f` e fecha a
-/// conexão com o servidor.
-pub async fn rodar_ldap<T, F, Fut>(
-    url: &str,
-    bind_dn: &str,
-    bind_pw: &str,
-    f: F,
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::metricas::registrar_operacao_ldap;
+use secrecy::SecretString;
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Executa a operação LDAP `op`, registrando a sua duração e se ela falhou nas
+/// [métricas](crate::metricas) com o nome `operacao`.
+pub async fn medir<T, Fut>(
+    operacao: &'static str,
+    op: Fut,
 ) -> Result<T, ErroLdap>
 where
-    F: FnOnce(Ldap) -> Fut,
-    Fut: Future<Output = (Result<T, ErroLdap>, Ldap)>,
+    Fut: Future<Output = Result<T, ErroLdap>>,
 {
-    let (conn, mut ldap) = LdapConnAsync::new(url).await?;
-    ldap3::drive!(conn);
-    ldap.simple_bind(bind_dn, bind_pw).await?.success()?;
+    let inicio = Instant::now();
+    let r = op.await;
+    registrar_operacao_ldap(operacao, inicio.elapsed(), r.is_ok());
+    r
+}
+
+/// Configuração do [LdapPool] da API, como
+///
+/// ```yaml
+/// ldap_pool:
+///   maximo: 8
+///   ociosidade_segundos: 300
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoPool {
+    /// Quantas conexões ociosas ficam guardadas. Com `0`, cada requisição
+    /// abre e fecha a sua conexão, como nos comandos.
+    pub maximo: usize,
+    /// Por quantos segundos uma conexão pode ficar ociosa antes de ser
+    /// fechada, para não esbarrar no `idletimeout` do servidor.
+    pub ociosidade_segundos: u64,
+}
+
+impl Default for ConfiguracaoPool {
+    fn default() -> Self {
+        Self {
+            maximo: 8,
+            ociosidade_segundos: 300,
+        }
+    }
+}
+
+/// As conexões guardadas num [LdapPool], com o momento em que foram
+/// guardadas.
+type Livres<C> = Arc<Mutex<Vec<(C, Instant)>>>;
+
+/// Uma [FonteLdap] que guarda as conexões fechadas para as próximas
+/// requisições, em vez de abrir uma conexão nova, com o TLS e o bind, a
+/// cada uma, o que pesa nos períodos de matrícula.
+///
+/// Antes de ser reusada, uma conexão guardada passa por um "Who am I?". Se
+/// o servidor tiver esquecido o bind, ele é refeito na mesma conexão com o
+/// [`autenticar`](FonteLdap::autenticar) da fonte; se o socket tiver caído
+/// ou o bind falhar, ela é descartada e a próxima é tentada, ou uma nova é
+/// aberta. As conexões ociosas há mais que a `ociosidade` e as que não são
+/// [reutilizáveis](DiretorioLdap::reutilizavel), como as com uma réplica,
+/// são fechadas em vez de guardadas. As cópias de um pool compartilham as
+/// conexões guardadas.
+pub struct LdapPool<F: FonteLdap> {
+    fonte: F,
+    livres: Livres<F::Conexao>,
+    maximo: usize,
+    ociosidade: Duration,
+}
+
+impl<F: FonteLdap + Clone> Clone for LdapPool<F> {
+    fn clone(&self) -> Self {
+        Self {
+            fonte: self.fonte.clone(),
+            livres: self.livres.clone(),
+            maximo: self.maximo,
+            ociosidade: self.ociosidade,
+        }
+    }
+}
+
+impl<F: FonteLdap> LdapPool<F> {
+    /// Um pool vazio com as conexões da `fonte`.
+    pub fn new(fonte: F, cfg: &ConfiguracaoPool) -> Self {
+        Self {
+            fonte,
+            livres: Default::default(),
+            maximo: cfg.maximo,
+            ociosidade: Duration::from_secs(cfg.ociosidade_segundos),
+        }
+    }
+
+    /// A conexão guardada há menos tempo, ou `None` se não houver.
+    fn retirar(&self) -> Option<(F::Conexao, Instant)> {
+        self.livres.lock().unwrap().pop()
+    }
+
+    /// Se a `conexao` guardada ainda responde autenticada, refazendo o bind
+    /// se o servidor o tiver esquecido.
+    async fn saudavel(&self, conexao: &mut F::Conexao) -> bool {
+        match conexao.quem_sou().await {
+            Ok(identidade) if !identidade.is_empty() => true,
+            // Uma identidade vazia é a de uma conexão anônima
+            _ => {
+                conexao.reutilizavel()
+                    && self.fonte.autenticar(conexao).await.is_ok()
+            },
+        }
+    }
+}
+
+impl<F: FonteLdap> FonteLdap for LdapPool<F> {
+    type Conexao = F::Conexao;
+
+    async fn abrir(&self) -> Result<F::Conexao, ErroLdap> {
+        while let Some((mut conexao, desde)) = self.retirar() {
+            if desde.elapsed() > self.ociosidade || !conexao.reutilizavel() {
+                let _ = self.fonte.fechar(conexao).await;
+                continue;
+            }
+            if self.saudavel(&mut conexao).await {
+                return Ok(conexao);
+            }
+        }
+        self.fonte.abrir().await
+    }
+
+    async fn fechar(&self, conexao: F::Conexao) -> Result<(), ErroLdap> {
+        if conexao.reutilizavel() {
+            let mut livres = self.livres.lock().unwrap();
+            if livres.len() < self.maximo {
+                livres.push((conexao, Instant::now()));
+                return Ok(());
+            }
+        }
+        self.fonte.fechar(conexao).await
+    }
+
+    fn autenticar(
+        &self,
+        conexao: &mut F::Conexao,
+    ) -> impl Future<Output = Result<(), ErroLdap>> + Send {
+        self.fonte.autenticar(conexao)
+    }
+
+    fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> impl Future<Output = Result<bool, ErroLdap>> + Send {
+        self.fonte.verificar_senha(dn, senha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3::{Mod, Scope, SearchEntry};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Uma conexão que só responde ao "Who am I?".
+    struct ConexaoFalsa {
+        id: usize,
+        autenticada: bool,
+        aberta: bool,
+    }
+
+    impl DiretorioLdap for ConexaoFalsa {
+        async fn buscar(
+            &mut self,
+            _: &str,
+            _: Scope,
+            _: &str,
+            _: Vec<&str>,
+        ) -> Result<Vec<SearchEntry>, ErroLdap> {
+            Ok(vec![])
+        }
+
+        async fn adicionar(
+            &mut self,
+            _: &str,
+            _: Vec<(&str, HashSet<&str>)>,
+        ) -> Result<(), ErroLdap> {
+            Ok(())
+        }
+
+        async fn modificar(
+            &mut self,
+            _: &str,
+            _: Vec<Mod<&str>>,
+        ) -> Result<(), ErroLdap> {
+            Ok(())
+        }
+
+        async fn gravar_binario(
+            &mut self,
+            _: &str,
+            _: &str,
+            _: &[u8],
+        ) -> Result<(), ErroLdap> {
+            Ok(())
+        }
+
+        async fn mover(&mut self, _: &str, _: &str) -> Result<(), ErroLdap> {
+            Ok(())
+        }
+
+        async fn remover(&mut self, _: &str) -> Result<(), ErroLdap> {
+            Ok(())
+        }
+
+        async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+            Ok(match self.autenticada {
+                true => "dn:cn=admin,dc=dcc,dc=ufrj,dc=br".to_string(),
+                false => String::new(),
+            })
+        }
+
+        fn reutilizavel(&self) -> bool {
+            self.aberta
+        }
+    }
+
+    /// Uma fonte que conta as conexões abertas e fechadas e os binds.
+    #[derive(Default)]
+    struct FonteFalsa {
+        abertas: AtomicUsize,
+        fechadas: AtomicUsize,
+        binds: AtomicUsize,
+    }
+
+    impl FonteLdap for FonteFalsa {
+        type Conexao = ConexaoFalsa;
+
+        async fn abrir(&self) -> Result<ConexaoFalsa, ErroLdap> {
+            Ok(ConexaoFalsa {
+                id: self.abertas.fetch_add(1, Ordering::SeqCst),
+                autenticada: true,
+                aberta: true,
+            })
+        }
+
+        async fn fechar(&self, _: ConexaoFalsa) -> Result<(), ErroLdap> {
+            self.fechadas.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn autenticar(
+            &self,
+            conexao: &mut ConexaoFalsa,
+        ) -> Result<(), ErroLdap> {
+            self.binds.fetch_add(1, Ordering::SeqCst);
+            conexao.autenticada = true;
+            Ok(())
+        }
+
+        async fn verificar_senha(
+            &self,
+            _: &str,
+            _: &SecretString,
+        ) -> Result<bool, ErroLdap> {
+            Ok(false)
+        }
+    }
+
+    fn pool(maximo: usize, ociosidade_segundos: u64) -> LdapPool<FonteFalsa> {
+        let cfg = ConfiguracaoPool {
+            maximo,
+            ociosidade_segundos,
+        };
+        LdapPool::new(FonteFalsa::default(), &cfg)
+    }
+
+    #[tokio::test]
+    async fn reusa_as_conexoes_fechadas_ate_o_maximo() {
+        let pool = pool(2, 300);
+
+        let conexao = pool.abrir().await.unwrap();
+        pool.fechar(conexao).await.unwrap();
+        let conexao = pool.abrir().await.unwrap();
+        assert_eq!(conexao.id, 0);
+        assert_eq!(pool.fonte.abertas.load(Ordering::SeqCst), 1);
+
+        let outras = [pool.abrir().await.unwrap(), pool.abrir().await.unwrap()];
+        assert_eq!(pool.fonte.abertas.load(Ordering::SeqCst), 3);
+        pool.fechar(conexao).await.unwrap();
+        for conexao in outras {
+            pool.fechar(conexao).await.unwrap();
+        }
+        // Só cabem duas no pool, e a terceira é fechada de verdade
+        assert_eq!(pool.fonte.fechadas.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.livres.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn descarta_as_ociosas_e_as_que_cairam() {
+        let pool = pool(2, 0);
+
+        let conexao = pool.abrir().await.unwrap();
+        pool.fechar(conexao).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let mut conexao = pool.abrir().await.unwrap();
+        assert_eq!(conexao.id, 1);
+        assert_eq!(pool.fonte.fechadas.load(Ordering::SeqCst), 1);
+
+        // Uma conexão cujo socket caiu nem chega a ser guardada
+        conexao.aberta = false;
+        pool.fechar(conexao).await.unwrap();
+        assert_eq!(pool.fonte.fechadas.load(Ordering::SeqCst), 2);
+        assert!(pool.livres.lock().unwrap().is_empty());
+    }
 
-    let (ret, mut ldap) = f(ldap).await;
+    #[tokio::test]
+    async fn refaz_o_bind_esquecido_pelo_servidor() {
+        let pool = pool(2, 300);
 
-    ldap.unbind().await?;
+        let mut conexao = pool.abrir().await.unwrap();
+        conexao.autenticada = false;
+        pool.fechar(conexao).await.unwrap();
 
-    ret
+        let conexao = pool.abrir().await.unwrap();
+        assert_eq!(conexao.id, 0);
+        assert!(conexao.autenticada);
+        assert_eq!(pool.fonte.binds.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.fonte.abertas.load(Ordering::SeqCst), 1);
+    }
 }
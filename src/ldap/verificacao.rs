@@ -0,0 +1,131 @@
+//! Verificação das credenciais e das permissões do bind com o LDAP, feita ao
+//! subir a API e pelo `alumnic verificar-config`. Em vez de descobrir no
+//! primeiro cadastro de verdade que o bind DN não pode escrever nas OUs dos
+//! alunos, o alumnic pergunta ao servidor quem ele é e cria, altera e apaga
+//! uma entrada descartável em cada OU onde cadastra contas.
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::BASE_ACADEMICOS;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use ldap3::Mod;
+use thiserror::Error;
+
+/// As OUs onde o cadastro cria as contas.
+const OUS_DO_CADASTRO: [&str; 2] = ["alunos", "profcomp"];
+
+#[derive(Debug, Error)]
+pub enum ErroDeVerificacao {
+    #[error("Não foi possível fazer o bind com o LDAP: {0}")]
+    Bind(ErroLdap),
+    #[error("O LDAP não respondeu com que identidade fez o bind: {0}")]
+    Identidade(ErroLdap),
+    #[error("O bind com o LDAP é anônimo; confira o ldap_bind_dn")]
+    Anonimo,
+    #[error("A identidade {identidade} não pode {operacao} em {dn}: {erro}")]
+    SemPermissao {
+        identidade: String,
+        operacao: &'static str,
+        dn: String,
+        erro: ErroLdap,
+    },
+    #[error("A entrada de teste {0} ficou no LDAP e precisa ser apagada: {1}")]
+    EntradaEsquecida(String, ErroLdap),
+}
+
+impl ErroDeVerificacao {
+    /// Se a verificação falhou porque o servidor principal está fora do ar e
+    /// a conexão foi com uma réplica, que só aceita consultas.
+    pub fn somente_leitura(&self) -> bool {
+        matches!(
+            self,
+            ErroDeVerificacao::SemPermissao {
+                erro: ErroLdap::SomenteLeitura,
+                ..
+            }
+        )
+    }
+}
+
+/// Verifica o bind com o `ldap` e as permissões dele nas OUs do cadastro,
+/// retornando a identidade com que o servidor autorizou a conexão.
+pub async fn verificar_bind<F: FonteLdap>(
+    ldap: &F,
+) -> Result<String, ErroDeVerificacao> {
+    let mut conexao = ldap.abrir().await.map_err(ErroDeVerificacao::Bind)?;
+    let r = async {
+        let identidade = conexao
+            .quem_sou()
+            .await
+            .map_err(ErroDeVerificacao::Identidade)?;
+        if identidade.is_empty() {
+            return Err(ErroDeVerificacao::Anonimo);
+        }
+
+        for ou in OUS_DO_CADASTRO {
+            escrever_e_apagar(&identidade, ou, &mut conexao).await?;
+        }
+        Ok(identidade)
+    }
+    .await;
+    ldap.fechar(conexao)
+        .await
+        .map_err(ErroDeVerificacao::Bind)?;
+    r
+}
+
+/// Cria, altera e apaga uma entrada descartável na `ou`.
+async fn escrever_e_apagar<D: DiretorioLdap>(
+    identidade: &str,
+    ou: &str,
+    ldap: &mut D,
+) -> Result<(), ErroDeVerificacao> {
+    let cn = format!("alumnic-verificacao-{:08x}", rand::random::<u32>());
+    let dn = filtros::dn("cn", &cn, &filtros::dn("ou", ou, BASE_ACADEMICOS));
+    let sem_permissao = |operacao, erro| ErroDeVerificacao::SemPermissao {
+        identidade: identidade.to_string(),
+        operacao,
+        dn: dn.clone(),
+        erro,
+    };
+
+    ldap.adicionar(
+        &dn,
+        vec![
+            ("objectClass", ["top", "organizationalRole"].into()),
+            ("cn", [cn.as_str()].into()),
+            ("description", ["Teste do alumnic; pode ser apagada"].into()),
+        ],
+    )
+    .await
+    .map_err(|e| sem_permissao("criar entradas", e))?;
+
+    let alterada = ldap
+        .modificar(&dn, vec![Mod::Replace("description", ["Alterada"].into())])
+        .await
+        .map_err(|e| sem_permissao("alterar entradas", e));
+
+    // A entrada é apagada mesmo se a alteração falhar
+    ldap.remover(&dn)
+        .await
+        .map_err(|e| ErroDeVerificacao::EntradaEsquecida(dn.clone(), e))?;
+    alterada
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ldap::memoria::DiretorioMemoria;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn verifica_sem_deixar_entradas() {
+        let ldap = Arc::new(Mutex::new(DiretorioMemoria::default()));
+
+        let identidade = verificar_bind(&ldap).await.unwrap();
+
+        assert_eq!(identidade, DiretorioMemoria::IDENTIDADE);
+        assert_eq!(ldap.lock().await.entradas().count(), 0);
+    }
+}
@@ -1,6 +1,60 @@
+pub mod agendador;
 pub mod api;
+pub mod auditoria;
+pub mod autorizacao;
+pub mod backup;
 pub mod cadastro_aluno;
+pub mod cadastros_por_ip;
+pub mod caixa_email;
+pub mod cancelamento;
+pub mod chaves_ssh;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod configuracao;
+pub mod contingencia;
+pub mod cotas;
+pub mod desligamento;
+pub mod docente;
+#[cfg(feature = "pdf")]
+pub mod documento_pdf;
+pub mod duplicidade;
+pub mod egresso;
+pub mod em_andamento;
+pub mod espelho_ad;
+pub mod estatisticas;
+pub mod exportacao;
+pub mod forja;
+pub mod foto;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hooks;
+pub mod impressao;
+#[cfg(feature = "kerberos")]
+pub mod kerberos;
 pub mod ldap;
+pub mod lotes;
+pub mod manutencao;
+pub mod metricas;
+pub mod migracao;
+pub mod moodle;
+pub mod notificacao;
+pub mod origens;
+pub mod painel;
+pub mod plano;
 pub mod portal_ufrj;
+pub mod prazos;
+pub mod projeto;
+pub mod proximos_passos;
+pub mod reativacao;
+pub mod reconciliacao;
+pub mod redefinicao_senha;
+pub mod relatorio;
+pub mod remocao;
+pub mod renovacao;
+pub mod restauracao;
+pub mod scim;
+#[cfg(feature = "dev")]
+pub mod simulacao;
+pub mod totp;
+pub mod turmas;
 pub mod utils;
@@ -0,0 +1,315 @@
+//! Acompanhamento dos lotes em andamento, como o `alumnic renovar` e a fila
+//! de contingência da API. Cada lote grava o progresso em
+//! `DADOS/lotes/ID.json` depois de cada item, para que o `alumnic lotes` e a
+//! rota `/api/admin/lotes` mostrem os lotes de qualquer processo, com os
+//! itens processados, as falhas e a previsão de término.
+//!
+//! Um lote é pausado com o arquivo `DADOS/lotes/ID.pausa`: ele confere a
+//! pausa no mesmo ponto seguro do [cancelamento](crate::cancelamento), antes
+//! de começar cada item, então os itens em andamento terminam e os próximos
+//! esperam a pausa ser retirada.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// De quanto em quanto tempo um lote pausado confere se pode continuar.
+const INTERVALO_PAUSA: Duration = Duration::from_secs(1);
+
+/// O progresso de um lote, como gravado no arquivo dele.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressoLote {
+    pub id: String,
+    /// O que o lote faz, como `renovacao` ou `contingencia`.
+    pub tipo: String,
+    /// O processo que roda o lote.
+    pub pid: u32,
+    pub total: usize,
+    /// Os itens terminados, com sucesso ou não.
+    pub processados: usize,
+    pub falhas: usize,
+    pub inicio: DateTime<Utc>,
+    pub atualizado: DateTime<Utc>,
+    /// O tempo que o lote passou pausado, que fica fora da previsão.
+    pub segundos_pausado: u64,
+    /// Se o lote foi pausado; os itens em andamento ainda podem terminar.
+    #[serde(default)]
+    pub pausado: bool,
+    /// Quanto falta para o lote terminar, pelo ritmo até agora.
+    #[serde(default)]
+    pub segundos_restantes: Option<u64>,
+}
+
+impl ProgressoLote {
+    /// A previsão de quanto falta, pelo tempo médio dos itens processados
+    /// sem contar as pausas, ou `None` antes do primeiro item.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::lotes::ProgressoLote;
+    /// # use chrono::{TimeDelta, Utc};
+    /// # use std::time::Duration;
+    /// let inicio = Utc::now();
+    /// let mut p = ProgressoLote {
+    ///     id: "renovacao-1".into(),
+    ///     tipo: "renovacao".into(),
+    ///     pid: 1,
+    ///     total: 100,
+    ///     processados: 0,
+    ///     falhas: 0,
+    ///     inicio,
+    ///     atualizado: inicio + TimeDelta::seconds(60),
+    ///     segundos_pausado: 20,
+    ///     pausado: false,
+    ///     segundos_restantes: None,
+    /// };
+    /// assert_eq!(p.restante(), None);
+    /// p.processados = 20;
+    /// assert_eq!(p.restante(), Some(Duration::from_secs(160)));
+    /// ```
+    pub fn restante(&self) -> Option<Duration> {
+        if self.processados == 0 {
+            return None;
+        }
+        let ativo = (self.atualizado - self.inicio)
+            .to_std()
+            .unwrap_or_default()
+            .saturating_sub(Duration::from_secs(self.segundos_pausado));
+        let faltam = self.total.saturating_sub(self.processados) as u32;
+        Some(ativo / self.processados as u32 * faltam)
+    }
+}
+
+/// O diretório dos lotes dentro do diretório dos `dados`.
+fn diretorio(dados: &Path) -> PathBuf {
+    dados.join("lotes")
+}
+
+fn arquivo(dados: &Path, id: &str) -> PathBuf {
+    diretorio(dados).join(format!("{id}.json"))
+}
+
+fn arquivo_pausa(dados: &Path, id: &str) -> PathBuf {
+    diretorio(dados).join(format!("{id}.pausa"))
+}
+
+/// Um lote em andamento neste processo, com o `cancelamento` que para os
+/// itens que ainda não começaram. Os arquivos do acompanhamento são apagados
+/// quando ele é destruído.
+pub struct Lote {
+    /// O diretório dos dados, ou `None` se o lote não é acompanhado.
+    dados: Option<PathBuf>,
+    progresso: Mutex<ProgressoLote>,
+    cancelamento: CancellationToken,
+    /// Quando a última espera pela pausa terminou, para que os itens que
+    /// esperam juntos contem a mesma pausa uma vez só.
+    fim_da_pausa: Mutex<Option<Instant>>,
+}
+
+impl Lote {
+    /// Um lote sem acompanhamento, que só para com o `cancelamento`.
+    pub fn sem_acompanhamento(cancelamento: CancellationToken) -> Lote {
+        Lote::novo(None, "lote", 0, cancelamento)
+    }
+
+    /// Começa o acompanhamento de um lote de `total` itens do `tipo`,
+    /// gravando o arquivo dele no diretório dos `dados`.
+    pub fn iniciar(
+        dados: &Path,
+        tipo: &str,
+        total: usize,
+        cancelamento: CancellationToken,
+    ) -> io::Result<Lote> {
+        std::fs::create_dir_all(diretorio(dados))?;
+        let lote =
+            Lote::novo(Some(dados.to_path_buf()), tipo, total, cancelamento);
+        lote.gravar(&lote.progresso.lock().unwrap())?;
+        Ok(lote)
+    }
+
+    /// Como o [Lote::iniciar], mas o lote segue sem acompanhamento se o
+    /// arquivo dele não pode ser gravado, com o erro no log.
+    pub fn acompanhar(
+        dados: &Path,
+        tipo: &str,
+        total: usize,
+        cancelamento: CancellationToken,
+    ) -> Lote {
+        match Lote::iniciar(dados, tipo, total, cancelamento.clone()) {
+            Ok(lote) => lote,
+            Err(e) => {
+                eprintln!("O lote de {tipo} vai rodar sem acompanhamento: {e}");
+                Lote::sem_acompanhamento(cancelamento)
+            },
+        }
+    }
+
+    fn novo(
+        dados: Option<PathBuf>,
+        tipo: &str,
+        total: usize,
+        cancelamento: CancellationToken,
+    ) -> Lote {
+        let agora = Utc::now();
+        let pid = std::process::id();
+        Lote {
+            dados,
+            cancelamento,
+            fim_da_pausa: Mutex::new(None),
+            progresso: Mutex::new(ProgressoLote {
+                id: format!("{tipo}-{pid}-{}", agora.timestamp_millis()),
+                tipo: tipo.to_string(),
+                pid,
+                total,
+                processados: 0,
+                falhas: 0,
+                inicio: agora,
+                atualizado: agora,
+                segundos_pausado: 0,
+                pausado: false,
+                segundos_restantes: None,
+            }),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.progresso.lock().unwrap().id.clone()
+    }
+
+    /// Grava o `progresso` com outro nome e só depois renomeia, para quem lê
+    /// nunca ver um arquivo pela metade.
+    fn gravar(&self, progresso: &ProgressoLote) -> io::Result<()> {
+        let Some(dados) = &self.dados else {
+            return Ok(());
+        };
+        let arquivo = arquivo(dados, &progresso.id);
+        let mut novo = arquivo.clone().into_os_string();
+        novo.push(".novo");
+        std::fs::write(&novo, serde_json::to_vec(progresso)?)?;
+        std::fs::rename(&novo, &arquivo)
+    }
+
+    /// Atualiza o progresso e grava o arquivo, com o erro no log, para que
+    /// uma falha no acompanhamento não pare o lote.
+    fn atualizar(&self, f: impl FnOnce(&mut ProgressoLote)) {
+        let mut progresso = self.progresso.lock().unwrap();
+        f(&mut progresso);
+        progresso.atualizado = Utc::now();
+        if let Err(e) = self.gravar(&progresso) {
+            eprintln!("Não foi possível gravar o progresso do lote: {e}");
+        }
+    }
+
+    /// Espera enquanto o lote estiver pausado, antes de começar um item, e
+    /// diz se o item pode começar, o que não acontece depois do
+    /// cancelamento.
+    pub async fn comecar_item(&self) -> bool {
+        let Some(dados) = &self.dados else {
+            return !self.cancelamento.is_cancelled();
+        };
+        let pausa = arquivo_pausa(dados, &self.id());
+        if pausa.exists() {
+            let inicio = Instant::now();
+            while pausa.exists() && !self.cancelamento.is_cancelled() {
+                tokio::select! {
+                    _ = tokio::time::sleep(INTERVALO_PAUSA) => {},
+                    _ = self.cancelamento.cancelled() => {},
+                }
+            }
+            let fim = Instant::now();
+            let mut fim_da_pausa = self.fim_da_pausa.lock().unwrap();
+            let desde = fim_da_pausa.map_or(inicio, |f| f.max(inicio));
+            *fim_da_pausa = Some(fim);
+            drop(fim_da_pausa);
+            let pausado = (fim - desde).as_secs();
+            self.atualizar(|p| p.segundos_pausado += pausado);
+        }
+        !self.cancelamento.is_cancelled()
+    }
+
+    /// Conta um item terminado, que falhou se não teve `sucesso`.
+    pub fn registrar(&self, sucesso: bool) {
+        self.atualizar(|p| {
+            p.processados += 1;
+            if !sucesso {
+                p.falhas += 1;
+            }
+        });
+    }
+}
+
+impl Drop for Lote {
+    fn drop(&mut self) {
+        if let Some(dados) = &self.dados {
+            let id = self.id();
+            let _ = std::fs::remove_file(arquivo(dados, &id));
+            let _ = std::fs::remove_file(arquivo_pausa(dados, &id));
+        }
+    }
+}
+
+/// Se o processo `pid` ainda existe.
+fn processo_vivo(pid: u32) -> bool {
+    Path::new(&format!("/proc/{pid}")).exists()
+}
+
+/// Os lotes em andamento gravados no diretório dos `dados`, dos mais antigos
+/// para os mais novos. Os arquivos dos lotes de processos que morreram sem
+/// apagá-los são apagados.
+pub fn lotes(dados: &Path) -> io::Result<Vec<ProgressoLote>> {
+    let entradas = match std::fs::read_dir(diretorio(dados)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        r => r?,
+    };
+
+    let mut lotes = vec![];
+    for entrada in entradas {
+        let caminho = entrada?.path();
+        if caminho.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let Ok(mut lote) = std::fs::read(&caminho)
+            .map_err(serde_json::Error::io)
+            .and_then(|b| serde_json::from_slice::<ProgressoLote>(&b))
+        else {
+            continue;
+        };
+        if !processo_vivo(lote.pid) {
+            let _ = std::fs::remove_file(&caminho);
+            let _ = std::fs::remove_file(arquivo_pausa(dados, &lote.id));
+            continue;
+        }
+        lote.pausado = arquivo_pausa(dados, &lote.id).exists();
+        lote.segundos_restantes = lote.restante().map(|r| r.as_secs());
+        lotes.push(lote);
+    }
+    lotes.sort_by_key(|l| l.inicio);
+    Ok(lotes)
+}
+
+/// Pausa ou retoma o lote `id`, retornando o progresso dele, ou `None` se
+/// ele não está em andamento.
+pub fn pausar(
+    dados: &Path,
+    id: &str,
+    pausado: bool,
+) -> io::Result<Option<ProgressoLote>> {
+    let Some(mut lote) = lotes(dados)?.into_iter().find(|l| l.id == id) else {
+        return Ok(None);
+    };
+
+    let pausa = arquivo_pausa(dados, id);
+    if pausado {
+        std::fs::write(&pausa, b"")?;
+    } else if let Err(e) = std::fs::remove_file(&pausa)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        return Err(e);
+    }
+    lote.pausado = pausado;
+    Ok(Some(lote))
+}
@@ -1,15 +1,76 @@
+use alumnic::auditoria::{self, Registro, registrar};
+use alumnic::backup::{fazer_backup, gerar_chave, ler_backup};
 use alumnic::cadastro_aluno::DadosParaCadastro;
-use alumnic::configuracao::Configuracao;
+use alumnic::caixa_email::provisionar_pendentes;
+use alumnic::cancelamento::cancelar_no_termino;
+use alumnic::chaves_ssh::{adicionar_chave, listar_chaves, remover_chave};
+use alumnic::configuracao::{Caminhos, Configuracao};
+use alumnic::cotas::{
+    Criterio, ErroDeCotas, ajustar_em_lote, csv as cotas_csv, relatorio,
+};
+use alumnic::desligamento::ResultadoDesligamento;
+use alumnic::docente::{self, DadosDoDocente};
+use alumnic::duplicidade::{
+    Relatorio as RelatorioDuplicidades, csv as duplicidades_csv,
+    ler as ler_duplicidades,
+};
+use alumnic::egresso::tornar_egresso_em;
+use alumnic::espelho_ad::{divergencias, espelhar};
+use alumnic::exportacao::{buscar_novos, csv as exportar_csv};
+use alumnic::hooks::{Evento, TipoEvento, disparar_evento};
+use alumnic::ldap::cadastrar::{home_directory, normalizar_gecos};
+use alumnic::ldap::conexao::{FonteLdap, ServidorLdap};
 use alumnic::ldap::consulta::consultar_cadastro_ldap;
-use clap::{Parser, Subcommand};
-use dialoguer::{Password, theme::ColorfulTheme};
+use alumnic::ldap::contadores::{corrigir_contadores, verificar_contadores};
+use alumnic::ldap::dominios_email::{
+    EtapaMigracao, MigracaoDominio, migrar_email, planejar_migracao,
+};
+use alumnic::ldap::reparo::{buscar_reparos, reparar_conta};
+use alumnic::ldap::schema::verificar_schema;
+use alumnic::ldap::verificacao::verificar_bind;
+use alumnic::lotes::{self, Lote};
+use alumnic::migracao::DadosParaMigracao;
+use alumnic::origens;
+use alumnic::plano::{
+    Acao, Plano, aplicar_desligamento, aplicar_remocao, planejar_desligamento,
+    planejar_remocao,
+};
+use alumnic::prazos::aplicar_prazos_em;
+use alumnic::projeto::{DadosDoProjeto, expirar_projetos};
+use alumnic::reativacao::reativar;
+use alumnic::reconciliacao::reconciliar;
+use alumnic::relatorio::gerar as gerar_relatorio;
+use alumnic::renovacao::{linhas_do_lote, renovar_em_lote};
+use alumnic::restauracao::restaurar;
+use alumnic::turmas::{planejar, sincronizar};
+use alumnic::utils::periodo::Periodo;
+use chrono::{NaiveDate, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use dialoguer::{Confirm, Password, theme::ColorfulTheme};
 use secrecy::SecretString;
 use std::error::Error;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// O arquivo de configuração, no lugar do `~/.config/alumnic/config.yaml`
+    /// ou do `/etc/alumnic/config.yaml`
+    #[arg(long, global = true, env = "ALUMNIC_CONFIG")]
+    config: Option<PathBuf>,
+    /// O diretório dos dados, como a fila de contingência, no lugar do
+    /// `dados` da configuração
+    #[arg(long, global = true, env = "ALUMNIC_DADOS")]
+    dados: Option<PathBuf>,
+    /// O diretório dos registros, como a auditoria, no lugar do `logs` da
+    /// configuração
+    #[arg(long, global = true, env = "ALUMNIC_LOGS")]
+    logs: Option<PathBuf>,
+
     #[command(subcommand)]
     comando: Comandos,
 }
@@ -19,6 +80,9 @@ enum Comandos {
     Serve {
         endereco: String,
     },
+    /// Confere a configuração e o bind com o LDAP, criando e apagando uma
+    /// entrada de teste em cada OU do cadastro para confirmar as permissões
+    VerificarConfig,
     Matricula {
         dre: String,
         data: String,
@@ -36,18 +100,405 @@ enum Comandos {
         nome: String,
         email: String,
         telefone: String,
+        /// Uma chave SSH pública para a conta
+        #[arg(long)]
+        chave_ssh: Option<String>,
+    },
+    /// Cria uma conta temporária de projeto de extensão ou de visitante, com
+    /// o username tirado do nome
+    NovoProjeto {
+        nome: String,
+        email: String,
+        /// O nome do projeto, ou o motivo da visita
+        #[arg(long)]
+        projeto: String,
+        /// O uid do docente responsável pela conta
+        #[arg(long)]
+        responsavel: String,
+        /// O último dia em que a conta funciona
+        #[arg(long)]
+        expiracao: NaiveDate,
+    },
+    /// Pede uma conta de docente ou técnico, que só é criada com a aprovação
+    /// de outro operador
+    NovoDocente {
+        nome: String,
+        email: String,
+        /// A OU da conta, um dos perfis de `docentes`
+        #[arg(long)]
+        ou: String,
+    },
+    /// Mostra os pedidos de contas de docentes esperando aprovação
+    PedidosDocentes,
+    /// Aprova um pedido de conta de docente feito por outro operador e cria a
+    /// conta
+    AprovarDocente {
+        id: String,
+    },
+    /// Recusa um pedido de conta de docente, sem criar a conta
+    RecusarDocente {
+        id: String,
+    },
+    /// Renova as contas dos documentos listados no arquivo, um por linha no
+    /// formato `dre,data,hora,codigo`
+    Renovar {
+        lista: PathBuf,
+    },
+    /// Mostra o progresso dos lotes em andamento, como os do `renovar` e a
+    /// fila de contingência da API
+    Lotes,
+    /// Pausa um lote em andamento depois dos itens que já começaram
+    PausarLote {
+        id: String,
+    },
+    /// Retoma um lote pausado
+    RetomarLote {
+        id: String,
+    },
+    /// Aplica os prazos vencidos, colocando em carência, suspendendo e
+    /// removendo as contas, e bloqueia as contas de projeto expiradas
+    Prazos,
+    /// Bloqueia as contas dos DREs listados na primeira coluna do arquivo,
+    /// como as listas de formados e jubilados, e avisa os alunos. Sem o
+    /// `--aplicar`, só grava o plano com o que será alterado
+    Desligar {
+        /// A lista de DREs, da qual é gerado o plano
+        #[arg(long, required_unless_present = "aplicar")]
+        lista: Option<PathBuf>,
+        /// Aplica o plano gerado antes a partir da lista
+        #[arg(long, conflicts_with = "lista")]
+        aplicar: Option<PathBuf>,
+    },
+    /// Remove de uma vez as contas com os uids listados na primeira coluna
+    /// do arquivo. Sem o `--aplicar`, só grava o plano com o que será
+    /// alterado
+    Remover {
+        /// A lista de uids, da qual é gerado o plano
+        #[arg(long, required_unless_present = "aplicar")]
+        lista: Option<PathBuf>,
+        /// O motivo da remoção, guardado na auditoria
+        #[arg(long, required_unless_present = "aplicar")]
+        motivo: Option<String>,
+        /// Aplica o plano gerado antes a partir da lista
+        #[arg(long, conflicts_with_all = ["lista", "motivo"])]
+        aplicar: Option<PathBuf>,
+    },
+    /// Reativa uma conta suspensa, como a de um aluno que trancou e voltou
+    Reativar {
+        uid: String,
+        /// O motivo da reativação, guardado na auditoria
+        #[arg(long)]
+        motivo: String,
+    },
+    /// Grava o backup de todas as contas em LDIF, assinado com a chave
+    /// privada dos backups
+    Backup {
+        #[arg(long)]
+        para: PathBuf,
+    },
+    /// Gera a chave privada dos backups no arquivo e mostra a chave pública
+    /// para a configuração
+    GerarChaveBackup {
+        arquivo: PathBuf,
+    },
+    /// Recria uma conta apagada por engano a partir de um backup em LDIF,
+    /// com o uidNumber e o sambaSID originais
+    Restaurar {
+        uid: String,
+        /// O LDIF do backup, como o gerado pelo `slapcat`
+        #[arg(long)]
+        de: PathBuf,
+    },
+    /// Verifica o encadeamento da trilha de auditoria, encontrando as linhas
+    /// alteradas, apagadas ou inseridas, e mostra o último hash para guardar
+    VerificarAuditoria {
+        /// O arquivo da trilha, se não for o da configuração
+        arquivo: Option<PathBuf>,
+        /// O último hash guardado, para detectar o truncamento da trilha
+        #[arg(long)]
+        ultimo: Option<String>,
+    },
+    /// Move a conta para a OU de egressos, tirando o acesso aos laboratórios
+    /// mas mantendo a identidade e o email de contato do ex-aluno
+    Egresso {
+        uid: String,
+        /// O motivo da mudança, guardado na auditoria
+        #[arg(long)]
+        motivo: String,
+    },
+    /// Tenta de novo criar as caixas de email pendentes ou que falharam
+    Caixas,
+    /// Corrige as contas criadas por versões antigas com atributos fora do
+    /// padrão, pedindo confirmação para cada uma
+    Reparar {
+        /// Corrige todas as contas sem perguntar
+        #[arg(long)]
+        sim: bool,
+    },
+    /// Compara os contadores de IDs do domínio do Samba com os IDs usados
+    /// pelas contas, apontando contadores atrasados e uidNumbers repetidos
+    Contadores {
+        /// Adianta os contadores atrasados, depois de confirmar
+        #[arg(long)]
+        corrigir: bool,
+    },
+    /// Migra a conta para a pós-graduação mantendo o uid, a partir do
+    /// documento de matrícula do novo vínculo
+    MigrarPos {
+        uid: String,
+        dre: String,
+        data: String,
+        hora: String,
+        codigo: String,
     },
+    /// Lista as chaves SSH públicas da conta
+    ChavesSsh {
+        uid: String,
+    },
+    /// Adiciona uma chave SSH pública à conta
+    AdicionarChaveSsh {
+        uid: String,
+        /// A chave, no formato do `authorized_keys`
+        chave: String,
+    },
+    /// Remove uma chave SSH pública da conta
+    RemoverChaveSsh {
+        uid: String,
+        /// A chave ou a sua impressão digital, como `SHA256:...`
+        chave: String,
+    },
+    /// Espelha no Active Directory as contas criadas, bloqueadas e removidas,
+    /// listando as divergências encontradas
+    EspelharAd {
+        /// Só lista as divergências, sem corrigi-las
+        #[arg(long)]
+        simular: bool,
+    },
+    /// Sincroniza os grupos das turmas com a listagem de inscritos exportada
+    /// do SIGA, removendo os grupos dos períodos encerrados
+    Turmas {
+        #[arg(long)]
+        lista: PathBuf,
+        /// Só lista as alterações, sem aplicá-las
+        #[arg(long)]
+        simular: bool,
+    },
+    /// Conta as contas criadas, renovadas, bloqueadas e removidas em um
+    /// período letivo, como `2025.2`, e a origem dos cadastros dele
+    Relatorio {
+        #[arg(long)]
+        periodo: String,
+        /// Exporta o relatório em CSV para o arquivo
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Resume os DREs achados com mais de uma conta depois do cadastro, por
+    /// dia, por hora, pelo período de ingresso e pela latência do LDAP
+    Duplicidades {
+        /// Exporta os incidentes em CSV para o arquivo
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+    /// Lista as contas criadas a partir de uma data, como `2025-07-01`, para
+    /// a secretaria acadêmica
+    ExportarNovos {
+        #[arg(long)]
+        desde: NaiveDate,
+        #[arg(long, value_enum, default_value_t = Formato::Csv)]
+        formato: Formato,
+    },
+    /// Compara a listagem de alunos ativos exportada do SIGA com as contas,
+    /// listando os alunos sem conta, as contas fora da listagem e os nomes
+    /// divergentes
+    Reconciliar {
+        #[arg(long)]
+        lista: PathBuf,
+        /// Só aponta as contas desta OU, como `alunos`, como fora da listagem
+        #[arg(long)]
+        ou: Option<String>,
+    },
+    /// Lista as contas que usam pelo menos uma porcentagem da cota, com o uso
+    /// vindo do servidor de arquivos
+    RelatorioCotas {
+        /// A porcentagem mínima da cota usada
+        #[arg(long, default_value_t = 100)]
+        acima_de: u64,
+        #[arg(long, value_enum, default_value_t = Formato::Csv)]
+        formato: Formato,
+    },
+    /// Troca a cota das contas de um grupo, de uma OU ou de uma lista de
+    /// uids, como a dos monitores no início do período
+    #[command(group(
+        clap::ArgGroup::new("criterio").required(true).args(["grupo", "ou", "lista"])
+    ))]
+    AjustarCotas {
+        #[arg(long)]
+        cota: u64,
+        /// O `cn` do grupo, como `monitores`
+        #[arg(long)]
+        grupo: Option<String>,
+        /// A OU das contas, como `profcomp`
+        #[arg(long)]
+        ou: Option<String>,
+        /// Um arquivo com um uid por linha
+        #[arg(long)]
+        lista: Option<PathBuf>,
+        /// O motivo do ajuste, guardado na auditoria
+        #[arg(long)]
+        motivo: String,
+        /// Só mostra as contas que seriam alteradas
+        #[arg(long)]
+        simular: bool,
+    },
+    /// Migra o email institucional das contas de um domínio para outro, em
+    /// duas etapas: primeiro o endereço novo é acrescentado como alias, e
+    /// depois ele passa a ser o `mail`, com o antigo como alias
+    MigrarDominioEmail {
+        /// O domínio atual, como `dcc.ufrj.br`
+        #[arg(long)]
+        de: String,
+        /// O domínio novo, como `ic.ufrj.br`
+        #[arg(long)]
+        para: String,
+        #[arg(long, value_enum)]
+        etapa: Etapa,
+        /// Só as contas da OU, como `profcomp`
+        #[arg(long)]
+        ou: Option<String>,
+        /// Só mostra as contas que seriam alteradas
+        #[arg(long)]
+        simular: bool,
+    },
+    /// Faz cadastros sintéticos pela API, com um Gnosys falso, e mede a
+    /// vazão e a taxa de erro. Cria contas no LDAP da configuração, que deve
+    /// ser o de homologação
+    #[cfg(feature = "dev")]
+    SimularCarga {
+        /// Quantos cadastros fazer
+        #[arg(long, default_value_t = 1000)]
+        cadastros: usize,
+        /// Quantos cadastros fazer ao mesmo tempo
+        #[arg(long, default_value_t = 50)]
+        concorrencia: usize,
+        /// O DRE do primeiro aluno sintético
+        #[arg(long, default_value_t = 990000000)]
+        primeiro_dre: u32,
+        /// Apaga as contas criadas no final
+        #[arg(long)]
+        limpar: bool,
+        /// Não pede a confirmação
+        #[arg(long)]
+        sim: bool,
+    },
+}
+
+/// A etapa da migração de domínio do email.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Etapa {
+    /// Acrescenta o endereço no domínio novo como alias
+    Adicionar,
+    /// Troca o `mail` pelo endereço no domínio novo
+    Trocar,
+}
+
+/// O formato de uma exportação.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Formato {
+    Csv,
+    Json,
+}
+
+/// Quem está usando o alumnic na máquina, mesmo pelo `sudo`.
+fn operador() -> String {
+    ["SUDO_USER", "USER"]
+        .iter()
+        .find_map(|v| std::env::var(v).ok().filter(|u| !u.is_empty()))
+        .unwrap_or_else(|| "desconhecido".to_string())
+}
+
+/// Avisa quando a senha do bind e os hashes vão passar sem TLS pela rede.
+fn avisar_texto_claro(ldap: &ServidorLdap) {
+    if ldap.em_texto_claro() {
+        eprintln!(
+            "Aviso: a conexão com {} não usa TLS; configure uma URL \
+             ldaps:// ou o ldap_tls.starttls",
+            ldap.url,
+        );
+    }
+}
+
+/// Grava o `plano` ao lado da `lista`, como `LISTA.plano.json`, e mostra o
+/// que ele altera e como aplicá-lo com o `comando`.
+fn gravar_plano(
+    plano: &Plano,
+    lista: &Path,
+    comando: &str,
+) -> Result<(), Box<dyn Error>> {
+    for item in &plano.itens {
+        let acao = match item.acao {
+            Acao::Bloquear => "será bloqueada",
+            Acao::Remover => "será removida",
+            Acao::JaSuspensa => "já está suspensa",
+            Acao::JaRemovida => "já foi removida",
+            Acao::NaoEncontrado => "não encontrado no LDAP",
+            Acao::Invalido => "não é válido",
+        };
+        match (&item.uid, &item.estado) {
+            (Some(uid), Some(estado)) => {
+                println!("{}: {uid} ({estado}) {acao}", item.alvo)
+            },
+            _ => println!("{}: {acao}", item.alvo),
+        }
+    }
+
+    let caminho = lista.with_extension("plano.json");
+    let hash = plano.gravar(&caminho)?;
+    println!(
+        "{} contas serão alteradas. Revise o plano e aplique-o com\n    \
+         alumnic {comando} --aplicar {}\nO hash do plano é {hash}",
+        plano.alteracoes(),
+        caminho.display(),
+    );
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
 
-    let cfg = Configuracao::importar()?;
+    let cfg = Configuracao::importar_de(&Caminhos {
+        config: cli.config,
+        dados: cli.dados,
+        logs: cli.logs,
+    })?;
+    cfg.concorrencia.limitar_gnosys();
+    alumnic::portal_ufrj::usar_leitura(cfg.leitura_gnosys);
 
     match cli.comando {
         Comandos::Serve { endereco } => {
-            alumnic::api::main(endereco, Arc::new(cfg)).await;
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+            avisar_texto_claro(&ldap);
+            // Um bind sem as permissões do cadastro falha aqui, e não no
+            // primeiro aluno. Com o principal fora do ar, a API sobe com as
+            // consultas na réplica
+            match verificar_bind(&ldap).await {
+                Ok(identidade) => println!("Bind com o LDAP como {identidade}"),
+                Err(e) if e.somente_leitura() => {
+                    eprintln!("A API sobe só com as consultas: {e}");
+                },
+                Err(e) => Err(e)?,
+            }
+            verificar_schema(&cfg.usuario_novo, &ldap).await?;
+            alumnic::api::main(endereco, Arc::new(cfg), ldap).await;
+        },
+        Comandos::VerificarConfig => {
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+            avisar_texto_claro(&ldap);
+            let identidade = verificar_bind(&ldap).await?;
+            println!("Bind com o LDAP como {identidade}");
+            verificar_schema(&cfg.usuario_novo, &ldap).await?;
+            println!("O schema do LDAP tem todas as classes e atributos");
+            println!("A configuração e as permissões do bind estão corretas");
         },
         Comandos::Matricula {
             dre,
@@ -55,21 +506,126 @@ async fn main() -> Result<(), Box<dyn Error>> {
             hora,
             codigo,
         } => {
-            let r = alumnic::portal_ufrj::consulta(&dre, &data, &hora, &codigo)
-                .await?;
+            let r = alumnic::portal_ufrj::consulta_em(
+                &cfg.gnosys_url,
+                &dre,
+                &data,
+                &hora,
+                &codigo,
+            )
+            .await?;
             println!("{r:?}");
         },
         Comandos::Registro { dre, nome } => {
             let r = consultar_cadastro_ldap(
                 &dre,
                 &nome,
-                &cfg.ldap_url,
-                &cfg.ldap_bind_dn,
-                &cfg.ldap_bind_pw,
+                &ServidorLdap::da_configuracao(&cfg),
             )
             .await?;
             println!("{r:?}");
         },
+        Comandos::NovoProjeto {
+            nome,
+            email,
+            projeto,
+            responsavel,
+            expiracao,
+        } => {
+            let senha: SecretString =
+                Password::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Senha")
+                    .with_confirmation("Confirmar senha", "Senhas diferentes")
+                    .interact()
+                    .unwrap()
+                    .into();
+
+            let dados = DadosDoProjeto {
+                nome,
+                email,
+                projeto,
+                responsavel,
+                expiracao,
+                senha,
+            };
+            let (username, dn) = dados
+                .cadastrar(
+                    &cfg,
+                    &ServidorLdap::da_configuracao(&cfg),
+                    Utc::now(),
+                )
+                .await?;
+            println!("Conta {username} criada em {dn}");
+        },
+        Comandos::NovoDocente { nome, email, ou } => {
+            let senha: SecretString =
+                Password::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Senha")
+                    .with_confirmation("Confirmar senha", "Senhas diferentes")
+                    .interact()
+                    .unwrap()
+                    .into();
+
+            let dados = DadosDoDocente {
+                nome,
+                email,
+                ou,
+                senha,
+            };
+            let pedido = dados.pedir(&operador(), &cfg, Utc::now()).await?;
+            println!(
+                "Pedido {} registrado; a conta é criada quando outro operador \
+                 rodar `alumnic aprovar-docente {}`",
+                pedido.id, pedido.id,
+            );
+        },
+        Comandos::PedidosDocentes => {
+            for pedido in docente::pedidos(&cfg.dados)? {
+                println!(
+                    "{}: {} <{}> em {}, pedido por {} em {}",
+                    pedido.id,
+                    pedido.nome,
+                    pedido.email,
+                    pedido.ou,
+                    pedido.solicitante,
+                    pedido.pedido_em.format("%d/%m/%Y %H:%M"),
+                );
+            }
+        },
+        Comandos::AprovarDocente { id } => {
+            let Some(pedido) = docente::pedidos(&cfg.dados)?
+                .into_iter()
+                .find(|p| p.id == id)
+            else {
+                return Err(format!("Não existe o pedido {id:?}").into());
+            };
+            println!(
+                "{} <{}> em {}, pedido por {}",
+                pedido.nome, pedido.email, pedido.ou, pedido.solicitante,
+            );
+            if !Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Criar a conta?")
+                .default(false)
+                .interact()?
+            {
+                return Ok(());
+            }
+
+            let (username, dn) = docente::aprovar(
+                &id,
+                &operador(),
+                &cfg,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            println!("Conta {username} criada em {dn}");
+        },
+        Comandos::RecusarDocente { id } => {
+            let pedido =
+                docente::recusar(&id, &operador(), &cfg, Utc::now()).await?;
+            println!("O pedido da conta de {} foi recusado", pedido.nome);
+        },
         Comandos::NovoAluno {
             username,
             ou,
@@ -77,6 +633,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             nome,
             email,
             telefone,
+            chave_ssh,
         } => {
             let senha: SecretString =
                 Password::with_theme(&ColorfulTheme::default())
@@ -95,18 +652,889 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 email,
                 telefone,
                 senha,
+                chave_ssh,
+                username: None,
+                foto: None,
             };
 
+            let dre = dados.dre.clone();
+            let nome = normalizar_gecos(&dados.nome);
+            let responsavel =
+                format!("{} com o bind {}", operador(), cfg.ldap_bind_dn);
             dados
                 .cadastrar_sem_verificar_documento(
-                    username,
+                    username.clone(),
+                    &responsavel,
                     &cfg.usuario_novo,
+                    &cfg.renovacao,
                     &ou,
-                    &cfg.ldap_url,
-                    &cfg.ldap_bind_dn,
-                    &cfg.ldap_bind_pw,
+                    &ServidorLdap::da_configuracao(&cfg),
                 )
                 .await?;
+            registrar(
+                &cfg.auditoria,
+                &Registro {
+                    quando: Utc::now(),
+                    operacao: "cadastro_manual",
+                    uid: &username,
+                    motivo: &format!("por {responsavel}"),
+                },
+            )
+            .await?;
+
+            let home = home_directory(&username);
+            let email = cfg.usuario_novo.dominios_email.email(&username, &ou);
+            let evento = Evento {
+                evento: TipoEvento::Cadastro,
+                uid: &username,
+                dre: &dre,
+                home: &home,
+                nome: &nome,
+                email: &email,
+            };
+            disparar_evento(&cfg.hooks, &cfg.auditoria, &evento).await?;
+        },
+        Comandos::Renovar { lista } => {
+            let lista = std::fs::read_to_string(lista)?;
+            let cancelamento = CancellationToken::new();
+            tokio::spawn(cancelar_no_termino(cancelamento.clone()));
+            let lote = Lote::acompanhar(
+                &cfg.dados,
+                "renovacao",
+                linhas_do_lote(&lista).count(),
+                cancelamento,
+            );
+            eprintln!("Lote {}; acompanhe com `alumnic lotes`", lote.id());
+            let resultados = renovar_em_lote(
+                &lista,
+                &cfg.renovacao,
+                &cfg.gnosys_url,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+                cfg.concorrencia.lote,
+                &lote,
+            )
+            .await;
+
+            for (linha, r) in resultados {
+                match r {
+                    Ok(r) => {
+                        println!(
+                            "{linha}: {} renovada até {}",
+                            r.username, r.proxima_renovacao,
+                        );
+                        let evento =
+                            Evento::da_conta(TipoEvento::Renovacao, &r.conta);
+                        if let Err(e) =
+                            disparar_evento(&cfg.hooks, &cfg.auditoria, &evento)
+                                .await
+                        {
+                            println!("{linha}: {e}");
+                        }
+                    },
+                    Err(e) => println!("{linha}: {e}"),
+                }
+            }
+        },
+        Comandos::Lotes => {
+            for lote in lotes::lotes(&cfg.dados)? {
+                let restante = match lote.segundos_restantes {
+                    Some(s) => format!("faltam {}min{:02}s", s / 60, s % 60),
+                    None => "sem previsão".to_string(),
+                };
+                println!(
+                    "{}: {}/{} processados, {} falhas, {restante}{}",
+                    lote.id,
+                    lote.processados,
+                    lote.total,
+                    lote.falhas,
+                    if lote.pausado { " (pausado)" } else { "" },
+                );
+            }
+        },
+        Comandos::PausarLote { id } => {
+            match lotes::pausar(&cfg.dados, &id, true)? {
+                Some(_) => println!(
+                    "{id} pausado; os itens que já começaram vão terminar"
+                ),
+                None => println!("Não há lote {id:?} em andamento"),
+            }
+        },
+        Comandos::RetomarLote { id } => {
+            match lotes::pausar(&cfg.dados, &id, false)? {
+                Some(_) => println!("{id} retomado"),
+                None => println!("Não há lote {id:?} em andamento"),
+            }
+        },
+        Comandos::Prazos => {
+            let t = aplicar_prazos_em(
+                &ServidorLdap::da_configuracao(&cfg),
+                &cfg,
+                Utc::now(),
+            )
+            .await?;
+
+            for conta in t.em_carencia {
+                println!("{} ({}) entrou em carência", conta.uid, conta.dre);
+            }
+            for conta in t.suspensas {
+                println!("{} ({}) foi suspensa", conta.uid, conta.dre);
+            }
+            for conta in t.removidas {
+                println!("{} ({}) foi removida", conta.uid, conta.dre);
+            }
+            for (conta, e) in t.falhas {
+                println!("{} ({}): {e}", conta.uid, conta.dre);
+            }
+
+            let t = expirar_projetos(
+                &ServidorLdap::da_configuracao(&cfg),
+                &cfg,
+                Utc::now(),
+            )
+            .await?;
+            for conta in t.bloqueadas {
+                println!("{} ({}) expirou", conta.uid, conta.projeto);
+            }
+            for conta in t.avisadas {
+                println!(
+                    "{} foi avisado da expiração de {}",
+                    conta.responsavel, conta.uid,
+                );
+            }
+            for (conta, e) in t.falhas {
+                println!("Aviso da expiração de {}: {e}", conta.uid);
+            }
+        },
+        Comandos::Desligar {
+            lista: Some(lista), ..
+        } => {
+            let texto = std::fs::read_to_string(&lista)?;
+            let plano = planejar_desligamento(
+                &texto,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            gravar_plano(&plano, &lista, "desligar")?;
+        },
+        Comandos::Desligar { aplicar, .. } => {
+            let (plano, hash) = Plano::ler(&aplicar.unwrap_or_default())?;
+            let cancelamento = CancellationToken::new();
+            tokio::spawn(cancelar_no_termino(cancelamento.clone()));
+            let aplicado = aplicar_desligamento(
+                &plano,
+                &hash,
+                &cfg,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+                &cancelamento,
+            )
+            .await?;
+
+            let mut desligados = 0;
+            for (dre, r) in aplicado.resultados {
+                match r {
+                    Ok(ResultadoDesligamento::Desligado {
+                        uid,
+                        data_remocao,
+                        aviso,
+                        hooks,
+                    }) => {
+                        desligados += 1;
+                        println!(
+                            "{dre}: {uid} bloqueada, remoção em {data_remocao}"
+                        );
+                        if let Err(e) = aviso {
+                            println!("{dre}: o aviso não foi enviado: {e}");
+                        }
+                        if let Err(e) = hooks {
+                            println!("{dre}: {e}");
+                        }
+                    },
+                    Ok(ResultadoDesligamento::JaSuspensa { uid }) => {
+                        println!("{dre}: {uid} já estava suspensa");
+                    },
+                    Ok(ResultadoDesligamento::NaoEncontrado) => {
+                        println!("{dre}: não encontrado no LDAP");
+                    },
+                    Err(e) => println!("{dre}: {e}"),
+                }
+            }
+
+            println!("{desligados} contas bloqueadas, plano {hash}");
+            if !aplicado.sem_auditoria.is_empty() {
+                for (uid, e) in &aplicado.sem_auditoria {
+                    eprintln!("{uid}: o bloqueio não foi registrado: {e}");
+                }
+                Err(format!(
+                    "{} contas bloqueadas não foram registradas na auditoria",
+                    aplicado.sem_auditoria.len()
+                ))?
+            }
+        },
+        Comandos::Remover {
+            lista: Some(lista),
+            motivo,
+            ..
+        } => {
+            let texto = std::fs::read_to_string(&lista)?;
+            let plano = planejar_remocao(
+                &texto,
+                &motivo.unwrap_or_default(),
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            gravar_plano(&plano, &lista, "remover")?;
+        },
+        Comandos::Remover { aplicar, .. } => {
+            let (plano, hash) = Plano::ler(&aplicar.unwrap_or_default())?;
+            let cancelamento = CancellationToken::new();
+            tokio::spawn(cancelar_no_termino(cancelamento.clone()));
+            let resultados = aplicar_remocao(
+                &plano,
+                &hash,
+                &cfg,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+                &cancelamento,
+            )
+            .await?;
+
+            let mut removidas = 0;
+            for (uid, r) in &resultados {
+                match r {
+                    Ok(_) => {
+                        removidas += 1;
+                        println!("{uid}: removida");
+                    },
+                    Err(e) => println!("{uid}: {e}"),
+                }
+            }
+            if resultados.len() < plano.alteracoes() {
+                println!(
+                    "Interrompido: {} contas não foram removidas",
+                    plano.alteracoes() - resultados.len()
+                );
+            }
+            println!("{removidas} contas removidas, plano {hash}");
+        },
+        Comandos::Reativar { uid, motivo } => {
+            let renovacao = reativar(
+                &uid,
+                &motivo,
+                &cfg,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            println!("{uid} reativada, renovação até {renovacao}");
+        },
+        Comandos::Backup { para } => {
+            let contas =
+                fazer_backup(&para, &cfg, &ServidorLdap::da_configuracao(&cfg))
+                    .await?;
+            println!("{contas} contas gravadas em {}", para.display());
+        },
+        Comandos::GerarChaveBackup { arquivo } => {
+            let (privada, publica) = gerar_chave()?;
+            // Só o dono pode ler a chave privada
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&arquivo)?
+                .write_all(&privada)?;
+            println!("Chave pública dos backups: {publica}");
+        },
+        Comandos::Restaurar { uid, de } => {
+            // A assinatura é verificada antes de qualquer leitura do LDIF
+            let ldif = ler_backup(&de, &cfg)?;
+            let dn = restaurar(
+                &uid,
+                &ldif,
+                &de.display().to_string(),
+                &cfg,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            println!("{uid} restaurada em {dn}");
+        },
+        Comandos::Egresso { uid, motivo } => {
+            let dn = tornar_egresso_em(
+                &uid,
+                &motivo,
+                &cfg,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            println!("{uid} movida para {dn}");
+        },
+        Comandos::MigrarPos {
+            uid,
+            dre,
+            data,
+            hora,
+            codigo,
+        } => {
+            let dados = DadosParaMigracao {
+                uid,
+                dre,
+                data,
+                hora,
+                codigo,
+            };
+            let dn = dados
+                .migrar(&cfg, &ServidorLdap::da_configuracao(&cfg), Utc::now())
+                .await?;
+            println!("Conta migrada para {dn}");
+        },
+        Comandos::VerificarAuditoria { arquivo, ultimo } => {
+            let Some(arquivo) = arquivo.or(cfg.auditoria.arquivo.clone())
+            else {
+                return Err("a auditoria não está configurada".into());
+            };
+            let texto = std::fs::read_to_string(&arquivo)?;
+            let v = auditoria::verificar(&texto, ultimo.as_deref());
+
+            println!("{} registros encadeados", v.encadeadas);
+            if v.antigas > 0 {
+                println!("{} registros anteriores ao encadeamento", v.antigas);
+            }
+            if let Some(depois) = v.depois_do_ultimo {
+                println!("{depois} registros depois do último hash guardado");
+            }
+            if let Some(hash) = &v.ultimo_hash {
+                println!("Último hash: {hash}");
+            }
+            if ultimo.is_none() {
+                eprintln!(
+                    "Aviso: sem o --ultimo, a remoção das últimas linhas e a \
+                     troca da trilha por linhas sem encadeamento não são \
+                     detectadas"
+                );
+            }
+            if !v.problemas.is_empty() {
+                for problema in &v.problemas {
+                    eprintln!("{problema}");
+                }
+                return Err(format!(
+                    "a trilha de auditoria foi adulterada ({} problemas)",
+                    v.problemas.len()
+                )
+                .into());
+            }
+        },
+        Comandos::Caixas => {
+            let Some(caixa) = &cfg.usuario_novo.caixa_email else {
+                return Err("a criação das caixas de email não está \
+                            configurada"
+                    .into());
+            };
+
+            let resultados = provisionar_pendentes(
+                caixa,
+                &ServidorLdap::da_configuracao(&cfg),
+            )
+            .await?;
+            for (pendente, r) in resultados {
+                match r {
+                    Ok(()) => println!("{}: criada", pendente.endereco),
+                    Err(e) => println!("{}: {e}", pendente.endereco),
+                }
+            }
+        },
+        Comandos::Contadores { corrigir } => {
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let mut conexao = ldap.abrir().await?;
+            let v = verificar_contadores(
+                &cfg.usuario_novo.samba_sid_prefix,
+                &mut conexao,
+            )
+            .await;
+            ldap.fechar(conexao).await?;
+            let v = v?;
+
+            let maior =
+                |n: Option<i64>| n.map_or("-".to_string(), |n| n.to_string());
+            println!(
+                "uidNumber: contador {}, maior usado {}",
+                v.contadores.uid_number,
+                maior(v.maior_uid_number),
+            );
+            println!(
+                "sambaNextRid: contador {}, maior usado {}",
+                v.contadores.next_rid,
+                maior(v.maior_rid),
+            );
+            for (uid_number, uids) in &v.duplicados {
+                println!(
+                    "uidNumber {uid_number} repetido: {}",
+                    uids.join(", ")
+                );
+            }
+
+            let Some(corrigidos) = v.correcao() else {
+                println!("Os contadores estão em dia");
+                return Ok(());
+            };
+            println!(
+                "Correção: uidNumber {} -> {}, sambaNextRid {} -> {}",
+                v.contadores.uid_number,
+                corrigidos.uid_number,
+                v.contadores.next_rid,
+                corrigidos.next_rid,
+            );
+
+            if corrigir
+                && Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Corrigir?")
+                    .default(false)
+                    .interact()?
+            {
+                let mut conexao = ldap.abrir().await?;
+                let r = corrigir_contadores(
+                    &v.contadores,
+                    &corrigidos,
+                    &mut conexao,
+                )
+                .await;
+                ldap.fechar(conexao).await?;
+                r?;
+                println!("Contadores corrigidos");
+            }
+        },
+        Comandos::Reparar { sim } => {
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let mut conexao = ldap.abrir().await?;
+            let contas = buscar_reparos(
+                &cfg.renovacao,
+                &cfg.usuario_novo.dominios_email,
+                &mut conexao,
+            )
+            .await;
+            ldap.fechar(conexao).await?;
+
+            let mut confirmadas = vec![];
+            for conta in contas? {
+                println!("{} ({})", conta.uid, conta.dn);
+                for c in &conta.correcoes {
+                    println!(
+                        "  {}: {:?} -> {:?}",
+                        c.atributo,
+                        c.atual.as_deref().unwrap_or_default(),
+                        c.canonico,
+                    );
+                }
+
+                if sim
+                    || Confirm::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Reparar?")
+                        .default(false)
+                        .interact()?
+                {
+                    confirmadas.push(conta);
+                }
+            }
+
+            let mut conexao = ldap.abrir().await?;
+            let r: Result<(), Box<dyn Error>> = async {
+                for conta in &confirmadas {
+                    reparar_conta(conta, &mut conexao).await?;
+
+                    let atributos: Vec<_> =
+                        conta.correcoes.iter().map(|c| c.atributo).collect();
+                    registrar(
+                        &cfg.auditoria,
+                        &Registro {
+                            quando: Utc::now(),
+                            operacao: "reparar",
+                            uid: &conta.uid,
+                            motivo: &atributos.join(", "),
+                        },
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            .await;
+            ldap.fechar(conexao).await?;
+            r?;
+
+            println!("{} contas reparadas", confirmadas.len());
+        },
+        Comandos::ChavesSsh { uid } => {
+            let chaves =
+                listar_chaves(&uid, &ServidorLdap::da_configuracao(&cfg))
+                    .await?;
+            for chave in chaves {
+                println!("{chave}");
+            }
+        },
+        Comandos::AdicionarChaveSsh { uid, chave } => {
+            let impressao = adicionar_chave(
+                &uid,
+                &chave,
+                &cfg.auditoria,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            println!("Chave {impressao} adicionada");
+        },
+        Comandos::RemoverChaveSsh { uid, chave } => {
+            let removida = remover_chave(
+                &uid,
+                &chave,
+                &cfg.auditoria,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+            println!("Chave removida: {removida}");
+        },
+        Comandos::EspelharAd { simular } => {
+            let Some(cfg_ad) = &cfg.ad else {
+                return Err("o Active Directory não está configurado".into());
+            };
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+            let ad = cfg_ad.servidor()?;
+
+            let divergencias = divergencias(cfg_ad, &ldap, &ad).await?;
+            if simular {
+                for d in &divergencias {
+                    println!("{d}");
+                }
+            } else {
+                let resultados = espelhar(cfg_ad, &divergencias, &ad).await?;
+                for (d, r) in divergencias.iter().zip(resultados) {
+                    match r {
+                        Ok(()) => println!("{d}: corrigida"),
+                        Err(e) => println!("{d}: {e}"),
+                    }
+                }
+            }
+            println!("{} divergências", divergencias.len());
+        },
+        Comandos::Turmas { lista, simular } => {
+            let lista = std::fs::read_to_string(lista)?;
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let plano = planejar(&lista, &cfg.turmas, &ldap).await?;
+            for dre in &plano.sem_conta {
+                println!("{dre}: sem conta, fora dos grupos");
+            }
+            if simular {
+                for m in &plano.mudancas {
+                    println!("{m}");
+                }
+            } else {
+                let resultados =
+                    sincronizar(&plano.mudancas, &cfg.turmas, &ldap).await?;
+                for (m, r) in plano.mudancas.iter().zip(resultados) {
+                    match r {
+                        Ok(()) => println!("{m}"),
+                        Err(e) => println!("{m}: {e}"),
+                    }
+                }
+            }
+            println!("{} alterações nos grupos", plano.mudancas.len());
+        },
+        Comandos::Relatorio { periodo, csv } => {
+            let periodo = Periodo::interpretar(&periodo)
+                .ok_or(format!("período inválido: {periodo:?}"))?;
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let mut conexao = ldap.abrir().await?;
+            let relatorio = gerar_relatorio(
+                periodo,
+                &cfg.renovacao,
+                &cfg.desligamento,
+                &mut conexao,
+            )
+            .await;
+            ldap.fechar(conexao).await?;
+            let relatorio = relatorio?;
+
+            println!("Período {periodo}");
+            for (ou, c) in relatorio.linhas() {
+                println!(
+                    "{ou}: {} criadas, {} renovadas, {} bloqueadas, {} \
+                     removidas",
+                    c.criadas, c.renovadas, c.bloqueadas, c.removidas,
+                );
+            }
+            let origens = origens::do_periodo(&cfg.dados, periodo)?;
+            if !origens.is_empty() {
+                println!("Origem dos cadastros:");
+                for (origem, cadastros) in origens {
+                    println!("{origem}: {cadastros}");
+                }
+            }
+
+            if let Some(csv) = csv {
+                std::fs::write(&csv, relatorio.csv())?;
+                println!("Relatório exportado para {}", csv.display());
+            }
+        },
+        Comandos::Duplicidades { csv } => {
+            let Some(duplicidade) = &cfg.usuario_novo.duplicidade else {
+                return Err("a verificação de duplicidade não está \
+                            configurada em usuario_novo.duplicidade"
+                    .into());
+            };
+            let incidentes = ler_duplicidades(&duplicidade.arquivo)?;
+            let r = RelatorioDuplicidades::de(&incidentes);
+
+            println!("{} incidentes, {} corrigidos", r.total, r.corrigidos);
+            for (dia, n) in &r.por_dia {
+                println!("{dia}: {n}");
+            }
+            for (hora, n) in &r.por_hora {
+                println!("{hora:02}h UTC: {n}");
+            }
+            for (ingresso, n) in &r.por_ingresso {
+                println!("Ingresso em {ingresso}: {n}");
+            }
+            for (dre, n) in &r.dres_repetidos {
+                println!("DRE {dre} duplicado {n} vezes");
+            }
+            println!(
+                "Latência da criação: média de {}ms, máxima de {}ms",
+                r.latencia_criacao_media_ms, r.latencia_criacao_maxima_ms,
+            );
+
+            if let Some(csv) = csv {
+                std::fs::write(&csv, duplicidades_csv(&incidentes))?;
+                println!("Incidentes exportados para {}", csv.display());
+            }
+        },
+        Comandos::ExportarNovos { desde, formato } => {
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let mut conexao = ldap.abrir().await?;
+            let novos = buscar_novos(desde, &mut conexao).await;
+            ldap.fechar(conexao).await?;
+            let novos = novos?;
+
+            match formato {
+                Formato::Csv => print!("{}", exportar_csv(&novos)),
+                Formato::Json => {
+                    println!("{}", serde_json::to_string_pretty(&novos)?)
+                },
+            }
+        },
+        Comandos::Reconciliar { lista, ou } => {
+            let lista = std::fs::read_to_string(lista)?;
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let r = reconciliar(&lista, ou.as_deref(), &ldap).await?;
+            for aluno in &r.sem_conta {
+                println!("{} ({}): sem conta", aluno.dre, aluno.nome);
+            }
+            for conta in &r.fora_da_listagem {
+                println!(
+                    "{} ({}): fora da listagem, conta {}",
+                    conta.uid,
+                    conta.dre,
+                    conta.estado.valor(),
+                );
+            }
+            for d in &r.divergencias {
+                println!(
+                    "{} ({}): nome {:?} na listagem e {:?} no LDAP",
+                    d.uid, d.dre, d.nome_listagem, d.nome_ldap,
+                );
+            }
+            println!(
+                "{} sem conta, {} fora da listagem, {} nomes divergentes",
+                r.sem_conta.len(),
+                r.fora_da_listagem.len(),
+                r.divergencias.len(),
+            );
+        },
+        Comandos::RelatorioCotas { acima_de, formato } => {
+            let cotas =
+                cfg.cotas.as_ref().ok_or(ErroDeCotas::SemConfiguracao)?;
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let relatorio = relatorio(cotas, acima_de, &ldap).await?;
+            match formato {
+                Formato::Csv => print!("{}", cotas_csv(&relatorio)),
+                Formato::Json => {
+                    println!("{}", serde_json::to_string_pretty(&relatorio)?)
+                },
+            }
+        },
+        Comandos::AjustarCotas {
+            cota,
+            grupo,
+            ou,
+            lista,
+            motivo,
+            simular,
+        } => {
+            let criterio = match (grupo, ou, lista) {
+                (Some(grupo), _, _) => Criterio::Grupo(grupo),
+                (_, Some(ou), _) => Criterio::Ou(ou),
+                (_, _, Some(lista)) => Criterio::Uids(
+                    std::fs::read_to_string(lista)?
+                        .lines()
+                        .map(str::trim)
+                        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                        .map(str::to_string)
+                        .collect(),
+                ),
+                (None, None, None) => unreachable!(),
+            };
+
+            let resultados = ajustar_em_lote(
+                &criterio,
+                cota,
+                &motivo,
+                simular,
+                &cfg.auditoria,
+                &ServidorLdap::da_configuracao(&cfg),
+                Utc::now(),
+            )
+            .await?;
+
+            let mut falhas = 0;
+            for (conta, r) in &resultados {
+                let anterior =
+                    conta.cota.map_or("nenhuma".to_string(), |c| c.to_string());
+                match r {
+                    Ok(()) if simular => println!(
+                        "{}: a cota mudaria de {anterior} para {cota}",
+                        conta.uid
+                    ),
+                    Ok(()) => println!(
+                        "{}: cota mudada de {anterior} para {cota}",
+                        conta.uid
+                    ),
+                    Err(e) => {
+                        falhas += 1;
+                        eprintln!("{}: {e}", conta.uid);
+                    },
+                }
+            }
+            println!(
+                "{} contas ajustadas, {falhas} falhas",
+                resultados.len() - falhas
+            );
+        },
+        Comandos::MigrarDominioEmail {
+            de,
+            para,
+            etapa,
+            ou,
+            simular,
+        } => {
+            let migracao = MigracaoDominio {
+                de,
+                para,
+                ou,
+                etapa: match etapa {
+                    Etapa::Adicionar => EtapaMigracao::Adicionar,
+                    Etapa::Trocar => EtapaMigracao::Trocar,
+                },
+            };
+            let alias =
+                cfg.usuario_novo.alias_email.clone().unwrap_or_default();
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+
+            let mut conexao = ldap.abrir().await?;
+            let r: Result<_, Box<dyn Error>> = async {
+                let planos =
+                    planejar_migracao(&migracao, &alias, &mut conexao).await?;
+                let mut falhas = 0;
+                for plano in &planos {
+                    if simular {
+                        match &plano.conflito {
+                            Some(dono) => println!(
+                                "{}: {} já é de {dono}",
+                                plano.uid, plano.novo
+                            ),
+                            None => println!(
+                                "{}: {} -> {}",
+                                plano.uid, plano.antigo, plano.novo
+                            ),
+                        }
+                        continue;
+                    }
+                    if let Err(e) = migrar_email(plano, &mut conexao).await {
+                        falhas += 1;
+                        eprintln!("{}: {e}", plano.uid);
+                        continue;
+                    }
+                    println!(
+                        "{}: {} -> {}",
+                        plano.uid, plano.antigo, plano.novo
+                    );
+                    registrar(
+                        &cfg.auditoria,
+                        &Registro {
+                            quando: Utc::now(),
+                            operacao: "migrar_dominio_email",
+                            uid: &plano.uid,
+                            motivo: &format!(
+                                "{etapa:?} de {} para {}",
+                                plano.antigo, plano.novo
+                            ),
+                        },
+                    )
+                    .await?;
+                }
+                Ok((planos.len(), falhas))
+            }
+            .await;
+            ldap.fechar(conexao).await?;
+            let (total, falhas) = r?;
+            if !simular {
+                println!("{} contas migradas, {falhas} falhas", total - falhas);
+            }
+        },
+        #[cfg(feature = "dev")]
+        Comandos::SimularCarga {
+            cadastros,
+            concorrencia,
+            primeiro_dre,
+            limpar,
+            sim,
+        } => {
+            use alumnic::simulacao::{ConfiguracaoSimulacao, simular};
+
+            println!(
+                "{cadastros} contas serão criadas em {}, a partir do DRE \
+                 {primeiro_dre:09}",
+                cfg.ldap_url
+            );
+            if !sim
+                && !Confirm::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Esse é o LDAP de homologação?")
+                    .default(false)
+                    .interact()?
+            {
+                return Ok(());
+            }
+
+            let simulacao = ConfiguracaoSimulacao {
+                cadastros,
+                concorrencia,
+                primeiro_dre,
+                limpar,
+            };
+            let ldap = ServidorLdap::da_configuracao(&cfg);
+            let r = simular(cfg, ldap, &simulacao).await?;
+            print!("{r}");
+            if limpar {
+                println!("{} contas sintéticas apagadas", r.apagadas);
+            }
         },
     }
 
@@ -0,0 +1,201 @@
+//! Modo somente leitura, usado durante a manutenção do servidor LDAP (uma
+//! migração, por exemplo). Com ele ativo, as consultas continuam funcionando,
+//! mas os cadastros e renovações da API respondem `503` e as tarefas
+//! periódicas não alteram nada.
+//!
+//! Numa manutenção maior, a API pública pode ser interrompida: todas as rotas
+//! dos alunos respondem `503` com uma mensagem e a previsão de retorno, e só
+//! ficam no ar o healthcheck, as rotas administrativas e o painel.
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// O estado inicial do modo somente leitura e da interrupção, que podem ser
+/// mudados depois pelas rotas administrativas `/api/admin/somente-leitura` e
+/// `/api/admin/manutencao`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoManutencao {
+    pub somente_leitura: bool,
+    /// A mensagem mostrada a quem tenta uma operação de escrita.
+    pub mensagem: String,
+    /// Se a API pública começa interrompida.
+    pub interrompida: bool,
+    /// A mensagem mostrada a quem acessa a API pública interrompida.
+    pub mensagem_interrupcao: String,
+    /// A previsão de retorno da API pública, como
+    /// `2026-10-20T18:00:00-03:00`.
+    pub retorno: Option<DateTime<FixedOffset>>,
+}
+
+impl Default for ConfiguracaoManutencao {
+    fn default() -> Self {
+        Self {
+            somente_leitura: false,
+            mensagem: "O sistema está em manutenção. Tente novamente mais \
+                       tarde."
+                .to_string(),
+            interrompida: false,
+            mensagem_interrupcao: "O sistema está fora do ar para \
+                                   manutenção."
+                .to_string(),
+            retorno: None,
+        }
+    }
+}
+
+/// O modo atual, como visto e alterado pela rota administrativa.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModoManutencao {
+    pub somente_leitura: bool,
+    /// Se for omitida ao mudar o modo, a mensagem atual é mantida.
+    #[serde(default)]
+    pub mensagem: Option<String>,
+}
+
+/// A interrupção da API pública, como vista e alterada pela rota
+/// administrativa.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModoInterrupcao {
+    pub interrompida: bool,
+    /// Se for omitida ao mudar o modo, a mensagem atual é mantida.
+    #[serde(default)]
+    pub mensagem: Option<String>,
+    /// A previsão de retorno. Ao mudar o modo, ela é sempre trocada, então
+    /// omitir apaga a previsão.
+    #[serde(default)]
+    pub retorno: Option<DateTime<FixedOffset>>,
+}
+
+impl ModoInterrupcao {
+    /// A mensagem da resposta `503`, com a previsão de retorno se houver.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::manutencao::ModoInterrupcao;
+    /// let mut modo = ModoInterrupcao {
+    ///     interrompida: true,
+    ///     mensagem: Some("Migrando o LDAP.".to_string()),
+    ///     retorno: None,
+    /// };
+    /// assert_eq!(modo.resposta(), "Migrando o LDAP.");
+    ///
+    /// modo.retorno = Some("2026-10-20T18:00:00-03:00".parse().unwrap());
+    /// assert_eq!(
+    ///     modo.resposta(),
+    ///     "Migrando o LDAP. Previsão de retorno: 20/10/2026 às 18:00.",
+    /// );
+    /// ```
+    pub fn resposta(&self) -> String {
+        let mensagem = self.mensagem.clone().unwrap_or_default();
+        match self.retorno {
+            Some(retorno) => format!(
+                "{mensagem} Previsão de retorno: {}.",
+                retorno.format("%d/%m/%Y às %H:%M"),
+            ),
+            None => mensagem,
+        }
+    }
+}
+
+/// O modo somente leitura compartilhado entre a API e as tarefas periódicas,
+/// junto com a interrupção da API pública.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::manutencao::{ConfiguracaoManutencao, Manutencao, ModoManutencao};
+/// let manutencao = Manutencao::nova(&ConfiguracaoManutencao::default());
+/// assert_eq!(manutencao.somente_leitura(), None);
+///
+/// manutencao.mudar(ModoManutencao {
+///     somente_leitura: true,
+///     mensagem: Some("Migrando o LDAP".to_string()),
+/// });
+/// assert_eq!(manutencao.somente_leitura().as_deref(), Some("Migrando o LDAP"));
+/// ```
+#[derive(Debug)]
+pub struct Manutencao {
+    somente_leitura: Mutex<ModoManutencao>,
+    interrupcao: Mutex<ModoInterrupcao>,
+}
+
+impl Manutencao {
+    pub fn nova(cfg: &ConfiguracaoManutencao) -> Self {
+        Self {
+            somente_leitura: Mutex::new(ModoManutencao {
+                somente_leitura: cfg.somente_leitura,
+                mensagem: Some(cfg.mensagem.clone()),
+            }),
+            interrupcao: Mutex::new(ModoInterrupcao {
+                interrompida: cfg.interrompida,
+                mensagem: Some(cfg.mensagem_interrupcao.clone()),
+                retorno: cfg.retorno,
+            }),
+        }
+    }
+
+    /// A mensagem do modo somente leitura, se ele estiver ativo.
+    pub fn somente_leitura(&self) -> Option<String> {
+        let modo = self.somente_leitura.lock().unwrap();
+        modo.somente_leitura
+            .then(|| modo.mensagem.clone().unwrap_or_default())
+    }
+
+    /// Muda o modo, retornando o novo.
+    pub fn mudar(&self, novo: ModoManutencao) -> ModoManutencao {
+        let mut modo = self.somente_leitura.lock().unwrap();
+        modo.somente_leitura = novo.somente_leitura;
+        if novo.mensagem.is_some() {
+            modo.mensagem = novo.mensagem;
+        }
+        modo.clone()
+    }
+
+    pub fn modo(&self) -> ModoManutencao {
+        self.somente_leitura.lock().unwrap().clone()
+    }
+
+    /// A interrupção da API pública, se ela estiver ativa.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::manutencao::{ConfiguracaoManutencao, Manutencao};
+    /// let manutencao = Manutencao::nova(&ConfiguracaoManutencao::default());
+    /// assert_eq!(manutencao.interrompida(), None);
+    ///
+    /// manutencao.alternar_interrupcao();
+    /// assert!(manutencao.interrompida().is_some());
+    /// manutencao.alternar_interrupcao();
+    /// assert_eq!(manutencao.interrompida(), None);
+    /// ```
+    pub fn interrompida(&self) -> Option<ModoInterrupcao> {
+        let modo = self.interrupcao.lock().unwrap();
+        modo.interrompida.then(|| modo.clone())
+    }
+
+    /// Muda a interrupção, retornando a nova.
+    pub fn mudar_interrupcao(&self, novo: ModoInterrupcao) -> ModoInterrupcao {
+        let mut modo = self.interrupcao.lock().unwrap();
+        modo.interrompida = novo.interrompida;
+        modo.retorno = novo.retorno;
+        if novo.mensagem.is_some() {
+            modo.mensagem = novo.mensagem;
+        }
+        modo.clone()
+    }
+
+    /// Liga ou desliga a interrupção, mantendo a mensagem e a previsão, como
+    /// no sinal `SIGUSR1`. Retorna se ela ficou ativa.
+    pub fn alternar_interrupcao(&self) -> bool {
+        let mut modo = self.interrupcao.lock().unwrap();
+        modo.interrompida = !modo.interrompida;
+        modo.interrompida
+    }
+
+    pub fn interrupcao(&self) -> ModoInterrupcao {
+        self.interrupcao.lock().unwrap().clone()
+    }
+}
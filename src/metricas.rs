@@ -0,0 +1,389 @@
+//! Métricas de funcionamento do serviço, exportadas no formato texto do
+//! Prometheus. Diferente das [estatísticas](crate::estatisticas), que são
+//! números do período para a supervisão, as métricas servem para diagnosticar
+//! problemas de desempenho, como lentidão do LDAP.
+
+use crate::utils::ingresso::periodo_do_dre;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Limites superiores, em segundos, dos baldes dos histogramas de duração.
+pub const BALDES_SEGUNDOS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static METRICAS: LazyLock<Mutex<Metricas>> =
+    LazyLock::new(|| Mutex::new(Metricas::default()));
+
+/// Um histograma cumulativo de durações, no mesmo formato do Prometheus.
+#[derive(Debug, Clone)]
+pub struct Histograma {
+    /// Quantidade de observações menores ou iguais a cada limite de
+    /// [`BALDES_SEGUNDOS`].
+    pub baldes: Vec<u64>,
+    /// Soma de todas as durações observadas, em segundos.
+    pub soma: f64,
+    /// Quantidade total de observações.
+    pub total: u64,
+}
+
+impl Default for Histograma {
+    fn default() -> Self {
+        Self {
+            baldes: vec![0; BALDES_SEGUNDOS.len()],
+            soma: 0.0,
+            total: 0,
+        }
+    }
+}
+
+impl Histograma {
+    /// Adiciona uma observação ao histograma.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::metricas::Histograma;
+    /// # use std::time::Duration;
+    /// let mut h = Histograma::default();
+    /// h.observar(Duration::from_millis(30));
+    /// h.observar(Duration::from_secs(20));
+    ///
+    /// assert_eq!(h.total, 2);
+    /// // 30ms não cabe no balde de 25ms, mas cabe no de 50ms
+    /// assert_eq!(h.baldes[2], 0);
+    /// assert_eq!(h.baldes[3], 1);
+    /// // 20s não cabe em nenhum balde e só é contado no total (o +Inf)
+    /// assert_eq!(h.baldes.last(), Some(&1));
+    /// ```
+    pub fn observar(&mut self, duracao: Duration) {
+        let segundos = duracao.as_secs_f64();
+
+        for (balde, limite) in self.baldes.iter_mut().zip(BALDES_SEGUNDOS) {
+            if segundos <= *limite {
+                *balde += 1;
+            }
+        }
+        self.soma += segundos;
+        self.total += 1;
+    }
+}
+
+/// Métricas de uma operação LDAP específica.
+#[derive(Debug, Default, Clone)]
+pub struct MetricasOperacao {
+    /// Durações de todas as execuções, com sucesso ou não.
+    pub duracao: Histograma,
+    /// Quantidade de execuções que retornaram erro.
+    pub falhas: u64,
+}
+
+/// Conjunto de todas as métricas coletadas pelo processo.
+#[derive(Debug, Default, Clone)]
+pub struct Metricas {
+    /// Métricas por operação LDAP (`bind`, `busca`, `add`, ...).
+    pub operacoes_ldap: BTreeMap<&'static str, MetricasOperacao>,
+    /// Cadastros feitos, pelo [período de ingresso](periodo_do_dre) do
+    /// DRE, ou `desconhecido`.
+    pub cadastros_por_ingresso: BTreeMap<String, u64>,
+    /// Cadastros que falharam, por
+    /// [etapa](crate::cadastro_aluno::EtapaCadastro).
+    pub falhas_cadastro: BTreeMap<&'static str, u64>,
+    /// Durações de cada
+    /// [etapa do cadastro](crate::cadastro_aluno::EtapaCadastro).
+    pub etapas_cadastro: BTreeMap<&'static str, Histograma>,
+    /// DREs achados com mais de uma conta depois do cadastro, pela
+    /// [verificação de duplicidade](crate::duplicidade), separados pelos que
+    /// foram corrigidos ou não.
+    pub duplicidades: BTreeMap<bool, u64>,
+    /// A [fila das consultas ao Gnosys](crate::portal_ufrj::limitar_consultas).
+    pub fila_gnosys: MetricasFilaGnosys,
+    /// Itens expirados apagados pela
+    /// [limpeza periódica](crate::configuracao::ConfiguracaoLimpeza), por
+    /// tipo.
+    pub expirados: BTreeMap<&'static str, u64>,
+}
+
+/// Métricas da fila das consultas ao Gnosys.
+#[derive(Debug, Default, Clone)]
+pub struct MetricasFilaGnosys {
+    /// Consultas esperando uma vaga.
+    pub esperando: u64,
+    /// Consultas com uma vaga, em andamento.
+    pub em_andamento: u64,
+    /// Quanto as consultas esperaram por uma vaga, com ou sem sucesso.
+    pub espera: Histograma,
+    /// Consultas que desistiram depois de esperar o limite.
+    pub esgotadas: u64,
+}
+
+impl Metricas {
+    /// Exporta as métricas no formato texto do Prometheus.
+    pub fn prometheus(&self) -> String {
+        let mut s = String::new();
+
+        s.push_str(concat!(
+            "# HELP alumnic_ldap_duracao_segundos Duração das operações ",
+            "LDAP.\n",
+            "# TYPE alumnic_ldap_duracao_segundos histogram\n",
+        ));
+        for (op, m) in &self.operacoes_ldap {
+            for (limite, qtd) in BALDES_SEGUNDOS.iter().zip(&m.duracao.baldes) {
+                let _ = writeln!(
+                    s,
+                    "alumnic_ldap_duracao_segundos_bucket\
+                     {{operacao=\"{op}\",le=\"{limite}\"}} {qtd}",
+                );
+            }
+            let _ = writeln!(
+                s,
+                "alumnic_ldap_duracao_segundos_bucket\
+                 {{operacao=\"{op}\",le=\"+Inf\"}} {}",
+                m.duracao.total,
+            );
+            let _ = writeln!(
+                s,
+                "alumnic_ldap_duracao_segundos_sum{{operacao=\"{op}\"}} {}",
+                m.duracao.soma,
+            );
+            let _ = writeln!(
+                s,
+                "alumnic_ldap_duracao_segundos_count{{operacao=\"{op}\"}} {}",
+                m.duracao.total,
+            );
+        }
+
+        s.push_str(concat!(
+            "# HELP alumnic_ldap_falhas_total Operações LDAP que falharam.\n",
+            "# TYPE alumnic_ldap_falhas_total counter\n",
+        ));
+        for (op, m) in &self.operacoes_ldap {
+            let _ = writeln!(
+                s,
+                "alumnic_ldap_falhas_total{{operacao=\"{op}\"}} {}",
+                m.falhas,
+            );
+        }
+
+        s.push_str(concat!(
+            "# HELP alumnic_cadastro_etapa_duracao_segundos Duração de cada ",
+            "etapa do cadastro.\n",
+            "# TYPE alumnic_cadastro_etapa_duracao_segundos histogram\n",
+        ));
+        for (etapa, h) in &self.etapas_cadastro {
+            for (limite, qtd) in BALDES_SEGUNDOS.iter().zip(&h.baldes) {
+                let _ = writeln!(
+                    s,
+                    "alumnic_cadastro_etapa_duracao_segundos_bucket\
+                     {{etapa=\"{etapa}\",le=\"{limite}\"}} {qtd}",
+                );
+            }
+            let _ = writeln!(
+                s,
+                "alumnic_cadastro_etapa_duracao_segundos_bucket\
+                 {{etapa=\"{etapa}\",le=\"+Inf\"}} {}",
+                h.total,
+            );
+            let _ = writeln!(
+                s,
+                "alumnic_cadastro_etapa_duracao_segundos_sum\
+                 {{etapa=\"{etapa}\"}} {}",
+                h.soma,
+            );
+            let _ = writeln!(
+                s,
+                "alumnic_cadastro_etapa_duracao_segundos_count\
+                 {{etapa=\"{etapa}\"}} {}",
+                h.total,
+            );
+        }
+
+        s.push_str(concat!(
+            "# HELP alumnic_cadastros_total Cadastros feitos, pelo período ",
+            "de ingresso do DRE.\n",
+            "# TYPE alumnic_cadastros_total counter\n",
+        ));
+        for (ingresso, total) in &self.cadastros_por_ingresso {
+            let _ = writeln!(
+                s,
+                "alumnic_cadastros_total{{ingresso=\"{ingresso}\"}} {total}",
+            );
+        }
+
+        s.push_str(concat!(
+            "# HELP alumnic_cadastro_falhas_total Cadastros que falharam, ",
+            "pela etapa em que pararam.\n",
+            "# TYPE alumnic_cadastro_falhas_total counter\n",
+        ));
+        for (etapa, falhas) in &self.falhas_cadastro {
+            let _ = writeln!(
+                s,
+                "alumnic_cadastro_falhas_total{{etapa=\"{etapa}\"}} {falhas}",
+            );
+        }
+
+        s.push_str(concat!(
+            "# HELP alumnic_cadastro_duplicidades_total DREs com mais de uma ",
+            "conta achados depois do cadastro.\n",
+            "# TYPE alumnic_cadastro_duplicidades_total counter\n",
+        ));
+        for (corrigido, total) in &self.duplicidades {
+            let _ = writeln!(
+                s,
+                "alumnic_cadastro_duplicidades_total\
+                 {{corrigido=\"{corrigido}\"}} {total}",
+            );
+        }
+
+        let fila = &self.fila_gnosys;
+        s.push_str(concat!(
+            "# HELP alumnic_gnosys_fila_consultas Consultas ao Gnosys ",
+            "esperando uma vaga ou em andamento.\n",
+            "# TYPE alumnic_gnosys_fila_consultas gauge\n",
+        ));
+        let _ = writeln!(
+            s,
+            "alumnic_gnosys_fila_consultas{{estado=\"esperando\"}} {}",
+            fila.esperando,
+        );
+        let _ = writeln!(
+            s,
+            "alumnic_gnosys_fila_consultas{{estado=\"em_andamento\"}} {}",
+            fila.em_andamento,
+        );
+
+        s.push_str(concat!(
+            "# HELP alumnic_gnosys_espera_segundos Espera das consultas ao ",
+            "Gnosys por uma vaga.\n",
+            "# TYPE alumnic_gnosys_espera_segundos histogram\n",
+        ));
+        for (limite, qtd) in BALDES_SEGUNDOS.iter().zip(&fila.espera.baldes) {
+            let _ = writeln!(
+                s,
+                "alumnic_gnosys_espera_segundos_bucket{{le=\"{limite}\"}} {qtd}",
+            );
+        }
+        let _ = writeln!(
+            s,
+            "alumnic_gnosys_espera_segundos_bucket{{le=\"+Inf\"}} {}",
+            fila.espera.total,
+        );
+        let _ = writeln!(
+            s,
+            "alumnic_gnosys_espera_segundos_sum {}",
+            fila.espera.soma,
+        );
+        let _ = writeln!(
+            s,
+            "alumnic_gnosys_espera_segundos_count {}",
+            fila.espera.total,
+        );
+
+        s.push_str(concat!(
+            "# HELP alumnic_gnosys_esperas_esgotadas_total Consultas ao ",
+            "Gnosys que desistiram de esperar uma vaga.\n",
+            "# TYPE alumnic_gnosys_esperas_esgotadas_total counter\n",
+        ));
+        let _ = writeln!(
+            s,
+            "alumnic_gnosys_esperas_esgotadas_total {}",
+            fila.esgotadas,
+        );
+
+        s.push_str(concat!(
+            "# HELP alumnic_limpeza_expirados_total Itens expirados apagados ",
+            "pela limpeza periódica.\n",
+            "# TYPE alumnic_limpeza_expirados_total counter\n",
+        ));
+        for (tipo, total) in &self.expirados {
+            let _ = writeln!(
+                s,
+                "alumnic_limpeza_expirados_total{{tipo=\"{tipo}\"}} {total}",
+            );
+        }
+
+        s
+    }
+}
+
+/// Registra a execução de uma operação LDAP.
+pub fn registrar_operacao_ldap(
+    operacao: &'static str,
+    duracao: Duration,
+    sucesso: bool,
+) {
+    let mut metricas = METRICAS.lock().unwrap();
+    let m = metricas.operacoes_ldap.entry(operacao).or_default();
+
+    m.duracao.observar(duracao);
+    if !sucesso {
+        m.falhas += 1;
+    }
+}
+
+/// Registra o cadastro feito do `dre`, pelo período de ingresso dele.
+pub fn registrar_cadastro(dre: &str) {
+    let ingresso = periodo_do_dre(dre)
+        .map_or_else(|| "desconhecido".to_string(), |p| p.to_string());
+    let mut metricas = METRICAS.lock().unwrap();
+    *metricas.cadastros_por_ingresso.entry(ingresso).or_default() += 1;
+}
+
+/// Registra um cadastro que falhou na `etapa`.
+pub fn registrar_falha_cadastro(etapa: &'static str) {
+    let mut metricas = METRICAS.lock().unwrap();
+    *metricas.falhas_cadastro.entry(etapa).or_default() += 1;
+}
+
+/// Registra quanto uma etapa do cadastro levou, com sucesso ou não.
+pub fn registrar_etapa_cadastro(etapa: &'static str, duracao: Duration) {
+    let mut metricas = METRICAS.lock().unwrap();
+    metricas
+        .etapas_cadastro
+        .entry(etapa)
+        .or_default()
+        .observar(duracao);
+}
+
+/// Registra um DRE achado com mais de uma conta depois do cadastro.
+pub fn registrar_duplicidade(corrigido: bool) {
+    let mut metricas = METRICAS.lock().unwrap();
+    *metricas.duplicidades.entry(corrigido).or_default() += 1;
+}
+
+/// Registra `n` itens do `tipo` apagados pela limpeza periódica.
+pub fn registrar_expirados(tipo: &'static str, n: usize) {
+    let mut metricas = METRICAS.lock().unwrap();
+    *metricas.expirados.entry(tipo).or_default() += n as u64;
+}
+
+/// Registra uma consulta ao Gnosys entrando na fila.
+pub fn registrar_entrada_fila_gnosys() {
+    METRICAS.lock().unwrap().fila_gnosys.esperando += 1;
+}
+
+/// Registra uma consulta ao Gnosys saindo da fila depois de esperar
+/// `espera`, com uma vaga ou desistindo.
+pub fn registrar_saida_fila_gnosys(espera: Duration, com_vaga: bool) {
+    let mut metricas = METRICAS.lock().unwrap();
+    let fila = &mut metricas.fila_gnosys;
+    fila.esperando -= 1;
+    fila.espera.observar(espera);
+    if com_vaga {
+        fila.em_andamento += 1;
+    } else {
+        fila.esgotadas += 1;
+    }
+}
+
+/// Registra o fim de uma consulta ao Gnosys, que devolve a vaga.
+pub fn registrar_fim_consulta_gnosys() {
+    METRICAS.lock().unwrap().fila_gnosys.em_andamento -= 1;
+}
+
+/// Retorna uma cópia das métricas atuais.
+pub fn metricas() -> Metricas {
+    METRICAS.lock().unwrap().clone()
+}
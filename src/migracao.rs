@@ -0,0 +1,172 @@
+//! Módulo com a migração de um aluno da graduação para a pós-graduação. O
+//! aluno continua com o mesmo uid, uidNumber e homeDirectory, mas passa para
+//! a OU, o DRE, o gid e a cota da pós.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{buscar_conta_por_dre, buscar_conta_por_uid};
+use crate::ldap::migrar::migrar_para_pos;
+use crate::portal_ufrj::{Consulta, ConsultaErro, consulta_em};
+use crate::utils::nome::Nome;
+use crate::utils::validacao_entradas::*;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// O uid da conta e os dados do documento "Regularmente Matriculado" do novo
+/// vínculo, na pós-graduação.
+#[derive(Debug, Deserialize)]
+pub struct DadosParaMigracao {
+    pub uid: String,
+    /// O novo DRE, somente números, 9 dígitos.
+    pub dre: String,
+    /// A data de emissão contida no documento, no formato `dd/mm/aaaa`.
+    pub data: String,
+    /// A hora de emissão contida no documento, no formato `hh:mm`, 24 horas.
+    pub hora: String,
+    /// O código contido no documento, no formato
+    /// `XXXX.XXXX.XXXX.XXXX.XXXX.XXXX.XXXX.XXXX`.
+    pub codigo: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeMigracao {
+    #[error("A migração para a pós-graduação não está configurada")]
+    Desativada,
+    #[error("O DRE {0:?} não é válido")]
+    DREInvalido(String),
+    #[error("A data {0:?} não é válida")]
+    DataInvalida(String),
+    #[error("A hora {0:?} não é válida")]
+    HoraInvalida(String),
+    #[error("O código {0:?} não é válido")]
+    CodigoInvalido(String),
+
+    #[error("Não foi possível obter informações do SIGA: {0}")]
+    ErroNaConsulta(#[from] ConsultaErro),
+    #[error("O documento é de um aluno de {0}, não da pós-graduação")]
+    OutroCurso(String),
+    #[error("O documento de matrícula é inválido")]
+    DocumentoInvalido,
+
+    #[error("Não existe conta com o uid {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta {0} está bloqueada")]
+    ContaBloqueada(String),
+    #[error("O documento é de {siga:?}, não do dono da conta {conta:?}")]
+    OutraPessoa { conta: String, siga: String },
+    #[error("O DRE já pertence à conta {0:?}")]
+    DRERedundante(String),
+    #[error("Houve um problema ao migrar a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A conta foi migrada, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+impl DadosParaMigracao {
+    /// Valida os dados, autentica o documento no Gnosys e, se ele for do curso
+    /// de pós-graduação configurado e da mesma pessoa, migra a conta,
+    /// retornando o seu novo DN. A migração é guardada na auditoria.
+    pub async fn migrar<F: FonteLdap>(
+        mut self,
+        cfg: &Configuracao,
+        ldap: &F,
+        agora: DateTime<Utc>,
+    ) -> Result<String, ErroDeMigracao> {
+        let pos = cfg.pos.as_ref().ok_or(ErroDeMigracao::Desativada)?;
+
+        self.dre = processar_dre(&self.dre)
+            .ok_or_else(move || ErroDeMigracao::DREInvalido(self.dre))?;
+        self.data = processar_data(&self.data)
+            .ok_or_else(move || ErroDeMigracao::DataInvalida(self.data))?;
+        self.hora = processar_hora(&self.hora)
+            .ok_or_else(move || ErroDeMigracao::HoraInvalida(self.hora))?;
+        self.codigo = processar_codigo(&self.codigo)
+            .ok_or_else(move || ErroDeMigracao::CodigoInvalido(self.codigo))?;
+
+        let siga = match consulta_em(
+            &cfg.gnosys_url,
+            &self.dre,
+            &self.data,
+            &self.hora,
+            &self.codigo,
+        )
+        .await?
+        {
+            Consulta::AlunoOutroCurso { nome, curso }
+                if curso.to_lowercase() == pos.curso.to_lowercase() =>
+            {
+                nome
+            },
+            Consulta::AlunoOutroCurso { curso, .. } => {
+                Err(ErroDeMigracao::OutroCurso(curso))?
+            },
+            Consulta::AlunoBCC { .. } => {
+                Err(ErroDeMigracao::OutroCurso("Ciência da Computação".into()))?
+            },
+            Consulta::AlunoProfComp { .. } => {
+                Err(ErroDeMigracao::OutroCurso("Ensino de Computação".into()))?
+            },
+            Consulta::Desconhecido => Err(ErroDeMigracao::DocumentoInvalido)?,
+        };
+
+        let mut conexao = ldap.abrir().await?;
+        let r: Result<_, ErroDeMigracao> = async {
+            let conta = buscar_conta_por_uid(&self.uid, &mut conexao)
+                .await?
+                .ok_or_else(|| {
+                    ErroDeMigracao::ContaInexistente(self.uid.clone())
+                })?;
+            if conta.estado.bloqueada() {
+                return Err(ErroDeMigracao::ContaBloqueada(conta.uid));
+            }
+
+            // O nome do SIGA é o mesmo do cadastro, a menos de acentos e
+            // partículas como "de"
+            let mesma_pessoa =
+                match (siga.parse::<Nome>(), conta.nome.parse::<Nome>()) {
+                    (Ok(a), Ok(b)) => a == b,
+                    _ => false,
+                };
+            if !mesma_pessoa {
+                return Err(ErroDeMigracao::OutraPessoa {
+                    conta: conta.nome,
+                    siga,
+                });
+            }
+
+            if let Some(outra) =
+                buscar_conta_por_dre(&self.dre, &mut conexao).await?
+            {
+                return Err(ErroDeMigracao::DRERedundante(outra.uid));
+            }
+
+            let dn = migrar_para_pos(
+                &conta,
+                &self.dre,
+                pos,
+                cfg.usuario_novo.campos_academicos.as_ref(),
+                &mut conexao,
+            )
+            .await?;
+            Ok((dn, conta.dre))
+        }
+        .await;
+        ldap.fechar(conexao).await?;
+        let (dn, dre_antigo) = r?;
+
+        registrar(
+            &cfg.auditoria,
+            &Registro {
+                quando: agora,
+                operacao: "migracao_pos",
+                uid: &self.uid,
+                motivo: &format!("DRE {dre_antigo} → {}", self.dre),
+            },
+        )
+        .await?;
+
+        Ok(dn)
+    }
+}
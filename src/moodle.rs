@@ -0,0 +1,218 @@
+//! Inscrição das contas novas no Moodle, o AVA do IC, pelos web services
+//! REST dele. O usuário é criado com o mesmo username da conta e o email
+//! institucional, e autentica pelo LDAP, então não tem senha própria no
+//! Moodle.
+//!
+//! Os outros campos do usuário vêm de modelos, em que `{username}`,
+//! `{email}`, `{ou}`, `{dre}` e `{nome}` são trocados pelos dados da conta,
+//! como nos [próximos passos](crate::proximos_passos).
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// A função dos web services que cria os usuários.
+const CRIAR_USUARIOS: &str = "core_user_create_users";
+
+/// O Moodle em que as contas novas são inscritas, configurado como
+///
+/// ```yaml
+/// url: "https://ava.ic.ufrj.br"
+/// token: "..."
+/// autenticacao: ldap
+/// campos:
+///   idnumber: "{dre}"
+///   department: "{ou}"
+/// campos_personalizados:
+///   curso: "{ou}"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoMoodle {
+    pub url: String,
+    /// O token de um serviço externo com a função `core_user_create_users`.
+    pub token: SecretString,
+    /// O método de autenticação do usuário no Moodle. Com `manual`, o Moodle
+    /// gera uma senha e a manda para o email institucional.
+    #[serde(default = "autenticacao_padrao")]
+    pub autenticacao: String,
+    /// Os modelos dos campos do usuário, pelo nome do campo no Moodle, como
+    /// `idnumber` ou `institution`. Valem mais que os campos preenchidos
+    /// pelo alumnic.
+    #[serde(default)]
+    pub campos: BTreeMap<String, String>,
+    /// Os modelos dos campos de perfil criados no Moodle, pelo nome curto
+    /// de cada um.
+    #[serde(default)]
+    pub campos_personalizados: BTreeMap<String, String>,
+}
+
+fn autenticacao_padrao() -> String {
+    "ldap".to_string()
+}
+
+#[derive(Debug, Error)]
+pub enum ErroMoodle {
+    #[error("a chamada ao Moodle falhou: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("o Moodle recusou o usuário ({codigo}): {mensagem}")]
+    Recusada { codigo: String, mensagem: String },
+    #[error("o Moodle respondeu algo inesperado: {0}")]
+    RespostaEstranha(String),
+}
+
+/// Um usuário inscrito no Moodle.
+#[derive(Debug, Clone, Copy)]
+pub struct UsuarioMoodle<'a> {
+    pub uid: &'a str,
+    pub nome: &'a str,
+    pub email: &'a str,
+    /// A OU da conta, como `alunos` ou `profcomp`.
+    pub ou: &'a str,
+    pub dre: &'a str,
+}
+
+impl UsuarioMoodle<'_> {
+    /// Troca os campos do `modelo` pelos dados do usuário.
+    fn preencher(&self, modelo: &str) -> String {
+        [
+            ("{username}", self.uid),
+            ("{email}", self.email),
+            ("{ou}", self.ou),
+            ("{dre}", self.dre),
+            ("{nome}", self.nome),
+        ]
+        .iter()
+        .fold(modelo.to_string(), |texto, (campo, valor)| {
+            texto.replace(campo, valor)
+        })
+    }
+}
+
+/// A resposta do Moodle quando a chamada falha.
+#[derive(Deserialize)]
+struct Excecao {
+    errorcode: String,
+    message: String,
+}
+
+/// Um usuário criado, na resposta do Moodle.
+#[derive(Deserialize)]
+struct Criado {
+    id: u64,
+}
+
+impl ConfiguracaoMoodle {
+    /// Os parâmetros da chamada que cria o `usuario`, sem o token. O nome vai
+    /// com a primeira palavra no `firstname` e o resto no `lastname`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::moodle::{ConfiguracaoMoodle, UsuarioMoodle};
+    /// let cfg = ConfiguracaoMoodle {
+    ///     url: "https://ava.ic.ufrj.br".to_string(),
+    ///     token: "segredo".to_string().into(),
+    ///     autenticacao: "ldap".to_string(),
+    ///     campos: [("idnumber".to_string(), "{dre}".to_string())].into(),
+    ///     campos_personalizados: [("curso".to_string(), "{ou}".to_string())]
+    ///         .into(),
+    /// };
+    /// let usuario = UsuarioMoodle {
+    ///     uid: "claudiolc",
+    ///     nome: "Cláudio de Lima Cavalcante",
+    ///     email: "claudiolc@ic.ufrj.br",
+    ///     ou: "alunos",
+    ///     dre: "123456789",
+    /// };
+    /// let parametros = cfg.parametros(&usuario);
+    /// let valor = |nome: &str| {
+    ///     parametros.iter().find(|(n, _)| n == nome).map(|(_, v)| v.as_str())
+    /// };
+    /// assert_eq!(valor("users[0][username]"), Some("claudiolc"));
+    /// assert_eq!(valor("users[0][firstname]"), Some("Cláudio"));
+    /// assert_eq!(valor("users[0][lastname]"), Some("de Lima Cavalcante"));
+    /// assert_eq!(valor("users[0][idnumber]"), Some("123456789"));
+    /// assert_eq!(valor("users[0][customfields][0][type]"), Some("curso"));
+    /// assert_eq!(valor("users[0][customfields][0][value]"), Some("alunos"));
+    /// assert_eq!(valor("users[0][createpassword]"), None);
+    /// ```
+    pub fn parametros(&self, usuario: &UsuarioMoodle) -> Vec<(String, String)> {
+        let (primeiro, resto) =
+            usuario.nome.split_once(' ').unwrap_or((usuario.nome, ""));
+
+        let mut campos: BTreeMap<String, String> = [
+            ("username", usuario.uid),
+            ("auth", &self.autenticacao),
+            ("firstname", primeiro),
+            ("lastname", resto),
+            ("email", usuario.email),
+        ]
+        .into_iter()
+        .map(|(campo, valor)| (campo.to_string(), valor.to_string()))
+        .collect();
+        if self.autenticacao == "manual" {
+            campos.insert("createpassword".to_string(), "1".to_string());
+        }
+        for (campo, modelo) in &self.campos {
+            campos.insert(campo.clone(), usuario.preencher(modelo));
+        }
+
+        let mut parametros: Vec<_> = campos
+            .into_iter()
+            .map(|(campo, valor)| (format!("users[0][{campo}]"), valor))
+            .collect();
+        for (i, (campo, modelo)) in
+            self.campos_personalizados.iter().enumerate()
+        {
+            parametros.push((
+                format!("users[0][customfields][{i}][type]"),
+                campo.clone(),
+            ));
+            parametros.push((
+                format!("users[0][customfields][{i}][value]"),
+                usuario.preencher(modelo),
+            ));
+        }
+        parametros
+    }
+
+    /// Cria o `usuario` no Moodle, retornando o id dele.
+    pub async fn inscrever(
+        &self,
+        usuario: &UsuarioMoodle<'_>,
+    ) -> Result<u64, ErroMoodle> {
+        let mut parametros = vec![
+            (
+                "wstoken".to_string(),
+                self.token.expose_secret().to_string(),
+            ),
+            ("wsfunction".to_string(), CRIAR_USUARIOS.to_string()),
+            ("moodlewsrestformat".to_string(), "json".to_string()),
+        ];
+        parametros.extend(self.parametros(usuario));
+
+        let resposta = reqwest::Client::new()
+            .post(format!(
+                "{}/webservice/rest/server.php",
+                self.url.trim_end_matches('/'),
+            ))
+            .form(&parametros)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        // O Moodle responde as falhas com 200 e a exceção no corpo
+        if let Ok(excecao) = serde_json::from_str::<Excecao>(&resposta) {
+            return Err(ErroMoodle::Recusada {
+                codigo: excecao.errorcode,
+                mensagem: excecao.message,
+            });
+        }
+        match serde_json::from_str::<Vec<Criado>>(&resposta) {
+            Ok(criados) if criados.len() == 1 => Ok(criados[0].id),
+            _ => Err(ErroMoodle::RespostaEstranha(resposta)),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Envio de avisos por email aos alunos. As mensagens são entregues a um
+//! comando externo compatível com o `sendmail -t`, que recebe a mensagem
+//! completa, com os cabeçalhos, na entrada padrão.
+use base64::prelude::*;
+use serde::Deserialize;
+use std::process::{ExitStatus, Stdio};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Configuração do envio de avisos.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ConfiguracaoNotificacao {
+    /// O comando e seus argumentos, por exemplo
+    /// `["/usr/sbin/sendmail", "-t"]`. Se for vazio, nenhum aviso é enviado.
+    pub comando: Vec<String>,
+    /// O endereço usado no cabeçalho `From`.
+    pub remetente: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ErroNotificacao {
+    #[error("o envio de avisos não está configurado")]
+    Desativada,
+    #[error("o aluno não tem email cadastrado")]
+    SemEndereco,
+    #[error("não foi possível rodar o comando de envio: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("o comando de envio falhou com {0}")]
+    Falha(ExitStatus),
+}
+
+/// Um aviso a ser enviado.
+#[derive(Debug, Clone)]
+pub struct Mensagem {
+    pub para: String,
+    pub assunto: String,
+    pub corpo: String,
+}
+
+impl Mensagem {
+    /// Formata a mensagem com os cabeçalhos, codificando o assunto para que
+    /// ele possa ter acentos.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::notificacao::Mensagem;
+    /// let m = Mensagem {
+    ///     para: "aluno@exemplo.com".to_string(),
+    ///     assunto: "Olá".to_string(),
+    ///     corpo: "Tudo bem?".to_string(),
+    /// };
+    /// let texto = m.formatar("supervisao@ic.ufrj.br");
+    /// assert!(texto.starts_with("From: supervisao@ic.ufrj.br\n"));
+    /// assert!(texto.contains("To: aluno@exemplo.com\n"));
+    /// assert!(texto.contains("Subject: =?UTF-8?B?T2zDoQ==?=\n"));
+    /// assert!(texto.ends_with("\n\nTudo bem?\n"));
+    /// ```
+    pub fn formatar(&self, remetente: &str) -> String {
+        format!(
+            concat!(
+                "From: {}\n",
+                "To: {}\n",
+                "Subject: =?UTF-8?B?{}?=\n",
+                "MIME-Version: 1.0\n",
+                "Content-Type: text/plain; charset=UTF-8\n",
+                "Content-Transfer-Encoding: 8bit\n",
+                "\n",
+                "{}\n",
+            ),
+            remetente,
+            self.para,
+            BASE64_STANDARD.encode(&self.assunto),
+            self.corpo,
+        )
+    }
+}
+
+/// Envia a `mensagem` pelo comando configurado.
+pub async fn enviar(
+    cfg: &ConfiguracaoNotificacao,
+    mensagem: &Mensagem,
+) -> Result<(), ErroNotificacao> {
+    let Some((programa, argumentos)) = cfg.comando.split_first() else {
+        return Err(ErroNotificacao::Desativada);
+    };
+
+    let mut processo = Command::new(programa)
+        .args(argumentos)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    let mut entrada = processo.stdin.take().expect("stdin foi configurado");
+    entrada
+        .write_all(mensagem.formatar(&cfg.remetente).as_bytes())
+        .await?;
+    // Fecha a entrada padrão para o comando saber que a mensagem acabou
+    drop(entrada);
+
+    let status = processo.wait().await?;
+    if !status.success() {
+        return Err(ErroNotificacao::Falha(status));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,229 @@
+//! Estatísticas anônimas da origem dos cadastros: a rede dos laboratórios, o
+//! eduroam ou fora da universidade. O endereço de quem se cadastrou é
+//! classificado na hora pelas faixas configuradas e descartado, e só a
+//! contagem de cada origem no período é guardada, em `DADOS/origens.json`,
+//! para o [relatório](crate::relatorio) do período.
+use crate::cadastros_por_ip::endereco_da_requisicao;
+use crate::utils::periodo::Periodo;
+use axum::http::HeaderMap;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+use tokio::sync::Mutex;
+
+/// O arquivo das contagens, no diretório de dados.
+const ARQUIVO: &str = "origens.json";
+
+/// Impede que duas contagens leiam o arquivo ao mesmo tempo e uma perca a
+/// outra.
+static CONTAGEM: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("faixa de IP inválida: {0:?}")]
+pub struct FaixaInvalida(String);
+
+/// Uma faixa de endereços na notação CIDR, como `146.164.2.0/24`. Um
+/// endereço sem o prefixo é uma faixa só com ele.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Faixa {
+    rede: IpAddr,
+    prefixo: u32,
+}
+
+/// Os bits do endereço `ip` e quantos são.
+fn bits(ip: IpAddr) -> (u128, u32) {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => (u32::from(ip).into(), 32),
+        IpAddr::V6(ip) => (u128::from(ip), 128),
+    }
+}
+
+impl Faixa {
+    /// Se o `ip` está na faixa. Os endereços IPv4 mapeados em IPv6, como os
+    /// de um servidor escutando nos dois, contam como IPv4.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::origens::Faixa;
+    /// let faixa: Faixa = "146.164.2.0/23".parse().unwrap();
+    /// assert!(faixa.contem("146.164.3.7".parse().unwrap()));
+    /// assert!(faixa.contem("::ffff:146.164.2.1".parse().unwrap()));
+    /// assert!(!faixa.contem("146.164.4.1".parse().unwrap()));
+    /// assert!(!faixa.contem("2001:12f0::1".parse().unwrap()));
+    /// ```
+    pub fn contem(&self, ip: IpAddr) -> bool {
+        let (rede, tamanho) = bits(self.rede);
+        let (ip, tamanho_ip) = bits(ip);
+        let livres = tamanho - self.prefixo;
+        tamanho == tamanho_ip
+            && rede.checked_shr(livres).unwrap_or(0)
+                == ip.checked_shr(livres).unwrap_or(0)
+    }
+}
+
+impl FromStr for Faixa {
+    type Err = FaixaInvalida;
+
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::origens::Faixa;
+    /// assert!("10.20.0.0/16".parse::<Faixa>().is_ok());
+    /// assert!("2001:12f0::/32".parse::<Faixa>().is_ok());
+    /// assert!("10.20.0.0/33".parse::<Faixa>().is_err());
+    /// assert!("laboratorio".parse::<Faixa>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalida = || FaixaInvalida(s.to_string());
+        let (rede, prefixo) = match s.trim().split_once('/') {
+            Some((rede, prefixo)) => (rede, Some(prefixo)),
+            None => (s.trim(), None),
+        };
+        let rede: IpAddr = rede.parse().map_err(|_| invalida())?;
+        let (_, tamanho) = bits(rede);
+        let prefixo = match prefixo {
+            Some(prefixo) => prefixo.parse().map_err(|_| invalida())?,
+            None => tamanho,
+        };
+        if prefixo > tamanho {
+            return Err(invalida());
+        }
+
+        Ok(Faixa {
+            rede: rede.to_canonical(),
+            prefixo,
+        })
+    }
+}
+
+impl TryFrom<String> for Faixa {
+    type Error = FaixaInvalida;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Uma origem dos cadastros, com as faixas de endereços dela.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rede {
+    pub nome: String,
+    pub faixas: Vec<Faixa>,
+}
+
+/// A classificação dos cadastros pela origem, configurada como
+///
+/// ```yaml
+/// origens:
+///   proxy: true
+///   redes:
+///     - nome: "laboratorios"
+///       faixas: ["146.164.2.0/24", "146.164.3.0/24"]
+///     - nome: "eduroam"
+///       faixas: ["10.20.0.0/16"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfiguracaoOrigens {
+    /// As redes, na ordem em que são testadas.
+    pub redes: Vec<Rede>,
+    /// A origem dos endereços fora das redes.
+    #[serde(default = "externa_padrao")]
+    pub externa: String,
+    /// Se a API fica atrás de um proxy reverso, como no
+    /// [cadastros por IP](crate::cadastros_por_ip::ConfiguracaoCadastrosPorIp::proxy).
+    #[serde(default)]
+    pub proxy: bool,
+}
+
+fn externa_padrao() -> String {
+    "externa".to_string()
+}
+
+impl ConfiguracaoOrigens {
+    /// A origem do `ip`: a primeira rede que o contém, ou a
+    /// [externa](Self::externa).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::origens::{ConfiguracaoOrigens, Rede};
+    /// let cfg = ConfiguracaoOrigens {
+    ///     redes: vec![Rede {
+    ///         nome: "eduroam".to_string(),
+    ///         faixas: vec!["10.20.0.0/16".parse().unwrap()],
+    ///     }],
+    ///     externa: "externa".to_string(),
+    ///     proxy: false,
+    /// };
+    /// assert_eq!(cfg.classificar("10.20.4.2".parse().unwrap()), "eduroam");
+    /// assert_eq!(cfg.classificar("8.8.8.8".parse().unwrap()), "externa");
+    /// ```
+    pub fn classificar(&self, ip: IpAddr) -> &str {
+        self.redes
+            .iter()
+            .find(|rede| rede.faixas.iter().any(|faixa| faixa.contem(ip)))
+            .map_or(&self.externa, |rede| &rede.nome)
+    }
+
+    /// A origem de quem enviou a requisição, pelo endereço da `conexao` ou
+    /// do proxy.
+    pub fn origem(
+        &self,
+        headers: &HeaderMap,
+        conexao: Option<IpAddr>,
+    ) -> Option<&str> {
+        let ip = endereco_da_requisicao(self.proxy, headers, conexao)?;
+        Some(self.classificar(ip))
+    }
+}
+
+/// As contagens gravadas, de cada origem em cada período.
+type Contagens = BTreeMap<String, BTreeMap<String, u64>>;
+
+fn ler(caminho: &Path) -> std::io::Result<Contagens> {
+    match std::fs::read(caminho) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(Contagens::new())
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Conta um cadastro da `origem` feito no `dia`, no período dele.
+pub async fn contar(
+    dados: &Path,
+    origem: &str,
+    dia: NaiveDate,
+) -> std::io::Result<()> {
+    let _vez = CONTAGEM.lock().await;
+    let caminho = dados.join(ARQUIVO);
+
+    let mut contagens = ler(&caminho)?;
+    *contagens
+        .entry(Periodo::da_data(dia).to_string())
+        .or_default()
+        .entry(origem.to_string())
+        .or_default() += 1;
+
+    // O arquivo é trocado de uma vez, para não ficar pela metade
+    let temporario = dados.join(format!("{ARQUIVO}.tmp"));
+    tokio::fs::create_dir_all(dados).await?;
+    tokio::fs::write(&temporario, serde_json::to_vec(&contagens)?).await?;
+    tokio::fs::rename(&temporario, &caminho).await
+}
+
+/// Os cadastros de cada origem no `periodo`.
+pub fn do_periodo(
+    dados: &Path,
+    periodo: Periodo,
+) -> std::io::Result<BTreeMap<String, u64>> {
+    let mut contagens = ler(&dados.join(ARQUIVO))?;
+    Ok(contagens.remove(&periodo.to_string()).unwrap_or_default())
+}
@@ -0,0 +1,770 @@
+//! Painel web administrativo, servido pelo próprio `alumnic serve` em
+//! `/painel`. O supervisor entra com a própria conta do LDAP, desde que ela
+//! seja membro do grupo configurado, e vê as pendências, as últimas falhas da
+//! API e a busca de alunos, com botões para as operações administrativas
+//! mais comuns. As páginas são HTML simples, sem JavaScript.
+//!
+//! A sessão fica num cookie `SameSite=Strict`, então os formulários do painel
+//! não podem ser enviados a partir de outros sites.
+use crate::api::EstadoApi;
+use crate::autorizacao::{Acao, Papel, autenticar_supervisor};
+use crate::caixa_email::provisionar_pendentes;
+use crate::estatisticas::escapar_html;
+use crate::ldap::ErroLdap;
+use crate::ldap::caixa_email::{CaixaPendente, buscar_caixas_pendentes};
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{Conta, EstadoConta, buscar_contas};
+use crate::ldap::filtros;
+use crate::prazos::aplicar_prazos_em;
+use crate::reativacao::reativar;
+use crate::renovacao::dia_para_data;
+use axum::Router;
+use axum::extract::{Form, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use chrono::Utc;
+use deunicode::deunicode;
+use rand::Rng;
+use secrecy::SecretString;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Nome do cookie da sessão.
+const COOKIE: &str = "alumnic_painel";
+
+/// Quantos alunos são mostrados em cada lista.
+const MAXIMO_LINHAS: usize = 100;
+
+/// O painel, configurado como
+///
+/// ```yaml
+/// painel:
+///   grupo: "cn=supervisores,ou=grupos,dc=dcc,dc=ufrj,dc=br"
+/// ```
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoPainel {
+    /// O grupo (um `posixGroup`) cujos membros, no `memberUid`, podem entrar
+    /// no painel.
+    pub grupo: String,
+    /// Por quanto tempo, em minutos, a sessão vale depois do login.
+    pub sessao_minutos: u64,
+    /// Quantas tentativas de login podem ser feitas por minuto.
+    pub tentativas_por_minuto: u32,
+    /// Marca o cookie da sessão como `Secure`, para que o navegador só o
+    /// envie por HTTPS. Só deve ser desligado em testes.
+    pub cookie_seguro: bool,
+}
+
+impl Default for ConfiguracaoPainel {
+    fn default() -> Self {
+        Self {
+            grupo: "cn=supervisores,ou=grupos,dc=dcc,dc=ufrj,dc=br".to_string(),
+            sessao_minutos: 60,
+            tentativas_por_minuto: 10,
+            cookie_seguro: true,
+        }
+    }
+}
+
+/// Uma sessão aberta no painel.
+struct Sessao {
+    uid: String,
+    papel: Papel,
+    expira: Instant,
+}
+
+/// As sessões abertas, pelo token guardado no cookie. Ficam só em memória,
+/// então reiniciar o serviço encerra todas.
+#[derive(Default)]
+pub(crate) struct Sessoes(Mutex<HashMap<String, Sessao>>);
+
+impl Sessoes {
+    /// Abre uma sessão para o `uid` com o `papel`, retornando o token dela.
+    fn abrir(&self, uid: &str, papel: Papel, duracao: Duration) -> String {
+        let token = hex::encode(rand::rng().random::<[u8; 32]>());
+        let agora = Instant::now();
+
+        self.limpar(agora);
+        let mut sessoes = self.0.lock().unwrap();
+        sessoes.insert(
+            token.clone(),
+            Sessao {
+                uid: uid.to_string(),
+                papel,
+                expira: agora + duracao,
+            },
+        );
+        token
+    }
+
+    /// O uid e o papel do dono da sessão com o `token`, se ela não tiver
+    /// expirado.
+    fn usuario(&self, token: &str) -> Option<(String, Papel)> {
+        let sessoes = self.0.lock().unwrap();
+        sessoes
+            .get(token)
+            .filter(|s| s.expira > Instant::now())
+            .map(|s| (s.uid.clone(), s.papel))
+    }
+
+    fn encerrar(&self, token: &str) {
+        self.0.lock().unwrap().remove(token);
+    }
+
+    /// Esquece as sessões expiradas em `agora`, retornando quantas eram.
+    pub(crate) fn limpar(&self, agora: Instant) -> usize {
+        let mut sessoes = self.0.lock().unwrap();
+        let antes = sessoes.len();
+        sessoes.retain(|_, s| s.expira > agora);
+        antes - sessoes.len()
+    }
+}
+
+/// O token da sessão no cookie da requisição.
+fn token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .find_map(|c| c.trim().strip_prefix(COOKIE)?.strip_prefix('='))
+}
+
+/// Por que uma página do painel foi recusada.
+enum Recusa {
+    /// O painel não foi configurado, e a resposta é `404`.
+    SemPainel,
+    /// A sessão não existe ou expirou, e a resposta é o redirecionamento para
+    /// o login.
+    SemSessao,
+    /// O papel do supervisor não permite a operação.
+    SemPermissao { uid: String },
+    /// O modo somente leitura está ativo e a operação alteraria o LDAP.
+    SomenteLeitura { uid: String, mensagem: String },
+}
+
+impl IntoResponse for Recusa {
+    fn into_response(self) -> Response {
+        match self {
+            Recusa::SemPainel => StatusCode::NOT_FOUND.into_response(),
+            Recusa::SemSessao => Redirect::to("/painel/login").into_response(),
+            Recusa::SemPermissao { uid } => resultado(
+                &uid,
+                StatusCode::FORBIDDEN,
+                "O seu papel não permite essa operação.",
+            ),
+            Recusa::SomenteLeitura { uid, mensagem } => {
+                resultado(&uid, StatusCode::SERVICE_UNAVAILABLE, &mensagem)
+            },
+        }
+    }
+}
+
+/// O uid e o papel do supervisor logado, se o papel permitir a `acao`.
+fn supervisor<F>(
+    estado: &EstadoApi<F>,
+    headers: &HeaderMap,
+    acao: Acao,
+) -> Result<(String, Papel), Recusa> {
+    if estado.cfg.painel.is_none() {
+        return Err(Recusa::SemPainel);
+    }
+
+    let (uid, papel) = token(headers)
+        .and_then(|t| estado.sessoes.usuario(t))
+        .ok_or(Recusa::SemSessao)?;
+    if !papel.permite(acao) {
+        return Err(Recusa::SemPermissao { uid });
+    }
+    Ok((uid, papel))
+}
+
+/// Uma página do painel com o `corpo` em HTML.
+fn pagina(titulo: &str, corpo: &str) -> Html<String> {
+    Html(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <title>alumnic - {titulo}</title>\
+         <style>body{{font-family:sans-serif;margin:2em}}\
+         table{{border-collapse:collapse}}\
+         td,th{{border:1px solid #ccc;padding:.2em .5em}}\
+         form{{display:inline}}</style></head>\
+         <body><h1>{titulo}</h1>{corpo}</body></html>"
+    ))
+}
+
+/// O cabeçalho das páginas de quem está logado.
+fn menu(uid: &str) -> String {
+    format!(
+        "<p>Logado como <b>{}</b> | <a href=\"/painel\">Início</a> | \
+         <form method=\"post\" action=\"/painel/sair\">\
+         <button>Sair</button></form></p>",
+        escapar_html(uid),
+    )
+}
+
+/// A página com o `resultado` de uma operação.
+fn resultado(uid: &str, status: StatusCode, resultado: &str) -> Response {
+    let corpo = format!(
+        "{}<p>{}</p><p><a href=\"/painel\">Voltar</a></p>",
+        menu(uid),
+        escapar_html(resultado),
+    );
+    (status, pagina("Resultado", &corpo)).into_response()
+}
+
+/// O formulário de login, com a `mensagem` de erro.
+fn formulario_login(mensagem: Option<&str>) -> Html<String> {
+    let mensagem = mensagem
+        .map(|m| format!("<p><b>{}</b></p>", escapar_html(m)))
+        .unwrap_or_default();
+    pagina(
+        "Painel administrativo",
+        &format!(
+            "{mensagem}<form method=\"post\" action=\"/painel/login\">\
+             <p><label>Usuário <input name=\"uid\" required></label></p>\
+             <p><label>Senha <input name=\"senha\" type=\"password\" \
+             required></label></p><p><button>Entrar</button></p></form>"
+        ),
+    )
+}
+
+async fn pagina_login<F>(State(estado): State<Arc<EstadoApi<F>>>) -> Response {
+    if estado.cfg.painel.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    formulario_login(None).into_response()
+}
+
+#[derive(Deserialize)]
+struct Login {
+    uid: String,
+    senha: SecretString,
+}
+
+async fn entrar<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    Form(login): Form<Login>,
+) -> Response {
+    let Some(cfg) = &estado.cfg.painel else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let contagem = estado
+        .limite_login
+        .lock()
+        .unwrap()
+        .contar(cfg.tentativas_por_minuto, Instant::now());
+    if contagem.is_err() {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            formulario_login(Some(
+                "Muitas tentativas de login. Tente novamente em um minuto.",
+            )),
+        )
+            .into_response();
+    }
+
+    let uid = login.uid.trim();
+    match autenticar_supervisor(uid, &login.senha, &estado.cfg, &estado.ldap)
+        .await
+    {
+        Ok(Some(papel)) => {
+            let duracao = Duration::from_secs(cfg.sessao_minutos * 60);
+            let token = estado.sessoes.abrir(uid, papel, duracao);
+            println!("O supervisor {uid:?} entrou no painel como {papel:?}");
+
+            let cookie = format!(
+                "{COOKIE}={token}; Path=/painel; Max-Age={}; HttpOnly; \
+                 SameSite=Strict{}",
+                duracao.as_secs(),
+                if cfg.cookie_seguro { "; Secure" } else { "" },
+            );
+            ([(header::SET_COOKIE, cookie)], Redirect::to("/painel"))
+                .into_response()
+        },
+        Ok(None) => (
+            StatusCode::UNAUTHORIZED,
+            formulario_login(Some("Usuário ou senha incorretos.")),
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Erro no login do painel: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                formulario_login(Some("Não foi possível consultar o LDAP.")),
+            )
+                .into_response()
+        },
+    }
+}
+
+async fn sair<F>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(token) = token(&headers) {
+        estado.sessoes.encerrar(token);
+    }
+    (
+        [(
+            header::SET_COOKIE,
+            format!("{COOKIE}=; Path=/painel; Max-Age=0"),
+        )],
+        Redirect::to("/painel/login"),
+    )
+        .into_response()
+}
+
+/// O que está esperando a ação de alguém.
+struct Pendencias {
+    carencia: Vec<Conta>,
+    suspensas: Vec<Conta>,
+    /// Só é buscada se a criação das caixas estiver configurada.
+    caixas: Vec<CaixaPendente>,
+}
+
+async fn buscar_pendencias<F: FonteLdap>(
+    estado: &EstadoApi<F>,
+) -> Result<Pendencias, ErroLdap> {
+    let mut conexao = estado.ldap.abrir().await?;
+    let r = async {
+        Ok(Pendencias {
+            carencia: buscar_contas(
+                &filtros::igual("estadoConta", "carencia"),
+                &mut conexao,
+            )
+            .await?,
+            suspensas: buscar_contas(
+                &filtros::igual("estadoConta", "suspensa"),
+                &mut conexao,
+            )
+            .await?,
+            caixas: match estado.cfg.usuario_novo.caixa_email {
+                Some(_) => buscar_caixas_pendentes(&mut conexao).await?,
+                None => vec![],
+            },
+        })
+    }
+    .await;
+    estado.ldap.fechar(conexao).await?;
+    r
+}
+
+/// A tabela das `contas`, com o botão de reativar nas suspensas se o
+/// `papel` permitir.
+fn tabela_de_contas(html: &mut String, contas: &[Conta], papel: Papel) {
+    let data = |dia: Option<i64>| {
+        dia.map(|d| dia_para_data(d).format("%d/%m/%Y").to_string())
+            .unwrap_or_default()
+    };
+
+    html.push_str(
+        "<table><tr><th>uid</th><th>DRE</th><th>Nome</th><th>Estado</th>\
+         <th>Renovação</th><th>Remoção</th><th></th></tr>",
+    );
+    for conta in contas.iter().take(MAXIMO_LINHAS) {
+        let uid = escapar_html(&conta.uid);
+        let acao = if conta.estado == EstadoConta::Suspensa
+            && papel.permite(Acao::Reativar)
+        {
+            format!(
+                "<form method=\"post\" action=\"/painel/reativar\">\
+                 <input type=\"hidden\" name=\"uid\" value=\"{uid}\">\
+                 <input name=\"motivo\" placeholder=\"Motivo\" required> \
+                 <button>Reativar</button></form>"
+            )
+        } else {
+            String::new()
+        };
+        let _ = write!(
+            html,
+            "<tr><td>{uid}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{acao}</td></tr>",
+            escapar_html(&conta.dre),
+            escapar_html(&conta.nome),
+            conta.estado.valor(),
+            data(conta.data_renovacao),
+            data(conta.data_remocao),
+        );
+    }
+    html.push_str("</table>");
+    if contas.len() > MAXIMO_LINHAS {
+        let _ = write!(
+            html,
+            "<p>E mais {} contas.</p>",
+            contas.len() - MAXIMO_LINHAS,
+        );
+    }
+}
+
+/// O formulário da busca de alunos, com o `termo` buscado.
+fn formulario_busca(termo: &str) -> String {
+    format!(
+        "<form method=\"get\" action=\"/painel/busca\">\
+         <input name=\"q\" value=\"{}\" placeholder=\"uid, DRE ou nome\"> \
+         <button>Buscar</button></form>",
+        escapar_html(termo),
+    )
+}
+
+/// Um botão que envia um POST para a `acao`, com os `campos` escondidos.
+fn botao(acao: &str, texto: &str, campos: &[(&str, &str)]) -> String {
+    let campos: String = campos
+        .iter()
+        .map(|(nome, valor)| {
+            format!("<input type=\"hidden\" name=\"{nome}\" value=\"{valor}\">")
+        })
+        .collect();
+    format!(
+        "<form method=\"post\" action=\"{acao}\">{campos}\
+         <button>{texto}</button></form>"
+    )
+}
+
+async fn inicio<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+) -> Response {
+    let (uid, papel) = match supervisor(&estado, &headers, Acao::VerPainel) {
+        Ok(logado) => logado,
+        Err(recusa) => return recusa.into_response(),
+    };
+
+    let mut html = menu(&uid);
+    html.push_str(&formulario_busca(""));
+
+    // O modo somente leitura e as operações, com os botões que o papel do
+    // supervisor permite
+    html.push_str("<h2>Operações</h2><p>");
+    let ativar = match estado.manutencao.somente_leitura() {
+        Some(mensagem) => {
+            let _ = write!(
+                html,
+                "Modo somente leitura <b>ativo</b>: {} ",
+                escapar_html(&mensagem),
+            );
+            false
+        },
+        None => {
+            html.push_str("Modo somente leitura desativado. ");
+            true
+        },
+    };
+    if papel.permite(Acao::MudarManutencao) {
+        html.push_str(&botao(
+            "/painel/somente-leitura",
+            if ativar { "Ativar" } else { "Desativar" },
+            &[("ativar", if ativar { "true" } else { "false" })],
+        ));
+    }
+    html.push_str("</p><p>");
+    if papel.permite(Acao::AplicarPrazos) {
+        html.push_str(&botao("/painel/prazos", "Aplicar os prazos agora", &[]));
+    }
+    if estado.cfg.usuario_novo.caixa_email.is_some()
+        && papel.permite(Acao::CriarCaixas)
+    {
+        html.push(' ');
+        html.push_str(&botao(
+            "/painel/caixas",
+            "Criar as caixas de email pendentes",
+            &[],
+        ));
+    }
+    html.push_str("</p>");
+
+    // As pendências
+    match buscar_pendencias(&estado).await {
+        Ok(p) => {
+            let _ = write!(
+                html,
+                "<h2>Contas em carência ({})</h2>",
+                p.carencia.len(),
+            );
+            tabela_de_contas(&mut html, &p.carencia, papel);
+            let _ = write!(
+                html,
+                "<h2>Contas suspensas ({})</h2>",
+                p.suspensas.len()
+            );
+            tabela_de_contas(&mut html, &p.suspensas, papel);
+            if estado.cfg.usuario_novo.caixa_email.is_some() {
+                let _ = write!(
+                    html,
+                    "<h2>Caixas de email pendentes ({})</h2><ul>",
+                    p.caixas.len(),
+                );
+                for caixa in p.caixas.iter().take(MAXIMO_LINHAS) {
+                    let _ = write!(
+                        html,
+                        "<li>{} ({})</li>",
+                        escapar_html(&caixa.endereco),
+                        caixa.estado.valor(),
+                    );
+                }
+                html.push_str("</ul>");
+            }
+        },
+        Err(e) => {
+            eprintln!("Erro ao buscar as pendências do painel: {e}");
+            let _ = write!(
+                html,
+                "<p><b>Não foi possível buscar as pendências: {}</b></p>",
+                escapar_html(&e.to_string()),
+            );
+        },
+    }
+
+    // As últimas falhas
+    let falhas = estado.estatisticas.lock().unwrap().ultimas_falhas.clone();
+    let _ = write!(html, "<h2>Últimas falhas ({})</h2>", falhas.len());
+    html.push_str(
+        "<table><tr><th>Quando</th><th>Operação</th><th>Conta</th>\
+         <th>Etapa</th><th>Erro</th></tr>",
+    );
+    for falha in &falhas {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             </tr>",
+            falha.quando.format("%d/%m/%Y %H:%M:%S"),
+            escapar_html(&falha.operacao),
+            escapar_html(falha.conta.as_deref().unwrap_or_default()),
+            escapar_html(falha.etapa.as_deref().unwrap_or_default()),
+            escapar_html(&falha.mensagem),
+        );
+    }
+    html.push_str("</table>");
+
+    pagina("Painel administrativo", &html).into_response()
+}
+
+#[derive(Deserialize)]
+struct ParametrosBusca {
+    #[serde(default)]
+    q: String,
+}
+
+/// O filtro LDAP que busca o `termo` no DRE, se ele for um, ou no uid e no
+/// nome.
+fn filtro_de_busca(termo: &str) -> String {
+    if termo.len() == 9 && termo.chars().all(|c| c.is_ascii_digit()) {
+        filtros::igual("dccDRE", termo)
+    } else {
+        filtros::ou([
+            filtros::contem("uid", termo),
+            filtros::contem("gecos", &deunicode(termo)),
+        ])
+    }
+}
+
+async fn busca<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    Query(params): Query<ParametrosBusca>,
+) -> Response {
+    let (uid, papel) = match supervisor(&estado, &headers, Acao::VerPainel) {
+        Ok(logado) => logado,
+        Err(recusa) => return recusa.into_response(),
+    };
+    let termo = params.q.trim();
+
+    let mut html = menu(&uid);
+    html.push_str(&formulario_busca(termo));
+    if termo.is_empty() {
+        return pagina("Busca de alunos", &html).into_response();
+    }
+
+    let contas: Result<_, ErroLdap> = async {
+        let mut conexao = estado.ldap.abrir().await?;
+        let r = buscar_contas(&filtro_de_busca(termo), &mut conexao).await;
+        estado.ldap.fechar(conexao).await?;
+        r
+    }
+    .await;
+
+    match contas {
+        Ok(contas) => {
+            let _ = write!(html, "<p>{} contas encontradas.</p>", contas.len());
+            tabela_de_contas(&mut html, &contas, papel);
+        },
+        Err(e) => {
+            eprintln!("Erro na busca do painel: {e}");
+            let _ = write!(
+                html,
+                "<p><b>Não foi possível buscar: {}</b></p>",
+                escapar_html(&e.to_string()),
+            );
+        },
+    }
+
+    pagina("Busca de alunos", &html).into_response()
+}
+
+/// O supervisor logado, se o papel dele permitir a `acao` e o modo somente
+/// leitura não estiver ativo.
+fn supervisor_para_escrita<F>(
+    estado: &EstadoApi<F>,
+    headers: &HeaderMap,
+    acao: Acao,
+) -> Result<String, Recusa> {
+    let (uid, _) = supervisor(estado, headers, acao)?;
+    match estado.manutencao.somente_leitura() {
+        Some(mensagem) => Err(Recusa::SomenteLeitura { uid, mensagem }),
+        None => Ok(uid),
+    }
+}
+
+#[derive(Deserialize)]
+struct Reativacao {
+    uid: String,
+    motivo: String,
+}
+
+async fn reativar_conta<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    Form(dados): Form<Reativacao>,
+) -> Response {
+    let supervisor =
+        match supervisor_para_escrita(&estado, &headers, Acao::Reativar) {
+            Ok(uid) => uid,
+            Err(recusa) => return recusa.into_response(),
+        };
+
+    // A auditoria guarda quem reativou
+    let motivo = match dados.motivo.trim() {
+        "" => String::new(),
+        motivo => format!("{motivo} (pelo painel, por {supervisor})"),
+    };
+    match reativar(&dados.uid, &motivo, &estado.cfg, &estado.ldap, Utc::now())
+        .await
+    {
+        Ok(renovacao) => resultado(
+            &supervisor,
+            StatusCode::OK,
+            &format!(
+                "A conta {} foi reativada, com renovação até {}.",
+                dados.uid,
+                renovacao.format("%d/%m/%Y"),
+            ),
+        ),
+        Err(e) => {
+            resultado(&supervisor, StatusCode::BAD_REQUEST, &e.to_string())
+        },
+    }
+}
+
+async fn prazos<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+) -> Response {
+    let supervisor =
+        match supervisor_para_escrita(&estado, &headers, Acao::AplicarPrazos) {
+            Ok(uid) => uid,
+            Err(recusa) => return recusa.into_response(),
+        };
+
+    match aplicar_prazos_em(&estado.ldap, &estado.cfg, Utc::now()).await {
+        Ok(t) => resultado(
+            &supervisor,
+            StatusCode::OK,
+            &format!(
+                "{} contas entraram em carência, {} foram suspensas e {} \
+                 foram removidas. {} hooks falharam.",
+                t.em_carencia.len(),
+                t.suspensas.len(),
+                t.removidas.len(),
+                t.falhas.len(),
+            ),
+        ),
+        Err(e) => resultado(
+            &supervisor,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Não foi possível aplicar os prazos: {e}"),
+        ),
+    }
+}
+
+async fn caixas<F: FonteLdap>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+) -> Response {
+    let supervisor =
+        match supervisor_para_escrita(&estado, &headers, Acao::CriarCaixas) {
+            Ok(uid) => uid,
+            Err(recusa) => return recusa.into_response(),
+        };
+    let Some(caixa) = &estado.cfg.usuario_novo.caixa_email else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match provisionar_pendentes(caixa, &estado.ldap).await {
+        Ok(resultados) => {
+            let falhas = resultados.iter().filter(|(_, r)| r.is_err()).count();
+            resultado(
+                &supervisor,
+                StatusCode::OK,
+                &format!(
+                    "{} caixas criadas, {falhas} falharam.",
+                    resultados.len() - falhas,
+                ),
+            )
+        },
+        Err(e) => resultado(
+            &supervisor,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("Não foi possível buscar as caixas pendentes: {e}"),
+        ),
+    }
+}
+
+#[derive(Deserialize)]
+struct MudancaDeModo {
+    ativar: bool,
+}
+
+/// Ativa ou desativa o modo somente leitura, mantendo a mensagem.
+async fn somente_leitura<F>(
+    State(estado): State<Arc<EstadoApi<F>>>,
+    headers: HeaderMap,
+    Form(mudanca): Form<MudancaDeModo>,
+) -> Response {
+    let supervisor = match supervisor(&estado, &headers, Acao::MudarManutencao)
+    {
+        Ok((uid, _)) => uid,
+        Err(recusa) => return recusa.into_response(),
+    };
+
+    let mut modo = estado.manutencao.modo();
+    modo.somente_leitura = mudanca.ativar;
+    estado.manutencao.mudar(modo);
+    println!(
+        "Modo somente leitura {} pelo supervisor {supervisor:?}",
+        if mudanca.ativar {
+            "ativado"
+        } else {
+            "desativado"
+        },
+    );
+
+    Redirect::to("/painel").into_response()
+}
+
+/// As rotas do painel, que compartilham o estado da API.
+pub(crate) fn rotas<F: FonteLdap + 'static>() -> Router<Arc<EstadoApi<F>>> {
+    Router::new()
+        .route("/painel", get(inicio::<F>))
+        .route("/painel/login", get(pagina_login::<F>).post(entrar::<F>))
+        .route("/painel/sair", post(sair::<F>))
+        .route("/painel/busca", get(busca::<F>))
+        .route("/painel/reativar", post(reativar_conta::<F>))
+        .route("/painel/prazos", post(prazos::<F>))
+        .route("/painel/caixas", post(caixas::<F>))
+        .route("/painel/somente-leitura", post(somente_leitura::<F>))
+}
@@ -0,0 +1,384 @@
+//! Confirmação dupla das operações em massa destrutivas, o
+//! `alumnic desligar` e o `alumnic remover`. O comando primeiro grava um
+//! plano, um arquivo JSON com o que acontecerá com cada item da lista, sem
+//! alterar nada. Só depois de o plano ser revisado e passado ao
+//! `--aplicar`, as contas são alteradas.
+//!
+//! Antes de aplicar, o plano é refeito a partir do LDAP, e se alguma conta
+//! tiver mudado desde então nada é alterado, para que seja aplicado
+//! exatamente o que foi revisado. O SHA-256 do arquivo do plano vai para o
+//! motivo de cada operação registrada na auditoria.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::desligamento::{
+    ErroDeDesligamento, ResultadoDesligamento, desligar_em_lote,
+    primeira_coluna,
+};
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{
+    Conta, EstadoConta, buscar_conta_por_dre, buscar_conta_por_uid,
+};
+use crate::remocao::{ErroDeRemocao, remover_agora};
+use crate::utils::validacao_entradas::processar_dre;
+use chrono::{DateTime, Utc};
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Debug, Error)]
+pub enum ErroDePlano {
+    #[error("Não foi possível ler ou gravar o plano: {0}")]
+    Arquivo(#[from] io::Error),
+    #[error("O plano não é válido: {0}")]
+    Invalido(#[from] serde_json::Error),
+    #[error(
+        "O plano {0} já existe; aplique-o ou apague-o antes de gerar outro"
+    )]
+    PlanoExistente(PathBuf),
+    #[error("O plano é de {0:?}, não de {1:?}")]
+    OutraOperacao(Operacao, Operacao),
+    #[error("É preciso informar o motivo da remoção")]
+    SemMotivo,
+    #[error(
+        "A conta de {0} mudou desde que o plano foi gerado; gere outro plano"
+    )]
+    Desatualizado(String),
+    #[error("Houve um problema ao consultar o LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+}
+
+/// A operação em massa de um plano.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operacao {
+    /// O `alumnic desligar`, com uma lista de DREs.
+    Desligar,
+    /// O `alumnic remover`, com uma lista de uids.
+    Remover,
+}
+
+/// O que acontecerá com um item da lista.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Acao {
+    /// A conta será bloqueada e ficará suspensa até a remoção.
+    Bloquear,
+    /// A conta será removida.
+    Remover,
+    /// A conta já está bloqueada, e nada será alterado.
+    JaSuspensa,
+    /// A conta já foi removida, e nada será alterado.
+    JaRemovida,
+    /// Não há conta com o DRE ou o uid.
+    NaoEncontrado,
+    /// O DRE não é válido.
+    Invalido,
+}
+
+impl Acao {
+    /// Se a ação altera a conta.
+    pub fn altera(self) -> bool {
+        matches!(self, Acao::Bloquear | Acao::Remover)
+    }
+}
+
+/// Um item da lista e o que acontecerá com a conta dele.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemPlano {
+    /// O DRE ou o uid, como está na lista.
+    pub alvo: String,
+    pub uid: Option<String>,
+    pub nome: Option<String>,
+    /// O `estadoConta` da conta quando o plano foi gerado.
+    pub estado: Option<String>,
+    pub acao: Acao,
+}
+
+impl ItemPlano {
+    fn da_conta(alvo: &str, conta: &Conta, acao: Acao) -> ItemPlano {
+        ItemPlano {
+            alvo: alvo.to_string(),
+            uid: Some(conta.uid.clone()),
+            nome: Some(conta.nome.clone()),
+            estado: Some(conta.estado.valor().to_string()),
+            acao,
+        }
+    }
+
+    fn sem_conta(alvo: &str, acao: Acao) -> ItemPlano {
+        ItemPlano {
+            alvo: alvo.to_string(),
+            uid: None,
+            nome: None,
+            estado: None,
+            acao,
+        }
+    }
+}
+
+/// Um plano de operação em massa, como gravado no arquivo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plano {
+    pub operacao: Operacao,
+    pub gerado: DateTime<Utc>,
+    /// O motivo da remoção, guardado na auditoria.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motivo: Option<String>,
+    pub itens: Vec<ItemPlano>,
+}
+
+impl Plano {
+    /// Quantos itens alteram alguma conta.
+    pub fn alteracoes(&self) -> usize {
+        self.itens.iter().filter(|i| i.acao.altera()).count()
+    }
+
+    /// Os alvos da lista original, na ordem.
+    fn lista(&self) -> String {
+        self.itens
+            .iter()
+            .map(|i| i.alvo.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Os alvos dos itens que alteram alguma conta.
+    fn alterados(&self) -> impl Iterator<Item = &str> {
+        self.itens
+            .iter()
+            .filter(|i| i.acao.altera())
+            .map(|i| i.alvo.as_str())
+    }
+
+    /// Grava o plano em `caminho`, só para o dono, e retorna o hash dele.
+    ///
+    /// # Errors
+    ///
+    /// Retorna [ErroDePlano::PlanoExistente] se já houver um arquivo em
+    /// `caminho`, como um plano ainda não aplicado, que não é sobrescrito.
+    pub fn gravar(&self, caminho: &Path) -> Result<String, ErroDePlano> {
+        let texto = serde_json::to_string_pretty(self)?;
+        let mut arquivo = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(caminho)
+        {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(ErroDePlano::PlanoExistente(caminho.to_path_buf()));
+            },
+            r => r?,
+        };
+        arquivo.write_all(texto.as_bytes())?;
+        Ok(hash(&texto))
+    }
+
+    /// Lê o plano gravado em `caminho`, junto com o hash dele.
+    pub fn ler(caminho: &Path) -> Result<(Plano, String), ErroDePlano> {
+        let texto = std::fs::read_to_string(caminho)?;
+        Ok((serde_json::from_str(&texto)?, hash(&texto)))
+    }
+
+    /// Confere que o plano é da `operacao` e que refazê-lo agora dá os
+    /// mesmos itens.
+    async fn conferir<F: FonteLdap>(
+        &self,
+        operacao: Operacao,
+        ldap: &F,
+    ) -> Result<(), ErroDePlano> {
+        if self.operacao != operacao {
+            return Err(ErroDePlano::OutraOperacao(self.operacao, operacao));
+        }
+        let agora = match operacao {
+            Operacao::Desligar => {
+                planejar_desligamento(&self.lista(), ldap, self.gerado).await?
+            },
+            Operacao::Remover => {
+                let motivo = self.motivo.as_deref().unwrap_or_default();
+                planejar_remocao(&self.lista(), motivo, ldap, self.gerado)
+                    .await?
+            },
+        };
+        if agora.itens.len() != self.itens.len() {
+            return Err(ErroDePlano::Desatualizado("a lista".into()));
+        }
+        match agora.itens.iter().zip(&self.itens).find(|(a, p)| a != p) {
+            Some((_, p)) => Err(ErroDePlano::Desatualizado(p.alvo.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// O SHA-256 do `texto`, em hexadecimal.
+fn hash(texto: &str) -> String {
+    hex::encode(digest(&SHA256, texto.as_bytes()))
+}
+
+/// Gera o plano do desligamento dos DREs da `lista`, no formato do
+/// [`desligar_em_lote`], sem alterar nada.
+pub async fn planejar_desligamento<F: FonteLdap>(
+    lista: &str,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<Plano, ErroLdap> {
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        let mut itens = vec![];
+        for alvo in primeira_coluna(lista, "dre") {
+            let Some(dre) = processar_dre(alvo) else {
+                itens.push(ItemPlano::sem_conta(alvo, Acao::Invalido));
+                continue;
+            };
+            let item = match buscar_conta_por_dre(&dre, &mut conexao).await? {
+                None => ItemPlano::sem_conta(alvo, Acao::NaoEncontrado),
+                Some(c) if c.estado.bloqueada() => {
+                    ItemPlano::da_conta(alvo, &c, Acao::JaSuspensa)
+                },
+                Some(c) => ItemPlano::da_conta(alvo, &c, Acao::Bloquear),
+            };
+            itens.push(item);
+        }
+        Ok::<_, ErroLdap>(itens)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+
+    Ok(Plano {
+        operacao: Operacao::Desligar,
+        gerado: agora,
+        motivo: None,
+        itens: r?,
+    })
+}
+
+/// Gera o plano da remoção das contas com os uids da `lista`, um por linha
+/// na primeira coluna de um CSV, sem alterar nada. O `motivo` vai para a
+/// auditoria de cada conta removida.
+pub async fn planejar_remocao<F: FonteLdap>(
+    lista: &str,
+    motivo: &str,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<Plano, ErroDePlano> {
+    if motivo.trim().is_empty() {
+        return Err(ErroDePlano::SemMotivo);
+    }
+
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        let mut itens = vec![];
+        for uid in primeira_coluna(lista, "uid") {
+            let item = match buscar_conta_por_uid(uid, &mut conexao).await? {
+                None => ItemPlano::sem_conta(uid, Acao::NaoEncontrado),
+                Some(c) if c.estado == EstadoConta::Removida => {
+                    ItemPlano::da_conta(uid, &c, Acao::JaRemovida)
+                },
+                Some(c) => ItemPlano::da_conta(uid, &c, Acao::Remover),
+            };
+            itens.push(item);
+        }
+        Ok::<_, ErroLdap>(itens)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+
+    Ok(Plano {
+        operacao: Operacao::Remover,
+        gerado: agora,
+        motivo: Some(motivo.trim().to_string()),
+        itens: r?,
+    })
+}
+
+/// O que aconteceu ao aplicar um plano de desligamento.
+#[derive(Debug)]
+pub struct DesligamentoAplicado {
+    /// O resultado de cada DRE que o plano bloqueia, na ordem do plano.
+    pub resultados:
+        Vec<(String, Result<ResultadoDesligamento, ErroDeDesligamento>)>,
+    /// Os uids das contas bloqueadas que não foram registradas na
+    /// auditoria. O bloqueio delas não é desfeito.
+    pub sem_auditoria: Vec<(String, io::Error)>,
+}
+
+/// Aplica o `plano` de desligamento, com o `hash` do arquivo dele, se o
+/// LDAP não tiver mudado desde que ele foi gerado. Só os DREs que o plano
+/// bloqueia são desligados, e cada conta bloqueada é registrada na
+/// auditoria com o hash do plano. Uma falha na auditoria não interrompe os
+/// registros das outras contas.
+pub async fn aplicar_desligamento<F: FonteLdap>(
+    plano: &Plano,
+    hash: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+    cancelamento: &CancellationToken,
+) -> Result<DesligamentoAplicado, ErroDePlano> {
+    plano.conferir(Operacao::Desligar, ldap).await?;
+
+    let lista = plano.alterados().collect::<Vec<_>>().join("\n");
+    let resultados =
+        desligar_em_lote(&lista, cfg, ldap, agora, cancelamento).await?;
+
+    let motivo = format!("plano {hash}");
+    let mut sem_auditoria = vec![];
+    for (_, r) in &resultados {
+        if let Ok(ResultadoDesligamento::Desligado { uid, .. }) = r {
+            let registro = Registro {
+                quando: agora,
+                operacao: "desligar",
+                uid,
+                motivo: &motivo,
+            };
+            if let Err(e) = registrar(&cfg.auditoria, &registro).await {
+                sem_auditoria.push((uid.clone(), e));
+            }
+        }
+    }
+
+    Ok(DesligamentoAplicado {
+        resultados,
+        sem_auditoria,
+    })
+}
+
+/// Aplica o `plano` de remoção, com o `hash` do arquivo dele, se o LDAP não
+/// tiver mudado desde que ele foi gerado. Um plano sem o motivo falha com
+/// [`ErroDePlano::SemMotivo`], sem remover nada. Cada conta é removida como no
+/// [`remover_agora`], com o motivo do plano e o hash dele na auditoria.
+/// Depois de o `cancelamento` ser cancelado, as contas que faltam não são
+/// removidas e não aparecem no resultado.
+pub async fn aplicar_remocao<F: FonteLdap>(
+    plano: &Plano,
+    hash: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+    cancelamento: &CancellationToken,
+) -> Result<Vec<(String, Result<Conta, ErroDeRemocao>)>, ErroDePlano> {
+    let motivo = plano
+        .motivo
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .ok_or(ErroDePlano::SemMotivo)?;
+    plano.conferir(Operacao::Remover, ldap).await?;
+
+    let motivo = format!("{motivo} (plano {hash})");
+    let mut resultados = vec![];
+    for uid in plano.alterados() {
+        if cancelamento.is_cancelled() {
+            break;
+        }
+        let r = remover_agora(uid, &motivo, cfg, ldap, agora).await;
+        resultados.push((uid.to_string(), r));
+    }
+
+    Ok(resultados)
+}
@@ -6,15 +6,24 @@
 use chrono::Local;
 use reqwest::ClientBuilder;
 use select::document::Document;
+use select::node::Node;
 use select::predicate::{Attr, Class};
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
+use crate::metricas;
 use crate::utils::nome::Nome;
 
-const GET_URL: &str =
-    "https://gnosys.ufrj.br/Documentos/autenticacao/regularmenteMatriculado";
-const POST_URL: &str = "https://gnosys.ufrj.br/Documentos/autenticacao.seam";
+/// Endereço do Gnosys de produção.
+pub const GNOSYS_URL: &str = "https://gnosys.ufrj.br";
+const GET_CAMINHO: &str = "/Documentos/autenticacao/regularmenteMatriculado";
+const POST_CAMINHO: &str = "/Documentos/autenticacao.seam";
 
 /// Representa um erro no processo de consulta.
 #[derive(Debug, Error)]
@@ -42,10 +51,23 @@ pub enum ConsultaErro {
     )]
     NumeroEstranhoDeItens,
 
+    /// Na [leitura tolerante](LeituraResposta::Tolerante), a resposta tem
+    /// rótulos, mas não o do campo, que pode ter mudado de nome.
+    #[error("a resposta não tem o campo {0}, pode ser uma mudança do gnosys")]
+    SemCampo(&'static str),
+
     /// O nome retornado pelo sistema não foi considerado um nome válido pelo
     /// módulo responsável pela criação de nomes
     #[error("nome inválido retornado pelo gnosys: {0:?}")]
     NomeInvalido(String),
+
+    /// A consulta esperou mais que o limite por uma vaga na
+    /// [fila](limitar_consultas) das consultas ao Gnosys.
+    #[error(
+        "o gnosys está com muitas consultas; a espera passou de {}s",
+        .0.as_secs()
+    )]
+    EsperaEsgotada(Duration),
 }
 
 /// Representa o resultado de uma consulta bem-sucedida.
@@ -63,6 +85,116 @@ pub enum Consulta {
     Desconhecido,
 }
 
+/// As versões conhecidas do formato da resposta do Gnosys a um documento
+/// válido.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContratoGnosys {
+    /// Exatamente três itens, na ordem Nome, RG e Curso.
+    V1 = 1,
+    /// Os itens identificados pelos rótulos Nome e Curso, em qualquer ordem
+    /// e com outros itens no meio.
+    V2 = 2,
+}
+
+impl fmt::Display for ContratoGnosys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", *self as u8)
+    }
+}
+
+/// Como a resposta do Gnosys é lida.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeituraResposta {
+    /// Só aceita o [ContratoGnosys::V1].
+    #[default]
+    Estrita,
+    /// Procura os itens pelos rótulos ([ContratoGnosys::V2]) e, se a
+    /// resposta não tem rótulos, aceita o [ContratoGnosys::V1]. Sobrevive a
+    /// reordenações e campos novos na página do SIGA.
+    Tolerante,
+}
+
+static LEITURA: AtomicU8 = AtomicU8::new(LeituraResposta::Estrita as u8);
+
+/// A versão do contrato da última resposta lida, ou 0 antes da primeira.
+static ULTIMO_CONTRATO: AtomicU8 = AtomicU8::new(0);
+
+/// Define como as respostas do Gnosys são lidas em todo o processo.
+pub fn usar_leitura(leitura: LeituraResposta) {
+    LEITURA.store(leitura as u8, Ordering::Relaxed);
+}
+
+fn leitura() -> LeituraResposta {
+    match LEITURA.load(Ordering::Relaxed) {
+        x if x == LeituraResposta::Tolerante as u8 => {
+            LeituraResposta::Tolerante
+        },
+        _ => LeituraResposta::Estrita,
+    }
+}
+
+/// O limite das consultas simultâneas ao Gnosys, compartilhado por todo o
+/// processo.
+struct FilaGnosys {
+    vagas: Arc<Semaphore>,
+    limite: usize,
+    espera: Duration,
+}
+
+static FILA: Mutex<Option<Arc<FilaGnosys>>> = Mutex::new(None);
+
+/// Limita a `limite` as consultas simultâneas ao Gnosys, para não derrubar o
+/// SIGA nem ser bloqueado por ele. As consultas além do limite esperam numa
+/// fila, na ordem de chegada, por até `espera`. Sem essa chamada, as
+/// consultas não têm limite.
+pub fn limitar_consultas(limite: usize, espera: Duration) {
+    let mut fila = FILA.lock().unwrap();
+    if fila
+        .as_ref()
+        .is_some_and(|f| f.limite == limite && f.espera == espera)
+    {
+        return;
+    }
+    *fila = Some(Arc::new(FilaGnosys {
+        vagas: Arc::new(Semaphore::new(limite.max(1))),
+        limite,
+        espera,
+    }));
+}
+
+/// Uma vaga para consultar o Gnosys, devolvida quando a consulta termina.
+struct Vaga {
+    _permissao: OwnedSemaphorePermit,
+}
+
+impl Drop for Vaga {
+    fn drop(&mut self) {
+        metricas::registrar_fim_consulta_gnosys();
+    }
+}
+
+/// Espera uma vaga na fila das consultas, se ela foi configurada.
+async fn esperar_vaga() -> Result<Option<Vaga>, ConsultaErro> {
+    let Some(fila) = FILA.lock().unwrap().clone() else {
+        return Ok(None);
+    };
+
+    let inicio = Instant::now();
+    metricas::registrar_entrada_fila_gnosys();
+    let vaga =
+        tokio::time::timeout(fila.espera, fila.vagas.clone().acquire_owned())
+            .await;
+    metricas::registrar_saida_fila_gnosys(inicio.elapsed(), vaga.is_ok());
+
+    match vaga {
+        Ok(vaga) => Ok(Some(Vaga {
+            _permissao: vaga.expect("o semáforo da fila nunca é fechado"),
+        })),
+        Err(_) => Err(ConsultaErro::EsperaEsgotada(fila.espera)),
+    }
+}
+
 /// Realiza uma consulta no sistema Gnosys para validar um documento de
 /// regularmente matriculado com as informações necessárias:
 ///
@@ -81,16 +213,35 @@ pub async fn consulta(
     hora: &str,
     codigo: &str,
 ) -> Result<Consulta, ConsultaErro> {
+    consulta_em(GNOSYS_URL, dre, data, hora, codigo).await
+}
+
+/// Faz o mesmo que [consulta], mas no Gnosys hospedado em `url_base`. Útil
+/// para testes com um Gnosys falso.
+///
+/// # Errors
+///
+/// Além dos erros da [consulta], [ConsultaErro::EsperaEsgotada] se a
+/// consulta não conseguiu uma vaga na [fila](limitar_consultas) a tempo.
+pub async fn consulta_em(
+    url_base: &str,
+    dre: &str,
+    data: &str,
+    hora: &str,
+    codigo: &str,
+) -> Result<Consulta, ConsultaErro> {
+    let _vaga = esperar_vaga().await?;
+
     let client = ClientBuilder::new().cookie_store(true).build()?;
 
-    let res_form = client.get(GET_URL).send().await?.text().await?;
+    let res_form = client
+        .get(format!("{url_base}{GET_CAMINHO}"))
+        .send()
+        .await?
+        .text()
+        .await?;
 
-    let view_state = Document::from(res_form.as_str())
-        .find(Attr("name", "javax.faces.ViewState"))
-        .next()
-        .and_then(|v| v.attr("value"))
-        .ok_or(ConsultaErro::SemViewState)?
-        .to_string();
+    let view_state = extrair_view_state(&res_form)?;
 
     let mes_hoje = Local::now().format("%m/%Y").to_string();
 
@@ -110,14 +261,87 @@ pub async fn consulta(
     form.insert("", "");
 
     let res = client
-        .post(POST_URL)
+        .post(format!("{url_base}{POST_CAMINHO}"))
         .form(&form)
         .send()
         .await?
         .text()
         .await?;
 
-    let res_doc = Document::from(res.as_str());
+    interpretar_resposta(&res)
+}
+
+/// Extrai o `javax.faces.ViewState` do formulário de autenticação do Gnosys.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::portal_ufrj::extrair_view_state;
+/// let html = r#"<input name="javax.faces.ViewState" value="j_id7" />"#;
+/// assert_eq!(extrair_view_state(html).unwrap(), "j_id7");
+/// assert!(extrair_view_state("<html></html>").is_err());
+/// ```
+pub fn extrair_view_state(html: &str) -> Result<String, ConsultaErro> {
+    Ok(Document::from(html)
+        .find(Attr("name", "javax.faces.ViewState"))
+        .next()
+        .and_then(|v| v.attr("value"))
+        .ok_or(ConsultaErro::SemViewState)?
+        .to_string())
+}
+
+/// Interpreta o HTML devolvido pelo Gnosys depois do envio do formulário de
+/// autenticação, com a [leitura](usar_leitura) configurada. A versão do
+/// contrato da resposta vai para o log na primeira resposta e sempre que
+/// muda.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::portal_ufrj::{Consulta, interpretar_resposta};
+/// let html = r#"<span id="msgDocumentoInvalido">Documento inválido</span>"#;
+/// assert!(matches!(interpretar_resposta(html), Ok(Consulta::Desconhecido)));
+/// assert!(interpretar_resposta("<html></html>").is_err());
+/// ```
+pub fn interpretar_resposta(html: &str) -> Result<Consulta, ConsultaErro> {
+    let (consulta, contrato) = ler_resposta(html, leitura())?;
+    if let Some(contrato) = contrato {
+        let anterior = ULTIMO_CONTRATO.swap(contrato as u8, Ordering::Relaxed);
+        if anterior != contrato as u8 {
+            eprintln!("Resposta do Gnosys lida com o contrato {contrato}");
+        }
+    }
+    Ok(consulta)
+}
+
+/// Faz o mesmo que [interpretar_resposta] com a `leitura` dada, devolvendo
+/// também a versão do contrato da resposta de um documento válido.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::portal_ufrj::*;
+/// let html = concat!(
+///     r#"<span id="msgDocumentoValido">Documento válido</span>"#,
+///     r#"<div><label>Curso:</label>"#,
+///     r#"<div class="gnosys-item-visualizacao">Matemática</div></div>"#,
+///     r#"<div><label>Nome:</label>"#,
+///     r#"<div class="gnosys-item-visualizacao">JOSE LIMA</div></div>"#,
+/// );
+/// let (consulta, contrato) =
+///     ler_resposta(html, LeituraResposta::Tolerante).unwrap();
+/// assert!(matches!(
+///     consulta,
+///     Consulta::AlunoOutroCurso { curso, .. } if curso == "Matemática",
+/// ));
+/// assert_eq!(contrato, Some(ContratoGnosys::V2));
+/// assert!(ler_resposta(html, LeituraResposta::Estrita).is_err());
+/// ```
+pub fn ler_resposta(
+    html: &str,
+    leitura: LeituraResposta,
+) -> Result<(Consulta, Option<ContratoGnosys>), ConsultaErro> {
+    let res_doc = Document::from(html);
 
     let valido = res_doc
         .find(Attr("id", "msgDocumentoValido"))
@@ -134,28 +358,70 @@ pub async fn consulta(
     }
 
     if invalido {
-        return Ok(Consulta::Desconhecido);
+        return Ok((Consulta::Desconhecido, None));
     }
 
-    let consulta: Vec<_> = res_doc
-        .find(Class("gnosys-item-visualizacao"))
-        .map(|x| x.text())
-        .collect();
+    let itens: Vec<_> =
+        res_doc.find(Class("gnosys-item-visualizacao")).collect();
 
-    if consulta.len() != 3 {
+    // O rótulo inteiro, para um "Nome social" ou "Curso anterior" antes do
+    // campo não ser lido no lugar dele
+    let campo = |campo: &'static str| {
+        itens
+            .iter()
+            .find(|i| rotulo(i).is_some_and(|r| r == campo))
+            .map(|i| i.text().trim().to_string())
+    };
+
+    // Sem os rótulos, a leitura tolerante cai para a versão 1
+    let rotulados = leitura == LeituraResposta::Tolerante
+        && (campo("nome").is_some() || campo("curso").is_some());
+    let (nome, curso, contrato) = if rotulados {
+        (
+            campo("nome").ok_or(ConsultaErro::SemCampo("nome"))?,
+            campo("curso").ok_or(ConsultaErro::SemCampo("curso"))?,
+            ContratoGnosys::V2,
+        )
+    } else if itens.len() == 3 {
+        (itens[0].text(), itens[2].text(), ContratoGnosys::V1)
+    } else {
         return Err(ConsultaErro::NumeroEstranhoDeItens);
-    }
+    };
+
+    let consulta = match nome.parse() {
+        Err(_) => return Err(ConsultaErro::NomeInvalido(nome)),
+        Ok(nome) if curso == "Ciência da Computação" => {
+            Consulta::AlunoBCC { nome }
+        },
+        Ok(nome) if curso == "Ensino de Computação" => {
+            Consulta::AlunoProfComp { nome }
+        },
+        Ok(_) => Consulta::AlunoOutroCurso { nome, curso },
+    };
+    Ok((consulta, Some(contrato)))
+}
 
-    let nome: Nome = consulta[0]
-        .parse()
-        .map_err(|_| ConsultaErro::NomeInvalido(consulta[0].clone()))?;
-
-    match consulta[2].as_str() {
-        "Ciência da Computação" => Ok(Consulta::AlunoBCC { nome }),
-        "Ensino de Computação" => Ok(Consulta::AlunoProfComp { nome }),
-        _ => Ok(Consulta::AlunoOutroCurso {
-            nome: consulta[0].clone(),
-            curso: consulta[2].clone(),
-        }),
+/// O rótulo de um item da resposta, sem acentos, em minúsculas e sem os dois
+/// pontos: o texto do elemento anterior a ele ou, se ele é o primeiro, o do
+/// elemento anterior ao pai dele. Um item logo depois do outro não tem
+/// rótulo.
+fn rotulo(item: &Node) -> Option<String> {
+    let mut no = *item;
+    for _ in 0..2 {
+        let anterior = std::iter::successors(no.prev(), Node::prev)
+            .find(|n| !n.text().trim().is_empty());
+        if let Some(anterior) = anterior {
+            if anterior.is(Class("gnosys-item-visualizacao")) {
+                return None;
+            }
+            let rotulo = deunicode::deunicode(anterior.text().trim())
+                .to_lowercase()
+                .trim_end_matches(':')
+                .trim()
+                .to_string();
+            return Some(rotulo);
+        }
+        no = no.parent()?;
     }
+    None
 }
@@ -0,0 +1,95 @@
+//! Módulo com as mudanças de estado das contas feitas quando os prazos
+//! vencem. Em vez de apagar a entrada de uma vez, a conta passa por estados
+//! intermediários, guardados no atributo `estadoConta`:
+//!
+//! - ativa → carência, quando a `dataRenovacao` passa;
+//! - carência → suspensa, quando a carência acaba sem renovação;
+//! - suspensa → removida, quando a `dataRemocao` chega.
+use crate::configuracao::Configuracao;
+use crate::hooks::{ErroHook, Evento, TipoEvento, disparar_evento};
+use crate::ldap::ErroLdap;
+use crate::ldap::bloqueio::{
+    contas_a_remover, marcar_removida, suspender_carencias_vencidas,
+};
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::Conta;
+use crate::ldap::renovar::aplicar_carencia;
+use crate::ldap::uids_liberados::liberar_uid;
+use crate::renovacao::dia;
+use chrono::{DateTime, Utc};
+
+/// As contas que mudaram de estado em uma aplicação dos prazos.
+#[derive(Debug, Default)]
+pub struct Transicoes {
+    pub em_carencia: Vec<Conta>,
+    pub suspensas: Vec<Conta>,
+    pub removidas: Vec<Conta>,
+    /// As contas cujos hooks falharam. As que deveriam ser removidas
+    /// continuam suspensas.
+    pub falhas: Vec<(Conta, ErroHook)>,
+}
+
+/// Aplica os prazos vencidos até `agora` às contas do LDAP fornecido por
+/// `ldap`. Cada conta anda no máximo um estado por execução.
+///
+/// Os hooks rodam com a conexão com o LDAP fechada, já que arquivar um home
+/// pode demorar.
+pub async fn aplicar_prazos_em<F: FonteLdap>(
+    ldap: &F,
+    cfg: &Configuracao,
+    agora: DateTime<Utc>,
+) -> Result<Transicoes, ErroLdap> {
+    let hoje = dia(agora);
+    let remocao = hoje + cfg.desligamento.dias_ate_remocao;
+    let mut t = Transicoes::default();
+
+    let mut conexao = ldap.abrir().await?;
+    let a_remover = contas_a_remover(hoje, &mut conexao).await;
+    ldap.fechar(conexao).await?;
+
+    // Os hooks de remoção rodam antes, para arquivar o que for preciso
+    let mut arquivadas = vec![];
+    for conta in a_remover? {
+        let evento = Evento::da_conta(TipoEvento::Remocao, &conta);
+        match disparar_evento(&cfg.hooks, &cfg.auditoria, &evento).await {
+            Ok(()) => arquivadas.push(conta),
+            Err(e) => t.falhas.push((conta, e)),
+        }
+    }
+
+    let mut conexao = ldap.abrir().await?;
+    let r: Result<(), ErroLdap> = async {
+        // A ordem inversa evita que uma conta ande mais de um estado
+        for mut conta in arquivadas {
+            marcar_removida(&mut conta, &mut conexao).await?;
+            if cfg.usuario_novo.reutilizacao_uids.is_some() {
+                liberar_uid(&conta.dn, hoje, &mut conexao).await?;
+            }
+            t.removidas.push(conta);
+        }
+        t.suspensas = suspender_carencias_vencidas(
+            hoje,
+            cfg.renovacao.carencia_dias,
+            remocao,
+            &mut conexao,
+        )
+        .await?;
+        t.em_carencia = aplicar_carencia(hoje, &mut conexao).await?;
+
+        Ok(())
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    r?;
+
+    for conta in &t.suspensas {
+        let evento = Evento::da_conta(TipoEvento::Suspensao, conta);
+        if let Err(e) =
+            disparar_evento(&cfg.hooks, &cfg.auditoria, &evento).await
+        {
+            t.falhas.push((conta.clone(), e));
+        }
+    }
+
+    Ok(t)
+}
@@ -0,0 +1,307 @@
+//! Módulo com o cadastro das contas temporárias de projetos de extensão e de
+//! visitantes, feito pela supervisão. Ao contrário do cadastro de alunos, não
+//! há documento do SIGA: a conta vale até uma data de expiração e fica sob a
+//! responsabilidade de um docente, que é avisado antes de ela expirar. Na
+//! expiração, o [agendador](crate::agendador) bloqueia a conta.
+use crate::auditoria::{Registro, registrar};
+use crate::cadastro_aluno::SECAO_CRITICA;
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::consulta::UidsExistentes;
+use crate::ldap::projeto::{
+    ContaDeProjeto, bloquear_projeto, cadastrar_projeto_em, contas_de_projeto,
+    docente_existe, email_do_docente,
+};
+use crate::notificacao::{ErroNotificacao, Mensagem, enviar};
+use crate::renovacao::{dia, dia_para_data};
+use crate::utils::nome::Nome;
+use crate::utils::validacao_entradas::{processar_email, validar_senha};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use secrecy::SecretString;
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+/// Os dados de uma conta de projeto.
+#[derive(Debug)]
+pub struct DadosDoProjeto {
+    /// O nome completo de quem usa a conta, de onde sai o username.
+    pub nome: String,
+    /// O email de contato. Precisa ser um email válido.
+    pub email: String,
+    /// O nome do projeto, ou o motivo da visita.
+    pub projeto: String,
+    /// O uid do docente responsável pela conta.
+    pub responsavel: String,
+    /// O último dia em que a conta funciona.
+    pub expiracao: NaiveDate,
+    /// A senha, com as mesmas regras das contas de alunos.
+    pub senha: SecretString,
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeProjeto {
+    #[error("O nome {0:?} não é válido")]
+    NomeInvalido(String),
+    #[error("O email {0:?} não é válido")]
+    EmailInvalido(String),
+    #[error("O nome do projeto não pode ser vazio")]
+    SemProjeto,
+    #[error(
+        "A senha precisa ter entre 8 e 25 caracteres, uma letra minúscula, uma maiúscula e um dígito"
+    )]
+    SenhaInvalida,
+    #[error("A expiração {0} já passou")]
+    ExpiracaoPassada(NaiveDate),
+    #[error("A expiração {0} passa do limite de {1} dias")]
+    ExpiracaoLonga(NaiveDate, i64),
+    #[error("Não existe o docente {0:?}")]
+    ResponsavelInexistente(String),
+    #[error("Houve um problema ao cadastrar a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A conta foi criada, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+impl DadosDoProjeto {
+    /// Valida os dados, confere o docente responsável e cria a conta,
+    /// retornando o username e o DN. O cadastro é guardado na auditoria.
+    pub async fn cadastrar<F: FonteLdap>(
+        mut self,
+        cfg: &Configuracao,
+        ldap: &F,
+        agora: DateTime<Utc>,
+    ) -> Result<(String, String), ErroDeProjeto> {
+        let projetos = &cfg.projetos;
+
+        if self.nome.parse::<Nome>().is_err() {
+            return Err(ErroDeProjeto::NomeInvalido(self.nome));
+        }
+        self.email = processar_email(&self.email)
+            .ok_or_else(move || ErroDeProjeto::EmailInvalido(self.email))?;
+        self.projeto = self.projeto.trim().to_string();
+        if self.projeto.is_empty() {
+            return Err(ErroDeProjeto::SemProjeto);
+        }
+        validar_senha(&self.senha)
+            .then_some(())
+            .ok_or(ErroDeProjeto::SenhaInvalida)?;
+
+        let hoje = agora.date_naive();
+        if self.expiracao < hoje {
+            return Err(ErroDeProjeto::ExpiracaoPassada(self.expiracao));
+        }
+        let limite = hoje + Days::new(projetos.validade_maxima as u64);
+        if self.expiracao > limite {
+            return Err(ErroDeProjeto::ExpiracaoLonga(
+                self.expiracao,
+                projetos.validade_maxima,
+            ));
+        }
+
+        let mut conexao = ldap.abrir().await?;
+        let r = async {
+            if !docente_existe(
+                &self.responsavel,
+                &projetos.base_docentes,
+                &mut conexao,
+            )
+            .await?
+            {
+                return Err(ErroDeProjeto::ResponsavelInexistente(
+                    self.responsavel.clone(),
+                ));
+            }
+
+            let confundiveis = cfg.usuario_novo.usernames_confundiveis.as_ref();
+            let _secao = SECAO_CRITICA.lock().await;
+            let mut uids = match confundiveis {
+                Some(_) => UidsExistentes::carregar(&mut conexao).await?,
+                None => UidsExistentes::default(),
+            };
+            let username = uids
+                .achar_nome_livre(&self.nome, confundiveis, &mut conexao)
+                .await?;
+            let dn = cadastrar_projeto_em(
+                &username,
+                &self,
+                (&cfg.usuario_novo, projetos, &cfg.renovacao),
+                agora,
+                &mut conexao,
+            )
+            .await?;
+            Ok((username, dn))
+        }
+        .await;
+        ldap.fechar(conexao).await?;
+        let (username, dn) = r?;
+
+        registrar(
+            &cfg.auditoria,
+            &Registro {
+                quando: agora,
+                operacao: "cadastro_projeto",
+                uid: &username,
+                motivo: &format!(
+                    "{}, responsável {}, até {}",
+                    self.projeto,
+                    self.responsavel,
+                    self.expiracao.format("%d/%m/%Y"),
+                ),
+            },
+        )
+        .await?;
+
+        Ok((username, dn))
+    }
+}
+
+/// O arquivo, no diretório dos dados, com os avisos de expiração já
+/// enviados: o `shadowExpire` avisado de cada conta, para que o aviso saia
+/// uma vez só e saia de novo se a expiração for estendida.
+const AVISOS: &str = "avisos_projetos.json";
+
+/// O que mudou em uma [expiração das contas de projeto](expirar_projetos).
+#[derive(Debug, Default)]
+pub struct Expiracoes {
+    /// As contas que expiraram e foram bloqueadas.
+    pub bloqueadas: Vec<ContaDeProjeto>,
+    /// As contas cujo responsável foi avisado da expiração próxima.
+    pub avisadas: Vec<ContaDeProjeto>,
+    /// Os avisos que não puderam ser enviados, tentados de novo na próxima
+    /// execução.
+    pub falhas: Vec<(ContaDeProjeto, ErroNotificacao)>,
+}
+
+/// O aviso ao docente, no email `para`, de que a `conta` de projeto sob a
+/// responsabilidade dele vai expirar.
+fn aviso(conta: &ContaDeProjeto, para: &str) -> Mensagem {
+    let ultimo_dia = dia_para_data(conta.expiracao - 1);
+    Mensagem {
+        para: para.to_string(),
+        assunto: format!("A conta {} vai expirar", conta.uid),
+        corpo: format!(
+            "A conta {} ({}), do projeto \"{}\", que está sob a sua \
+             responsabilidade, funciona até {} e depois será bloqueada. Para \
+             estender o prazo, procure a supervisão.",
+            conta.uid,
+            conta.nome,
+            conta.projeto,
+            ultimo_dia.format("%d/%m/%Y"),
+        ),
+    }
+}
+
+/// Lê os avisos já enviados. Um arquivo que não pode ser lido vale como
+/// vazio, com o erro no log, e no pior caso os avisos saem de novo.
+fn ler_avisos(caminho: &Path) -> BTreeMap<String, i64> {
+    match std::fs::read(caminho) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            eprintln!("Os avisos de expiração estão corrompidos: {e}");
+            BTreeMap::new()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            eprintln!("Não foi possível ler os avisos de expiração: {e}");
+            BTreeMap::new()
+        },
+    }
+}
+
+/// Bloqueia as contas de projeto cuja expiração chegou em `agora` e avisa o
+/// docente responsável pelas que expiram nos próximos `aviso_dias`. O
+/// bloqueio é guardado na auditoria.
+///
+/// Os avisos saem com a conexão com o LDAP fechada, e um aviso que falha é
+/// tentado de novo na próxima execução.
+pub async fn expirar_projetos<F: FonteLdap>(
+    ldap: &F,
+    cfg: &Configuracao,
+    agora: DateTime<Utc>,
+) -> Result<Expiracoes, ErroLdap> {
+    let projetos = &cfg.projetos;
+    let hoje = dia(agora);
+    let caminho = cfg.dados.join(AVISOS);
+    let avisos = ler_avisos(&caminho);
+    let mut t = Expiracoes::default();
+
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        let mut a_avisar = vec![];
+        for conta in contas_de_projeto(&projetos.ou, &mut conexao).await? {
+            if conta.bloqueada {
+                continue;
+            }
+            if conta.expiracao <= hoje {
+                bloquear_projeto(&conta, &mut conexao).await?;
+                t.bloqueadas.push(conta);
+            } else if conta.expiracao - hoje <= projetos.aviso_dias
+                && avisos.get(&conta.uid) != Some(&conta.expiracao)
+            {
+                let email = email_do_docente(
+                    &conta.responsavel,
+                    &projetos.base_docentes,
+                    &mut conexao,
+                )
+                .await?;
+                a_avisar.push((conta, email));
+            }
+        }
+        Ok::<_, ErroLdap>(a_avisar)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    let a_avisar = r?;
+
+    for conta in &t.bloqueadas {
+        let registro = Registro {
+            quando: agora,
+            operacao: "expiracao_projeto",
+            uid: &conta.uid,
+            motivo: &format!(
+                "{}, responsável {}",
+                conta.projeto, conta.responsavel,
+            ),
+        };
+        if let Err(e) = registrar(&cfg.auditoria, &registro).await {
+            eprintln!(
+                "Não foi possível registrar a expiração de {:?}: {e}",
+                conta.uid,
+            );
+        }
+    }
+
+    // Só os avisos das contas que ainda vão expirar continuam guardados
+    let mut avisos: BTreeMap<_, _> = avisos
+        .into_iter()
+        .filter(|(_, expiracao)| *expiracao > hoje)
+        .collect();
+    for (conta, email) in a_avisar {
+        let r = match email {
+            Some(email) => {
+                enviar(&cfg.notificacao, &aviso(&conta, &email)).await
+            },
+            None => Err(ErroNotificacao::SemEndereco),
+        };
+        match r {
+            Ok(()) => {
+                avisos.insert(conta.uid.clone(), conta.expiracao);
+                t.avisadas.push(conta);
+            },
+            Err(e) => t.falhas.push((conta, e)),
+        }
+    }
+    if !t.avisadas.is_empty()
+        && let Err(e) = serde_json::to_vec(&avisos)
+            .map_err(std::io::Error::from)
+            .and_then(|bytes| {
+                std::fs::create_dir_all(&cfg.dados)?;
+                std::fs::write(&caminho, bytes)
+            })
+    {
+        eprintln!("Não foi possível gravar os avisos de expiração: {e}");
+    }
+
+    Ok(t)
+}
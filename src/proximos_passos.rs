@@ -0,0 +1,108 @@
+//! Os próximos passos do aluno depois do cadastro, mandados na resposta da
+//! API para o frontend montar a tela final.
+//!
+//! A mensagem e o link das instruções são modelos, em que `{username}`,
+//! `{email}`, `{ou}`, `{dre}` e `{nome}` são trocados pelos dados da conta
+//! criada.
+use crate::cadastro_aluno::CadastroRealizado;
+use chrono::{Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// Os modelos dos próximos passos.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoProximosPassos {
+    /// O modelo da mensagem de sucesso.
+    pub mensagem: String,
+    /// O modelo do link das instruções para o primeiro acesso.
+    pub instrucoes: Option<String>,
+    /// Em quantos dias depois do cadastro o aluno precisa fazer o primeiro
+    /// login. Sem ele, a resposta não tem prazo.
+    pub prazo_primeiro_login: Option<u64>,
+}
+
+impl Default for ConfiguracaoProximosPassos {
+    fn default() -> Self {
+        Self {
+            mensagem: "Cadastrado como \"{username}\" com sucesso. Sua conta \
+                       de e-mail deve funcionar em até 24 horas. Seu login é \
+                       {email} e a senha é o seu DRE. A senha digitada nesse \
+                       formulário é usada somente no login dos laboratórios."
+                .to_string(),
+            instrucoes: None,
+            prazo_primeiro_login: None,
+        }
+    }
+}
+
+/// Os próximos passos de um aluno recém-cadastrado.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProximosPassos {
+    pub username: String,
+    pub email_institucional: String,
+    /// O último dia para o primeiro login.
+    pub prazo_primeiro_login: Option<NaiveDate>,
+    /// O link das instruções para o primeiro acesso.
+    pub instrucoes: Option<String>,
+}
+
+impl ConfiguracaoProximosPassos {
+    /// A mensagem de sucesso do `cadastro`.
+    pub fn mensagem(&self, cadastro: &CadastroRealizado) -> String {
+        preencher(&self.mensagem, cadastro)
+    }
+
+    /// Os próximos passos do `cadastro`, feito `hoje`.
+    pub fn proximos_passos(
+        &self,
+        cadastro: &CadastroRealizado,
+        hoje: NaiveDate,
+    ) -> ProximosPassos {
+        ProximosPassos {
+            username: cadastro.username.clone(),
+            email_institucional: cadastro.email.clone(),
+            prazo_primeiro_login: self
+                .prazo_primeiro_login
+                .and_then(|dias| hoje.checked_add_days(Days::new(dias))),
+            instrucoes: self
+                .instrucoes
+                .as_deref()
+                .map(|modelo| preencher(modelo, cadastro)),
+        }
+    }
+}
+
+/// Troca os campos do `modelo` pelos dados do `cadastro`.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::cadastro_aluno::CadastroRealizado;
+/// # use alumnic::proximos_passos::preencher;
+/// let cadastro = CadastroRealizado {
+///     username: "claudiolc".to_string(),
+///     ou: "profcomp",
+///     dre: "123456789".to_string(),
+///     home: "/home/profcomp/claudiolc".to_string(),
+///     nome: "Claudio de Lima Cavalcante".to_string(),
+///     email: "claudiolc@profcomp.ic.ufrj.br".to_string(),
+/// };
+/// assert_eq!(
+///     preencher("https://ic.ufrj.br/contas/{ou}?u={username}", &cadastro),
+///     "https://ic.ufrj.br/contas/profcomp?u=claudiolc",
+/// );
+/// assert_eq!(preencher("{email} {outro}", &cadastro), "claudiolc@profcomp.ic.ufrj.br {outro}");
+/// ```
+pub fn preencher(modelo: &str, cadastro: &CadastroRealizado) -> String {
+    [
+        ("{username}", cadastro.username.as_str()),
+        ("{email}", &cadastro.email),
+        ("{ou}", cadastro.ou),
+        ("{dre}", &cadastro.dre),
+        ("{nome}", &cadastro.nome),
+    ]
+    .iter()
+    .fold(modelo.to_string(), |texto, (campo, valor)| {
+        texto.replace(campo, valor)
+    })
+}
@@ -0,0 +1,74 @@
+//! Módulo com a reativação das contas suspensas, usada para os alunos que
+//! trancaram a matrícula e voltaram ao curso.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::bloqueio::desbloquear_conta;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{EstadoConta, buscar_conta_por_uid};
+use crate::renovacao::{dia, dia_para_data};
+use chrono::{DateTime, NaiveDate, Utc};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDeReativacao {
+    #[error("É preciso informar o motivo da reativação")]
+    SemMotivo,
+    #[error("Não existe conta com o uid {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta {0} não está suspensa, e sim {1}")]
+    NaoSuspensa(String, &'static str),
+    #[error("Houve um problema ao reativar a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A conta foi reativada, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+/// Reativa a conta suspensa com o `uid`, desfazendo o bloqueio e renovando o
+/// vínculo a partir de `agora`. O `motivo` é guardado na auditoria. Retorna o
+/// dia até o qual a conta precisa ser renovada de novo.
+pub async fn reativar<F: FonteLdap>(
+    uid: &str,
+    motivo: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<NaiveDate, ErroDeReativacao> {
+    if motivo.trim().is_empty() {
+        Err(ErroDeReativacao::SemMotivo)?
+    }
+
+    let mut conexao = ldap.abrir().await?;
+    let r: Result<_, ErroDeReativacao> = async {
+        let conta = buscar_conta_por_uid(uid, &mut conexao)
+            .await?
+            .ok_or_else(|| ErroDeReativacao::ContaInexistente(uid.into()))?;
+        if conta.estado != EstadoConta::Suspensa {
+            return Err(ErroDeReativacao::NaoSuspensa(
+                conta.uid,
+                conta.estado.valor(),
+            ));
+        }
+
+        let renovacao =
+            desbloquear_conta(&conta, &cfg.renovacao, dia(agora), &mut conexao)
+                .await?;
+        Ok(renovacao)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    let renovacao = r?;
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "reativar",
+            uid,
+            motivo: motivo.trim(),
+        },
+    )
+    .await?;
+
+    Ok(dia_para_data(renovacao))
+}
@@ -0,0 +1,179 @@
+//! Reconciliação da listagem oficial de alunos ativos do SIGA com as contas
+//! do LDAP, a base da limpeza anual. Aponta os alunos ativos sem conta, as
+//! contas de alunos que não aparecem mais na listagem e os nomes que
+//! divergem entre os dois.
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{Conta, EstadoConta, buscar_contas, ou};
+use crate::utils::listagem::ler_listagem;
+use crate::utils::nome::Nome;
+use crate::utils::validacao_entradas::processar_dre;
+use deunicode::deunicode;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDeReconciliacao {
+    #[error("A listagem não tem a coluna {0:?}")]
+    SemColuna(&'static str),
+    #[error("A linha {linha} da listagem tem um {campo} inválido: {valor:?}")]
+    LinhaInvalida {
+        linha: usize,
+        campo: &'static str,
+        valor: String,
+    },
+    #[error("Houve um problema ao buscar as contas no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+}
+
+/// As colunas da listagem e os nomes aceitos para cada uma no cabeçalho.
+const COLUNAS: [(&str, &[&str]); 2] = [
+    ("dre", &["dre"]),
+    ("nome", &["nome", "nome do aluno", "nome civil"]),
+];
+
+/// Um aluno ativo da listagem do SIGA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlunoListado {
+    pub dre: String,
+    pub nome: String,
+}
+
+/// Lê a listagem de alunos ativos do SIGA, um CSV separado por vírgula ou
+/// ponto e vírgula cujo cabeçalho tem as colunas `dre` e `nome`, em qualquer
+/// ordem e entre outras. Os alunos são retornados em ordem de DRE.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::reconciliacao::ler_alunos;
+/// let alunos = ler_alunos(
+///     "Curso;DRE;Nome\n\
+///      Ciência da Computação;987654321;Maria Silva\n\
+///      Ciência da Computação;123456789;João Souza\n",
+/// )
+/// .unwrap();
+/// assert_eq!(alunos[0].dre, "123456789");
+/// assert_eq!(alunos[1].nome, "Maria Silva");
+/// ```
+pub fn ler_alunos(csv: &str) -> Result<Vec<AlunoListado>, ErroDeReconciliacao> {
+    let linhas =
+        ler_listagem(csv, COLUNAS).map_err(ErroDeReconciliacao::SemColuna)?;
+
+    let mut alunos = BTreeMap::new();
+    for (linha, [dre, nome]) in linhas {
+        let invalido = |campo: &'static str, valor: &str| {
+            ErroDeReconciliacao::LinhaInvalida {
+                linha,
+                campo,
+                valor: valor.to_string(),
+            }
+        };
+
+        let dre = processar_dre(&dre).ok_or_else(|| invalido("DRE", &dre))?;
+        if nome.is_empty() {
+            return Err(invalido("nome", &nome));
+        }
+        alunos.insert(dre.clone(), AlunoListado { dre, nome });
+    }
+
+    Ok(alunos.into_values().collect())
+}
+
+/// Se os nomes são os mesmos, ignorando acentos, maiúsculas e as palavras
+/// "de", "da", etc., como na comparação entre [Nome]s.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::reconciliacao::mesmo_nome;
+/// assert!(mesmo_nome("JOSÉ LIMA DA SILVA", "Jose Lima Silva"));
+/// assert!(mesmo_nome("Ana D'Ávila", "ANA D'AVILA"));
+/// assert!(!mesmo_nome("José Lima Silva", "José Lima Souza"));
+/// ```
+pub fn mesmo_nome(a: &str, b: &str) -> bool {
+    match (a.parse::<Nome>(), b.parse::<Nome>()) {
+        (Ok(a), Ok(b)) => a == b,
+        // Os nomes com apóstrofos ou hífens não são [Nome]s válidos
+        _ => {
+            let normalizar = |s: &str| {
+                deunicode(s)
+                    .to_lowercase()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            normalizar(a) == normalizar(b)
+        },
+    }
+}
+
+/// Um aluno cujo nome na conta difere do nome na listagem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergencia {
+    pub uid: String,
+    pub dre: String,
+    pub nome_listagem: String,
+    pub nome_ldap: String,
+}
+
+/// O resultado da reconciliação.
+#[derive(Debug, Default)]
+pub struct Reconciliacao {
+    /// Os alunos da listagem sem uma conta vigente, isto é, que não foi
+    /// removida.
+    pub sem_conta: Vec<AlunoListado>,
+    /// As contas desbloqueadas de alunos que não estão na listagem.
+    pub fora_da_listagem: Vec<Conta>,
+    pub divergencias: Vec<Divergencia>,
+}
+
+/// Compara a `listagem` de alunos ativos com as contas do `ldap`. Se a
+/// `ou_do_curso` for dada, só as contas dela (como `alunos`) são apontadas
+/// como fora da listagem, já que a listagem é de um curso só.
+pub async fn reconciliar<F: FonteLdap>(
+    listagem: &str,
+    ou_do_curso: Option<&str>,
+    ldap: &F,
+) -> Result<Reconciliacao, ErroDeReconciliacao> {
+    let alunos = ler_alunos(listagem)?;
+
+    let mut conexao = ldap.abrir().await?;
+    let r = buscar_contas("", &mut conexao).await;
+    ldap.fechar(conexao).await?;
+
+    let mut contas: BTreeMap<String, Vec<Conta>> = BTreeMap::new();
+    for conta in r? {
+        if !matches!(conta.estado, EstadoConta::Removida | EstadoConta::Egresso)
+        {
+            contas.entry(conta.dre.clone()).or_default().push(conta);
+        }
+    }
+
+    let mut reconciliacao = Reconciliacao::default();
+    for aluno in alunos {
+        let Some(do_aluno) = contas.remove(&aluno.dre) else {
+            reconciliacao.sem_conta.push(aluno);
+            continue;
+        };
+        for conta in do_aluno {
+            if !mesmo_nome(&aluno.nome, &conta.nome) {
+                reconciliacao.divergencias.push(Divergencia {
+                    uid: conta.uid,
+                    dre: conta.dre,
+                    nome_listagem: aluno.nome.clone(),
+                    nome_ldap: conta.nome,
+                });
+            }
+        }
+    }
+
+    reconciliacao.fora_da_listagem = contas
+        .into_values()
+        .flatten()
+        .filter(|c| !c.estado.bloqueada())
+        .filter(|c| ou_do_curso.is_none_or(|o| ou(&c.dn) == o))
+        .collect();
+
+    Ok(reconciliacao)
+}
@@ -0,0 +1,99 @@
+//! Módulo com a redefinição da senha de uma conta pela supervisão, para o
+//! aluno que esqueceu a sua ou teve a conta comprometida.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::buscar_conta_por_uid;
+use crate::ldap::senha::trocar_senha;
+use crate::utils::validacao_entradas::validar_senha;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use secrecy::SecretString;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDeRedefinicao {
+    #[error("É preciso informar o motivo da redefinição")]
+    SemMotivo,
+    #[error(
+        "A senha precisa ter entre 8 e 25 caracteres, uma letra minúscula, uma maiúscula e um dígito"
+    )]
+    SenhaInvalida,
+    #[error("Não existe conta com o uid {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta {0} está {1}; reative-a antes de trocar a senha")]
+    Bloqueada(String, &'static str),
+    #[error("Houve um problema ao trocar a senha no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A senha foi trocada, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+impl ErroDeRedefinicao {
+    /// O status HTTP com que o erro é retornado pela API.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErroDeRedefinicao::SemMotivo | ErroDeRedefinicao::SenhaInvalida => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            },
+            ErroDeRedefinicao::ContaInexistente(..) => StatusCode::NOT_FOUND,
+            ErroDeRedefinicao::Bloqueada(..) => StatusCode::CONFLICT,
+            ErroDeRedefinicao::ErroLdap(..)
+            | ErroDeRedefinicao::ErroNaAuditoria(..) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            },
+        }
+    }
+}
+
+/// Troca a senha da conta com o `uid` pela `senha` em `agora`. O `motivo` é
+/// guardado na auditoria. As contas bloqueadas não têm a senha trocada, já
+/// que o bloqueio guarda a senha antiga para a reativação.
+pub async fn redefinir_senha<F: FonteLdap>(
+    uid: &str,
+    senha: &SecretString,
+    motivo: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<(), ErroDeRedefinicao> {
+    if motivo.trim().is_empty() {
+        Err(ErroDeRedefinicao::SemMotivo)?
+    }
+    if !validar_senha(senha) {
+        Err(ErroDeRedefinicao::SenhaInvalida)?
+    }
+
+    let mut conexao = ldap.abrir().await?;
+    let r: Result<_, ErroDeRedefinicao> = async {
+        let conta = buscar_conta_por_uid(uid, &mut conexao)
+            .await?
+            .ok_or_else(|| ErroDeRedefinicao::ContaInexistente(uid.into()))?;
+        if conta.estado.bloqueada() {
+            return Err(ErroDeRedefinicao::Bloqueada(
+                conta.uid,
+                conta.estado.valor(),
+            ));
+        }
+        trocar_senha(&conta, senha, &cfg.renovacao, agora, &mut conexao)
+            .await?;
+        Ok(())
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    r?;
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "redefinir_senha",
+            uid,
+            motivo: motivo.trim(),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Relatório semestral das contas, com quantas foram criadas, renovadas,
+//! bloqueadas e removidas em um período letivo. As datas vêm dos atributos
+//! das contas: o LDAP só guarda a última renovação e o último bloqueio de
+//! cada uma, então uma conta renovada duas vezes conta só no período da
+//! última renovação.
+use crate::configuracao::{ConfiguracaoDesligamento, ConfiguracaoRenovacao};
+use crate::ldap::ErroLdap;
+use crate::ldap::conta::{BASE_CONTAS, EstadoConta, ou};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::renovacao::dia_para_data;
+use crate::utils::ingresso::periodo_do_dre;
+use crate::utils::periodo::Periodo;
+use ldap3::{Scope, SearchEntry};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Atributos lidos de cada conta.
+const ATRIBUTOS: [&str; 5] = [
+    "dccDRE",
+    "estadoConta",
+    "dataCriacao",
+    "dataRenovacao",
+    "dataRemocao",
+];
+
+/// As contagens de um período.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Contagens {
+    pub criadas: usize,
+    pub renovadas: usize,
+    pub bloqueadas: usize,
+    pub removidas: usize,
+}
+
+impl Contagens {
+    fn somar(&mut self, outra: &Contagens) {
+        self.criadas += outra.criadas;
+        self.renovadas += outra.renovadas;
+        self.bloqueadas += outra.bloqueadas;
+        self.removidas += outra.removidas;
+    }
+}
+
+/// O relatório de um período, com as contagens de cada OU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relatorio {
+    pub periodo: Periodo,
+    pub por_ou: BTreeMap<String, Contagens>,
+}
+
+impl Relatorio {
+    /// As contagens somadas de todas as OUs.
+    pub fn total(&self) -> Contagens {
+        let mut total = Contagens::default();
+        for contagens in self.por_ou.values() {
+            total.somar(contagens);
+        }
+        total
+    }
+
+    /// As contagens de cada OU, seguidas do total.
+    pub fn linhas(&self) -> impl Iterator<Item = (&str, Contagens)> {
+        self.por_ou
+            .iter()
+            .map(|(ou, c)| (ou.as_str(), *c))
+            .chain([("total", self.total())])
+    }
+
+    /// O relatório em CSV, com uma linha por OU e uma com o total.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::utils::periodo::Periodo;
+    /// # use alumnic::relatorio::{Contagens, Relatorio};
+    /// let relatorio = Relatorio {
+    ///     periodo: Periodo { ano: 2025, semestre: 2 },
+    ///     por_ou: [(
+    ///         "alunos".to_string(),
+    ///         Contagens { criadas: 3, renovadas: 2, bloqueadas: 1, removidas: 0 },
+    ///     )]
+    ///     .into(),
+    /// };
+    /// assert_eq!(
+    ///     relatorio.csv(),
+    ///     "periodo,ou,criadas,renovadas,bloqueadas,removidas\n\
+    ///      2025.2,alunos,3,2,1,0\n\
+    ///      2025.2,total,3,2,1,0\n",
+    /// );
+    /// ```
+    pub fn csv(&self) -> String {
+        let mut csv =
+            "periodo,ou,criadas,renovadas,bloqueadas,removidas\n".to_string();
+        for (ou, c) in self.linhas() {
+            writeln!(
+                csv,
+                "{},{ou},{},{},{},{}",
+                self.periodo, c.criadas, c.renovadas, c.bloqueadas, c.removidas,
+            )
+            .unwrap();
+        }
+        csv
+    }
+}
+
+/// Conta o que aconteceu com a conta da entrada `e` no `periodo`.
+fn contar(
+    e: &SearchEntry,
+    periodo: Periodo,
+    prazos: &ConfiguracaoRenovacao,
+    desligamento: &ConfiguracaoDesligamento,
+) -> Contagens {
+    let primeiro = |atributo: &str| {
+        e.attrs
+            .get(atributo)
+            .and_then(|v| v.first())
+            .map(String::as_str)
+    };
+    let dia =
+        |atributo: &str| primeiro(atributo).and_then(|d| d.parse::<i64>().ok());
+    let no_periodo = |dia: i64| Periodo::da_data(dia_para_data(dia)) == periodo;
+
+    // As contas antigas, sem a data de criação, contam no período de
+    // ingresso do DRE
+    let criacao = dia("dataCriacao");
+    let criada = match criacao {
+        Some(criacao) => no_periodo(criacao),
+        None => primeiro("dccDRE").and_then(periodo_do_dre) == Some(periodo),
+    };
+
+    // A dataRenovacao é a da última renovação (ou da criação) mais a
+    // validade
+    let renovacao = dia("dataRenovacao")
+        .map(|d| d - prazos.validade_conta)
+        .filter(|&d| Some(d) != criacao);
+
+    let estado = primeiro("estadoConta").and_then(EstadoConta::do_valor);
+    let remocao = dia("dataRemocao").filter(|_| {
+        matches!(estado, Some(EstadoConta::Suspensa | EstadoConta::Removida))
+    });
+    let bloqueio = remocao.map(|d| d - desligamento.dias_ate_remocao);
+
+    Contagens {
+        criadas: criada as usize,
+        renovadas: renovacao.is_some_and(no_periodo) as usize,
+        bloqueadas: bloqueio.is_some_and(no_periodo) as usize,
+        removidas: (estado == Some(EstadoConta::Removida)
+            && remocao.is_some_and(no_periodo)) as usize,
+    }
+}
+
+/// Monta o relatório do `periodo` com as contas dos alunos no `ldap`. Os
+/// `prazos` e o `desligamento` devem ser os usados nas contas, já que as
+/// datas da última renovação e do bloqueio são calculadas a partir deles.
+pub async fn gerar<D: DiretorioLdap>(
+    periodo: Periodo,
+    prazos: &ConfiguracaoRenovacao,
+    desligamento: &ConfiguracaoDesligamento,
+    ldap: &mut D,
+) -> Result<Relatorio, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            "(objectClass=dccAluno)",
+            ATRIBUTOS.to_vec(),
+        )
+        .await?;
+
+    let mut por_ou: BTreeMap<String, Contagens> = BTreeMap::new();
+    for e in &entradas {
+        let contagens = contar(e, periodo, prazos, desligamento);
+        if contagens != Contagens::default() {
+            por_ou.entry(ou(&e.dn)).or_default().somar(&contagens);
+        }
+    }
+
+    Ok(Relatorio { periodo, por_ou })
+}
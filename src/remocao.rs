@@ -0,0 +1,107 @@
+//! Módulo com a remoção imediata de uma conta pela supervisão, sem esperar
+//! os prazos, como a de uma conta comprometida. A conta passa pelos mesmos
+//! passos da remoção feita pelos [prazos](crate::prazos): é bloqueada, os
+//! hooks de remoção rodam e ela é marcada como removida.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::hooks::{ErroHook, Evento, TipoEvento, disparar_evento};
+use crate::ldap::ErroLdap;
+use crate::ldap::bloqueio::{bloquear_conta, marcar_removida};
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{Conta, EstadoConta, buscar_conta_por_uid};
+use crate::ldap::uids_liberados::liberar_uid;
+use crate::renovacao::dia;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDeRemocao {
+    #[error("É preciso informar o motivo da remoção")]
+    SemMotivo,
+    #[error("Não existe conta com o uid {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta {0} já foi removida")]
+    JaRemovida(String),
+    #[error("A conta foi bloqueada, mas os hooks de remoção falharam: {0}")]
+    ErroNoHook(#[from] ErroHook),
+    #[error("Houve um problema ao remover a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A conta foi removida, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+impl ErroDeRemocao {
+    /// O status HTTP com que o erro é retornado pela API.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErroDeRemocao::SemMotivo => StatusCode::UNPROCESSABLE_ENTITY,
+            ErroDeRemocao::ContaInexistente(..) => StatusCode::NOT_FOUND,
+            ErroDeRemocao::JaRemovida(..) => StatusCode::CONFLICT,
+            ErroDeRemocao::ErroNoHook(..) => StatusCode::BAD_GATEWAY,
+            ErroDeRemocao::ErroLdap(..)
+            | ErroDeRemocao::ErroNaAuditoria(..) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            },
+        }
+    }
+}
+
+/// Remove a conta com o `uid` em `agora`. O `motivo` é guardado na
+/// auditoria. Se os hooks de remoção falharem, a conta fica suspensa, com a
+/// remoção marcada para hoje, e os prazos tentam de novo.
+pub async fn remover_agora<F: FonteLdap>(
+    uid: &str,
+    motivo: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<Conta, ErroDeRemocao> {
+    if motivo.trim().is_empty() {
+        Err(ErroDeRemocao::SemMotivo)?
+    }
+    let hoje = dia(agora);
+
+    let mut conexao = ldap.abrir().await?;
+    let r: Result<_, ErroDeRemocao> = async {
+        let conta = buscar_conta_por_uid(uid, &mut conexao)
+            .await?
+            .ok_or_else(|| ErroDeRemocao::ContaInexistente(uid.into()))?;
+        if conta.estado == EstadoConta::Removida {
+            return Err(ErroDeRemocao::JaRemovida(conta.uid));
+        }
+        bloquear_conta(&conta, hoje, hoje, &mut conexao).await?;
+        Ok(conta)
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    let mut conta = r?;
+
+    let evento = Evento::da_conta(TipoEvento::Remocao, &conta);
+    disparar_evento(&cfg.hooks, &cfg.auditoria, &evento).await?;
+
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        marcar_removida(&mut conta, &mut conexao).await?;
+        if cfg.usuario_novo.reutilizacao_uids.is_some() {
+            liberar_uid(&conta.dn, hoje, &mut conexao).await?;
+        }
+        Ok::<_, ErroLdap>(())
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    r?;
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "remover",
+            uid,
+            motivo: motivo.trim(),
+        },
+    )
+    .await?;
+
+    Ok(conta)
+}
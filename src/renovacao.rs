@@ -0,0 +1,286 @@
+//! Módulo com os tipos e funções necessárias para a renovação anual do vínculo
+//! de um aluno já cadastrado. O aluno comprova que continua matriculado com o
+//! mesmo documento de "Regularmente Matriculado" usado no cadastro.
+use crate::configuracao::ConfiguracaoRenovacao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{Conta, buscar_conta_por_dre};
+use crate::ldap::renovar::renovar_conta;
+use crate::lotes::Lote;
+use crate::portal_ufrj::{Consulta, ConsultaErro, consulta_em};
+use crate::utils::validacao_entradas::*;
+use axum::http::StatusCode;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{Stream, StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Os dados do documento "Regularmente Matriculado" usado para renovar, no
+/// mesmo formato dos [`DadosParaCadastro`].
+///
+/// [`DadosParaCadastro`]: crate::cadastro_aluno::DadosParaCadastro
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DadosParaRenovacao {
+    pub dre: String,
+    pub data: String,
+    pub hora: String,
+    pub codigo: String,
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeRenovacao {
+    #[error("O DRE {0:?} não é válido")]
+    DREInvalido(String),
+    #[error("A data {0:?} não é válida")]
+    DataInvalida(String),
+    #[error("A hora {0:?} não é válida")]
+    HoraInvalida(String),
+    #[error("O código {0:?} não é válido")]
+    CodigoInvalido(String),
+    #[error("O documento foi emitido em {0}, emita um documento mais recente")]
+    DocumentoAntigo(NaiveDate),
+    #[error("A linha {0:?} não está no formato dre,data,hora,codigo")]
+    LinhaInvalida(String),
+    #[error(transparent)]
+    CampoGrande(#[from] CampoGrande),
+
+    #[error("Não foi possível obter informações do SIGA: {0}")]
+    ErroNaConsulta(#[from] ConsultaErro),
+    #[error("Alunos de {0} não têm direito a contas do IC")]
+    AlunoOutroCurso(String),
+    #[error("Seu documento de matrícula é inválido")]
+    DocumentoInvalido,
+
+    #[error("Houve um problema ao renovar a conta no LDAP: {0}")]
+    ErroNaRenovacao(#[from] ErroLdap),
+    #[error("Não existe conta com o DRE {0:?}")]
+    ContaInexistente(String),
+    #[error("A conta {0} está bloqueada, procure a supervisão do LCI")]
+    ContaBloqueada(String),
+    #[error("O lote foi interrompido antes desta linha")]
+    Cancelado,
+}
+
+impl ErroDeRenovacao {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErroDeRenovacao::DREInvalido(..)
+            | ErroDeRenovacao::DataInvalida(..)
+            | ErroDeRenovacao::HoraInvalida(..)
+            | ErroDeRenovacao::CodigoInvalido(..)
+            | ErroDeRenovacao::DocumentoAntigo(..)
+            | ErroDeRenovacao::LinhaInvalida(..)
+            | ErroDeRenovacao::CampoGrande(..) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            },
+            ErroDeRenovacao::AlunoOutroCurso(..)
+            | ErroDeRenovacao::ContaBloqueada(..) => StatusCode::FORBIDDEN,
+            ErroDeRenovacao::DocumentoInvalido => StatusCode::UNAUTHORIZED,
+            ErroDeRenovacao::ErroNaRenovacao(ErroLdap::Timeout) => {
+                StatusCode::GATEWAY_TIMEOUT
+            },
+            ErroDeRenovacao::ErroNaRenovacao(ErroLdap::SomenteLeitura) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            },
+            ErroDeRenovacao::ErroNaConsulta(ConsultaErro::EsperaEsgotada(
+                ..,
+            )) => StatusCode::SERVICE_UNAVAILABLE,
+            ErroDeRenovacao::ErroNaConsulta(..)
+            | ErroDeRenovacao::ErroNaRenovacao(..) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            },
+            ErroDeRenovacao::ContaInexistente(..) => StatusCode::NOT_FOUND,
+            ErroDeRenovacao::Cancelado => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Resultado de uma renovação bem-sucedida.
+#[derive(Debug, Clone)]
+pub struct RenovacaoRealizada {
+    /// O username/uid da conta renovada.
+    pub username: String,
+    /// O dia até o qual a conta precisa ser renovada de novo.
+    pub proxima_renovacao: NaiveDate,
+    /// A conta renovada, para os hooks.
+    pub conta: Conta,
+}
+
+/// Converte um dia contado desde 01/01/1970, como os guardados no LDAP, para
+/// uma data.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::renovacao::dia_para_data;
+/// # use chrono::NaiveDate;
+/// assert_eq!(
+///     dia_para_data(20148),
+///     NaiveDate::from_ymd_opt(2025, 3, 1).unwrap(),
+/// );
+/// ```
+pub fn dia_para_data(dia: i64) -> NaiveDate {
+    DateTime::UNIX_EPOCH.date_naive() + chrono::Duration::days(dia)
+}
+
+/// O dia contado desde 01/01/1970 em que `agora` está, no formato guardado
+/// no LDAP.
+pub fn dia(agora: DateTime<Utc>) -> i64 {
+    agora.timestamp().div_euclid(24 * 60 * 60)
+}
+
+impl DadosParaRenovacao {
+    /// Valida os dados, autentica o documento no Gnosys hospedado em
+    /// `gnosys_url` e renova a conta com o mesmo DRE no LDAP fornecido por
+    /// `ldap`.
+    pub async fn renovar<F: FonteLdap>(
+        mut self,
+        prazos: &ConfiguracaoRenovacao,
+        gnosys_url: &str,
+        ldap: &F,
+        agora: DateTime<Utc>,
+    ) -> Result<RenovacaoRealizada, ErroDeRenovacao> {
+        limitar("dre", &self.dre, MAXIMO_CAMPO)?;
+        limitar("data", &self.data, MAXIMO_CAMPO)?;
+        limitar("hora", &self.hora, MAXIMO_CAMPO)?;
+        limitar("codigo", &self.codigo, MAXIMO_CAMPO)?;
+        self.dre = processar_dre(&self.dre)
+            .ok_or_else(move || ErroDeRenovacao::DREInvalido(self.dre))?;
+        self.data = processar_data(&self.data)
+            .ok_or_else(move || ErroDeRenovacao::DataInvalida(self.data))?;
+        self.hora = processar_hora(&self.hora)
+            .ok_or_else(move || ErroDeRenovacao::HoraInvalida(self.hora))?;
+        self.codigo = processar_codigo(&self.codigo)
+            .ok_or_else(move || ErroDeRenovacao::CodigoInvalido(self.codigo))?;
+
+        // Um documento antigo não comprova que o aluno ainda está matriculado
+        let emissao = NaiveDate::parse_from_str(&self.data, "%d/%m/%Y")
+            .map_err(|_| ErroDeRenovacao::DataInvalida(self.data.clone()))?;
+        if (agora.date_naive() - emissao).num_days()
+            > prazos.idade_documento_dias
+        {
+            Err(ErroDeRenovacao::DocumentoAntigo(emissao))?
+        }
+
+        match consulta_em(
+            gnosys_url,
+            &self.dre,
+            &self.data,
+            &self.hora,
+            &self.codigo,
+        )
+        .await?
+        {
+            Consulta::AlunoBCC { .. } | Consulta::AlunoProfComp { .. } => {},
+            Consulta::AlunoOutroCurso { curso, .. } => {
+                Err(ErroDeRenovacao::AlunoOutroCurso(curso))?
+            },
+            Consulta::Desconhecido => Err(ErroDeRenovacao::DocumentoInvalido)?,
+        }
+
+        let hoje = dia(agora);
+
+        let mut conexao = ldap.abrir().await?;
+        let r = async {
+            let conta = buscar_conta_por_dre(&self.dre, &mut conexao)
+                .await?
+                .ok_or_else(|| {
+                    ErroDeRenovacao::ContaInexistente(self.dre.clone())
+                })?;
+            // Uma conta bloqueada só volta a funcionar pela supervisão
+            if conta.estado.bloqueada() {
+                Err(ErroDeRenovacao::ContaBloqueada(conta.uid.clone()))?
+            }
+
+            let renovacao =
+                renovar_conta(&conta, prazos, hoje, &mut conexao).await?;
+
+            Ok(RenovacaoRealizada {
+                username: conta.uid.clone(),
+                proxima_renovacao: dia_para_data(renovacao),
+                conta,
+            })
+        }
+        .await;
+        ldap.fechar(conexao).await?;
+
+        r
+    }
+}
+
+/// As linhas de uma lista de renovação em lote, sem as vazias e as
+/// começadas por `#`.
+pub fn linhas_do_lote(lista: &str) -> impl Iterator<Item = &str> {
+    lista
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+/// Renova em lote as contas dos documentos listados em `lista`, um por linha
+/// no formato `dre,data,hora,codigo`. Linhas vazias e começadas por `#` são
+/// ignoradas. Até `concorrencia` linhas são renovadas ao mesmo tempo.
+/// Depois de o `cancelamento` ser cancelado, as linhas em andamento terminam
+/// e as que faltam voltam com [`ErroDeRenovacao::Cancelado`]. Com um
+/// `lote`, o progresso fica no [acompanhamento](crate::lotes) e as linhas
+/// esperam enquanto ele estiver pausado. Retorna o resultado de cada linha,
+/// na ordem da lista.
+pub async fn renovar_em_lote<F: FonteLdap>(
+    lista: &str,
+    prazos: &ConfiguracaoRenovacao,
+    gnosys_url: &str,
+    ldap: &F,
+    agora: DateTime<Utc>,
+    concorrencia: usize,
+    lote: &Lote,
+) -> Vec<(String, Result<RenovacaoRealizada, ErroDeRenovacao>)> {
+    renovacoes_em_lote(
+        lista,
+        prazos,
+        gnosys_url,
+        ldap,
+        agora,
+        concorrencia,
+        lote,
+    )
+    .collect()
+    .await
+}
+
+/// Como o [renovar_em_lote], mas entrega o resultado de cada linha assim que
+/// ele sai, para quem acompanha o progresso do lote.
+pub fn renovacoes_em_lote<'a, F: FonteLdap>(
+    lista: &'a str,
+    prazos: &'a ConfiguracaoRenovacao,
+    gnosys_url: &'a str,
+    ldap: &'a F,
+    agora: DateTime<Utc>,
+    concorrencia: usize,
+    lote: &'a Lote,
+) -> impl Stream<Item = (String, Result<RenovacaoRealizada, ErroDeRenovacao>)> + 'a
+{
+    stream::iter(linhas_do_lote(lista))
+        .map(move |linha| async move {
+            let comecar = lote.comecar_item().await;
+            let campos: Vec<_> = linha.split(',').map(str::trim).collect();
+            let r = match campos[..] {
+                // Cada linha só confere o cancelamento antes de começar
+                _ if !comecar => Err(ErroDeRenovacao::Cancelado),
+                [dre, data, hora, codigo] => {
+                    DadosParaRenovacao {
+                        dre: dre.to_string(),
+                        data: data.to_string(),
+                        hora: hora.to_string(),
+                        codigo: codigo.to_string(),
+                    }
+                    .renovar(prazos, gnosys_url, ldap, agora)
+                    .await
+                },
+                _ => Err(ErroDeRenovacao::LinhaInvalida(linha.to_string())),
+            };
+
+            lote.registrar(r.is_ok());
+            (linha.to_string(), r)
+        })
+        .buffered(concorrencia.max(1))
+}
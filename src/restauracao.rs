@@ -0,0 +1,164 @@
+//! Módulo com a restauração seletiva de uma conta a partir de um backup em
+//! LDIF, para a conta apagada ou estragada por engano. A entrada é recriada
+//! exatamente como está no backup, com o mesmo uidNumber e o mesmo sambaSID,
+//! depois de verificar que nenhuma outra conta ocupa a identidade dela.
+use crate::auditoria::{Registro, registrar};
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::{BASE_CONTAS, EstadoConta};
+use crate::ldap::diretorio::DiretorioLdap;
+use crate::ldap::filtros;
+use crate::ldap::ldif::{ErroLdif, ler_ldif};
+use chrono::{DateTime, Utc};
+use ldap3::{Scope, SearchEntry};
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErroDeRestauracao {
+    #[error("O backup não é um LDIF válido: {0}")]
+    Ldif(#[from] ErroLdif),
+    #[error("O backup não tem a conta {0:?}")]
+    ContaAusente(String),
+    #[error("O backup tem {1} entradas com o uid {0:?}")]
+    ContaRepetida(String, usize),
+    #[error("O {atributo} {valor} da conta já é usado por {dn}")]
+    Conflito {
+        atributo: &'static str,
+        valor: String,
+        dn: String,
+    },
+    #[error("Houve um problema ao restaurar a conta no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+    #[error("A conta foi restaurada, mas não foi possível registrar: {0}")]
+    ErroNaAuditoria(#[from] std::io::Error),
+}
+
+/// Os valores do `atributo` da entrada, sem diferenciar maiúsculas de
+/// minúsculas no nome, como no LDAP.
+fn valores<'a>(entrada: &'a SearchEntry, atributo: &str) -> &'a [String] {
+    entrada
+        .attrs
+        .iter()
+        .find(|(nome, _)| nome.eq_ignore_ascii_case(atributo))
+        .map(|(_, v)| v.as_slice())
+        .unwrap_or_default()
+}
+
+/// Recria a conta com o `uid` a partir do `ldif` do backup, lido de
+/// `origem`, que fica na auditoria. Antes de gravar, verifica que nenhuma
+/// conta tem o mesmo uid ou DRE, nem, entre as que não foram removidas, o
+/// mesmo uidNumber ou sambaSID. Retorna o DN da entrada recriada.
+pub async fn restaurar<F: FonteLdap>(
+    uid: &str,
+    ldif: &str,
+    origem: &str,
+    cfg: &Configuracao,
+    ldap: &F,
+    agora: DateTime<Utc>,
+) -> Result<String, ErroDeRestauracao> {
+    let mut entradas: Vec<_> = ler_ldif(ldif)?
+        .into_iter()
+        .filter(|e| valores(e, "uid").iter().any(|u| u == uid))
+        .collect();
+    let entrada = match entradas.len() {
+        0 => Err(ErroDeRestauracao::ContaAusente(uid.to_string()))?,
+        1 => entradas.remove(0),
+        n => Err(ErroDeRestauracao::ContaRepetida(uid.to_string(), n))?,
+    };
+
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        verificar_conflitos(&entrada, &mut conexao).await?;
+
+        let atributos = entrada
+            .attrs
+            .iter()
+            .map(|(nome, v)| {
+                (nome.as_str(), v.iter().map(String::as_str).collect())
+            })
+            .collect();
+        conexao.adicionar(&entrada.dn, atributos).await?;
+        // Os atributos binários, como a foto, só têm um valor
+        for (nome, v) in &entrada.bin_attrs {
+            if let Some(valor) = v.first() {
+                conexao.gravar_binario(&entrada.dn, nome, valor).await?;
+            }
+        }
+        Ok::<_, ErroDeRestauracao>(())
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    r?;
+
+    registrar(
+        &cfg.auditoria,
+        &Registro {
+            quando: agora,
+            operacao: "restaurar",
+            uid,
+            motivo: &format!("Restaurada do backup {origem}"),
+        },
+    )
+    .await?;
+
+    Ok(entrada.dn)
+}
+
+/// Procura no LDAP as contas que ocupam a identidade da `entrada`.
+async fn verificar_conflitos<D: DiretorioLdap>(
+    entrada: &SearchEntry,
+    ldap: &mut D,
+) -> Result<(), ErroDeRestauracao> {
+    // O uid e o DRE nunca são reaproveitados, nem de contas removidas; o
+    // uidNumber e o sambaSID podem ter sido liberados pela remoção
+    let identidade = [
+        ("uid", true),
+        ("dccDRE", true),
+        ("uidNumber", false),
+        ("sambaSID", false),
+    ]
+    .map(|(atributo, sempre)| (atributo, valores(entrada, atributo), sempre));
+
+    let filtro = filtros::ou(identidade.iter().flat_map(|(atributo, v, _)| {
+        v.iter().map(move |v| filtros::igual(atributo, v))
+    }));
+    let mut atributos: Vec<_> = identidade.iter().map(|(a, ..)| *a).collect();
+    atributos.push("estadoConta");
+    let ocupantes = ldap
+        .buscar(
+            BASE_CONTAS,
+            Scope::Subtree,
+            // O domínio do Samba guarda o próximo uidNumber livre, que não
+            // conta como conflito
+            &filtros::e([
+                filtros::igual("objectClass", "posixAccount"),
+                filtro,
+            ]),
+            atributos,
+        )
+        .await?;
+
+    for ocupante in ocupantes {
+        let removida = valores(&ocupante, "estadoConta")
+            .first()
+            .and_then(|e| EstadoConta::do_valor(e))
+            == Some(EstadoConta::Removida);
+        for (atributo, v, sempre) in &identidade {
+            let do_ocupante: HashSet<_> =
+                valores(&ocupante, atributo).iter().collect();
+            let Some(valor) = v.iter().find(|v| do_ocupante.contains(v)) else {
+                continue;
+            };
+            if *sempre || !removida {
+                return Err(ErroDeRestauracao::Conflito {
+                    atributo,
+                    valor: valor.clone(),
+                    dn: ocupante.dn,
+                });
+            }
+        }
+    }
+    Ok(())
+}
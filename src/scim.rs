@@ -0,0 +1,268 @@
+//! Representação das contas dos alunos no schema de usuários do SCIM 2.0
+//! (RFC 7643), para os serviços que importam as contas por SCIM. Só a
+//! leitura é suportada, com paginação e filtros simples (RFC 7644).
+use crate::ldap::ErroLdap;
+use crate::ldap::cadastrar::BASE_ACADEMICOS;
+use crate::ldap::conta::EstadoConta;
+use crate::ldap::diretorio::DiretorioLdap;
+use ldap3::{Scope, SearchEntry};
+use secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const SCHEMA_USUARIO: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const SCHEMA_LISTA: &str =
+    "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const SCHEMA_ERRO: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+/// Atributos lidos de cada conta.
+const ATRIBUTOS: [&str; 6] =
+    ["uid", "cn", "sn", "gecos", "mail", "estadoConta"];
+
+/// Configuração das rotas SCIM.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoScim {
+    /// Token exigido pelas rotas, no cabeçalho `Authorization: Bearer`.
+    pub token: SecretString,
+    /// O maior número de usuários retornados em uma página.
+    #[serde(default = "maximo_por_pagina_padrao")]
+    pub maximo_por_pagina: usize,
+}
+
+fn maximo_por_pagina_padrao() -> usize {
+    100
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Nome {
+    pub formatted: String,
+    pub given_name: String,
+    pub family_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Email {
+    pub value: String,
+    #[serde(rename = "type")]
+    pub tipo: &'static str,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    pub resource_type: &'static str,
+    pub location: String,
+}
+
+/// Uma conta de aluno no schema de usuários do SCIM. O `id` é o próprio uid,
+/// que nunca muda.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsuarioScim {
+    pub schemas: [&'static str; 1],
+    pub id: String,
+    pub user_name: String,
+    pub name: Nome,
+    pub display_name: String,
+    pub emails: Vec<Email>,
+    /// Se a conta funciona, ou seja, se não está bloqueada.
+    pub active: bool,
+    pub meta: Meta,
+}
+
+impl UsuarioScim {
+    /// Consome a entrada, movendo os valores dos atributos em vez de
+    /// copiá-los.
+    fn da_entrada(mut e: SearchEntry) -> Option<Self> {
+        let mut primeiro = |atributo: &str| {
+            e.attrs
+                .remove(atributo)
+                .and_then(|v| v.into_iter().next())
+                .unwrap_or_default()
+        };
+
+        let uid = primeiro("uid");
+        if uid.is_empty() {
+            return None;
+        }
+        let estado = EstadoConta::do_valor(&primeiro("estadoConta"))
+            .unwrap_or(EstadoConta::Ativa);
+        let mail = primeiro("mail");
+        let gecos = primeiro("gecos");
+
+        Some(UsuarioScim {
+            schemas: [SCHEMA_USUARIO],
+            id: uid.clone(),
+            user_name: uid.clone(),
+            name: Nome {
+                formatted: gecos.clone(),
+                given_name: primeiro("cn"),
+                family_name: primeiro("sn"),
+            },
+            display_name: gecos,
+            emails: if mail.is_empty() {
+                vec![]
+            } else {
+                vec![Email {
+                    value: mail,
+                    tipo: "work",
+                    primary: true,
+                }]
+            },
+            active: !estado.bloqueada(),
+            meta: Meta {
+                resource_type: "User",
+                location: format!("/scim/v2/Users/{uid}"),
+            },
+        })
+    }
+}
+
+/// Uma página de usuários.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListaScim {
+    pub schemas: [&'static str; 1],
+    pub total_results: usize,
+    pub start_index: usize,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<UsuarioScim>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("filtro não suportado: {0:?}")]
+pub struct FiltroInvalido(pub String);
+
+/// Um filtro simples, com um único `eq` em um dos atributos suportados.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FiltroScim {
+    UserName(String),
+    Email(String),
+    Active(bool),
+}
+
+impl FiltroScim {
+    /// Interpreta um filtro como `userName eq "joaops"`. Só o `eq` nos
+    /// atributos `userName`, `emails.value` (ou `emails`) e `active` é
+    /// suportado.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::scim::FiltroScim;
+    /// assert_eq!(
+    ///     FiltroScim::interpretar(r#"userName eq "joaops""#),
+    ///     Ok(FiltroScim::UserName("joaops".to_string())),
+    /// );
+    /// assert_eq!(
+    ///     FiltroScim::interpretar("active EQ false"),
+    ///     Ok(FiltroScim::Active(false)),
+    /// );
+    /// assert!(FiltroScim::interpretar(r#"userName sw "j""#).is_err());
+    /// assert!(FiltroScim::interpretar(r#"title eq "x""#).is_err());
+    /// ```
+    pub fn interpretar(filtro: &str) -> Result<Self, FiltroInvalido> {
+        let invalido = || FiltroInvalido(filtro.to_string());
+
+        let mut partes = filtro.trim().splitn(3, ' ');
+        let (Some(atributo), Some(operador), Some(valor)) =
+            (partes.next(), partes.next(), partes.next())
+        else {
+            return Err(invalido());
+        };
+        if !operador.eq_ignore_ascii_case("eq") {
+            return Err(invalido());
+        }
+        let valor = valor.trim();
+        let texto = || {
+            valor
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .map(str::to_string)
+                .ok_or_else(invalido)
+        };
+
+        match atributo.to_lowercase().as_str() {
+            "username" => Ok(FiltroScim::UserName(texto()?)),
+            "emails" | "emails.value" => Ok(FiltroScim::Email(texto()?)),
+            "active" => match valor.to_lowercase().as_str() {
+                "true" => Ok(FiltroScim::Active(true)),
+                "false" => Ok(FiltroScim::Active(false)),
+                _ => Err(invalido()),
+            },
+            _ => Err(invalido()),
+        }
+    }
+
+    /// Se o `usuario` satisfaz o filtro. Como no SCIM, o `userName` e os
+    /// emails são comparados sem diferenciar maiúsculas de minúsculas.
+    pub fn aceita(&self, usuario: &UsuarioScim) -> bool {
+        match self {
+            FiltroScim::UserName(u) => {
+                usuario.user_name.eq_ignore_ascii_case(u)
+            },
+            FiltroScim::Email(e) => usuario
+                .emails
+                .iter()
+                .any(|m| m.value.eq_ignore_ascii_case(e)),
+            FiltroScim::Active(a) => usuario.active == *a,
+        }
+    }
+}
+
+/// Busca as contas dos alunos como usuários SCIM, ordenadas pelo uid.
+pub async fn buscar_usuarios<D: DiretorioLdap>(
+    ldap: &mut D,
+) -> Result<Vec<UsuarioScim>, ErroLdap> {
+    let entradas = ldap
+        .buscar(
+            BASE_ACADEMICOS,
+            Scope::Subtree,
+            "(objectClass=dccAluno)",
+            ATRIBUTOS.to_vec(),
+        )
+        .await?;
+
+    let mut usuarios: Vec<_> = entradas
+        .into_iter()
+        .filter_map(UsuarioScim::da_entrada)
+        .collect();
+    usuarios.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(usuarios)
+}
+
+/// Monta a página dos `usuarios` que começa no `inicio` (contado a partir de
+/// 1, como no SCIM) e tem até `quantidade` usuários.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::scim::paginar;
+/// let pagina = paginar(Vec::new(), 0, 10);
+/// assert_eq!(pagina.start_index, 1);
+/// assert_eq!(pagina.total_results, 0);
+/// ```
+pub fn paginar(
+    usuarios: Vec<UsuarioScim>,
+    inicio: usize,
+    quantidade: usize,
+) -> ListaScim {
+    let inicio = inicio.max(1);
+    let total = usuarios.len();
+    let pagina: Vec<_> = usuarios
+        .into_iter()
+        .skip(inicio - 1)
+        .take(quantidade)
+        .collect();
+
+    ListaScim {
+        schemas: [SCHEMA_LISTA],
+        total_results: total,
+        start_index: inicio,
+        items_per_page: pagina.len(),
+        resources: pagina,
+    }
+}
@@ -0,0 +1,320 @@
+//! Simulação de carga do período de matrícula, para validar as mudanças de
+//! desempenho antes dos dias de pico.
+//!
+//! A simulação sobe a API e um Gnosys sintético, que considera válido
+//! qualquer documento com o [`CODIGO_SINTETICO`], e faz os cadastros pela API
+//! como os alunos fariam, contra o LDAP da configuração. Ela cria contas de
+//! verdade, então só deve ser usada num ambiente de homologação.
+use crate::configuracao::Configuracao;
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::conta::buscar_conta_por_dre;
+use crate::ldap::diretorio::DiretorioLdap;
+use axum::Router;
+use axum::extract::Form;
+use axum::http::header;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use futures::StreamExt;
+use futures::stream;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// O código de autenticação dos documentos sintéticos.
+pub const CODIGO_SINTETICO: &str = "5151.5151.5151.5151.5151.5151.5151.5151";
+
+const PRIMEIROS: [&str; 20] = [
+    "Ana", "Bruno", "Carla", "Diego", "Elisa", "Fabio", "Gabriela", "Heitor",
+    "Iara", "Joao", "Karina", "Lucas", "Marina", "Nelson", "Olivia", "Paulo",
+    "Raquel", "Sergio", "Tatiana", "Vitor",
+];
+const SOBRENOMES: [&str; 20] = [
+    "Almeida", "Barbosa", "Cardoso", "Duarte", "Esteves", "Ferraz", "Gouveia",
+    "Honorato", "Ismael", "Jardim", "Lacerda", "Macedo", "Nogueira", "Orlando",
+    "Pacheco", "Quintela", "Rezende", "Siqueira", "Toledo", "Valente",
+];
+
+/// O nome do `i`-ésimo aluno sintético. Os 8000 primeiros nomes são
+/// diferentes, para os usernames não se esgotarem.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::simulacao::nome_sintetico;
+/// assert_eq!(nome_sintetico(0), "Ana Almeida Almeida");
+/// assert_eq!(nome_sintetico(21), "Bruno Barbosa Almeida");
+/// assert_ne!(nome_sintetico(7999), nome_sintetico(0));
+/// ```
+pub fn nome_sintetico(i: usize) -> String {
+    format!(
+        "{} {} {}",
+        PRIMEIROS[i % 20],
+        SOBRENOMES[(i / 20) % 20],
+        SOBRENOMES[(i / 400) % 20],
+    )
+}
+
+/// Como a simulação é feita.
+#[derive(Debug, Clone)]
+pub struct ConfiguracaoSimulacao {
+    /// Quantos cadastros são feitos.
+    pub cadastros: usize,
+    /// Quantos cadastros são feitos ao mesmo tempo.
+    pub concorrencia: usize,
+    /// O DRE do primeiro aluno sintético. Os seguintes são consecutivos.
+    pub primeiro_dre: u32,
+    /// Se as contas criadas são apagadas no final.
+    pub limpar: bool,
+}
+
+/// O resultado de uma simulação.
+#[derive(Debug, Clone, Default)]
+pub struct ResultadoSimulacao {
+    /// Quantos cadastros terminaram com cada status HTTP, ou com 0 se a API
+    /// não respondeu.
+    pub por_status: BTreeMap<u16, usize>,
+    /// A duração de cada cadastro, em ordem crescente.
+    pub latencias: Vec<Duration>,
+    /// A duração da simulação inteira.
+    pub duracao: Duration,
+    /// Quantas contas criadas foram apagadas no final.
+    pub apagadas: usize,
+}
+
+impl ResultadoSimulacao {
+    pub fn total(&self) -> usize {
+        self.por_status.values().sum()
+    }
+
+    pub fn sucessos(&self) -> usize {
+        self.por_status.get(&201).copied().unwrap_or(0)
+    }
+
+    /// Quantos cadastros foram feitos por segundo.
+    pub fn vazao(&self) -> f64 {
+        self.total() as f64 / self.duracao.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// A fração dos cadastros que não criaram a conta.
+    pub fn taxa_de_erro(&self) -> f64 {
+        match self.total() {
+            0 => 0.0,
+            total => (total - self.sucessos()) as f64 / total as f64,
+        }
+    }
+
+    /// A latência abaixo da qual ficam `p` por cento dos cadastros.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::simulacao::ResultadoSimulacao;
+    /// # use std::time::Duration;
+    /// let r = ResultadoSimulacao {
+    ///     latencias: (1..=100).map(Duration::from_millis).collect(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(r.percentil(50), Duration::from_millis(50));
+    /// assert_eq!(r.percentil(99), Duration::from_millis(99));
+    /// assert_eq!(r.percentil(100), Duration::from_millis(100));
+    /// ```
+    pub fn percentil(&self, p: usize) -> Duration {
+        match self.latencias.len() {
+            0 => Duration::ZERO,
+            n => self.latencias[(n * p).div_ceil(100).clamp(1, n) - 1],
+        }
+    }
+}
+
+impl fmt::Display for ResultadoSimulacao {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} cadastros em {:.1}s: {:.1} cadastros/s, {:.1}% de erros",
+            self.total(),
+            self.duracao.as_secs_f64(),
+            self.vazao(),
+            self.taxa_de_erro() * 100.0,
+        )?;
+        writeln!(
+            f,
+            "Latência: p50 {:?}, p95 {:?}, p99 {:?}",
+            self.percentil(50),
+            self.percentil(95),
+            self.percentil(99),
+        )?;
+        for (status, n) in &self.por_status {
+            match status {
+                0 => writeln!(f, "Sem resposta: {n}")?,
+                status => writeln!(f, "HTTP {status}: {n}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Erros da simulação.
+#[derive(Debug, thiserror::Error)]
+pub enum ErroDeSimulacao {
+    #[error("Erro ao escutar numa porta local: {0}")]
+    Porta(#[from] std::io::Error),
+    #[error("Erro ao apagar as contas sintéticas: {0}")]
+    Limpeza(#[from] ErroLdap),
+}
+
+/// Faz os cadastros sintéticos da `simulacao` pela API, com a configuração
+/// `cfg` e o LDAP `ldap`.
+///
+/// O Gnosys da configuração é trocado pelo sintético, e o limite de
+/// cadastros por IP é desligado, já que todos os cadastros saem da própria
+/// máquina.
+pub async fn simular<F: FonteLdap + Clone + 'static>(
+    mut cfg: Configuracao,
+    ldap: F,
+    simulacao: &ConfiguracaoSimulacao,
+) -> Result<ResultadoSimulacao, ErroDeSimulacao> {
+    let gnosys = servir(gnosys_sintetico()).await?;
+    cfg.gnosys_url = format!("http://{gnosys}");
+    cfg.cadastros_por_ip = None;
+    let api = servir(crate::api::router(Arc::new(cfg), ldap.clone())).await?;
+
+    let cliente = reqwest::Client::new();
+    let dres: Vec<_> = (0..simulacao.cadastros)
+        .map(|i| simulacao.primeiro_dre as usize + i)
+        .collect();
+
+    let inicio = Instant::now();
+    let cadastros: Vec<_> = stream::iter(dres)
+        .map(|dre| {
+            let cliente = &cliente;
+            async move {
+                let dre = format!("{dre:09}");
+                let corpo = json!({
+                    "dre": dre,
+                    "data": "01/03/2025",
+                    "hora": "10:00",
+                    "codigo": CODIGO_SINTETICO,
+                    "nome": nome_do_dre(&dre),
+                    "email": format!("{dre}@simulacao.invalid"),
+                    "telefone": "(21) 98765-4321",
+                    "senha": format!("Simulacao{dre}"),
+                });
+                let inicio = Instant::now();
+                let status = cliente
+                    .post(format!("http://{api}/api/cadastrar"))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(corpo.to_string())
+                    .send()
+                    .await
+                    .map_or(0, |r| r.status().as_u16());
+                (dre, status, inicio.elapsed())
+            }
+        })
+        .buffer_unordered(simulacao.concorrencia.max(1))
+        .collect()
+        .await;
+
+    let mut resultado = ResultadoSimulacao {
+        duracao: inicio.elapsed(),
+        ..Default::default()
+    };
+    for (_, status, latencia) in &cadastros {
+        *resultado.por_status.entry(*status).or_default() += 1;
+        resultado.latencias.push(*latencia);
+    }
+    resultado.latencias.sort();
+
+    if simulacao.limpar {
+        let mut conexao = ldap.abrir().await?;
+        let r: Result<(), ErroLdap> = async {
+            for (dre, status, _) in &cadastros {
+                if *status != 201 {
+                    continue;
+                }
+                if let Some(conta) =
+                    buscar_conta_por_dre(dre, &mut conexao).await?
+                {
+                    conexao.remover(&conta.dn).await?;
+                    resultado.apagadas += 1;
+                }
+            }
+            Ok(())
+        }
+        .await;
+        ldap.fechar(conexao).await?;
+        r?;
+    }
+
+    Ok(resultado)
+}
+
+/// Serve o `app` numa porta local livre, retornando o endereço dela.
+async fn servir(app: Router) -> Result<SocketAddr, std::io::Error> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let endereco = listener.local_addr()?;
+    tokio::spawn(async move {
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+        let _ = axum::serve(listener, app).await;
+    });
+    Ok(endereco)
+}
+
+/// Um Gnosys que segue o fluxo do [`portal_ufrj`](crate::portal_ufrj) e
+/// considera válido qualquer documento com o [`CODIGO_SINTETICO`], de um
+/// aluno de Ciência da Computação.
+fn gnosys_sintetico() -> Router {
+    async fn formulario() -> Response {
+        (
+            [(header::SET_COOKIE, "JSESSIONID=simulacao; Path=/")],
+            Html(concat!(
+                r#"<html><body><form id="gnosys-filtro">"#,
+                r#"<input type="hidden" name="javax.faces.ViewState" "#,
+                r#"value="j_id1" /></form></body></html>"#,
+            )),
+        )
+            .into_response()
+    }
+
+    async fn autenticar(
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Html<String> {
+        let valido = form
+            .get("assinatura")
+            .is_some_and(|c| c == CODIGO_SINTETICO);
+        Html(match valido {
+            true => format!(
+                concat!(
+                    r#"<html><body><span id="msgDocumentoValido">Documento válido</span>"#,
+                    r#"<div class="gnosys-item-visualizacao">{}</div>"#,
+                    r#"<div class="gnosys-item-visualizacao">00.000.000-0</div>"#,
+                    r#"<div class="gnosys-item-visualizacao">Ciência da Computação</div>"#,
+                    "</body></html>",
+                ),
+                nome_do_dre(form.get("alunoMatricula").map_or("", |d| d))
+                    .to_uppercase(),
+            ),
+            false => concat!(
+                r#"<html><body><span id="msgDocumentoInvalido">"#,
+                "Documento inválido</span></body></html>",
+            )
+            .to_string(),
+        })
+    }
+
+    Router::new()
+        .route(
+            "/Documentos/autenticacao/regularmenteMatriculado",
+            get(formulario),
+        )
+        .route("/Documentos/autenticacao.seam", post(autenticar))
+}
+
+/// O nome do aluno sintético do `dre`, o mesmo no cadastro e no Gnosys.
+/// Os nomes de 8000 DREs consecutivos são diferentes.
+fn nome_do_dre(dre: &str) -> String {
+    nome_sintetico(dre.parse::<usize>().unwrap_or(0) % 8000)
+}
@@ -0,0 +1,308 @@
+//! Segundo fator das operações administrativas destrutivas, como a remoção
+//! de uma conta e a redefinição da senha. O supervisor manda, além da senha,
+//! o código TOTP (RFC 6238) do aplicativo autenticador, gerado a partir do
+//! segredo provisionado para ele. Cada código vale por `passo_segundos`, com
+//! uma tolerância de `janela` passos para os relógios fora de sincronia, e só
+//! pode ser usado uma vez. Depois de [MAXIMO_FALHAS] códigos errados seguidos,
+//! o supervisor fica bloqueado por [BLOQUEIO_MINUTOS], para os 6 dígitos não
+//! serem adivinhados por tentativa.
+use crate::utils::hashes::iguais_tempo_constante;
+use base32::Alphabet;
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use sha1::Sha1;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// O segundo fator, configurado como
+///
+/// ```yaml
+/// totp:
+///   janela: 1
+///   passo_segundos: 30
+///   segredos:
+///     fulano: "JBSWY3DPEHPK3PXP"
+/// ```
+///
+/// Os segredos ficam em base32, como os autenticadores os mostram. Sem essa
+/// configuração, as operações destrutivas não existem na API.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConfiguracaoTotp {
+    /// O segredo de cada supervisor, pelo uid.
+    pub segredos: BTreeMap<String, SecretString>,
+    /// Quantos passos antes e depois do atual ainda são aceitos.
+    #[serde(default = "janela_padrao")]
+    pub janela: u64,
+    #[serde(default = "passo_padrao")]
+    pub passo_segundos: u64,
+}
+
+/// Quantos códigos errados seguidos bloqueiam o supervisor.
+pub const MAXIMO_FALHAS: u32 = 5;
+
+/// Por quanto tempo o supervisor fica bloqueado depois da última falha.
+pub const BLOQUEIO_MINUTOS: i64 = 15;
+
+fn janela_padrao() -> u64 {
+    1
+}
+
+fn passo_padrao() -> u64 {
+    30
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ErroTotp {
+    #[error("O supervisor {0:?} não tem segredo TOTP")]
+    SemSegredo(String),
+    #[error("O segredo TOTP do supervisor {0:?} não está em base32")]
+    SegredoInvalido(String),
+    #[error("O código TOTP está incorreto ou expirou")]
+    Incorreto,
+    #[error("O código TOTP já foi usado")]
+    Reusado,
+    #[error(
+        "Códigos TOTP errados demais; tente de novo em {BLOQUEIO_MINUTOS} \
+         minutos"
+    )]
+    Bloqueado,
+}
+
+/// O código de 6 dígitos do `segredo` no `passo`, contado em passos desde
+/// 01/01/1970.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::totp::codigo;
+/// // Do apêndice B da RFC 6238
+/// assert_eq!(codigo(b"12345678901234567890", 59 / 30), "287082");
+/// ```
+pub fn codigo(segredo: &[u8], passo: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(segredo)
+        .expect("o HMAC aceita chaves de qualquer tamanho");
+    mac.update(&passo.to_be_bytes());
+    let h = mac.finalize().into_bytes();
+
+    // O truncamento dinâmico da RFC 4226
+    let i = (h[19] & 0xf) as usize;
+    let n = u32::from_be_bytes([h[i] & 0x7f, h[i + 1], h[i + 2], h[i + 3]]);
+    format!("{:06}", n % 1_000_000)
+}
+
+/// Decodifica um segredo em base32, aceitando os espaços e as minúsculas com
+/// que os autenticadores costumam mostrá-lo.
+pub fn decodificar_segredo(segredo: &str) -> Option<Vec<u8>> {
+    let segredo = segredo.replace(' ', "").to_uppercase();
+    base32::decode(
+        Alphabet::Rfc4648 { padding: false },
+        segredo.trim_end_matches('='),
+    )
+    .filter(|s| !s.is_empty())
+}
+
+/// Verifica os códigos, lembrando o último passo usado por cada supervisor
+/// para recusar o reuso, e as falhas seguidas dele para o bloqueio.
+#[derive(Debug, Default)]
+pub struct VerificadorTotp {
+    usados: Mutex<HashMap<String, u64>>,
+    /// Quantas falhas seguidas cada supervisor teve, e quando foi a última.
+    falhas: Mutex<HashMap<String, (u32, DateTime<Utc>)>>,
+}
+
+impl VerificadorTotp {
+    /// Verifica o `codigo_recebido` do supervisor `uid` em `agora`. Um código
+    /// aceito, ou um de um passo anterior a ele, não é aceito de novo.
+    ///
+    /// # Errors
+    ///
+    /// Retorna [ErroTotp::Bloqueado], sem olhar o código, enquanto o `uid`
+    /// estiver bloqueado pelas falhas seguidas. Um código aceito zera as
+    /// falhas.
+    pub fn verificar(
+        &self,
+        cfg: &ConfiguracaoTotp,
+        uid: &str,
+        codigo_recebido: &str,
+        agora: DateTime<Utc>,
+    ) -> Result<(), ErroTotp> {
+        let segredo = cfg
+            .segredos
+            .get(uid)
+            .ok_or_else(|| ErroTotp::SemSegredo(uid.to_string()))?;
+        let segredo = decodificar_segredo(segredo.expose_secret())
+            .ok_or_else(|| ErroTotp::SegredoInvalido(uid.to_string()))?;
+
+        let mut falhas = self.falhas.lock().unwrap();
+        let bloqueio = TimeDelta::minutes(BLOQUEIO_MINUTOS);
+        match falhas.get(uid) {
+            Some(&(n, ultima))
+                if n >= MAXIMO_FALHAS && agora < ultima + bloqueio =>
+            {
+                return Err(ErroTotp::Bloqueado);
+            },
+            Some(&(_, ultima)) if agora >= ultima + bloqueio => {
+                falhas.remove(uid);
+            },
+            _ => {},
+        }
+
+        let r = self.conferir(cfg, &segredo, uid, codigo_recebido, agora);
+        match r {
+            Ok(()) => {
+                falhas.remove(uid);
+            },
+            Err(_) => {
+                let (n, ultima) =
+                    falhas.entry(uid.to_string()).or_insert((0, agora));
+                *n += 1;
+                *ultima = agora;
+            },
+        }
+        r
+    }
+
+    /// Confere o código, sem as falhas, e guarda o passo dele se for aceito.
+    fn conferir(
+        &self,
+        cfg: &ConfiguracaoTotp,
+        segredo: &[u8],
+        uid: &str,
+        codigo_recebido: &str,
+        agora: DateTime<Utc>,
+    ) -> Result<(), ErroTotp> {
+        let atual = agora.timestamp().max(0) as u64 / cfg.passo_segundos.max(1);
+        let passo = (atual.saturating_sub(cfg.janela)..=atual + cfg.janela)
+            .find(|&p| {
+                iguais_tempo_constante(&codigo(segredo, p), codigo_recebido)
+            })
+            .ok_or(ErroTotp::Incorreto)?;
+
+        let mut usados = self.usados.lock().unwrap();
+        if usados.get(uid).is_some_and(|&usado| passo <= usado) {
+            return Err(ErroTotp::Reusado);
+        }
+        usados.insert(uid.to_string(), passo);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// O segredo da RFC 6238, `12345678901234567890`, em base32.
+    const SEGREDO: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn cfg() -> ConfiguracaoTotp {
+        ConfiguracaoTotp {
+            segredos: [("prof".to_string(), SEGREDO.into())].into(),
+            janela: 1,
+            passo_segundos: 30,
+        }
+    }
+
+    fn em(segundos: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(segundos, 0).unwrap()
+    }
+
+    #[test]
+    fn gera_os_codigos_da_rfc() {
+        let segredo = decodificar_segredo(SEGREDO).unwrap();
+        assert_eq!(segredo, b"12345678901234567890");
+        assert_eq!(codigo(&segredo, 1111111109 / 30), "081804");
+        assert_eq!(codigo(&segredo, 1234567890 / 30), "005924");
+        assert_eq!(codigo(&segredo, 2000000000 / 30), "279037");
+    }
+
+    #[test]
+    fn aceita_a_janela_e_recusa_o_reuso() {
+        let verificador = VerificadorTotp::default();
+        let segredo = decodificar_segredo(SEGREDO).unwrap();
+        let agora = em(1111111109);
+        let passo = 1111111109 / 30;
+
+        // Um passo antes ainda vale, mas dois não
+        let antigo = codigo(&segredo, passo - 2);
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &antigo, agora),
+            Err(ErroTotp::Incorreto),
+        );
+        let anterior = codigo(&segredo, passo - 1);
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &anterior, agora),
+            Ok(()),
+        );
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &anterior, agora),
+            Err(ErroTotp::Reusado),
+        );
+
+        let atual = codigo(&segredo, passo);
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &atual, agora),
+            Ok(())
+        );
+        assert_eq!(
+            verificador.verificar(&cfg(), "outro", &atual, agora),
+            Err(ErroTotp::SemSegredo("outro".to_string())),
+        );
+    }
+
+    #[test]
+    fn bloqueia_depois_das_falhas_seguidas() {
+        let verificador = VerificadorTotp::default();
+        let segredo = decodificar_segredo(SEGREDO).unwrap();
+        let agora = em(1111111109);
+        let passo = 1111111109 / 30;
+        let errado = codigo(&segredo, passo + 5);
+
+        for _ in 0..MAXIMO_FALHAS {
+            assert_eq!(
+                verificador.verificar(&cfg(), "prof", &errado, agora),
+                Err(ErroTotp::Incorreto),
+            );
+        }
+        // Nem o código certo vale durante o bloqueio
+        let atual = codigo(&segredo, passo);
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &atual, agora),
+            Err(ErroTotp::Bloqueado),
+        );
+
+        let depois = agora + TimeDelta::minutes(BLOQUEIO_MINUTOS);
+        let passo = depois.timestamp() as u64 / 30;
+        let atual = codigo(&segredo, passo);
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &atual, depois),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn acerto_zera_as_falhas() {
+        let verificador = VerificadorTotp::default();
+        let segredo = decodificar_segredo(SEGREDO).unwrap();
+        let agora = em(1111111109);
+        let passo = 1111111109 / 30;
+        let errado = codigo(&segredo, passo + 5);
+
+        for _ in 1..MAXIMO_FALHAS {
+            verificador
+                .verificar(&cfg(), "prof", &errado, agora)
+                .unwrap_err();
+        }
+        let atual = codigo(&segredo, passo);
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &atual, agora),
+            Ok(())
+        );
+        assert_eq!(
+            verificador.verificar(&cfg(), "prof", &errado, agora),
+            Err(ErroTotp::Incorreto),
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Sincronização dos grupos LDAP das turmas com as listagens de inscritos
+//! exportadas do SIGA. Cada turma tem um grupo `turma-<código>-<período>` com
+//! os uids dos inscritos que têm conta, e os grupos dos períodos encerrados
+//! são removidos.
+use crate::ldap::ErroLdap;
+use crate::ldap::conexao::FonteLdap;
+use crate::ldap::turmas::{
+    MudancaTurma, Periodo, Turma, aplicar, buscar_grupos, comparar,
+    uids_por_dre,
+};
+use crate::utils::listagem::ler_listagem;
+use crate::utils::validacao_entradas::processar_dre;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use thiserror::Error;
+
+/// Onde ficam os grupos das turmas.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoTurmas {
+    /// A OU dos grupos. Os grupos `turma-*` dela são gerenciados só pelo
+    /// alumnic.
+    pub ou: String,
+}
+
+impl Default for ConfiguracaoTurmas {
+    fn default() -> Self {
+        Self {
+            ou: "ou=turmas,ou=grupos,dc=dcc,dc=ufrj,dc=br".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ErroDeTurmas {
+    #[error("A listagem não tem a coluna {0:?}")]
+    SemColuna(&'static str),
+    #[error("A linha {linha} da listagem tem um {campo} inválido: {valor:?}")]
+    LinhaInvalida {
+        linha: usize,
+        campo: &'static str,
+        valor: String,
+    },
+    #[error("Houve um problema ao ler os grupos no LDAP: {0}")]
+    ErroLdap(#[from] ErroLdap),
+}
+
+/// As colunas da listagem e os nomes aceitos para cada uma no cabeçalho.
+const COLUNAS: [(&str, &[&str]); 3] = [
+    ("disciplina", &["disciplina", "codigo", "cod. disciplina"]),
+    ("periodo", &["periodo", "periodo letivo"]),
+    ("dre", &["dre"]),
+];
+
+/// Lê a listagem de inscritos do SIGA, um CSV separado por vírgula ou ponto
+/// e vírgula cujo cabeçalho tem as colunas `disciplina` (ou `codigo`),
+/// `periodo` e `dre`, em qualquer ordem e entre outras. Retorna os DREs
+/// inscritos em cada turma.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::turmas::ler_inscricoes;
+/// let inscricoes = ler_inscricoes(
+///     "Nome;DRE;Disciplina;Período\n\
+///      João;123456789;MAB123;2024/1\n\
+///      Maria;987654321;MAB123;2024/1\n",
+/// )
+/// .unwrap();
+/// let (turma, dres) = inscricoes.iter().next().unwrap();
+/// assert_eq!(turma.grupo(), "turma-mab123-2024.1");
+/// assert_eq!(dres.len(), 2);
+/// ```
+pub fn ler_inscricoes(
+    csv: &str,
+) -> Result<BTreeMap<Turma, BTreeSet<String>>, ErroDeTurmas> {
+    let linhas = ler_listagem(csv, COLUNAS).map_err(ErroDeTurmas::SemColuna)?;
+
+    let mut inscricoes: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    for (linha, [disciplina, periodo, dre]) in linhas {
+        let invalido =
+            |campo: &'static str, valor: &str| ErroDeTurmas::LinhaInvalida {
+                linha,
+                campo,
+                valor: valor.to_string(),
+            };
+
+        if disciplina.is_empty()
+            || !disciplina.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(invalido("disciplina", &disciplina));
+        }
+        let p = Periodo::interpretar(&periodo)
+            .ok_or_else(|| invalido("período", &periodo))?;
+        let d = processar_dre(&dre).ok_or_else(|| invalido("DRE", &dre))?;
+
+        inscricoes
+            .entry(Turma {
+                codigo: disciplina.to_uppercase(),
+                periodo: p,
+            })
+            .or_default()
+            .insert(d);
+    }
+
+    Ok(inscricoes)
+}
+
+/// As alterações necessárias para que os grupos reflitam a listagem.
+#[derive(Debug)]
+pub struct Sincronizacao {
+    pub mudancas: Vec<MudancaTurma>,
+    /// Os DREs da listagem que não têm conta, e por isso ficam fora dos
+    /// grupos.
+    pub sem_conta: BTreeSet<String>,
+}
+
+/// Compara a `listagem` de inscritos com os grupos das turmas no `ldap`.
+pub async fn planejar<F: FonteLdap>(
+    listagem: &str,
+    cfg: &ConfiguracaoTurmas,
+    ldap: &F,
+) -> Result<Sincronizacao, ErroDeTurmas> {
+    let inscricoes = ler_inscricoes(listagem)?;
+
+    let mut conexao = ldap.abrir().await?;
+    let r = async {
+        Ok::<_, ErroLdap>((
+            uids_por_dre(&mut conexao).await?,
+            buscar_grupos(&cfg.ou, &mut conexao).await?,
+        ))
+    }
+    .await;
+    ldap.fechar(conexao).await?;
+    let (uids, grupos) = r?;
+
+    let mut sem_conta = BTreeSet::new();
+    let inscritos = inscricoes
+        .into_iter()
+        .map(|(turma, dres)| {
+            let membros = dres
+                .into_iter()
+                .filter_map(|dre| {
+                    let uid = uids.get(&dre).cloned();
+                    if uid.is_none() {
+                        sem_conta.insert(dre);
+                    }
+                    uid
+                })
+                .collect();
+            (turma, membros)
+        })
+        .collect();
+
+    Ok(Sincronizacao {
+        mudancas: comparar(&inscritos, &grupos),
+        sem_conta,
+    })
+}
+
+/// Aplica as `mudancas` nos grupos, retornando o resultado de cada uma. Uma
+/// mudança que falha não impede as outras.
+pub async fn sincronizar<F: FonteLdap>(
+    mudancas: &[MudancaTurma],
+    cfg: &ConfiguracaoTurmas,
+    ldap: &F,
+) -> Result<Vec<Result<(), ErroLdap>>, ErroLdap> {
+    let mut conexao = ldap.abrir().await?;
+
+    let mut resultados = vec![];
+    for mudanca in mudancas {
+        resultados.push(aplicar(mudanca, &cfg.ou, &mut conexao).await);
+    }
+
+    ldap.fechar(conexao).await?;
+    Ok(resultados)
+}
@@ -0,0 +1,148 @@
+//! Comparação de usernames que se confundem na leitura, como `joaosilva` e
+//! `joa0silva`, ou `joaosilva` e `joaoslva`. Um username novo parecido com um
+//! que já existe leva a erros do suporte e facilita que alguém se passe por
+//! outra pessoa dentro do Instituto.
+use serde::Deserialize;
+
+/// Os trechos que se confundem na leitura, trocados pelo segundo antes da
+/// comparação.
+const PARECIDOS: &[(&str, &str)] = &[
+    ("rn", "m"),
+    ("vv", "w"),
+    ("0", "o"),
+    ("1", "l"),
+    ("i", "l"),
+    ("5", "s"),
+];
+
+/// A rejeição dos usernames confundíveis com os que já existem, configurada
+/// como
+///
+/// ```yaml
+/// usuario_novo:
+///   usernames_confundiveis:
+///     omissoes: true
+///     tamanho_minimo: 6
+///     validade_cache_segundos: 30
+/// ```
+///
+/// Os caracteres parecidos, como `0` e `o`, e a troca de dois caracteres
+/// vizinhos sempre são considerados.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfiguracaoConfundiveis {
+    /// Se um caractere a mais ou a menos também confunde, como em
+    /// `joaosilva` e `joaoslva`. Um dígito a mais no fim, como nos usernames
+    /// com sufixo numérico, não confunde.
+    pub omissoes: bool,
+    /// O tamanho mínimo do menor username para que um caractere a mais ou a
+    /// menos confunda; nos curtos, como `anab` e `anb`, uma letra já faz
+    /// diferença.
+    pub tamanho_minimo: usize,
+    /// Por quanto tempo a API reaproveita os uids carregados do LDAP nas
+    /// consultas dos usernames livres, em vez de carregá-los a cada
+    /// requisição.
+    pub validade_cache_segundos: u64,
+}
+
+impl Default for ConfiguracaoConfundiveis {
+    fn default() -> Self {
+        Self {
+            omissoes: true,
+            tamanho_minimo: 6,
+            validade_cache_segundos: 30,
+        }
+    }
+}
+
+/// O `username` em minúsculas, com os trechos [PARECIDOS] trocados.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::confundiveis::esqueleto;
+/// assert_eq!(esqueleto("Joa0Si1va"), "joaosllva");
+/// assert_eq!(esqueleto("carnen"), "camen");
+/// ```
+pub fn esqueleto(username: &str) -> String {
+    PARECIDOS
+        .iter()
+        .fold(username.to_lowercase(), |u, (de, para)| u.replace(de, para))
+}
+
+/// Se `a` é igual a `b` trocando dois caracteres vizinhos.
+fn transposicao(a: &[char], b: &[char]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diferencas: Vec<_> = (0..a.len()).filter(|&i| a[i] != b[i]).collect();
+    matches!(
+        diferencas[..],
+        [i, j] if j == i + 1 && a[i] == b[j] && a[j] == b[i]
+    )
+}
+
+/// Se `longo` é igual a `curto` com um caractere a mais, que não seja um
+/// dígito no fim.
+fn omissao(longo: &[char], curto: &[char]) -> bool {
+    if longo.len() != curto.len() + 1 {
+        return false;
+    }
+    let i = (0..curto.len())
+        .find(|&i| longo[i] != curto[i])
+        .unwrap_or(curto.len());
+    let digito_no_fim = i == curto.len() && longo[i].is_ascii_digit();
+    !digito_no_fim && longo[i + 1..] == curto[i..]
+}
+
+impl ConfiguracaoConfundiveis {
+    /// Se os usernames `a` e `b`, que são diferentes, se confundem. Um
+    /// username não se confunde com ele mesmo.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::utils::confundiveis::ConfiguracaoConfundiveis;
+    /// let cfg = ConfiguracaoConfundiveis::default();
+    /// assert!(cfg.confundiveis("joaosilva", "joa0silva"));
+    /// assert!(cfg.confundiveis("joaosilva", "joaoslva"));
+    /// assert!(cfg.confundiveis("joaosilva", "jaoosilva"));
+    /// assert!(cfg.confundiveis("mariarn", "mariam"));
+    ///
+    /// assert!(!cfg.confundiveis("joaosilva", "joaosilva"));
+    /// assert!(!cfg.confundiveis("joaosilva", "joaosilva2"));
+    /// assert!(!cfg.confundiveis("anab", "anb"));
+    /// assert!(!cfg.confundiveis("joaops", "joaopc"));
+    /// ```
+    pub fn confundiveis(&self, a: &str, b: &str) -> bool {
+        if a.eq_ignore_ascii_case(b) {
+            return false;
+        }
+
+        let a: Vec<char> = esqueleto(a).chars().collect();
+        let b: Vec<char> = esqueleto(b).chars().collect();
+        let (curto, longo) = if a.len() <= b.len() {
+            (&a, &b)
+        } else {
+            (&b, &a)
+        };
+
+        a == b
+            || transposicao(&a, &b)
+            || (self.omissoes
+                && curto.len() >= self.tamanho_minimo
+                && omissao(longo, curto))
+    }
+
+    /// O primeiro dos `existentes` que se confunde com o `username`, se
+    /// houver.
+    pub fn parecido<'a>(
+        &self,
+        username: &str,
+        existentes: impl IntoIterator<Item = &'a str>,
+    ) -> Option<&'a str> {
+        existentes
+            .into_iter()
+            .find(|e| self.confundiveis(username, e))
+    }
+}
@@ -57,10 +57,28 @@ pub fn hash_ssha(passwd: &SecretString) -> SecretString {
     r
 }
 
-fn hash_ssha_with_salt(passwd: &SecretString, salt: &[u8; 4]) -> SecretString {
+/// Faz o mesmo que [hash_ssha], mas com um `salt` fornecido em vez de
+/// aleatório.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::hashes::hash_ssha_with_salt;
+/// # use secrecy::ExposeSecret;
+/// assert_eq!(
+///     hash_ssha_with_salt(&"12345678".to_string().into(), &[1, 2, 3, 4])
+///         .expose_secret(),
+///     hash_ssha_with_salt(&"12345678".to_string().into(), &[1, 2, 3, 4])
+///         .expose_secret(),
+/// );
+/// ```
+pub fn hash_ssha_with_salt(
+    passwd: &SecretString,
+    salt: &[u8; 4],
+) -> SecretString {
     let mut hasher = Sha1::new();
     hasher.update(passwd.expose_secret().as_bytes());
-    hasher.update(&salt);
+    hasher.update(salt);
     let mut hash = hasher.finalize();
 
     let mut salted = BASE64_STANDARD.encode([hash.as_slice(), salt].concat());
@@ -95,3 +113,40 @@ pub fn compare_ssha(passwd: &SecretString, hash: &SecretString) -> bool {
 
     new_hash.expose_secret() == hash.expose_secret()
 }
+
+/// Verifica se `hash` é um hash SSHA que pode ser passado para
+/// [compare_ssha] sem pânico. O hash de uma conta bloqueada, com o prefixo
+/// `!`, não é.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::hashes::ssha_valido;
+/// assert!(ssha_valido("{SSHA}k2vI0fdKSCGeA4EEVQlZrBHTOdcBAgME"));
+/// assert!(!ssha_valido("!{SSHA}k2vI0fdKSCGeA4EEVQlZrBHTOdcBAgME"));
+/// assert!(!ssha_valido("{SSHA}abc"));
+/// ```
+pub fn ssha_valido(hash: &str) -> bool {
+    hash.strip_prefix("{SSHA}")
+        .and_then(|h| BASE64_STANDARD.decode(h).ok())
+        .is_some_and(|h| h.len() == 24)
+}
+
+/// Compara duas strings sem depender da posição do primeiro byte diferente,
+/// para não vazar um token ou um código por diferença de tempo.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::hashes::iguais_tempo_constante;
+/// assert!(iguais_tempo_constante("287082", "287082"));
+/// assert!(!iguais_tempo_constante("287082", "287083"));
+/// assert!(!iguais_tempo_constante("287082", "28708"));
+/// ```
+pub fn iguais_tempo_constante(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
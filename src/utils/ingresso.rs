@@ -0,0 +1,101 @@
+//! O período de ingresso de um aluno, tirado do DRE, usado para agrupar as
+//! métricas e os relatórios por turma de entrada.
+use crate::utils::periodo::Periodo;
+
+/// O período de ingresso do aluno com o `dre`. Os DREs da UFRJ têm 9
+/// dígitos: o primeiro é o século do ingresso (`0` para 1900 e `1` para
+/// 2000), seguido dos dois últimos dígitos do ano e do semestre.
+///
+/// Também são aceitos os formatos dos DREs antigos em listas e planilhas:
+/// com pontos, hífens ou espaços entre os dígitos, como `119.134.567`, e com
+/// 8 dígitos, dos DREs de 1900 guardados como número, que perderam o zero do
+/// começo.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::ingresso::periodo_do_dre;
+/// assert_eq!(periodo_do_dre("122134567").unwrap().to_string(), "2022.1");
+/// assert_eq!(periodo_do_dre("125234567").unwrap().to_string(), "2025.2");
+/// assert_eq!(periodo_do_dre("098134567").unwrap().to_string(), "1998.1");
+/// assert_eq!(periodo_do_dre("98134567").unwrap().to_string(), "1998.1");
+/// assert_eq!(periodo_do_dre("119.134.567").unwrap().to_string(), "2019.1");
+/// assert_eq!(periodo_do_dre("123456789"), None);
+/// ```
+pub fn periodo_do_dre(dre: &str) -> Option<Periodo> {
+    let mut digitos = String::with_capacity(9);
+    for c in dre.trim().chars() {
+        match c {
+            '0'..='9' => digitos.push(c),
+            '.' | '-' | ' ' => {},
+            _ => return None,
+        }
+    }
+    if digitos.len() == 8 {
+        digitos.insert(0, '0');
+    }
+    if digitos.len() != 9 {
+        return None;
+    }
+
+    let seculo = match &digitos[..1] {
+        "0" => 1900,
+        "1" => 2000,
+        _ => return None,
+    };
+    let ano: u16 = digitos[1..3].parse().ok()?;
+    let semestre = match &digitos[3..4] {
+        "1" => 1,
+        "2" => 2,
+        _ => return None,
+    };
+
+    Some(Periodo {
+        ano: seculo + ano,
+        semestre,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn periodo(dre: &str) -> Option<String> {
+        periodo_do_dre(dre).map(|p| p.to_string())
+    }
+
+    #[test]
+    fn formatos_atuais() {
+        assert_eq!(periodo("100134567").as_deref(), Some("2000.1"));
+        assert_eq!(periodo("119234567").as_deref(), Some("2019.2"));
+        assert_eq!(periodo(" 125134567 ").as_deref(), Some("2025.1"));
+    }
+
+    #[test]
+    fn formatos_historicos() {
+        // Antes de 2000, com e sem o zero do começo
+        assert_eq!(periodo("095234567").as_deref(), Some("1995.2"));
+        assert_eq!(periodo("95234567").as_deref(), Some("1995.2"));
+        assert_eq!(periodo("099134567").as_deref(), Some("1999.1"));
+        // Com os separadores das listas antigas
+        assert_eq!(periodo("109.134.567").as_deref(), Some("2009.1"));
+        assert_eq!(periodo("10913456-7").as_deref(), Some("2009.1"));
+        assert_eq!(periodo("1 09 1 34567").as_deref(), Some("2009.1"));
+    }
+
+    #[test]
+    fn dres_invalidos() {
+        for dre in [
+            "",
+            "1234567",
+            "1221345678",
+            "223134567",
+            "122334567",
+            "122034567",
+            "12213456a",
+            "122/134567",
+        ] {
+            assert_eq!(periodo(dre), None, "{dre:?}");
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Leitura das listagens em CSV exportadas do SIGA. As listagens são
+//! separadas por vírgula ou ponto e vírgula e têm um cabeçalho, cujas colunas
+//! podem estar em qualquer ordem e ter nomes diferentes conforme o relatório
+//! de onde vieram.
+use deunicode::deunicode;
+
+/// Lê a `listagem`, retornando o número de cada linha e os valores das
+/// `colunas` nela, na mesma ordem. Cada coluna é dada pelo nome dela e pelos
+/// nomes aceitos no cabeçalho, sem acentos e em minúsculas. Linhas vazias ou
+/// começadas por `#` são ignoradas, e um campo que falta na linha fica vazio.
+///
+/// # Errors
+///
+/// Retorna o nome da primeira coluna que não está no cabeçalho.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::listagem::ler_listagem;
+/// let linhas = ler_listagem(
+///     "Nome;DRE\n\
+///      \"João\";123456789\n\
+///      \n\
+///      Maria\n",
+///     [("dre", &["dre"]), ("nome", &["nome", "nome civil"])],
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     linhas,
+///     [
+///         (2, ["123456789".to_string(), "João".to_string()]),
+///         (4, ["".to_string(), "Maria".to_string()]),
+///     ],
+/// );
+///
+/// assert_eq!(ler_listagem("Nome\nJoão\n", [("dre", &["dre"])]), Err("dre"));
+/// ```
+pub fn ler_listagem<const N: usize>(
+    listagem: &str,
+    colunas: [(&'static str, &[&str]); N],
+) -> Result<Vec<(usize, [String; N])>, &'static str> {
+    let mut linhas = listagem
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'));
+    let Some((_, cabecalho)) = linhas.next() else {
+        return Ok(vec![]);
+    };
+
+    let separador = if cabecalho.contains(';') { ';' } else { ',' };
+    let campos = |linha: &str| -> Vec<String> {
+        linha
+            .split(separador)
+            .map(|c| c.trim().trim_matches('"').trim().to_string())
+            .collect()
+    };
+    let cabecalho: Vec<_> = campos(cabecalho)
+        .iter()
+        .map(|c| deunicode(c).to_lowercase())
+        .collect();
+
+    let mut posicoes = [0; N];
+    for (posicao, (coluna, nomes)) in posicoes.iter_mut().zip(colunas) {
+        *posicao = cabecalho
+            .iter()
+            .position(|c| nomes.contains(&c.as_str()))
+            .ok_or(coluna)?;
+    }
+
+    Ok(linhas
+        .map(|(linha, conteudo)| {
+            let campos = campos(conteudo);
+            (
+                linha,
+                posicoes.map(|i| campos.get(i).cloned().unwrap_or_default()),
+            )
+        })
+        .collect())
+}
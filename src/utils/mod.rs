@@ -1,6 +1,11 @@
 //! Módulo de utilidades gerais. São funcionalidades úteis, mas que não
 //! necessitam de integração com o resto da biblioteca.
 
+pub mod confundiveis;
 pub mod hashes;
+pub mod ingresso;
+pub mod listagem;
 pub mod nome;
+pub mod periodo;
+pub mod triagem_nome;
 pub mod validacao_entradas;
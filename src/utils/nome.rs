@@ -10,7 +10,7 @@ use thiserror::Error;
 use unicode_normalization::UnicodeNormalization;
 use derive_more::Display;
 
-const PALAVRAS_IGNORADAS: &'static [&'static str] = &[
+pub(crate) const PALAVRAS_IGNORADAS: &[&str] = &[
     "de", "do", "da", "dos", "das", "e",
 ];
 
@@ -112,11 +112,7 @@ impl Nome {
                 .collect()
         }
 
-        let nomes: Vec<String> = sem_acentos_e_minusculo(&self.0)
-            .split_whitespace()
-            .filter(|x| !PALAVRAS_IGNORADAS.contains(x))
-            .map(str::to_string)
-            .collect();
+        let nomes = self.partes();
 
         // Isso gera um iterador com os elementos contando em binário, ou seja,
         // algo como isso:
@@ -134,6 +130,24 @@ impl Nome {
             .map(move |m| expansao_sobrenomica(m, &nomes))
             .filter(|u| u.len() < 20)
     }
+
+    /// As partes do nome, sem acentos e em minúsculas, sem as palavras
+    /// ignoradas na comparação, como "de" e "da".
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::utils::nome::Nome;
+    /// let nome: Nome = "Cláudio de Lima Cavalcante".parse().unwrap();
+    /// assert_eq!(nome.partes(), ["claudio", "lima", "cavalcante"]);
+    /// ```
+    pub fn partes(&self) -> Vec<String> {
+        sem_acentos_e_minusculo(&self.0)
+            .split_whitespace()
+            .filter(|x| !PALAVRAS_IGNORADAS.contains(x))
+            .map(str::to_string)
+            .collect()
+    }
 }
 
 impl PartialEq for Nome {
@@ -222,7 +236,7 @@ impl FromStr for Nome {
     }
 }
 
-fn sem_acentos_e_minusculo(a: &str) -> String {
+pub(crate) fn sem_acentos_e_minusculo(a: &str) -> String {
     a
         // Substitui os cedilha por C
         .replace(['ç', 'Ç'], "C")
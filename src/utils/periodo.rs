@@ -0,0 +1,69 @@
+//! O período letivo do SIGA, como o `2024.1`, usado nas turmas, no ingresso
+//! dos alunos e nos relatórios.
+use chrono::{Datelike, NaiveDate};
+use std::fmt;
+
+/// Um período letivo do SIGA, como o `2024.1`. O semestre 0 é o de verão.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Periodo {
+    pub ano: u16,
+    pub semestre: u8,
+}
+
+impl Periodo {
+    /// Interpreta o período como o SIGA escreve, separado por ponto, barra ou
+    /// hífen, ou sem separador.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::utils::periodo::Periodo;
+    /// let p = Periodo { ano: 2024, semestre: 1 };
+    /// assert_eq!(Periodo::interpretar("2024.1"), Some(p));
+    /// assert_eq!(Periodo::interpretar("2024/1"), Some(p));
+    /// assert_eq!(Periodo::interpretar("20241"), Some(p));
+    /// assert_eq!(Periodo::interpretar("2024.12"), None);
+    /// assert_eq!(Periodo::interpretar("24.1"), None);
+    /// ```
+    pub fn interpretar(periodo: &str) -> Option<Self> {
+        let periodo = periodo.trim();
+        let ano = periodo.get(..4)?;
+        let semestre = periodo
+            .get(4..)?
+            .trim_start_matches(['.', '/', '-'])
+            .to_string();
+        if semestre.len() != 1 || !ano.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(Periodo {
+            ano: ano.parse().ok()?,
+            semestre: semestre.parse().ok()?,
+        })
+    }
+
+    /// O semestre em que a `data` está. Janeiro a julho, com as férias de
+    /// verão, contam como o primeiro semestre, e agosto a dezembro como o
+    /// segundo.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use alumnic::utils::periodo::Periodo;
+    /// # use chrono::NaiveDate;
+    /// let data = NaiveDate::from_ymd_opt(2025, 8, 1).unwrap();
+    /// assert_eq!(Periodo::da_data(data).to_string(), "2025.2");
+    /// ```
+    pub fn da_data(data: NaiveDate) -> Self {
+        Periodo {
+            ano: data.year() as u16,
+            semestre: if data.month() <= 7 { 1 } else { 2 },
+        }
+    }
+}
+
+impl fmt::Display for Periodo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.ano, self.semestre)
+    }
+}
@@ -0,0 +1,299 @@
+//! A triagem dos nomes que não batem com o do SIGA. Em vez de só mostrar os
+//! dois nomes, o cadastro aponta as diferenças palavra a palavra e sugere o
+//! nome provável, para o aluno corrigir de primeira em vez de tentar de novo
+//! contra o Gnosys.
+//!
+//! As diferenças de acentuação e das palavras "de", "da" etc. já são
+//! ignoradas na [comparação dos nomes](Nome), então não aparecem aqui.
+use crate::utils::nome::{Nome, PALAVRAS_IGNORADAS, sem_acentos_e_minusculo};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Uma diferença entre o nome informado e o do SIGA.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum Diferenca {
+    /// Uma palavra do SIGA que não foi informada, como um sobrenome.
+    FaltaPalavra { palavra: String },
+    /// Uma palavra informada que não está no SIGA.
+    PalavraAMais { palavra: String },
+    /// Uma palavra informada abreviada, como `C` ou `Cav` para `Cavalcante`.
+    Abreviacao { informada: String, siga: String },
+    /// Uma palavra informada com a grafia diferente, como `Cavalcanti` para
+    /// `Cavalcante`.
+    Grafia { informada: String, siga: String },
+    /// As palavras são as mesmas, mas em outra ordem.
+    Ordem,
+}
+
+impl fmt::Display for Diferenca {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diferenca::FaltaPalavra { palavra } => {
+                write!(f, "Faltou {palavra:?}")
+            },
+            Diferenca::PalavraAMais { palavra } => {
+                write!(f, "{palavra:?} não está no nome do SIGA")
+            },
+            Diferenca::Abreviacao { informada, siga } => {
+                write!(f, "{informada:?} está abreviado; o SIGA tem {siga:?}")
+            },
+            Diferenca::Grafia { informada, siga } => {
+                write!(f, "{informada:?} está escrito como {siga:?} no SIGA")
+            },
+            Diferenca::Ordem => write!(f, "As palavras estão em outra ordem"),
+        }
+    }
+}
+
+/// As diferenças entre os nomes e o nome sugerido ao aluno.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriagemNome {
+    pub diferencas: Vec<Diferenca>,
+    /// O nome do SIGA, com a grafia informada nas palavras que batem quando
+    /// o SIGA não tem os acentos.
+    pub sugestao: String,
+}
+
+/// Uma palavra do nome como foi escrita e a chave dela na comparação, sem
+/// acentos e em minúsculas.
+struct Palavra {
+    escrita: String,
+    chave: String,
+}
+
+/// As palavras comparadas do `nome`, sem as palavras ignoradas.
+fn palavras(nome: &Nome) -> Vec<Palavra> {
+    nome.to_string()
+        .split_whitespace()
+        .map(|escrita| Palavra {
+            escrita: escrita.to_string(),
+            chave: sem_acentos_e_minusculo(escrita),
+        })
+        .filter(|p| !PALAVRAS_IGNORADAS.contains(&p.chave.as_str()))
+        .collect()
+}
+
+/// A distância de edição entre `a` e `b`.
+fn distancia(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut anterior: Vec<_> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut atual = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let troca = anterior[j] + usize::from(ca != *cb);
+            atual.push(troca.min(anterior[j + 1] + 1).min(atual[j] + 1));
+        }
+        anterior = atual;
+    }
+    anterior[b.len()]
+}
+
+/// Se a `informada` abrevia a palavra `siga`.
+fn abreviacao(informada: &str, siga: &str) -> bool {
+    informada.len() < siga.len() && siga.starts_with(informada)
+}
+
+/// Se a `informada` é um erro de grafia da palavra `siga`: até uma letra
+/// diferente em palavras curtas e até duas nas longas.
+fn grafia(informada: &str, siga: &str) -> bool {
+    let limite = if siga.len() < 6 { 1 } else { 2 };
+    distancia(informada, siga) <= limite
+}
+
+/// Um passo do alinhamento das palavras informadas com as do SIGA.
+enum Passo {
+    Igual,
+    Troca(usize, usize),
+    AMais(usize),
+    Falta(usize),
+}
+
+/// Alinha as palavras `a` informadas com as `b` do SIGA, com o menor número
+/// de palavras trocadas, a mais ou faltando. Só palavras parecidas podem ser
+/// trocadas.
+fn alinhar(a: &[Palavra], b: &[Palavra]) -> Vec<Passo> {
+    let parecidas = |i: usize, j: usize| {
+        abreviacao(&a[i].chave, &b[j].chave) || grafia(&a[i].chave, &b[j].chave)
+    };
+    // custo[i][j] é o custo de alinhar a[i..] com b[j..]
+    let mut custo = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..=a.len()).rev() {
+        for j in (0..=b.len()).rev() {
+            custo[i][j] = match (i < a.len(), j < b.len()) {
+                (false, false) => 0,
+                (true, false) => custo[i + 1][j] + 1,
+                (false, true) => custo[i][j + 1] + 1,
+                (true, true) => {
+                    let mut c = (custo[i + 1][j] + 1).min(custo[i][j + 1] + 1);
+                    if a[i].chave == b[j].chave {
+                        c = c.min(custo[i + 1][j + 1]);
+                    } else if parecidas(i, j) {
+                        c = c.min(custo[i + 1][j + 1] + 1);
+                    }
+                    c
+                },
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut passos = vec![];
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() {
+            if a[i].chave == b[j].chave && custo[i][j] == custo[i + 1][j + 1] {
+                passos.push(Passo::Igual);
+                (i, j) = (i + 1, j + 1);
+                continue;
+            }
+            if parecidas(i, j) && custo[i][j] == custo[i + 1][j + 1] + 1 {
+                passos.push(Passo::Troca(i, j));
+                (i, j) = (i + 1, j + 1);
+                continue;
+            }
+        }
+        if i < a.len() && (j == b.len() || custo[i][j] == custo[i + 1][j] + 1) {
+            passos.push(Passo::AMais(i));
+            i += 1;
+        } else {
+            passos.push(Passo::Falta(j));
+            j += 1;
+        }
+    }
+    passos
+}
+
+/// Compara o nome `informado` com o do `siga`, apontando as diferenças e
+/// sugerindo o nome provável.
+///
+/// # Examples
+///
+/// ```
+/// # use alumnic::utils::triagem_nome::{Diferenca, triagem};
+/// let t = triagem(
+///     &"Cláudio L Cavalcanti".parse().unwrap(),
+///     &"CLAUDIO DE LIMA CAVALCANTE FILHO".parse().unwrap(),
+/// );
+/// assert_eq!(t.diferencas, [
+///     Diferenca::Abreviacao { informada: "L".into(), siga: "Lima".into() },
+///     Diferenca::Grafia {
+///         informada: "Cavalcanti".into(),
+///         siga: "Cavalcante".into(),
+///     },
+///     Diferenca::FaltaPalavra { palavra: "Filho".into() },
+/// ]);
+/// assert_eq!(t.sugestao, "Cláudio de Lima Cavalcante Filho");
+/// ```
+pub fn triagem(informado: &Nome, siga: &Nome) -> TriagemNome {
+    let a = palavras(informado);
+    let b = palavras(siga);
+
+    let diferencas = {
+        let mut chaves_a: Vec<_> = a.iter().map(|p| &p.chave).collect();
+        let mut chaves_b: Vec<_> = b.iter().map(|p| &p.chave).collect();
+        chaves_a.sort();
+        chaves_b.sort();
+        if chaves_a == chaves_b {
+            vec![Diferenca::Ordem]
+        } else {
+            alinhar(&a, &b)
+                .into_iter()
+                .filter_map(|passo| match passo {
+                    Passo::Igual => None,
+                    Passo::Troca(i, j) => {
+                        let informada = a[i].escrita.clone();
+                        let siga = b[j].escrita.clone();
+                        Some(if abreviacao(&a[i].chave, &b[j].chave) {
+                            Diferenca::Abreviacao { informada, siga }
+                        } else {
+                            Diferenca::Grafia { informada, siga }
+                        })
+                    },
+                    Passo::AMais(i) => Some(Diferenca::PalavraAMais {
+                        palavra: a[i].escrita.clone(),
+                    }),
+                    Passo::Falta(j) => Some(Diferenca::FaltaPalavra {
+                        palavra: b[j].escrita.clone(),
+                    }),
+                })
+                .collect()
+        }
+    };
+
+    // O SIGA pode vir sem acentos; nas palavras iguais, fica a grafia
+    // acentuada do aluno
+    let sugestao = siga
+        .to_string()
+        .split_whitespace()
+        .map(|escrita| {
+            let chave = sem_acentos_e_minusculo(escrita);
+            a.iter()
+                .find(|p| p.chave == chave && escrita.is_ascii())
+                .map_or(escrita, |p| p.escrita.as_str())
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    TriagemNome {
+        diferencas,
+        sugestao,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triar(informado: &str, siga: &str) -> TriagemNome {
+        triagem(&informado.parse().unwrap(), &siga.parse().unwrap())
+    }
+
+    #[test]
+    fn sobrenome_a_mais_e_faltando() {
+        let t = triar("Ana Souza Lima", "ANA MARIA SOUZA");
+        assert_eq!(
+            t.diferencas,
+            [
+                Diferenca::FaltaPalavra {
+                    palavra: "Maria".into()
+                },
+                Diferenca::PalavraAMais {
+                    palavra: "Lima".into()
+                },
+            ]
+        );
+        assert_eq!(t.sugestao, "Ana Maria Souza");
+    }
+
+    #[test]
+    fn ordem_trocada() {
+        let t = triar("Souza Ana", "ANA SOUZA");
+        assert_eq!(t.diferencas, [Diferenca::Ordem]);
+        assert_eq!(t.sugestao, "Ana Souza");
+    }
+
+    #[test]
+    fn palavras_diferentes_nao_sao_grafia() {
+        let t = triar("Ana Costa", "ANA SOUZA");
+        assert_eq!(
+            t.diferencas,
+            [
+                Diferenca::PalavraAMais {
+                    palavra: "Costa".into()
+                },
+                Diferenca::FaltaPalavra {
+                    palavra: "Souza".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn distancia_de_edicao() {
+        assert_eq!(distancia("cavalcanti", "cavalcante"), 1);
+        assert_eq!(distancia("", "ana"), 3);
+        assert_eq!(distancia("luis", "luiz"), 1);
+        assert_eq!(distancia("souza", "sousa"), 1);
+    }
+}
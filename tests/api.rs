@@ -0,0 +1,880 @@
+//! Testes do contrato da API de cadastro com o frontend: o status de cada
+//! [`ErroDeCadastro`](alumnic::cadastro_aluno::ErroDeCadastro) e o formato do
+//! JSON das respostas.
+
+mod comum;
+
+use alumnic::cadastro_aluno::{
+    CadastroBuilder, DadosParaCadastro, ErroDeCadastro,
+};
+use alumnic::configuracao::ConfiguracaoReservaUsername;
+use alumnic::foto::ConfiguracaoFoto;
+use alumnic::ldap::alias_email::ConfiguracaoAliasEmail;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::ldap::reservas::{dn_reserva, reservar_username};
+use base64::prelude::*;
+use chrono::Utc;
+use comum::api::{ApiDeTeste, configuracao, diretorio_com_samba};
+use comum::gnosys::{Documento, Modo};
+use image::RgbImage;
+use image::codecs::jpeg::JpegEncoder;
+use serde_json::{Value, json};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+fn documento() -> Documento {
+    Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    )
+}
+
+/// O corpo de um cadastro válido para o [documento], com o campo `campo`
+/// trocado por `valor`.
+fn corpo_com(campo: &str, valor: &str) -> String {
+    let d = documento();
+    let mut corpo = json!({
+        "dre": d.dre,
+        "data": d.data,
+        "hora": d.hora,
+        "codigo": d.codigo,
+        "nome": "Cláudio de Lima Cavalcante",
+        "email": "claudio@exemplo.com",
+        "telefone": "(21) 98765-4321",
+        "senha": "Senha1234",
+    });
+    corpo[campo] = valor.into();
+    corpo.to_string()
+}
+
+fn corpo() -> String {
+    corpo_com("nome", "Cláudio de Lima Cavalcante")
+}
+
+/// Verifica que a resposta tem exatamente os campos esperados pelo frontend,
+/// com os `proximos_passos` só no cadastro feito e a `triagem_nome` só nos
+/// nomes diferentes do SIGA.
+fn assert_formato(resposta: &Value) {
+    let campos = resposta.as_object().expect("a resposta não é um objeto");
+    let esperados = 2
+        + usize::from(campos.contains_key("proximos_passos"))
+        + usize::from(campos.contains_key("triagem_nome"));
+    assert_eq!(campos.len(), esperados, "campos inesperados em {resposta}");
+    assert!(campos["message"].is_string());
+    assert!(campos["sabar_mais"].is_null() || campos["sabar_mais"].is_string());
+}
+
+async fn cadastrar(api: &ApiDeTeste, corpo: &str) -> (u16, Value) {
+    let (status, resposta) = api.post("/api/cadastrar", corpo).await;
+    assert_formato(&resposta);
+    (status, resposta)
+}
+
+#[tokio::test]
+async fn cadastro_valido_201() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+
+    assert_eq!(status, 201, "{resposta}");
+    assert!(resposta["message"].as_str().unwrap().contains("claudiolc"));
+    assert!(resposta["sabar_mais"].is_null());
+    assert_eq!(resposta["proximos_passos"]["username"], "claudiolc");
+    assert_eq!(
+        resposta["proximos_passos"]["email_institucional"],
+        "claudiolc@ic.ufrj.br"
+    );
+
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).expect("entrada não foi criada");
+    assert_eq!(entrada.attrs["telephoneNumber"], vec!["+5521987654321"]);
+}
+
+#[tokio::test]
+async fn entradas_invalidas_422() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    for (campo, valor) in [
+        ("dre", "12345"),
+        ("data", "32/13"),
+        ("hora", "meio-dia"),
+        ("codigo", "A3B1.7E5D"),
+        ("email", "claudio.exemplo.com"),
+        ("telefone", "98765-4321"),
+        ("senha", "fraca"),
+        ("nome", "Claudio Lima"),
+    ] {
+        let (status, resposta) =
+            cadastrar(&api, &corpo_com(campo, valor)).await;
+        assert_eq!(status, 422, "{campo}: {resposta}");
+    }
+
+    // Só o nome, que é comparado com o do SIGA, chega a ser enviado ao Gnosys
+    assert_eq!(api.gnosys.consultas(), 1);
+}
+
+#[tokio::test]
+async fn cadastro_montado_pelo_builder() {
+    let api = ApiDeTeste::iniciar().await;
+    let d = documento();
+    api.gnosys.registrar(d.clone());
+
+    let dados = CadastroBuilder::novo()
+        .username("claudiolc")
+        .unwrap()
+        .dre(&d.dre)
+        .unwrap()
+        .documento(&d.data, &d.hora, &d.codigo)
+        .unwrap()
+        .nome("Cláudio de Lima Cavalcante")
+        .unwrap()
+        .email("claudio@exemplo.com")
+        .unwrap()
+        .telefone("(21) 98765-4321")
+        .unwrap()
+        .senha("Senha1234".into())
+        .unwrap()
+        .construir()
+        .unwrap();
+    assert_eq!(dados.telefone, "+5521987654321");
+
+    let corpo = serde_json::to_string(&dados).unwrap();
+    let (status, resposta) = cadastrar(&api, &corpo).await;
+    assert_eq!(status, 201, "{resposta}");
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_some());
+}
+
+#[test]
+fn builder_recusa_cada_campo_na_hora() {
+    let b = CadastroBuilder::novo;
+    assert!(matches!(
+        b().dre("12345"),
+        Err(ErroDeCadastro::DREInvalido(_))
+    ));
+    assert!(matches!(
+        b().documento("32/13", "10:00", ""),
+        Err(ErroDeCadastro::DataInvalida(_))
+    ));
+    assert!(matches!(
+        b().email("claudio.exemplo.com"),
+        Err(ErroDeCadastro::EmailInvalido(_))
+    ));
+    assert!(matches!(
+        b().senha("fraca".into()),
+        Err(ErroDeCadastro::SenhaInvalida)
+    ));
+    assert!(matches!(
+        b().nome(&"A".repeat(100_000)),
+        Err(ErroDeCadastro::CampoGrande(_))
+    ));
+    // O username é conferido com o nome, em qualquer ordem
+    let nome = b().nome("Cláudio de Lima Cavalcante").unwrap();
+    assert!(matches!(
+        nome.username("fulano"),
+        Err(ErroDeCadastro::UsernameInvalido(_))
+    ));
+    let username = b().username("fulano").unwrap();
+    assert!(matches!(
+        username.nome("Cláudio de Lima Cavalcante"),
+        Err(ErroDeCadastro::UsernameInvalido(_))
+    ));
+
+    let erro = b().dre("123456789").unwrap().construir().unwrap_err();
+    assert!(
+        matches!(erro, ErroDeCadastro::CampoAusente("documento")),
+        "{erro}"
+    );
+}
+
+#[tokio::test]
+async fn campos_enormes_422_sem_consultas() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let enorme = "A".repeat(100_000);
+    for campo in ["dre", "codigo", "nome", "email", "telefone", "senha"] {
+        let (status, resposta) =
+            cadastrar(&api, &corpo_com(campo, &enorme)).await;
+        assert_eq!(status, 422, "{campo}: {resposta}");
+        let mensagem = resposta["message"].as_str().unwrap();
+        assert!(mensagem.contains(campo), "{mensagem}");
+        assert!(!mensagem.contains("AAAA"), "{mensagem}");
+    }
+
+    assert_eq!(api.gnosys.consultas(), 0);
+}
+
+#[tokio::test]
+async fn nome_com_caracteres_de_controle_e_normalizado() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let nome = "Cláudio\n[ERRO] de\u{202e} Lima\r\nCavalcante\u{0}";
+    let (status, resposta) = cadastrar(&api, &corpo_com("nome", nome)).await;
+    // O "[ERRO]" continua no nome, que deixa de ser válido, mas fica na
+    // mesma linha
+    assert_eq!(status, 422, "{resposta}");
+    let mensagem = resposta["message"].as_str().unwrap();
+    assert!(!mensagem.contains('\n'), "{mensagem}");
+    assert_eq!(api.gnosys.consultas(), 0);
+
+    let nome = "Cláudio\tde\u{200b} Lima\r\nCavalcante";
+    let (status, resposta) = cadastrar(&api, &corpo_com("nome", nome)).await;
+    assert_eq!(status, 201, "{resposta}");
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).expect("entrada não foi criada");
+    assert_eq!(entrada.attrs["gecos"], vec!["Claudio de Lima Cavalcante"]);
+}
+
+#[tokio::test]
+async fn validacao_sem_tocar_no_ldap() {
+    // Sem o domínio do Samba, o cadastro falharia no LDAP
+    let api = ApiDeTeste::iniciar_com(DiretorioMemoria::default()).await;
+    api.gnosys.registrar(documento());
+    let mut dados: Value = serde_json::from_str(&corpo()).unwrap();
+    dados.as_object_mut().unwrap().remove("senha");
+
+    let (status, resposta) = api.post("/api/validar", &dados.to_string()).await;
+    assert_eq!(status, 200, "{resposta}");
+    assert_eq!(resposta["telefone"], "+5521987654321");
+    assert_eq!(resposta["ou"], "alunos");
+    assert_eq!(resposta["usernames"][0], "claudiolc");
+    assert_eq!(api.gnosys.consultas(), 1);
+    assert_eq!(api.ldap.lock().await.entradas().count(), 0);
+
+    // Os erros são os mesmos do cadastro
+    dados["nome"] = "Claudio Lima".into();
+    let (status, resposta) = api.post("/api/validar", &dados.to_string()).await;
+    assert_eq!(status, 422);
+    assert_formato(&resposta);
+    dados["codigo"] = "0000.0000.0000.0000.0000.0000.0000.0009".into();
+    let (status, _) = api.post("/api/validar", &dados.to_string()).await;
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn nomes_diferentes_trazem_a_triagem() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let (status, resposta) =
+        cadastrar(&api, &corpo_com("nome", "Claudio L Cavalcanti")).await;
+
+    assert_eq!(status, 422, "{resposta}");
+    let triagem = &resposta["triagem_nome"];
+    assert_eq!(
+        triagem["diferencas"],
+        json!([
+            {"tipo": "abreviacao", "informada": "L", "siga": "Lima"},
+            {"tipo": "grafia", "informada": "Cavalcanti", "siga": "Cavalcante"},
+        ])
+    );
+    assert_eq!(triagem["sugestao"], "Cláudio de Lima Cavalcante");
+
+    // A validação, que o frontend chama antes do cadastro, também traz a
+    // triagem
+    let mut dados: Value =
+        serde_json::from_str(&corpo_com("nome", "Lima Cavalcante Claudio"))
+            .unwrap();
+    dados.as_object_mut().unwrap().remove("senha");
+    let (status, resposta) = api.post("/api/validar", &dados.to_string()).await;
+    assert_eq!(status, 422);
+    assert_eq!(
+        resposta["triagem_nome"]["diferencas"],
+        json!([{"tipo": "ordem"}])
+    );
+}
+
+#[tokio::test]
+async fn documento_invalido_401() {
+    let api = ApiDeTeste::iniciar().await;
+
+    let (status, _) = cadastrar(&api, &corpo()).await;
+
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn aluno_de_outro_curso_403() {
+    let api = ApiDeTeste::iniciar().await;
+    let mut d = documento();
+    d.curso = "Matemática".to_string();
+    api.gnosys.registrar(d);
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+
+    assert_eq!(status, 403);
+    assert!(resposta["message"].as_str().unwrap().contains("Matemática"));
+}
+
+#[tokio::test]
+async fn cadastro_redundante_409() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    assert_eq!(cadastrar(&api, &corpo()).await.0, 201);
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+
+    assert_eq!(status, 409);
+    assert!(resposta["message"].as_str().unwrap().contains("claudiolc"));
+}
+
+#[tokio::test]
+async fn lista_os_usernames_livres() {
+    let api = ApiDeTeste::iniciar().await;
+    api.cadastrar(documento()).await;
+
+    let (status, resposta) = api
+        .get("/api/usernames?nome=Cl%C3%A1udio%20de%20Lima")
+        .await;
+    assert_eq!(status, 200, "{resposta}");
+    assert_eq!(resposta, json!({"usernames": ["claudiol", "claudiolima"]}));
+
+    // Com o cadastro do documento, o primeiro já está ocupado
+    let (_, resposta) = api
+        .get("/api/usernames?nome=Cl%C3%A1udio%20de%20Lima%20Cavalcante")
+        .await;
+    assert_eq!(resposta["usernames"][0], "claudiolcavalcante");
+
+    let (status, resposta) = api.get("/api/usernames?nome=Cl4udio").await;
+    assert_eq!(status, 422);
+    assert_formato(&resposta);
+}
+
+#[tokio::test]
+async fn cadastro_com_username_escolhido() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let (status, _) = cadastrar(&api, &corpo_com("username", "claudio")).await;
+    assert_eq!(status, 422);
+
+    let (status, resposta) =
+        cadastrar(&api, &corpo_com("username", "ClaudioLimaC")).await;
+    assert_eq!(status, 201, "{resposta}");
+    assert!(
+        resposta["message"]
+            .as_str()
+            .unwrap()
+            .contains("claudiolimac")
+    );
+
+    // Um username escolhido que já foi usado por outro cadastro
+    let mut outro = documento();
+    outro.dre = "987654321".to_string();
+    api.gnosys.registrar(outro.clone());
+    let mut corpo: Value =
+        serde_json::from_str(&corpo_com("dre", &outro.dre)).unwrap();
+    corpo["username"] = "claudiolimac".into();
+    let (status, _) = cadastrar(&api, &corpo.to_string()).await;
+    assert_eq!(status, 409);
+}
+
+#[tokio::test]
+async fn cadastro_pula_o_username_reservado() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+    {
+        let mut ldap = api.ldap.lock().await;
+        let cfg = ConfiguracaoReservaUsername::default();
+        reservar_username(
+            "claudiolc",
+            "987654321",
+            &cfg,
+            Utc::now(),
+            &mut *ldap,
+        )
+        .await
+        .unwrap();
+    }
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 201, "{resposta}");
+    assert!(
+        resposta["message"]
+            .as_str()
+            .unwrap()
+            .contains("claudiolcavalcante")
+    );
+
+    // A reserva do cadastro foi desfeita, e a do outro aluno continua
+    let ldap = api.ldap.lock().await;
+    assert!(ldap.entrada(&dn_reserva("claudiolcavalcante")).is_none());
+    assert!(ldap.entrada(&dn_reserva("claudiolc")).is_some());
+}
+
+#[tokio::test]
+async fn cadastros_simultaneos() {
+    let api = ApiDeTeste::iniciar().await;
+    let mut outro = documento();
+    outro.dre = "987654321".to_string();
+    api.gnosys.registrar(documento());
+    api.gnosys.registrar(outro.clone());
+
+    // O mesmo aluno duas vezes ao mesmo tempo gera uma conta só, e o
+    // segundo envio recebe a resposta do primeiro
+    let corpo = corpo();
+    let (a, b) = tokio::join!(cadastrar(&api, &corpo), cadastrar(&api, &corpo));
+    assert_eq!((a.0, b.0), (201, 201), "{a:?} {b:?}");
+    assert_eq!(a.1, b.1);
+    assert_eq!(api.gnosys.consultas(), 1);
+
+    // Dois alunos com o mesmo nome ao mesmo tempo ganham usernames diferentes
+    let corpo_outro = corpo_com("dre", &outro.dre);
+    let terceiro = Documento {
+        dre: "111111111".to_string(),
+        ..outro
+    };
+    api.gnosys.registrar(terceiro.clone());
+    let corpo_terceiro = corpo_com("dre", &terceiro.dre);
+    let (a, b) = tokio::join!(
+        cadastrar(&api, &corpo_outro),
+        cadastrar(&api, &corpo_terceiro),
+    );
+    assert_eq!((a.0, b.0), (201, 201), "{a:?} {b:?}");
+    assert_ne!(a.1["message"], b.1["message"]);
+    let ldap = api.ldap.lock().await;
+    let alunos = ldap
+        .entradas()
+        .filter(|e| e.attrs.contains_key("dccDRE"))
+        .count();
+    assert_eq!(alunos, 3);
+}
+
+#[tokio::test]
+async fn gnosys_fora_do_ar_500() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+    api.gnosys.modo(Modo::Manutencao);
+
+    let (status, _) = cadastrar(&api, &corpo()).await;
+
+    assert_eq!(status, 500);
+}
+
+#[tokio::test]
+async fn erro_no_ldap_500() {
+    // Sem o domínio do Samba não é possível gerar os IDs do usuário
+    let api = ApiDeTeste::iniciar_com(DiretorioMemoria::default()).await;
+    api.gnosys.registrar(documento());
+
+    let (status, _) = cadastrar(&api, &corpo()).await;
+
+    assert_eq!(status, 500);
+}
+
+#[tokio::test]
+async fn json_invalido() {
+    let api = ApiDeTeste::iniciar().await;
+
+    // Campo faltando
+    let (status, resposta) = cadastrar(&api, r#"{"dre": "123456789"}"#).await;
+    assert_eq!(status, 422);
+    assert!(resposta["sabar_mais"].is_string());
+
+    // JSON mal formado
+    let (status, resposta) = cadastrar(&api, "{").await;
+    assert_eq!(status, 400);
+    assert!(resposta["sabar_mais"].is_string());
+}
+
+#[tokio::test]
+async fn estatisticas_contam_os_erros() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    cadastrar(&api, &corpo_com("senha", "fraca")).await;
+    cadastrar(&api, &corpo()).await;
+    // O mesmo DRE de novo
+    cadastrar(&api, &corpo()).await;
+
+    let (status, estatisticas) = api.get_admin("/api/admin/estatisticas").await;
+    assert_eq!(status, 200);
+
+    let estatisticas: Value = serde_json::from_str(&estatisticas).unwrap();
+    assert_eq!(estatisticas["erros_por_tipo"]["SenhaInvalida"], 1);
+    assert_eq!(estatisticas["cadastros_por_curso"]["alunos"], 1);
+    assert_eq!(estatisticas["erros_por_etapa"]["validacao"], 1);
+    assert_eq!(estatisticas["erros_por_etapa"]["consulta_ldap"], 1);
+    assert_eq!(estatisticas["ultimas_falhas"][0]["etapa"], "consulta_ldap");
+
+    let (_, metricas) = api.get_admin("/api/admin/metricas").await;
+    assert!(
+        metricas.contains("alumnic_cadastro_falhas_total{etapa=\"validacao\"}"),
+        "{metricas}"
+    );
+}
+
+#[tokio::test]
+async fn metricas_medem_as_etapas_do_cadastro() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+    cadastrar(&api, &corpo()).await;
+
+    let (status, metricas) = api.get_admin("/api/admin/metricas").await;
+    assert_eq!(status, 200);
+    for etapa in [
+        "validacao",
+        "consulta_gnosys",
+        "consulta_ldap",
+        "criacao_ldap",
+        "servicos",
+    ] {
+        let linha = format!(
+            "alumnic_cadastro_etapa_duracao_segundos_count{{etapa=\"{etapa}\"}}"
+        );
+        assert!(metricas.contains(&linha), "{etapa}: {metricas}");
+    }
+    // O DRE 123456789 não tem o semestre no quarto dígito
+    assert!(
+        metricas.contains("alumnic_cadastros_total{ingresso=\"desconhecido\"}"),
+        "{metricas}"
+    );
+}
+
+#[tokio::test]
+async fn cadastro_cancelado_para_antes_da_secao_critica() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+    let cfg = configuracao(&api.gnosys.url);
+    let cancelamento = CancellationToken::new();
+    cancelamento.cancel();
+
+    let dados: DadosParaCadastro = serde_json::from_str(&corpo()).unwrap();
+    let erro = dados
+        .cadastrar(
+            &cfg.usuario_novo,
+            &cfg.renovacao,
+            &cfg.gnosys_url,
+            &api.ldap,
+            None,
+            &cancelamento,
+        )
+        .await
+        .unwrap_err();
+
+    assert!(matches!(erro, ErroDeCadastro::Cancelado), "{erro}");
+    assert_eq!(erro.status(), 503);
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_none());
+}
+
+#[tokio::test]
+async fn cadastro_repetido_espera_o_em_andamento() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+    api.gnosys.modo(Modo::Lento);
+
+    let corpo = corpo();
+    let (primeiro, segundo) = tokio::join!(cadastrar(&api, &corpo), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cadastrar(&api, &corpo).await
+    });
+
+    assert_eq!(primeiro.0, 201, "{}", primeiro.1);
+    assert_eq!(segundo, primeiro);
+    assert_eq!(api.gnosys.consultas(), 1);
+
+    // Terminado o primeiro, um novo envio faz o fluxo todo
+    let (status, _) = cadastrar(&api, &corpo).await;
+    assert_eq!(status, 409);
+    assert_eq!(api.gnosys.consultas(), 2);
+}
+
+#[tokio::test]
+async fn cadastro_com_outro_documento_nao_espera_o_em_andamento() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+    api.gnosys.modo(Modo::Lento);
+
+    // O mesmo DRE, mas com um documento que não é o do aluno
+    let corpo = corpo();
+    let outro = corpo_com("codigo", "AAAA.AAAA.AAAA.AAAA.AAAA.AAAA.AAAA.AAAA");
+    let (primeiro, segundo) = tokio::join!(cadastrar(&api, &corpo), async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cadastrar(&api, &outro).await
+    });
+
+    assert_eq!(primeiro.0, 201, "{}", primeiro.1);
+    assert_eq!(segundo.0, 401, "{}", segundo.1);
+    assert!(segundo.1.get("proximos_passos").is_none(), "{}", segundo.1);
+    assert!(
+        !segundo.1["message"].as_str().unwrap().contains("claudiolc"),
+        "{}",
+        segundo.1,
+    );
+    assert_eq!(api.gnosys.consultas(), 2);
+}
+
+#[tokio::test]
+async fn somente_leitura_503() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let (status, _) = api
+        .put_admin(
+            "/api/admin/somente-leitura",
+            r#"{"somente_leitura": true, "mensagem": "Migrando o LDAP"}"#,
+        )
+        .await;
+    assert_eq!(status, 200);
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 503);
+    assert_eq!(resposta["message"], "Migrando o LDAP");
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_none());
+    assert_eq!(api.gnosys.consultas(), 0);
+
+    // As consultas continuam funcionando
+    let (status, modo) = api.get_admin("/api/admin/somente-leitura").await;
+    assert_eq!(status, 200);
+    assert!(modo.contains(r#""somente_leitura":true"#), "{modo}");
+
+    // Desativado, a mensagem é mantida para a próxima vez
+    api.put_admin(
+        "/api/admin/somente-leitura",
+        r#"{"somente_leitura": false}"#,
+    )
+    .await;
+    assert_eq!(cadastrar(&api, &corpo()).await.0, 201);
+}
+
+#[tokio::test]
+async fn interrupcao_503_com_previsao_de_retorno() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(documento());
+
+    let retorno = (Utc::now() + chrono::Duration::hours(2)).fixed_offset();
+    let modo = json!({
+        "interrompida": true,
+        "mensagem": "Migrando o LDAP.",
+        "retorno": retorno,
+    });
+    let (status, _) = api
+        .put_admin("/api/admin/manutencao", &modo.to_string())
+        .await;
+    assert_eq!(status, 200);
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 503);
+    let mensagem = resposta["message"].as_str().unwrap();
+    assert!(
+        mensagem.starts_with("Migrando o LDAP. Previsão de retorno: "),
+        "{mensagem}",
+    );
+    assert_eq!(api.gnosys.consultas(), 0);
+
+    let res = reqwest::get(format!("{}/api/usernames?nome=Ana", api.url))
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 503);
+    let segundos: i64 = res.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((7000..=7200).contains(&segundos), "{segundos}");
+
+    // O healthcheck e as rotas administrativas continuam no ar
+    let (status, saude) = api.get("/api/saude").await;
+    assert_eq!(status, 200);
+    assert_eq!(saude["interrompida"], true);
+    let (status, _) = api.get_admin("/api/admin/estatisticas").await;
+    assert_eq!(status, 200);
+
+    api.put_admin("/api/admin/manutencao", r#"{"interrompida": false}"#)
+        .await;
+    assert_eq!(cadastrar(&api, &corpo()).await.0, 201);
+}
+
+#[tokio::test]
+async fn features_desligam_fluxos_especificos() {
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.features.cadastro_novo = false;
+            cfg.features.api_admin = false;
+        })
+        .await;
+    api.gnosys.registrar(documento());
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 503);
+    assert_eq!(
+        resposta["message"],
+        "O cadastro de contas novas está desativado no momento.",
+    );
+    assert_eq!(api.gnosys.consultas(), 0);
+
+    // A renovação continua ligada, e recusa o documento antigo
+    let (status, _) = api.post("/api/renovar", &corpo()).await;
+    assert_eq!(status, 422);
+
+    let (status, _) = api.get_admin("/api/admin/estatisticas").await;
+    assert_eq!(status, 404);
+    let (status, _) = api.get("/api/saude").await;
+    assert_eq!(status, 200);
+}
+
+/// Um JPEG de 32x32 com um comentário, como os metadados de uma foto.
+fn foto_com_comentario() -> String {
+    let mut jpeg = vec![];
+    JpegEncoder::new(&mut jpeg)
+        .encode_image(&RgbImage::new(32, 32))
+        .unwrap();
+    let mut comentario = vec![0xFF, 0xFE, 0x00, 0x07];
+    comentario.extend(b"Canon");
+    jpeg.splice(2..2, comentario);
+    BASE64_STANDARD.encode(jpeg)
+}
+
+#[tokio::test]
+async fn foto_vai_recodificada_para_o_jpeg_photo() {
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.usuario_novo.foto = Some(ConfiguracaoFoto::default());
+        })
+        .await;
+    api.gnosys.registrar(documento());
+
+    // Um PNG é recusado antes de consultar o SIGA
+    let png = BASE64_STANDARD.encode(b"\x89PNG\r\n\x1a\n\0\0\0\0");
+    let (status, resposta) = cadastrar(&api, &corpo_com("foto", &png)).await;
+    assert_eq!(status, 422, "{resposta}");
+    assert_eq!(api.gnosys.consultas(), 0);
+
+    let corpo = corpo_com("foto", &foto_com_comentario());
+    let (status, resposta) = cadastrar(&api, &corpo).await;
+    assert_eq!(status, 201, "{resposta}");
+
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).expect("entrada não foi criada");
+    let foto = &entrada.bin_attrs["jpegPhoto"][0];
+    assert!(foto.starts_with(&[0xFF, 0xD8]));
+    assert!(!foto.windows(5).any(|w| w == b"Canon"));
+}
+
+#[tokio::test]
+async fn alias_de_email_pula_os_ocupados() {
+    let mut ldap = diretorio_com_samba().await;
+    ldap.adicionar(
+        "uid=claudioc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+        vec![
+            ("uid", ["claudioc"].into()),
+            ("mail", ["claudio.cavalcante@ic.ufrj.br"].into()),
+        ],
+    )
+    .await
+    .unwrap();
+    ldap.adicionar(
+        "uid=claudiolima,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+        vec![
+            ("uid", ["claudiolima"].into()),
+            ("mail", ["claudiolima@ic.ufrj.br"].into()),
+            ("mailAlternateAddress", ["claudio.l.cavalcante@ic.ufrj.br"].into()),
+        ],
+    )
+    .await
+    .unwrap();
+    let api = ApiDeTeste::iniciar_ajustando(ldap, |cfg| {
+        cfg.usuario_novo.alias_email = Some(ConfiguracaoAliasEmail::default());
+    })
+    .await;
+    api.gnosys.registrar(documento());
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 201, "{resposta}");
+
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).expect("entrada não foi criada");
+    assert_eq!(entrada.attrs["mail"], ["claudiolc@ic.ufrj.br"]);
+    assert_eq!(
+        entrada.attrs["mailAlternateAddress"],
+        ["claudio.cavalcante2@ic.ufrj.br"],
+    );
+    assert!(
+        entrada.attrs["objectClass"]
+            .contains(&"inetLocalMailRecipient".to_string())
+    );
+}
+
+#[tokio::test]
+async fn listagem_de_contas_pagina_e_ordena() {
+    let mut ldap = diretorio_com_samba().await;
+    for (uid, criacao) in [
+        ("ana", "20100"),
+        ("bia", "20300"),
+        ("caio", "900"),
+        ("davi", "20200"),
+    ] {
+        ldap.adicionar(
+            &format!(
+                "uid={uid},ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"
+            ),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("dataCriacao", [criacao].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    let api = ApiDeTeste::iniciar_com(ldap).await;
+
+    let (status, resposta) = api
+        .get_admin("/api/admin/contas?pagina=2&tamanho=2&ordenar=-dataCriacao")
+        .await;
+    assert_eq!(status, 200, "{resposta}");
+    let resposta: Value = serde_json::from_str(&resposta).unwrap();
+    assert_eq!(resposta["total"], 4);
+    assert_eq!(resposta["ultima"], true);
+    let uids: Vec<_> = resposta["contas"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["uid"].as_str().unwrap())
+        .collect();
+    assert_eq!(uids, ["ana", "caio"]);
+
+    let (status, _) = api
+        .get_admin("/api/admin/contas?ordenar=userPassword")
+        .await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn proximos_passos_seguem_os_modelos() {
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.proximos_passos.mensagem =
+                "Conta {username} criada".to_string();
+            cfg.proximos_passos.instrucoes =
+                Some("https://ic.ufrj.br/primeiro-acesso/{ou}".to_string());
+            cfg.proximos_passos.prazo_primeiro_login = Some(7);
+        })
+        .await;
+    api.gnosys.registrar(documento());
+
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 201, "{resposta}");
+
+    assert_eq!(resposta["message"], "Conta claudiolc criada");
+    let passos = &resposta["proximos_passos"];
+    assert_eq!(
+        passos["instrucoes"],
+        "https://ic.ufrj.br/primeiro-acesso/alunos"
+    );
+    let prazo = chrono::Local::now().date_naive() + chrono::Days::new(7);
+    assert_eq!(passos["prazo_primeiro_login"], prazo.to_string());
+
+    // Um erro continua só com a mensagem
+    let (status, resposta) = cadastrar(&api, &corpo()).await;
+    assert_eq!(status, 409, "{resposta}");
+    assert!(resposta.get("proximos_passos").is_none());
+}
@@ -0,0 +1,151 @@
+//! Testes do encadeamento da trilha de auditoria.
+
+use alumnic::auditoria::{
+    Adulteracao, ConfiguracaoAuditoria, Registro, registrar, verificar,
+};
+use chrono::Utc;
+use std::path::PathBuf;
+
+/// Grava na trilha `nome` os registros de reativação dos `uids`, retornando
+/// o arquivo.
+async fn trilha(nome: &str, uids: &[&str]) -> PathBuf {
+    let arquivo = std::env::temp_dir()
+        .join(format!("alumnic-{nome}-{}.jsonl", std::process::id()));
+    let cfg = ConfiguracaoAuditoria {
+        arquivo: Some(arquivo.clone()),
+    };
+    for uid in uids {
+        let registro = Registro {
+            quando: Utc::now(),
+            operacao: "reativar",
+            uid,
+            motivo: "Destrancou a matrícula",
+        };
+        registrar(&cfg, &registro).await.unwrap();
+    }
+    arquivo
+}
+
+#[tokio::test]
+async fn trilha_intacta_e_verificada() {
+    let arquivo = trilha("auditoria-intacta", &["ana", "bruno", "carla"]).await;
+    let texto = std::fs::read_to_string(&arquivo).unwrap();
+    std::fs::remove_file(&arquivo).unwrap();
+
+    let v = verificar(&texto, None);
+    assert_eq!(v.encadeadas, 3);
+    assert_eq!(v.antigas, 0);
+    assert!(v.problemas.is_empty(), "{:?}", v.problemas);
+
+    let linhas: Vec<_> = texto.lines().collect();
+    let segunda: serde_json::Value = serde_json::from_str(linhas[1]).unwrap();
+    let terceira: serde_json::Value = serde_json::from_str(linhas[2]).unwrap();
+    assert_eq!(segunda["uid"], "bruno");
+    assert_eq!(terceira["anterior"], segunda["hash"]);
+    assert_eq!(v.ultimo_hash.as_deref(), terceira["hash"].as_str());
+
+    // Com o último hash guardado antes do registro da carla
+    let ultimo = segunda["hash"].as_str();
+    assert_eq!(verificar(&texto, ultimo).depois_do_ultimo, Some(1));
+}
+
+#[tokio::test]
+async fn encontra_linhas_alteradas_e_apagadas() {
+    let arquivo =
+        trilha("auditoria-adulterada", &["ana", "bruno", "carla"]).await;
+    let texto = std::fs::read_to_string(&arquivo).unwrap();
+    std::fs::remove_file(&arquivo).unwrap();
+    let linhas: Vec<_> = texto.lines().collect();
+
+    let alterada = texto.replace("\"uid\":\"bruno\"", "\"uid\":\"bruna\"");
+    assert_eq!(
+        verificar(&alterada, None).problemas,
+        vec![Adulteracao::Alterada(2)],
+    );
+
+    let sem_a_segunda = format!("{}\n{}\n", linhas[0], linhas[2]);
+    assert_eq!(
+        verificar(&sem_a_segunda, None).problemas,
+        vec![Adulteracao::EncadeamentoQuebrado(2)],
+    );
+
+    // Sem a última linha, só o hash guardado revela o truncamento
+    let truncada = format!("{}\n{}\n", linhas[0], linhas[1]);
+    let ultimo = verificar(&texto, None).ultimo_hash;
+    assert!(verificar(&truncada, None).problemas.is_empty());
+    assert_eq!(
+        verificar(&truncada, ultimo.as_deref()).problemas,
+        vec![Adulteracao::UltimoAusente],
+    );
+}
+
+#[tokio::test]
+async fn continua_trilhas_anteriores_ao_encadeamento() {
+    let antiga = r#"{"quando":"2024-01-01T00:00:00Z","operacao":"reativar","uid":"ana","motivo":"x"}"#;
+    let arquivo = std::env::temp_dir().join(format!(
+        "alumnic-auditoria-antiga-{}.jsonl",
+        std::process::id()
+    ));
+    std::fs::write(&arquivo, format!("{antiga}\n")).unwrap();
+    let cfg = ConfiguracaoAuditoria {
+        arquivo: Some(arquivo.clone()),
+    };
+    let registro = Registro {
+        quando: Utc::now(),
+        operacao: "reativar",
+        uid: "bruno",
+        motivo: "Destrancou a matrícula",
+    };
+    registrar(&cfg, &registro).await.unwrap();
+    let texto = std::fs::read_to_string(&arquivo).unwrap();
+    std::fs::remove_file(&arquivo).unwrap();
+
+    let v = verificar(&texto, None);
+    assert_eq!((v.antigas, v.encadeadas), (1, 1));
+    assert!(v.problemas.is_empty(), "{:?}", v.problemas);
+
+    // Uma linha sem encadeamento depois do começo dele foi inserida
+    let inserida = format!("{texto}{antiga}\n");
+    assert_eq!(
+        verificar(&inserida, None).problemas,
+        vec![Adulteracao::SemEncadeamento(3)],
+    );
+}
+
+#[tokio::test]
+async fn trilha_sem_nenhum_hash() {
+    let arquivo = trilha("auditoria-sem-hash", &["ana", "bruno"]).await;
+    let texto = std::fs::read_to_string(&arquivo).unwrap();
+    std::fs::remove_file(&arquivo).unwrap();
+    let ultimo = verificar(&texto, None).ultimo_hash;
+
+    let sem_hash: String = texto
+        .lines()
+        .map(|l| {
+            let (sem_hash, _) = l.rsplit_once(",\"hash\":").unwrap();
+            format!("{sem_hash}}}\n")
+        })
+        .collect();
+    let v = verificar(&sem_hash, None);
+    assert_eq!((v.antigas, v.encadeadas), (0, 0));
+    assert_eq!(
+        v.problemas,
+        vec![Adulteracao::HashApagado(1), Adulteracao::HashApagado(2)],
+    );
+
+    // Reescrita como uma trilha antiga, só o hash guardado revela a troca
+    let como_antiga: String = sem_hash
+        .lines()
+        .map(|l| {
+            let (sem_anterior, _) = l.rsplit_once(",\"anterior\":").unwrap();
+            format!("{sem_anterior}}}\n")
+        })
+        .collect();
+    let v = verificar(&como_antiga, None);
+    assert_eq!(v.antigas, 2);
+    assert!(v.problemas.is_empty(), "{:?}", v.problemas);
+    assert_eq!(
+        verificar(&como_antiga, ultimo.as_deref()).problemas,
+        vec![Adulteracao::UltimoAusente],
+    );
+}
@@ -0,0 +1,141 @@
+//! Testes dos backups assinados.
+
+mod comum;
+
+use alumnic::backup::{
+    ConfiguracaoBackup, ErroDeBackup, arquivo_da_assinatura, fazer_backup,
+    gerar_chave, ler_backup,
+};
+use alumnic::configuracao::Configuracao;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::restauracao::restaurar;
+use chrono::Utc;
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// Um diretório temporário só para o `teste`.
+fn diretorio(teste: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("alumnic-backup-{teste}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A configuração com um par de chaves novo, com a privada em `dir`.
+fn com_chaves(gnosys_url: &str, dir: &std::path::Path) -> Configuracao {
+    let (privada, publica) = gerar_chave().unwrap();
+    let chave_privada = dir.join("backup.pk8");
+    std::fs::write(&chave_privada, privada).unwrap();
+
+    let mut cfg = configuracao(gnosys_url);
+    cfg.backup = Some(ConfiguracaoBackup {
+        chave_privada: Some(chave_privada),
+        chave_publica: publica,
+    });
+    cfg
+}
+
+async fn cadastrar_aluno(api: &ApiDeTeste) {
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn restaura_do_backup_assinado() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar_aluno(&api).await;
+    let dir = diretorio("restaura");
+    let cfg = com_chaves(&api.gnosys.url, &dir);
+    let arquivo = dir.join("contas.ldif");
+
+    let contas = fazer_backup(&arquivo, &cfg, &api.ldap).await.unwrap();
+    assert_eq!(contas, 1);
+    assert!(arquivo_da_assinatura(&arquivo).exists());
+
+    api.ldap.lock().await.remover(DN_ALUNO).await.unwrap();
+    let ldif = ler_backup(&arquivo, &cfg).unwrap();
+    restaurar("claudiolc", &ldif, "b", &cfg, &api.ldap, Utc::now())
+        .await
+        .unwrap();
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_some());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn backup_e_so_do_dono_e_nao_sobrescreve_outro() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar_aluno(&api).await;
+    let dir = diretorio("modo");
+    let cfg = com_chaves(&api.gnosys.url, &dir);
+    let arquivo = dir.join("contas.ldif");
+
+    fazer_backup(&arquivo, &cfg, &api.ldap).await.unwrap();
+    let modo = std::fs::metadata(&arquivo).unwrap().permissions().mode();
+    assert_eq!(modo & 0o777, 0o600);
+
+    let original = std::fs::read(&arquivo).unwrap();
+    assert!(matches!(
+        fazer_backup(&arquivo, &cfg, &api.ldap).await,
+        Err(ErroDeBackup::Io(e)) if e.kind() == std::io::ErrorKind::AlreadyExists
+    ));
+    assert_eq!(std::fs::read(&arquivo).unwrap(), original);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn recusa_backups_adulterados_truncados_ou_sem_assinatura() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar_aluno(&api).await;
+    let dir = diretorio("adulterado");
+    let cfg = com_chaves(&api.gnosys.url, &dir);
+    let arquivo = dir.join("contas.ldif");
+    fazer_backup(&arquivo, &cfg, &api.ldap).await.unwrap();
+    let original = std::fs::read_to_string(&arquivo).unwrap();
+
+    let adulterado = original.replace("uidNumber: ", "uidNumber: 1");
+    std::fs::write(&arquivo, adulterado).unwrap();
+    assert!(matches!(
+        ler_backup(&arquivo, &cfg),
+        Err(ErroDeBackup::AssinaturaInvalida)
+    ));
+
+    std::fs::write(&arquivo, &original[..original.len() / 2]).unwrap();
+    assert!(matches!(
+        ler_backup(&arquivo, &cfg),
+        Err(ErroDeBackup::AssinaturaInvalida)
+    ));
+
+    // Assinado com outra chave
+    std::fs::write(&arquivo, &original).unwrap();
+    let outra = com_chaves(&api.gnosys.url, &diretorio("outra"));
+    assert!(matches!(
+        ler_backup(&arquivo, &outra),
+        Err(ErroDeBackup::AssinaturaInvalida)
+    ));
+    assert_eq!(ler_backup(&arquivo, &cfg).unwrap(), original);
+
+    std::fs::remove_file(arquivo_da_assinatura(&arquivo)).unwrap();
+    assert!(matches!(
+        ler_backup(&arquivo, &cfg),
+        Err(ErroDeBackup::SemAssinatura(_))
+    ));
+    // Sem as chaves configuradas, a assinatura não é exigida
+    assert_eq!(
+        ler_backup(&arquivo, &configuracao(&api.gnosys.url)).unwrap(),
+        original
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(diretorio("outra")).unwrap();
+}
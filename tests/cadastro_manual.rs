@@ -0,0 +1,66 @@
+//! Testes do cadastro feito pela supervisão, sem o documento.
+
+mod comum;
+
+use alumnic::cadastro_aluno::DadosParaCadastro;
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use comum::api::{configuracao, diretorio_com_samba};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+async fn cadastrar(atributo: Option<&str>) -> Arc<Mutex<DiretorioMemoria>> {
+    let mut cfg = configuracao("http://gnosys.invalido").usuario_novo;
+    cfg.atributo_responsavel = atributo.map(String::from);
+    let ldap = Arc::new(Mutex::new(diretorio_com_samba().await));
+
+    DadosParaCadastro {
+        dre: "123456789".to_string(),
+        data: String::new(),
+        hora: String::new(),
+        codigo: String::new(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".to_string().into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    }
+    .cadastrar_sem_verificar_documento(
+        "claudiolc".to_string(),
+        "arthur com o bind cn=admin,dc=dcc,dc=ufrj,dc=br",
+        &cfg,
+        &ConfiguracaoRenovacao::default(),
+        "alunos",
+        &ldap,
+    )
+    .await
+    .unwrap();
+
+    ldap
+}
+
+#[tokio::test]
+async fn grava_quem_criou_a_conta() {
+    let ldap = cadastrar(Some("description")).await;
+
+    let ldap = ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).unwrap();
+    assert_eq!(
+        entrada.attrs["description"],
+        ["arthur com o bind cn=admin,dc=dcc,dc=ufrj,dc=br"]
+    );
+}
+
+#[tokio::test]
+async fn sem_o_atributo_a_entrada_nao_muda() {
+    let ldap = cadastrar(None).await;
+
+    let ldap = ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).unwrap();
+    assert!(!entrada.attrs.contains_key("description"));
+}
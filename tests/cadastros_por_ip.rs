@@ -0,0 +1,172 @@
+//! Testes do aviso à supervisão quando um mesmo IP envia muitos cadastros e
+//! da contagem da origem dos cadastros.
+
+mod comum;
+
+use alumnic::cadastros_por_ip::ConfiguracaoCadastrosPorIp;
+use alumnic::ldap::turmas::Periodo;
+use alumnic::origens::{self, ConfiguracaoOrigens, Rede};
+use chrono::Local;
+use comum::api::{ApiDeTeste, diretorio_com_samba};
+use comum::gnosys::Documento;
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn sh(script: String) -> Vec<String> {
+    vec!["sh".to_string(), "-c".to_string(), script]
+}
+
+/// Um arquivo temporário com o `nome`, único para o processo de teste.
+fn temporario(nome: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("alumnic-{nome}-{}", std::process::id()))
+}
+
+/// Espera o aviso, que é enviado depois da resposta, ser gravado no
+/// `caminho`.
+async fn esperar_aviso(caminho: &Path) -> String {
+    for _ in 0..50 {
+        if let Ok(aviso) = std::fs::read_to_string(caminho)
+            && !aviso.is_empty()
+        {
+            std::fs::remove_file(caminho).unwrap();
+            return aviso;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("o aviso não foi enviado");
+}
+
+/// Envia um cadastro do `dre`, que falha por não estar no Gnosys, pelo
+/// proxy com o endereço `ip`.
+async fn cadastrar_de(api: &ApiDeTeste, ip: &str, dre: &str) {
+    let corpo = json!({
+        "dre": dre,
+        "data": "01/01/2025",
+        "hora": "12:00",
+        "codigo": "ABCD.EFGH.IJKL",
+        "nome": "Cláudio de Lima Cavalcante",
+        "email": "claudio@exemplo.com",
+        "telefone": "(21) 98765-4321",
+        "senha": "Senha1234",
+    });
+    let res = reqwest::Client::new()
+        .post(format!("{}/api/cadastrar", api.url))
+        .header("X-Forwarded-For", format!("10.0.0.1, {ip}"))
+        .header("Content-Type", "application/json")
+        .body(corpo.to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(res.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn avisa_a_supervisao_dos_cadastros_do_mesmo_ip() {
+    let caixa = temporario("cadastros-por-ip.eml");
+    let comando = sh(format!("cat > {}", caixa.display()));
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.notificacao.comando = comando;
+            cfg.notificacao.remetente = "alumnic@ic.ufrj.br".to_string();
+            cfg.cadastros_por_ip = Some(ConfiguracaoCadastrosPorIp {
+                janela_minutos: 60,
+                limite: 3,
+                destinatario: "supervisao@ic.ufrj.br".to_string(),
+                proxy: true,
+            });
+        })
+        .await;
+
+    for (ip, dre) in [
+        ("146.164.2.7", "111111111"),
+        ("146.164.2.7", "222222222"),
+        ("146.164.2.7", "222222222"),
+        ("146.164.9.9", "333333333"),
+    ] {
+        cadastrar_de(&api, ip, dre).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!caixa.exists(), "aviso antes do limite");
+
+    cadastrar_de(&api, "146.164.2.7", "444444444").await;
+    let aviso = esperar_aviso(&caixa).await;
+    assert!(aviso.contains("To: supervisao@ic.ufrj.br\n"), "{aviso}");
+    assert!(aviso.contains("O endereço 146.164.2.7 enviou cadastros de 3"));
+    assert!(
+        aviso.ends_with("111111111\n222222222\n444444444\n"),
+        "{aviso}"
+    );
+}
+
+#[tokio::test]
+async fn conta_a_origem_dos_cadastros_sem_o_endereco() {
+    let dados = temporario("origens");
+    let dir = dados.clone();
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.dados = dir;
+            cfg.origens = Some(ConfiguracaoOrigens {
+                redes: vec![Rede {
+                    nome: "laboratorios".to_string(),
+                    faixas: vec!["146.164.2.0/24".parse().unwrap()],
+                }],
+                externa: "externa".to_string(),
+                proxy: true,
+            });
+        })
+        .await;
+    let documento = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    api.gnosys.registrar(documento.clone());
+
+    // O cadastro que falha não conta
+    cadastrar_de(&api, "146.164.9.9", "333333333").await;
+    let res = reqwest::Client::new()
+        .post(format!("{}/api/cadastrar", api.url))
+        .header("X-Forwarded-For", "146.164.2.7")
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "dre": documento.dre,
+                "data": documento.data,
+                "hora": documento.hora,
+                "codigo": documento.codigo,
+                "nome": "Cláudio de Lima Cavalcante",
+                "email": "claudio@exemplo.com",
+                "telefone": "(21) 98765-4321",
+                "senha": "Senha1234",
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 201);
+
+    let (status, corpo) = api.get_admin("/api/admin/estatisticas").await;
+    assert_eq!(status, 200, "{corpo}");
+    let estatisticas: Value = serde_json::from_str(&corpo).unwrap();
+    assert_eq!(
+        estatisticas["cadastros_por_origem"],
+        json!({"laboratorios": 1})
+    );
+
+    // A contagem do período é gravada depois da resposta
+    let periodo = Periodo::da_data(Local::now().date_naive());
+    let mut contagem = Default::default();
+    for _ in 0..50 {
+        contagem = origens::do_periodo(&dados, periodo).unwrap();
+        if !contagem.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(contagem, [("laboratorios".to_string(), 1)].into());
+    let gravado = std::fs::read_to_string(dados.join("origens.json")).unwrap();
+    assert!(!gravado.contains("146.164"), "{gravado}");
+    std::fs::remove_dir_all(&dados).unwrap();
+}
@@ -0,0 +1,89 @@
+//! Testes da criação das caixas de email no cadastro.
+
+mod comum;
+
+use alumnic::cadastro_aluno::DadosParaCadastro;
+use alumnic::caixa_email::{ConfiguracaoCaixa, provisionar_pendentes};
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use comum::api::{configuracao, diretorio_com_samba};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+fn sh(script: &str) -> ConfiguracaoCaixa {
+    ConfiguracaoCaixa::Comando {
+        comando: vec!["sh".into(), "-c".into(), script.into()],
+    }
+}
+
+/// Cadastra o aluno criando a caixa com o `script`, retornando o diretório.
+async fn cadastrar_com_caixa(script: &str) -> Arc<Mutex<DiretorioMemoria>> {
+    let mut cfg = configuracao("http://gnosys.invalido").usuario_novo;
+    cfg.caixa_email = Some(sh(script));
+    let ldap = Arc::new(Mutex::new(diretorio_com_samba().await));
+
+    let dados = DadosParaCadastro {
+        dre: "123456789".to_string(),
+        data: String::new(),
+        hora: String::new(),
+        codigo: String::new(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".to_string().into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    };
+    dados
+        .cadastrar_sem_verificar_documento(
+            "claudiolc".to_string(),
+            "supervisor",
+            &cfg,
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &ldap,
+        )
+        .await
+        .unwrap();
+
+    ldap
+}
+
+async fn estado_caixa(ldap: &Mutex<DiretorioMemoria>) -> Vec<String> {
+    ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoCaixa"].clone()
+}
+
+#[tokio::test]
+async fn cria_a_caixa_no_cadastro() {
+    let ldap = cadastrar_com_caixa(concat!(
+        r#"test "$ALUMNIC_EMAIL" = claudiolc@ic.ufrj.br && "#,
+        r#"test "$ALUMNIC_NOME" = "Cláudio de Lima Cavalcante""#,
+    ))
+    .await;
+
+    assert_eq!(estado_caixa(&ldap).await, vec!["criada"]);
+}
+
+#[tokio::test]
+async fn caixa_que_falhou_e_criada_depois() {
+    // A conta é criada mesmo se a caixa falhar
+    let ldap = cadastrar_com_caixa("exit 1").await;
+    assert_eq!(estado_caixa(&ldap).await, vec!["falhou"]);
+
+    let resultados =
+        provisionar_pendentes(&sh(r#"test -n "$ALUMNIC_NOME""#), &ldap)
+            .await
+            .unwrap();
+    assert_eq!(resultados.len(), 1);
+    assert_eq!(resultados[0].0.uid, "claudiolc");
+    assert!(resultados[0].1.is_ok());
+    assert_eq!(estado_caixa(&ldap).await, vec!["criada"]);
+
+    // Uma caixa criada não é criada de novo
+    let resultados = provisionar_pendentes(&sh("exit 1"), &ldap).await.unwrap();
+    assert!(resultados.is_empty());
+}
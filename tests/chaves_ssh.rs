@@ -0,0 +1,142 @@
+//! Testes das chaves SSH públicas, no cadastro e depois dele.
+
+mod comum;
+
+use alumnic::auditoria::ConfiguracaoAuditoria;
+use alumnic::chaves_ssh::{
+    ErroChaveSsh, adicionar_chave, listar_chaves, remover_chave,
+};
+use chrono::Utc;
+use comum::api::ApiDeTeste;
+use comum::gnosys::Documento;
+use serde_json::json;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+const ED25519: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIDgJE5tJHqoMuq5JLqULfbJIvBzZ51WPDlDDxodR6vun joao@exemplo";
+const ECDSA: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBGf4Lb/kc8U/JKyxhI3Cl1w7ar4IzkNSwfN994+MNOICoLdDVFTBzI8OXyUs+1csmbFXOG1YA1HVuF12L3t98Ic=";
+
+/// Cadastra o aluno pela API com a `chave_ssh`, retornando o status.
+async fn cadastrar_com_chave(api: &ApiDeTeste, chave_ssh: &str) -> u16 {
+    let documento = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    api.gnosys.registrar(documento.clone());
+
+    let corpo = json!({
+        "dre": documento.dre,
+        "data": documento.data,
+        "hora": documento.hora,
+        "codigo": documento.codigo,
+        "nome": "Cláudio de Lima Cavalcante",
+        "email": "claudio@exemplo.com",
+        "telefone": "(21) 98765-4321",
+        "senha": "Senha1234",
+        "chave_ssh": chave_ssh,
+    });
+    api.post("/api/cadastrar", &corpo.to_string()).await.0
+}
+
+#[tokio::test]
+async fn cadastro_grava_a_chave() {
+    let api = ApiDeTeste::iniciar().await;
+
+    assert_eq!(
+        cadastrar_com_chave(&api, &format!(" {ED25519}\n")).await,
+        201
+    );
+
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).unwrap();
+    assert_eq!(entrada.attrs["sshPublicKey"], vec![ED25519]);
+    assert!(entrada.attrs["objectClass"].contains(&"ldapPublicKey".into()));
+}
+
+#[tokio::test]
+async fn cadastro_recusa_chave_invalida() {
+    let api = ApiDeTeste::iniciar().await;
+
+    assert_eq!(cadastrar_com_chave(&api, "ssh-ed25519 AAAA").await, 422);
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_none());
+}
+
+#[tokio::test]
+async fn adiciona_e_remove_chaves() {
+    let api = ApiDeTeste::iniciar().await;
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+    let auditoria = ConfiguracaoAuditoria::default();
+
+    assert!(
+        listar_chaves("claudiolc", &api.ldap)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let impressao = adicionar_chave(
+        "claudiolc",
+        ED25519,
+        &auditoria,
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        impressao,
+        "SHA256:jGfrNhkq7KoGXSqttQMF265Tbv2ltp64A6do8OzrP8Q"
+    );
+    adicionar_chave("claudiolc", ECDSA, &auditoria, &api.ldap, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(
+        listar_chaves("claudiolc", &api.ldap).await.unwrap(),
+        vec![ED25519, ECDSA],
+    );
+
+    // A mesma chave com outro comentário é repetida
+    let r = adicionar_chave(
+        "claudiolc",
+        &format!("{ECDSA} outro"),
+        &auditoria,
+        &api.ldap,
+        Utc::now(),
+    )
+    .await;
+    assert!(matches!(r, Err(ErroChaveSsh::ChaveRepetida(..))));
+
+    // Pela impressão digital e pela própria chave
+    remover_chave("claudiolc", &impressao, &auditoria, &api.ldap, Utc::now())
+        .await
+        .unwrap();
+    remover_chave("claudiolc", ECDSA, &auditoria, &api.ldap, Utc::now())
+        .await
+        .unwrap();
+
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).unwrap();
+    assert!(!entrada.attrs.contains_key("sshPublicKey"));
+    assert!(!entrada.attrs["objectClass"].contains(&"ldapPublicKey".into()));
+}
+
+#[tokio::test]
+async fn conta_inexistente() {
+    let api = ApiDeTeste::iniciar().await;
+
+    let r = adicionar_chave(
+        "ninguem",
+        ED25519,
+        &ConfiguracaoAuditoria::default(),
+        &api.ldap,
+        Utc::now(),
+    )
+    .await;
+    assert!(matches!(r, Err(ErroChaveSsh::ContaInexistente(..))));
+}
@@ -0,0 +1,81 @@
+//! Testes do cliente tipado da API. Só rodam com a feature `client`.
+#![cfg(feature = "client")]
+
+mod comum;
+
+use alumnic::cadastro_aluno::DadosParaCadastro;
+use alumnic::client::{ClienteApi, ErroCliente};
+use alumnic::manutencao::ModoManutencao;
+use comum::api::{ApiDeTeste, TOKEN};
+use comum::gnosys::Documento;
+
+fn dados(d: &Documento) -> DadosParaCadastro {
+    DadosParaCadastro {
+        dre: d.dre.clone(),
+        data: d.data.clone(),
+        hora: d.hora.clone(),
+        codigo: d.codigo.clone(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    }
+}
+
+#[tokio::test]
+async fn cadastra_e_consulta_pelo_cliente() {
+    let api = ApiDeTeste::iniciar().await;
+    let cliente =
+        ClienteApi::novo(format!("{}/", api.url)).com_token(TOKEN.into());
+
+    let usernames = cliente
+        .usernames("Cláudio de Lima Cavalcante")
+        .await
+        .unwrap();
+    assert!(usernames.contains(&"claudiolc".to_string()));
+
+    let d = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    api.gnosys.registrar(d.clone());
+    let resposta = cliente.cadastrar(&dados(&d)).await.unwrap();
+    assert!(resposta.message.contains("\"claudiolc\""), "{resposta:?}");
+
+    // O erro vem com a mensagem da API
+    match cliente.cadastrar(&dados(&d)).await {
+        Err(ErroCliente::Api { status, mensagem }) => {
+            assert_eq!(status, 409);
+            assert!(mensagem.contains("claudiolc"), "{mensagem}");
+        },
+        r => panic!("o cadastro repetido não foi recusado: {r:?}"),
+    }
+
+    let estatisticas = cliente.estatisticas().await.unwrap();
+    assert_eq!(estatisticas.cadastros_por_curso["alunos"], 1);
+    assert!(cliente.metricas().await.unwrap().contains("# TYPE"));
+}
+
+#[tokio::test]
+async fn rotas_administrativas_pelo_cliente() {
+    let api = ApiDeTeste::iniciar().await;
+    let cliente = ClienteApi::novo(&api.url).com_token(TOKEN.into());
+
+    let modo = ModoManutencao {
+        somente_leitura: true,
+        mensagem: Some("Migrando o LDAP".to_string()),
+    };
+    assert_eq!(cliente.mudar_somente_leitura(&modo).await.unwrap(), modo);
+    assert_eq!(cliente.somente_leitura().await.unwrap(), modo);
+    assert!(cliente.saude().await.unwrap().somente_leitura);
+
+    let sem_token = ClienteApi::novo(&api.url);
+    assert!(matches!(
+        sem_token.estatisticas().await,
+        Err(ErroCliente::Api { status: 401, .. }),
+    ));
+}
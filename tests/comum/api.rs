@@ -0,0 +1,232 @@
+//! A API do alumnic rodando em uma porta local, com o LDAP em memória e o
+//! [Gnosys falso](super::gnosys::GnosysFalso).
+
+use super::gnosys::{Documento, GnosysFalso};
+use alumnic::configuracao::Configuracao;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use config::{Config, File, FileFormat};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Token das rotas administrativas da API de teste.
+pub const TOKEN: &str = "token-de-teste";
+
+/// Token da rota do helpdesk da API de teste.
+pub const TOKEN_HELPDESK: &str = "token-do-helpdesk";
+
+/// Consultas por minuto permitidas ao helpdesk na API de teste.
+pub const LIMITE_HELPDESK: u32 = 5;
+
+/// Token das rotas SCIM da API de teste.
+pub const TOKEN_SCIM: &str = "token-do-scim";
+
+/// O grupo dos supervisores que entram no painel da API de teste.
+pub const GRUPO_PAINEL: &str = "cn=supervisores,ou=grupos,dc=dcc,dc=ufrj,dc=br";
+
+/// A configuração usada nos testes, apontando para o Gnosys em `gnosys_url`.
+pub fn configuracao(gnosys_url: &str) -> Configuracao {
+    let yaml = format!(
+        r#"
+ldap_url: "ldap://ldap.invalido"
+ldap_bind_dn: "cn=admin,dc=dcc,dc=ufrj,dc=br"
+ldap_bind_pw: "admin"
+api_token: "{TOKEN}"
+gnosys_url: "{gnosys_url}"
+helpdesk:
+  token: "{TOKEN_HELPDESK}"
+  limite_por_minuto: {LIMITE_HELPDESK}
+scim:
+  token: "{TOKEN_SCIM}"
+  maximo_por_pagina: 2
+painel:
+  grupo: "{GRUPO_PAINEL}"
+  cookie_seguro: false
+usuario_novo:
+  gid_number: "1000"
+  samba_sid_prefix: "S-1-5-21-1-2-3-"
+  samba_acct_flags: "[UX]"
+  samba_lm_password: "XXX"
+  samba_password_history: "000"
+  samba_primary_group_sid: "S-1-5-21-1-2-3-513"
+  cota: "1000"
+  reserva_username:
+    minutos: 30
+"#
+    );
+
+    Config::builder()
+        .add_source(File::from_str(&yaml, FileFormat::Yaml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+/// Um diretório com o domínio do Samba, o mínimo para um cadastro funcionar.
+pub async fn diretorio_com_samba() -> DiretorioMemoria {
+    let mut d = DiretorioMemoria::default();
+    d.adicionar(
+        "sambaDomainName=DCC,dc=dcc,dc=ufrj,dc=br",
+        vec![
+            ("objectClass", ["sambaDomain"].into()),
+            ("uidNumber", ["5000"].into()),
+            ("gidNumber", ["5000"].into()),
+            ("sambaNextRid", ["9000"].into()),
+        ],
+    )
+    .await
+    .unwrap();
+    d
+}
+
+pub struct ApiDeTeste {
+    /// Endereço base da API.
+    pub url: String,
+    pub gnosys: GnosysFalso,
+    pub ldap: Arc<Mutex<DiretorioMemoria>>,
+    cliente: reqwest::Client,
+    tarefa: JoinHandle<()>,
+}
+
+impl ApiDeTeste {
+    /// Sobe a API com um diretório que só tem o domínio do Samba.
+    pub async fn iniciar() -> Self {
+        Self::iniciar_com(diretorio_com_samba().await).await
+    }
+
+    /// Sobe a API com o diretório `ldap`.
+    pub async fn iniciar_com(ldap: DiretorioMemoria) -> Self {
+        Self::iniciar_ajustando(ldap, |_| {}).await
+    }
+
+    /// Sobe a API com o diretório `ldap` e a configuração de teste mudada
+    /// pelo `ajuste`.
+    pub async fn iniciar_ajustando(
+        ldap: DiretorioMemoria,
+        ajuste: impl FnOnce(&mut Configuracao),
+    ) -> Self {
+        let gnosys = GnosysFalso::iniciar().await;
+        let ldap = Arc::new(Mutex::new(ldap));
+
+        let mut cfg = configuracao(&gnosys.url);
+        ajuste(&mut cfg);
+        let app = alumnic::api::router(Arc::new(cfg), ldap.clone());
+
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let tarefa = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self {
+            url,
+            gnosys,
+            ldap,
+            cliente: reqwest::Client::new(),
+            tarefa,
+        }
+    }
+
+    /// Envia `corpo` como JSON para `caminho`, retornando o status e o JSON da
+    /// resposta.
+    pub async fn post(&self, caminho: &str, corpo: &str) -> (u16, Value) {
+        let res = self
+            .cliente
+            .post(format!("{}{caminho}", self.url))
+            .header("Content-Type", "application/json")
+            .body(corpo.to_string())
+            .send()
+            .await
+            .unwrap();
+
+        let status = res.status().as_u16();
+        (
+            status,
+            serde_json::from_str(&res.text().await.unwrap()).unwrap(),
+        )
+    }
+
+    /// Registra o `documento` no Gnosys e cadastra o aluno dele pela API,
+    /// com o nome "Cláudio de Lima Cavalcante", que gera o uid `claudiolc`.
+    pub async fn cadastrar(&self, documento: Documento) {
+        self.gnosys.registrar(documento.clone());
+
+        let corpo = json!({
+            "dre": documento.dre,
+            "data": documento.data,
+            "hora": documento.hora,
+            "codigo": documento.codigo,
+            "nome": "Cláudio de Lima Cavalcante",
+            "email": "claudio@exemplo.com",
+            "telefone": "(21) 98765-4321",
+            "senha": "Senha1234",
+        });
+        let (status, resposta) =
+            self.post("/api/cadastrar", &corpo.to_string()).await;
+        assert_eq!(status, 201, "{resposta}");
+    }
+
+    /// Faz um GET em `caminho`, retornando o status e o JSON da resposta.
+    pub async fn get(&self, caminho: &str) -> (u16, Value) {
+        let res = self
+            .cliente
+            .get(format!("{}{caminho}", self.url))
+            .send()
+            .await
+            .unwrap();
+
+        let status = res.status().as_u16();
+        (
+            status,
+            serde_json::from_str(&res.text().await.unwrap()).unwrap(),
+        )
+    }
+
+    /// Faz um GET em uma rota administrativa, com o token de teste.
+    pub async fn get_admin(&self, caminho: &str) -> (u16, String) {
+        self.get_com_token(caminho, TOKEN).await
+    }
+
+    /// Envia `corpo` como JSON num PUT para uma rota administrativa, com o
+    /// token de teste.
+    pub async fn put_admin(&self, caminho: &str, corpo: &str) -> (u16, String) {
+        let res = self
+            .cliente
+            .put(format!("{}{caminho}", self.url))
+            .bearer_auth(TOKEN)
+            .header("Content-Type", "application/json")
+            .body(corpo.to_string())
+            .send()
+            .await
+            .unwrap();
+
+        (res.status().as_u16(), res.text().await.unwrap())
+    }
+
+    /// Faz um GET em `caminho` com o `token` no cabeçalho `Authorization`.
+    pub async fn get_com_token(
+        &self,
+        caminho: &str,
+        token: &str,
+    ) -> (u16, String) {
+        let res = self
+            .cliente
+            .get(format!("{}{caminho}", self.url))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+
+        (res.status().as_u16(), res.text().await.unwrap())
+    }
+}
+
+impl Drop for ApiDeTeste {
+    fn drop(&mut self) {
+        self.tarefa.abort();
+    }
+}
@@ -0,0 +1,194 @@
+//! Um Gnosys falso, que reproduz o fluxo JSF usado pelo
+//! [`portal_ufrj`](alumnic::portal_ufrj): o GET devolve o formulário com o
+//! `javax.faces.ViewState` e um cookie de sessão, e o POST responde se o
+//! documento é válido ou não.
+
+use axum::Router;
+use axum::extract::{Form, State};
+use axum::http::{HeaderMap, header};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const VIEW_STATE: &str = "j_id7";
+const COOKIE: &str = "JSESSIONID=sessao-falsa";
+
+/// Como o Gnosys falso deve se comportar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Modo {
+    /// Responde normalmente.
+    #[default]
+    Normal,
+    /// Responde o POST com a página de manutenção, que não diz se o documento
+    /// é válido ou não.
+    Manutencao,
+    /// O formulário do GET vem sem o ViewState, como quando o Gnosys muda.
+    SemViewState,
+    /// Responde normalmente, mas o POST leva meio segundo, como nos dias de
+    /// pico.
+    Lento,
+}
+
+/// Um documento de "Regularmente Matriculado" que o Gnosys falso considera
+/// válido.
+#[derive(Debug, Clone)]
+pub struct Documento {
+    pub dre: String,
+    pub data: String,
+    pub hora: String,
+    pub codigo: String,
+    pub nome: String,
+    pub curso: String,
+}
+
+impl Documento {
+    /// Um documento válido do curso `curso`, com o código usado nos testes.
+    pub fn novo(dre: &str, nome: &str, curso: &str) -> Self {
+        Self {
+            dre: dre.to_string(),
+            data: "01/03/2025".to_string(),
+            hora: "10:00".to_string(),
+            codigo: "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF".to_string(),
+            nome: nome.to_string(),
+            curso: curso.to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Estado {
+    modo: Modo,
+    documentos: Vec<Documento>,
+    consultas: usize,
+}
+
+/// Servidor do Gnosys falso rodando em uma porta local. O servidor é parado
+/// quando a estrutura é destruída.
+pub struct GnosysFalso {
+    /// Endereço base para passar para o
+    /// [`consulta_em`](alumnic::portal_ufrj::consulta_em).
+    pub url: String,
+    estado: Arc<Mutex<Estado>>,
+    tarefa: JoinHandle<()>,
+}
+
+impl GnosysFalso {
+    pub async fn iniciar() -> Self {
+        let estado = Arc::new(Mutex::new(Estado::default()));
+
+        let app = Router::new()
+            .route(
+                "/Documentos/autenticacao/regularmenteMatriculado",
+                get(formulario),
+            )
+            .route("/Documentos/autenticacao.seam", post(autenticar))
+            .with_state(estado.clone());
+
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let tarefa = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self {
+            url,
+            estado,
+            tarefa,
+        }
+    }
+
+    pub fn registrar(&self, documento: Documento) {
+        self.estado.lock().unwrap().documentos.push(documento);
+    }
+
+    pub fn modo(&self, modo: Modo) {
+        self.estado.lock().unwrap().modo = modo;
+    }
+
+    /// Quantas vezes o formulário de autenticação foi enviado.
+    pub fn consultas(&self) -> usize {
+        self.estado.lock().unwrap().consultas
+    }
+}
+
+impl Drop for GnosysFalso {
+    fn drop(&mut self) {
+        self.tarefa.abort();
+    }
+}
+
+async fn formulario(State(estado): State<Arc<Mutex<Estado>>>) -> Response {
+    let view_state = match estado.lock().unwrap().modo {
+        Modo::SemViewState => String::new(),
+        _ => format!(
+            r#"<input type="hidden" name="javax.faces.ViewState" id="javax.faces.ViewState" value="{VIEW_STATE}" />"#
+        ),
+    };
+
+    (
+        [(header::SET_COOKIE, format!("{COOKIE}; Path=/"))],
+        Html(format!(
+            r#"<html><body><form id="gnosys-filtro">{view_state}</form></body></html>"#
+        )),
+    )
+        .into_response()
+}
+
+async fn autenticar(
+    State(estado): State<Arc<Mutex<Estado>>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Html<String> {
+    if estado.lock().unwrap().modo == Modo::Lento {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    let mut estado = estado.lock().unwrap();
+    estado.consultas += 1;
+
+    let sessao_valida = headers
+        .get(header::COOKIE)
+        .and_then(|c| c.to_str().ok())
+        .is_some_and(|c| c.contains(COOKIE));
+    let view_state_valido = form
+        .get("javax.faces.ViewState")
+        .is_some_and(|v| v == VIEW_STATE);
+
+    // O JSF responde com uma página sem nenhuma das duas mensagens quando a
+    // sessão expirou, o que é parecido com a página de manutenção
+    if estado.modo == Modo::Manutencao || !sessao_valida || !view_state_valido {
+        return Html(
+            "<html><body><h1>Sistema em manutenção</h1></body></html>"
+                .to_string(),
+        );
+    }
+
+    let campo = |nome: &str| form.get(nome).map(String::as_str).unwrap_or("");
+    let documento = estado.documentos.iter().find(|d| {
+        d.dre == campo("alunoMatricula")
+            && d.data == campo("dataAutenticacaoInputDate")
+            && d.hora == campo("hora")
+            && d.codigo == campo("assinatura")
+    });
+
+    Html(match documento {
+        Some(d) => format!(
+            concat!(
+                r#"<html><body><span id="msgDocumentoValido">Documento válido</span>"#,
+                r#"<div class="gnosys-item-visualizacao">{}</div>"#,
+                r#"<div class="gnosys-item-visualizacao">12.345.678-9</div>"#,
+                r#"<div class="gnosys-item-visualizacao">{}</div>"#,
+                "</body></html>",
+            ),
+            d.nome, d.curso,
+        ),
+        None => concat!(
+            r#"<html><body><span id="msgDocumentoInvalido">"#,
+            "Documento inválido</span></body></html>",
+        )
+        .to_string(),
+    })
+}
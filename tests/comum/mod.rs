@@ -0,0 +1,7 @@
+//! Utilidades compartilhadas entre os testes de integração.
+
+// Cada arquivo de teste usa só uma parte das utilidades
+#![allow(dead_code)]
+
+pub mod api;
+pub mod gnosys;
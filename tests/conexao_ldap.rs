@@ -0,0 +1,219 @@
+//! Testes do timeout e do encerramento das conexões com um servidor LDAP de
+//! verdade, simulado por servidores TCP mínimos.
+
+use alumnic::ldap::ErroLdap;
+use alumnic::ldap::conexao::{
+    CertificadoCliente, ConfiguracaoTls, FonteLdap, Replicas, ServidorLdap,
+};
+use alumnic::ldap::diretorio::DiretorioLdap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// A resposta de sucesso ao bind com o id de mensagem 1.
+const BIND_OK: [u8; 14] = [
+    0x30, 0x0c, 0x02, 0x01, 0x01, 0x61, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00,
+    0x04, 0x00,
+];
+
+fn servidor(porta: u16) -> ServidorLdap {
+    ServidorLdap {
+        url: format!("ldap://127.0.0.1:{porta}"),
+        bind_dn: "cn=admin,dc=dcc,dc=ufrj,dc=br".to_string(),
+        bind_pw: "admin".to_string(),
+        timeout: Duration::from_millis(200),
+        certificado: None,
+        tls: ConfiguracaoTls::default(),
+        replicas: Replicas::default(),
+    }
+}
+
+/// Um servidor que aceita o bind de uma conexão e então envia, pelo canal
+/// retornado, tudo o que recebeu do cliente até ele fechar o socket.
+async fn servidor_de_bind() -> (u16, oneshot::Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let porta = listener.local_addr().unwrap().port();
+    let (tx, rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut bind = [0; 256];
+        let lido = socket.read(&mut bind).await.unwrap();
+        assert!(lido > 0, "o cliente não enviou o bind");
+        socket.write_all(&BIND_OK).await.unwrap();
+
+        let mut recebido = vec![];
+        socket.read_to_end(&mut recebido).await.unwrap();
+        let _ = tx.send(recebido);
+    });
+
+    (porta, rx)
+}
+
+#[tokio::test]
+async fn servidor_travado_da_timeout() {
+    // Um servidor que aceita a conexão mas nunca responde ao bind
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let porta = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut conexoes = vec![];
+        while let Ok((socket, _)) = listener.accept().await {
+            conexoes.push(socket);
+        }
+    });
+
+    let inicio = Instant::now();
+    let r = servidor(porta).abrir().await;
+    assert!(matches!(r, Err(ErroLdap::Timeout)), "{:?}", r.err());
+    assert!(inicio.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn fechar_envia_o_unbind() {
+    let (porta, recebido) = servidor_de_bind().await;
+    let servidor = servidor(porta);
+
+    let conexao = servidor.abrir().await.unwrap();
+    servidor.fechar(conexao).await.unwrap();
+
+    let recebido = tokio::time::timeout(Duration::from_secs(5), recebido)
+        .await
+        .expect("a conexão não foi fechada")
+        .unwrap();
+    // O UnbindRequest é o [APPLICATION 2] NULL
+    assert!(
+        recebido.windows(2).any(|w| w == [0x42, 0x00]),
+        "{recebido:x?}"
+    );
+}
+
+#[tokio::test]
+async fn conexao_abandonada_e_fechada() {
+    let (porta, recebido) = servidor_de_bind().await;
+
+    // Uma requisição cancelada ou que entrou em pânico nunca chama o fechar
+    let conexao = servidor(porta).abrir().await.unwrap();
+    drop(conexao);
+
+    tokio::time::timeout(Duration::from_secs(5), recebido)
+        .await
+        .expect("a conexão ficou pendurada no servidor")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn principal_fora_do_ar_usa_a_replica_so_para_leitura() {
+    // Uma porta sem ninguém escutando, como um servidor fora do ar
+    let fechada = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let porta_fechada = fechada.local_addr().unwrap().port();
+    drop(fechada);
+    let (porta, _) = servidor_de_bind().await;
+    let servidor = ServidorLdap {
+        replicas: Replicas::new(vec![format!("ldap://127.0.0.1:{porta}")]),
+        ..servidor(porta_fechada)
+    };
+
+    let mut conexao = servidor.abrir().await.unwrap();
+    assert!(conexao.somente_leitura());
+    let r = conexao.remover("uid=joaops,dc=dcc,dc=ufrj,dc=br").await;
+    assert!(matches!(r, Err(ErroLdap::SomenteLeitura)), "{:?}", r.err());
+    // A escrita recusada não leva o cadastro para a fila de contingência
+    assert!(!r.unwrap_err().indisponivel());
+}
+
+/// O certificado de cliente de teste, autoassinado, em `tests/certificados/`.
+fn certificado() -> CertificadoCliente {
+    let pasta =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/certificados");
+    CertificadoCliente {
+        certificado: pasta.join("cliente.pem"),
+        chave: pasta.join("cliente.key"),
+        ca: Some(pasta.join("cliente.pem")),
+        starttls: false,
+    }
+}
+
+#[test]
+fn certificado_de_cliente_valido() {
+    certificado().conector().unwrap();
+}
+
+#[tokio::test]
+async fn certificado_invalido_nao_conecta() {
+    let (porta, _) = servidor_de_bind().await;
+
+    let mut sem_chave = certificado();
+    sem_chave.chave = sem_chave.chave.with_extension("inexistente");
+    // A chave não corresponde ao certificado
+    let mut trocado = certificado();
+    trocado.chave = trocado.certificado.clone();
+
+    for certificado in [sem_chave, trocado] {
+        let servidor = ServidorLdap {
+            certificado: Some(certificado),
+            ..servidor(porta)
+        };
+        let r = servidor.abrir().await;
+        assert!(matches!(r, Err(ErroLdap::Certificado(..))), "{:?}", r.err());
+    }
+}
+
+#[tokio::test]
+async fn starttls_vem_antes_do_bind() {
+    // Um servidor que guarda o primeiro pedido e fecha a conexão
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let porta = listener.local_addr().unwrap().port();
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut pedido = [0; 256];
+        let lido = socket.read(&mut pedido).await.unwrap();
+        let _ = tx.send(pedido[..lido].to_vec());
+    });
+
+    let servidor = ServidorLdap {
+        tls: ConfiguracaoTls {
+            ca: None,
+            starttls: true,
+        },
+        ..servidor(porta)
+    };
+    assert!(!servidor.em_texto_claro());
+    assert!(servidor.abrir().await.is_err());
+
+    let pedido = tokio::time::timeout(Duration::from_secs(5), rx)
+        .await
+        .unwrap()
+        .unwrap();
+    let contem = |s: &[u8]| pedido.windows(s.len()).any(|w| w == s);
+    assert!(contem(b"1.3.6.1.4.1.1466.20037"), "{pedido:x?}");
+    assert!(!contem(b"cn=admin"), "{pedido:x?}");
+}
+
+#[tokio::test]
+async fn ca_propria_do_servidor() {
+    let pasta =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/certificados");
+    let com_ca = |ca: PathBuf| ServidorLdap {
+        tls: ConfiguracaoTls {
+            ca: Some(ca),
+            starttls: false,
+        },
+        ..servidor(389)
+    };
+
+    assert!(servidor(389).conector().unwrap().is_none());
+    assert!(
+        com_ca(pasta.join("cliente.pem"))
+            .conector()
+            .unwrap()
+            .is_some()
+    );
+
+    let sem_ca = com_ca(pasta.join("inexistente.pem"));
+    assert!(matches!(sem_ca.conector(), Err(ErroLdap::Certificado(..))));
+    let r = sem_ca.abrir().await;
+    assert!(matches!(r, Err(ErroLdap::Certificado(..))), "{:?}", r.err());
+}
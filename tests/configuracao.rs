@@ -0,0 +1,90 @@
+//! Testes da leitura da configuração com os caminhos dados pelas flags.
+
+use alumnic::configuracao::{Caminhos, Configuracao};
+use std::path::{Path, PathBuf};
+
+const CONFIG: &str = r#"
+ldap_url: "ldap://ldap.invalido"
+logs: "/srv/alumnic/logs"
+auditoria:
+  arquivo: "auditoria.jsonl"
+hooks:
+  registro: "/var/log/hooks.jsonl"
+backup:
+  chave_privada: "backup.pk8"
+  chave_publica: "..."
+usuario_novo:
+  gid_number: "1000"
+  samba_sid_prefix: "S-1-5-21-1-2-3-"
+  samba_acct_flags: "[UX]"
+  samba_lm_password: "XXX"
+  samba_password_history: "000"
+  samba_primary_group_sid: "S-1-5-21-1-2-3-513"
+  cota: "1000"
+  contingencia:
+    diretorio: "contingencia"
+"#;
+
+/// Um diretório temporário com o `config.yaml`.
+fn diretorio_com_config() -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("alumnic-configuracao-{}", rand::random::<u32>()));
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("config.yaml"), CONFIG).unwrap();
+    dir
+}
+
+#[test]
+fn caminhos_relativos_vao_para_os_diretorios() {
+    let dir = diretorio_com_config();
+
+    let cfg = Configuracao::importar_de(&Caminhos {
+        config: Some(dir.join("config.yaml")),
+        dados: Some(PathBuf::from("/srv/alumnic/dados")),
+        logs: None,
+    })
+    .unwrap();
+
+    assert_eq!(cfg.dados, Path::new("/srv/alumnic/dados"));
+    assert_eq!(
+        cfg.usuario_novo.contingencia.unwrap().diretorio,
+        Path::new("/srv/alumnic/dados/contingencia"),
+    );
+    assert_eq!(
+        cfg.auditoria.arquivo.unwrap(),
+        Path::new("/srv/alumnic/logs/auditoria.jsonl"),
+    );
+    // Os absolutos continuam como estão
+    assert_eq!(
+        cfg.hooks.registro.unwrap(),
+        Path::new("/var/log/hooks.jsonl"),
+    );
+    assert_eq!(
+        cfg.backup.unwrap().chave_privada.unwrap(),
+        dir.join("backup.pk8"),
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sem_flags_os_padroes_sao_os_do_sistema() {
+    let dir = diretorio_com_config();
+    std::fs::write(
+        dir.join("config.yaml"),
+        CONFIG.replace("logs: \"/srv/alumnic/logs\"\n", ""),
+    )
+    .unwrap();
+
+    let cfg = Configuracao::importar_de(&Caminhos {
+        config: Some(dir.join("config.yaml")),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert_eq!(cfg.dados, Path::new("/var/lib/alumnic"));
+    assert_eq!(
+        cfg.auditoria.arquivo.unwrap(),
+        Path::new("/var/log/alumnic/auditoria.jsonl"),
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
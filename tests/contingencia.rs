@@ -0,0 +1,384 @@
+//! Testes da fila de contingência dos cadastros com o LDAP fora do ar.
+
+mod comum;
+
+use alumnic::cadastro_aluno::{DadosParaCadastro, ErroDeCadastro};
+use alumnic::configuracao::Configuracao;
+use alumnic::contingencia::{
+    CadastroPendente, ConfiguracaoContingencia, processar_fila,
+};
+use alumnic::ldap::ErroLdap;
+use alumnic::ldap::conexao::FonteLdap;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use chrono::{TimeDelta, Utc};
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+use ldap3::{Mod, Scope, SearchEntry};
+use secrecy::SecretString;
+use serde_json::json;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio_util::sync::CancellationToken;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// O LDAP em memória da API de teste, que pode ser tirado do ar.
+struct LdapInstavel {
+    ldap: Arc<Mutex<DiretorioMemoria>>,
+    fora: AtomicBool,
+}
+
+impl FonteLdap for LdapInstavel {
+    type Conexao = OwnedMutexGuard<DiretorioMemoria>;
+
+    async fn abrir(&self) -> Result<Self::Conexao, ErroLdap> {
+        if self.fora.load(Ordering::SeqCst) {
+            return Err(ErroLdap::Timeout);
+        }
+        self.ldap.abrir().await
+    }
+
+    async fn fechar(&self, conexao: Self::Conexao) -> Result<(), ErroLdap> {
+        self.ldap.fechar(conexao).await
+    }
+
+    async fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> Result<bool, ErroLdap> {
+        self.ldap.verificar_senha(dn, senha).await
+    }
+}
+
+/// O LDAP em memória da API de teste, cuja conexão cai logo depois de o
+/// servidor gravar a entrada do aluno, antes de a resposta chegar.
+struct CaiNaCriacao(Arc<Mutex<DiretorioMemoria>>);
+
+struct ConexaoQueCai(OwnedMutexGuard<DiretorioMemoria>);
+
+impl FonteLdap for CaiNaCriacao {
+    type Conexao = ConexaoQueCai;
+
+    async fn abrir(&self) -> Result<Self::Conexao, ErroLdap> {
+        Ok(ConexaoQueCai(self.0.abrir().await?))
+    }
+
+    async fn fechar(&self, conexao: Self::Conexao) -> Result<(), ErroLdap> {
+        self.0.fechar(conexao.0).await
+    }
+
+    async fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> Result<bool, ErroLdap> {
+        self.0.verificar_senha(dn, senha).await
+    }
+}
+
+impl DiretorioLdap for ConexaoQueCai {
+    async fn buscar(
+        &mut self,
+        base: &str,
+        escopo: Scope,
+        filtro: &str,
+        atributos: Vec<&str>,
+    ) -> Result<Vec<SearchEntry>, ErroLdap> {
+        self.0.buscar(base, escopo, filtro, atributos).await
+    }
+
+    async fn adicionar(
+        &mut self,
+        dn: &str,
+        atributos: Vec<(&str, HashSet<&str>)>,
+    ) -> Result<(), ErroLdap> {
+        self.0.adicionar(dn, atributos).await?;
+        if dn == DN_ALUNO {
+            return Err(ErroLdap::Timeout);
+        }
+        Ok(())
+    }
+
+    async fn modificar(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<&str>>,
+    ) -> Result<(), ErroLdap> {
+        self.0.modificar(dn, mods).await
+    }
+
+    async fn gravar_binario(
+        &mut self,
+        dn: &str,
+        atributo: &str,
+        valor: &[u8],
+    ) -> Result<(), ErroLdap> {
+        self.0.gravar_binario(dn, atributo, valor).await
+    }
+
+    async fn mover(
+        &mut self,
+        dn: &str,
+        nova_base: &str,
+    ) -> Result<(), ErroLdap> {
+        self.0.mover(dn, nova_base).await
+    }
+
+    async fn remover(&mut self, dn: &str) -> Result<(), ErroLdap> {
+        self.0.remover(dn).await
+    }
+
+    async fn quem_sou(&mut self) -> Result<String, ErroLdap> {
+        self.0.quem_sou().await
+    }
+}
+
+/// O LDAP em memória da API de teste, aberto enquanto o aluno envia o
+/// formulário de novo, com a `fila` ainda com o cadastro anterior.
+struct ReenviaNaTentativa {
+    ldap: Arc<Mutex<DiretorioMemoria>>,
+    fila: ConfiguracaoContingencia,
+    reenviado: AtomicBool,
+}
+
+impl FonteLdap for ReenviaNaTentativa {
+    type Conexao = OwnedMutexGuard<DiretorioMemoria>;
+
+    async fn abrir(&self) -> Result<Self::Conexao, ErroLdap> {
+        if !self.reenviado.swap(true, Ordering::SeqCst) {
+            let pendente = CadastroPendente {
+                dados: dados(),
+                ou: "alunos".to_string(),
+                recebido: Utc::now(),
+            };
+            self.fila.enfileirar(&pendente).unwrap();
+        }
+        self.ldap.abrir().await
+    }
+
+    async fn fechar(&self, conexao: Self::Conexao) -> Result<(), ErroLdap> {
+        self.ldap.fechar(conexao).await
+    }
+
+    async fn verificar_senha(
+        &self,
+        dn: &str,
+        senha: &SecretString,
+    ) -> Result<bool, ErroLdap> {
+        self.ldap.verificar_senha(dn, senha).await
+    }
+}
+
+/// Um diretório temporário só para o `teste`, ainda não criado.
+fn diretorio(teste: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "alumnic-contingencia-{teste}-{}",
+        std::process::id()
+    ))
+}
+
+/// A configuração com a fila em `dir` e os avisos gravados em `avisos`.
+fn com_fila(gnosys_url: &str, dir: &Path, avisos: &Path) -> Configuracao {
+    let mut cfg = configuracao(gnosys_url);
+    cfg.usuario_novo.contingencia = Some(ConfiguracaoContingencia {
+        diretorio: dir.join("fila"),
+        intervalo_segundos: 60,
+    });
+    cfg.notificacao.comando = vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        format!("cat >> {}", avisos.display()),
+    ];
+    cfg
+}
+
+fn dados() -> DadosParaCadastro {
+    serde_json::from_value(json!({
+        "dre": "123456789",
+        "data": "01/03/2025",
+        "hora": "10:00",
+        "codigo": "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF",
+        "nome": "Cláudio de Lima Cavalcante",
+        "email": "claudio@exemplo.com",
+        "telefone": "(21) 98765-4321",
+        "senha": "Senha1234",
+    }))
+    .unwrap()
+}
+
+async fn cadastrar(
+    dados: DadosParaCadastro,
+    cfg: &Configuracao,
+    ldap: &LdapInstavel,
+) -> Result<String, ErroDeCadastro> {
+    dados
+        .cadastrar(
+            &cfg.usuario_novo,
+            &cfg.renovacao,
+            &cfg.gnosys_url,
+            ldap,
+            None,
+            &CancellationToken::new(),
+        )
+        .await
+        .map(|c| c.username)
+}
+
+#[tokio::test]
+async fn cadastro_espera_o_ldap_voltar_na_fila() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ));
+    let dir = diretorio("espera");
+    let avisos = dir.join("avisos");
+    let cfg = com_fila(&api.gnosys.url, &dir, &avisos);
+    let fila = cfg.usuario_novo.contingencia.clone().unwrap();
+    let ldap = LdapInstavel {
+        ldap: api.ldap.clone(),
+        fora: AtomicBool::new(true),
+    };
+
+    let erro = cadastrar(dados(), &cfg, &ldap).await.unwrap_err();
+    assert!(matches!(erro, ErroDeCadastro::Enfileirado), "{erro}");
+    assert_eq!(erro.status(), 202);
+    // Enviado de novo durante a queda, o cadastro só é trocado na fila
+    cadastrar(dados(), &cfg, &ldap).await.unwrap_err();
+    let pendentes = fila.pendentes().unwrap();
+    assert_eq!(pendentes.len(), 1);
+    assert_eq!(pendentes[0].dados.dre, "123456789");
+    assert_eq!(pendentes[0].ou, "alunos");
+
+    // Com o LDAP ainda fora do ar, o cadastro continua na fila
+    let cancelamento = CancellationToken::new();
+    let p = processar_fila(&cfg, &ldap, &cancelamento).await.unwrap();
+    assert!(p.realizados.is_empty());
+    assert_eq!(p.pendentes, 1);
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_none());
+
+    ldap.fora.store(false, Ordering::SeqCst);
+    let p = processar_fila(&cfg, &ldap, &cancelamento).await.unwrap();
+    assert_eq!(p.realizados.len(), 1);
+    assert_eq!(p.realizados[0].username, "claudiolc");
+    assert_eq!(p.pendentes, 0);
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_some());
+    assert!(fila.pendentes().unwrap().is_empty());
+
+    let aviso = std::fs::read_to_string(&avisos).unwrap();
+    assert!(aviso.contains("To: claudio@exemplo.com\n"), "{aviso}");
+    assert!(aviso.contains("\"claudiolc\""), "{aviso}");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn so_o_documento_autenticado_vai_para_a_fila() {
+    let api = ApiDeTeste::iniciar().await;
+    let dir = diretorio("documento");
+    let cfg = com_fila(&api.gnosys.url, &dir, &dir.join("avisos"));
+    let ldap = LdapInstavel {
+        ldap: api.ldap.clone(),
+        fora: AtomicBool::new(true),
+    };
+
+    // O documento não está no Gnosys
+    let erro = cadastrar(dados(), &cfg, &ldap).await.unwrap_err();
+    assert!(matches!(erro, ErroDeCadastro::DocumentoInvalido), "{erro}");
+    let fila = cfg.usuario_novo.contingencia.as_ref().unwrap();
+    assert!(fila.pendentes().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn sem_a_fila_o_cadastro_falha_com_o_ldap_fora_do_ar() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ));
+    let cfg = configuracao(&api.gnosys.url);
+    let ldap = LdapInstavel {
+        ldap: api.ldap.clone(),
+        fora: AtomicBool::new(true),
+    };
+
+    let erro = cadastrar(dados(), &cfg, &ldap).await.unwrap_err();
+    assert!(erro.ldap_indisponivel(), "{erro}");
+    assert_eq!(erro.status(), 504);
+}
+
+#[tokio::test]
+async fn queda_durante_a_criacao_nao_vai_para_a_fila() {
+    let api = ApiDeTeste::iniciar().await;
+    api.gnosys.registrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ));
+    let dir = diretorio("criacao");
+    let cfg = com_fila(&api.gnosys.url, &dir, &dir.join("avisos"));
+    let ldap = CaiNaCriacao(api.ldap.clone());
+
+    let erro = dados()
+        .cadastrar(
+            &cfg.usuario_novo,
+            &cfg.renovacao,
+            &cfg.gnosys_url,
+            &ldap,
+            None,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap_err();
+
+    // A conta pode ter sido criada, então o cadastro não é refeito pela fila
+    assert!(
+        matches!(erro, ErroDeCadastro::ErroNoCadastro(_, ErroLdap::Timeout)),
+        "{erro}",
+    );
+    assert_eq!(erro.status(), 503);
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_some());
+    let fila = cfg.usuario_novo.contingencia.as_ref().unwrap();
+    assert!(fila.pendentes().unwrap().is_empty());
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn reenvio_durante_a_tentativa_fica_na_fila() {
+    let api = ApiDeTeste::iniciar().await;
+    let dir = diretorio("reenvio");
+    let cfg = com_fila(&api.gnosys.url, &dir, &dir.join("avisos"));
+    let fila = cfg.usuario_novo.contingencia.clone().unwrap();
+    let anterior = Utc::now() - TimeDelta::hours(1);
+    fila.enfileirar(&CadastroPendente {
+        dados: dados(),
+        ou: "alunos".to_string(),
+        recebido: anterior,
+    })
+    .unwrap();
+    let ldap = ReenviaNaTentativa {
+        ldap: api.ldap.clone(),
+        fila: fila.clone(),
+        reenviado: AtomicBool::new(false),
+    };
+
+    let cancelamento = CancellationToken::new();
+    let p = processar_fila(&cfg, &ldap, &cancelamento).await.unwrap();
+    assert_eq!(p.realizados.len(), 1);
+
+    // Só o cadastro tentado sai da fila, não o que chegou durante a tentativa
+    let pendentes = fila.pendentes().unwrap();
+    assert_eq!(pendentes.len(), 1);
+    assert!(pendentes[0].recebido > anterior);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,151 @@
+//! Testes do relatório de uso das cotas e do ajuste das cotas em lote.
+
+use alumnic::auditoria::ConfiguracaoAuditoria;
+use alumnic::cotas::{ConfiguracaoCotas, Criterio, ajustar_em_lote, relatorio};
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// O DN da conta `uid` na OU `ou`.
+fn dn(uid: &str, ou: &str) -> String {
+    format!("uid={uid},ou={ou},ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br")
+}
+
+/// Um diretório com três alunos, com cotas de 1000, e um egresso, além do
+/// grupo dos monitores.
+async fn diretorio() -> Arc<Mutex<DiretorioMemoria>> {
+    let mut d = DiretorioMemoria::default();
+    for (uid, ou, estado) in [
+        ("joaops", "alunos", "ativa"),
+        ("mariaas", "alunos", "ativa"),
+        ("pedrocc", "profcomp", "ativa"),
+        ("antigo", "egressos", "egresso"),
+    ] {
+        d.adicionar(
+            &dn(uid, ou),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("estadoConta", [estado].into()),
+                ("cota", ["1000"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    d.adicionar(
+        "cn=monitores,ou=grupos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+        vec![
+            ("objectClass", ["posixGroup"].into()),
+            ("cn", ["monitores"].into()),
+            ("memberUid", ["mariaas", "pedrocc"].into()),
+        ],
+    )
+    .await
+    .unwrap();
+    Arc::new(Mutex::new(d))
+}
+
+#[tokio::test]
+async fn lista_as_contas_acima_do_limiar() {
+    let ldap = diretorio().await;
+    let cfg = ConfiguracaoCotas::Comando {
+        comando: [
+            "printf",
+            "# uid uso\\njoaops 1200\\nmariaas 950\\npedrocc 300\\nantigo 5000\\n",
+        ]
+        .map(String::from)
+        .into(),
+    };
+
+    let r = relatorio(&cfg, 90, &ldap).await.unwrap();
+    let r: Vec<_> =
+        r.iter().map(|u| (u.uid.as_str(), u.percentual())).collect();
+    assert_eq!(r, [("joaops", 120), ("mariaas", 95)]);
+
+    let falha = ConfiguracaoCotas::Comando {
+        comando: vec!["false".to_string()],
+    };
+    assert!(relatorio(&falha, 90, &ldap).await.is_err());
+}
+
+#[tokio::test]
+async fn ajusta_as_cotas_dos_membros_do_grupo() {
+    let ldap = diretorio().await;
+    let auditoria = ConfiguracaoAuditoria::default();
+    let monitores = Criterio::Grupo("monitores".to_string());
+
+    let simulado = ajustar_em_lote(
+        &monitores,
+        5000,
+        "Monitoria",
+        true,
+        &auditoria,
+        &ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(simulado.len(), 2);
+    assert_eq!(
+        ldap.lock()
+            .await
+            .entrada(&dn("mariaas", "alunos"))
+            .unwrap()
+            .attrs["cota"],
+        vec!["1000"],
+    );
+
+    let ajustados = ajustar_em_lote(
+        &monitores,
+        5000,
+        "Monitoria",
+        false,
+        &auditoria,
+        &ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert!(ajustados.iter().all(|(_, r)| r.is_ok()));
+    let d = ldap.lock().await;
+    for (uid, ou, cota) in [
+        ("joaops", "alunos", "1000"),
+        ("mariaas", "alunos", "5000"),
+        ("pedrocc", "profcomp", "5000"),
+    ] {
+        assert_eq!(d.entrada(&dn(uid, ou)).unwrap().attrs["cota"], vec![cota]);
+    }
+    drop(d);
+
+    // As contas que já têm a cota ficam de fora
+    let de_novo = ajustar_em_lote(
+        &monitores,
+        5000,
+        "Monitoria",
+        false,
+        &auditoria,
+        &ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert!(de_novo.is_empty());
+
+    let inexistente = Criterio::Grupo("ninguem".to_string());
+    assert!(
+        ajustar_em_lote(
+            &inexistente,
+            1,
+            "Nada",
+            true,
+            &auditoria,
+            &ldap,
+            Utc::now()
+        )
+        .await
+        .is_err()
+    );
+}
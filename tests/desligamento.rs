@@ -0,0 +1,425 @@
+//! Testes do desligamento em massa a partir de uma lista de DREs.
+
+mod comum;
+
+use alumnic::desligamento::{
+    ErroDeDesligamento, ResultadoDesligamento, desligar_em_lote,
+};
+use alumnic::hooks::Hook;
+use alumnic::plano::{
+    Acao, ErroDePlano, Plano, aplicar_desligamento, aplicar_remocao,
+    planejar_desligamento, planejar_remocao,
+};
+use alumnic::prazos::aplicar_prazos_em;
+use chrono::{Duration, Utc};
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+fn sh(script: String) -> Vec<String> {
+    vec!["sh".to_string(), "-c".to_string(), script]
+}
+
+/// Um arquivo temporário com o `nome`, único para o processo de teste.
+fn temporario(nome: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("alumnic-{nome}-{}", std::process::id()))
+}
+
+fn ler_e_apagar(caminho: &Path) -> String {
+    let conteudo = std::fs::read_to_string(caminho).unwrap();
+    std::fs::remove_file(caminho).unwrap();
+    conteudo
+}
+
+async fn api_com_aluno() -> ApiDeTeste {
+    let api = ApiDeTeste::iniciar().await;
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+    api
+}
+
+#[tokio::test]
+async fn desliga_os_dres_da_lista() {
+    let api = api_com_aluno().await;
+
+    // O "sendmail" e o hook do teste só guardam o que receberam
+    let caixa = temporario("desligamento.eml");
+    let suspensoes = temporario("suspensoes");
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.notificacao.comando = sh(format!("cat > {}", caixa.display()));
+    cfg.notificacao.remetente = "supervisao@ic.ufrj.br".to_string();
+    cfg.hooks.ao_suspender = vec![Hook::Comando {
+        comando: sh(format!("echo $ALUMNIC_HOME >> {}", suspensoes.display())),
+    }];
+
+    let lista = "dre,nome\n123456789,Claudio\n111111111,Outro\nabc\n";
+    let resultados = desligar_em_lote(
+        lista,
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(resultados.len(), 3);
+    match &resultados[0] {
+        (
+            dre,
+            Ok(ResultadoDesligamento::Desligado {
+                uid, aviso, hooks, ..
+            }),
+        ) => {
+            assert_eq!(dre, "123456789");
+            assert_eq!(uid, "claudiolc");
+            assert!(aviso.is_ok(), "{aviso:?}");
+            assert!(hooks.is_ok(), "{hooks:?}");
+        },
+        r => panic!("resultado inesperado: {r:?}"),
+    }
+    assert!(matches!(
+        resultados[1].1,
+        Ok(ResultadoDesligamento::NaoEncontrado),
+    ));
+    assert!(matches!(
+        resultados[2].1,
+        Err(ErroDeDesligamento::DREInvalido(..)),
+    ));
+
+    let mensagem = ler_e_apagar(&caixa);
+    assert!(mensagem.contains("To: claudio@exemplo.com\n"));
+    assert!(mensagem.contains("claudiolc"));
+    assert_eq!(ler_e_apagar(&suspensoes), "/usuarios/alunos/claudiolc\n");
+
+    {
+        let ldap = api.ldap.lock().await;
+        let e = ldap.entrada(DN_ALUNO).unwrap();
+        assert_eq!(e.attrs["estadoConta"], vec!["suspensa"]);
+        assert!(e.attrs["userPassword"][0].starts_with("!{SSHA}"));
+        assert_eq!(e.attrs["sambaAcctFlags"], vec!["[DUX]"]);
+    }
+
+    // Rodar de novo não bloqueia nem avisa de novo
+    let resultados = desligar_em_lote(
+        "123456789",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        resultados[0].1,
+        Ok(ResultadoDesligamento::JaSuspensa { .. }),
+    ));
+    assert!(!caixa.exists());
+    assert!(!suspensoes.exists());
+}
+
+#[tokio::test]
+async fn remocao_espera_o_arquivamento() {
+    let api = api_com_aluno().await;
+    let mut cfg = configuracao(&api.gnosys.url);
+    desligar_em_lote(
+        "123456789",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    let data_remocao =
+        Utc::now() + Duration::days(cfg.desligamento.dias_ate_remocao);
+
+    // Se o arquivamento falha, a conta continua suspensa
+    cfg.hooks.ao_remover = vec![Hook::Comando {
+        comando: sh("exit 1".to_string()),
+    }];
+    let t = aplicar_prazos_em(&api.ldap, &cfg, data_remocao)
+        .await
+        .unwrap();
+    assert!(t.removidas.is_empty());
+    assert_eq!(t.falhas.len(), 1);
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["suspensa"],
+    );
+
+    let arquivadas = temporario("arquivadas");
+    cfg.hooks.ao_remover = vec![Hook::Comando {
+        comando: sh(format!("echo $ALUMNIC_UID >> {}", arquivadas.display())),
+    }];
+    let t = aplicar_prazos_em(&api.ldap, &cfg, data_remocao)
+        .await
+        .unwrap();
+    assert_eq!(t.removidas.len(), 1);
+    assert_eq!(ler_e_apagar(&arquivadas), "claudiolc\n");
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["removida"],
+    );
+}
+
+#[tokio::test]
+async fn lote_cancelado_nao_bloqueia_mais_ninguem() {
+    let api = api_com_aluno().await;
+    let cancelamento = CancellationToken::new();
+    cancelamento.cancel();
+
+    let resultados = desligar_em_lote(
+        "123456789",
+        &configuracao(&api.gnosys.url),
+        &api.ldap,
+        Utc::now(),
+        &cancelamento,
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        resultados[0].1,
+        Err(ErroDeDesligamento::Cancelado)
+    ));
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["ativa"],
+    );
+}
+
+#[tokio::test]
+async fn plano_so_desliga_depois_de_aplicado() {
+    let api = api_com_aluno().await;
+    let auditoria = temporario("auditoria-plano-desligamento.jsonl");
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.auditoria.arquivo = Some(auditoria.clone());
+
+    let plano = planejar_desligamento(
+        "dre\n123456789\n111111111\n",
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    let acoes: Vec<_> = plano.itens.iter().map(|i| i.acao).collect();
+    assert_eq!(acoes, [Acao::Bloquear, Acao::NaoEncontrado]);
+    assert_eq!(plano.itens[0].uid.as_deref(), Some("claudiolc"));
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["ativa"],
+    );
+
+    let caminho = temporario("desligamento.plano.json");
+    let hash = plano.gravar(&caminho).unwrap();
+    let modo = std::fs::metadata(&caminho).unwrap().permissions().mode();
+    assert_eq!(modo & 0o777, 0o600);
+    // O plano gravado não é trocado por outro
+    assert!(matches!(
+        plano.gravar(&caminho),
+        Err(ErroDePlano::PlanoExistente(c)) if c == caminho
+    ));
+    let (lido, hash_lido) = Plano::ler(&caminho).unwrap();
+    std::fs::remove_file(&caminho).unwrap();
+    assert_eq!(lido, plano);
+    assert_eq!(hash_lido, hash);
+
+    let aplicado = aplicar_desligamento(
+        &lido,
+        &hash,
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+    assert!(aplicado.sem_auditoria.is_empty());
+    assert_eq!(aplicado.resultados.len(), 1);
+    assert!(matches!(
+        aplicado.resultados[0].1,
+        Ok(ResultadoDesligamento::Desligado { .. }),
+    ));
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["suspensa"],
+    );
+
+    let registro = ler_e_apagar(&auditoria);
+    assert!(registro.contains("\"operacao\":\"desligar\""));
+    assert!(registro.contains(&format!("plano {hash}")));
+}
+
+#[tokio::test]
+async fn plano_desatualizado_nao_altera_nada() {
+    let api = api_com_aluno().await;
+    let cfg = configuracao(&api.gnosys.url);
+
+    let plano = planejar_remocao(
+        "uid\nclaudiolc\n",
+        "conta comprometida",
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(plano.itens[0].acao, Acao::Remover);
+
+    // O plano de uma operação não serve para outra
+    let r = aplicar_desligamento(
+        &plano,
+        "",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await;
+    assert!(matches!(r, Err(ErroDePlano::OutraOperacao(..))));
+
+    // A conta é bloqueada depois de o plano ser gerado
+    desligar_em_lote(
+        "123456789",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    let r = aplicar_remocao(
+        &plano,
+        "",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await;
+    match r {
+        Err(ErroDePlano::Desatualizado(uid)) => assert_eq!(uid, "claudiolc"),
+        r => panic!("resultado inesperado: {r:?}"),
+    }
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["suspensa"],
+    );
+}
+
+#[tokio::test]
+async fn remocao_em_lote_registra_o_hash_do_plano() {
+    let api = api_com_aluno().await;
+    let auditoria = temporario("auditoria-plano-remocao.jsonl");
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.auditoria.arquivo = Some(auditoria.clone());
+
+    let r = planejar_remocao("claudiolc", " ", &api.ldap, Utc::now()).await;
+    assert!(matches!(r, Err(ErroDePlano::SemMotivo)));
+
+    let plano = planejar_remocao(
+        "claudiolc\nninguem\n",
+        "conta comprometida",
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(plano.alteracoes(), 1);
+
+    let resultados = aplicar_remocao(
+        &plano,
+        "abc123",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resultados.len(), 1);
+    assert_eq!(resultados[0].0, "claudiolc");
+    assert!(resultados[0].1.is_ok(), "{:?}", resultados[0].1);
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["removida"],
+    );
+
+    let registro = ler_e_apagar(&auditoria);
+    assert!(registro.contains("conta comprometida (plano abc123)"));
+}
+
+#[tokio::test]
+async fn falha_na_auditoria_nao_esconde_os_bloqueios() {
+    let api = api_com_aluno().await;
+    let mut cfg = configuracao(&api.gnosys.url);
+    // Um diretório no lugar do arquivo faz a escrita da auditoria falhar
+    cfg.auditoria.arquivo = Some(std::env::temp_dir());
+
+    let plano = planejar_desligamento("123456789", &api.ldap, Utc::now())
+        .await
+        .unwrap();
+    let aplicado = aplicar_desligamento(
+        &plano,
+        "abc123",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    assert!(matches!(
+        aplicado.resultados[0].1,
+        Ok(ResultadoDesligamento::Desligado { .. }),
+    ));
+    assert_eq!(aplicado.sem_auditoria.len(), 1);
+    assert_eq!(aplicado.sem_auditoria[0].0, "claudiolc");
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["suspensa"],
+    );
+}
+
+#[tokio::test]
+async fn plano_de_remocao_sem_motivo_nao_remove_nada() {
+    let api = api_com_aluno().await;
+    let cfg = configuracao(&api.gnosys.url);
+
+    let mut plano = planejar_remocao(
+        "claudiolc",
+        "conta comprometida",
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    plano.motivo = None;
+
+    let r = aplicar_remocao(
+        &plano,
+        "abc123",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await;
+    assert!(matches!(r, Err(ErroDePlano::SemMotivo)));
+    assert_eq!(
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["ativa"],
+    );
+}
@@ -0,0 +1,246 @@
+//! Testes das contas de docentes e técnicos pedidas e aprovadas pela
+//! supervisão.
+
+mod comum;
+
+use alumnic::configuracao::Configuracao;
+use alumnic::docente::{
+    self, ConfiguracaoDocentes, DadosDoDocente, ErroDeDocente, PerfilDocente,
+};
+use alumnic::ldap::conexao::FonteLdap;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::utils::hashes::hash_ssha;
+use comum::api::{
+    ApiDeTeste, GRUPO_PAINEL, TOKEN, configuracao, diretorio_com_samba,
+};
+use secrecy::ExposeSecret;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DN_DOCENTE: &str =
+    "uid=marias,ou=tecnicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+const SENHA: &str = "SenhaDoSupervisor";
+
+/// Um diretório de dados temporário com o `nome`, único para o processo de
+/// teste.
+fn dados(nome: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("alumnic-{nome}-{}", std::process::id()))
+}
+
+/// Configura os perfis `professores`, sem validade, e `tecnicos`, que vence
+/// em 730 dias, com os pedidos no diretório `dir`.
+fn com_docentes(cfg: &mut Configuracao, dir: PathBuf) {
+    cfg.dados = dir;
+    cfg.docentes = Some(ConfiguracaoDocentes {
+        classes: ["posixAccount", "inetOrgPerson"].map(String::from).into(),
+        perfis: [
+            (
+                "professores".to_string(),
+                PerfilDocente {
+                    gid_number: "4000".to_string(),
+                    validade_dias: None,
+                    cota: Some("10000".to_string()),
+                },
+            ),
+            (
+                "tecnicos".to_string(),
+                PerfilDocente {
+                    gid_number: "4100".to_string(),
+                    validade_dias: Some(730),
+                    cota: None,
+                },
+            ),
+        ]
+        .into(),
+    });
+}
+
+fn maria() -> DadosDoDocente {
+    DadosDoDocente {
+        nome: "Maria Silva".to_string(),
+        email: "maria@exemplo.com".to_string(),
+        ou: "tecnicos".to_string(),
+        senha: "Senha1234".to_string().into(),
+    }
+}
+
+#[tokio::test]
+async fn conta_so_e_criada_com_a_aprovacao_de_outro_operador() {
+    let dir = dados("docentes-aprovacao");
+    let mut cfg = configuracao("http://gnosys.invalido");
+    com_docentes(&mut cfg, dir.clone());
+    let ldap = Arc::new(Mutex::new(diretorio_com_samba().await));
+    let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+
+    let pedido = maria().pedir("ana", &cfg, agora).await.unwrap();
+    assert_eq!(docente::pedidos(&dir).unwrap(), vec![pedido.clone()]);
+    assert!(ldap.lock().await.entrada(DN_DOCENTE).is_none());
+
+    let r = docente::aprovar(&pedido.id, "ana", &cfg, &ldap, agora).await;
+    assert!(matches!(r, Err(ErroDeDocente::MesmoOperador(_))), "{r:?}");
+
+    let (username, dn) =
+        docente::aprovar(&pedido.id, "bruno", &cfg, &ldap, agora)
+            .await
+            .unwrap();
+    assert_eq!(username, "marias");
+    assert_eq!(dn, DN_DOCENTE);
+    assert!(docente::pedidos(&dir).unwrap().is_empty());
+
+    {
+        let d = ldap.lock().await;
+        let e = d.entrada(DN_DOCENTE).unwrap();
+        assert_eq!(e.attrs["gidNumber"], vec!["4100"]);
+        assert_eq!(e.attrs["cota"], vec!["1000"]);
+        assert_eq!(e.attrs["homeDirectory"], vec!["/usuarios/tecnicos/marias"]);
+        // 01/03/2025 é o dia 20148
+        assert_eq!(e.attrs["shadowExpire"], vec!["20878"]);
+        assert!(!e.attrs.contains_key("dccDRE"));
+        assert!(!e.attrs.contains_key("sambaSID"));
+    }
+    assert!(
+        ldap.verificar_senha(DN_DOCENTE, &"Senha1234".to_string().into())
+            .await
+            .unwrap()
+    );
+
+    let r = docente::aprovar(&pedido.id, "bruno", &cfg, &ldap, agora).await;
+    assert!(
+        matches!(r, Err(ErroDeDocente::PedidoInexistente(_))),
+        "{r:?}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn recusa_pedidos_invalidos_e_recusados() {
+    let dir = dados("docentes-recusa");
+    let mut cfg = configuracao("http://gnosys.invalido");
+    let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+
+    let r = maria().pedir("ana", &cfg, agora).await;
+    assert!(matches!(r, Err(ErroDeDocente::Desativada)), "{r:?}");
+
+    com_docentes(&mut cfg, dir.clone());
+    let mut alunos = maria();
+    alunos.ou = "alunos".to_string();
+    let r = alunos.pedir("ana", &cfg, agora).await;
+    assert!(
+        matches!(&r, Err(ErroDeDocente::OuDesconhecida(ou)) if ou == "alunos"),
+        "{r:?}"
+    );
+    let mut fraca = maria();
+    fraca.senha = "1234".to_string().into();
+    let r = fraca.pedir("ana", &cfg, agora).await;
+    assert!(matches!(r, Err(ErroDeDocente::SenhaInvalida)), "{r:?}");
+
+    let pedido = maria().pedir("ana", &cfg, agora).await.unwrap();
+    let recusado = docente::recusar(&pedido.id, "bruno", &cfg, agora)
+        .await
+        .unwrap();
+    assert_eq!(recusado, pedido);
+    assert!(docente::pedidos(&dir).unwrap().is_empty());
+
+    let r = docente::recusar("../../etc/passwd", "bruno", &cfg, agora).await;
+    assert!(
+        matches!(r, Err(ErroDeDocente::PedidoInexistente(_))),
+        "{r:?}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn aprovacao_pela_api_exige_outro_supervisor() {
+    let dir = dados("docentes-api");
+    let dados_api = dir.clone();
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            com_docentes(cfg, dados_api);
+        })
+        .await;
+    {
+        let mut ldap = api.ldap.lock().await;
+        let hash = hash_ssha(&SENHA.to_string().into());
+        for uid in ["ana", "bruno"] {
+            ldap.adicionar(
+                &format!(
+                    "uid={uid},ou=professores,ou=usuarios,dc=dcc,dc=ufrj,dc=br"
+                ),
+                vec![
+                    ("objectClass", ["posixAccount"].into()),
+                    ("uid", [uid].into()),
+                    ("userPassword", [hash.expose_secret()].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+        ldap.adicionar(
+            GRUPO_PAINEL,
+            vec![
+                ("objectClass", ["posixGroup"].into()),
+                ("cn", ["supervisores"].into()),
+                ("memberUid", ["ana", "bruno"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    let cliente = reqwest::Client::new();
+    let pedir = |caminho: &str, uid: &str, corpo: Option<Value>| {
+        let req = cliente.post(format!("{}{caminho}", api.url));
+        let req = match corpo {
+            Some(corpo) => req
+                .header("Content-Type", "application/json")
+                .body(corpo.to_string()),
+            None => req,
+        };
+        let req = match uid {
+            "" => req.bearer_auth(TOKEN),
+            uid => req.basic_auth(uid, Some(SENHA)),
+        };
+        async move {
+            let res = req.send().await.unwrap();
+            (res.status().as_u16(), res.text().await.unwrap())
+        }
+    };
+    let conta = json!({
+        "nome": "Maria Silva",
+        "email": "maria@exemplo.com",
+        "ou": "tecnicos",
+        "senha": "Senha1234",
+    });
+
+    // O token não diz quem pediu, então não vale
+    let (status, _) =
+        pedir("/api/admin/docentes", "", Some(conta.clone())).await;
+    assert_eq!(status, 403);
+
+    let (status, corpo) =
+        pedir("/api/admin/docentes", "ana", Some(conta)).await;
+    assert_eq!(status, 202, "{corpo}");
+    let pedido: Value = serde_json::from_str(&corpo).unwrap();
+    assert_eq!(pedido["solicitante"], "ana");
+    assert!(pedido.get("hash_senha").is_none());
+    let id = pedido["id"].as_str().unwrap();
+
+    let (status, corpo) = api.get_admin("/api/admin/docentes").await;
+    assert_eq!(status, 200, "{corpo}");
+    let pedidos: Value = serde_json::from_str(&corpo).unwrap();
+    assert_eq!(pedidos[0]["id"], id);
+
+    let aprovar = format!("/api/admin/docentes/{id}/aprovar");
+    let (status, _) = pedir(&aprovar, "ana", None).await;
+    assert_eq!(status, 403);
+    assert!(api.ldap.lock().await.entrada(DN_DOCENTE).is_none());
+
+    let (status, corpo) = pedir(&aprovar, "bruno", None).await;
+    assert_eq!(status, 201, "{corpo}");
+    assert!(api.ldap.lock().await.entrada(DN_DOCENTE).is_some());
+    let (status, _) = pedir(&aprovar, "bruno", None).await;
+    assert_eq!(status, 404);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,109 @@
+//! Testes da leitura dos dados do documento a partir do PDF. Só rodam com a
+//! feature `pdf`.
+#![cfg(feature = "pdf")]
+
+mod comum;
+
+use alumnic::documento_pdf::{DadosDoDocumento, ErroDoPdf};
+use comum::api::ApiDeTeste;
+use serde_json::Value;
+
+/// Um PDF de uma página com as `linhas` de texto, como o do SIGA.
+fn pdf(linhas: &[&str]) -> Vec<u8> {
+    let mut texto = "BT /F1 12 Tf 72 720 Td".to_string();
+    for linha in linhas {
+        texto.push_str(&format!(" ({linha}) Tj 0 -20 Td"));
+    }
+    texto.push_str(" ET");
+
+    let objetos = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+         /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica \
+         /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        format!("<< /Length {} >>\nstream\n{texto}\nendstream", texto.len()),
+    ];
+
+    let mut pdf = "%PDF-1.4\n".to_string();
+    let mut posicoes = vec![];
+    for (i, objeto) in objetos.iter().enumerate() {
+        posicoes.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{objeto}\nendobj\n", i + 1));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!(
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objetos.len() + 1
+    ));
+    for posicao in posicoes {
+        pdf.push_str(&format!("{posicao:010} 00000 n \n"));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objetos.len() + 1
+    ));
+    pdf.into_bytes()
+}
+
+fn documento() -> Vec<u8> {
+    pdf(&[
+        "Declaramos que CLAUDIO DE LIMA CAVALCANTE, DRE 123456789,",
+        "esta regularmente matriculado no curso de Ciencia da Computacao.",
+        "Documento emitido em 01/03/2025 as 10:00.",
+        "Codigo de autenticacao: A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF",
+    ])
+}
+
+#[test]
+fn le_os_dados_do_pdf() {
+    assert_eq!(
+        DadosDoDocumento::do_pdf(&documento()).unwrap(),
+        DadosDoDocumento {
+            dre: "123456789".to_string(),
+            data: "01/03/2025".to_string(),
+            hora: "10:00".to_string(),
+            codigo: "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF".to_string(),
+        },
+    );
+
+    let sem_codigo = pdf(&["DRE 123456789", "Emitido em 01/03/2025 as 10:00"]);
+    assert!(matches!(
+        DadosDoDocumento::do_pdf(&sem_codigo),
+        Err(ErroDoPdf::SemCampo(_)),
+    ));
+    assert!(matches!(
+        DadosDoDocumento::do_pdf(b"nada de PDF"),
+        Err(ErroDoPdf::Ilegivel(_)),
+    ));
+}
+
+#[tokio::test]
+async fn rota_responde_os_dados_do_pdf() {
+    let api = ApiDeTeste::iniciar().await;
+    let enviar = |corpo: Vec<u8>| {
+        let req = reqwest::Client::new()
+            .post(format!("{}/api/documento", api.url))
+            .header("Content-Type", "application/pdf")
+            .body(corpo);
+        async move {
+            let res = req.send().await.unwrap();
+            let status = res.status().as_u16();
+            let corpo: Value =
+                serde_json::from_str(&res.text().await.unwrap()).unwrap();
+            (status, corpo)
+        }
+    };
+
+    let (status, dados) = enviar(documento()).await;
+    assert_eq!(status, 200, "{dados}");
+    assert_eq!(dados["dre"], "123456789");
+    assert_eq!(dados["codigo"], "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF");
+
+    let (status, resposta) = enviar(b"%PDF-1.4 quebrado".to_vec()).await;
+    assert_eq!(status, 422, "{resposta}");
+    assert!(resposta["message"].as_str().unwrap().starts_with("Erro: "));
+}
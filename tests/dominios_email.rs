@@ -0,0 +1,164 @@
+//! Testes da migração do email institucional de um domínio para outro.
+
+use alumnic::ldap::ErroLdap;
+use alumnic::ldap::alias_email::ConfiguracaoAliasEmail;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::dominios_email::{
+    EtapaMigracao, MigracaoDominio, migrar_email, planejar_migracao,
+};
+use alumnic::ldap::memoria::DiretorioMemoria;
+
+/// O DN da conta `uid` na OU `ou`.
+fn dn(uid: &str, ou: &str) -> String {
+    format!("uid={uid},ou={ou},ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br")
+}
+
+/// Um diretório com três contas no `dcc.ufrj.br`, uma delas já com o
+/// `joaops@ic.ufrj.br` como alias.
+async fn diretorio() -> DiretorioMemoria {
+    let mut d = DiretorioMemoria::default();
+    for (uid, ou) in [("joaops", "alunos"), ("mariaas", "profcomp")] {
+        let mail = format!("{uid}@dcc.ufrj.br");
+        d.adicionar(
+            &dn(uid, ou),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("mail", [mail.as_str()].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    d.adicionar(
+        &dn("pedrocc", "alunos"),
+        vec![
+            ("objectClass", ["dccAluno", "inetLocalMailRecipient"].into()),
+            ("uid", ["pedrocc"].into()),
+            ("mail", ["pedro@dcc.ufrj.br"].into()),
+            ("mailAlternateAddress", ["joaops@ic.ufrj.br"].into()),
+        ],
+    )
+    .await
+    .unwrap();
+    d
+}
+
+fn migracao(etapa: EtapaMigracao, ou: Option<&str>) -> MigracaoDominio {
+    MigracaoDominio {
+        de: "dcc.ufrj.br".to_string(),
+        para: "ic.ufrj.br".to_string(),
+        ou: ou.map(str::to_string),
+        etapa,
+    }
+}
+
+/// Os valores do `atributo` da conta `uid` na OU `ou`.
+fn valores(
+    d: &DiretorioMemoria,
+    uid: &str,
+    ou: &str,
+    atributo: &str,
+) -> Vec<String> {
+    d.entrada(&dn(uid, ou))
+        .unwrap()
+        .attrs
+        .get(atributo)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn migra_em_duas_etapas() {
+    let mut d = diretorio().await;
+    let alias = ConfiguracaoAliasEmail::default();
+
+    let planos = planejar_migracao(
+        &migracao(EtapaMigracao::Adicionar, Some("profcomp")),
+        &alias,
+        &mut d,
+    )
+    .await
+    .unwrap();
+    assert_eq!(planos.len(), 1);
+    assert_eq!(planos[0].novo, "mariaas@ic.ufrj.br");
+    migrar_email(&planos[0], &mut d).await.unwrap();
+
+    assert_eq!(
+        valores(&d, "mariaas", "profcomp", "mail"),
+        ["mariaas@dcc.ufrj.br"]
+    );
+    assert_eq!(
+        valores(&d, "mariaas", "profcomp", "mailAlternateAddress"),
+        ["mariaas@ic.ufrj.br"]
+    );
+    assert!(
+        valores(&d, "mariaas", "profcomp", "objectClass")
+            .contains(&"inetLocalMailRecipient".to_string())
+    );
+
+    // A primeira etapa não repete a conta que já tem o alias
+    let planos = planejar_migracao(
+        &migracao(EtapaMigracao::Adicionar, Some("profcomp")),
+        &alias,
+        &mut d,
+    )
+    .await
+    .unwrap();
+    assert!(planos.is_empty());
+
+    let planos = planejar_migracao(
+        &migracao(EtapaMigracao::Trocar, Some("profcomp")),
+        &alias,
+        &mut d,
+    )
+    .await
+    .unwrap();
+    migrar_email(&planos[0], &mut d).await.unwrap();
+
+    assert_eq!(
+        valores(&d, "mariaas", "profcomp", "mail"),
+        ["mariaas@ic.ufrj.br"]
+    );
+    assert_eq!(
+        valores(&d, "mariaas", "profcomp", "mailAlternateAddress"),
+        ["mariaas@dcc.ufrj.br"]
+    );
+}
+
+#[tokio::test]
+async fn endereco_de_outra_conta_nao_e_migrado() {
+    let mut d = diretorio().await;
+    let alias = ConfiguracaoAliasEmail::default();
+
+    let planos = planejar_migracao(
+        &migracao(EtapaMigracao::Trocar, None),
+        &alias,
+        &mut d,
+    )
+    .await
+    .unwrap();
+    let uids: Vec<_> = planos.iter().map(|p| p.uid.as_str()).collect();
+    assert_eq!(uids, ["joaops", "mariaas", "pedrocc"]);
+    assert_eq!(planos[0].conflito.as_deref(), Some("pedrocc"));
+
+    let r = migrar_email(&planos[0], &mut d).await;
+    assert!(
+        matches!(r, Err(ErroLdap::EmailEmUso { dono, .. }) if dono == "pedrocc")
+    );
+    assert_eq!(
+        valores(&d, "joaops", "alunos", "mail"),
+        ["joaops@dcc.ufrj.br"]
+    );
+
+    // O endereço antigo da conta trocada continua chegando, como alias
+    migrar_email(&planos[2], &mut d).await.unwrap();
+    assert_eq!(
+        valores(&d, "pedrocc", "alunos", "mail"),
+        ["pedro@ic.ufrj.br"]
+    );
+    assert_eq!(
+        valores(&d, "pedrocc", "alunos", "mailAlternateAddress"),
+        ["joaops@ic.ufrj.br", "pedro@dcc.ufrj.br"]
+    );
+}
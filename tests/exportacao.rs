@@ -0,0 +1,54 @@
+//! Testes da exportação dos alunos novos para a secretaria.
+
+use alumnic::exportacao::{buscar_novos, csv};
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use chrono::{DateTime, NaiveDate};
+
+/// O dia de `data`, no formato guardado no LDAP.
+fn dia(data: &str) -> String {
+    let data: NaiveDate = data.parse().unwrap();
+    (data - DateTime::UNIX_EPOCH.date_naive())
+        .num_days()
+        .to_string()
+}
+
+#[tokio::test]
+async fn exporta_as_contas_criadas_desde_a_data() {
+    let mut d = DiretorioMemoria::default();
+    for (uid, dre, criacao) in [
+        ("mariasilva", "222222222", Some("2025-07-15")),
+        ("antigo", "111111111", Some("2025-06-30")),
+        ("semdata", "333333333", None),
+        ("claudiolc", "123456789", Some("2025-07-01")),
+    ] {
+        let criacao = criacao.map(dia);
+        let mut entrada = vec![
+            ("objectClass", ["dccAluno"].into()),
+            ("uid", [uid].into()),
+            ("dccDRE", [dre].into()),
+            ("gecos", [uid].into()),
+        ];
+        if let Some(criacao) = &criacao {
+            entrada.push(("dataCriacao", [criacao.as_str()].into()));
+        }
+        let mail = format!("{uid}@ic.ufrj.br");
+        entrada.push(("mail", [mail.as_str()].into()));
+        d.adicionar(
+            &format!("uid={uid},ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"),
+            entrada,
+        )
+        .await
+        .unwrap();
+    }
+
+    let novos = buscar_novos("2025-07-01".parse().unwrap(), &mut d)
+        .await
+        .unwrap();
+    assert_eq!(
+        csv(&novos),
+        "uid,nome,dre,email,data_criacao\n\
+         claudiolc,claudiolc,123456789,claudiolc@ic.ufrj.br,01/07/2025\n\
+         mariasilva,mariasilva,222222222,mariasilva@ic.ufrj.br,15/07/2025\n",
+    );
+}
@@ -0,0 +1,68 @@
+//! Testes do limite das consultas simultâneas ao Gnosys. O limite vale para o
+//! processo inteiro, então esses testes ficam num binário só deles.
+
+mod comum;
+
+use alumnic::metricas::metricas;
+use alumnic::portal_ufrj::{
+    Consulta, ConsultaErro, consulta_em, limitar_consultas,
+};
+use comum::gnosys::{Documento, GnosysFalso, Modo};
+use std::time::{Duration, Instant};
+
+async fn consultar(
+    gnosys: &GnosysFalso,
+    d: &Documento,
+) -> Result<Consulta, ConsultaErro> {
+    consulta_em(&gnosys.url, &d.dre, &d.data, &d.hora, &d.codigo).await
+}
+
+#[tokio::test]
+async fn consultas_alem_do_limite_esperam_na_fila() {
+    let gnosys = GnosysFalso::iniciar().await;
+    gnosys.modo(Modo::Lento);
+    let d = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    gnosys.registrar(d.clone());
+
+    // Com uma vaga e pouca espera, a segunda consulta desiste antes de a
+    // primeira, que leva meio segundo, terminar
+    limitar_consultas(1, Duration::from_millis(100));
+    let (a, b) = tokio::join!(consultar(&gnosys, &d), consultar(&gnosys, &d));
+    let esgotadas = [&a, &b]
+        .iter()
+        .filter(|r| matches!(r, Err(ConsultaErro::EsperaEsgotada(_))))
+        .count();
+    assert_eq!(esgotadas, 1, "{a:?} {b:?}");
+    assert!(matches!(a.or(b), Ok(Consulta::AlunoBCC { .. })));
+    assert_eq!(gnosys.consultas(), 1);
+
+    let fila = metricas().fila_gnosys;
+    assert_eq!(fila.esgotadas, 1);
+    assert_eq!(fila.esperando, 0);
+    assert_eq!(fila.em_andamento, 0);
+    assert_eq!(fila.espera.total, 2);
+    assert!(
+        metricas()
+            .prometheus()
+            .contains("alumnic_gnosys_esperas_esgotadas_total 1")
+    );
+
+    // Com espera suficiente, as duas são feitas, uma depois da outra
+    limitar_consultas(1, Duration::from_secs(5));
+    let inicio = Instant::now();
+    let (a, b) = tokio::join!(consultar(&gnosys, &d), consultar(&gnosys, &d));
+    assert!(a.is_ok() && b.is_ok());
+    assert!(inicio.elapsed() >= Duration::from_millis(1000));
+
+    // Com duas vagas, ao mesmo tempo
+    limitar_consultas(2, Duration::from_secs(5));
+    let inicio = Instant::now();
+    let (a, b) = tokio::join!(consultar(&gnosys, &d), consultar(&gnosys, &d));
+    assert!(a.is_ok() && b.is_ok());
+    assert!(inicio.elapsed() < Duration::from_millis(1000));
+    assert_eq!(gnosys.consultas(), 5);
+}
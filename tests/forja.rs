@@ -0,0 +1,140 @@
+//! Testes dos hooks que criam o usuário no GitLab e no Gitea, contra um
+//! servidor falso que responde como as APIs deles.
+
+use alumnic::auditoria::ConfiguracaoAuditoria;
+use alumnic::hooks::{
+    ConfiguracaoHooks, ErroHook, Evento, Hook, TipoEvento, disparar_evento,
+};
+use axum::Router;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+
+/// As requisições recebidas: o token e o corpo.
+type Recebidas = Arc<Mutex<Vec<(String, Value)>>>;
+
+async fn criar(
+    State(recebidas): State<Recebidas>,
+    headers: HeaderMap,
+    corpo: String,
+) -> (StatusCode, String) {
+    let token = ["PRIVATE-TOKEN", "Authorization"]
+        .iter()
+        .find_map(|h| headers.get(*h))
+        .map(|v| v.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    recebidas
+        .lock()
+        .unwrap()
+        .push((token, serde_json::from_str(&corpo).unwrap()));
+
+    (StatusCode::CREATED, json!({ "id": 42 }).to_string())
+}
+
+/// Sobe o servidor falso, retornando a URL e as requisições recebidas.
+async fn servidor() -> (String, Recebidas) {
+    let recebidas = Recebidas::default();
+    let app = Router::new()
+        .route("/api/v4/users", post(criar))
+        .route("/api/v1/admin/users", post(criar))
+        .with_state(recebidas.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, recebidas)
+}
+
+fn evento() -> Evento<'static> {
+    Evento {
+        evento: TipoEvento::Cadastro,
+        uid: "joaops",
+        dre: "123456789",
+        home: "/usuarios/alunos/joaops",
+        nome: "Joao Pedro Silva",
+        email: "joaops@ic.ufrj.br",
+    }
+}
+
+#[tokio::test]
+async fn cria_no_gitlab_e_registra_o_id() {
+    let (url, recebidas) = servidor().await;
+    let auditoria = std::env::temp_dir()
+        .join(format!("alumnic-forja-{}.jsonl", std::process::id()));
+    let cfg = ConfiguracaoHooks {
+        ao_cadastrar: vec![Hook::Gitlab {
+            url: format!("{url}/"),
+            token: "token-gitlab".to_string().into(),
+        }],
+        ..Default::default()
+    };
+
+    disparar_evento(
+        &cfg,
+        &ConfiguracaoAuditoria {
+            arquivo: Some(auditoria.clone()),
+        },
+        &evento(),
+    )
+    .await
+    .unwrap();
+
+    let (token, corpo) = recebidas.lock().unwrap()[0].clone();
+    assert_eq!(token, "token-gitlab");
+    assert_eq!(corpo["username"], "joaops");
+    assert_eq!(corpo["email"], "joaops@ic.ufrj.br");
+    assert_eq!(corpo["name"], "Joao Pedro Silva");
+
+    let linhas = std::fs::read_to_string(&auditoria).unwrap();
+    std::fs::remove_file(&auditoria).unwrap();
+    let registro: Value = serde_json::from_str(linhas.trim()).unwrap();
+    assert_eq!(registro["operacao"], "criar_usuario_externo");
+    assert_eq!(registro["uid"], "joaops");
+    assert_eq!(registro["motivo"], format!("gitlab {url}/ id 42"));
+}
+
+#[tokio::test]
+async fn cria_no_gitea_com_a_fonte_ldap() {
+    let (url, recebidas) = servidor().await;
+    let hook = Hook::Gitea {
+        url: url.clone(),
+        token: "token-gitea".to_string().into(),
+        fonte_de_autenticacao: Some(2),
+    };
+
+    assert_eq!(hook.disparar(&evento()).await.unwrap(), Some(42));
+    let (token, corpo) = recebidas.lock().unwrap()[0].clone();
+    assert_eq!(token, "token token-gitea");
+    assert_eq!(corpo["source_id"], 2);
+    assert_eq!(corpo["login_name"], "joaops");
+    assert!(corpo.get("password").is_none());
+
+    // Sem a fonte, com uma senha aleatória
+    let hook = Hook::Gitea {
+        url,
+        token: "token-gitea".to_string().into(),
+        fonte_de_autenticacao: None,
+    };
+    hook.disparar(&evento()).await.unwrap();
+    let (_, corpo) = recebidas.lock().unwrap()[1].clone();
+    assert_eq!(corpo["password"].as_str().unwrap().len(), 32);
+    assert_eq!(corpo["must_change_password"], true);
+}
+
+#[tokio::test]
+async fn resposta_sem_id_e_um_erro() {
+    let (url, _) = servidor().await;
+    let hook = Hook::Gitlab {
+        url: format!("{url}/outro"),
+        token: "token-gitlab".to_string().into(),
+    };
+
+    // A rota não existe no servidor falso
+    assert!(matches!(
+        hook.disparar(&evento()).await,
+        Err(ErroHook::Http(..))
+    ));
+}
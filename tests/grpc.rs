@@ -0,0 +1,244 @@
+//! Testes do serviço gRPC. Só rodam com a feature `grpc`.
+#![cfg(feature = "grpc")]
+
+mod comum;
+
+use alumnic::grpc::mensagens::progresso_lote::Resultado;
+use alumnic::grpc::mensagens::{
+    Cadastro, CadastroRealizado, ConsultaConta, ConsultaUsernames, Lote,
+    ProgressoLote, SituacaoConta, Usernames,
+};
+use alumnic::grpc::{SERVICO, ServicoGrpc};
+use chrono::Utc;
+use comum::api::{TOKEN, configuracao, diretorio_com_samba};
+use comum::gnosys::{Documento, GnosysFalso};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::codegen::tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Request, Status};
+use tonic_prost::ProstCodec;
+
+/// O serviço gRPC rodando numa porta local, com o LDAP em memória.
+struct GrpcDeTeste {
+    gnosys: GnosysFalso,
+    canal: Channel,
+}
+
+impl GrpcDeTeste {
+    async fn iniciar() -> Self {
+        let gnosys = GnosysFalso::iniciar().await;
+        let cfg = Arc::new(configuracao(&gnosys.url));
+        let ldap = Arc::new(Mutex::new(diretorio_com_samba().await));
+
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            Server::builder()
+                .add_service(ServicoGrpc::novo(cfg, ldap))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let canal = Channel::from_shared(url).unwrap().connect().await.unwrap();
+        Self { gnosys, canal }
+    }
+
+    /// A `mensagem` com o `token` no metadado.
+    fn requisicao<M>(mensagem: M, token: &str) -> Request<M> {
+        let mut req = Request::new(mensagem);
+        req.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        req
+    }
+
+    /// O caminho do `metodo` do serviço.
+    fn caminho(metodo: &str) -> PathAndQuery {
+        format!("/{SERVICO}/{metodo}").parse().unwrap()
+    }
+
+    async fn chamar_com_token<M, R>(
+        &self,
+        metodo: &str,
+        mensagem: M,
+        token: &str,
+    ) -> Result<R, Status>
+    where
+        M: prost::Message + Send + Sync + 'static,
+        R: prost::Message + Default + Send + Sync + 'static,
+    {
+        let mut grpc = tonic::client::Grpc::new(self.canal.clone());
+        grpc.ready().await.unwrap();
+        grpc.unary(
+            Self::requisicao(mensagem, token),
+            Self::caminho(metodo),
+            ProstCodec::default(),
+        )
+        .await
+        .map(|r| r.into_inner())
+    }
+
+    async fn chamar<M, R>(&self, metodo: &str, mensagem: M) -> Result<R, Status>
+    where
+        M: prost::Message + Send + Sync + 'static,
+        R: prost::Message + Default + Send + Sync + 'static,
+    {
+        self.chamar_com_token(metodo, mensagem, TOKEN).await
+    }
+
+    /// Renova o `lote`, retornando o progresso enviado pelo serviço.
+    async fn renovar_lote(&self, lote: Lote) -> Vec<ProgressoLote> {
+        let mut grpc = tonic::client::Grpc::new(self.canal.clone());
+        grpc.ready().await.unwrap();
+        let mut progresso = grpc
+            .server_streaming(
+                Self::requisicao(lote, TOKEN),
+                Self::caminho("RenovarLote"),
+                ProstCodec::<Lote, ProgressoLote>::default(),
+            )
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut recebido = vec![];
+        while let Some(p) = progresso.message().await.unwrap() {
+            recebido.push(p);
+        }
+        recebido
+    }
+}
+
+fn documento_de_hoje(codigo: &str) -> Documento {
+    let mut d = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    d.data = Utc::now().format("%d/%m/%Y").to_string();
+    d.codigo = codigo.to_string();
+    d
+}
+
+fn cadastro(d: &Documento) -> Cadastro {
+    Cadastro {
+        dre: d.dre.clone(),
+        data: d.data.clone(),
+        hora: d.hora.clone(),
+        codigo: d.codigo.clone(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".to_string(),
+        chave_ssh: None,
+        username: None,
+    }
+}
+
+#[tokio::test]
+async fn recusa_sem_o_token() {
+    let grpc = GrpcDeTeste::iniciar().await;
+    let consulta = ConsultaUsernames {
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+    };
+
+    let erro = grpc
+        .chamar_com_token::<_, Usernames>("UsernamesLivres", consulta, "errado")
+        .await
+        .unwrap_err();
+    assert_eq!(erro.code(), Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn cadastra_e_consulta_a_conta() {
+    let grpc = GrpcDeTeste::iniciar().await;
+
+    let usernames: Usernames = grpc
+        .chamar(
+            "UsernamesLivres",
+            ConsultaUsernames {
+                nome: "Cláudio de Lima Cavalcante".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(usernames.usernames.contains(&"claudiolc".to_string()));
+
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0001");
+    grpc.gnosys.registrar(d.clone());
+    let realizado: CadastroRealizado =
+        grpc.chamar("Cadastrar", cadastro(&d)).await.unwrap();
+    assert_eq!(realizado.username, "claudiolc");
+    assert_eq!(realizado.ou, "alunos");
+
+    // O mesmo cadastro de novo é recusado, como na API REST
+    let erro = grpc
+        .chamar::<_, CadastroRealizado>("Cadastrar", cadastro(&d))
+        .await
+        .unwrap_err();
+    assert_eq!(erro.code(), Code::AlreadyExists, "{erro:?}");
+
+    let situacao: SituacaoConta = grpc
+        .chamar(
+            "ConsultarConta",
+            ConsultaConta {
+                dre: "123456789".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(situacao.uid, "claudiolc");
+    assert_eq!(situacao.estado, "ativa");
+    assert!(!situacao.valida_ate.is_empty());
+    assert_eq!(situacao.remocao_em, "");
+
+    let erro = grpc
+        .chamar::<_, SituacaoConta>(
+            "ConsultarConta",
+            ConsultaConta {
+                dre: "999999999".to_string(),
+            },
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(erro.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn renovacao_em_lote_envia_o_progresso() {
+    let grpc = GrpcDeTeste::iniciar().await;
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0001");
+    grpc.gnosys.registrar(d.clone());
+    let _: CadastroRealizado =
+        grpc.chamar("Cadastrar", cadastro(&d)).await.unwrap();
+
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0002");
+    grpc.gnosys.registrar(d.clone());
+    let lote = Lote {
+        linhas: vec![
+            "# comentário".to_string(),
+            format!("{},{},{},{}", d.dre, d.data, d.hora, d.codigo),
+            "linha quebrada".to_string(),
+        ],
+    };
+
+    let progresso = grpc.renovar_lote(lote).await;
+    assert_eq!(progresso.len(), 2);
+    assert_eq!(
+        progresso
+            .iter()
+            .map(|p| (p.feitas, p.total))
+            .collect::<Vec<_>>(),
+        [(1, 2), (2, 2)],
+    );
+    match &progresso[0].resultado {
+        Some(Resultado::Renovada(r)) => assert_eq!(r.username, "claudiolc"),
+        r => panic!("a primeira linha não foi renovada: {r:?}"),
+    }
+    assert!(matches!(
+        &progresso[1].resultado,
+        Some(Resultado::Erro(e)) if e.contains("linha quebrada"),
+    ));
+}
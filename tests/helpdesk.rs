@@ -0,0 +1,59 @@
+//! Testes da rota de consulta das contas pelo helpdesk.
+
+mod comum;
+
+use comum::api::{ApiDeTeste, LIMITE_HELPDESK, TOKEN, TOKEN_HELPDESK};
+use comum::gnosys::Documento;
+use serde_json::Value;
+
+const ROTA: &str = "/api/helpdesk/contas/123456789";
+
+#[tokio::test]
+async fn consulta_a_conta_pelo_dre() {
+    let api = ApiDeTeste::iniciar().await;
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+
+    let (status, corpo) = api.get_com_token(ROTA, TOKEN_HELPDESK).await;
+    assert_eq!(status, 200, "{corpo}");
+    let conta: Value = serde_json::from_str(&corpo).unwrap();
+    assert_eq!(conta["uid"], "claudiolc");
+    assert_eq!(conta["estado"], "ativa");
+    assert!(conta["valida_ate"].is_string());
+    assert!(conta["carencia_ate"].as_str() > conta["valida_ate"].as_str());
+    assert!(conta["remocao_em"].is_null());
+
+    let (status, _) = api
+        .get_com_token("/api/helpdesk/contas/987654321", TOKEN_HELPDESK)
+        .await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn exige_o_token_do_helpdesk() {
+    let api = ApiDeTeste::iniciar().await;
+
+    // O token administrativo não dá acesso ao helpdesk
+    let (status, _) = api.get_com_token(ROTA, TOKEN).await;
+    assert_eq!(status, 401);
+    let (status, _) = api
+        .get_com_token("/api/admin/estatisticas", TOKEN_HELPDESK)
+        .await;
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn limita_as_consultas_por_minuto() {
+    let api = ApiDeTeste::iniciar().await;
+
+    for _ in 0..LIMITE_HELPDESK {
+        let (status, _) = api.get_com_token(ROTA, TOKEN_HELPDESK).await;
+        assert_eq!(status, 404);
+    }
+    let (status, _) = api.get_com_token(ROTA, TOKEN_HELPDESK).await;
+    assert_eq!(status, 429);
+}
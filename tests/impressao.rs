@@ -0,0 +1,152 @@
+//! Testes do registro das contas novas no sistema de impressão.
+
+mod comum;
+
+use alumnic::cadastro_aluno::DadosParaCadastro;
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::impressao::{
+    ConfiguracaoImpressao, ErroImpressao, SistemaImpressao, UsuarioImpressao,
+};
+use axum::Router;
+use axum::extract::State;
+use axum::routing::post;
+use comum::api::{configuracao, diretorio_com_samba};
+use std::sync::{Arc, Mutex};
+
+/// As chamadas XML-RPC recebidas.
+type Chamadas = Arc<Mutex<Vec<String>>>;
+
+const SUCESSO: &str = "<?xml version=\"1.0\"?><methodResponse><params>\
+    <param><value><boolean>1</boolean></value></param>\
+    </params></methodResponse>";
+const FALHA: &str = "<?xml version=\"1.0\"?><methodResponse><fault>\
+    <value><string>Usuário já existe</string></value>\
+    </fault></methodResponse>";
+
+async fn xmlrpc(State(chamadas): State<Chamadas>, corpo: String) -> String {
+    let falha = corpo.contains("recusado");
+    chamadas.lock().unwrap().push(corpo);
+    if falha { FALHA } else { SUCESSO }.to_string()
+}
+
+/// Sobe um PaperCut falso, retornando a URL e as chamadas recebidas.
+async fn papercut() -> (String, Chamadas) {
+    let chamadas = Chamadas::default();
+    let app = Router::new()
+        .route("/rpc/api/xmlrpc", post(xmlrpc))
+        .with_state(chamadas.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, chamadas)
+}
+
+fn cfg(sistema: SistemaImpressao) -> ConfiguracaoImpressao {
+    ConfiguracaoImpressao {
+        sistema,
+        cota_padrao: 50.0,
+        cotas: [("profcomp".to_string(), 100.5)].into(),
+    }
+}
+
+fn usuario(uid: &str) -> UsuarioImpressao<'_> {
+    UsuarioImpressao {
+        uid,
+        nome: "Cláudio D'Ávila & Cia",
+        email: "claudiolc@profcomp.ic.ufrj.br",
+        perfil: "profcomp",
+    }
+}
+
+#[tokio::test]
+async fn cria_o_usuario_no_papercut_com_a_cota_do_perfil() {
+    let (url, chamadas) = papercut().await;
+    let cfg = cfg(SistemaImpressao::Papercut {
+        url,
+        token: "segredo".to_string().into(),
+    });
+
+    cfg.registrar(&usuario("claudiolc")).await.unwrap();
+
+    let chamadas = chamadas.lock().unwrap();
+    assert_eq!(chamadas.len(), 4);
+    assert!(chamadas[0].contains("<methodName>api.addNewUser</methodName>"));
+    assert!(chamadas[0].contains("<string>segredo</string>"));
+    assert!(chamadas[0].contains("<string>claudiolc</string>"));
+    assert!(chamadas[1].contains("<string>Cláudio D'Ávila &amp; Cia</string>"));
+    assert!(chamadas[2].contains("<string>email</string>"));
+    assert!(chamadas[3].contains("api.setUserAccountBalance"));
+    assert!(chamadas[3].contains("<double>100.5</double>"));
+}
+
+#[tokio::test]
+async fn falha_do_papercut_interrompe_o_registro() {
+    let (url, chamadas) = papercut().await;
+    let cfg = cfg(SistemaImpressao::Papercut {
+        url,
+        token: "segredo".to_string().into(),
+    });
+
+    let r = cfg.registrar(&usuario("recusado")).await;
+    assert!(matches!(
+        r,
+        Err(ErroImpressao::Recusada {
+            metodo: "api.addNewUser",
+            ..
+        })
+    ));
+    assert_eq!(chamadas.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn cadastro_registra_na_impressao() {
+    let marcador = std::env::temp_dir()
+        .join(format!("alumnic-impressao-{}", std::process::id()));
+    let mut usuario_novo = configuracao("http://gnosys.invalido").usuario_novo;
+    usuario_novo.impressao = Some(cfg(SistemaImpressao::Comando {
+        comando: vec![
+            "sh".into(),
+            "-c".into(),
+            format!(
+                concat!(
+                    r#"test "$ALUMNIC_UID" = claudiolc && "#,
+                    r#"test "$ALUMNIC_PERFIL" = alunos && "#,
+                    r#"test "$ALUMNIC_COTA" = 50 && "#,
+                    r#"test "$ALUMNIC_EMAIL" = claudiolc@ic.ufrj.br && "#,
+                    "touch {}",
+                ),
+                marcador.display(),
+            ),
+        ],
+    }));
+    let ldap = Arc::new(tokio::sync::Mutex::new(diretorio_com_samba().await));
+
+    DadosParaCadastro {
+        dre: "123456789".to_string(),
+        data: String::new(),
+        hora: String::new(),
+        codigo: String::new(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".to_string().into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    }
+    .cadastrar_sem_verificar_documento(
+        "claudiolc".to_string(),
+        "supervisor",
+        &usuario_novo,
+        &ConfiguracaoRenovacao::default(),
+        "alunos",
+        &ldap,
+    )
+    .await
+    .unwrap();
+
+    assert!(marcador.exists());
+    std::fs::remove_file(&marcador).unwrap();
+}
@@ -0,0 +1,74 @@
+//! Testes da criação do principal Kerberos no cadastro. Só rodam com a
+//! feature `kerberos`.
+#![cfg(feature = "kerberos")]
+
+mod comum;
+
+use alumnic::cadastro_aluno::{DadosParaCadastro, ErroDeCadastro};
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::kerberos::{ConfiguracaoKerberos, Kdc};
+use comum::api::{configuracao, diretorio_com_samba};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+fn dados() -> DadosParaCadastro {
+    DadosParaCadastro {
+        dre: "123456789".to_string(),
+        data: String::new(),
+        hora: String::new(),
+        codigo: String::new(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".to_string().into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    }
+}
+
+/// Cadastra o aluno com um kadmin falso que roda o `script`.
+async fn cadastrar_com_kadmin(
+    script: &str,
+) -> (Result<(), ErroDeCadastro>, bool) {
+    let mut cfg = configuracao("http://gnosys.invalido").usuario_novo;
+    cfg.kerberos = Some(ConfiguracaoKerberos {
+        realm: "IC.UFRJ.BR".to_string(),
+        kdc: Kdc::Kadmin {
+            comando: vec!["sh".into(), "-c".into(), script.into()],
+        },
+    });
+    let ldap = Arc::new(Mutex::new(diretorio_com_samba().await));
+
+    let r = dados()
+        .cadastrar_sem_verificar_documento(
+            "claudiolc".to_string(),
+            "supervisor",
+            &cfg,
+            &ConfiguracaoRenovacao::default(),
+            "alunos",
+            &ldap,
+        )
+        .await;
+    let existe = ldap.lock().await.entrada(DN_ALUNO).is_some();
+    (r, existe)
+}
+
+#[tokio::test]
+async fn cria_o_principal_junto_com_a_conta() {
+    let (r, existe) =
+        cadastrar_com_kadmin(r#"test "$1" = "addprinc claudiolc@IC.UFRJ.BR""#)
+            .await;
+    r.unwrap();
+    assert!(existe);
+}
+
+#[tokio::test]
+async fn falha_no_kdc_desfaz_o_cadastro() {
+    let (r, existe) = cadastrar_com_kadmin("exit 1").await;
+    assert!(matches!(r, Err(ErroDeCadastro::ErroKerberos(..))));
+    assert!(!existe);
+}
@@ -0,0 +1,126 @@
+//! Testes do acompanhamento e da pausa dos lotes em andamento.
+
+mod comum;
+
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::lotes::{self, Lote, ProgressoLote};
+use alumnic::renovacao::renovar_em_lote;
+use chrono::Utc;
+use comum::api::{ApiDeTeste, diretorio_com_samba};
+use comum::gnosys::Documento;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Um diretório de dados temporário com o `nome`, único para o processo de
+/// teste.
+fn dados(nome: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("alumnic-{nome}-{}", std::process::id()))
+}
+
+fn documento(codigo: &str) -> Documento {
+    let mut d = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    d.data = Utc::now().format("%d/%m/%Y").to_string();
+    d.codigo = codigo.to_string();
+    d
+}
+
+#[tokio::test]
+async fn pausa_e_retoma_a_renovacao_em_lote() {
+    let dir = dados("lotes-pausa");
+    let dados_api = dir.clone();
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.dados = dados_api;
+        })
+        .await;
+    api.cadastrar(documento("0000.0000.0000.0000.0000.0000.0000.0001"))
+        .await;
+    let d = documento("0000.0000.0000.0000.0000.0000.0000.0002");
+    api.gnosys.registrar(d.clone());
+    let linha = format!("{},{},{},{}", d.dre, d.data, d.hora, d.codigo);
+    let lista = format!("{linha}\n{linha}\n");
+
+    let lote =
+        Lote::iniciar(&dir, "renovacao", 2, CancellationToken::new()).unwrap();
+    let id = lote.id();
+    lotes::pausar(&dir, &id, true).unwrap().unwrap();
+
+    let prazos = ConfiguracaoRenovacao::default();
+    let renovacao = renovar_em_lote(
+        &lista,
+        &prazos,
+        &api.gnosys.url,
+        &api.ldap,
+        Utc::now(),
+        2,
+        &lote,
+    );
+    let acompanhamento = async {
+        // Pausado, o lote não começa nenhuma linha
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(api.gnosys.consultas(), 1);
+        let (status, corpo) = api.get_admin("/api/admin/lotes").await;
+        assert_eq!(status, 200, "{corpo}");
+        let lotes: Value = serde_json::from_str(&corpo).unwrap();
+        assert_eq!(lotes[0]["id"], id);
+        assert_eq!(lotes[0]["pausado"], true);
+        assert_eq!(lotes[0]["processados"], 0);
+
+        let (status, corpo) = api
+            .put_admin(
+                &format!("/api/admin/lotes/{id}"),
+                r#"{"pausado":false}"#,
+            )
+            .await;
+        assert_eq!(status, 200, "{corpo}");
+    };
+    let (resultados, ()) = tokio::join!(renovacao, acompanhamento);
+
+    assert!(resultados.iter().all(|(_, r)| r.is_ok()), "{resultados:?}");
+    let progresso = &lotes::lotes(&dir).unwrap()[0];
+    assert_eq!((progresso.processados, progresso.falhas), (2, 0));
+    assert!(progresso.segundos_pausado >= 1);
+    assert_eq!(progresso.segundos_restantes, Some(0));
+
+    // O lote terminado sai do acompanhamento
+    drop(lote);
+    assert!(lotes::lotes(&dir).unwrap().is_empty());
+    let (status, _) = api
+        .put_admin(&format!("/api/admin/lotes/{id}"), r#"{"pausado":true}"#)
+        .await;
+    assert_eq!(status, 404);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lote_de_processo_encerrado_e_descartado() {
+    let dir = dados("lotes-encerrado");
+    std::fs::create_dir_all(dir.join("lotes")).unwrap();
+    let agora = Utc::now();
+    let progresso = ProgressoLote {
+        id: "renovacao-1".to_string(),
+        tipo: "renovacao".to_string(),
+        pid: u32::MAX,
+        total: 10,
+        processados: 3,
+        falhas: 0,
+        inicio: agora,
+        atualizado: agora,
+        segundos_pausado: 0,
+        pausado: false,
+        segundos_restantes: None,
+    };
+    let arquivo = dir.join("lotes/renovacao-1.json");
+    std::fs::write(&arquivo, serde_json::to_vec(&progresso).unwrap()).unwrap();
+
+    assert!(lotes::lotes(&dir).unwrap().is_empty());
+    assert!(!arquivo.exists());
+    assert!(lotes::pausar(&dir, "renovacao-1", true).unwrap().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,140 @@
+//! Testes da migração da graduação para a pós-graduação.
+
+mod comum;
+
+use alumnic::configuracao::{Configuracao, ConfiguracaoPos};
+use alumnic::migracao::{DadosParaMigracao, ErroDeMigracao};
+use chrono::Utc;
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+const DN_PPGI: &str =
+    "uid=claudiolc,ou=ppgi,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+fn configuracao_com_pos(gnosys_url: &str) -> Configuracao {
+    let mut cfg = configuracao(gnosys_url);
+    cfg.pos = Some(ConfiguracaoPos {
+        curso: "Informática".into(),
+        ou: "ppgi".into(),
+        gid_number: "2000".into(),
+        cota: "5000".into(),
+    });
+    cfg
+}
+
+/// Cadastra o aluno `claudiolc` na graduação e registra no Gnosys o documento
+/// do novo vínculo, com o `nome` e o `curso` dados.
+async fn aluno_com_documento_da_pos(
+    api: &ApiDeTeste,
+    nome: &str,
+    curso: &str,
+) -> DadosParaMigracao {
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+
+    let mut documento = Documento::novo("125000111", nome, curso);
+    documento.codigo = "B4C2.8F6E.0113.2ABD.5A7C.AE4F.93D2.CBB0".into();
+    api.gnosys.registrar(documento.clone());
+
+    DadosParaMigracao {
+        uid: "claudiolc".into(),
+        dre: documento.dre,
+        data: documento.data,
+        hora: documento.hora,
+        codigo: documento.codigo,
+    }
+}
+
+#[tokio::test]
+async fn migra_mantendo_o_uid() {
+    let api = ApiDeTeste::iniciar().await;
+    let dados = aluno_com_documento_da_pos(
+        &api,
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Informática",
+    )
+    .await;
+    let antes = api
+        .ldap
+        .lock()
+        .await
+        .entrada(DN_ALUNO)
+        .unwrap()
+        .attrs
+        .clone();
+
+    let mut cfg = configuracao_com_pos(&api.gnosys.url);
+    cfg.usuario_novo.campos_academicos = Some(Default::default());
+    let dn = dados.migrar(&cfg, &api.ldap, Utc::now()).await.unwrap();
+    assert_eq!(dn, DN_PPGI);
+
+    let ldap = api.ldap.lock().await;
+    assert!(ldap.entrada(DN_ALUNO).is_none());
+    let e = ldap.entrada(DN_PPGI).unwrap();
+    assert_eq!(e.attrs["dccDRE"], vec!["125000111"]);
+    assert_eq!(e.attrs["gidNumber"], vec!["2000"]);
+    assert_eq!(e.attrs["cota"], vec!["5000"]);
+    assert_eq!(e.attrs["dccCurso"], vec!["Informática"]);
+    // O DRE novo não segue o padrão, então não há período de ingresso
+    assert!(!e.attrs.contains_key("dccIngresso"));
+    for atributo in ["uid", "uidNumber", "homeDirectory", "userPassword"] {
+        assert_eq!(e.attrs[atributo], antes[atributo], "{atributo}");
+    }
+}
+
+#[tokio::test]
+async fn migracao_exige_o_curso_e_a_pessoa() {
+    let api = ApiDeTeste::iniciar().await;
+    let dados =
+        aluno_com_documento_da_pos(&api, "FULANO DE TAL", "Informática").await;
+
+    assert!(matches!(
+        dados
+            .migrar(&configuracao(&api.gnosys.url), &api.ldap, Utc::now())
+            .await,
+        Err(ErroDeMigracao::Desativada),
+    ));
+
+    let dados = DadosParaMigracao {
+        uid: "claudiolc".into(),
+        dre: "125000111".into(),
+        data: "01/03/2025".into(),
+        hora: "10:00".into(),
+        codigo: "B4C2.8F6E.0113.2ABD.5A7C.AE4F.93D2.CBB0".into(),
+    };
+    let cfg = configuracao_com_pos(&api.gnosys.url);
+    assert!(matches!(
+        dados.migrar(&cfg, &api.ldap, Utc::now()).await,
+        Err(ErroDeMigracao::OutraPessoa { .. }),
+    ));
+
+    // A conta continua na graduação
+    let ldap = api.ldap.lock().await;
+    assert_eq!(
+        ldap.entrada(DN_ALUNO).unwrap().attrs["dccDRE"],
+        vec!["123456789"]
+    );
+}
+
+#[tokio::test]
+async fn documento_de_outro_curso_nao_migra() {
+    let api = ApiDeTeste::iniciar().await;
+    let dados = aluno_com_documento_da_pos(
+        &api,
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Engenharia Civil",
+    )
+    .await;
+
+    let cfg = configuracao_com_pos(&api.gnosys.url);
+    assert!(matches!(
+        dados.migrar(&cfg, &api.ldap, Utc::now()).await,
+        Err(ErroDeMigracao::OutroCurso(curso)) if curso == "Engenharia Civil",
+    ));
+}
@@ -0,0 +1,148 @@
+//! Testes da inscrição das contas novas no Moodle.
+
+mod comum;
+
+use alumnic::cadastro_aluno::DadosParaCadastro;
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::moodle::{ConfiguracaoMoodle, ErroMoodle, UsuarioMoodle};
+use axum::extract::{Form, State};
+use axum::routing::post;
+use axum::{Json, Router};
+use comum::api::{configuracao, diretorio_com_samba};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Os parâmetros de cada chamada recebida.
+type Chamadas = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+async fn servidor(
+    State(chamadas): State<Chamadas>,
+    Form(parametros): Form<HashMap<String, String>>,
+) -> Json<Value> {
+    let resposta = if parametros["wstoken"] != "segredo" {
+        json!({
+            "exception": "moodle_exception",
+            "errorcode": "invalidtoken",
+            "message": "Token inválido",
+        })
+    } else if parametros["users[0][username]"] == "repetido" {
+        json!({
+            "exception": "invalid_parameter_exception",
+            "errorcode": "invalidparameter",
+            "message": "Username already exists: repetido",
+        })
+    } else {
+        json!([{"id": 42, "username": parametros["users[0][username]"]}])
+    };
+    chamadas.lock().unwrap().push(parametros);
+    Json(resposta)
+}
+
+/// Sobe um Moodle falso, retornando a URL e as chamadas recebidas.
+async fn moodle() -> (String, Chamadas) {
+    let chamadas = Chamadas::default();
+    let app = Router::new()
+        .route("/webservice/rest/server.php", post(servidor))
+        .with_state(chamadas.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    (url, chamadas)
+}
+
+fn cfg(url: String) -> ConfiguracaoMoodle {
+    ConfiguracaoMoodle {
+        url,
+        token: "segredo".to_string().into(),
+        autenticacao: "ldap".to_string(),
+        campos: [
+            ("idnumber".to_string(), "{dre}".to_string()),
+            ("institution".to_string(), "UFRJ".to_string()),
+        ]
+        .into(),
+        campos_personalizados: [("curso".to_string(), "{ou}".to_string())]
+            .into(),
+    }
+}
+
+fn usuario(uid: &str) -> UsuarioMoodle<'_> {
+    UsuarioMoodle {
+        uid,
+        nome: "Cláudio de Lima Cavalcante",
+        email: "claudiolc@profcomp.ic.ufrj.br",
+        ou: "profcomp",
+        dre: "123456789",
+    }
+}
+
+#[tokio::test]
+async fn cria_o_usuario_com_os_campos_mapeados() {
+    let (url, chamadas) = moodle().await;
+
+    let id = cfg(url).inscrever(&usuario("claudiolc")).await.unwrap();
+    assert_eq!(id, 42);
+
+    let chamadas = chamadas.lock().unwrap();
+    let p = &chamadas[0];
+    assert_eq!(p["wsfunction"], "core_user_create_users");
+    assert_eq!(p["users[0][username]"], "claudiolc");
+    assert_eq!(p["users[0][auth]"], "ldap");
+    assert_eq!(p["users[0][email]"], "claudiolc@profcomp.ic.ufrj.br");
+    assert_eq!(p["users[0][lastname]"], "de Lima Cavalcante");
+    assert_eq!(p["users[0][idnumber]"], "123456789");
+    assert_eq!(p["users[0][institution]"], "UFRJ");
+    assert_eq!(p["users[0][customfields][0][value]"], "profcomp");
+    assert!(!p.contains_key("users[0][password]"));
+}
+
+#[tokio::test]
+async fn recusa_do_moodle_vira_erro() {
+    let (url, _) = moodle().await;
+
+    let r = cfg(url).inscrever(&usuario("repetido")).await;
+    assert!(matches!(
+        r,
+        Err(ErroMoodle::Recusada { codigo, .. }) if codigo == "invalidparameter"
+    ));
+}
+
+#[tokio::test]
+async fn cadastro_inscreve_no_moodle() {
+    let (url, chamadas) = moodle().await;
+    let mut usuario_novo = configuracao("http://gnosys.invalido").usuario_novo;
+    usuario_novo.moodle = Some(cfg(url));
+    let ldap = Arc::new(tokio::sync::Mutex::new(diretorio_com_samba().await));
+
+    DadosParaCadastro {
+        dre: "123456789".to_string(),
+        data: String::new(),
+        hora: String::new(),
+        codigo: String::new(),
+        nome: "Cláudio de Lima Cavalcante".to_string(),
+        email: "claudio@exemplo.com".to_string(),
+        telefone: "(21) 98765-4321".to_string(),
+        senha: "Senha1234".to_string().into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    }
+    .cadastrar_sem_verificar_documento(
+        "claudiolc".to_string(),
+        "supervisor",
+        &usuario_novo,
+        &ConfiguracaoRenovacao::default(),
+        "alunos",
+        &ldap,
+    )
+    .await
+    .unwrap();
+
+    let chamadas = chamadas.lock().unwrap();
+    assert_eq!(chamadas.len(), 1);
+    assert_eq!(chamadas[0]["users[0][username]"], "claudiolc");
+    assert_eq!(chamadas[0]["users[0][email]"], "claudiolc@ic.ufrj.br");
+    assert_eq!(chamadas[0]["users[0][customfields][0][value]"], "alunos");
+}
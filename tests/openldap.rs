@@ -0,0 +1,377 @@
+//! Testes de ponta a ponta contra um OpenLDAP de verdade, com o schema do DCC
+//! e do Samba. Precisam do Docker e por isso só rodam com
+//! `cargo test --test openldap -- --ignored`.
+
+mod comum;
+
+use alumnic::cadastro_aluno::{DadosParaCadastro, DadosValidados};
+use alumnic::configuracao::{ConfiguracaoRenovacao, ConfiguracaoUsuario};
+use alumnic::ldap::cadastrar::cadastrar_usuario;
+use alumnic::ldap::conexao::{
+    ConfiguracaoTls, FonteLdap, Replicas, ServidorLdap,
+};
+use alumnic::ldap::consulta::{Consulta, consultar_cadastro_ldap};
+use alumnic::ldap::conta::{EstadoConta, buscar_conta_por_uid};
+use alumnic::ldap::egresso::tornar_egresso;
+use alumnic::ldap::renovar::aplicar_carencia;
+use alumnic::ldap::schema::verificar_schema;
+use alumnic::ldap::verificacao::{ErroDeVerificacao, verificar_bind};
+use alumnic::remocao::{ErroDeRemocao, remover_agora};
+use alumnic::renovacao::{DadosParaRenovacao, dia};
+use chrono::Utc;
+use comum::api::configuracao;
+use comum::gnosys::{Documento, GnosysFalso};
+use ldap3::{LdapConnAsync, Scope, SearchEntry};
+use std::path::Path;
+use testcontainers::core::WaitFor;
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage, ImageExt};
+
+const BIND_DN: &str = "cn=admin,dc=dcc,dc=ufrj,dc=br";
+const BIND_PW: &str = "admin";
+
+struct OpenLdap {
+    // Mantém o container vivo enquanto o teste roda
+    _container: ContainerAsync<GenericImage>,
+    url: String,
+    servidor: ServidorLdap,
+}
+
+async fn subir_openldap() -> OpenLdap {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/openldap");
+
+    let container = GenericImage::new("bitnami/openldap", "2.6")
+        .with_wait_for(WaitFor::message_on_stdout("** Starting slapd **"))
+        .with_env_var("LDAP_ROOT", "dc=dcc,dc=ufrj,dc=br")
+        .with_env_var("LDAP_ADMIN_USERNAME", "admin")
+        .with_env_var("LDAP_ADMIN_PASSWORD", BIND_PW)
+        .with_env_var("LDAP_EXTRA_SCHEMAS", "cosine,inetorgperson,nis")
+        .with_env_var("LDAP_CUSTOM_SCHEMA_DIR", "/schemas")
+        .with_env_var("LDAP_CUSTOM_LDIF_DIR", "/ldifs")
+        .with_copy_to("/schemas/samba.ldif", dir.join("schema/samba.ldif"))
+        .with_copy_to("/schemas/dcc.ldif", dir.join("schema/dcc.ldif"))
+        .with_copy_to(
+            "/schemas/openssh-lpk.ldif",
+            dir.join("schema/openssh-lpk.ldif"),
+        )
+        .with_copy_to("/ldifs/base.ldif", dir.join("ldifs/base.ldif"))
+        .start()
+        .await
+        .expect("não foi possível subir o OpenLDAP");
+
+    let porta = container.get_host_port_ipv4(1389).await.unwrap();
+    let url = format!("ldap://127.0.0.1:{porta}");
+
+    // O slapd pode demorar um pouco para aceitar conexões depois da mensagem
+    for _ in 0..50 {
+        if LdapConnAsync::new(&url).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+
+    OpenLdap {
+        _container: container,
+        servidor: ServidorLdap {
+            url: url.clone(),
+            bind_dn: BIND_DN.to_string(),
+            bind_pw: BIND_PW.to_string(),
+            timeout: std::time::Duration::from_secs(10),
+            certificado: None,
+            tls: ConfiguracaoTls::default(),
+            replicas: Replicas::default(),
+        },
+        url,
+    }
+}
+
+fn cfg() -> ConfiguracaoUsuario {
+    ConfiguracaoUsuario {
+        gid_number: "1000".to_string(),
+        samba_sid_prefix: "S-1-5-21-1-2-3-".to_string(),
+        samba_acct_flags: "[UX]".to_string(),
+        samba_lm_password: "XXX".to_string(),
+        samba_password_history: "000".to_string(),
+        samba_primary_group_sid: "S-1-5-21-1-2-3-513".to_string(),
+        cota: "1000".to_string(),
+        caixa_email: None,
+        impressao: None,
+        moodle: None,
+        #[cfg(feature = "kerberos")]
+        kerberos: None,
+        campos_academicos: Some(Default::default()),
+        reutilizacao_uids: None,
+        reserva_username: None,
+        usernames_confundiveis: None,
+        foto: None,
+        contingencia: None,
+        alias_email: None,
+        duplicidade: None,
+        telefone: Default::default(),
+        dominios_email: Default::default(),
+        atributo_responsavel: None,
+    }
+}
+
+fn dados(dre: &str, nome: &str) -> DadosValidados {
+    DadosParaCadastro {
+        dre: dre.to_string(),
+        data: "01/03/2025".to_string(),
+        hora: "10:00".to_string(),
+        codigo: "A3B1.7E5D.F002.19AC.4F6B.9D3E.82C1.BAAF".to_string(),
+        nome: nome.to_string(),
+        email: "aluno@exemplo.com".to_string(),
+        telefone: "+5521987654321".to_string(),
+        senha: "Senha1234".to_string().into(),
+        chave_ssh: None,
+        username: None,
+        foto: None,
+    }
+    .validar(&cfg())
+    .unwrap()
+}
+
+/// Faz a consulta e o cadastro, como o fluxo da API faz depois de validar o
+/// documento no Gnosys.
+async fn consultar_e_cadastrar(
+    servidor: &ServidorLdap,
+    dados: &DadosValidados,
+) -> Result<String, String> {
+    let uid = match consultar_cadastro_ldap(dados.dre(), dados.nome(), servidor)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        Consulta::CadastroDisponivel(uid) => uid,
+        Consulta::CadastroRedundante(uid) => {
+            return Err(format!("redundante: {uid}"));
+        },
+    };
+
+    cadastrar_usuario(
+        uid.clone(),
+        dados,
+        &cfg(),
+        &ConfiguracaoRenovacao::default(),
+        "alunos",
+        servidor,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    Ok(uid)
+}
+
+async fn entradas_com_dre(url: &str, dre: &str) -> Vec<SearchEntry> {
+    let (conn, mut ldap) = LdapConnAsync::new(url).await.unwrap();
+    ldap3::drive!(conn);
+    ldap.simple_bind(BIND_DN, BIND_PW).await.unwrap();
+
+    let (r, _) = ldap
+        .search(
+            "dc=dcc,dc=ufrj,dc=br",
+            Scope::Subtree,
+            &format!("(dccDRE={dre})"),
+            vec!["uid"],
+        )
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+
+    r.into_iter().map(SearchEntry::construct).collect()
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn cadastro_e_consulta() {
+    let ldap = subir_openldap().await;
+    let aluno = dados("123456789", "Cláudio de Lima Cavalcante");
+
+    let uid = consultar_e_cadastrar(&ldap.servidor, &aluno).await.unwrap();
+    assert_eq!(uid, "claudiolc");
+
+    // O mesmo DRE agora aparece como já cadastrado
+    let r = consultar_cadastro_ldap(aluno.dre(), aluno.nome(), &ldap.servidor)
+        .await
+        .unwrap();
+    assert!(matches!(r, Consulta::CadastroRedundante(u) if u == "claudiolc"));
+
+    // Um homônimo recebe o próximo username livre
+    let homonimo = dados("987654321", "Claudio Lima Cavalcante");
+    let uid = consultar_e_cadastrar(&ldap.servidor, &homonimo)
+        .await
+        .unwrap();
+    assert_eq!(uid, "claudiolcavalcante");
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn cadastros_concorrentes_do_mesmo_dre() {
+    let ldap = subir_openldap().await;
+    let aluno = dados("111222333", "Ana Braga Souza");
+
+    // Simula o duplo clique no formulário: as duas requisições consultam o
+    // LDAP antes de qualquer uma das duas terminar o cadastro
+    let (a, b) = tokio::join!(
+        consultar_e_cadastrar(&ldap.servidor, &aluno),
+        consultar_e_cadastrar(&ldap.servidor, &aluno),
+    );
+
+    assert!(a.is_ok() || b.is_ok(), "nenhum cadastro deu certo");
+    assert_eq!(entradas_com_dre(&ldap.url, aluno.dre()).await.len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn aluno_vira_egresso() {
+    let ldap = subir_openldap().await;
+    let aluno = dados("444555666", "Beatriz Moura Lima");
+    let uid = consultar_e_cadastrar(&ldap.servidor, &aluno).await.unwrap();
+
+    let mut conexao = ldap.servidor.abrir().await.unwrap();
+    let conta = buscar_conta_por_uid(&uid, &mut conexao)
+        .await
+        .unwrap()
+        .unwrap();
+    // O schema do OpenLDAP recusa a modificação se sobrar algum atributo das
+    // objectClasses removidas
+    let dn = tornar_egresso(
+        &conta,
+        "ou=egressos,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+        &mut conexao,
+    )
+    .await
+    .unwrap();
+    ldap.servidor.fechar(conexao).await.unwrap();
+
+    let entradas = entradas_com_dre(&ldap.url, aluno.dre()).await;
+    assert_eq!(entradas.len(), 1);
+    assert_eq!(entradas[0].dn, dn);
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn renova_uma_conta_em_carencia() {
+    let ldap = subir_openldap().await;
+    let aluno = dados("777888999", "Daniela Rocha Pires");
+    let uid = consultar_e_cadastrar(&ldap.servidor, &aluno).await.unwrap();
+
+    // Um ano e meio depois, a conta não renovada entra em carência
+    let prazos = ConfiguracaoRenovacao::default();
+    let mut conexao = ldap.servidor.abrir().await.unwrap();
+    let em_carencia = aplicar_carencia(
+        dia(Utc::now()) + prazos.validade_conta + 180,
+        &mut conexao,
+    )
+    .await
+    .unwrap();
+    ldap.servidor.fechar(conexao).await.unwrap();
+    assert_eq!(em_carencia.len(), 1);
+    assert_eq!(em_carencia[0].uid, uid);
+
+    let gnosys = GnosysFalso::iniciar().await;
+    let mut documento = Documento::novo(
+        aluno.dre(),
+        "DANIELA ROCHA PIRES",
+        "Ciência da Computação",
+    );
+    documento.data = Utc::now().format("%d/%m/%Y").to_string();
+    gnosys.registrar(documento.clone());
+
+    let renovada = DadosParaRenovacao {
+        dre: documento.dre,
+        data: documento.data,
+        hora: documento.hora,
+        codigo: documento.codigo,
+    }
+    .renovar(&prazos, &gnosys.url, &ldap.servidor, Utc::now())
+    .await
+    .unwrap();
+    assert_eq!(renovada.username, uid);
+
+    let mut conexao = ldap.servidor.abrir().await.unwrap();
+    let conta = buscar_conta_por_uid(&uid, &mut conexao)
+        .await
+        .unwrap()
+        .unwrap();
+    ldap.servidor.fechar(conexao).await.unwrap();
+    assert_eq!(conta.estado, EstadoConta::Ativa);
+    assert_eq!(
+        conta.data_renovacao,
+        Some(dia(Utc::now()) + prazos.validade_conta),
+    );
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn remove_uma_conta() {
+    let ldap = subir_openldap().await;
+    let aluno = dados("222333444", "Eduardo Nunes Faria");
+    let uid = consultar_e_cadastrar(&ldap.servidor, &aluno).await.unwrap();
+
+    let auditoria = std::env::temp_dir().join(format!(
+        "alumnic-auditoria-openldap-{}.jsonl",
+        std::process::id(),
+    ));
+    let mut cfg = configuracao("http://gnosys.invalido");
+    cfg.auditoria.arquivo = Some(auditoria.clone());
+
+    // O schema do OpenLDAP recusa o bloqueio e a marcação de removida se
+    // algum atributo alterado não existir nas objectClasses da conta
+    let conta = remover_agora(
+        &uid,
+        "conta comprometida",
+        &cfg,
+        &ldap.servidor,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(conta.estado, EstadoConta::Removida);
+
+    let mut conexao = ldap.servidor.abrir().await.unwrap();
+    let conta = buscar_conta_por_uid(&uid, &mut conexao)
+        .await
+        .unwrap()
+        .unwrap();
+    ldap.servidor.fechar(conexao).await.unwrap();
+    assert_eq!(conta.estado, EstadoConta::Removida);
+    assert_eq!(conta.data_remocao, Some(dia(Utc::now())));
+
+    // A entrada continua no LDAP, para o DRE e o uid não serem reaproveitados
+    assert_eq!(entradas_com_dre(&ldap.url, aluno.dre()).await.len(), 1);
+    assert!(matches!(
+        remover_agora(&uid, "de novo", &cfg, &ldap.servidor, Utc::now()).await,
+        Err(ErroDeRemocao::JaRemovida(..)),
+    ));
+
+    let registro = std::fs::read_to_string(&auditoria).unwrap();
+    std::fs::remove_file(&auditoria).unwrap();
+    assert!(registro.contains("conta comprometida"));
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn verifica_o_bind() {
+    let ldap = subir_openldap().await;
+
+    let identidade = verificar_bind(&ldap.servidor).await.unwrap();
+    assert_eq!(identidade, format!("dn:{BIND_DN}"));
+
+    let errado = ServidorLdap {
+        bind_pw: "errada".to_string(),
+        ..ldap.servidor.clone()
+    };
+    assert!(matches!(
+        verificar_bind(&errado).await,
+        Err(ErroDeVerificacao::Bind(_)),
+    ));
+}
+
+#[tokio::test]
+#[ignore = "precisa do Docker"]
+async fn verifica_o_schema() {
+    let ldap = subir_openldap().await;
+
+    verificar_schema(&cfg(), &ldap.servidor).await.unwrap();
+}
@@ -0,0 +1,437 @@
+//! Testes do painel web administrativo.
+
+mod comum;
+
+use alumnic::autorizacao::{ConfiguracaoAutorizacao, Papel};
+use alumnic::configuracao::Configuracao;
+use alumnic::desligamento::desligar_em_lote;
+use alumnic::ldap::conexao::FonteLdap;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::totp::{ConfiguracaoTotp, codigo, decodificar_segredo};
+use alumnic::utils::hashes::hash_ssha;
+use chrono::Utc;
+use comum::api::{
+    ApiDeTeste, GRUPO_PAINEL, TOKEN, configuracao, diretorio_com_samba,
+};
+use comum::gnosys::Documento;
+use reqwest::header::{COOKIE, LOCATION, SET_COOKIE};
+use reqwest::redirect::Policy;
+use secrecy::ExposeSecret;
+use tokio_util::sync::CancellationToken;
+
+const SENHA: &str = "SenhaDoSupervisor";
+
+/// Um grupo que dá o papel de operador nos testes dos papéis.
+const GRUPO_OPERADORES: &str = "cn=operadores,ou=grupos,dc=dcc,dc=ufrj,dc=br";
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// O painel da API de teste, acessado sem seguir os redirecionamentos.
+struct Painel {
+    api: ApiDeTeste,
+    cliente: reqwest::Client,
+    cookie: Option<String>,
+}
+
+impl Painel {
+    /// Sobe a API com os supervisores `prof`, `leitor` e `monitor` e com o
+    /// `intruso`, que não é supervisor, todos com a senha [SENHA]. O
+    /// `monitor` também está no [GRUPO_OPERADORES].
+    async fn iniciar() -> Self {
+        Self::iniciar_ajustando(|_| {}).await
+    }
+
+    /// Sobe o painel com a configuração de teste mudada pelo `ajuste`.
+    async fn iniciar_ajustando(ajuste: impl FnOnce(&mut Configuracao)) -> Self {
+        let api =
+            ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, ajuste)
+                .await;
+        {
+            let mut ldap = api.ldap.lock().await;
+            let hash = hash_ssha(&SENHA.to_string().into());
+            for uid in ["prof", "leitor", "monitor", "intruso"] {
+                ldap.adicionar(
+                    &format!("uid={uid},ou=professores,ou=usuarios,dc=dcc,dc=ufrj,dc=br"),
+                    vec![
+                        ("objectClass", ["posixAccount"].into()),
+                        ("uid", [uid].into()),
+                        ("userPassword", [hash.expose_secret()].into()),
+                    ],
+                )
+                .await
+                .unwrap();
+            }
+            ldap.adicionar(
+                GRUPO_PAINEL,
+                vec![
+                    ("objectClass", ["posixGroup"].into()),
+                    ("cn", ["supervisores"].into()),
+                    ("memberUid", ["prof", "leitor", "monitor"].into()),
+                ],
+            )
+            .await
+            .unwrap();
+            ldap.adicionar(
+                GRUPO_OPERADORES,
+                vec![
+                    ("objectClass", ["posixGroup"].into()),
+                    ("cn", ["operadores"].into()),
+                    ("memberUid", ["monitor"].into()),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+
+        Self {
+            api,
+            cliente: reqwest::Client::builder()
+                .redirect(Policy::none())
+                .build()
+                .unwrap(),
+            cookie: None,
+        }
+    }
+
+    /// Tenta entrar no painel, guardando o cookie da sessão se conseguir.
+    async fn entrar(&mut self, uid: &str, senha: &str) -> u16 {
+        let res = self
+            .cliente
+            .post(format!("{}/painel/login", self.api.url))
+            .form(&[("uid", uid), ("senha", senha)])
+            .send()
+            .await
+            .unwrap();
+
+        if let Some(cookie) = res.headers().get(SET_COOKIE) {
+            let cookie = cookie.to_str().unwrap();
+            assert!(cookie.contains("HttpOnly"), "{cookie}");
+            self.cookie = cookie.split(';').next().map(str::to_string);
+        }
+        res.status().as_u16()
+    }
+
+    /// Faz um GET ou, com um `formulario`, um POST em `caminho` com o cookie
+    /// da sessão. Retorna o status e o corpo, ou o destino de um
+    /// redirecionamento.
+    async fn pedir(
+        &self,
+        caminho: &str,
+        formulario: Option<&[(&str, &str)]>,
+    ) -> (u16, String) {
+        let url = format!("{}{caminho}", self.api.url);
+        let mut req = match formulario {
+            Some(formulario) => self.cliente.post(url).form(formulario),
+            None => self.cliente.get(url),
+        };
+        if let Some(cookie) = &self.cookie {
+            req = req.header(COOKIE, cookie);
+        }
+
+        let res = req.send().await.unwrap();
+        let status = res.status().as_u16();
+        match res.headers().get(LOCATION) {
+            Some(destino) => (status, destino.to_str().unwrap().to_string()),
+            None => (status, res.text().await.unwrap()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn so_supervisores_entram() {
+    let mut painel = Painel::iniciar().await;
+
+    assert_eq!(
+        painel.pedir("/painel", None).await,
+        (303, "/painel/login".to_string()),
+    );
+
+    assert_eq!(painel.entrar("prof", "errada").await, 401);
+    assert_eq!(painel.entrar("prof", "").await, 401);
+    assert_eq!(painel.entrar("ninguem", SENHA).await, 401);
+    assert_eq!(painel.entrar("intruso", SENHA).await, 401);
+    assert!(painel.cookie.is_none());
+
+    assert_eq!(painel.entrar("prof", SENHA).await, 303);
+    let (status, pagina) = painel.pedir("/painel", None).await;
+    assert_eq!(status, 200);
+    assert!(pagina.contains("Logado como <b>prof</b>"), "{pagina}");
+
+    // Depois de sair, o mesmo cookie não vale mais
+    painel.pedir("/painel/sair", Some(&[])).await;
+    assert_eq!(painel.pedir("/painel", None).await.0, 303);
+}
+
+#[tokio::test]
+async fn mostra_pendencias_e_falhas_e_reativa() {
+    let mut painel = Painel::iniciar().await;
+    let api = &painel.api;
+
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+    desligar_em_lote(
+        "123456789",
+        &configuracao(&api.gnosys.url),
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    // Uma renovação que falha aparece entre as últimas falhas
+    let (status, _) = api
+        .post(
+            "/api/renovar",
+            r#"{"dre": "12345", "data": "", "hora": "", "codigo": ""}"#,
+        )
+        .await;
+    assert_eq!(status, 422);
+
+    painel.entrar("prof", SENHA).await;
+    let (_, pagina) = painel.pedir("/painel", None).await;
+    assert!(pagina.contains("Contas suspensas (1)"), "{pagina}");
+    assert!(pagina.contains("<td>claudiolc</td>"), "{pagina}");
+    assert!(
+        pagina.contains("<td>renovação</td><td>12345</td>"),
+        "{pagina}"
+    );
+
+    let (_, pagina) = painel.pedir("/painel/busca?q=lima", None).await;
+    assert!(pagina.contains("1 contas encontradas"), "{pagina}");
+    let (_, pagina) = painel.pedir("/painel/busca?q=123456789", None).await;
+    assert!(pagina.contains("<td>claudiolc</td>"), "{pagina}");
+    // Um termo com `*)(uid=*` não amplia a busca para todas as contas
+    let (_, pagina) = painel
+        .pedir("/painel/busca?q=%2A%29%28uid%3D%2A", None)
+        .await;
+    assert!(pagina.contains("0 contas encontradas"), "{pagina}");
+
+    // Sem motivo, a conta não é reativada
+    let (status, _) = painel
+        .pedir(
+            "/painel/reativar",
+            Some(&[("uid", "claudiolc"), ("motivo", "")]),
+        )
+        .await;
+    assert_eq!(status, 400);
+
+    let (status, pagina) = painel
+        .pedir(
+            "/painel/reativar",
+            Some(&[("uid", "claudiolc"), ("motivo", "Destrancou")]),
+        )
+        .await;
+    assert_eq!(status, 200, "{pagina}");
+    let ldap = painel.api.ldap.lock().await;
+    assert_eq!(
+        ldap.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["ativa"],
+    );
+}
+
+#[tokio::test]
+async fn somente_leitura_bloqueia_as_operacoes() {
+    let mut painel = Painel::iniciar().await;
+    painel.entrar("prof", SENHA).await;
+
+    let (status, _) = painel
+        .pedir("/painel/somente-leitura", Some(&[("ativar", "true")]))
+        .await;
+    assert_eq!(status, 303);
+    let (_, pagina) = painel.pedir("/painel", None).await;
+    assert!(
+        pagina.contains("Modo somente leitura <b>ativo</b>"),
+        "{pagina}"
+    );
+
+    let (status, _) = painel.pedir("/painel/prazos", Some(&[])).await;
+    assert_eq!(status, 503);
+
+    painel
+        .pedir("/painel/somente-leitura", Some(&[("ativar", "false")]))
+        .await;
+    let (status, pagina) = painel.pedir("/painel/prazos", Some(&[])).await;
+    assert_eq!(status, 200, "{pagina}");
+}
+
+/// Os papéis dos testes: o `prof` é admin, os membros do [GRUPO_OPERADORES]
+/// são operadores e os demais supervisores só consultam.
+fn papeis(cfg: &mut Configuracao) {
+    cfg.autorizacao = Some(ConfiguracaoAutorizacao {
+        padrao: Papel::Leitura,
+        usuarios: [("prof".to_string(), Papel::Admin)].into(),
+        grupos: [(GRUPO_OPERADORES.to_string(), Papel::Operador)].into(),
+    });
+}
+
+#[tokio::test]
+async fn papeis_limitam_as_operacoes_do_painel() {
+    let mut painel = Painel::iniciar_ajustando(papeis).await;
+
+    assert_eq!(painel.entrar("leitor", SENHA).await, 303);
+    let (status, pagina) = painel.pedir("/painel", None).await;
+    assert_eq!(status, 200);
+    assert!(!pagina.contains("/painel/prazos"), "{pagina}");
+    let (status, _) = painel.pedir("/painel/prazos", Some(&[])).await;
+    assert_eq!(status, 403);
+
+    // O operador aplica os prazos, mas não muda o modo somente leitura
+    assert_eq!(painel.entrar("monitor", SENHA).await, 303);
+    let (_, pagina) = painel.pedir("/painel", None).await;
+    assert!(pagina.contains("/painel/prazos"), "{pagina}");
+    assert!(!pagina.contains("/painel/somente-leitura"), "{pagina}");
+    let (status, pagina) = painel.pedir("/painel/prazos", Some(&[])).await;
+    assert_eq!(status, 200, "{pagina}");
+    let (status, _) = painel
+        .pedir("/painel/somente-leitura", Some(&[("ativar", "true")]))
+        .await;
+    assert_eq!(status, 403);
+
+    assert_eq!(painel.entrar("prof", SENHA).await, 303);
+    let (status, _) = painel
+        .pedir("/painel/somente-leitura", Some(&[("ativar", "true")]))
+        .await;
+    assert_eq!(status, 303);
+}
+
+#[tokio::test]
+async fn papeis_valem_nas_rotas_administrativas() {
+    let painel = Painel::iniciar_ajustando(papeis).await;
+    let url = format!("{}/api/admin/somente-leitura", painel.api.url);
+    let pedir = |uid: &str, senha: &str, corpo: Option<&str>| {
+        let req = match corpo {
+            Some(corpo) => painel
+                .cliente
+                .put(&url)
+                .header("Content-Type", "application/json")
+                .body(corpo.to_string()),
+            None => painel.cliente.get(&url),
+        };
+        let req = req.basic_auth(uid, Some(senha));
+        async move { req.send().await.unwrap().status().as_u16() }
+    };
+    let ativar = Some(r#"{"somente_leitura": true}"#);
+
+    assert_eq!(pedir("leitor", SENHA, None).await, 200);
+    assert_eq!(pedir("leitor", SENHA, ativar).await, 403);
+    assert_eq!(pedir("monitor", SENHA, ativar).await, 403);
+    assert_eq!(pedir("prof", "errada", ativar).await, 401);
+    assert_eq!(pedir("intruso", SENHA, None).await, 401);
+    assert_eq!(pedir("prof", SENHA, ativar).await, 200);
+
+    // O token continua valendo como admin
+    let status = painel
+        .cliente
+        .put(&url)
+        .bearer_auth(TOKEN)
+        .header("Content-Type", "application/json")
+        .body(r#"{"somente_leitura": false}"#)
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, 200);
+}
+
+/// O segredo TOTP dos supervisores nos testes do segundo fator.
+const SEGREDO_TOTP: &str = "JBSWY3DPEHPK3PXP";
+
+/// O código TOTP do [SEGREDO_TOTP] `passos` depois do atual.
+fn codigo_totp(passos: u64) -> String {
+    let segredo = decodificar_segredo(SEGREDO_TOTP).unwrap();
+    codigo(&segredo, Utc::now().timestamp() as u64 / 30 + passos)
+}
+
+#[tokio::test]
+async fn operacoes_destrutivas_exigem_o_segundo_fator() {
+    let painel = Painel::iniciar_ajustando(|cfg| {
+        papeis(cfg);
+        cfg.totp = Some(ConfiguracaoTotp {
+            segredos: ["prof", "monitor"]
+                .map(|uid| (uid.to_string(), SEGREDO_TOTP.into()))
+                .into(),
+            janela: 1,
+            passo_segundos: 30,
+        });
+    })
+    .await;
+    painel
+        .api
+        .cadastrar(Documento::novo(
+            "123456789",
+            "CLÁUDIO DE LIMA CAVALCANTE",
+            "Ciência da Computação",
+        ))
+        .await;
+    let pedir = |operacao: &str, uid: &str, codigo: &str, corpo: &str| {
+        let req = painel
+            .cliente
+            .post(format!(
+                "{}/api/admin/contas/claudiolc/{operacao}",
+                painel.api.url,
+            ))
+            .header("Content-Type", "application/json")
+            .header("X-Alumnic-Totp", codigo)
+            .body(corpo.to_string());
+        let req = match uid {
+            "" => req.bearer_auth(TOKEN),
+            uid => req.basic_auth(uid, Some(SENHA)),
+        };
+        async move { req.send().await.unwrap().status().as_u16() }
+    };
+    let remocao = r#"{"motivo": "Conta comprometida"}"#;
+    let senha = r#"{"senha": "NovaSenha123", "motivo": "Esqueceu"}"#;
+
+    // O token sozinho não basta, e o operador não remove contas
+    assert_eq!(pedir("remover", "", &codigo_totp(0), remocao).await, 403);
+    assert_eq!(
+        pedir("remover", "monitor", &codigo_totp(0), remocao).await,
+        403
+    );
+    assert_eq!(pedir("remover", "prof", "", remocao).await, 401);
+    assert_eq!(pedir("remover", "prof", "000000", remocao).await, 401);
+
+    // No modo somente leitura, o código não é gasto na escrita recusada
+    let codigo = codigo_totp(0);
+    let modo = |ativar: bool| {
+        let req = painel
+            .cliente
+            .put(format!("{}/api/admin/somente-leitura", painel.api.url))
+            .bearer_auth(TOKEN)
+            .header("Content-Type", "application/json")
+            .body(format!(r#"{{"somente_leitura": {ativar}}}"#));
+        async move { req.send().await.unwrap().status().as_u16() }
+    };
+    assert_eq!(modo(true).await, 200);
+    assert_eq!(pedir("senha", "monitor", &codigo, senha).await, 503);
+    assert_eq!(modo(false).await, 200);
+
+    // O operador redefine a senha, mas o mesmo código não vale de novo
+    assert_eq!(pedir("senha", "monitor", &codigo, senha).await, 200);
+    assert_eq!(pedir("senha", "monitor", &codigo, senha).await, 401);
+    let nova = "NovaSenha123".to_string().into();
+    assert!(
+        painel
+            .api
+            .ldap
+            .verificar_senha(DN_ALUNO, &nova)
+            .await
+            .unwrap()
+    );
+
+    assert_eq!(
+        pedir("remover", "prof", &codigo_totp(1), remocao).await,
+        200
+    );
+    let ldap = painel.api.ldap.lock().await;
+    assert_eq!(
+        ldap.entrada(DN_ALUNO).unwrap().attrs["estadoConta"],
+        vec!["removida"],
+    );
+}
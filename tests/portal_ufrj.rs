@@ -0,0 +1,181 @@
+//! Testes da integração com o Gnosys, usando o Gnosys falso.
+
+mod comum;
+
+use alumnic::portal_ufrj::{
+    Consulta, ConsultaErro, ContratoGnosys, LeituraResposta, consulta_em,
+    ler_resposta,
+};
+use comum::gnosys::{Documento, GnosysFalso, Modo};
+
+fn aluno_bcc() -> Documento {
+    Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    )
+}
+
+async fn consultar(
+    gnosys: &GnosysFalso,
+    d: &Documento,
+) -> Result<Consulta, ConsultaErro> {
+    consulta_em(&gnosys.url, &d.dre, &d.data, &d.hora, &d.codigo).await
+}
+
+#[tokio::test]
+async fn documento_valido_de_cada_curso() {
+    let gnosys = GnosysFalso::iniciar().await;
+    let bcc = aluno_bcc();
+    let profcomp =
+        Documento::novo("222222222", "MARIA DAS DORES", "Ensino de Computação");
+    let outro = Documento::novo("333333333", "JOSE LIMA", "Matemática");
+    gnosys.registrar(bcc.clone());
+    gnosys.registrar(profcomp.clone());
+    gnosys.registrar(outro.clone());
+
+    match consultar(&gnosys, &bcc).await.unwrap() {
+        Consulta::AlunoBCC { nome } => {
+            assert_eq!(nome.usernames().next().unwrap(), "claudiolc");
+        },
+        r => panic!("resultado inesperado {r:?}"),
+    }
+    assert!(matches!(
+        consultar(&gnosys, &profcomp).await.unwrap(),
+        Consulta::AlunoProfComp { .. },
+    ));
+    assert!(matches!(
+        consultar(&gnosys, &outro).await.unwrap(),
+        Consulta::AlunoOutroCurso { curso, .. } if curso == "Matemática",
+    ));
+}
+
+#[tokio::test]
+async fn documento_invalido() {
+    let gnosys = GnosysFalso::iniciar().await;
+    gnosys.registrar(aluno_bcc());
+
+    let mut adulterado = aluno_bcc();
+    adulterado.hora = "10:01".to_string();
+
+    assert!(matches!(
+        consultar(&gnosys, &adulterado).await.unwrap(),
+        Consulta::Desconhecido,
+    ));
+}
+
+#[tokio::test]
+async fn gnosys_em_manutencao() {
+    let gnosys = GnosysFalso::iniciar().await;
+    gnosys.registrar(aluno_bcc());
+    gnosys.modo(Modo::Manutencao);
+
+    assert!(matches!(
+        consultar(&gnosys, &aluno_bcc()).await,
+        Err(ConsultaErro::CombinacaoInvalida),
+    ));
+}
+
+#[tokio::test]
+async fn formulario_sem_view_state() {
+    let gnosys = GnosysFalso::iniciar().await;
+    gnosys.modo(Modo::SemViewState);
+
+    assert!(matches!(
+        consultar(&gnosys, &aluno_bcc()).await,
+        Err(ConsultaErro::SemViewState),
+    ));
+    assert_eq!(gnosys.consultas(), 0);
+}
+
+/// Uma resposta válida com os `campos` (rótulo e valor) na ordem dada, como
+/// numa página do SIGA reorganizada.
+fn resposta_com_rotulos(campos: &[(&str, &str)]) -> String {
+    let campos: String = campos
+        .iter()
+        .map(|(rotulo, valor)| {
+            format!(
+                concat!(
+                    r#"<div class="gnosys-campo"><span>{}:</span> "#,
+                    r#"<div class="gnosys-item-visualizacao">{}</div></div>"#,
+                ),
+                rotulo, valor,
+            )
+        })
+        .collect();
+    format!(
+        r#"<html><body><span id="msgDocumentoValido">Documento válido</span>{campos}</body></html>"#
+    )
+}
+
+#[test]
+fn leitura_tolerante_usa_os_rotulos() {
+    let html = resposta_com_rotulos(&[
+        ("Curso", "Ciência da Computação"),
+        ("Situação", "Ativa"),
+        ("Nome", "CLÁUDIO DE LIMA CAVALCANTE"),
+        ("RG", "12.345.678-9"),
+    ]);
+
+    let (consulta, contrato) =
+        ler_resposta(&html, LeituraResposta::Tolerante).unwrap();
+    assert!(matches!(consulta, Consulta::AlunoBCC { .. }));
+    assert_eq!(contrato, Some(ContratoGnosys::V2));
+    assert!(matches!(
+        ler_resposta(&html, LeituraResposta::Estrita),
+        Err(ConsultaErro::NumeroEstranhoDeItens),
+    ));
+}
+
+#[test]
+fn leitura_tolerante_ignora_rotulos_parecidos() {
+    let html = resposta_com_rotulos(&[
+        ("Nome social", "CLAUDIA LIMA"),
+        ("Nome da mãe", "MARIA DE LIMA"),
+        ("Nome", "CLÁUDIO DE LIMA CAVALCANTE"),
+        ("Curso anterior", "Matemática"),
+        ("Curso", "Ciência da Computação"),
+    ]);
+
+    let (consulta, _) =
+        ler_resposta(&html, LeituraResposta::Tolerante).unwrap();
+    assert!(
+        matches!(
+            &consulta,
+            Consulta::AlunoBCC { nome }
+                if nome.to_string() == "Cláudio de Lima Cavalcante"
+        ),
+        "{consulta:?}",
+    );
+}
+
+#[test]
+fn leitura_tolerante_aceita_a_versao_1() {
+    let html = concat!(
+        r#"<html><body><span id="msgDocumentoValido">Documento válido</span>"#,
+        r#"<div class="gnosys-item-visualizacao">MARIA DAS DORES</div>"#,
+        r#"<div class="gnosys-item-visualizacao">12.345.678-9</div>"#,
+        r#"<div class="gnosys-item-visualizacao">Ensino de Computação</div>"#,
+        "</body></html>",
+    );
+
+    for leitura in [LeituraResposta::Estrita, LeituraResposta::Tolerante] {
+        let (consulta, contrato) = ler_resposta(html, leitura).unwrap();
+        assert!(matches!(consulta, Consulta::AlunoProfComp { .. }));
+        assert_eq!(contrato, Some(ContratoGnosys::V1));
+    }
+}
+
+#[test]
+fn rotulo_renomeado_e_apontado() {
+    let html = resposta_com_rotulos(&[
+        ("Nome", "JOSE LIMA"),
+        ("Documento", "12.345.678-9"),
+        ("Programa", "Matemática"),
+    ]);
+
+    assert!(matches!(
+        ler_resposta(&html, LeituraResposta::Tolerante),
+        Err(ConsultaErro::SemCampo("curso")),
+    ));
+}
@@ -0,0 +1,172 @@
+//! Testes das contas de projetos de extensão e de visitantes.
+
+mod comum;
+
+use alumnic::ldap::conexao::FonteLdap;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::projeto::{DadosDoProjeto, ErroDeProjeto, expirar_projetos};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use comum::api::{configuracao, diretorio_com_samba};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DN_PROJETO: &str =
+    "uid=anacs,ou=projetos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// Os dados de uma conta de `anacs`, do projeto de extensão da `prof`, que
+/// vence na `expiracao`.
+fn dados(expiracao: NaiveDate) -> DadosDoProjeto {
+    DadosDoProjeto {
+        nome: "Ana Carla Souza".to_string(),
+        email: "ana@exemplo.com".to_string(),
+        projeto: "Computação nas Escolas".to_string(),
+        responsavel: "prof".to_string(),
+        expiracao,
+        senha: "Senha1234".to_string().into(),
+    }
+}
+
+/// Um diretório com o domínio do Samba e a docente `prof`.
+async fn diretorio() -> Arc<Mutex<DiretorioMemoria>> {
+    let mut d = diretorio_com_samba().await;
+    d.adicionar(
+        "uid=prof,ou=professores,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+        vec![
+            ("objectClass", ["posixAccount"].into()),
+            ("uid", ["prof"].into()),
+            ("mail", ["prof@ic.ufrj.br"].into()),
+        ],
+    )
+    .await
+    .unwrap();
+    Arc::new(Mutex::new(d))
+}
+
+#[tokio::test]
+async fn cria_a_conta_com_expiracao_e_responsavel() {
+    let ldap = diretorio().await;
+    let cfg = configuracao("http://gnosys.invalido");
+    let agora = "2025-03-01T12:00:00Z".parse().unwrap();
+    let expiracao = NaiveDate::from_ymd_opt(2025, 12, 31).unwrap();
+
+    let (username, dn) = dados(expiracao)
+        .cadastrar(&cfg, &ldap, agora)
+        .await
+        .unwrap();
+    assert_eq!(username, "anacs");
+    assert_eq!(dn, DN_PROJETO);
+
+    let d = ldap.lock().await;
+    let e = d.entrada(DN_PROJETO).unwrap();
+    assert!(e.attrs["objectClass"].contains(&"dccProjeto".to_string()));
+    assert!(!e.attrs["objectClass"].contains(&"dccAluno".to_string()));
+    assert_eq!(e.attrs["dccResponsavel"], vec!["prof"]);
+    assert_eq!(e.attrs["dccNomeProjeto"], vec!["Computação nas Escolas"]);
+    assert_eq!(e.attrs["uidNumber"], vec!["5001"]);
+    assert_eq!(e.attrs["homeDirectory"], vec!["/usuarios/projetos/anacs"]);
+    // 31/12/2025 é o dia 20453, e a conta funciona até o fim dele
+    assert_eq!(e.attrs["shadowExpire"], vec!["20454"]);
+    assert!(!e.attrs.contains_key("dccDRE"));
+    assert!(!e.attrs.contains_key("sambaSID"));
+}
+
+#[tokio::test]
+async fn recusa_expiracao_invalida_e_responsavel_inexistente() {
+    let ldap = diretorio().await;
+    let cfg = configuracao("http://gnosys.invalido");
+    let agora = Utc::now();
+    let hoje = agora.date_naive();
+
+    let r = dados(hoje - Days::new(1))
+        .cadastrar(&cfg, &ldap, agora)
+        .await;
+    assert!(
+        matches!(r, Err(ErroDeProjeto::ExpiracaoPassada(_))),
+        "{r:?}"
+    );
+
+    let r = dados(hoje + Days::new(366))
+        .cadastrar(&cfg, &ldap, agora)
+        .await;
+    assert!(
+        matches!(r, Err(ErroDeProjeto::ExpiracaoLonga(_, 365))),
+        "{r:?}"
+    );
+
+    let mut sem_docente = dados(hoje + Days::new(30));
+    sem_docente.responsavel = "ninguem".to_string();
+    let r = sem_docente.cadastrar(&cfg, &ldap, agora).await;
+    assert!(
+        matches!(&r, Err(ErroDeProjeto::ResponsavelInexistente(uid)) if uid == "ninguem"),
+        "{r:?}"
+    );
+
+    assert!(ldap.lock().await.entrada(DN_PROJETO).is_none());
+}
+
+#[tokio::test]
+async fn expiracao_avisa_o_responsavel_e_bloqueia_a_conta() {
+    let ldap = diretorio().await;
+    let dir = std::env::temp_dir()
+        .join(format!("alumnic-expiracao-{}", std::process::id()));
+    let caixa = dir.join("avisos.eml");
+    let mut cfg = configuracao("http://gnosys.invalido");
+    cfg.dados = dir.clone();
+    std::fs::create_dir_all(&dir).unwrap();
+    cfg.notificacao.comando = vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        format!("cat >> {}", caixa.display()),
+    ];
+    let em = |data: &str| -> DateTime<Utc> {
+        format!("{data}T12:00:00Z").parse().unwrap()
+    };
+    let expiracao = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+    dados(expiracao)
+        .cadastrar(&cfg, &ldap, em("2025-03-01"))
+        .await
+        .unwrap();
+
+    // Longe da expiração, nada acontece
+    let t = expirar_projetos(&ldap, &cfg, em("2025-03-10"))
+        .await
+        .unwrap();
+    assert!(t.avisadas.is_empty() && t.bloqueadas.is_empty(), "{t:?}");
+
+    // A 12 dias, o responsável é avisado uma vez só
+    let t = expirar_projetos(&ldap, &cfg, em("2025-03-20"))
+        .await
+        .unwrap();
+    assert_eq!(t.avisadas.len(), 1, "{t:?}");
+    let t = expirar_projetos(&ldap, &cfg, em("2025-03-21"))
+        .await
+        .unwrap();
+    assert!(t.avisadas.is_empty(), "{t:?}");
+    let aviso = std::fs::read_to_string(&caixa).unwrap();
+    assert!(aviso.contains("To: prof@ic.ufrj.br\n"), "{aviso}");
+    assert!(aviso.contains("funciona até 31/03/2025"), "{aviso}");
+
+    // O último dia ainda funciona
+    let t = expirar_projetos(&ldap, &cfg, em("2025-03-31"))
+        .await
+        .unwrap();
+    assert!(t.bloqueadas.is_empty(), "{t:?}");
+    let senha = "Senha1234".to_string().into();
+    assert!(ldap.verificar_senha(DN_PROJETO, &senha).await.unwrap());
+
+    let t = expirar_projetos(&ldap, &cfg, em("2025-04-01"))
+        .await
+        .unwrap();
+    assert_eq!(t.bloqueadas.len(), 1, "{t:?}");
+    assert!(!ldap.verificar_senha(DN_PROJETO, &senha).await.unwrap());
+    assert_eq!(
+        ldap.lock().await.entrada(DN_PROJETO).unwrap().attrs["estadoConta"],
+        vec!["suspensa"],
+    );
+    let t = expirar_projetos(&ldap, &cfg, em("2025-04-02"))
+        .await
+        .unwrap();
+    assert!(t.bloqueadas.is_empty(), "{t:?}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,98 @@
+//! Testes da reativação de contas suspensas.
+
+mod comum;
+
+use alumnic::desligamento::desligar_em_lote;
+use alumnic::reativacao::{ErroDeReativacao, reativar};
+use chrono::Utc;
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// Cadastra e suspende o aluno `claudiolc`, retornando a sua senha original.
+async fn aluno_suspenso(api: &ApiDeTeste) -> Vec<String> {
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+    let senha =
+        api.ldap.lock().await.entrada(DN_ALUNO).unwrap().attrs["userPassword"]
+            .clone();
+
+    desligar_em_lote(
+        "123456789",
+        &configuracao(&api.gnosys.url),
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    senha
+}
+
+#[tokio::test]
+async fn reativa_e_registra_na_auditoria() {
+    let api = ApiDeTeste::iniciar().await;
+    let senha = aluno_suspenso(&api).await;
+
+    let auditoria = std::env::temp_dir()
+        .join(format!("alumnic-auditoria-{}.jsonl", std::process::id()));
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.auditoria.arquivo = Some(auditoria.clone());
+
+    reativar(
+        "claudiolc",
+        "Destrancou a matrícula",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+
+    {
+        let ldap = api.ldap.lock().await;
+        let e = ldap.entrada(DN_ALUNO).unwrap();
+        assert_eq!(e.attrs["userPassword"], senha);
+        assert_eq!(e.attrs["sambaAcctFlags"], vec!["[UX]"]);
+        assert_eq!(e.attrs["estadoConta"], vec!["ativa"]);
+        assert!(!e.attrs.contains_key("dataRemocao"));
+    }
+
+    let linhas = std::fs::read_to_string(&auditoria).unwrap();
+    std::fs::remove_file(&auditoria).unwrap();
+    let registro: Value = serde_json::from_str(linhas.trim()).unwrap();
+    assert_eq!(registro["operacao"], "reativar");
+    assert_eq!(registro["uid"], "claudiolc");
+    assert_eq!(registro["motivo"], "Destrancou a matrícula");
+
+    // Uma conta ativa não é reativada de novo
+    assert!(matches!(
+        reativar("claudiolc", "De novo", &cfg, &api.ldap, Utc::now()).await,
+        Err(ErroDeReativacao::NaoSuspensa(..)),
+    ));
+}
+
+#[tokio::test]
+async fn reativacao_exige_motivo_e_conta() {
+    let api = ApiDeTeste::iniciar().await;
+    aluno_suspenso(&api).await;
+    let cfg = configuracao(&api.gnosys.url);
+
+    assert!(matches!(
+        reativar("claudiolc", "  ", &cfg, &api.ldap, Utc::now()).await,
+        Err(ErroDeReativacao::SemMotivo),
+    ));
+    assert!(matches!(
+        reativar("ninguem", "Voltou", &cfg, &api.ldap, Utc::now()).await,
+        Err(ErroDeReativacao::ContaInexistente(..)),
+    ));
+}
@@ -0,0 +1,114 @@
+//! Testes da reconciliação da listagem de alunos ativos com as contas.
+
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::reconciliacao::{Divergencia, ErroDeReconciliacao, reconciliar};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+type Ldap = Arc<Mutex<DiretorioMemoria>>;
+
+/// Um diretório com as contas `(uid, ou, dre, nome, estado)`.
+async fn com_contas(contas: &[(&str, &str, &str, &str, &str)]) -> Ldap {
+    let mut d = DiretorioMemoria::default();
+    for (uid, ou, dre, nome, estado) in contas {
+        d.adicionar(
+            &format!("uid={uid},ou={ou},ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [*uid].into()),
+                ("dccDRE", [*dre].into()),
+                ("gecos", [*nome].into()),
+                ("estadoConta", [*estado].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    Arc::new(Mutex::new(d))
+}
+
+const LISTAGEM: &str = "DRE;Nome;Curso\n\
+                        123456789;CLÁUDIO DE LIMA CAVALCANTE;CC\n\
+                        987654321;Maria Souza;CC\n\
+                        111111111;Pedro Alves;CC\n\
+                        222222222;Ana Costa;CC\n";
+
+#[tokio::test]
+async fn aponta_sem_conta_fora_da_listagem_e_divergencias() {
+    let ldap = com_contas(&[
+        // Mesmo nome, sem os acentos e o "de"
+        (
+            "claudiolc",
+            "alunos",
+            "123456789",
+            "Claudio Lima Cavalcante",
+            "ativa",
+        ),
+        // Mudou de nome
+        (
+            "mariasilva",
+            "alunos",
+            "987654321",
+            "Maria Silva",
+            "carencia",
+        ),
+        // Só tem uma conta removida
+        ("pedroa", "alunos", "111111111", "Pedro Alves", "removida"),
+        // Saiu do curso
+        ("joaop", "alunos", "333333333", "Joao Pereira", "ativa"),
+        // Já bloqueada, em processo de remoção
+        ("luizs", "alunos", "444444444", "Luiz Santos", "suspensa"),
+        // De outro curso
+        ("ritam", "profcomp", "555555555", "Rita Moura", "ativa"),
+        // A listagem pode ter só parte do curso
+        ("anac", "alunos", "222222222", "Ana Costa", "ativa"),
+    ])
+    .await;
+
+    let r = reconciliar(LISTAGEM, Some("alunos"), &ldap).await.unwrap();
+    assert_eq!(
+        r.sem_conta.iter().map(|a| &a.dre[..]).collect::<Vec<_>>(),
+        ["111111111"],
+    );
+    assert_eq!(
+        r.fora_da_listagem
+            .iter()
+            .map(|c| &c.uid[..])
+            .collect::<Vec<_>>(),
+        ["joaop"],
+    );
+    assert_eq!(
+        r.divergencias,
+        [Divergencia {
+            uid: "mariasilva".to_string(),
+            dre: "987654321".to_string(),
+            nome_listagem: "Maria Souza".to_string(),
+            nome_ldap: "Maria Silva".to_string(),
+        }],
+    );
+
+    // Sem a OU, as contas dos outros cursos também são apontadas
+    let r = reconciliar(LISTAGEM, None, &ldap).await.unwrap();
+    assert_eq!(
+        r.fora_da_listagem
+            .iter()
+            .map(|c| &c.uid[..])
+            .collect::<Vec<_>>(),
+        ["joaop", "ritam"],
+    );
+}
+
+#[tokio::test]
+async fn recusa_listagem_invalida() {
+    let ldap = com_contas(&[]).await;
+
+    let r = reconciliar("DRE;Curso\n123456789;CC\n", None, &ldap).await;
+    assert!(matches!(r, Err(ErroDeReconciliacao::SemColuna("nome"))));
+
+    let r = reconciliar("DRE;Nome\n12345;Maria\n", None, &ldap).await;
+    assert!(matches!(
+        r,
+        Err(ErroDeReconciliacao::LinhaInvalida { linha: 2, .. })
+    ));
+}
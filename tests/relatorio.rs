@@ -0,0 +1,145 @@
+//! Testes do relatório semestral das contas.
+
+use alumnic::configuracao::{ConfiguracaoDesligamento, ConfiguracaoRenovacao};
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::ldap::turmas::Periodo;
+use alumnic::relatorio::{Contagens, gerar};
+use chrono::{DateTime, NaiveDate};
+
+/// O dia de `data`, no formato guardado no LDAP.
+fn dia(data: &str) -> String {
+    let data: NaiveDate = data.parse().unwrap();
+    (data - DateTime::UNIX_EPOCH.date_naive())
+        .num_days()
+        .to_string()
+}
+
+/// Adiciona uma conta na `ou` com os `atributos` de datas e estado.
+async fn conta(
+    d: &mut DiretorioMemoria,
+    uid: &str,
+    ou: &str,
+    dre: &str,
+    atributos: &[(&str, String)],
+) {
+    let mut entrada = vec![
+        ("objectClass", ["dccAluno"].into()),
+        ("uid", [uid].into()),
+        ("dccDRE", [dre].into()),
+    ];
+    for (atributo, valor) in atributos {
+        entrada.push((*atributo, [valor.as_str()].into()));
+    }
+
+    d.adicionar(
+        &format!(
+            "uid={uid},ou={ou},ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"
+        ),
+        entrada,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn conta_os_eventos_do_periodo() {
+    let prazos = ConfiguracaoRenovacao::default();
+    let desligamento = ConfiguracaoDesligamento::default();
+    let mais = |data: &str, dias: i64| {
+        (dia(data).parse::<i64>().unwrap() + dias).to_string()
+    };
+    let validade = prazos.validade_conta;
+    let ate_remocao = desligamento.dias_ate_remocao;
+
+    let mut d = DiretorioMemoria::default();
+    // Criada no período e nunca renovada
+    conta(
+        &mut d,
+        "ana",
+        "alunos",
+        "125200001",
+        &[
+            ("dataCriacao", dia("2025-09-01")),
+            ("dataRenovacao", mais("2025-09-01", validade)),
+        ],
+    )
+    .await;
+    // Criada antes e renovada no período
+    conta(
+        &mut d,
+        "bia",
+        "alunos",
+        "124200002",
+        &[
+            ("dataCriacao", dia("2024-09-01")),
+            ("dataRenovacao", mais("2025-10-01", validade)),
+        ],
+    )
+    .await;
+    // Bloqueada no período, com a remoção só no seguinte
+    conta(
+        &mut d,
+        "cal",
+        "profcomp",
+        "123200003",
+        &[
+            ("dataCriacao", dia("2023-09-01")),
+            ("estadoConta", "suspensa".to_string()),
+            ("dataRemocao", mais("2025-11-01", ate_remocao)),
+        ],
+    )
+    .await;
+    // Bloqueada e removida no período
+    conta(
+        &mut d,
+        "di",
+        "alunos",
+        "122200004",
+        &[
+            ("dataCriacao", dia("2022-09-01")),
+            ("estadoConta", "removida".to_string()),
+            ("dataRemocao", dia("2025-12-01")),
+        ],
+    )
+    .await;
+    // Antiga, sem a data de criação, com o ingresso no período pelo DRE
+    conta(&mut d, "eva", "alunos", "125200005", &[]).await;
+    // Nada aconteceu no período
+    conta(
+        &mut d,
+        "flo",
+        "alunos",
+        "121100006",
+        &[
+            ("dataCriacao", dia("2021-03-01")),
+            ("dataRenovacao", mais("2025-03-01", validade)),
+        ],
+    )
+    .await;
+
+    let periodo = Periodo::interpretar("2025.2").unwrap();
+    let relatorio = gerar(periodo, &prazos, &desligamento, &mut d)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        relatorio.por_ou["alunos"],
+        Contagens {
+            criadas: 2,
+            renovadas: 1,
+            bloqueadas: 1,
+            removidas: 1,
+        }
+    );
+    assert_eq!(
+        relatorio.por_ou["profcomp"],
+        Contagens {
+            criadas: 0,
+            renovadas: 0,
+            bloqueadas: 1,
+            removidas: 0,
+        }
+    );
+    assert_eq!(relatorio.csv().lines().last(), Some("2025.2,total,2,1,2,1"),);
+}
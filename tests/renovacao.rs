@@ -0,0 +1,146 @@
+//! Testes da renovação anual do vínculo, pela API e em lote.
+
+mod comum;
+
+use alumnic::configuracao::ConfiguracaoRenovacao;
+use alumnic::desligamento::desligar_em_lote;
+use alumnic::lotes::Lote;
+use alumnic::renovacao::{ErroDeRenovacao, dia, renovar_em_lote};
+use chrono::Utc;
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+fn documento_de_hoje(codigo: &str) -> Documento {
+    let mut d = Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    );
+    d.data = Utc::now().format("%d/%m/%Y").to_string();
+    d.codigo = codigo.to_string();
+    d
+}
+
+fn corpo(d: &Documento) -> String {
+    json!({
+        "dre": d.dre,
+        "data": d.data,
+        "hora": d.hora,
+        "codigo": d.codigo,
+    })
+    .to_string()
+}
+
+/// Cadastra o aluno do [documento_de_hoje] pela API.
+async fn cadastrar(api: &ApiDeTeste) {
+    api.cadastrar(documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0001"))
+        .await;
+}
+
+#[tokio::test]
+async fn renova_uma_conta_cadastrada() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar(&api).await;
+
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0002");
+    api.gnosys.registrar(d.clone());
+
+    let (status, resposta) = api.post("/api/renovar", &corpo(&d)).await;
+    assert_eq!(status, 200, "{resposta}");
+    assert!(resposta["message"].as_str().unwrap().contains("claudiolc"));
+
+    let ldap = api.ldap.lock().await;
+    let e = ldap.entrada(DN_ALUNO).unwrap();
+    let validade = ConfiguracaoRenovacao::default().validade_conta;
+    assert_eq!(
+        e.attrs["dataRenovacao"],
+        vec![(dia(Utc::now()) + validade).to_string()],
+    );
+    assert_eq!(e.attrs["estadoConta"], vec!["ativa"]);
+}
+
+#[tokio::test]
+async fn documento_antigo_nao_renova() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar(&api).await;
+
+    let mut d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0002");
+    d.data = "01/03/2020".to_string();
+    api.gnosys.registrar(d.clone());
+
+    let (status, _) = api.post("/api/renovar", &corpo(&d)).await;
+    assert_eq!(status, 422);
+    // O documento antigo nem chega a ser enviado ao Gnosys
+    assert_eq!(api.gnosys.consultas(), 1);
+}
+
+#[tokio::test]
+async fn renovar_conta_inexistente() {
+    let api = ApiDeTeste::iniciar().await;
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0002");
+    api.gnosys.registrar(d.clone());
+
+    let (status, _) = api.post("/api/renovar", &corpo(&d)).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn conta_suspensa_nao_renova() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar(&api).await;
+    desligar_em_lote(
+        "123456789",
+        &configuracao(&api.gnosys.url),
+        &api.ldap,
+        Utc::now(),
+        &CancellationToken::new(),
+    )
+    .await
+    .unwrap();
+
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0002");
+    api.gnosys.registrar(d.clone());
+
+    let (status, _) = api.post("/api/renovar", &corpo(&d)).await;
+    assert_eq!(status, 403);
+
+    let ldap = api.ldap.lock().await;
+    let e = ldap.entrada(DN_ALUNO).unwrap();
+    assert_eq!(e.attrs["estadoConta"], vec!["suspensa"]);
+}
+
+#[tokio::test]
+async fn renovacao_em_lote() {
+    let api = ApiDeTeste::iniciar().await;
+    cadastrar(&api).await;
+
+    let d = documento_de_hoje("0000.0000.0000.0000.0000.0000.0000.0002");
+    api.gnosys.registrar(d.clone());
+
+    let lista = format!(
+        "# dre,data,hora,codigo\n{},{},{},{}\n\n987654321,01/01/2025\n",
+        d.dre, d.data, d.hora, d.codigo,
+    );
+    let resultados = renovar_em_lote(
+        &lista,
+        &ConfiguracaoRenovacao::default(),
+        &api.gnosys.url,
+        &api.ldap,
+        Utc::now(),
+        4,
+        &Lote::sem_acompanhamento(CancellationToken::new()),
+    )
+    .await;
+
+    assert_eq!(resultados.len(), 2);
+    assert_eq!(resultados[0].1.as_ref().unwrap().username, "claudiolc");
+    assert!(matches!(
+        resultados[1].1,
+        Err(ErroDeRenovacao::LinhaInvalida(..)),
+    ));
+}
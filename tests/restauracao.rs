@@ -0,0 +1,162 @@
+//! Testes da restauração de contas a partir de um backup em LDIF.
+
+mod comum;
+
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::remocao::remover_agora;
+use alumnic::restauracao::{ErroDeRestauracao, restaurar};
+use chrono::Utc;
+use comum::api::{ApiDeTeste, configuracao};
+use comum::gnosys::Documento;
+use serde_json::Value;
+
+const DN_ALUNO: &str =
+    "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br";
+
+/// Cadastra o aluno `claudiolc` e retorna o backup da conta dele em LDIF,
+/// como o `slapcat` o geraria.
+async fn backup_do_aluno(api: &ApiDeTeste) -> String {
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+
+    let ldap = api.ldap.lock().await;
+    let entrada = ldap.entrada(DN_ALUNO).unwrap();
+    let mut ldif = format!("version: 1\n\ndn: {DN_ALUNO}\n");
+    for (atributo, valores) in &entrada.attrs {
+        for valor in valores {
+            ldif.push_str(&format!("{atributo}: {valor}\n"));
+        }
+    }
+    ldif
+}
+
+#[tokio::test]
+async fn restaura_com_a_identidade_original() {
+    let api = ApiDeTeste::iniciar().await;
+    let backup = backup_do_aluno(&api).await;
+    let original = api.ldap.lock().await.entrada(DN_ALUNO).unwrap().clone();
+    api.ldap.lock().await.remover(DN_ALUNO).await.unwrap();
+
+    let auditoria = std::env::temp_dir()
+        .join(format!("alumnic-restauracao-{}.jsonl", std::process::id()));
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.auditoria.arquivo = Some(auditoria.clone());
+
+    let dn = restaurar(
+        "claudiolc",
+        &backup,
+        "backup.ldif",
+        &cfg,
+        &api.ldap,
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(dn, DN_ALUNO);
+    let restaurada = api.ldap.lock().await.entrada(DN_ALUNO).unwrap().clone();
+    assert_eq!(restaurada.attrs["uidNumber"], original.attrs["uidNumber"]);
+    assert_eq!(
+        restaurada.attrs.get("sambaSID"),
+        original.attrs.get("sambaSID"),
+    );
+    assert_eq!(
+        restaurada.attrs["userPassword"],
+        original.attrs["userPassword"]
+    );
+
+    let registro: Value = serde_json::from_str(
+        std::fs::read_to_string(&auditoria)
+            .unwrap()
+            .lines()
+            .last()
+            .unwrap(),
+    )
+    .unwrap();
+    std::fs::remove_file(&auditoria).unwrap();
+    assert_eq!(registro["operacao"], "restaurar");
+    assert_eq!(registro["uid"], "claudiolc");
+    assert_eq!(registro["motivo"], "Restaurada do backup backup.ldif");
+}
+
+#[tokio::test]
+async fn recusa_o_uid_ou_o_dre_ja_usados() {
+    let api = ApiDeTeste::iniciar().await;
+    let backup = backup_do_aluno(&api).await;
+    let cfg = configuracao(&api.gnosys.url);
+
+    // A conta ainda existe
+    let erro =
+        restaurar("claudiolc", &backup, "b", &cfg, &api.ldap, Utc::now())
+            .await
+            .unwrap_err();
+    assert!(
+        matches!(
+            erro,
+            ErroDeRestauracao::Conflito {
+                atributo: "uid",
+                ..
+            }
+        ),
+        "{erro}",
+    );
+
+    // Outra conta com o mesmo DRE
+    let outra = backup.replace("claudiolc", "claudio2");
+    let erro = restaurar("claudio2", &outra, "b", &cfg, &api.ldap, Utc::now())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            erro,
+            ErroDeRestauracao::Conflito {
+                atributo: "dccDRE",
+                ..
+            }
+        ),
+        "{erro}",
+    );
+
+    let erro = restaurar("fulano", &backup, "b", &cfg, &api.ldap, Utc::now())
+        .await
+        .unwrap_err();
+    assert!(matches!(erro, ErroDeRestauracao::ContaAusente(_)), "{erro}");
+    assert!(api.ldap.lock().await.entrada(DN_ALUNO).is_some());
+}
+
+#[tokio::test]
+async fn reusa_o_uid_number_so_de_contas_removidas() {
+    let api = ApiDeTeste::iniciar().await;
+    let backup = backup_do_aluno(&api).await;
+    let cfg = configuracao(&api.gnosys.url);
+    // A mesma identidade numérica, com outro uid e outro DRE
+    let outra = backup
+        .replace("claudiolc", "claudio2")
+        .replace("123456789", "987654321");
+
+    let erro = restaurar("claudio2", &outra, "b", &cfg, &api.ldap, Utc::now())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            erro,
+            ErroDeRestauracao::Conflito {
+                atributo: "uidNumber",
+                ..
+            }
+        ),
+        "{erro}",
+    );
+
+    remover_agora("claudiolc", "Teste", &cfg, &api.ldap, Utc::now())
+        .await
+        .unwrap();
+    let dn = restaurar("claudio2", &outra, "b", &cfg, &api.ldap, Utc::now())
+        .await
+        .unwrap();
+    assert_eq!(dn, DN_ALUNO.replace("claudiolc", "claudio2"));
+}
@@ -0,0 +1,81 @@
+//! Testes da validação do schema do LDAP.
+
+mod comum;
+
+use alumnic::configuracao::ConfiguracaoUsuario;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::ldap::schema::{ErroDeSchema, exigidos, verificar_schema};
+use comum::api::configuracao;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+fn cfg() -> ConfiguracaoUsuario {
+    configuracao("http://localhost").usuario_novo
+}
+
+/// Um diretório com um subschema que define as classes e os atributos
+/// exigidos pela configuração dos testes, menos os `sem`.
+async fn diretorio(sem: &[&str]) -> Arc<Mutex<DiretorioMemoria>> {
+    let (classes, atributos) = exigidos(&cfg());
+    let definicoes = |nomes: Vec<String>| {
+        nomes
+            .into_iter()
+            .filter(|n| !sem.contains(&n.as_str()))
+            .enumerate()
+            .map(|(i, n)| format!("( 1.2.3.{i} NAME '{n}' )"))
+            .collect::<Vec<_>>()
+    };
+    let classes = definicoes(classes);
+    let atributos = definicoes(atributos);
+
+    let mut d = DiretorioMemoria::default();
+    d.adicionar(
+        "cn=Subschema",
+        vec![
+            ("objectClass", ["subschema"].into()),
+            ("cn", ["Subschema"].into()),
+            (
+                "objectClasses",
+                classes.iter().map(String::as_str).collect(),
+            ),
+            (
+                "attributeTypes",
+                atributos.iter().map(String::as_str).collect(),
+            ),
+        ],
+    )
+    .await
+    .unwrap();
+    Arc::new(Mutex::new(d))
+}
+
+#[tokio::test]
+async fn schema_completo() {
+    let ldap = diretorio(&[]).await;
+
+    verificar_schema(&cfg(), &ldap).await.unwrap();
+}
+
+#[tokio::test]
+async fn aponta_tudo_o_que_falta() {
+    let ldap = diretorio(&["dccAluno", "emailExterno", "cota"]).await;
+
+    let Err(ErroDeSchema::Incompleto { classes, atributos }) =
+        verificar_schema(&cfg(), &ldap).await
+    else {
+        panic!("o schema deveria estar incompleto");
+    };
+    assert_eq!(classes, ["dccAluno"]);
+    assert_eq!(atributos, ["cota", "emailExterno"]);
+}
+
+#[tokio::test]
+async fn sem_subschema() {
+    let ldap = Arc::new(Mutex::new(DiretorioMemoria::default()));
+
+    assert!(matches!(
+        verificar_schema(&cfg(), &ldap).await,
+        Err(ErroDeSchema::SemSubschema(_)),
+    ));
+}
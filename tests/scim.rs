@@ -0,0 +1,101 @@
+//! Testes das rotas SCIM, só de leitura.
+
+mod comum;
+
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use comum::api::{ApiDeTeste, TOKEN, TOKEN_SCIM, diretorio_com_samba};
+use serde_json::Value;
+
+/// Um diretório com três alunos, um deles suspenso.
+async fn diretorio() -> DiretorioMemoria {
+    let mut d = diretorio_com_samba().await;
+    for (uid, estado) in [
+        ("joaops", "ativa"),
+        ("anams", "suspensa"),
+        ("pedrors", "carencia"),
+    ] {
+        d.adicionar(
+            &format!(
+                "uid={uid},ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"
+            ),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("cn", ["Nome"].into()),
+                ("sn", ["Sobrenome"].into()),
+                ("gecos", ["Nome Sobrenome"].into()),
+                ("mail", [format!("{uid}@ic.ufrj.br").as_str()].into()),
+                ("estadoConta", [estado].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    d
+}
+
+async fn get(api: &ApiDeTeste, caminho: &str) -> (u16, Value) {
+    let (status, corpo) = api.get_com_token(caminho, TOKEN_SCIM).await;
+    (status, serde_json::from_str(&corpo).unwrap())
+}
+
+fn ids(lista: &Value) -> Vec<&str> {
+    lista["Resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|u| u["id"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn lista_os_usuarios_em_paginas() {
+    let api = ApiDeTeste::iniciar_com(diretorio().await).await;
+
+    // O máximo por página da configuração de teste é 2
+    let (status, lista) = get(&api, "/scim/v2/Users").await;
+    assert_eq!(status, 200);
+    assert_eq!(lista["totalResults"], 3);
+    assert_eq!(lista["itemsPerPage"], 2);
+    assert_eq!(ids(&lista), ["anams", "joaops"]);
+
+    let (_, lista) = get(&api, "/scim/v2/Users?startIndex=3&count=10").await;
+    assert_eq!(lista["startIndex"], 3);
+    assert_eq!(ids(&lista), ["pedrors"]);
+
+    let (_, usuario) = get(&api, "/scim/v2/Users/anams").await;
+    assert_eq!(usuario["userName"], "anams");
+    assert_eq!(usuario["active"], false);
+    assert_eq!(usuario["name"]["familyName"], "Sobrenome");
+    assert_eq!(usuario["emails"][0]["value"], "anams@ic.ufrj.br");
+}
+
+#[tokio::test]
+async fn filtra_os_usuarios() {
+    let api = ApiDeTeste::iniciar_com(diretorio().await).await;
+
+    let (_, lista) =
+        get(&api, "/scim/v2/Users?filter=userName%20eq%20%22JOAOPS%22").await;
+    assert_eq!(ids(&lista), ["joaops"]);
+
+    let (_, lista) =
+        get(&api, "/scim/v2/Users?filter=active%20eq%20true").await;
+    assert_eq!(ids(&lista), ["joaops", "pedrors"]);
+
+    let (status, erro) =
+        get(&api, "/scim/v2/Users?filter=userName%20co%20%22j%22").await;
+    assert_eq!(status, 400);
+    assert_eq!(erro["status"], "400");
+}
+
+#[tokio::test]
+async fn exige_o_token_do_scim() {
+    let api = ApiDeTeste::iniciar_com(diretorio().await).await;
+
+    let (status, _) = api.get_com_token("/scim/v2/Users", TOKEN).await;
+    assert_eq!(status, 401);
+
+    let (status, _) = get(&api, "/scim/v2/Users/ninguem").await;
+    assert_eq!(status, 404);
+}
@@ -0,0 +1,47 @@
+//! Testes da simulação de carga. Só rodam com a feature `dev`.
+#![cfg(feature = "dev")]
+
+mod comum;
+
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::simulacao::{ConfiguracaoSimulacao, simular};
+use comum::api::{configuracao, diretorio_com_samba};
+use ldap3::Scope;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+#[tokio::test]
+async fn simulacao_cadastra_e_apaga_as_contas_sinteticas() {
+    let ldap = Arc::new(Mutex::new(diretorio_com_samba().await));
+    let simulacao = ConfiguracaoSimulacao {
+        cadastros: 30,
+        concorrencia: 8,
+        primeiro_dre: 990000000,
+        limpar: true,
+    };
+
+    let r = simular(
+        configuracao("http://gnosys.invalid"),
+        ldap.clone(),
+        &simulacao,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(r.total(), 30);
+    assert_eq!(r.sucessos(), 30, "{r}");
+    assert_eq!(r.taxa_de_erro(), 0.0);
+    assert_eq!(r.apagadas, 30);
+    let contas = ldap
+        .lock()
+        .await
+        .buscar(
+            "dc=dcc,dc=ufrj,dc=br",
+            Scope::Subtree,
+            "(objectClass=dccAluno)",
+            vec!["uid"],
+        )
+        .await
+        .unwrap();
+    assert!(contas.is_empty());
+}
@@ -0,0 +1,278 @@
+//! Testes das rotas administrativas pelo socket Unix local.
+
+mod comum;
+
+use alumnic::api::{abrir_socket_admin, com_socket_local};
+use alumnic::configuracao::ConfiguracaoSocketAdmin;
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::totp::{ConfiguracaoTotp, codigo, decodificar_segredo};
+use alumnic::utils::hashes::hash_ssha;
+use chrono::Utc;
+use comum::api::{
+    ApiDeTeste, GRUPO_PAINEL, TOKEN, configuracao, diretorio_com_samba,
+};
+use comum::gnosys::Documento;
+use secrecy::ExposeSecret;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::sync::Mutex;
+
+/// Faz a requisição pelo socket em `caminho`, sem token, retornando a
+/// resposta inteira.
+async fn pelo_socket(
+    caminho: &Path,
+    metodo: &str,
+    rota: &str,
+    corpo: &str,
+) -> String {
+    pelo_socket_com(caminho, metodo, rota, corpo, &[]).await
+}
+
+/// Como o [pelo_socket], mas também com os `headers`.
+async fn pelo_socket_com(
+    caminho: &Path,
+    metodo: &str,
+    rota: &str,
+    corpo: &str,
+    headers: &[(&str, &str)],
+) -> String {
+    let mut socket = UnixStream::connect(caminho).await.unwrap();
+    let mut requisicao = format!(
+        "{metodo} {rota} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n"
+    );
+    for (nome, valor) in headers {
+        requisicao += &format!("{nome}: {valor}\r\n");
+    }
+    if !corpo.is_empty() {
+        requisicao += &format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            corpo.len(),
+        );
+    }
+    requisicao += &format!("\r\n{corpo}");
+    socket.write_all(requisicao.as_bytes()).await.unwrap();
+    let mut resposta = String::new();
+    socket.read_to_string(&mut resposta).await.unwrap();
+    resposta
+}
+
+#[tokio::test]
+async fn rotas_administrativas_so_pelo_socket() {
+    let caminho = std::env::temp_dir()
+        .join(format!("alumnic-admin-{}.sock", std::process::id()));
+    let socket = ConfiguracaoSocketAdmin {
+        caminho: caminho.clone(),
+        modo: 0o600,
+        somente_socket: true,
+    };
+
+    let ajuste = socket.clone();
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.socket_admin = Some(ajuste);
+        })
+        .await;
+
+    // Pelo TCP, as rotas somem mesmo com o token
+    let res = reqwest::Client::new()
+        .get(format!("{}/api/admin/manutencao", api.url))
+        .bearer_auth(TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.socket_admin = Some(socket.clone());
+    let app = alumnic::api::router(
+        Arc::new(cfg),
+        Arc::new(Mutex::new(diretorio_com_samba().await)),
+    );
+    // Um socket que sobrou de outra execução é trocado
+    let _ = std::os::unix::net::UnixListener::bind(&caminho);
+    let listener = abrir_socket_admin(&socket).unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, com_socket_local(app)).await.unwrap();
+    });
+
+    let modo = std::fs::metadata(&caminho).unwrap().permissions().mode();
+    assert_eq!(modo & 0o777, 0o600);
+
+    let resposta = pelo_socket(
+        &caminho,
+        "PUT",
+        "/api/admin/manutencao",
+        r#"{"interrompida":true}"#,
+    )
+    .await;
+    assert!(resposta.starts_with("HTTP/1.1 200"), "{resposta}");
+    let resposta =
+        pelo_socket(&caminho, "GET", "/api/admin/manutencao", "").await;
+    assert!(resposta.contains(r#""interrompida":true"#), "{resposta}");
+
+    std::fs::remove_file(&caminho).unwrap();
+}
+
+#[tokio::test]
+async fn nao_troca_o_que_nao_e_socket() {
+    let caminho = std::env::temp_dir()
+        .join(format!("alumnic-nao-socket-{}", std::process::id()));
+    std::fs::write(&caminho, "importante").unwrap();
+
+    let socket = ConfiguracaoSocketAdmin {
+        caminho: caminho.clone(),
+        modo: 0o600,
+        somente_socket: false,
+    };
+    assert!(abrir_socket_admin(&socket).is_err());
+    assert_eq!(std::fs::read_to_string(&caminho).unwrap(), "importante");
+
+    std::fs::remove_file(&caminho).unwrap();
+}
+
+#[tokio::test]
+async fn socket_fica_com_o_modo_configurado() {
+    let caminho = std::env::temp_dir()
+        .join(format!("alumnic-modo-{}.sock", std::process::id()));
+    let socket = ConfiguracaoSocketAdmin {
+        caminho: caminho.clone(),
+        modo: 0o660,
+        somente_socket: false,
+    };
+    // O diretório temporário de uma execução interrompida é trocado
+    let privado = caminho.with_extension("sock.novo");
+    std::fs::create_dir_all(&privado).unwrap();
+    std::fs::write(privado.join("admin.sock"), "").unwrap();
+
+    let _listener = abrir_socket_admin(&socket).unwrap();
+
+    let metadados = std::fs::symlink_metadata(&caminho).unwrap();
+    assert!(metadados.file_type().is_socket());
+    assert_eq!(metadados.permissions().mode() & 0o777, 0o660);
+    assert!(!privado.exists());
+
+    std::fs::remove_file(&caminho).unwrap();
+}
+
+#[tokio::test]
+async fn socket_identifica_o_supervisor_do_segundo_fator() {
+    const SEGREDO: &str = "JBSWY3DPEHPK3PXP";
+    let caminho = std::env::temp_dir()
+        .join(format!("alumnic-admin-totp-{}.sock", std::process::id()));
+    let socket = ConfiguracaoSocketAdmin {
+        caminho: caminho.clone(),
+        modo: 0o600,
+        somente_socket: true,
+    };
+    let totp = ConfiguracaoTotp {
+        segredos: [("prof".to_string(), SEGREDO.into())].into(),
+        janela: 1,
+        passo_segundos: 30,
+    };
+
+    let (ajuste_socket, ajuste_totp) = (socket.clone(), totp.clone());
+    let api =
+        ApiDeTeste::iniciar_ajustando(diretorio_com_samba().await, |cfg| {
+            cfg.socket_admin = Some(ajuste_socket);
+            cfg.totp = Some(ajuste_totp);
+        })
+        .await;
+    api.cadastrar(Documento::novo(
+        "123456789",
+        "CLÁUDIO DE LIMA CAVALCANTE",
+        "Ciência da Computação",
+    ))
+    .await;
+    {
+        let mut ldap = api.ldap.lock().await;
+        let senha = "SenhaDoSupervisor".to_string().into();
+        let hash = hash_ssha(&senha);
+        ldap.adicionar(
+            "uid=prof,ou=professores,ou=usuarios,dc=dcc,dc=ufrj,dc=br",
+            vec![
+                ("objectClass", ["posixAccount"].into()),
+                ("uid", ["prof"].into()),
+                ("userPassword", [hash.expose_secret()].into()),
+            ],
+        )
+        .await
+        .unwrap();
+        ldap.adicionar(
+            GRUPO_PAINEL,
+            vec![
+                ("objectClass", ["posixGroup"].into()),
+                ("cn", ["supervisores"].into()),
+                ("memberUid", ["prof"].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+
+    let mut cfg = configuracao(&api.gnosys.url);
+    cfg.socket_admin = Some(socket.clone());
+    cfg.totp = Some(totp);
+    let app = alumnic::api::router(Arc::new(cfg), api.ldap.clone());
+    let listener = abrir_socket_admin(&socket).unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, com_socket_local(app)).await.unwrap();
+    });
+
+    let segredo = decodificar_segredo(SEGREDO).unwrap();
+    let codigo = codigo(&segredo, Utc::now().timestamp() as u64 / 30);
+    let remover = |headers: Vec<(&'static str, String)>| {
+        let caminho = caminho.clone();
+        async move {
+            let headers: Vec<_> =
+                headers.iter().map(|(n, v)| (*n, v.as_str())).collect();
+            pelo_socket_com(
+                &caminho,
+                "POST",
+                "/api/admin/contas/claudiolc/remover",
+                r#"{"motivo": "Conta comprometida"}"#,
+                &headers,
+            )
+            .await
+        }
+    };
+
+    // Sem dizer quem é, o socket não faz operações destrutivas
+    let resposta = remover(vec![("X-Alumnic-Totp", codigo.clone())]).await;
+    assert!(resposta.starts_with("HTTP/1.1 403"), "{resposta}");
+    let resposta = remover(vec![
+        ("X-Alumnic-Operador", "intruso".to_string()),
+        ("X-Alumnic-Totp", codigo.clone()),
+    ])
+    .await;
+    assert!(resposta.starts_with("HTTP/1.1 403"), "{resposta}");
+    let resposta = remover(vec![
+        ("X-Alumnic-Operador", "prof".to_string()),
+        ("X-Alumnic-Totp", "000000".to_string()),
+    ])
+    .await;
+    assert!(resposta.starts_with("HTTP/1.1 401"), "{resposta}");
+
+    let resposta = remover(vec![
+        ("X-Alumnic-Operador", "prof".to_string()),
+        ("X-Alumnic-Totp", codigo),
+    ])
+    .await;
+    assert!(resposta.starts_with("HTTP/1.1 200"), "{resposta}");
+    assert_eq!(
+        api.ldap
+            .lock()
+            .await
+            .entrada(
+                "uid=claudiolc,ou=alunos,ou=academicos,ou=usuarios,dc=dcc,\
+                 dc=ufrj,dc=br"
+            )
+            .unwrap()
+            .attrs["estadoConta"],
+        vec!["removida"],
+    );
+
+    std::fs::remove_file(&caminho).unwrap();
+}
@@ -0,0 +1,127 @@
+//! Testes da sincronização dos grupos das turmas com as listagens do SIGA.
+
+mod comum;
+
+use alumnic::ldap::diretorio::DiretorioLdap;
+use alumnic::ldap::memoria::DiretorioMemoria;
+use alumnic::ldap::turmas::MudancaTurma;
+use alumnic::turmas::{
+    ConfiguracaoTurmas, ErroDeTurmas, planejar, sincronizar,
+};
+use comum::api::diretorio_com_samba;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+type Ldap = Arc<Mutex<DiretorioMemoria>>;
+
+const DN_TURMA: &str =
+    "cn=turma-mab123-2024.1,ou=turmas,ou=grupos,dc=dcc,dc=ufrj,dc=br";
+
+/// Um diretório com as contas de dois alunos.
+async fn com_alunos() -> Ldap {
+    let mut d = diretorio_com_samba().await;
+    for (uid, dre) in [("claudiolc", "123456789"), ("mariasilva", "987654321")]
+    {
+        d.adicionar(
+            &format!("uid={uid},ou=alunos,ou=academicos,ou=usuarios,dc=dcc,dc=ufrj,dc=br"),
+            vec![
+                ("objectClass", ["dccAluno"].into()),
+                ("uid", [uid].into()),
+                ("dccDRE", [dre].into()),
+            ],
+        )
+        .await
+        .unwrap();
+    }
+    Arc::new(Mutex::new(d))
+}
+
+/// Planeja e aplica a sincronização com a `listagem`.
+async fn sincronizar_com(ldap: &Ldap, listagem: &str) -> Vec<String> {
+    let cfg = ConfiguracaoTurmas::default();
+    let plano = planejar(listagem, &cfg, ldap).await.unwrap();
+    for r in sincronizar(&plano.mudancas, &cfg, ldap).await.unwrap() {
+        r.unwrap();
+    }
+    plano.mudancas.iter().map(ToString::to_string).collect()
+}
+
+#[tokio::test]
+async fn cria_atualiza_e_remove_os_grupos() {
+    let ldap = &com_alunos().await;
+
+    let cfg = ConfiguracaoTurmas::default();
+    let plano = planejar(
+        "DRE;Disciplina;Período\n\
+         123456789;MAB123;2024/1\n\
+         111111111;MAB123;2024/1\n",
+        &cfg,
+        ldap,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        plano.sem_conta.into_iter().collect::<Vec<_>>(),
+        ["111111111"]
+    );
+    assert!(matches!(plano.mudancas[..], [MudancaTurma::Criar { .. }]));
+    sincronizar(&plano.mudancas, &cfg, ldap).await.unwrap();
+
+    {
+        let ldap = ldap.lock().await;
+        let grupo = ldap.entrada(DN_TURMA).unwrap();
+        assert_eq!(grupo.attrs["memberUid"], vec!["claudiolc"]);
+        assert_eq!(grupo.attrs["gidNumber"], vec!["5001"]);
+    }
+
+    // A Maria entra na turma, e a mesma listagem não muda mais nada
+    let listagem = "dre,disciplina,periodo\n\
+                    123456789,MAB123,2024.1\n\
+                    987654321,MAB123,2024.1\n";
+    assert_eq!(
+        sincronizar_com(ldap, listagem).await,
+        ["turma-mab123-2024.1: 1 entram, 0 saem"],
+    );
+    assert!(sincronizar_com(ldap, listagem).await.is_empty());
+    {
+        let ldap = ldap.lock().await;
+        let mut membros =
+            ldap.entrada(DN_TURMA).unwrap().attrs["memberUid"].clone();
+        membros.sort();
+        assert_eq!(membros, ["claudiolc", "mariasilva"]);
+    }
+
+    // No período seguinte, o grupo antigo é removido
+    assert_eq!(
+        sincronizar_com(
+            ldap,
+            "dre,disciplina,periodo\n987654321,MAB456,2024.2"
+        )
+        .await,
+        [
+            "turma-mab456-2024.2: criado com 1 membros",
+            "turma-mab123-2024.1: removido, o período terminou",
+        ],
+    );
+    assert!(ldap.lock().await.entrada(DN_TURMA).is_none());
+}
+
+#[tokio::test]
+async fn listagem_invalida_nao_altera_nada() {
+    let ldap = &com_alunos().await;
+    let cfg = ConfiguracaoTurmas::default();
+
+    assert!(matches!(
+        planejar("dre,periodo\n123456789,2024.1", &cfg, ldap).await,
+        Err(ErroDeTurmas::SemColuna("disciplina")),
+    ));
+    assert!(matches!(
+        planejar(
+            "dre,disciplina,periodo\n123456789,MAB123,2024.1\n12345,MAB123,2024.1",
+            &cfg,
+            ldap,
+        )
+        .await,
+        Err(ErroDeTurmas::LinhaInvalida { linha: 3, .. }),
+    ));
+}
@@ -0,0 +1,163 @@
+//! Testes de propriedade dos `processar_*`: com qualquer entrada, eles nunca
+//! entram em pânico, a saída sempre está no formato canônico e processar a
+//! saída de novo devolve o mesmo valor.
+
+use alumnic::utils::validacao_entradas::*;
+use proptest::prelude::*;
+use regex::Regex;
+use secrecy::SecretString;
+
+/// Verifica as propriedades comuns a todos os processadores para uma entrada:
+/// se ela for aceita, a saída casa com `formato` e é um ponto fixo de `f`.
+fn verificar(
+    f: fn(&str) -> Option<String>,
+    formato: &str,
+    entrada: &str,
+) -> Result<(), TestCaseError> {
+    if let Some(saida) = f(entrada) {
+        let re = Regex::new(formato).unwrap();
+        prop_assert!(re.is_match(&saida), "{saida:?} fora de {formato}");
+        prop_assert_eq!(f(&saida), Some(saida));
+    }
+    Ok(())
+}
+
+const DRE: &str = r"^[0-9]{9}$";
+const DATA: &str = r"^[0-9]{2}/[0-9]{2}/[0-9]{4}$";
+const HORA: &str = r"^[0-9]{2}:[0-9]{2}$";
+const CODIGO: &str = r"^[0-9A-F]{4}(\.[0-9A-F]{4}){7}$";
+const TELEFONE: &str = r"^\+55[1-9][0-9](9?[0-9]{4})[0-9]{4}$";
+
+/// Espaços quaisquer, inclusive os que não são ASCII.
+macro_rules! espacos {
+    () => {
+        r"[ \t\u{a0}\u{2003}]{0,2}"
+    };
+}
+
+proptest! {
+    #[test]
+    fn dre_qualquer(s in any::<String>()) {
+        verificar(processar_dre, DRE, &s)?;
+    }
+
+    #[test]
+    fn dre_quase_valido(s in concat!(espacos!(), r"\d{9}", espacos!())) {
+        verificar(processar_dre, DRE, &s)?;
+    }
+
+    #[test]
+    fn data_qualquer(s in any::<String>()) {
+        verificar(processar_data, DATA, &s)?;
+    }
+
+    #[test]
+    fn data_quase_valida(
+        s in concat!(
+            espacos!(), r"\d{1,2}", espacos!(), r"/?",
+            espacos!(), r"\d{1,2}", espacos!(), r"/?",
+            espacos!(), r"\d{1,4}", espacos!(),
+        ),
+    ) {
+        verificar(processar_data, DATA, &s)?;
+    }
+
+    #[test]
+    fn data_aceita_ano_com_dois_digitos(
+        d in 1u8..=31,
+        m in 1u8..=12,
+        a in 0u16..100,
+    ) {
+        prop_assert_eq!(
+            processar_data(&format!("{d}/{m}/{a}")),
+            Some(format!("{d:02}/{m:02}/{}", 2000 + a)),
+        );
+    }
+
+    #[test]
+    fn hora_qualquer(s in any::<String>()) {
+        verificar(processar_hora, HORA, &s)?;
+    }
+
+    #[test]
+    fn hora_quase_valida(
+        s in concat!(
+            espacos!(), r"\d{1,2}", espacos!(), ":",
+            espacos!(), r"\d{1,2}", espacos!(),
+        ),
+    ) {
+        verificar(processar_hora, HORA, &s)?;
+    }
+
+    #[test]
+    fn codigo_qualquer(s in any::<String>()) {
+        verificar(processar_codigo, CODIGO, &s)?;
+    }
+
+    #[test]
+    fn codigo_quase_valido(
+        s in concat!(
+            espacos!(), "[0-9A-Fa-g]{4}",
+            "(", espacos!(), r"\.", espacos!(), "[0-9A-Fa-g]{4}){7}",
+            espacos!(),
+        ),
+    ) {
+        verificar(processar_codigo, CODIGO, &s)?;
+    }
+
+    #[test]
+    fn email_qualquer(s in any::<String>()) {
+        if let Some(email) = processar_email(&s) {
+            prop_assert_eq!(processar_email(&email), Some(email));
+        }
+    }
+
+    #[test]
+    fn email_quase_valido(
+        s in r" ?[a-zA-Z0-9.!+_-]{1,10}@[a-zA-Z0-9.-]{1,10} ?",
+    ) {
+        if let Some(email) = processar_email(&s) {
+            prop_assert!(!email.contains(' '));
+            prop_assert_eq!(processar_email(&email), Some(email));
+        }
+    }
+
+    #[test]
+    fn telefone_qualquer(s in any::<String>()) {
+        verificar(processar_telefone, TELEFONE, &s)?;
+    }
+
+    #[test]
+    fn telefone_quase_valido(
+        s in concat!(
+            r"(\+55)?", espacos!(), r"\(?0?\d\d\)?",
+            espacos!(), r"9?\d{4}", espacos!(), "-?",
+            espacos!(), r"\d{4}", espacos!(),
+        ),
+    ) {
+        verificar(processar_telefone, TELEFONE, &s)?;
+    }
+
+    #[test]
+    fn texto_qualquer(s in any::<String>()) {
+        if let Some(texto) = processar_texto(&s) {
+            prop_assert!(!texto.chars().any(char::is_control), "{texto:?}");
+            prop_assert!(!texto.contains("  "), "{texto:?}");
+            prop_assert_eq!(texto.trim(), &texto);
+            prop_assert_eq!(processar_texto(&texto), Some(texto));
+        }
+    }
+
+    #[test]
+    fn log_sem_caracteres_de_controle(s in any::<String>()) {
+        let escapado = escapar_para_log(&s);
+        prop_assert!(!escapado.chars().any(char::is_control), "{escapado:?}");
+        prop_assert!(!escapado.contains('\u{202e}'), "{escapado:?}");
+    }
+
+    #[test]
+    fn senha_qualquer(s in any::<String>()) {
+        // Só precisa não entrar em pânico
+        validar_senha(&SecretString::from(s));
+    }
+}